@@ -0,0 +1,362 @@
+/*!
+Types for reading and writing a trace of simulation events.
+
+When a host's `event_trace_enabled` option is set, Shadow writes the events that occur on that host
+to an `events.jsonl` file in the host's data directory. The trace is in the [JSON
+Lines](https://jsonlines.org/) format, with one [`Record`] per line. Records are written in the
+order that they occurred on the host, and times are simulation times rather than the host's
+(possibly skewed) clock times.
+
+Example trace:
+
+```json
+{"time_ns":1000000000,"host_id":0,"type":"process_start","pid":1000,"parent_pid":null,"name":"server"}
+{"time_ns":1000000000,"host_id":0,"type":"syscall_enter","pid":1000,"tid":1000,"number":39,"args":[0,0,0,0,0,0]}
+{"time_ns":1000000000,"host_id":0,"type":"syscall_exit","pid":1000,"tid":1000,"number":39,"result":{"done":1000}}
+```
+
+Example usage:
+
+```no_run
+use event_trace::{Event, Reader};
+
+let mut syscalls = 0;
+for record in Reader::open("shadow.data/hosts/server/events.jsonl")? {
+    if let Event::SyscallEnter { .. } = record?.event {
+        syscalls += 1;
+    }
+}
+println!("The host made {syscalls} syscalls");
+# Ok::<(), std::io::Error>(())
+```
+*/
+
+// https://github.com/rust-lang/rfcs/blob/master/text/2585-unsafe-block-in-unsafe-fn.md
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// An event that occurred on a host.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// The simulation time of the event, in nanoseconds since the start of the simulation.
+    pub time_ns: u64,
+    /// The id of the host that the event occurred on.
+    pub host_id: u32,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A packet reached a new stage of its delivery.
+    PacketStatus {
+        packet: Packet,
+        status: PacketStatus,
+    },
+    /// A managed thread made a syscall. A syscall that blocks will be entered again when it's
+    /// resumed.
+    SyscallEnter {
+        pid: u32,
+        tid: u32,
+        number: i64,
+        args: [u64; 6],
+    },
+    /// Shadow finished handling a syscall.
+    SyscallExit {
+        pid: u32,
+        tid: u32,
+        number: i64,
+        result: SyscallResult,
+    },
+    /// A process was started, either from the configuration or by `fork`.
+    ProcessStart {
+        pid: u32,
+        parent_pid: Option<u32>,
+        name: String,
+    },
+    /// A process exited.
+    ProcessExit { pid: u32, status: ExitStatus },
+    /// A timer expired, such as a timerfd or interval timer.
+    TimerFired {
+        /// The number of expirations since the timer's owner last read the count.
+        expiration_count: u64,
+        /// The interval of a periodic timer, in nanoseconds.
+        interval_ns: Option<u64>,
+    },
+}
+
+/// Identifies a packet and its endpoints.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Packet {
+    /// The id of the host that created the packet.
+    pub host_id: u32,
+    /// The id of the packet, which is unique on the host that created it.
+    pub id: u64,
+    pub protocol: Protocol,
+    /// The source address, which is an IPv6 address for IPv6 packets.
+    pub src: SocketAddr,
+    /// The destination address, which is an IPv6 address for IPv6 packets.
+    pub dst: SocketAddr,
+    pub payload_size: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Local,
+    Tcp,
+    Udp,
+    Icmp,
+    Unknown,
+}
+
+/// The stages of a packet's delivery. These match the packet delivery statuses that Shadow logs
+/// at the trace level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PacketStatus {
+    SndCreated,
+    SndTcpEnqueueThrottled,
+    SndTcpEnqueueRetransmit,
+    SndTcpDequeueRetransmit,
+    SndTcpRetransmitted,
+    SndSocketBuffered,
+    SndInterfaceSent,
+    InetSent,
+    InetDropped,
+    RouterEnqueued,
+    RouterDequeued,
+    RouterDropped,
+    RcvInterfaceReceived,
+    RcvInterfaceDropped,
+    RcvSocketProcessed,
+    RcvSocketDropped,
+    RcvTcpEnqueueUnordered,
+    RcvSocketBuffered,
+    RcvSocketDelivered,
+    Destroyed,
+    RelayCached,
+    RelayForwarded,
+    InetReordered,
+    InetDuplicated,
+    InetCorrupted,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyscallResult {
+    /// The syscall completed with the given return value.
+    Done(i64),
+    /// The syscall blocked and will be resumed later.
+    Blocked,
+    /// The syscall was passed through to be executed natively.
+    Native,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    Exited(i32),
+    Signaled(i32),
+    /// The process was still running when Shadow stopped it at the end of the simulation.
+    StoppedByShadow,
+}
+
+/// Writes records to a trace.
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write(&mut self, record: &Record) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// An iterator over the records in a trace.
+#[derive(Debug)]
+pub struct Reader<R: BufRead> {
+    lines: std::io::Lines<R>,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+        }
+    }
+}
+
+impl Reader<BufReader<File>> {
+    /// Open the trace at `path`.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = std::io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(serde_json::from_str(&line).map_err(std::io::Error::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        let packet = Packet {
+            host_id: 1,
+            id: 7,
+            protocol: Protocol::Tcp,
+            src: "11.0.0.1:80".parse().unwrap(),
+            dst: "11.0.0.2:5000".parse().unwrap(),
+            payload_size: 1400,
+        };
+        let events = [
+            Event::ProcessStart {
+                pid: 1000,
+                parent_pid: None,
+                name: "server".into(),
+            },
+            Event::SyscallEnter {
+                pid: 1000,
+                tid: 1001,
+                number: 0,
+                args: [3, 0x7fff0000, 100, 0, 0, 0],
+            },
+            Event::SyscallExit {
+                pid: 1000,
+                tid: 1001,
+                number: 0,
+                result: SyscallResult::Done(-11),
+            },
+            Event::SyscallExit {
+                pid: 1000,
+                tid: 1001,
+                number: 0,
+                result: SyscallResult::Blocked,
+            },
+            Event::PacketStatus {
+                packet,
+                status: PacketStatus::RouterDropped,
+            },
+            Event::TimerFired {
+                expiration_count: 1,
+                interval_ns: Some(u64::MAX),
+            },
+            Event::ProcessExit {
+                pid: 1000,
+                status: ExitStatus::Signaled(9),
+            },
+        ];
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(i, event)| Record {
+                time_ns: 1000 * i as u64,
+                host_id: 2,
+                event,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut writer = Writer::new(Vec::new());
+        for record in records() {
+            writer.write(&record).unwrap();
+        }
+        let trace = writer.into_inner();
+
+        // one record per line
+        assert_eq!(
+            trace.iter().filter(|x| **x == b'\n').count(),
+            records().len()
+        );
+
+        let read: Vec<Record> = Reader::new(&trace[..]).collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, records());
+    }
+
+    #[test]
+    fn test_format() {
+        let record = Record {
+            time_ns: 5,
+            host_id: 0,
+            event: Event::ProcessExit {
+                pid: 1000,
+                status: ExitStatus::Exited(0),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"time_ns":5,"host_id":0,"type":"process_exit","pid":1000,"status":{"exited":0}}"#
+        );
+    }
+
+    #[test]
+    fn test_read_ipv6() {
+        let trace = concat!(
+            r#"{"time_ns":5,"host_id":0,"type":"packet_status","packet":{"host_id":0,"id":3,"#,
+            r#""protocol":"udp","src":"[2001:db8::1]:5000","dst":"[2001:db8::2]:80","#,
+            r#""payload_size":10},"status":"snd_created"}"#,
+            "\n",
+        );
+        let read: Vec<Record> = Reader::new(trace.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let expected = Record {
+            time_ns: 5,
+            host_id: 0,
+            event: Event::PacketStatus {
+                packet: Packet {
+                    host_id: 0,
+                    id: 3,
+                    protocol: Protocol::Udp,
+                    src: "[2001:db8::1]:5000".parse().unwrap(),
+                    dst: "[2001:db8::2]:80".parse().unwrap(),
+                    payload_size: 10,
+                },
+                status: PacketStatus::SndCreated,
+            },
+        };
+        assert_eq!(read, [expected]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let trace = b"{\"time_ns\":5,\"host_id\":0,\"type\":\"unknown\"}\n";
+        let mut reader = Reader::new(&trace[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -397,6 +397,8 @@ pub struct siginfo_t(linux_siginfo_t);
 // Contains pointers, but they are understood to not necessarily be valid in the
 // current address space.
 unsafe impl Send for siginfo_t {}
+// SAFETY: Any initialized bit pattern is acceptable; see `Invariants` above.
+unsafe impl shadow_pod::Pod for siginfo_t {}
 
 impl siginfo_t {
     /// The bindings end up with a couple extra outer layers of unions.
@@ -451,6 +453,17 @@ impl siginfo_t {
         SigInfoCode::try_from_raw(self.inner().lsi_code, self.inner().lsi_signo)
     }
 
+    /// The `si_code` field, without checking that it's valid for the signal.
+    #[inline]
+    pub fn code_raw(&self) -> i32 {
+        self.inner().lsi_code
+    }
+
+    #[inline]
+    pub fn errno_raw(&self) -> i32 {
+        self.inner().lsi_errno
+    }
+
     /// # Safety
     ///
     /// Pointers are safe to dereference iff those used to construct `self` (or set
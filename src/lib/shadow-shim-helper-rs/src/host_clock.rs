@@ -0,0 +1,261 @@
+/*!
+A model of the error of a host's clocks relative to the true simulation time.
+
+Shadow schedules all events using the true simulation time, but each host can be configured with a
+clock that disagrees with it. The host's realtime clock (`CLOCK_REALTIME`, `gettimeofday()`,
+`time()`) starts with a constant offset from the true time, and then drifts from it at a constant
+rate. The realtime clock may periodically be stepped back to the true time, like a clock that is
+corrected by NTP. The host's monotonic clocks (`CLOCK_MONOTONIC`, `CLOCK_BOOTTIME`) drift at the
+same rate, but are never offset or stepped.
+*/
+
+use vasi::VirtualAddressSpaceIndependent;
+
+use crate::emulated_time::EmulatedTime;
+use crate::option::FfiOption;
+use crate::simulation_time::SimulationTime;
+
+const NANOS_PER_BILLION: i128 = 1_000_000_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, VirtualAddressSpaceIndependent)]
+#[repr(C)]
+pub struct HostClock {
+    /// The offset of the realtime clock from the true time at the start of the simulation, in
+    /// nanoseconds.
+    offset_nanos: i64,
+    /// The rate at which the clocks drift from the true time, in parts per billion.
+    drift_ppb: i64,
+    /// The interval at which the realtime clock is stepped back to the true time.
+    step_interval: FfiOption<SimulationTime>,
+}
+
+impl HostClock {
+    /// A clock that always agrees with the true time.
+    pub const TRUE_TIME: Self = Self {
+        offset_nanos: 0,
+        drift_ppb: 0,
+        step_interval: FfiOption::None,
+    };
+
+    /// Panics if the drift is not within (-1, 1) seconds per second, since the clocks would no
+    /// longer move forward.
+    pub fn new(offset_nanos: i64, drift_ppb: i64, step_interval: Option<SimulationTime>) -> Self {
+        assert!(drift_ppb.unsigned_abs() < NANOS_PER_BILLION as u64);
+        assert!(step_interval != Some(SimulationTime::ZERO));
+
+        Self {
+            offset_nanos,
+            drift_ppb,
+            step_interval: step_interval.into(),
+        }
+    }
+
+    /// Returns true if the clocks always agree with the true time.
+    pub fn is_true_time(&self) -> bool {
+        self.offset_nanos == 0 && self.drift_ppb == 0
+    }
+
+    /// The time of the host's realtime clock at the true time `now`.
+    pub fn realtime(&self, now: EmulatedTime) -> EmulatedTime {
+        let (start, offset) = self.realtime_segment(now);
+        let elapsed = nanos(now) - nanos(start);
+        from_nanos(nanos(now) + offset + self.drift(elapsed))
+    }
+
+    /// The time of the host's monotonic clocks at the true time `now`.
+    pub fn monotonic(&self, now: EmulatedTime) -> EmulatedTime {
+        let elapsed = nanos(now) - nanos(EmulatedTime::SIMULATION_START);
+        from_nanos(nanos(now) + self.drift(elapsed))
+    }
+
+    /// The earliest true time at which the host's realtime clock reaches `time`, assuming the
+    /// clock isn't stepped after the true time `now`. The result may be earlier than `now`.
+    pub fn realtime_to_true(&self, now: EmulatedTime, time: EmulatedTime) -> EmulatedTime {
+        let (start, offset) = self.realtime_segment(now);
+        let x = self.inverse_drift(nanos(time) - nanos(start) - offset);
+        from_nanos(nanos(start) + x)
+    }
+
+    /// The earliest true time at which the host's monotonic clocks reach `time`.
+    pub fn monotonic_to_true(&self, time: EmulatedTime) -> EmulatedTime {
+        let start = nanos(EmulatedTime::SIMULATION_START);
+        from_nanos(start + self.inverse_drift(nanos(time) - start))
+    }
+
+    /// The true time at which `duration` will have elapsed on the host's monotonic clocks, starting
+    /// at the true time `now`. Like Linux, relative timers should use this for any clock, including
+    /// the realtime clock, so that they aren't affected when the realtime clock is stepped.
+    pub fn monotonic_after(&self, now: EmulatedTime, duration: SimulationTime) -> EmulatedTime {
+        self.monotonic_to_true(self.monotonic(now) + duration)
+    }
+
+    /// The time that elapses on the host's monotonic clocks between the true times `start` and
+    /// `end`, or zero if `end` is before `start`.
+    pub fn monotonic_elapsed(&self, start: EmulatedTime, end: EmulatedTime) -> SimulationTime {
+        self.monotonic(end)
+            .saturating_duration_since(&self.monotonic(start))
+    }
+
+    /// The true time at which the realtime clock was last stepped (or the start of the
+    /// simulation), and the clock's offset at that time.
+    fn realtime_segment(&self, now: EmulatedTime) -> (EmulatedTime, i128) {
+        let elapsed = now.saturating_duration_since(&EmulatedTime::SIMULATION_START);
+
+        let last_step = match self.step_interval {
+            FfiOption::Some(interval) => elapsed.checked_rem(interval).map(|x| elapsed - x),
+            FfiOption::None => None,
+        };
+
+        match last_step {
+            Some(last_step) if !last_step.is_zero() => {
+                (EmulatedTime::SIMULATION_START + last_step, 0)
+            }
+            _ => (EmulatedTime::SIMULATION_START, self.offset_nanos.into()),
+        }
+    }
+
+    /// The amount that the clocks drift over `elapsed` nanoseconds of true time, rounded down.
+    fn drift(&self, elapsed: i128) -> i128 {
+        (elapsed * i128::from(self.drift_ppb)).div_euclid(NANOS_PER_BILLION)
+    }
+
+    /// The smallest amount of elapsed true time over which a drifting clock advances by at least
+    /// `elapsed` nanoseconds.
+    fn inverse_drift(&self, elapsed: i128) -> i128 {
+        let rate = NANOS_PER_BILLION + i128::from(self.drift_ppb);
+        // a lower bound that's off by a few nanoseconds at most due to rounding
+        let mut x = (elapsed * NANOS_PER_BILLION).div_euclid(rate);
+        while x + self.drift(x) < elapsed {
+            x += 1;
+        }
+        x
+    }
+}
+
+fn nanos(time: EmulatedTime) -> i128 {
+    EmulatedTime::to_c_emutime(Some(time)).into()
+}
+
+/// Convert nanoseconds since the unix epoch to an [`EmulatedTime`], saturating at its limits.
+fn from_nanos(nanos: i128) -> EmulatedTime {
+    let min = self::nanos(EmulatedTime::MIN);
+    let max = self::nanos(EmulatedTime::MAX);
+    let nanos = nanos.clamp(min, max);
+    EmulatedTime::from_c_emutime(nanos.try_into().unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> EmulatedTime {
+        EmulatedTime::SIMULATION_START + SimulationTime::from_secs(secs)
+    }
+
+    #[test]
+    fn test_true_time() {
+        let clock = HostClock::TRUE_TIME;
+        assert!(clock.is_true_time());
+        assert_eq!(clock.realtime(at(10)), at(10));
+        assert_eq!(clock.monotonic(at(10)), at(10));
+        assert_eq!(clock.realtime_to_true(at(0), at(10)), at(10));
+        assert_eq!(clock.monotonic_to_true(at(10)), at(10));
+    }
+
+    #[test]
+    fn test_offset() {
+        let clock = HostClock::new(-2_000_000_000, 0, None);
+        assert_eq!(clock.realtime(at(10)), at(8));
+        assert_eq!(clock.monotonic(at(10)), at(10));
+        assert_eq!(clock.realtime_to_true(at(0), at(8)), at(10));
+    }
+
+    #[test]
+    fn test_drift() {
+        // 100 ppm fast
+        let clock = HostClock::new(0, 100_000, None);
+        let drifted = at(10_000) + SimulationTime::SECOND;
+        assert_eq!(clock.realtime(at(10_000)), drifted);
+        assert_eq!(clock.monotonic(at(10_000)), drifted);
+        assert_eq!(clock.realtime_to_true(at(0), drifted), at(10_000));
+        assert_eq!(clock.monotonic_to_true(drifted), at(10_000));
+
+        // 100 ppm slow
+        let clock = HostClock::new(0, -100_000, None);
+        let drifted = at(10_000) - SimulationTime::SECOND;
+        assert_eq!(clock.realtime(at(10_000)), drifted);
+        assert_eq!(clock.realtime_to_true(at(0), drifted), at(10_000));
+    }
+
+    #[test]
+    fn test_inverse_rounding() {
+        // the inverse is the earliest time at which the clock has reached the given time
+        let clock = HostClock::new(123, 333, None);
+        for secs in [0, 1, 7, 1000] {
+            let time = at(secs) + SimulationTime::from_nanos(17);
+            let true_time = clock.realtime_to_true(at(0), time);
+            assert!(clock.realtime(true_time) >= time);
+            assert!(clock.realtime(true_time - SimulationTime::NANOSECOND) < time);
+
+            let true_time = clock.monotonic_to_true(time);
+            assert!(clock.monotonic(true_time) >= time);
+            assert!(clock.monotonic(true_time - SimulationTime::NANOSECOND) < time);
+        }
+    }
+
+    #[test]
+    fn test_relative() {
+        // 100 ppm fast, so 1 s on the host's clocks takes 0.9999 s of true time (rounded up)
+        let clock = HostClock::new(-2_000_000_000, 100_000, Some(SimulationTime::from_secs(5)));
+        let start = at(4) + SimulationTime::from_millis(500);
+        let end = clock.monotonic_after(start, SimulationTime::SECOND);
+        assert_eq!(end, start + SimulationTime::from_nanos(999_900_010));
+        assert_eq!(clock.monotonic_elapsed(start, end), SimulationTime::SECOND);
+
+        // the realtime clock was stepped forward in between, but relative times don't depend on it
+        assert!(clock.realtime(end) > clock.realtime(start) + SimulationTime::SECOND);
+
+        assert_eq!(clock.monotonic_elapsed(end, start), SimulationTime::ZERO);
+
+        let clock = HostClock::TRUE_TIME;
+        assert_eq!(clock.monotonic_after(at(3), SimulationTime::SECOND), at(4));
+        assert_eq!(
+            clock.monotonic_elapsed(at(3), at(4)),
+            SimulationTime::SECOND
+        );
+    }
+
+    #[test]
+    fn test_steps() {
+        let clock = HostClock::new(
+            5_000_000_000,
+            1_000_000,
+            Some(SimulationTime::from_secs(60)),
+        );
+
+        // before the first step, the clock has its initial offset
+        assert_eq!(
+            clock.realtime(at(30)),
+            at(35) + SimulationTime::from_millis(30)
+        );
+
+        // the clock is stepped to the true time, and then drifts again
+        assert_eq!(clock.realtime(at(60)), at(60));
+        assert_eq!(
+            clock.realtime(at(90)),
+            at(90) + SimulationTime::from_millis(30)
+        );
+        assert_eq!(clock.realtime(at(120)), at(120));
+
+        // the monotonic clock is never stepped
+        assert_eq!(
+            clock.monotonic(at(90)),
+            at(90) + SimulationTime::from_millis(90)
+        );
+
+        assert_eq!(
+            clock.realtime_to_true(at(90), at(90) + SimulationTime::from_millis(30)),
+            at(90)
+        );
+    }
+}
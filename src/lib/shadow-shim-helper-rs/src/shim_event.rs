@@ -35,13 +35,42 @@ pub struct ShimEventAddThreadReq {
     pub ctid: UntypedForeignPtr,
     /// clone tls.
     pub newtls: libc::c_ulong,
+    /// When creating a new process (i.e. without `CLONE_VM`), regions of the
+    /// address space that the child must make private before running any
+    /// other code. `ShimEventPrivatizeRegion` array in shim's memory, or NULL.
+    pub privatize_regions: UntypedForeignPtr,
+    /// Number of entries in `privatize_regions`.
+    pub privatize_regions_len: libc::c_ulong,
 }
 
+/// A region of a managed process's address space that is mapped from memory
+/// shared with Shadow. A child process created by `fork` would otherwise
+/// continue sharing this memory with its parent.
+#[derive(Copy, Clone, Debug, VirtualAddressSpaceIndependent)]
+#[repr(C)]
+pub struct ShimEventPrivatizeRegion {
+    pub start: usize,
+    pub len: usize,
+    /// Protection flags that the region should have after being made private.
+    pub prot: i32,
+}
+
+// SAFETY: Any bit pattern is valid, and there are no `UnsafeCell`s.
+unsafe impl shadow_pod::Pod for ShimEventPrivatizeRegion {}
+
 /// Data for [`ShimEventToShadow::AddThreadParentRes`]
 #[derive(Copy, Clone, Debug, VirtualAddressSpaceIndependent)]
 #[repr(C)]
 pub struct ShimEventAddThreadParentRes {
     pub clone_res: i64,
+    /// The calling thread's stack pointer at the `clone` syscall. u8 pointer in
+    /// shim's memory
+    pub stack_pointer: UntypedForeignPtr,
+    /// The calling thread's shim thread local storage, which Shadow must not
+    /// copy between processes. u8 pointer in shim's memory
+    pub shim_tls: UntypedForeignPtr,
+    /// Size of `shim_tls` in bytes.
+    pub shim_tls_len: libc::c_ulong,
 }
 
 /// Data for [`ShimEventToShadow::StartReq`]
@@ -3,6 +3,7 @@ use shadow_shmem::allocator::{ShMemBlock, ShMemBlockSerialized};
 use vasi::VirtualAddressSpaceIndependent;
 use vasi_sync::scmutex::SelfContainedMutex;
 
+use crate::host_clock::HostClock;
 use crate::option::FfiOption;
 use crate::HostId;
 use crate::{
@@ -90,6 +91,9 @@ pub struct HostShmem {
     // Current simulation time.
     pub sim_time: AtomicEmulatedTime,
 
+    // The error of the host's clocks relative to the simulation time.
+    pub clock: HostClock,
+
     pub shim_log_level: logger::LogLevel,
 
     pub manager_shmem: ShMemBlockSerialized,
@@ -106,6 +110,7 @@ impl HostShmem {
         unblocked_vdso_latency: SimulationTime,
         shadow_pid: libc::pid_t,
         tsc_hz: u64,
+        clock: HostClock,
         shim_log_level: ::logger::LogLevel,
         manager_shmem: &ShMemBlock<ManagerShmem>,
     ) -> Self {
@@ -124,6 +129,7 @@ impl HostShmem {
             shadow_pid,
             tsc_hz,
             sim_time: AtomicEmulatedTime::new(EmulatedTime::MIN),
+            clock,
             shim_log_level,
             manager_shmem: manager_shmem.serialize(),
         }
@@ -252,15 +258,16 @@ impl ProcessShmemProtected {
         &mut self,
         thread: &ThreadShmemProtected,
     ) -> Option<(Signal, siginfo_t)> {
-        let pending_unblocked_signals = self.pending_signals & !thread.blocked_signals;
-        if pending_unblocked_signals.is_empty() {
-            None
-        } else {
-            let signal = pending_unblocked_signals.lowest().unwrap();
-            let info = *self.pending_standard_siginfo(signal).unwrap();
-            self.pending_signals.del(signal);
-            Some((signal, info))
-        }
+        self.take_pending_signal_in(!thread.blocked_signals)
+    }
+
+    /// Take the lowest pending signal that is in `set`, such as when reading
+    /// from a signalfd.
+    pub fn take_pending_signal_in(&mut self, set: sigset_t) -> Option<(Signal, siginfo_t)> {
+        let signal = (self.pending_signals & set).lowest()?;
+        let info = *self.pending_standard_siginfo(signal).unwrap();
+        self.pending_signals.del(signal);
+        Some((signal, info))
     }
 }
 
@@ -353,15 +360,16 @@ impl ThreadShmemProtected {
     }
 
     pub fn take_pending_unblocked_signal(&mut self) -> Option<(Signal, siginfo_t)> {
-        let pending_unblocked_signals = self.pending_signals & !self.blocked_signals;
-        if pending_unblocked_signals.is_empty() {
-            None
-        } else {
-            let signal = pending_unblocked_signals.lowest().unwrap();
-            let info = *self.pending_standard_siginfo(signal).unwrap();
-            self.pending_signals.del(signal);
-            Some((signal, info))
-        }
+        self.take_pending_signal_in(!self.blocked_signals)
+    }
+
+    /// Take the lowest pending signal that is in `set`, such as when reading
+    /// from a signalfd.
+    pub fn take_pending_signal_in(&mut self, set: sigset_t) -> Option<(Signal, siginfo_t)> {
+        let signal = (self.pending_signals & set).lowest()?;
+        let info = *self.pending_standard_siginfo(signal).unwrap();
+        self.pending_signals.del(signal);
+        Some((signal, info))
     }
 }
 
@@ -495,6 +503,36 @@ pub mod export {
             .store(EmulatedTime::from_c_emutime(t).unwrap(), Ordering::Relaxed);
     }
 
+    /// Returns the time of the host's realtime clock at the emulated time `t`.
+    ///
+    /// # Safety
+    ///
+    /// Pointer args must be safely dereferenceable.
+    #[no_mangle]
+    pub unsafe extern "C" fn shimshmem_getRealtime(
+        host_mem: *const ShimShmemHost,
+        t: CEmulatedTime,
+    ) -> CEmulatedTime {
+        let host_mem = unsafe { host_mem.as_ref().unwrap() };
+        let t = EmulatedTime::from_c_emutime(t).unwrap();
+        EmulatedTime::to_c_emutime(Some(host_mem.clock.realtime(t)))
+    }
+
+    /// Returns the time of the host's monotonic clocks at the emulated time `t`.
+    ///
+    /// # Safety
+    ///
+    /// Pointer args must be safely dereferenceable.
+    #[no_mangle]
+    pub unsafe extern "C" fn shimshmem_getMonotonicTime(
+        host_mem: *const ShimShmemHost,
+        t: CEmulatedTime,
+    ) -> CEmulatedTime {
+        let host_mem = unsafe { host_mem.as_ref().unwrap() };
+        let t = EmulatedTime::from_c_emutime(t).unwrap();
+        EmulatedTime::to_c_emutime(Some(host_mem.clock.monotonic(t)))
+    }
+
     /// # Safety
     ///
     /// Pointer args must be safely dereferenceable.
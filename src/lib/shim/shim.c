@@ -161,4 +161,24 @@ void _shim_child_init_preload() {
     shim_swapAllowNativeSyscalls(oldNativeSyscallFlag);
 }
 
+void _shim_fork_child_init_preload() {
+    bool oldNativeSyscallFlag = shim_swapAllowNativeSyscalls(true);
+
+    // The child gets its own thread and process shared memory blocks.
+    _shim_ipc_wait_for_start_event();
+
+    // The parent's signal stack is still mapped, but we're currently running on
+    // it, and it's been disarmed for the rest of the current signal handler.
+    _shim_init_signal_stack();
+
+    // Not inherited across fork.
+    _shim_parent_init_death_signal();
+
+    // The child's address space is no longer shared with Shadow; give it its
+    // own MemoryManager.
+    _shim_parent_init_memory_manager();
+
+    shim_swapAllowNativeSyscalls(oldNativeSyscallFlag);
+}
+
 void shim_ensure_init() { _shim_load(); }
\ No newline at end of file
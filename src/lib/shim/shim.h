@@ -26,4 +26,5 @@ const ShimShmemManager* shim_managerSharedMem();
 // Exposed for Rust
 void _shim_parent_init_preload();
 void _shim_child_init_preload();
+void _shim_fork_child_init_preload();
 #endif // SHD_SHIM_SHIM_H_
@@ -10,6 +10,7 @@
 #include <errno.h>
 #include <fcntl.h>
 #include <glib.h>
+#include <limits.h>
 #include <netdb.h>
 #include <stdarg.h>
 #include <stdio.h>
@@ -89,8 +90,8 @@ static int _getaddrinfo_service(in_port_t* port, const char* service,
 // Creates an `addrinfo` pointing to `addr`, and adds it to the linked list
 // specified by `head` and `tail`. An empty list can be passed in by setting
 // `*head` and `*tail` to NULL.
-static void _getaddrinfo_append(struct addrinfo** head, struct addrinfo** tail, int socktype,
-                                struct sockaddr* addr, socklen_t addrlen) {
+static void _getaddrinfo_append(struct addrinfo** head, struct addrinfo** tail, int family,
+                                int socktype, struct sockaddr* addr, socklen_t addrlen) {
     int protocol = 0;
     if (socktype == SOCK_DGRAM) {
         protocol = IPPROTO_UDP;
@@ -103,7 +104,7 @@ static void _getaddrinfo_append(struct addrinfo** head, struct addrinfo** tail,
     }
     struct addrinfo* new_tail = malloc(sizeof(*new_tail));
     *new_tail = (struct addrinfo){.ai_flags = 0,
-                                  .ai_family = AF_INET,
+                                  .ai_family = family,
                                   .ai_socktype = socktype,
                                   .ai_protocol = protocol,
                                   .ai_addrlen = addrlen,
@@ -126,17 +127,39 @@ static void _getaddrinfo_appendv4(struct addrinfo** head, struct addrinfo** tail
     if (add_tcp) {
         struct sockaddr_in* sai = malloc(sizeof(*sai));
         *sai = (struct sockaddr_in){.sin_family = AF_INET, .sin_port = port, .sin_addr = {s_addr}};
-        _getaddrinfo_append(head, tail, SOCK_STREAM, (struct sockaddr*)sai, sizeof(*sai));
+        _getaddrinfo_append(head, tail, AF_INET, SOCK_STREAM, (struct sockaddr*)sai, sizeof(*sai));
     }
     if (add_udp) {
         struct sockaddr_in* sai = malloc(sizeof(*sai));
         *sai = (struct sockaddr_in){.sin_family = AF_INET, .sin_port = port, .sin_addr = {s_addr}};
-        _getaddrinfo_append(head, tail, SOCK_DGRAM, (struct sockaddr*)sai, sizeof(*sai));
+        _getaddrinfo_append(head, tail, AF_INET, SOCK_DGRAM, (struct sockaddr*)sai, sizeof(*sai));
     }
     if (add_raw) {
         struct sockaddr_in* sai = malloc(sizeof(*sai));
         *sai = (struct sockaddr_in){.sin_family = AF_INET, .sin_port = port, .sin_addr = {s_addr}};
-        _getaddrinfo_append(head, tail, SOCK_RAW, (struct sockaddr*)sai, sizeof(*sai));
+        _getaddrinfo_append(head, tail, AF_INET, SOCK_RAW, (struct sockaddr*)sai, sizeof(*sai));
+    }
+}
+
+// IPv6 wrapper for _getaddrinfo_append. Appends an entry for the address and
+// port for each requested socket type.
+static void _getaddrinfo_appendv6(struct addrinfo** head, struct addrinfo** tail, bool add_tcp,
+                                  bool add_udp, bool add_raw, const struct in6_addr* addr,
+                                  in_port_t port) {
+    if (add_tcp) {
+        struct sockaddr_in6* sai = malloc(sizeof(*sai));
+        *sai = (struct sockaddr_in6){.sin6_family = AF_INET6, .sin6_port = port, .sin6_addr = *addr};
+        _getaddrinfo_append(head, tail, AF_INET6, SOCK_STREAM, (struct sockaddr*)sai, sizeof(*sai));
+    }
+    if (add_udp) {
+        struct sockaddr_in6* sai = malloc(sizeof(*sai));
+        *sai = (struct sockaddr_in6){.sin6_family = AF_INET6, .sin6_port = port, .sin6_addr = *addr};
+        _getaddrinfo_append(head, tail, AF_INET6, SOCK_DGRAM, (struct sockaddr*)sai, sizeof(*sai));
+    }
+    if (add_raw) {
+        struct sockaddr_in6* sai = malloc(sizeof(*sai));
+        *sai = (struct sockaddr_in6){.sin6_family = AF_INET6, .sin6_port = port, .sin6_addr = *addr};
+        _getaddrinfo_append(head, tail, AF_INET6, SOCK_RAW, (struct sockaddr*)sai, sizeof(*sai));
     }
 }
 
@@ -259,6 +282,44 @@ static bool _shim_api_hostname_to_addr_ipv4(const char* node, uint32_t* addr) {
     }
 }
 
+// Ask shadow to provide an ipv6 addr for a node using a custom syscall.
+// Returns true if we got a valid address from shadow, false otherwise (for
+// example if the host doesn't have an IPv6 address).
+static bool _shim_api_hostname_to_addr_ipv6(const char* node, struct in6_addr* addr) {
+    if (!node || !addr) {
+        return false;
+    }
+
+    // Skip the Shadow syscall for localhost lookups.
+    if (strcasecmp(node, "localhost") == 0) {
+        *addr = in6addr_loopback;
+        trace("handled localhost getaddrinfo() lookup locally");
+        return true;
+    }
+
+    trace("Performing custom shadow syscall SYS_shadow_hostname_to_addr_ipv6 for name %s", node);
+    int rv = shim_syscall(
+        NULL, SYS_shadow_hostname_to_addr_ipv6, node, strlen(node), addr, sizeof(*addr));
+
+    if (rv == 0) {
+        trace("SYS_shadow_hostname_to_addr_ipv6 succeeded for name %s", node);
+        return true;
+    } else {
+        trace("SYS_shadow_hostname_to_addr_ipv6 failed for name %s", node);
+        return false;
+    }
+}
+
+// Returns true if the local host has been assigned an IPv6 address.
+static bool _shim_api_host_has_ipv6_address() {
+    char hostname[HOST_NAME_MAX + 1] = {0};
+    if (gethostname(hostname, sizeof(hostname)) != 0) {
+        return false;
+    }
+    struct in6_addr addr;
+    return _shim_api_hostname_to_addr_ipv6(hostname, &addr);
+}
+
 int shimc_api_getaddrinfo(const char* node, const char* service, const struct addrinfo* hints,
                          struct addrinfo** res) {
     // Quoted text is from the man page.
@@ -310,10 +371,11 @@ int shimc_api_getaddrinfo(const char* node, const char* service, const struct ad
     // returned only if the local system has at least one IPv6 address
     // configured."
     //
-    // Determining what kind of addresses the local system has configured is
-    // unimplemented. For now we assume it has IPv4 and not IPv6.
+    // Every host has an IPv4 address, but only hosts configured with
+    // `ipv6_addr` have an IPv6 address.
     const bool system_has_an_ipv4_address = true;
-    const bool system_has_an_ipv6_address = false;
+    const bool system_has_an_ipv6_address =
+        (hints->ai_flags & AI_ADDRCONFIG) ? _shim_api_host_has_ipv6_address() : true;
 
     // "There are several reasons why the linked list may have more than one
     // addrinfo structure, including: the network host is ... accessible  over
@@ -353,7 +415,7 @@ int shimc_api_getaddrinfo(const char* node, const char* service, const struct ad
                     res, &tail, add_tcp, add_udp, add_raw, ntohl(INADDR_ANY), port);
             }
             if (add_ipv6) {
-                // TODO: IPv6
+                _getaddrinfo_appendv6(res, &tail, add_tcp, add_udp, add_raw, &in6addr_any, port);
             }
         } else {
             // "If the AI_PASSIVE flag is not set in hints.ai_flags, then the
@@ -367,7 +429,8 @@ int shimc_api_getaddrinfo(const char* node, const char* service, const struct ad
                     res, &tail, add_tcp, add_udp, add_raw, ntohl(INADDR_LOOPBACK), port);
             }
             if (add_ipv6) {
-                // TODO: IPv6
+                _getaddrinfo_appendv6(
+                    res, &tail, add_tcp, add_udp, add_raw, &in6addr_loopback, port);
             }
         }
         // We've finished adding all relevant addresses.
@@ -380,7 +443,10 @@ int shimc_api_getaddrinfo(const char* node, const char* service, const struct ad
 
     // "`node` specifies either a numerical network address..."
     if (add_ipv6) {
-        // TODO: try parsing as IPv6
+        struct in6_addr addr;
+        if (inet_pton(AF_INET6, node, &addr) == 1) {
+            _getaddrinfo_appendv6(res, &tail, add_tcp, add_udp, add_raw, &addr, port);
+        }
     }
     if (add_ipv4) {
         uint32_t addr;
@@ -413,9 +479,9 @@ int shimc_api_getaddrinfo(const char* node, const char* service, const struct ad
     // order in which to try lookups.  We just hard-code trying `files` first
     // (and for now, only). For hosts lookups, the corresponding file is
     // /etc/hosts. See NSSWITCH.CONF(5).
-    if (add_ipv6) {
-        // TODO: look for IPv6 addresses in /etc/hosts.
-    }
+    //
+    // IPv4 addresses are returned before IPv6 addresses, which differs from
+    // the default RFC 6724 ordering used by glibc.
     if (add_ipv4) {
         // Try first to avoid scanning the /etc/hosts file.
         uint32_t addr;
@@ -430,6 +496,14 @@ int shimc_api_getaddrinfo(const char* node, const char* service, const struct ad
             _getaddrinfo_add_matching_hosts_ipv4(res, &tail, node, add_tcp, add_udp, add_raw, port);
         }
     }
+    if (add_ipv6) {
+        // Hosts without an IPv6 address have no IPv6 entry in /etc/hosts, so
+        // there's nothing to fall back to.
+        struct in6_addr addr;
+        if (_shim_api_hostname_to_addr_ipv6(node, &addr)) {
+            _getaddrinfo_appendv6(res, &tail, add_tcp, add_udp, add_raw, &addr, port);
+        }
+    }
 
     // TODO: maybe do DNS lookup, if we end up supporting that in Shadow.
 
@@ -33,6 +33,28 @@ static CEmulatedTime _shim_sys_get_time() {
     return shimshmem_getEmulatedTime(mem);
 }
 
+// The time of the host's realtime clock, which may disagree with the simulation time.
+static CEmulatedTime _shim_sys_get_realtime() {
+    return shimshmem_getRealtime(shim_hostSharedMem(), _shim_sys_get_time());
+}
+
+// The time of the clock `clk_id`, which must be valid.
+static CEmulatedTime _shim_sys_get_clock_time(clockid_t clk_id) {
+    switch (clk_id) {
+        case LINUX_CLOCK_REALTIME:
+        case LINUX_CLOCK_REALTIME_COARSE:
+        case LINUX_CLOCK_REALTIME_ALARM:
+        case LINUX_CLOCK_TAI: return _shim_sys_get_realtime();
+        case LINUX_CLOCK_MONOTONIC:
+        case LINUX_CLOCK_MONOTONIC_RAW:
+        case LINUX_CLOCK_MONOTONIC_COARSE:
+        case LINUX_CLOCK_BOOTTIME:
+        case LINUX_CLOCK_BOOTTIME_ALARM:
+            return shimshmem_getMonotonicTime(shim_hostSharedMem(), _shim_sys_get_time());
+        default: return _shim_sys_get_time();
+    }
+}
+
 uint64_t shim_sys_get_simtime_nanos() {
     return emutime_sub_emutime(_shim_sys_get_time(), EMUTIME_SIMULATION_START) /
            SIMTIME_ONE_NANOSECOND;
@@ -68,8 +90,6 @@ bool shim_sys_handle_syscall_locally(long syscall_num, long* rv, va_list args) {
         case SYS_clock_gettime: {
             syscallName = "clock_gettime";
 
-            CEmulatedTime emulated_time = _shim_sys_get_time();
-
             trace("servicing syscall %ld:clock_gettime from the shim", syscall_num);
 
             clockid_t clk_id = va_arg(args, clockid_t);
@@ -79,6 +99,7 @@ bool shim_sys_handle_syscall_locally(long syscall_num, long* rv, va_list args) {
                 trace("found invalid clock id %ld", (long)clk_id);
                 *rv = -EINVAL;
             } else if (tp) {
+                CEmulatedTime emulated_time = _shim_sys_get_clock_time(clk_id);
                 *tp = (struct timespec){
                     .tv_sec = emulated_time / SIMTIME_ONE_SECOND,
                     .tv_nsec = emulated_time % SIMTIME_ONE_SECOND,
@@ -96,7 +117,7 @@ bool shim_sys_handle_syscall_locally(long syscall_num, long* rv, va_list args) {
         case SYS_time: {
             syscallName = "time";
 
-            CEmulatedTime emulated_time = _shim_sys_get_time();
+            CEmulatedTime emulated_time = _shim_sys_get_realtime();
             time_t now = emulated_time / SIMTIME_ONE_SECOND;
 
             trace("servicing syscall %ld:time from the shim", syscall_num);
@@ -115,7 +136,7 @@ bool shim_sys_handle_syscall_locally(long syscall_num, long* rv, va_list args) {
         case SYS_gettimeofday: {
             syscallName = "gettimeofday";
 
-            CEmulatedTime emulated_time = _shim_sys_get_time();
+            CEmulatedTime emulated_time = _shim_sys_get_realtime();
             uint64_t micros = emulated_time / SIMTIME_ONE_MICROSECOND;
 
             trace("servicing syscall %ld:gettimeofday from the shim", syscall_num);
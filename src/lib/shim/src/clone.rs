@@ -1,5 +1,6 @@
 use linux_api::ucontext::{sigcontext, ucontext};
-use shadow_shim_helper_rs::shim_event::ShimEventAddThreadReq;
+use rustix::fd::{AsRawFd, RawFd};
+use shadow_shim_helper_rs::shim_event::{ShimEventAddThreadReq, ShimEventPrivatizeRegion};
 use shadow_shmem::allocator::ShMemBlockSerialized;
 
 /// Used below to validate the offset of `field` from `base`.
@@ -128,6 +129,11 @@ unsafe extern "C" fn tls_ipc_set(blk: *const ShMemBlockSerialized) {
 /// * `child_stack` must be "sufficiently big" for the child thread to run on.
 /// * `tls` if provided must point to correctly initialized thread local storage.
 pub unsafe fn do_clone(ctx: &ucontext, event: &ShimEventAddThreadReq) -> i64 {
+    if event.flags & (libc::CLONE_VM as u64) == 0 {
+        // Creating a new process rather than a new thread.
+        return unsafe { do_fork(ctx, event) };
+    }
+
     let flags = event.flags;
     let ptid: *mut i32 = event.ptid.cast::<i32>().into_raw_mut();
     let ctid: *mut i32 = event.ctid.cast::<i32>().into_raw_mut();
@@ -234,3 +240,214 @@ pub unsafe fn do_clone(ctx: &ucontext, event: &ShimEventAddThreadReq) -> i64 {
     }
     rv
 }
+
+/// Arguments for `init_fork_child`.
+#[repr(C)]
+struct ForkChildArgs<'a> {
+    event: &'a ShimEventAddThreadReq,
+    /// Write end of a pipe that the parent is waiting on.
+    done_writer: RawFd,
+    /// Read end of the same pipe; only needs to be closed in the child.
+    done_reader: RawFd,
+}
+
+/// Replace each region in `event`'s privatize list, which in a newly forked
+/// child is still mapped from memory shared with the parent process (and
+/// Shadow), with a private copy of its current contents.
+///
+/// Doesn't use thread local storage or allocate memory.
+///
+/// # Safety
+///
+/// Must only be called in a newly forked child, before running any code that
+/// could access the listed regions, and while the parent is suspended.
+unsafe fn privatize_regions(event: &ShimEventAddThreadReq) {
+    use rustix::mm::{MapFlags, MprotectFlags, MremapFlags, ProtFlags};
+
+    let regions: *const ShimEventPrivatizeRegion = event
+        .privatize_regions
+        .cast::<ShimEventPrivatizeRegion>()
+        .into_raw();
+    let len = usize::try_from(event.privatize_regions_len).unwrap();
+    for i in 0..len {
+        let region = unsafe { regions.add(i).read() };
+        let start = region.start as *mut core::ffi::c_void;
+
+        let private = unsafe {
+            rustix::mm::mmap_anonymous(
+                core::ptr::null_mut(),
+                region.len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::PRIVATE,
+            )
+        }
+        .unwrap();
+        // The region may not currently be readable.
+        unsafe { rustix::mm::mprotect(start, region.len, MprotectFlags::READ) }.unwrap();
+        unsafe {
+            core::ptr::copy_nonoverlapping(start as *const u8, private as *mut u8, region.len)
+        };
+        // Atomically replaces the shared mapping.
+        unsafe {
+            rustix::mm::mremap_fixed(private, region.len, region.len, MremapFlags::MAYMOVE, start)
+        }
+        .unwrap();
+        unsafe {
+            rustix::mm::mprotect(
+                start,
+                region.len,
+                MprotectFlags::from_bits_retain(region.prot as u32),
+            )
+        }
+        .unwrap();
+    }
+}
+
+/// Helper for `do_fork`, run in the child process before restoring the
+/// managed code's context.
+///
+/// Uses C ABI so that we can call from `asm`.
+///
+/// # Safety
+///
+/// Must only be called from the child branch of `do_fork`.
+unsafe extern "C" fn init_fork_child(args: &ForkChildArgs) {
+    // Nothing else may touch memory that's still shared with the parent until
+    // this is done; notably including thread local storage.
+    unsafe { privatize_regions(args.event) };
+
+    // The list itself was allocated privately in the parent before forking;
+    // the child doesn't need its copy.
+    if !args.event.privatize_regions.is_null() {
+        let len = usize::try_from(args.event.privatize_regions_len).unwrap()
+            * core::mem::size_of::<ShimEventPrivatizeRegion>();
+        unsafe { rustix::mm::munmap(args.event.privatize_regions.into_raw_mut().cast(), len) }
+            .unwrap();
+    }
+
+    // Let the parent continue, now that it's safe for it to modify its memory.
+    let done_writer = unsafe { rustix::fd::BorrowedFd::borrow_raw(args.done_writer) };
+    rustix::io::write(done_writer, &[0]).unwrap();
+    unsafe { rustix::io::close(args.done_writer) };
+    unsafe { rustix::io::close(args.done_reader) };
+
+    // Thread local storage was copied from the forking thread; none of it
+    // applies to the child.
+    // SAFETY: The parent's frames on this stack, which may hold references,
+    // are never returned to.
+    unsafe { crate::SHIM_TLS.reset_current_thread() };
+
+    // SAFETY: Shadow passes a block that outlives the new process.
+    unsafe { tls_ipc_set(&args.event.ipc_block) };
+
+    unsafe { crate::bindings::_shim_fork_child_init_preload() };
+    log::trace!("Finished shim fork child init");
+}
+
+/// Execute a native `clone` syscall that creates a new process (i.e. without
+/// `CLONE_VM`). As in `do_clone`, the child resumes execution from `ctx`.
+///
+/// The child continues on its copy of the current (signal handler) stack until
+/// restoring `ctx`; `event.child_stack`, if non-null, is only used as the stack
+/// pointer in the restored context. Memory regions that the parent shares with
+/// Shadow are made private in the child, as listed in `event`, before the
+/// parent is allowed to continue.
+///
+/// # Safety
+///
+/// * `ctx` must be dereferenceable, and must be safe for the newly spawned
+/// child process to restore.
+/// * Other pointers, if non-null, must be safely dereferenceable.
+/// * `event.privatize_regions` must describe all regions of the address space
+/// that are shared with Shadow.
+unsafe fn do_fork(ctx: &ucontext, event: &ShimEventAddThreadReq) -> i64 {
+    let child_stack: *mut u8 = event.child_stack.cast::<u8>().into_raw_mut();
+
+    // The child restores this context, switching to the requested stack if
+    // any. It's stored on the current stack, of which the child gets its own
+    // copy.
+    let mut child_sigcontext: sigcontext = ctx.uc_mcontext;
+    if !child_stack.is_null() {
+        child_sigcontext.rsp = child_stack as u64;
+    }
+
+    let ptid: *mut i32 = event.ptid.cast::<i32>().into_raw_mut();
+    let ctid: *mut i32 = event.ctid.cast::<i32>().into_raw_mut();
+
+    // The parent must not modify any of the regions that are still shared
+    // until the child has made its own copies; including e.g. any thread
+    // local storage. We use a pipe so that the parent can block until then,
+    // without either side using shared memory to synchronize.
+    let (done_reader, done_writer) = rustix::pipe::pipe_with(rustix::pipe::PipeFlags::CLOEXEC)
+        .expect("Couldn't create pipe for fork");
+    let child_args = ForkChildArgs {
+        event,
+        done_writer: done_writer.as_raw_fd(),
+        done_reader: done_reader.as_raw_fd(),
+    };
+
+    let rv: i64;
+    // SAFETY: See `do_clone`. In this case the child continues on its private
+    // copy of the current stack, never returning from this block.
+    unsafe {
+        core::arch::asm!(
+            // Make the clone syscall
+            "syscall",
+            // If in the parent, exit the asm block.
+            "cmp rax, 0",
+            "jne 2f",
+
+            // Ensure stack is 16-aligned so that we can safely make function
+            // calls. We never return, so don't need to restore it.
+            "and rsp, -16",
+
+            // Initialize state for the new process
+            "mov rdi, r13",
+            "call {init_fork_child}",
+
+            // Set CPU state from ctx
+            "mov rdi, r12",
+            "call {set_context}",
+
+            "2:",
+            // clone syscall number in, rv out
+            inout("rax") libc::SYS_clone => rv,
+            // clone syscall arg1
+            in("rdi") event.flags,
+            // clone syscall arg2: The child initially continues on (its copy of)
+            // the current stack.
+            in("rsi") core::ptr::null_mut::<u8>(),
+            // clone syscall arg3
+            in("rdx") ptid,
+            // clone syscall arg4
+            in("r10") ctid,
+            // clone syscall arg5
+            in("r8") event.newtls,
+            // callee-saved registers
+            in("r12") &child_sigcontext as *const sigcontext,
+            in("r13") &child_args as *const ForkChildArgs,
+            init_fork_child = sym init_fork_child,
+            set_context = sym set_context,
+            // clobbered by the syscall instruction
+            lateout("rcx") _,
+            lateout("r11") _,
+        )
+    }
+
+    // Close our copy of the write end, so that the read below returns EOF if
+    // the child dies before signalling.
+    drop(done_writer);
+    if rv > 0 {
+        let mut buf = [0u8; 1];
+        loop {
+            match rustix::io::read(&done_reader, &mut buf) {
+                Err(rustix::io::Errno::INTR) => continue,
+                res => {
+                    res.expect("Couldn't wait for fork child");
+                    break;
+                }
+            }
+        }
+    }
+    rv
+}
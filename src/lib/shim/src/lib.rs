@@ -329,48 +329,50 @@ mod global_host_shmem {
     }
 }
 
-// TODO: we'll probably need to make this per-thread to support fork.
 mod global_process_shmem {
-    use super::*;
+    use core::sync::atomic::{AtomicPtr, Ordering};
 
-    // This is set explicitly, so needs a Mutex.
-    static INITIALIZER: SelfContainedMutex<Option<ShMemBlockSerialized>> =
-        SelfContainedMutex::const_new(None);
+    use super::*;
+    use crate::mmap_box::MmapBox;
 
-    // The actual block is in a `LazyLock`, which is much faster to access.
-    // It uses `INITIALIZER` to do its one-time init.
-    static SHMEM: LazyLock<ShMemBlockAlias<ProcessShmem>> = LazyLock::const_new(|| {
-        let serialized = INITIALIZER.lock().take().unwrap();
-        unsafe { Serializer::global().deserialize(&serialized) }
-    });
+    // This is set explicitly. Unlike the host and manager blocks it can be set
+    // again, since a child process created by `fork` needs its own block.
+    //
+    // Blocks are leaked, so a non-null pointer is valid for the rest of the
+    // process's lifetime.
+    static SHMEM: AtomicPtr<ShMemBlockAlias<'static, ProcessShmem>> =
+        AtomicPtr::new(core::ptr::null_mut());
 
+    /// Sets the process's shared memory block. If already set (as in a child
+    /// process created by `fork`), the previous block is replaced.
+    ///
     /// # Safety
     ///
     /// `blk` must contained a serialized block referencing a `ShMemBlock` of type `ProcessShmem`.
     /// The `ShMemBlock` must outlive this process.
+    ///
+    /// If a block was previously set, no references to it obtained via `get`
+    /// or `try_get` may be used afterwards.
     pub unsafe fn set(blk: &ShMemBlockSerialized) {
-        assert!(!SHMEM.initd());
-        assert!(INITIALIZER.lock().replace(*blk).is_none());
-        // Ensure that `try_get` returns true (without it having to take the
-        // `INITIALIZER` lock to check), and that we fail early if `SHMEM` can't
-        // actually be initialized.
-        SHMEM.force();
+        let shmem: ShMemBlockAlias<ProcessShmem> = unsafe { Serializer::global().deserialize(blk) };
+        let shmem = MmapBox::leak(MmapBox::new(shmem));
+        // The previous block, if any, is leaked. This only happens once per `fork`.
+        SHMEM.store(shmem, Ordering::Release);
     }
 
     /// Panics if `set` hasn't been called yet.
     pub fn get() -> impl core::ops::Deref<Target = ShMemBlockAlias<'static, ProcessShmem>> + 'static
     {
-        SHMEM.force()
+        try_get().unwrap()
     }
 
     pub fn try_get(
     ) -> Option<impl core::ops::Deref<Target = ShMemBlockAlias<'static, ProcessShmem>> + 'static>
     {
-        if !SHMEM.initd() {
-            None
-        } else {
-            Some(get())
-        }
+        let shmem = SHMEM.load(Ordering::Acquire);
+        // SAFETY: Blocks are never freed, and `set`'s caller ensures we don't
+        // still use a reference after it's been replaced.
+        unsafe { shmem.as_ref() }
     }
 }
 
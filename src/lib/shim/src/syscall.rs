@@ -11,7 +11,7 @@ use shadow_shim_helper_rs::shim_event::{
     ShimEventAddThreadParentRes, ShimEventSyscall, ShimEventSyscallComplete, ShimEventToShadow,
     ShimEventToShim,
 };
-use shadow_shim_helper_rs::syscall_types::{SysCallArgs, SysCallReg};
+use shadow_shim_helper_rs::syscall_types::{ForeignPtr, SysCallArgs, SysCallReg};
 use shadow_shim_helper_rs::util::time::TimeParts;
 
 use crate::{bindings, global_host_shmem, tls_ipc, tls_thread_shmem};
@@ -160,10 +160,17 @@ unsafe fn emulated_syscall_event(
             ShimEventToShim::AddThreadReq(r) => {
                 // Create a new native thread under our control
 
-                let clone_res = unsafe { crate::clone::do_clone(ctx.as_mut().unwrap(), &r) };
+                let ctx = ctx.as_deref().unwrap();
+                let clone_res = unsafe { crate::clone::do_clone(ctx, &r) };
+                let (shim_tls, shim_tls_len) = crate::SHIM_TLS.current_thread_storage_range();
                 tls_ipc::with(|ipc| {
                     ipc.to_shadow().send(ShimEventToShadow::AddThreadParentRes(
-                        ShimEventAddThreadParentRes { clone_res },
+                        ShimEventAddThreadParentRes {
+                            clone_res,
+                            stack_pointer: ForeignPtr::from(ctx.uc_mcontext.rsp),
+                            shim_tls: ForeignPtr::from(shim_tls),
+                            shim_tls_len: shim_tls_len.try_into().unwrap(),
+                        },
                     ))
                 })
             }
@@ -383,6 +383,28 @@ impl ThreadLocalStorage {
         // any previous thread with this `id` has been removed.
         unsafe { storages.remove(id.to_nonzero_usize()) };
     }
+
+    /// Reset all of the current thread's variables to their uninitialized
+    /// state. Intended for use in a child process created by `fork`, which
+    /// would otherwise inherit the values of the forking thread.
+    ///
+    /// # Safety
+    ///
+    /// There must not be any live references to this thread's [`ShimTlsVar`]s.
+    pub unsafe fn reset_current_thread(&self) {
+        let storage = self.current_thread_storage();
+        let bytes: *mut [MaybeUninit<u8>; BYTES_PER_THREAD] = storage.bytes.get();
+        // SAFETY: All zeroes is the uninitialized state for every variable,
+        // and caller guarantees there are no references into the storage.
+        unsafe { core::ptr::write_bytes(bytes, 0, 1) };
+    }
+
+    /// The address and size of the current thread's storage. Intended for
+    /// telling Shadow which memory of the thread belongs to the shim.
+    pub fn current_thread_storage_range(&self) -> (usize, usize) {
+        let storage = self.current_thread_storage();
+        (storage.bytes.get() as usize, BYTES_PER_THREAD)
+    }
 }
 
 enum TlsOneThreadBackingStoreRef<'tls> {
@@ -143,6 +143,8 @@ fn run_bindgen(build_common: &ShadowBuildCommon) {
         .header("host/descriptor/regular_file.h")
         .header("host/descriptor/tcp_cong.h")
         .header("host/descriptor/tcp_cong_reno.h")
+        .header("host/descriptor/tcp_cong_cubic.h")
+        .header("host/descriptor/tcp_cong_bbr.h")
         .header("host/futex.h")
         .header("host/process.h")
         .header("host/status.h")
@@ -227,7 +229,11 @@ fn run_bindgen(build_common: &ShadowBuildCommon) {
         .allowlist_type("ProtocolTCPFlags")
         .allowlist_type("PacketDeliveryStatusFlags")
         .allowlist_var("AFFINITY_UNINIT")
+        .allowlist_var("CONFIG_HEADER_SIZE_IP")
+        .allowlist_var("CONFIG_HEADER_SIZE_IPV6")
         .allowlist_var("CONFIG_HEADER_SIZE_TCP")
+        .allowlist_var("CONFIG_HEADER_SIZE_ICMP")
+        .allowlist_var("CONFIG_IP_DEFAULT_TTL")
         .allowlist_var("CONFIG_PIPE_BUFFER_SIZE")
         .allowlist_var("CONFIG_MTU")
         .allowlist_var("SYSCALL_IO_BUFSIZE")
@@ -235,6 +241,8 @@ fn run_bindgen(build_common: &ShadowBuildCommon) {
         .allowlist_var("SUID_DUMP_USER")
         .allowlist_var("SUID_DUMP_DISABLE")
         .allowlist_var("TCP_CONG_RENO_NAME")
+        .allowlist_var("TCP_CONG_CUBIC_NAME")
+        .allowlist_var("TCP_CONG_BBR_NAME")
         .opaque_type("SysCallCondition")
         .opaque_type("LegacyFile")
         .opaque_type("Manager")
@@ -280,7 +288,7 @@ fn run_bindgen(build_common: &ShadowBuildCommon) {
         .raw_line("use crate::host::descriptor::socket::inet::{InetSocket, InetSocketWeak};")
         .raw_line("use crate::host::host::Host;")
         .raw_line("use crate::host::memory_manager::MemoryManager;")
-        .raw_line("use crate::host::process::Process;")
+        .raw_line("use crate::host::process::{ChildEventSource, Process};")
         .raw_line("use crate::host::syscall::handler::SyscallHandler;")
         .raw_line("use crate::host::syscall_types::SyscallReturn;")
         .raw_line("use crate::host::thread::Thread;")
@@ -342,13 +350,14 @@ fn build_shadow_c(build_common: &ShadowBuildCommon) {
         "host/descriptor/tcp.c",
         "host/descriptor/tcp_cong.c",
         "host/descriptor/tcp_cong_reno.c",
+        "host/descriptor/tcp_cong_cubic.c",
+        "host/descriptor/tcp_cong_bbr.c",
         "host/process.c",
         "host/futex.c",
         "host/futex_table.c",
         "host/syscall_handler.c",
         "host/syscall_types.c",
         "host/syscall/protected.c",
-        "host/syscall/epoll.c",
         "host/syscall/fcntl.c",
         "host/syscall/file.c",
         "host/syscall/fileat.c",
@@ -2,8 +2,8 @@ use std::collections::{BTreeMap, HashMap};
 use std::ffi::{CStr, CString, OsStr, OsString};
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicU32;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{self, Context};
@@ -21,6 +21,7 @@ use shadow_shmem::allocator::ShMemBlock;
 use crate::core::controller::{Controller, ShadowStatusBarState, SimController};
 use crate::core::cpu;
 use crate::core::resource_usage;
+use crate::core::scheduler::placement;
 use crate::core::scheduler::runahead::Runahead;
 use crate::core::scheduler::{HostIter, Scheduler, ThreadPerCoreSched, ThreadPerHostSched};
 use crate::core::sim_config::{Bandwidth, HostInfo};
@@ -250,6 +251,27 @@ impl<'a> Manager<'a> {
                 .unwrap(),
         );
 
+        // the lowest latency of any path into each host, which is how far each host can run past
+        // the start of a round
+        let host_min_latencies = self
+            .config
+            .experimental
+            .use_per_host_runahead
+            .unwrap()
+            .then(|| {
+                let node_latencies = manager_config.routing_info.get_smallest_latencies_to_ns();
+                manager_config
+                    .hosts
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, host)| {
+                        let latency = node_latencies.get(&host.network_node_id)?;
+                        let id = HostId::from(u32::try_from(i).unwrap());
+                        Some((id, SimulationTime::from_nanos(*latency)))
+                    })
+                    .collect()
+            });
+
         let dns = unsafe { c::dns_new() };
         assert!(!dns.is_null());
 
@@ -279,6 +301,24 @@ impl<'a> Manager<'a> {
         // shuffle the list of hosts to make sure that they are randomly assigned by the scheduler
         hosts.shuffle(&mut manager_config.random);
 
+        // hosts are only placed by the thread-per-core scheduler
+        let use_graph_placement = matches!(
+            self.config.experimental.scheduler.unwrap(),
+            configuration::Scheduler::ThreadPerCore
+        ) && self.config.experimental.host_placement.unwrap()
+            == configuration::HostPlacement::Graph;
+
+        // the network graph node of each host, indexed by host ID
+        let host_nodes: Vec<u32> = manager_config
+            .hosts
+            .iter()
+            .map(|x| x.network_node_id)
+            .collect();
+
+        // the time spent executing each host since the hosts were last placed, indexed by host ID
+        let host_exec_times: Vec<AtomicU64> =
+            host_nodes.iter().map(|_| AtomicU64::new(0)).collect();
+
         let use_cpu_pinning = self.config.experimental.use_cpu_pinning.unwrap();
 
         // an infinite iterator that always returns `<Option<Option<u32>>>::Some`
@@ -321,6 +361,7 @@ impl<'a> Manager<'a> {
                     self.config.experimental.use_dynamic_runahead.unwrap(),
                     smallest_latency,
                     min_runahead_config,
+                    host_min_latencies,
                 ),
                 child_pid_watcher: ChildPidWatcher::new(),
                 event_queues: hosts
@@ -329,6 +370,7 @@ impl<'a> Manager<'a> {
                     .collect(),
                 bootstrap_end_time,
                 sim_end_time: self.end_time,
+                count_host_packets: use_graph_placement,
             });
 
         // scope used so that the scheduler is dropped before we log the global counters below
@@ -346,6 +388,11 @@ impl<'a> Manager<'a> {
                 }
             };
 
+            if use_graph_placement {
+                let threads = placement::group_by_node(scheduler.parallelism(), &host_nodes);
+                scheduler.assign_hosts(|host| threads[host_index(host.id())]);
+            }
+
             // initialize the thread-local Worker
             scheduler.scope(|s| {
                 s.run(|thread_id| {
@@ -387,6 +434,10 @@ impl<'a> Manager<'a> {
 
             let mut last_heartbeat = EmulatedTime::SIMULATION_START;
             let mut time_of_last_usage_check = std::time::Instant::now();
+            let mut time_of_last_placement = std::time::Instant::now();
+
+            // a reference that can be moved into the closures below
+            let host_exec_times = &host_exec_times;
 
             // the scheduling loop
             while let Some((window_start, window_end)) = window {
@@ -411,12 +462,21 @@ impl<'a> Manager<'a> {
                             let mut next_event_time = next_event_time.borrow_mut();
 
                             worker::Worker::reset_next_event_time();
+                            worker::Worker::set_round_start_time(window_start);
                             worker::Worker::set_round_end_time(window_end);
 
                             for_each_host(hosts, |host| {
                                 let host_next_event_time = {
                                     host.lock_shmem();
-                                    host.execute(window_end);
+                                    let host_end = worker::Worker::host_round_end_time(host.id());
+                                    let start = use_graph_placement.then(std::time::Instant::now);
+                                    host.execute(host_end);
+                                    if let Some(start) = start {
+                                        let elapsed = start.elapsed().as_nanos();
+                                        let elapsed = u64::try_from(elapsed).unwrap_or(u64::MAX);
+                                        host_exec_times[host_index(host.id())]
+                                            .fetch_add(elapsed, Ordering::Relaxed);
+                                    }
                                     let host_next_event_time = host.next_event_time();
                                     host.unlock_shmem();
                                     host_next_event_time
@@ -470,6 +530,13 @@ impl<'a> Manager<'a> {
                     (min_next_event_time - EmulatedTime::SIMULATION_START).as_nanos(),
                 );
 
+                // regroup the hosts every 10 real seconds
+                if use_graph_placement && time_of_last_placement.elapsed() > Duration::from_secs(10)
+                {
+                    time_of_last_placement = std::time::Instant::now();
+                    reassign_hosts(&mut scheduler, host_exec_times);
+                }
+
                 // notify controller that we finished this round, and the time of our next event in
                 // order to fast-forward our execute window if possible
                 window = self
@@ -568,9 +635,11 @@ impl<'a> Manager<'a> {
                 node_id: host_info.network_node_id,
                 ip_addr: match host_info.ip_addr.unwrap() {
                     std::net::IpAddr::V4(ip) => u32::to_be(ip.into()),
-                    // the config only allows ipv4 addresses, so this shouldn't happen
-                    std::net::IpAddr::V6(_) => unreachable!("IPv6 not supported"),
+                    // hosts' IPv6 addresses are configured separately in `ipv6_addr`
+                    std::net::IpAddr::V6(_) => unreachable!("IPv6 address used as IPv4 address"),
                 },
+                ipv6_addr: host_info.ipv6_addr,
+                clock: host_info.clock,
                 sim_end_time: self.end_time,
                 requested_bw_down_bits: host_info.bandwidth_down_bits.unwrap(),
                 requested_bw_up_bits: host_info.bandwidth_up_bits.unwrap(),
@@ -592,11 +661,15 @@ impl<'a> Manager<'a> {
                     .map(|x| x.to_c_loglevel())
                     .unwrap_or(c::_LogLevel_LOGLEVEL_UNSET),
                 pcap_config: host_info.pcap_config,
+                event_trace_enabled: host_info.event_trace_enabled,
+                tcp_congestion: host_info.tcp_congestion,
+                router_queue: host_info.router_queue,
                 qdisc: host_info.qdisc,
                 init_sock_recv_buf_size: host_info.recv_buf_size,
                 autotune_recv_buf: host_info.autotune_recv_buf,
                 init_sock_send_buf_size: host_info.send_buf_size,
                 autotune_send_buf: host_info.autotune_send_buf,
+                use_new_tcp: host_info.use_new_tcp,
                 native_tsc_frequency: self.native_tsc_frequency,
                 model_unblocked_syscall_latency: self.config.model_unblocked_syscall_latency(),
                 max_unapplied_cpu_latency: self.config.max_unapplied_cpu_latency(),
@@ -885,6 +958,45 @@ fn for_each_host(host_iter: &mut HostIter, mut f: impl FnMut(&Host)) {
     });
 }
 
+/// Reassign hosts to threads using the packets they sent and the time spent executing them since
+/// they were last placed.
+fn reassign_hosts(scheduler: &mut Scheduler, host_exec_times: &[AtomicU64]) {
+    let packet_counts = Mutex::new(HashMap::new());
+
+    // collect the packet counts from each thread
+    scheduler.scope(|s| {
+        s.run(|_| {
+            let counts = worker::Worker::take_host_packet_counts();
+            let mut packet_counts = packet_counts.lock().unwrap();
+            for ((src, dst), count) in counts {
+                let total: &mut u64 = packet_counts
+                    .entry((host_index(src), host_index(dst)))
+                    .or_default();
+                *total = total.saturating_add(count);
+            }
+        });
+    });
+
+    let packet_counts = packet_counts.into_inner().unwrap();
+    let weights: Vec<u64> = host_exec_times
+        .iter()
+        .map(|x| x.swap(0, Ordering::Relaxed))
+        .collect();
+
+    let threads = placement::partition(scheduler.parallelism(), &weights, &packet_counts);
+    scheduler.assign_hosts(|host| threads[host_index(host.id())]);
+
+    log::debug!(
+        "Reassigned hosts to threads using {} pairs of communicating hosts",
+        packet_counts.len()
+    );
+}
+
+/// The index of a host in lists that are indexed by host ID.
+fn host_index(id: HostId) -> usize {
+    u32::from(id).try_into().unwrap()
+}
+
 /// Get the raw speed of the experiment machine.
 fn get_raw_cpu_frequency_hz() -> anyhow::Result<u64> {
     const CONFIG_CPU_MAX_FREQ_FILE: &str = "/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq";
@@ -5,6 +5,7 @@ pub use thread_per_core::ThreadPerCoreSched;
 pub use thread_per_host::ThreadPerHostSched;
 
 mod logical_processor;
+pub mod placement;
 pub mod pools;
 mod thread_per_core;
 mod thread_per_host;
@@ -54,6 +55,15 @@ impl Scheduler {
         }
     }
 
+    /// Move each host to the thread with the index returned by `f`. This is a no-op for schedulers
+    /// that don't share threads between hosts.
+    pub fn assign_hosts(&mut self, mut f: impl FnMut(&Host) -> usize) {
+        match self {
+            Self::ThreadPerHost(_) => {}
+            Self::ThreadPerCore(sched) => sched.assign_hosts(|host| f(host)),
+        }
+    }
+
     /// Join all threads started by the scheduler.
     pub fn join(self) {
         match self {
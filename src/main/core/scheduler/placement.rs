@@ -0,0 +1,184 @@
+//! Algorithms for choosing which thread each host is assigned to. Hosts are identified by their
+//! index, and each function returns the index of the thread assigned to each host.
+
+use std::collections::HashMap;
+
+/// How much heavier than the average thread any one thread is allowed to be when partitioning.
+const MAX_IMBALANCE_PERCENT: u64 = 10;
+
+/// Assign hosts to threads so that hosts on the same network graph node share a thread where
+/// possible, while giving each thread roughly the same number of hosts. `nodes` contains the
+/// network graph node of each host.
+pub fn group_by_node(num_threads: usize, nodes: &[u32]) -> Vec<usize> {
+    assert!(num_threads > 0);
+
+    let mut order: Vec<usize> = (0..nodes.len()).collect();
+    order.sort_by_key(|&host| (nodes[host], host));
+
+    // split the sorted hosts into contiguous chunks, one for each thread
+    let mut threads = vec![0; nodes.len()];
+    for (pos, host) in order.into_iter().enumerate() {
+        threads[host] = pos * num_threads / nodes.len();
+    }
+
+    threads
+}
+
+/// Assign hosts to threads so that hosts which exchange many packets share a thread, while
+/// balancing the total weight (for example the execution time) of each thread. `weights` contains
+/// the weight of each host, and `packet_counts` contains the number of packets sent from one host
+/// to another.
+///
+/// This is a greedy partitioning. Pairs of hosts are visited from the most to the fewest packets
+/// exchanged, and each pair is placed on the same thread as long as that thread doesn't become
+/// more than [`MAX_IMBALANCE_PERCENT`] heavier than the average thread. Any remaining hosts are then
+/// placed from heaviest to lightest on the least loaded thread.
+pub fn partition(
+    num_threads: usize,
+    weights: &[u64],
+    packet_counts: &HashMap<(usize, usize), u64>,
+) -> Vec<usize> {
+    assert!(num_threads > 0);
+
+    // hosts with no measured weight still need to be balanced
+    let weights: Vec<u64> = weights.iter().map(|x| std::cmp::max(*x, 1)).collect();
+
+    let total: u64 = weights.iter().fold(0, |sum, x| sum.saturating_add(*x));
+    let average = total / u64::try_from(num_threads).unwrap();
+    let max_weight = weights.iter().copied().max().unwrap_or(0);
+    let capacity = std::cmp::max(
+        average.saturating_add(average.saturating_mul(MAX_IMBALANCE_PERCENT) / 100),
+        max_weight,
+    );
+
+    // the packets exchanged in both directions between each pair of hosts
+    let mut pairs = HashMap::<(usize, usize), u64>::new();
+    for (&(src, dst), &count) in packet_counts {
+        if src != dst {
+            let pair = (std::cmp::min(src, dst), std::cmp::max(src, dst));
+            let total = pairs.entry(pair).or_default();
+            *total = total.saturating_add(count);
+        }
+    }
+    let mut pairs: Vec<_> = pairs.into_iter().collect();
+    pairs.sort_unstable_by_key(|&(pair, count)| (std::cmp::Reverse(count), pair));
+
+    let mut threads: Vec<Option<usize>> = vec![None; weights.len()];
+    let mut loads = vec![0u64; num_threads];
+
+    // the least loaded thread that has room for `weight`, or the least loaded thread if none do
+    let least_loaded = |loads: &[u64], weight: u64| -> (usize, bool) {
+        let thread = (0..num_threads).min_by_key(|&x| loads[x]).unwrap();
+        (thread, loads[thread].saturating_add(weight) <= capacity)
+    };
+
+    let place = |threads: &mut [Option<usize>], loads: &mut [u64], host: usize, thread: usize| {
+        debug_assert!(threads[host].is_none());
+        threads[host] = Some(thread);
+        loads[thread] = loads[thread].saturating_add(weights[host]);
+    };
+
+    for ((a, b), _) in pairs {
+        match (threads[a], threads[b]) {
+            (None, None) => {
+                let weight = weights[a].saturating_add(weights[b]);
+                match least_loaded(&loads, weight) {
+                    (thread, true) => {
+                        place(&mut threads, &mut loads, a, thread);
+                        place(&mut threads, &mut loads, b, thread);
+                    }
+                    _ => {
+                        place(&mut threads, &mut loads, a, least_loaded(&loads, 0).0);
+                        place(&mut threads, &mut loads, b, least_loaded(&loads, 0).0);
+                    }
+                }
+            }
+            (Some(thread), None) | (None, Some(thread)) => {
+                let host = if threads[a].is_none() { a } else { b };
+                let thread = if loads[thread].saturating_add(weights[host]) <= capacity {
+                    thread
+                } else {
+                    least_loaded(&loads, 0).0
+                };
+                place(&mut threads, &mut loads, host, thread);
+            }
+            (Some(_), Some(_)) => {}
+        }
+    }
+
+    let mut remaining: Vec<usize> = (0..weights.len())
+        .filter(|&x| threads[x].is_none())
+        .collect();
+    remaining.sort_by_key(|&host| (std::cmp::Reverse(weights[host]), host));
+    for host in remaining {
+        place(&mut threads, &mut loads, host, least_loaded(&loads, 0).0);
+    }
+
+    threads.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loads(num_threads: usize, threads: &[usize], weights: &[u64]) -> Vec<u64> {
+        let mut loads = vec![0; num_threads];
+        for (host, thread) in threads.iter().enumerate() {
+            loads[*thread] += weights[host];
+        }
+        loads
+    }
+
+    #[test]
+    fn test_group_by_node() {
+        let threads = group_by_node(2, &[7, 3, 7, 3]);
+        assert_eq!(threads[1], threads[3]);
+        assert_eq!(threads[0], threads[2]);
+        assert_ne!(threads[0], threads[1]);
+
+        // more threads than hosts
+        let threads = group_by_node(4, &[1, 1]);
+        assert_eq!(threads, [0, 2]);
+
+        assert!(group_by_node(3, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_partition_balanced() {
+        let weights = [10, 10, 10, 10, 10, 10];
+        let threads = partition(3, &weights, &HashMap::new());
+        assert_eq!(loads(3, &threads, &weights), [20, 20, 20]);
+
+        // hosts without a measured weight are still spread across threads
+        let weights = [0; 4];
+        let threads = partition(2, &weights, &HashMap::new());
+        assert_eq!(loads(2, &threads, &[1; 4]), [2, 2]);
+    }
+
+    #[test]
+    fn test_partition_groups_talkers() {
+        let weights = [10, 10, 10, 10];
+        let packet_counts = HashMap::from([((0, 3), 100), ((2, 1), 50), ((0, 1), 1)]);
+        let threads = partition(2, &weights, &packet_counts);
+        assert_eq!(threads[0], threads[3]);
+        assert_eq!(threads[1], threads[2]);
+        assert_ne!(threads[0], threads[1]);
+    }
+
+    #[test]
+    fn test_partition_capacity() {
+        // all hosts talk to each other, but they can't all fit on one thread
+        let weights = [10, 10, 10, 10];
+        let packet_counts: HashMap<_, _> = (0..4)
+            .flat_map(|x| (0..4).map(move |y| ((x, y), 100)))
+            .collect();
+        let threads = partition(2, &weights, &packet_counts);
+        assert_eq!(loads(2, &threads, &weights), [20, 20]);
+
+        // a host that is heavier than the average thread must still be placed
+        let weights = [100, 1, 1];
+        let threads = partition(2, &weights, &HashMap::new());
+        assert_ne!(threads[0], threads[1]);
+        assert_eq!(threads[1], threads[2]);
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::sync::RwLock;
 
 use shadow_shim_helper_rs::simulation_time::SimulationTime;
+use shadow_shim_helper_rs::HostId;
 
 /// Decides on the runahead for the next simulation round (the duration of the round). Having a
 /// larger runahead improves performance since more hosts and more events can be run in parallel
@@ -9,6 +11,10 @@ use shadow_shim_helper_rs::simulation_time::SimulationTime;
 /// the provided minimum possible latency when dynamic runahead is disabled, and otherwise uses a
 /// dynamic runahead of the minimum used latency. Both runahead calculations have a static lower
 /// bound.
+///
+/// If per-host runahead is enabled, each host may also run past the end of the round until the
+/// earliest time that a packet from any host could arrive, based on the lowest latency of any path
+/// into the host.
 #[derive(Debug)]
 pub struct Runahead {
     /// The lowest packet latency that shadow has used so far in the simulation. For performance, is
@@ -21,6 +27,9 @@ pub struct Runahead {
     min_runahead_config: Option<SimulationTime>,
     /// Is dynamic runahead enabled?
     is_runahead_dynamic: bool,
+    /// The lowest latency of any path into each host, if per-host runahead is enabled. Hosts that
+    /// no packets can reach aren't included.
+    host_min_latencies: Option<HashMap<HostId, SimulationTime>>,
 }
 
 impl Runahead {
@@ -28,14 +37,22 @@ impl Runahead {
         is_runahead_dynamic: bool,
         min_possible_latency: SimulationTime,
         min_runahead_config: Option<SimulationTime>,
+        host_min_latencies: Option<HashMap<HostId, SimulationTime>>,
     ) -> Self {
         assert!(!min_possible_latency.is_zero());
 
+        if let Some(host_min_latencies) = &host_min_latencies {
+            assert!(host_min_latencies
+                .values()
+                .all(|x| *x >= min_possible_latency));
+        }
+
         Self {
             min_used_latency: RwLock::new(None),
             min_possible_latency,
             min_runahead_config,
             is_runahead_dynamic,
+            host_min_latencies,
         }
     }
 
@@ -55,6 +72,19 @@ impl Runahead {
         std::cmp::max(runahead, runahead_config)
     }
 
+    /// Get how far past the start of a round the host may run, or `None` if per-host runahead is
+    /// disabled. This doesn't change during the simulation, and is [`SimulationTime::MAX`] if no
+    /// packets can reach the host.
+    pub fn get_for_host(&self, host: HostId) -> Option<SimulationTime> {
+        let host_min_latencies = self.host_min_latencies.as_ref()?;
+        Some(
+            host_min_latencies
+                .get(&host)
+                .copied()
+                .unwrap_or(SimulationTime::MAX),
+        )
+    }
+
     /// If dynamic runahead is enabled, will compare and update the stored lowest packet latency.
     /// This may shorten the runahead for future rounds.
     pub fn update_lowest_used_latency(&self, latency: SimulationTime) {
@@ -81,12 +81,7 @@ impl<HostType: Host> ThreadPerCoreSched<HostType> {
     ) {
         // we can't swap after the below `pool.scope()` due to lifetime restrictions, so we need to
         // do it before instead
-        if self.hosts_need_swap {
-            debug_assert!(self.thread_hosts.iter().all(|queue| queue.is_empty()));
-
-            std::mem::swap(&mut self.thread_hosts, &mut self.thread_hosts_processed);
-            self.hosts_need_swap = false;
-        }
+        self.swap_hosts_if_needed();
 
         // data/references that we'll pass to the scope
         let thread_hosts = &self.thread_hosts;
@@ -108,6 +103,35 @@ impl<HostType: Host> ThreadPerCoreSched<HostType> {
         });
     }
 
+    /// See [`crate::core::scheduler::Scheduler::assign_hosts`]. Threads may still steal hosts from
+    /// other threads after they've been assigned.
+    pub fn assign_hosts(&mut self, mut f: impl FnMut(&HostType) -> usize) {
+        self.swap_hosts_if_needed();
+
+        let hosts: Vec<HostType> = self
+            .thread_hosts
+            .iter()
+            .flat_map(|queue| std::iter::from_fn(|| queue.pop()))
+            .collect();
+
+        for host in hosts {
+            let thread = f(&host);
+            assert!(thread < self.num_threads);
+            self.thread_hosts[thread].push(host).unwrap();
+        }
+    }
+
+    /// If the hosts were processed in the last scope, make the processed hosts available for the
+    /// next scope.
+    fn swap_hosts_if_needed(&mut self) {
+        if self.hosts_need_swap {
+            debug_assert!(self.thread_hosts.iter().all(|queue| queue.is_empty()));
+
+            std::mem::swap(&mut self.thread_hosts, &mut self.thread_hosts_processed);
+            self.hosts_need_swap = false;
+        }
+    }
+
     /// See [`crate::core::scheduler::Scheduler::join`].
     pub fn join(self) {
         self.pool.join();
@@ -294,6 +318,35 @@ mod tests {
         sched.join();
     }
 
+    #[test]
+    fn test_assign_hosts() {
+        let hosts = [(); 5].map(|_| TestHost {});
+        let mut sched: ThreadPerCoreSched<TestHost> =
+            ThreadPerCoreSched::new(&[None, None], hosts, false);
+
+        let counter = AtomicU32::new(0);
+
+        for _ in 0..3 {
+            // move all hosts to the second thread
+            sched.assign_hosts(|_| 1);
+            assert_eq!(sched.thread_hosts[0].len(), 0);
+            assert_eq!(sched.thread_hosts[1].len(), 5);
+
+            sched.scope(|s| {
+                s.run_with_hosts(|_, hosts| {
+                    hosts.for_each(|host| {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        host
+                    });
+                });
+            });
+        }
+
+        assert_eq!(counter.load(Ordering::SeqCst), 5 * 3);
+
+        sched.join();
+    }
+
     #[test]
     fn test_run_with_data() {
         let hosts = [(); 5].map(|_| TestHost {});
@@ -11,15 +11,19 @@ use anyhow::Context;
 use once_cell::sync::Lazy;
 use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
+use shadow_shim_helper_rs::host_clock::HostClock;
 use shadow_shim_helper_rs::simulation_time::SimulationTime;
 
 use crate::core::support::configuration::Flatten;
 use crate::core::support::configuration::{
-    parse_string_as_args, ConfigOptions, EnvName, HostOptions, LogInfoFlag, LogLevel, ProcessArgs,
-    ProcessOptions, QDiscMode,
+    parse_string_as_args, ClockOptions, ConfigOptions, EnvName, HostOptions, LinkChangeOptions,
+    LogInfoFlag, LogLevel, ProcessArgs, ProcessOptions, QDiscMode, RouterQueueOptions,
+    TcpCongestion,
 };
 use crate::core::support::units::{self, Unit};
-use crate::network::graph::{load_network_graph, IpAssignment, NetworkGraph, RoutingInfo};
+use crate::network::graph::{
+    load_network_graph, EdgeChange, EdgeChanges, IpAssignment, NetworkGraph, RoutingInfo,
+};
 use crate::utility::tilde_expansion;
 
 use super::support::configuration::ProcessFinalState;
@@ -137,6 +141,7 @@ impl SimConfig {
             &graph,
             &ip_assignment.get_nodes(),
             config.network.use_shortest_path.unwrap(),
+            config.network.link_changes.as_deref().unwrap_or(&[]),
         )?;
 
         // get all host bandwidths
@@ -175,8 +180,13 @@ pub struct HostInfo {
     pub bandwidth_down_bits: Option<u64>,
     pub bandwidth_up_bits: Option<u64>,
     pub ip_addr: Option<std::net::IpAddr>,
+    pub ipv6_addr: Option<std::net::Ipv6Addr>,
+    pub clock: HostClock,
     pub log_level: Option<LogLevel>,
     pub pcap_config: Option<PcapConfig>,
+    pub event_trace_enabled: bool,
+    pub tcp_congestion: TcpCongestion,
+    pub router_queue: RouterQueueOptions,
     pub heartbeat_log_level: Option<LogLevel>,
     pub heartbeat_log_info: HashSet<LogInfoFlag>,
     pub heartbeat_interval: Option<SimulationTime>,
@@ -184,6 +194,7 @@ pub struct HostInfo {
     pub recv_buf_size: u64,
     pub autotune_send_buf: bool,
     pub autotune_recv_buf: bool,
+    pub use_new_tcp: bool,
     pub qdisc: QDiscMode,
 }
 
@@ -237,6 +248,15 @@ fn build_host(
         })
         .collect::<anyhow::Result<_>>()?;
 
+    let router_queue = host.host_options.router_queue.unwrap();
+    validate_router_queue(&router_queue).context("Invalid router queue options")?;
+
+    if let Some(ip) = host.ipv6_addr {
+        validate_ipv6_addr(ip).with_context(|| format!("Invalid IPv6 address '{ip}'"))?;
+    }
+
+    let clock = build_clock(&host.clock).context("Invalid clock options")?;
+
     Ok(HostInfo {
         name: hostname,
         processes,
@@ -256,6 +276,8 @@ fn build_host(
             .map(|x| x.convert(units::SiPrefixUpper::Base).unwrap().value()),
 
         ip_addr: host.ip_addr.map(|x| x.into()),
+        ipv6_addr: host.ipv6_addr,
+        clock,
         log_level: host.host_options.log_level.flatten(),
         pcap_config: host
             .host_options
@@ -270,6 +292,9 @@ fn build_host(
                     .unwrap()
                     .value(),
             }),
+        event_trace_enabled: host.host_options.event_trace_enabled.unwrap(),
+        tcp_congestion: host.host_options.tcp_congestion.unwrap(),
+        router_queue,
 
         // some options come from the config options and not the host options
         heartbeat_log_level: config.experimental.host_heartbeat_log_level,
@@ -299,6 +324,7 @@ fn build_host(
             .value(),
         autotune_send_buf: config.experimental.socket_send_autotune.unwrap(),
         autotune_recv_buf: config.experimental.socket_recv_autotune.unwrap(),
+        use_new_tcp: config.experimental.use_new_tcp.unwrap(),
         qdisc: config.experimental.interface_qdisc.unwrap(),
     })
 }
@@ -394,6 +420,140 @@ fn build_process(proc: &ProcessOptions, config: &ConfigOptions) -> anyhow::Resul
     })
 }
 
+/// Check that the router queue parameters are valid.
+fn validate_router_queue(options: &RouterQueueOptions) -> anyhow::Result<()> {
+    let check_codel = |target: units::Time<units::TimePrefix>,
+                       interval: units::Time<units::TimePrefix>|
+     -> anyhow::Result<()> {
+        if Duration::from(interval).is_zero() {
+            return Err(anyhow::anyhow!("The CoDel interval must not be 0"));
+        }
+        if Duration::from(target) >= Duration::from(interval) {
+            return Err(anyhow::anyhow!(
+                "The CoDel target '{target}' must be less than the interval '{interval}'"
+            ));
+        }
+        Ok(())
+    };
+
+    match *options {
+        RouterQueueOptions::Codel {
+            target, interval, ..
+        } => check_codel(target, interval)?,
+        RouterQueueOptions::FqCodel {
+            target,
+            interval,
+            flows,
+            quantum,
+            ..
+        } => {
+            check_codel(target, interval)?;
+            if flows == 0 {
+                return Err(anyhow::anyhow!(
+                    "The number of FQ-CoDel flows must not be 0"
+                ));
+            }
+            let quantum = quantum.convert(units::SiPrefixUpper::Base).unwrap().value();
+            if quantum == 0 || u32::try_from(quantum).is_err() {
+                return Err(anyhow::anyhow!(
+                    "The FQ-CoDel quantum '{quantum}' must be between 1 and {} bytes",
+                    u32::MAX
+                ));
+            }
+        }
+        RouterQueueOptions::Red {
+            min_threshold,
+            max_threshold,
+            max_probability,
+            weight,
+            ..
+        } => {
+            if min_threshold >= max_threshold {
+                return Err(anyhow::anyhow!(
+                    "The RED min threshold '{min_threshold}' must be less than the max threshold '{max_threshold}'"
+                ));
+            }
+            if !(max_probability > 0.0 && max_probability <= 1.0) {
+                return Err(anyhow::anyhow!(
+                    "The RED max probability '{max_probability}' is not in the range (0,1]"
+                ));
+            }
+            if !(weight > 0.0 && weight <= 1.0) {
+                return Err(anyhow::anyhow!(
+                    "The RED weight '{weight}' is not in the range (0,1]"
+                ));
+            }
+        }
+        RouterQueueOptions::DropTail { limit } => {
+            if limit == 0 {
+                return Err(anyhow::anyhow!("The drop-tail limit must not be 0"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the model of a host's clock from its clock options.
+fn build_clock(options: &ClockOptions) -> anyhow::Result<HostClock> {
+    let offset = options
+        .offset
+        .convert(units::TimePrefix::Nano)
+        .map_err(|e| anyhow::anyhow!("The clock offset '{}' is too large: {e}", options.offset))?
+        .value();
+
+    // the clock must still move forward
+    let drift_ppm = options.drift_ppm;
+    let drift_ppb = (drift_ppm * 1000.0).round();
+    if !(drift_ppb.abs() < 1_000_000_000.0) {
+        return Err(anyhow::anyhow!(
+            "The clock drift '{drift_ppm}' is not in the range (-1000000,1000000) ppm"
+        ));
+    }
+    let drift_ppb = drift_ppb as i64;
+
+    let step_interval = options
+        .step_interval
+        .map(|x| Duration::from(x).try_into().unwrap());
+    if step_interval == Some(SimulationTime::ZERO) {
+        return Err(anyhow::anyhow!("The clock step interval must not be 0"));
+    }
+
+    Ok(HostClock::new(offset, drift_ppb, step_interval))
+}
+
+/// Check that a host's IPv6 address is a global unicast address. Shadow maps each IPv6 address to
+/// an internal IPv4 address, so special addresses such as `::1` or IPv4-mapped addresses would
+/// conflict with the addresses that Shadow already handles.
+fn validate_ipv6_addr(ip: std::net::Ipv6Addr) -> anyhow::Result<()> {
+    if ip.is_unspecified() {
+        return Err(anyhow::anyhow!("The address must not be unspecified"));
+    }
+    if ip.is_loopback() {
+        return Err(anyhow::anyhow!(
+            "The address must not be a loopback address"
+        ));
+    }
+    if ip.is_multicast() {
+        return Err(anyhow::anyhow!(
+            "The address must not be a multicast address"
+        ));
+    }
+    if ip.to_ipv4_mapped().is_some() {
+        return Err(anyhow::anyhow!(
+            "The address must not be an IPv4-mapped address"
+        ));
+    }
+    // global unicast addresses are currently all allocated from 2000::/3 (RFC 4291 section 2.4)
+    if ip.segments()[0] & 0xe000 != 0x2000 {
+        return Err(anyhow::anyhow!(
+            "The address must be a global unicast address in 2000::/3"
+        ));
+    }
+
+    Ok(())
+}
+
 /// Generate an IP assignment map using hosts' configured IP addresses and graph node IDs. For hosts
 /// without IP addresses, they will be assigned an arbitrary IP address.
 fn assign_ips(hosts: &mut [HostInfo]) -> anyhow::Result<IpAssignment<u32>> {
@@ -409,6 +569,20 @@ fn assign_ips(hosts: &mut [HostInfo]) -> anyhow::Result<IpAssignment<u32>> {
         })?;
     }
 
+    // register hosts' IPv6 addresses, which are never assigned dynamically
+    for host in hosts.iter().filter(|x| x.ipv6_addr.is_some()) {
+        let ip = host.ipv6_addr.unwrap();
+        let hostname = &host.name;
+        let node_id = host.network_node_id;
+        ip_assignment
+            .assign_ip(node_id, ip.into())
+            .with_context(|| {
+                format!(
+                    "Failed to assign IPv6 address {ip} for host '{hostname}' to node '{node_id}'"
+                )
+            })?;
+    }
+
     // then register remaining hosts
     for host in hosts.iter_mut().filter(|x| x.ip_addr.is_none()) {
         let ip = ip_assignment.assign(host.network_node_id);
@@ -420,11 +594,13 @@ fn assign_ips(hosts: &mut [HostInfo]) -> anyhow::Result<IpAssignment<u32>> {
 }
 
 /// Generate a map containing routing information (latency, packet loss, etc) for each pair of
-/// nodes.
+/// nodes. If there are link changes, the routing information is also generated for each point in
+/// time at which the set of active link changes differs.
 fn generate_routing_info(
     graph: &NetworkGraph,
     nodes: &std::collections::HashSet<u32>,
     use_shortest_paths: bool,
+    link_changes: &[LinkChangeOptions],
 ) -> anyhow::Result<RoutingInfo<u32>> {
     // convert gml node IDs to petgraph indexes
     let nodes: Vec<_> = nodes
@@ -433,31 +609,134 @@ fn generate_routing_info(
         .collect();
 
     // helper to convert petgraph indexes back to gml node IDs
-    let to_ids = |((src, dst), path)| {
-        let src = graph.node_index_to_id(src).unwrap();
-        let dst = graph.node_index_to_id(dst).unwrap();
-        ((src, dst), path)
+    let to_id = |index| graph.node_index_to_id(index).unwrap();
+    let to_ids = |((src, dst), path)| ((to_id(src), to_id(dst)), path);
+
+    let get_routes = |changes: &EdgeChanges| -> anyhow::Result<_> {
+        Ok(if use_shortest_paths {
+            let (paths, vias) = graph
+                .compute_shortest_paths(&nodes[..], changes)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to compute shortest paths between graph nodes")?;
+            let vias = vias
+                .into_iter()
+                .map(|((src, dst), vias)| {
+                    let vias = vias.into_iter().map(to_id).collect();
+                    ((to_id(src), to_id(dst)), vias)
+                })
+                .collect();
+            (paths.into_iter().map(to_ids).collect(), vias)
+        } else {
+            let paths = graph
+                .get_direct_paths(&nodes[..], changes)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to get the direct paths between graph nodes")?;
+            // direct paths don't pass through any other nodes
+            (paths.into_iter().map(to_ids).collect(), HashMap::new())
+        })
     };
 
-    let paths = if use_shortest_paths {
-        graph
-            .compute_shortest_paths(&nodes[..])
-            .map_err(|e| anyhow::anyhow!(e))
-            .context("Failed to compute shortest paths between graph nodes")?
-            .into_iter()
-            .map(to_ids)
-            .collect()
-    } else {
-        graph
-            .get_direct_paths(&nodes[..])
-            .map_err(|e| anyhow::anyhow!(e))
-            .context("Failed to get the direct paths between graph nodes")?
-            .into_iter()
-            .map(to_ids)
-            .collect()
-    };
+    let (paths, vias) = get_routes(&EdgeChanges::new())?;
+    let mut routing_info = RoutingInfo::new(paths, vias);
+
+    let link_changes = link_changes
+        .iter()
+        .map(|x| build_link_change(x, graph))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // the times at which the set of active link changes may differ
+    let mut change_times: Vec<SimulationTime> = link_changes
+        .iter()
+        .flat_map(|x| std::iter::once(x.start_time).chain(x.end_time))
+        .collect();
+    change_times.sort();
+    change_times.dedup();
+
+    for time in change_times {
+        // later link changes take precedence over earlier link changes for the same edge
+        let active_changes: EdgeChanges = link_changes
+            .iter()
+            .filter(|x| x.start_time <= time && x.end_time.map_or(true, |end| time < end))
+            .map(|x| ((x.source, x.target), x.change))
+            .collect();
+
+        log::debug!(
+            "Computing routing information for {} active link changes at time {:?}",
+            active_changes.len(),
+            time,
+        );
+
+        let (paths, vias) = get_routes(&active_changes)?;
+        routing_info.add_change(time, paths, vias);
+    }
 
-    Ok(RoutingInfo::new(paths))
+    Ok(routing_info)
+}
+
+struct LinkChange {
+    source: u32,
+    target: u32,
+    start_time: SimulationTime,
+    end_time: Option<SimulationTime>,
+    change: EdgeChange,
+}
+
+/// For a link change entry in the configuration options, build a `LinkChange` object.
+fn build_link_change(
+    link_change: &LinkChangeOptions,
+    graph: &NetworkGraph,
+) -> anyhow::Result<LinkChange> {
+    let source = link_change.source;
+    let target = link_change.target;
+
+    if !graph.has_edge(source, target) {
+        return Err(anyhow::anyhow!(
+            "Link change for edge {source}->{target}, but the network graph has no such edge"
+        ));
+    }
+
+    let start_time: SimulationTime = Duration::from(link_change.start_time).try_into().unwrap();
+    let end_time: Option<SimulationTime> = link_change
+        .end_time
+        .map(|x| Duration::from(x).try_into().unwrap());
+
+    if let Some(end_time) = end_time {
+        if start_time >= end_time {
+            return Err(anyhow::anyhow!(
+                "Link change start time '{}' must be earlier than its end time '{}'",
+                link_change.start_time,
+                link_change.end_time.unwrap(),
+            ));
+        }
+    }
+
+    let latency_ns = link_change
+        .latency
+        .map(|x| x.convert(units::TimePrefix::Nano).unwrap().value());
+
+    if latency_ns == Some(0) {
+        return Err(anyhow::anyhow!("Link change latency must not be 0"));
+    }
+
+    if let Some(packet_loss) = link_change.packet_loss {
+        if !(0.0..=1.0).contains(&packet_loss) {
+            return Err(anyhow::anyhow!(
+                "Link change packet loss '{packet_loss}' is not in the range [0,1]"
+            ));
+        }
+    }
+
+    Ok(LinkChange {
+        source,
+        target,
+        start_time,
+        end_time,
+        change: EdgeChange {
+            is_down: link_change.down,
+            latency_ns,
+            packet_loss: link_change.packet_loss,
+        },
+    })
 }
 
 /// Check that the plugin path is valid.
@@ -274,6 +274,11 @@ pub struct NetworkOptions {
     #[clap(long, value_name = "bool")]
     #[clap(help = NETWORK_HELP.get("use_shortest_path").unwrap().as_str())]
     pub use_shortest_path: Option<bool>,
+
+    /// Scheduled changes to edges of the network graph, such as link outages or latency spikes
+    #[serde(default)]
+    #[clap(skip)]
+    pub link_changes: Option<Vec<LinkChangeOptions>>,
 }
 
 impl NetworkOptions {
@@ -366,6 +371,13 @@ pub struct ExperimentalOptions {
     #[clap(help = EXP_HELP.get("use_dynamic_runahead").unwrap().as_str())]
     pub use_dynamic_runahead: Option<bool>,
 
+    /// Allow each host to run past the end of a scheduling round until the earliest time that a
+    /// packet could arrive at it, based on the lowest latency of any path into the host.
+    #[clap(hide_short_help = true)]
+    #[clap(long, value_name = "bool")]
+    #[clap(help = EXP_HELP.get("use_per_host_runahead").unwrap().as_str())]
+    pub use_per_host_runahead: Option<bool>,
+
     /// Initial size of the socket's send buffer
     #[clap(hide_short_help = true)]
     #[clap(long, value_name = "bytes")]
@@ -390,6 +402,13 @@ pub struct ExperimentalOptions {
     #[clap(help = EXP_HELP.get("socket_recv_autotune").unwrap().as_str())]
     pub socket_recv_autotune: Option<bool>,
 
+    /// Use the Rust TCP implementation instead of the legacy C TCP implementation for new TCP
+    /// sockets
+    #[clap(hide_short_help = true)]
+    #[clap(long, value_name = "bool")]
+    #[clap(help = EXP_HELP.get("use_new_tcp").unwrap().as_str())]
+    pub use_new_tcp: Option<bool>,
+
     /// The queueing discipline to use at the network interface
     #[clap(hide_short_help = true)]
     #[clap(long, value_name = "mode")]
@@ -453,6 +472,13 @@ pub struct ExperimentalOptions {
     #[clap(long, value_name = "name")]
     #[clap(help = EXP_HELP.get("scheduler").unwrap().as_str())]
     pub scheduler: Option<Scheduler>,
+
+    /// How the thread-per-core scheduler decides which hosts are assigned to each thread. This is
+    /// ignored if not using the thread-per-core scheduler.
+    #[clap(hide_short_help = true)]
+    #[clap(long, value_name = "name")]
+    #[clap(help = EXP_HELP.get("host_placement").unwrap().as_str())]
+    pub host_placement: Option<HostPlacement>,
 }
 
 impl ExperimentalOptions {
@@ -488,10 +514,12 @@ impl Default for ExperimentalOptions {
                 units::TimePrefix::Milli,
             ))),
             use_dynamic_runahead: Some(false),
+            use_per_host_runahead: Some(false),
             socket_send_buffer: Some(units::Bytes::new(131_072, units::SiPrefixUpper::Base)),
             socket_send_autotune: Some(true),
             socket_recv_buffer: Some(units::Bytes::new(174_760, units::SiPrefixUpper::Base)),
             socket_recv_autotune: Some(true),
+            use_new_tcp: Some(false),
             interface_qdisc: Some(QDiscMode::Fifo),
             host_heartbeat_log_level: Some(LogLevel::Info),
             host_heartbeat_log_info: Some(IntoIterator::into_iter([LogInfoFlag::Node]).collect()),
@@ -501,6 +529,7 @@ impl Default for ExperimentalOptions {
             ))),
             strace_logging_mode: Some(StraceLoggingMode::Off),
             scheduler: Some(Scheduler::ThreadPerCore),
+            host_placement: Some(HostPlacement::Random),
         }
     }
 }
@@ -532,6 +561,21 @@ pub struct HostDefaultOptions {
     #[clap(long, value_name = "bytes")]
     #[clap(help = HOST_HELP.get("pcap_capture_size").unwrap().as_str())]
     pub pcap_capture_size: Option<units::Bytes<units::SiPrefixUpper>>,
+
+    /// Should shadow write a trace of simulation events?
+    #[clap(long, value_name = "bool")]
+    #[clap(help = HOST_HELP.get("event_trace_enabled").unwrap().as_str())]
+    pub event_trace_enabled: Option<bool>,
+
+    /// Default TCP congestion control algorithm for new TCP sockets
+    #[clap(long, value_name = "algorithm")]
+    #[clap(help = HOST_HELP.get("tcp_congestion").unwrap().as_str())]
+    pub tcp_congestion: Option<TcpCongestion>,
+
+    /// The queue discipline of the router queue that holds packets arriving from the network
+    #[clap(long, value_name = "queue")]
+    #[clap(help = HOST_HELP.get("router_queue").unwrap().as_str())]
+    pub router_queue: Option<RouterQueueOptions>,
 }
 
 impl HostDefaultOptions {
@@ -543,6 +587,13 @@ impl HostDefaultOptions {
             // capture all the data available from the packet". The maximum length of an IP packet
             // (including the header) is 65535 bytes.
             pcap_capture_size: Some(units::Bytes::new(65535, units::SiPrefixUpper::Base)),
+            event_trace_enabled: Some(false),
+            tcp_congestion: Some(TcpCongestion::Reno),
+            router_queue: Some(RouterQueueOptions::Codel {
+                target: default_codel_target(),
+                interval: default_codel_interval(),
+                limit: None,
+            }),
         }
     }
 
@@ -565,6 +616,9 @@ impl Default for HostDefaultOptions {
             log_level: None,
             pcap_enabled: None,
             pcap_capture_size: None,
+            event_trace_enabled: None,
+            tcp_congestion: None,
+            router_queue: None,
         }
     }
 }
@@ -651,6 +705,10 @@ pub struct HostOptions {
     #[serde(default)]
     pub ip_addr: Option<std::net::Ipv4Addr>,
 
+    /// IPv6 address to assign to the host, in addition to its IPv4 address
+    #[serde(default)]
+    pub ipv6_addr: Option<std::net::Ipv6Addr>,
+
     /// Downstream bandwidth capacity of the host
     #[serde(default)]
     pub bandwidth_down: Option<units::BitsPerSec<units::SiPrefixUpper>>,
@@ -659,10 +717,35 @@ pub struct HostOptions {
     #[serde(default)]
     pub bandwidth_up: Option<units::BitsPerSec<units::SiPrefixUpper>>,
 
+    /// The error of the host's clocks relative to the simulation time
+    #[serde(default)]
+    pub clock: ClockOptions,
+
     #[serde(default)]
     pub host_options: HostDefaultOptions,
 }
 
+/// A model of the error of a host's clocks relative to the simulation time. Shadow still schedules
+/// events using the simulation time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClockOptions {
+    /// The offset of the host's realtime clock from the simulation time when the simulation
+    /// starts; may be negative
+    #[serde(default)]
+    pub offset: units::SignedTime<units::TimePrefix>,
+
+    /// The rate in parts per million at which the host's realtime and monotonic clocks drift from
+    /// the simulation time; may be negative
+    #[serde(default)]
+    pub drift_ppm: f64,
+
+    /// The interval at which the host's realtime clock is stepped back to the simulation time, or
+    /// null to never step the clock
+    #[serde(default)]
+    pub step_interval: Option<units::Time<units::TimePrefix>>,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum LogLevel {
@@ -874,6 +957,21 @@ impl FromStr for Scheduler {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostPlacement {
+    Random,
+    Graph,
+}
+
+impl FromStr for HostPlacement {
+    type Err = serde_yaml::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_yaml::from_str(s)
+    }
+}
+
 fn default_data_directory() -> Option<String> {
     Some("shadow.data".into())
 }
@@ -940,6 +1038,90 @@ impl FromStr for QDiscMode {
     }
 }
 
+/// A TCP congestion control algorithm, using the names that Linux gives them.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum TcpCongestion {
+    Reno,
+    Cubic,
+    Bbr,
+}
+
+impl FromStr for TcpCongestion {
+    type Err = serde_yaml::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_yaml::from_str(s)
+    }
+}
+
+/// A queue discipline for the router queue that holds packets arriving at a host from the
+/// network.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum RouterQueueOptions {
+    /// CoDel active queue management (RFC 8289).
+    Codel {
+        /// The target minimum standing queue delay
+        #[serde(default = "default_codel_target")]
+        target: units::Time<units::TimePrefix>,
+        /// The interval over which the standing queue delay is measured
+        #[serde(default = "default_codel_interval")]
+        interval: units::Time<units::TimePrefix>,
+        /// The maximum number of packets in the queue, or null for no limit
+        #[serde(default)]
+        limit: Option<u32>,
+    },
+    /// Flow queue CoDel (RFC 8290), which hashes flows into separate CoDel queues and schedules
+    /// them using deficit round robin.
+    FqCodel {
+        /// The target minimum standing queue delay of each flow queue
+        #[serde(default = "default_codel_target")]
+        target: units::Time<units::TimePrefix>,
+        /// The interval over which the standing queue delay is measured
+        #[serde(default = "default_codel_interval")]
+        interval: units::Time<units::TimePrefix>,
+        /// The maximum number of packets in all flow queues, or null for no limit
+        #[serde(default)]
+        limit: Option<u32>,
+        /// The number of flow queues that flows are hashed into
+        #[serde(default = "default_fq_codel_flows")]
+        flows: u32,
+        /// The number of bytes that a flow may dequeue in each scheduling round
+        #[serde(default = "default_fq_codel_quantum")]
+        quantum: units::Bytes<units::SiPrefixUpper>,
+    },
+    /// Random early detection.
+    Red {
+        /// The average queue length in packets at which packets start being dropped
+        min_threshold: u32,
+        /// The average queue length in packets at which all packets are dropped
+        max_threshold: u32,
+        /// The drop probability when the average queue length reaches `max_threshold`
+        #[serde(default = "default_red_max_probability")]
+        max_probability: f64,
+        /// The weight of new samples in the average queue length
+        #[serde(default = "default_red_weight")]
+        weight: f64,
+        /// The maximum number of packets in the queue, or null for no limit
+        #[serde(default)]
+        limit: Option<u32>,
+    },
+    /// A first-in first-out queue that drops arriving packets when it's full.
+    DropTail {
+        /// The maximum number of packets in the queue
+        limit: u32,
+    },
+}
+
+impl FromStr for RouterQueueOptions {
+    type Err = serde_yaml::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_yaml::from_str(s)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum Compression {
@@ -972,6 +1154,36 @@ pub enum GraphOptions {
     OneGbitSwitch,
 }
 
+/// A change to the properties of a network graph edge during the simulation.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LinkChangeOptions {
+    /// Network graph node ID of the edge's source
+    pub source: u32,
+
+    /// Network graph node ID of the edge's target
+    pub target: u32,
+
+    /// The simulated time at which the change takes effect
+    pub start_time: units::Time<units::TimePrefix>,
+
+    /// The simulated time at which the edge reverts to its properties from the network graph
+    #[serde(default)]
+    pub end_time: Option<units::Time<units::TimePrefix>>,
+
+    /// Take the edge down so that packets can't be sent over it
+    #[serde(default)]
+    pub down: bool,
+
+    /// The edge's latency during the change
+    #[serde(default)]
+    pub latency: Option<units::Time<units::TimePrefix>>,
+
+    /// The edge's packet loss during the change
+    #[serde(default)]
+    pub packet_loss: Option<f32>,
+}
+
 #[derive(Debug, Clone, Serialize, JsonSchema)]
 #[serde(untagged)]
 pub enum ProcessArgs {
@@ -1310,6 +1522,38 @@ fn default_some_info() -> Option<LogLevel> {
     Some(LogLevel::Info)
 }
 
+/// Helper function for serde default CoDel `target` values.
+fn default_codel_target() -> units::Time<units::TimePrefix> {
+    // RFC 8289 recommends 5 ms for internet routers, but we use 10 ms in shadow
+    units::Time::new(10, units::TimePrefix::Milli)
+}
+
+/// Helper function for serde default CoDel `interval` values.
+fn default_codel_interval() -> units::Time<units::TimePrefix> {
+    units::Time::new(100, units::TimePrefix::Milli)
+}
+
+/// Helper function for serde default FQ-CoDel `flows` values.
+fn default_fq_codel_flows() -> u32 {
+    1024
+}
+
+/// Helper function for serde default FQ-CoDel `quantum` values.
+fn default_fq_codel_quantum() -> units::Bytes<units::SiPrefixUpper> {
+    // the MTU plus the size of an ethernet header, like linux
+    units::Bytes::new(1514, units::SiPrefixUpper::Base)
+}
+
+/// Helper function for serde default RED `max_probability` values.
+fn default_red_max_probability() -> f64 {
+    0.1
+}
+
+/// Helper function for serde default RED `weight` values.
+fn default_red_weight() -> f64 {
+    0.002
+}
+
 // when updating this graph, make sure to also update the copy in docs/shadow_config_spec.md
 pub const ONE_GBIT_SWITCH_GRAPH: &str = r#"graph [
   directed 0
@@ -96,6 +96,11 @@ typedef struct Host Host;
  */
 #define CONFIG_HEADER_SIZE_IP 20
 
+/**
+ * Default IPv6 header size in bytes.
+ */
+#define CONFIG_HEADER_SIZE_IPV6 40
+
 /**
  * Default UDP header size in bytes.
  */
@@ -106,6 +111,11 @@ typedef struct Host Host;
  */
 #define CONFIG_HEADER_SIZE_TCP 20
 
+/**
+ * Default ICMP header size in bytes.
+ */
+#define CONFIG_HEADER_SIZE_ICMP 8
+
 /**
  * Header size in bytes of a routable packet with UDP encapsulation; includes
  * the IP and UDP headers but excludes the ethernet header and packet payload.
@@ -118,6 +128,17 @@ typedef struct Host Host;
  */
 #define CONFIG_HEADER_SIZE_TCPIP (CONFIG_HEADER_SIZE_TCP + CONFIG_HEADER_SIZE_IP)
 
+/**
+ * Header size in bytes of a routable packet with ICMP encapsulation; includes
+ * the IP and ICMP headers but excludes the ethernet header and packet payload.
+ */
+#define CONFIG_HEADER_SIZE_ICMPIP (CONFIG_HEADER_SIZE_ICMP + CONFIG_HEADER_SIZE_IP)
+
+/**
+ * Default time-to-live of IP packets, as in Linux's net.ipv4.ip_default_ttl.
+ */
+#define CONFIG_IP_DEFAULT_TTL 64
+
 /**
  * Maximum size of an IP packet without fragmenting over Ethernetv2
  */
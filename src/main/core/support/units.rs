@@ -559,6 +559,16 @@ impl From<Time<TimePrefixUpper>> for std::time::Duration {
     }
 }
 
+/// An amount of time that may be negative, such as an offset between two clocks. Should only use
+/// the time prefix types ([`TimePrefix`] and [`TimePrefixUpper`]) with this type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedTime<T: Prefix> {
+    value: i64,
+    prefix: T,
+}
+
+unit_impl!(SignedTime, i64, [""]);
+
 /// A number of bytes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Bytes<T: Prefix> {
@@ -634,6 +644,21 @@ mod tests {
         assert!(Time::<TimePrefix>::from_str("10 abc").is_err());
         assert!(Time::<TimePrefixUpper>::from_str("10 ms").is_err());
 
+        assert_eq!(
+            SignedTime::from_str("10 ms").unwrap(),
+            SignedTime::new(10, TimePrefix::Milli)
+        );
+        assert_eq!(
+            SignedTime::from_str("-10 ms").unwrap(),
+            SignedTime::new(-10, TimePrefix::Milli)
+        );
+        assert_eq!(
+            SignedTime::from_str("+10").unwrap(),
+            SignedTime::new(10, TimePrefix::Sec)
+        );
+        assert!(SignedTime::<TimePrefix>::from_str("- 10 ms").is_err());
+        assert!(SignedTime::<TimePrefix>::from_str("-10.5 ms").is_err());
+
         assert_eq!(
             Bytes::from_str("10").unwrap(),
             Bytes::new(10, SiPrefixUpper::Base)
@@ -772,5 +797,11 @@ mod tests {
             std::time::Duration::new(1, 123),
             std::time::Duration::from(time)
         );
+
+        let time = SignedTime::from_str("-3 min").unwrap();
+        assert_eq!(
+            time.convert(TimePrefix::Nano).unwrap().value(),
+            -180_000_000_000
+        );
     }
 }
@@ -21,10 +21,11 @@ use crate::core::sim_stats::{LocalSimStats, SharedSimStats};
 use crate::core::work::event::Event;
 use crate::cshadow;
 use crate::host::host::Host;
+use crate::host::network::icmp;
 use crate::host::process::{Process, ProcessId};
 use crate::host::thread::{Thread, ThreadId};
-use crate::network::graph::{IpAssignment, RoutingInfo};
-use crate::network::packet::PacketRc;
+use crate::network::graph::{IpAssignment, PathProperties, RoutingInfo};
+use crate::network::packet::{PacketRc, PacketStatus};
 use crate::utility::childpid_watcher::ChildPidWatcher;
 use crate::utility::counter::Counter;
 use crate::utility::status_bar;
@@ -52,6 +53,7 @@ pub struct WorkerThreadID(pub u32);
 
 struct Clock {
     now: Option<EmulatedTime>,
+    round_start: Option<EmulatedTime>,
     barrier: Option<EmulatedTime>,
 }
 
@@ -80,6 +82,10 @@ pub struct Worker {
     sim_stats: LocalSimStats,
 
     next_event_time: Cell<Option<EmulatedTime>>,
+
+    // The number of packets sent between hosts by this worker, if enabled by
+    // `WorkerShared::count_host_packets`.
+    host_packet_counts: RefCell<HashMap<(HostId, HostId), u64>>,
 }
 
 impl Worker {
@@ -94,11 +100,13 @@ impl Worker {
                 active_thread: RefCell::new(None),
                 clock: RefCell::new(Clock {
                     now: None,
+                    round_start: None,
                     barrier: None,
                 }),
                 min_latency_cache: Cell::new(None),
                 sim_stats: LocalSimStats::new(),
                 next_event_time: Cell::new(None),
+                host_packet_counts: RefCell::new(HashMap::new()),
             }));
             assert!(res.is_ok(), "Worker already initialized");
         });
@@ -155,7 +163,7 @@ impl Worker {
     ///
     /// Prefer to pass Thread explicitly where feasible. e.g. see `ThreadContext`.
     #[must_use]
-    fn with_active_thread<F, R>(f: F) -> Option<R>
+    pub fn with_active_thread<F, R>(f: F) -> Option<R>
     where
         F: FnOnce(&Thread) -> R,
     {
@@ -261,6 +269,10 @@ impl Worker {
         Worker::with_active_thread(|thread| thread.native_tid())
     }
 
+    pub fn set_round_start_time(t: EmulatedTime) {
+        Worker::with(|w| w.clock.borrow_mut().round_start.replace(t)).unwrap();
+    }
+
     pub fn set_round_end_time(t: EmulatedTime) {
         Worker::with(|w| w.clock.borrow_mut().barrier.replace(t)).unwrap();
     }
@@ -269,9 +281,29 @@ impl Worker {
         Worker::with(|w| w.clock.borrow().barrier).flatten()
     }
 
+    /// The time that the host may run until in the current round. This is the end of the round,
+    /// unless per-host runahead allows the host to run further.
+    pub fn host_round_end_time(host: HostId) -> EmulatedTime {
+        let round_end = Worker::round_end_time().unwrap();
+
+        Worker::with(|w| {
+            let Some(runahead) = w.shared.runahead.get_for_host(host) else {
+                return round_end;
+            };
+
+            // no packet can arrive at the host before this time
+            let round_start = w.clock.borrow().round_start.unwrap();
+            let host_end = round_start.saturating_add(runahead);
+            let host_end = std::cmp::min(host_end, w.shared.sim_end_time);
+
+            std::cmp::max(round_end, host_end)
+        })
+        .unwrap()
+    }
+
     /// Maximum time that the current event may run ahead to.
     pub fn max_event_runahead_time(host: &Host) -> EmulatedTime {
-        let mut max = Worker::round_end_time().unwrap();
+        let mut max = Worker::host_round_end_time(host.id());
         if let Some(next_event_time) = host.next_event_time() {
             max = std::cmp::min(max, next_event_time);
         }
@@ -329,7 +361,6 @@ impl Worker {
         assert!(!packet.is_null());
 
         let current_time = Worker::current_time().unwrap();
-        let round_end_time = Worker::round_end_time().unwrap();
 
         let is_completed = current_time >= Worker::with(|w| w.shared.sim_end_time).unwrap();
         let is_bootstrapping =
@@ -353,34 +384,95 @@ impl Worker {
                 .expect("No host ID for dest address {dst_ip}")
         })
         .unwrap();
+        let round_end_time = Worker::host_round_end_time(dst_host_id);
 
         let src_ip = std::net::IpAddr::V4(src_ip);
         let dst_ip = std::net::IpAddr::V4(dst_ip);
 
+        let path = Worker::with(|w| w.shared.path(src_ip, dst_ip, current_time).unwrap()).unwrap();
+
+        // a link along the path is down, so all packets are dropped
+        if path.is_down {
+            unsafe {
+                cshadow::packet_addDeliveryStatus(
+                    packet,
+                    cshadow::_PacketDeliveryStatusFlags_PDS_INET_DROPPED,
+                )
+            };
+            return;
+        }
+
         // check if network reliability forces us to 'drop' the packet
-        let reliability: f64 = Worker::with(|w| w.shared.reliability(src_ip, dst_ip).unwrap())
-            .unwrap()
-            .try_into()
-            .unwrap();
+        let reliability: f64 = (1.0 - path.packet_loss).into();
         let chance: f64 = src_host.random_mut().gen();
 
         // don't drop control packets with length 0, otherwise congestion control has problems
         // responding to packet loss
         // https://github.com/shadow/shadow/issues/2517
-        if !is_bootstrapping && chance >= reliability && payload_size > 0 {
+        let can_drop = !is_bootstrapping && payload_size > 0;
+        let mut is_dropped = can_drop && chance >= reliability;
+
+        // bursty losses are modelled in addition to the independent losses above, and the model
+        // advances for every packet that could be dropped
+        if let Some(model) = path.gilbert_elliott {
+            if can_drop {
+                is_dropped |= src_host.gilbert_elliott_is_lost(dst_ip, &model);
+            }
+        }
+
+        if is_dropped {
+            unsafe {
+                cshadow::packet_addDeliveryStatus(
+                    packet,
+                    cshadow::_PacketDeliveryStatusFlags_PDS_INET_DROPPED,
+                )
+            };
+            return;
+        }
+
+        // the router of each node along the path decrements the packet's TTL, and the router where
+        // it reaches 0 drops the packet and responds with an ICMP time exceeded error
+        let ttl = unsafe { cshadow::packet_getTTL(packet) };
+        let path_nodes =
+            Worker::with(|w| w.shared.path_nodes(src_ip, dst_ip, current_time).unwrap()).unwrap();
+        let num_hops = u8::try_from(path_nodes.len()).unwrap_or(u8::MAX);
+
+        if ttl <= num_hops {
             unsafe {
                 cshadow::packet_addDeliveryStatus(
                     packet,
                     cshadow::_PacketDeliveryStatusFlags_PDS_INET_DROPPED,
                 )
             };
+
+            let hops = std::cmp::max(ttl, 1);
+            let router = path_nodes[usize::from(hops) - 1];
+
+            // the error travels from the source to the router and back again
+            let delay = path.latency_ns * 2 * u64::from(hops) / u64::from(num_hops);
+            let delay = SimulationTime::from_nanos(delay);
+
+            unsafe { Self::send_time_exceeded(src_host, packet, router, current_time + delay) };
             return;
         }
 
-        let delay = Worker::with(|w| w.shared.latency(src_ip, dst_ip).unwrap()).unwrap();
+        // decide which of the path's other impairments apply to this packet; we only draw random
+        // values for impairments that the path has so that simulations without them are unchanged
+        let impaired = |probability: f32| -> bool {
+            !is_bootstrapping
+                && probability > 0.0
+                && src_host.random_mut().gen::<f64>() < f64::from(probability)
+        };
+        // like packet loss, don't corrupt control packets with length 0
+        let is_corrupted = payload_size > 0 && impaired(path.packet_corrupt);
+        let is_duplicated = impaired(path.packet_duplicate);
+        let is_reordered = impaired(path.packet_reorder);
+
+        let delay = SimulationTime::from_nanos(path.latency_ns);
 
         Worker::update_lowest_used_latency(delay);
         Worker::with(|w| w.shared.increment_packet_count(src_ip, dst_ip)).unwrap();
+        Worker::increment_host_packet_count(src_host.id(), dst_host_id);
 
         // TODO: this should change for sending to remote manager (on a different machine); this is
         // the only place where tasks are sent between separate host
@@ -393,10 +485,24 @@ impl Worker {
         };
 
         // copy the packet
-        let packet = PacketRc::from_raw(unsafe { cshadow::packet_copy(packet) });
+        let mut packet = PacketRc::from_raw(unsafe { cshadow::packet_copy(packet) });
+        packet.set_ttl(ttl - num_hops);
+
+        if is_corrupted {
+            let num_bits = usize::try_from(payload_size).unwrap() * 8;
+            packet.set_corrupted(src_host.random_mut().gen_range(0..num_bits));
+            packet.add_status(PacketStatus::InetCorrupted);
+        }
 
-        // delay the packet until the next round
         let mut deliver_time = current_time + delay;
+
+        // a reordered packet is held back so that packets sent after it can overtake it
+        if is_reordered {
+            deliver_time += SimulationTime::from_nanos(path.packet_reorder_gap_ns);
+            packet.add_status(PacketStatus::InetReordered);
+        }
+
+        // delay the packet until the destination host's next round
         if deliver_time < round_end_time {
             deliver_time = round_end_time;
         }
@@ -405,6 +511,17 @@ impl Worker {
         // round and calculated its min event time, so we put this in our min event time instead
         Worker::update_next_event_time(deliver_time);
 
+        if is_duplicated {
+            let mut duplicate =
+                PacketRc::from_raw(unsafe { cshadow::packet_copy(packet.borrow_inner()) });
+            duplicate.add_status(PacketStatus::InetDuplicated);
+            Worker::with(|w| {
+                w.shared
+                    .push_packet_to_host(duplicate, dst_host_id, deliver_time, src_host)
+            })
+            .unwrap();
+        }
+
         Worker::with(|w| {
             w.shared
                 .push_packet_to_host(packet, dst_host_id, deliver_time, src_host)
@@ -412,6 +529,43 @@ impl Worker {
         .unwrap();
     }
 
+    /// Send an ICMP time exceeded error from the router of `node` to `src_host` in response to
+    /// `packet`, which `src_host` sent. The error arrives at `deliver_time` (or the end of the
+    /// current round if later).
+    ///
+    /// # Safety
+    ///
+    /// `packet` must be valid.
+    unsafe fn send_time_exceeded(
+        src_host: &Host,
+        packet: *mut cshadow::Packet,
+        node: u32,
+        deliver_time: EmulatedTime,
+    ) {
+        // we don't own the reference to the packet, so we need our own reference
+        unsafe { cshadow::packet_ref(packet) };
+        let packet = PacketRc::from_raw(packet);
+
+        let Some(error) = icmp::new_error_packet(
+            &packet,
+            WorkerShared::router_ip(node),
+            icmp::ICMP_TIME_EXCEEDED,
+            icmp::ICMP_EXC_TTL,
+            src_host.get_next_packet_priority(),
+        ) else {
+            return;
+        };
+
+        let deliver_time = std::cmp::max(deliver_time, Worker::round_end_time().unwrap());
+        Worker::update_next_event_time(deliver_time);
+
+        Worker::with(|w| {
+            w.shared
+                .push_packet_to_host(error, src_host.id(), deliver_time, src_host)
+        })
+        .unwrap();
+    }
+
     // Runs `f` with a shared reference to the current thread's Worker. Returns
     // None if this thread has no Worker object.
     #[must_use]
@@ -474,6 +628,23 @@ impl Worker {
         });
     }
 
+    fn increment_host_packet_count(src: HostId, dst: HostId) {
+        Worker::with(|w| {
+            if w.shared.count_host_packets {
+                let mut counts = w.host_packet_counts.borrow_mut();
+                let count = counts.entry((src, dst)).or_default();
+                *count = count.saturating_add(1);
+            }
+        })
+        .unwrap()
+    }
+
+    /// Get the number of packets sent between hosts by this worker since the last call, if enabled
+    /// by [`WorkerShared::count_host_packets`].
+    pub fn take_host_packet_counts() -> HashMap<(HostId, HostId), u64> {
+        Worker::with(|w| std::mem::take(&mut *w.host_packet_counts.borrow_mut())).unwrap()
+    }
+
     pub fn add_to_global_sim_stats() {
         Worker::with(|w| SIM_STATS.add_from_local_stats(&w.sim_stats)).unwrap()
     }
@@ -482,6 +653,16 @@ impl Worker {
         Worker::with(|w| w.shared.is_routable(src, dst)).unwrap()
     }
 
+    /// See [`WorkerShared::ipv6_to_ipv4`].
+    pub fn ipv6_to_ipv4(ip: std::net::Ipv6Addr) -> Option<std::net::Ipv4Addr> {
+        Worker::with(|w| w.shared.ipv6_to_ipv4(ip)).unwrap()
+    }
+
+    /// See [`WorkerShared::ipv4_to_ipv6`].
+    pub fn ipv4_to_ipv6(ip: std::net::Ipv4Addr) -> std::net::Ipv6Addr {
+        Worker::with(|w| w.shared.ipv4_to_ipv6(ip)).unwrap()
+    }
+
     pub fn increment_plugin_error_count() {
         Worker::with(|w| w.shared.increment_plugin_error_count()).unwrap()
     }
@@ -511,6 +692,8 @@ pub struct WorkerShared {
     pub event_queues: HashMap<HostId, Arc<Mutex<EventQueue>>>,
     pub bootstrap_end_time: EmulatedTime,
     pub sim_end_time: EmulatedTime,
+    /// Should workers count the packets sent between each pair of hosts?
+    pub count_host_packets: bool,
 }
 
 impl WorkerShared {
@@ -526,20 +709,64 @@ impl WorkerShared {
         unsafe { self.dns.ptr().as_ref() }.unwrap()
     }
 
-    pub fn latency(&self, src: std::net::IpAddr, dst: std::net::IpAddr) -> Option<SimulationTime> {
+    /// Get the properties of the path between two hosts at the given time. The network graph
+    /// may change during the simulation, so paths may change over time.
+    pub fn path(
+        &self,
+        src: std::net::IpAddr,
+        dst: std::net::IpAddr,
+        time: EmulatedTime,
+    ) -> Option<PathProperties> {
         let src = self.ip_assignment.get_node(src)?;
         let dst = self.ip_assignment.get_node(dst)?;
+        let time = time.duration_since(&EmulatedTime::SIMULATION_START);
 
-        Some(SimulationTime::from_nanos(
-            self.routing_info.path(src, dst)?.latency_ns,
-        ))
+        self.routing_info.path(src, dst, time)
     }
 
-    pub fn reliability(&self, src: std::net::IpAddr, dst: std::net::IpAddr) -> Option<f32> {
+    /// The nodes along the path from `src` to `dst` at `time`, starting with the node of `src` and
+    /// ending with the node of `dst`. Each node has a router that forwards the packets.
+    pub fn path_nodes(
+        &self,
+        src: std::net::IpAddr,
+        dst: std::net::IpAddr,
+        time: EmulatedTime,
+    ) -> Option<Vec<u32>> {
         let src = self.ip_assignment.get_node(src)?;
         let dst = self.ip_assignment.get_node(dst)?;
+        let time = time.duration_since(&EmulatedTime::SIMULATION_START);
 
-        Some(1.0 - self.routing_info.path(src, dst)?.packet_loss)
+        Some(self.routing_info.path_nodes(src, dst, time))
+    }
+
+    /// The address of the router of a node, which is used as the source address of ICMP errors
+    /// that the router sends. Addresses are taken from the shared address space (RFC 6598) so that
+    /// they're unlikely to conflict with host addresses.
+    pub fn router_ip(node: u32) -> std::net::Ipv4Addr {
+        let shared_address_space = u32::from(std::net::Ipv4Addr::new(100, 64, 0, 0));
+        std::net::Ipv4Addr::from(shared_address_space | (node & 0x003f_ffff))
+    }
+
+    pub fn latency(
+        &self,
+        src: std::net::IpAddr,
+        dst: std::net::IpAddr,
+        time: EmulatedTime,
+    ) -> Option<SimulationTime> {
+        Some(SimulationTime::from_nanos(
+            self.path(src, dst, time)?.latency_ns,
+        ))
+    }
+
+    pub fn reliability(
+        &self,
+        src: std::net::IpAddr,
+        dst: std::net::IpAddr,
+        time: EmulatedTime,
+    ) -> Option<f32> {
+        let path = self.path(src, dst, time)?;
+        let burst_loss = path.gilbert_elliott.map_or(0.0, |x| x.mean_loss());
+        Some((1.0 - path.packet_loss) * (1.0 - burst_loss))
     }
 
     pub fn bandwidth(&self, ip: std::net::IpAddr) -> Option<&Bandwidth> {
@@ -576,6 +803,52 @@ impl WorkerShared {
         Some(unsafe { cshadow::address_getID(addr) })
     }
 
+    /// Packets are always routed using the IPv4 addresses of hosts, so sockets using IPv6
+    /// addresses need to map them to IPv4 addresses. Returns the IPv4 address of the host with
+    /// the given IPv6 address, or `None` if no host has that address. The loopback, unspecified,
+    /// and IPv4-mapped addresses are mapped to their IPv4 counterparts.
+    pub fn ipv6_to_ipv4(&self, ip: std::net::Ipv6Addr) -> Option<std::net::Ipv4Addr> {
+        if let Some(ipv4) = ip.to_ipv4_mapped() {
+            return Some(ipv4);
+        }
+        if ip == std::net::Ipv6Addr::LOCALHOST {
+            return Some(std::net::Ipv4Addr::LOCALHOST);
+        }
+        if ip == std::net::Ipv6Addr::UNSPECIFIED {
+            return Some(std::net::Ipv4Addr::UNSPECIFIED);
+        }
+
+        let dns = self.dns.ptr();
+        let addr = unsafe { cshadow::dns_resolveIPv6ToAddress(dns, ip.octets().as_ptr()) };
+        if addr.is_null() {
+            return None;
+        }
+        Some(u32::from_be(unsafe { cshadow::address_toNetworkIP(addr) }).into())
+    }
+
+    /// The inverse of [`Self::ipv6_to_ipv4`]. Addresses of hosts without an IPv6 address are
+    /// returned as IPv4-mapped IPv6 addresses.
+    pub fn ipv4_to_ipv6(&self, ip: std::net::Ipv4Addr) -> std::net::Ipv6Addr {
+        if ip == std::net::Ipv4Addr::LOCALHOST {
+            return std::net::Ipv6Addr::LOCALHOST;
+        }
+        if ip.is_unspecified() {
+            return std::net::Ipv6Addr::UNSPECIFIED;
+        }
+
+        let dns = self.dns.ptr();
+        let addr = unsafe { cshadow::dns_resolveIPToAddress(dns, u32::from(ip).to_be()) };
+        if !addr.is_null() {
+            let ipv6 = unsafe { cshadow::address_toNetworkIPv6(addr) };
+            if !ipv6.is_null() {
+                let octets: [u8; 16] = unsafe { ipv6.cast::<[u8; 16]>().read() };
+                return octets.into();
+            }
+        }
+
+        ip.to_ipv6_mapped()
+    }
+
     pub fn increment_plugin_error_count(&self) {
         let old_count = self
             .num_plugin_errors
@@ -665,7 +938,8 @@ mod export {
         let src = std::net::IpAddr::V4(u32::from_be(src).into());
         let dst = std::net::IpAddr::V4(u32::from_be(dst).into());
 
-        let latency = Worker::with(|w| w.shared.latency(src, dst)).unwrap();
+        let now = Worker::current_time().unwrap();
+        let latency = Worker::with(|w| w.shared.latency(src, dst, now)).unwrap();
         SimulationTime::to_c_simtime(latency)
     }
 
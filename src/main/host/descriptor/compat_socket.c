@@ -120,6 +120,22 @@ CompatSocket compatsocket_fromTagged(uintptr_t ptr) {
     return socket;
 }
 
+bool compatsocket_isSameSocket(const CompatSocket* socket, const CompatSocket* other) {
+    if (socket->type != other->type) {
+        return false;
+    }
+
+    switch (socket->type) {
+        case CST_LEGACY_SOCKET:
+            return socket->object.as_legacy_socket == other->object.as_legacy_socket;
+        case CST_INET_SOCKET:
+            return inetsocket_ptrEq(socket->object.as_inet_socket, other->object.as_inet_socket);
+        case CST_NONE: utility_panic("Unexpected CompatSocket type");
+    }
+
+    utility_panic("Invalid CompatSocket type");
+}
+
 int compatsocket_peekNextPacketPriority(const CompatSocket* socket, uint64_t* priorityOut) {
     switch (socket->type) {
         case CST_LEGACY_SOCKET: {
@@ -150,6 +166,20 @@ bool compatsocket_hasDataToSend(const CompatSocket* socket) {
     utility_panic("Invalid CompatSocket type");
 }
 
+bool compatsocket_isListening(const CompatSocket* socket) {
+    switch (socket->type) {
+        case CST_LEGACY_SOCKET: {
+            LegacySocket* legacySocket = socket->object.as_legacy_socket;
+            return legacysocket_getProtocol(legacySocket) == PTCP &&
+                   tcp_isValidListener((TCP*)legacySocket);
+        }
+        case CST_INET_SOCKET: return inetsocket_isListening(socket->object.as_inet_socket);
+        case CST_NONE: utility_panic("Unexpected CompatSocket type");
+    }
+
+    utility_panic("Invalid CompatSocket type");
+}
+
 void compatsocket_pushInPacket(const CompatSocket* socket, const Host* host, Packet* packet,
                                CEmulatedTime recvTime) {
     switch (socket->type) {
@@ -44,9 +44,13 @@ uintptr_t compatsocket_getCanonicalHandle(const CompatSocket* socket);
 uintptr_t compatsocket_toTagged(const CompatSocket* socket);
 CompatSocket compatsocket_fromTagged(uintptr_t ptr);
 
+/* do both objects refer to the same socket? */
+bool compatsocket_isSameSocket(const CompatSocket* socket, const CompatSocket* other);
+
 /* compatability wrappers */
 int compatsocket_peekNextPacketPriority(const CompatSocket* socket, uint64_t* priorityOut);
 bool compatsocket_hasDataToSend(const CompatSocket* socket);
+bool compatsocket_isListening(const CompatSocket* socket);
 void compatsocket_pushInPacket(const CompatSocket* socket, const Host* host, Packet* packet,
                                CEmulatedTime recvTime);
 Packet* compatsocket_pullOutPacket(const CompatSocket* socket, const Host* host);
@@ -1,5 +1,6 @@
 use std::collections::{BTreeSet, HashMap};
 
+use linux_api::fcntl::DescriptorFlags;
 use log::*;
 
 use crate::cshadow as c;
@@ -116,6 +117,11 @@ impl DescriptorTable {
         self.descriptors.get_mut(&idx)
     }
 
+    /// Iterate over all registered descriptors, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&DescriptorHandle, &Descriptor)> {
+        self.descriptors.iter()
+    }
+
     /// Insert a descriptor at `index`. If a descriptor is already present at that index, it is
     /// unregistered from that index and returned.
     #[must_use]
@@ -192,6 +198,22 @@ impl DescriptorTable {
         }
     }
 
+    /// Remove and return all descriptors that have the `FD_CLOEXEC` flag set. Used when the
+    /// process image is replaced by `execve`.
+    pub fn remove_cloexec(&mut self) -> Vec<Descriptor> {
+        let cloexec_fds: Vec<DescriptorHandle> = self
+            .descriptors
+            .iter()
+            .filter(|(_, desc)| desc.flags().contains(DescriptorFlags::FD_CLOEXEC))
+            .map(|(fd, _)| *fd)
+            .collect();
+
+        cloexec_fds
+            .into_iter()
+            .map(|fd| self.deregister_descriptor(fd).unwrap())
+            .collect()
+    }
+
     /// Remove and return all descriptors.
     pub fn remove_all(&mut self) -> impl Iterator<Item = Descriptor> {
         // reset the descriptor table
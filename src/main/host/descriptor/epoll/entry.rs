@@ -0,0 +1,122 @@
+use nix::sys::epoll::EpollFlags;
+
+use crate::host::descriptor::{File, FileState};
+use crate::utility::callback_queue::Handle;
+
+/// A file that is being monitored by an epoll, along with the events that the user is interested
+/// in. Corresponds to a Linux `struct epitem`.
+pub(super) struct Entry {
+    /// The file being monitored. Holding this does not keep the file open.
+    file: File,
+    /// The events and flags that the user registered with.
+    interest: EpollFlags,
+    /// The data to return with events, given to us by the user.
+    data: u64,
+    /// The most recent state of the file.
+    state: FileState,
+    /// Set after an event has been reported for an `EPOLLONESHOT` entry, and cleared when the
+    /// entry is modified.
+    disabled: bool,
+    /// The position of this entry in the epoll's ready queue, if it's in the queue.
+    priority: Option<u64>,
+    /// The handle for our listener on the file, which stops listening when dropped.
+    listener_handle: Option<Handle<(FileState, FileState)>>,
+}
+
+impl Entry {
+    pub fn new(file: File, interest: EpollFlags, data: u64, state: FileState) -> Self {
+        Self {
+            file,
+            interest,
+            data,
+            state,
+            disabled: false,
+            priority: None,
+            listener_handle: None,
+        }
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn interest(&self) -> EpollFlags {
+        self.interest
+    }
+
+    pub fn data(&self) -> u64 {
+        self.data
+    }
+
+    pub fn set_listener_handle(&mut self, handle: Handle<(FileState, FileState)>) {
+        self.listener_handle = Some(handle);
+    }
+
+    /// Update the events and data after an `EPOLL_CTL_MOD`. This also re-enables an entry that
+    /// was disabled by `EPOLLONESHOT`.
+    pub fn modify(&mut self, interest: EpollFlags, data: u64) {
+        self.interest = interest;
+        self.data = data;
+        self.disabled = false;
+    }
+
+    pub fn state(&self) -> FileState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: FileState) {
+        self.state = state;
+    }
+
+    pub fn priority(&self) -> Option<u64> {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, priority: Option<u64>) {
+        self.priority = priority;
+    }
+
+    pub fn is_edge_triggered(&self) -> bool {
+        self.interest.contains(EpollFlags::EPOLLET)
+    }
+
+    pub fn is_oneshot(&self) -> bool {
+        self.interest.contains(EpollFlags::EPOLLONESHOT)
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.interest.contains(EpollFlags::EPOLLEXCLUSIVE)
+    }
+
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+
+    /// The events that would be reported for the given file state. `EPOLLERR` and `EPOLLHUP` are
+    /// always reported, even if the user didn't ask for them.
+    pub fn events_for_state(&self, state: FileState) -> EpollFlags {
+        if self.disabled {
+            return EpollFlags::empty();
+        }
+
+        let mut events = EpollFlags::empty();
+        events.set(EpollFlags::EPOLLIN, state.contains(FileState::READABLE));
+        events.set(EpollFlags::EPOLLOUT, state.contains(FileState::WRITABLE));
+        events.set(
+            EpollFlags::EPOLLRDHUP,
+            state.contains(FileState::SOCKET_RDHUP),
+        );
+        events.set(EpollFlags::EPOLLHUP, state.contains(FileState::HUP));
+        events.set(
+            EpollFlags::EPOLLERR,
+            state.contains(FileState::SOCKET_ERROR),
+        );
+
+        events & (self.interest | EpollFlags::EPOLLERR | EpollFlags::EPOLLHUP)
+    }
+
+    /// The events that would currently be reported.
+    pub fn events(&self) -> EpollFlags {
+        self.events_for_state(self.state)
+    }
+}
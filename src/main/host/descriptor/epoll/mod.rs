@@ -0,0 +1,519 @@
+//! An implementation of epoll(7).
+//!
+//! An epoll monitors other files by adding listeners to them, and keeps a queue of the monitored
+//! files that may have events to report. The epoll is readable when this queue is not empty.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Weak};
+
+use atomic_refcell::AtomicRefCell;
+use linux_api::errno::Errno;
+use linux_api::ioctls::IoctlRequest;
+use nix::sys::epoll::EpollFlags;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+
+use self::entry::Entry;
+use crate::cshadow as c;
+use crate::host::descriptor::{
+    File, FileMode, FileState, FileStatus, StateEventSource, StateListenerFilter,
+};
+use crate::host::memory_manager::MemoryManager;
+use crate::host::syscall::io::IoVec;
+use crate::host::syscall_types::{SyscallError, SyscallResult};
+use crate::utility::callback_queue::{CallbackQueue, Handle};
+use crate::utility::HostTreePointer;
+
+mod entry;
+
+/// The maximum depth of nested epolls. Linux calls this `EP_MAX_NESTS`.
+const MAX_NESTING_DEPTH: usize = 4;
+
+/// The only flags that may be used together with `EPOLLEXCLUSIVE`. Linux calls this
+/// `EPOLLEXCLUSIVE_OK_BITS`.
+const EXCLUSIVE_OK_FLAGS: EpollFlags = EpollFlags::EPOLLIN
+    .union(EpollFlags::EPOLLOUT)
+    .union(EpollFlags::EPOLLERR)
+    .union(EpollFlags::EPOLLHUP)
+    .union(EpollFlags::EPOLLWAKEUP)
+    .union(EpollFlags::EPOLLET)
+    .union(EpollFlags::EPOLLEXCLUSIVE);
+
+/// The file states that can affect the events reported for a monitored file.
+const MONITORED_STATES: FileState = FileState::READABLE
+    .union(FileState::WRITABLE)
+    .union(FileState::HUP)
+    .union(FileState::SOCKET_RDHUP)
+    .union(FileState::SOCKET_ERROR)
+    .union(FileState::CLOSED);
+
+/// An operation for [`Epoll::ctl`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EpollCtlOp {
+    Add,
+    Mod,
+    Del,
+}
+
+impl TryFrom<libc::c_int> for EpollCtlOp {
+    type Error = ();
+
+    fn try_from(op: libc::c_int) -> Result<Self, Self::Error> {
+        match op {
+            libc::EPOLL_CTL_ADD => Ok(Self::Add),
+            libc::EPOLL_CTL_MOD => Ok(Self::Mod),
+            libc::EPOLL_CTL_DEL => Ok(Self::Del),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Monitored files are identified by both the fd and the file that were used when adding them,
+/// so the same file can be added multiple times under different fds (for example after a `dup()`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    fd: i32,
+    handle: usize,
+}
+
+impl Key {
+    fn new(fd: i32, file: &File) -> Self {
+        Self {
+            fd,
+            handle: file.canonical_handle(),
+        }
+    }
+}
+
+pub struct Epoll {
+    event_source: StateEventSource,
+    status: FileStatus,
+    state: FileState,
+    // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
+    // this file
+    has_open_file: bool,
+    // used by the listeners that we add to monitored files
+    weak_self: Weak<AtomicRefCell<Epoll>>,
+    /// The files that we're monitoring.
+    entries: HashMap<Key, Entry>,
+    /// The entries that may have events to report, in the order that they'll be reported. Entries
+    /// are added to the back of the queue, so that a file that always has events can't starve the
+    /// others.
+    ready: BTreeMap<u64, Key>,
+    next_priority: u64,
+}
+
+impl Epoll {
+    /// Creates a new [`Epoll`]. The epoll is wrapped in an [`Arc<AtomicRefCell>`] since the
+    /// listeners that it adds to the files it monitors need a weak reference back to it.
+    pub fn new(status: FileStatus) -> Arc<AtomicRefCell<Self>> {
+        Arc::new_cyclic(|weak| {
+            AtomicRefCell::new(Self {
+                event_source: StateEventSource::new(),
+                status,
+                state: FileState::ACTIVE,
+                has_open_file: false,
+                weak_self: weak.clone(),
+                entries: HashMap::new(),
+                ready: BTreeMap::new(),
+                next_priority: 0,
+            })
+        })
+    }
+
+    pub fn get_status(&self) -> FileStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: FileStatus) {
+        self.status = status;
+    }
+
+    pub fn mode(&self) -> FileMode {
+        FileMode::READ
+    }
+
+    pub fn has_open_file(&self) -> bool {
+        self.has_open_file
+    }
+
+    pub fn supports_sa_restart(&self) -> bool {
+        false
+    }
+
+    pub fn set_has_open_file(&mut self, val: bool) {
+        self.has_open_file = val;
+    }
+
+    pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
+        // stop listening to all monitored files
+        self.entries.clear();
+        self.ready.clear();
+
+        // set the closed flag and remove the active and readable flags
+        self.copy_state(
+            FileState::CLOSED | FileState::ACTIVE | FileState::READABLE,
+            FileState::CLOSED,
+            cb_queue,
+        );
+
+        Ok(())
+    }
+
+    pub fn readv(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // epoll(7): "an epoll instance can't be read from or written to"
+        Err(Errno::EINVAL.into())
+    }
+
+    pub fn writev(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        Err(Errno::EINVAL.into())
+    }
+
+    pub fn ioctl(
+        &mut self,
+        request: IoctlRequest,
+        _arg_ptr: ForeignPtr<()>,
+        _memory_manager: &mut MemoryManager,
+    ) -> SyscallResult {
+        log::warn!("We do not yet handle ioctl request {request:?} on epolls");
+        Err(Errno::EINVAL.into())
+    }
+
+    pub fn add_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
+        self.event_source.add_legacy_listener(ptr);
+    }
+
+    pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener) {
+        self.event_source.remove_legacy_listener(ptr);
+    }
+
+    pub fn state(&self) -> FileState {
+        self.state
+    }
+
+    /// Add, modify, or remove a monitored file, as in `epoll_ctl(2)`. The `events` and `data` are
+    /// ignored for [`EpollCtlOp::Del`].
+    pub fn ctl(
+        &mut self,
+        op: EpollCtlOp,
+        fd: i32,
+        target: File,
+        events: EpollFlags,
+        data: u64,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<(), Errno> {
+        // we don't support waking the system from suspend, and linux ignores this flag when the
+        // caller doesn't have the `CAP_BLOCK_SUSPEND` capability
+        let events = events - EpollFlags::EPOLLWAKEUP;
+
+        if target.canonical_handle() == self.weak_self.as_ptr() as usize {
+            log::trace!("An epoll can't monitor itself");
+            return Err(Errno::EINVAL);
+        }
+
+        if op != EpollCtlOp::Del && events.contains(EpollFlags::EPOLLEXCLUSIVE) {
+            if op == EpollCtlOp::Mod {
+                log::trace!("EPOLLEXCLUSIVE can't be used with EPOLL_CTL_MOD");
+                return Err(Errno::EINVAL);
+            }
+            if matches!(target, File::Epoll(_)) || !EXCLUSIVE_OK_FLAGS.contains(events) {
+                log::trace!("Invalid use of EPOLLEXCLUSIVE with events {events:?}");
+                return Err(Errno::EINVAL);
+            }
+        }
+
+        if op == EpollCtlOp::Add {
+            if let File::Epoll(ref target) = target {
+                self.check_nesting(target, 0)?;
+            }
+        }
+
+        let key = Key::new(fd, &target);
+
+        match op {
+            EpollCtlOp::Add => {
+                if self.entries.contains_key(&key) {
+                    return Err(Errno::EEXIST);
+                }
+
+                let state = target.borrow().state();
+                let mut entry = Entry::new(target.clone(), events, data, state);
+                entry.set_listener_handle(self.listen_to(key, &target, entry.is_exclusive()));
+
+                self.entries.insert(key, entry);
+
+                // any events that are already available should be reported, even for
+                // edge-triggered entries
+                self.update_entry(key, FileState::empty(), state, cb_queue);
+            }
+            EpollCtlOp::Mod => {
+                let entry = self.entries.get_mut(&key).ok_or(Errno::ENOENT)?;
+
+                if entry.is_exclusive() {
+                    log::trace!("Can't modify an entry that was added with EPOLLEXCLUSIVE");
+                    return Err(Errno::EINVAL);
+                }
+
+                entry.modify(events, data);
+
+                // any events that are now available should be reported, even for edge-triggered
+                // entries
+                let state = entry.state();
+                self.update_entry(key, FileState::empty(), state, cb_queue);
+            }
+            EpollCtlOp::Del => {
+                let entry = self.entries.remove(&key).ok_or(Errno::ENOENT)?;
+                if let Some(priority) = entry.priority() {
+                    self.ready.remove(&priority);
+                }
+                self.refresh_state(cb_queue);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns an error if adding `target` would create a loop of epolls, or nest epolls too
+    /// deeply.
+    fn check_nesting(&self, target: &Arc<AtomicRefCell<Epoll>>, depth: usize) -> Result<(), Errno> {
+        if std::ptr::eq(Arc::as_ptr(target), self.weak_self.as_ptr()) {
+            log::trace!("Adding the epoll would create a loop");
+            return Err(Errno::ELOOP);
+        }
+
+        if depth >= MAX_NESTING_DEPTH {
+            log::trace!("Adding the epoll would nest epolls too deeply");
+            return Err(Errno::ELOOP);
+        }
+
+        let target = target.borrow();
+        for entry in target.entries.values() {
+            if let File::Epoll(ref nested) = entry.file() {
+                self.check_nesting(nested, depth + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add a listener to `target` that updates the entry with the given key when the file's state
+    /// changes.
+    fn listen_to(
+        &self,
+        key: Key,
+        target: &File,
+        exclusive: bool,
+    ) -> Handle<(FileState, FileState)> {
+        let weak_self = self.weak_self.clone();
+
+        if !exclusive {
+            return target.borrow_mut().add_listener(
+                MONITORED_STATES,
+                StateListenerFilter::Always,
+                move |state, changed, cb_queue| {
+                    if let Some(epoll) = weak_self.upgrade() {
+                        epoll
+                            .borrow_mut()
+                            .update_entry(key, state ^ changed, state, cb_queue);
+                    }
+                },
+            );
+        }
+
+        // Like linux, an exclusive entry will stop the file from notifying other exclusive
+        // entries if the epoll has a thread waiting on it and the entry now has events to report.
+        target.borrow_mut().add_exclusive_listener(
+            MONITORED_STATES,
+            StateListenerFilter::Always,
+            move |state, changed, cb_queue| {
+                let Some(epoll) = weak_self.upgrade() else {
+                    return false;
+                };
+                let mut epoll = epoll.as_ref().borrow_mut();
+
+                epoll.update_entry(key, state ^ changed, state, cb_queue);
+
+                let has_events = epoll
+                    .entries
+                    .get(&key)
+                    .is_some_and(|entry| !entry.events().is_empty());
+
+                has_events && epoll.event_source.has_legacy_listeners()
+            },
+        )
+    }
+
+    /// Update an entry after its file's state changed from `old_state` to `new_state`, and add or
+    /// remove it from the ready queue.
+    fn update_entry(
+        &mut self,
+        key: Key,
+        old_state: FileState,
+        new_state: FileState,
+        cb_queue: &mut CallbackQueue,
+    ) {
+        let Some(entry) = self.entries.get_mut(&key) else {
+            // the entry was removed after this notification was queued
+            return;
+        };
+
+        if new_state.contains(FileState::CLOSED) {
+            // epoll(7): "closing a file descriptor cause[s] it to be removed from all epoll
+            // interest lists" once all references to the open file description are closed
+            let entry = self.entries.remove(&key).unwrap();
+            if let Some(priority) = entry.priority() {
+                self.ready.remove(&priority);
+            }
+            self.refresh_state(cb_queue);
+            return;
+        }
+
+        entry.set_state(new_state);
+
+        let events = entry.events();
+        let new_events = events - entry.events_for_state(old_state);
+
+        if events.is_empty() {
+            if let Some(priority) = entry.priority() {
+                self.ready.remove(&priority);
+                entry.set_priority(None);
+            }
+        } else if entry.priority().is_none()
+            && (!entry.is_edge_triggered() || !new_events.is_empty())
+        {
+            // level-triggered entries are ready whenever they have events, but edge-triggered
+            // entries are only ready after a new event occurs
+            let priority = self.next_priority;
+            self.next_priority += 1;
+            self.ready.insert(priority, key);
+            entry.set_priority(Some(priority));
+        }
+
+        self.refresh_state(cb_queue);
+    }
+
+    /// Returns true if there are monitored files that may have events to report.
+    pub fn has_ready_events(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// Collect up to `max_events` events from the monitored files, as in `epoll_wait(2)`.
+    pub fn collect_ready_events(
+        &mut self,
+        max_events: usize,
+        cb_queue: &mut CallbackQueue,
+    ) -> Vec<libc::epoll_event> {
+        let mut events = Vec::new();
+        let mut still_ready = Vec::new();
+
+        while events.len() < max_events {
+            let Some((_, key)) = self.ready.pop_first() else {
+                break;
+            };
+
+            let entry = self.entries.get_mut(&key).unwrap();
+            entry.set_priority(None);
+
+            let entry_events = entry.events();
+            if entry_events.is_empty() {
+                continue;
+            }
+
+            events.push(libc::epoll_event {
+                events: entry_events.bits() as u32,
+                u64: entry.data(),
+            });
+
+            if entry.is_oneshot() {
+                // don't report any more events until the entry is modified
+                entry.disable();
+            } else if !entry.is_edge_triggered() {
+                // level-triggered entries are ready until they no longer have events
+                still_ready.push(key);
+            }
+        }
+
+        // move the level-triggered entries to the back of the queue
+        for key in still_ready {
+            let priority = self.next_priority;
+            self.next_priority += 1;
+            self.ready.insert(priority, key);
+            self.entries
+                .get_mut(&key)
+                .unwrap()
+                .set_priority(Some(priority));
+        }
+
+        self.refresh_state(cb_queue);
+
+        events
+    }
+
+    fn refresh_state(&mut self, cb_queue: &mut CallbackQueue) {
+        if self.state.contains(FileState::CLOSED) {
+            return;
+        }
+
+        let readable = self
+            .has_ready_events()
+            .then_some(FileState::READABLE)
+            .unwrap_or_default();
+
+        self.copy_state(/* mask= */ FileState::READABLE, readable, cb_queue);
+    }
+
+    fn copy_state(&mut self, mask: FileState, state: FileState, cb_queue: &mut CallbackQueue) {
+        let old_state = self.state;
+
+        // remove the masked flags, then copy the masked flags
+        self.state.remove(mask);
+        self.state.insert(state & mask);
+
+        self.handle_state_change(old_state, cb_queue);
+    }
+
+    fn handle_state_change(&mut self, old_state: FileState, cb_queue: &mut CallbackQueue) {
+        let states_changed = self.state ^ old_state;
+
+        // if nothing changed
+        if states_changed.is_empty() {
+            return;
+        }
+
+        self.event_source
+            .notify_listeners(self.state, states_changed, cb_queue);
+    }
+}
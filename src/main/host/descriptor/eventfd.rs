@@ -200,6 +200,16 @@ impl EventFd {
             .add_listener(monitoring, filter, notify_fn)
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         self.event_source.add_legacy_listener(ptr);
     }
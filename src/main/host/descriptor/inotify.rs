@@ -0,0 +1,442 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use linux_api::errno::Errno;
+use linux_api::ioctls::IoctlRequest;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+
+use crate::cshadow as c;
+use crate::host::descriptor::{
+    FileMode, FileState, FileStatus, StateEventSource, StateListenerFilter,
+};
+use crate::host::memory_manager::MemoryManager;
+use crate::host::syscall::io::{IoVec, IoVecWriter};
+use crate::host::syscall_types::{SyscallError, SyscallResult};
+use crate::utility::callback_queue::{CallbackQueue, Handle};
+use crate::utility::HostTreePointer;
+
+/// The default value of "/proc/sys/fs/inotify/max_queued_events".
+const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// The events that are reported for the watched file itself. All events are reported for the
+/// children of a watched directory.
+const SELF_EVENTS: u32 = libc::IN_MODIFY | libc::IN_CLOSE_WRITE;
+
+struct Watch {
+    /// The canonical path of the watched file or directory.
+    path: PathBuf,
+    mask: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InotifyEvent {
+    wd: libc::c_int,
+    mask: u32,
+    cookie: u32,
+    name: Option<Vec<u8>>,
+}
+
+impl InotifyEvent {
+    /// The length of the name field, including the nul terminator and padding.
+    fn name_len(&self) -> usize {
+        const ALIGN: usize = std::mem::size_of::<libc::inotify_event>();
+
+        match &self.name {
+            // inotify(7): "The name field is present only when an event is returned for a file
+            // inside a watched directory [...] it may also include additional null bytes ('\0') to
+            // pad subsequent reads to a suitable address boundary"
+            Some(name) => (name.len() + 1 + ALIGN - 1) / ALIGN * ALIGN,
+            None => 0,
+        }
+    }
+
+    /// The size of the event when read from the inotify file.
+    fn size(&self) -> usize {
+        std::mem::size_of::<libc::inotify_event>() + self.name_len()
+    }
+
+    fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let name_len = self.name_len();
+
+        writer.write_all(&self.wd.to_ne_bytes())?;
+        writer.write_all(&self.mask.to_ne_bytes())?;
+        writer.write_all(&self.cookie.to_ne_bytes())?;
+        writer.write_all(&u32::try_from(name_len).unwrap().to_ne_bytes())?;
+
+        if let Some(name) = &self.name {
+            writer.write_all(name)?;
+            writer.write_all(&vec![0u8; name_len - name.len()])?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Inotify {
+    /// The watches, indexed by their watch descriptor.
+    watches: BTreeMap<libc::c_int, Watch>,
+    next_wd: libc::c_int,
+    events: VecDeque<InotifyEvent>,
+    event_source: StateEventSource,
+    state: FileState,
+    status: FileStatus,
+    // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
+    // this file
+    has_open_file: bool,
+}
+
+impl Inotify {
+    pub fn new(status: FileStatus) -> Self {
+        Self {
+            watches: BTreeMap::new(),
+            next_wd: 1,
+            events: VecDeque::new(),
+            event_source: StateEventSource::new(),
+            state: FileState::ACTIVE,
+            status,
+            has_open_file: false,
+        }
+    }
+
+    pub fn get_status(&self) -> FileStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: FileStatus) {
+        self.status = status;
+    }
+
+    pub fn mode(&self) -> FileMode {
+        FileMode::READ
+    }
+
+    pub fn has_open_file(&self) -> bool {
+        self.has_open_file
+    }
+
+    pub fn supports_sa_restart(&self) -> bool {
+        true
+    }
+
+    pub fn set_has_open_file(&mut self, val: bool) {
+        self.has_open_file = val;
+    }
+
+    pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
+        self.watches.clear();
+        self.events.clear();
+
+        // set the closed flag and remove the active and readable flags
+        self.copy_state(
+            FileState::CLOSED | FileState::ACTIVE | FileState::READABLE,
+            FileState::CLOSED,
+            cb_queue,
+        );
+
+        Ok(())
+    }
+
+    /// Add a watch for the file at the canonical path `path`, or modify the existing watch for that
+    /// file. Returns the watch descriptor.
+    pub fn add_watch(&mut self, path: PathBuf, mask: u32) -> Result<libc::c_int, Errno> {
+        let mask_create = mask & libc::IN_MASK_CREATE != 0;
+        let mask_add = mask & libc::IN_MASK_ADD != 0;
+
+        // these only affect how the watch is added, and aren't part of the watch's mask
+        let mask = mask
+            & !(libc::IN_MASK_CREATE | libc::IN_MASK_ADD | libc::IN_DONT_FOLLOW | libc::IN_ONLYDIR);
+
+        let existing = self
+            .watches
+            .iter_mut()
+            .find(|(_, watch)| watch.path == path);
+
+        if let Some((wd, watch)) = existing {
+            if mask_create {
+                return Err(Errno::EEXIST);
+            }
+
+            if mask_add {
+                watch.mask |= mask;
+            } else {
+                watch.mask = mask;
+            }
+
+            return Ok(*wd);
+        }
+
+        let wd = self.next_wd;
+        self.next_wd = self.next_wd.checked_add(1).ok_or(Errno::ENOSPC)?;
+
+        self.watches.insert(wd, Watch { path, mask });
+
+        Ok(wd)
+    }
+
+    /// Remove the watch `wd`, as in `inotify_rm_watch(2)`.
+    pub fn rm_watch(&mut self, wd: libc::c_int, cb_queue: &mut CallbackQueue) -> Result<(), Errno> {
+        if !self.watches.contains_key(&wd) {
+            return Err(Errno::EINVAL);
+        }
+
+        self.remove_watch(wd, cb_queue);
+
+        Ok(())
+    }
+
+    /// Queue events for any watches on the file at the canonical path `path` or on its parent
+    /// directory. `mask` is the event that occurred, and may include `IN_ISDIR`.
+    pub fn notify(&mut self, path: &Path, mask: u32, cb_queue: &mut CallbackQueue) {
+        if self.state.contains(FileState::CLOSED) {
+            return;
+        }
+
+        let event = mask & !libc::IN_ISDIR;
+        let parent = path.parent();
+
+        // collect first since watches may be removed while queuing events
+        let wds: Vec<_> = self.watches.keys().copied().collect();
+
+        for wd in wds {
+            let watch = &self.watches[&wd];
+            let watch_mask = watch.mask;
+
+            let name = if watch.path == path {
+                if event == libc::IN_DELETE {
+                    // the watched file itself was deleted, so the watch is removed
+                    if watch_mask & libc::IN_DELETE_SELF != 0 {
+                        self.push_event(wd, libc::IN_DELETE_SELF, None, cb_queue);
+                    }
+                    self.remove_watch(wd, cb_queue);
+                    continue;
+                }
+
+                if event & SELF_EVENTS == 0 {
+                    continue;
+                }
+
+                None
+            } else if Some(watch.path.as_path()) == parent {
+                path.file_name().map(|x| x.as_bytes().to_vec())
+            } else {
+                continue;
+            };
+
+            if watch_mask & event == 0 {
+                continue;
+            }
+
+            self.push_event(wd, mask, name, cb_queue);
+
+            if watch_mask & libc::IN_ONESHOT != 0 {
+                self.remove_watch(wd, cb_queue);
+            }
+        }
+    }
+
+    /// Remove the watch and queue an `IN_IGNORED` event for it.
+    fn remove_watch(&mut self, wd: libc::c_int, cb_queue: &mut CallbackQueue) {
+        // inotify(7): "IN_IGNORED: Watch was removed explicitly (inotify_rm_watch(2)) or
+        // automatically (file was deleted, or filesystem was unmounted)"
+        if self.watches.remove(&wd).is_some() {
+            self.push_event(wd, libc::IN_IGNORED, None, cb_queue);
+        }
+    }
+
+    fn push_event(
+        &mut self,
+        wd: libc::c_int,
+        mask: u32,
+        name: Option<Vec<u8>>,
+        cb_queue: &mut CallbackQueue,
+    ) {
+        let event = InotifyEvent {
+            wd,
+            mask,
+            cookie: 0,
+            name,
+        };
+
+        // inotify(7): "If successive output inotify events produced on the inotify file descriptor
+        // are identical (same wd, mask, cookie, and name), then they are coalesced into a single
+        // event if the older event has not yet been read"
+        if self.events.back() == Some(&event) {
+            return;
+        }
+
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            let overflow = InotifyEvent {
+                wd: -1,
+                mask: libc::IN_Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            };
+
+            if self.events.back() != Some(&overflow) {
+                self.events.push_back(overflow);
+            }
+        } else {
+            log::trace!("Queuing inotify event {event:?}");
+            self.events.push_back(event);
+        }
+
+        self.refresh_state(cb_queue);
+    }
+
+    pub fn readv(
+        &mut self,
+        iovs: &[IoVec],
+        offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        mem: &mut MemoryManager,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // inotify files don't support seeking
+        if offset.is_some() {
+            return Err(Errno::ESPIPE.into());
+        }
+
+        let Some(first) = self.events.front() else {
+            return Err(Errno::EWOULDBLOCK.into());
+        };
+
+        let len: libc::size_t = iovs.iter().map(|x| x.len).sum();
+
+        // inotify(7): "if the buffer given to read(2) is too small to return information about the
+        // next event, an EINVAL error results"
+        if len < first.size() {
+            log::trace!(
+                "Reading from inotify requires a buffer of at least {} bytes",
+                first.size()
+            );
+            return Err(Errno::EINVAL.into());
+        }
+
+        let mut writer = IoVecWriter::new(iovs, mem);
+        let mut bytes_written = 0;
+
+        while let Some(event) = self.events.front() {
+            if bytes_written + event.size() > len {
+                break;
+            }
+
+            event.write(&mut writer)?;
+            bytes_written += event.size();
+            self.events.pop_front();
+        }
+
+        self.refresh_state(cb_queue);
+
+        Ok(bytes_written.try_into().unwrap())
+    }
+
+    pub fn writev(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // inotify files don't support writing
+        Err(Errno::EINVAL.into())
+    }
+
+    pub fn ioctl(
+        &mut self,
+        request: IoctlRequest,
+        arg_ptr: ForeignPtr<()>,
+        mem: &mut MemoryManager,
+    ) -> SyscallResult {
+        match request {
+            IoctlRequest::FIONREAD => {
+                let len: libc::c_int = self
+                    .events
+                    .iter()
+                    .map(|x| x.size())
+                    .sum::<usize>()
+                    .try_into()
+                    .unwrap();
+
+                let arg_ptr = arg_ptr.cast::<libc::c_int>();
+                mem.write(arg_ptr, &len)?;
+
+                Ok(0.into())
+            }
+            request => {
+                log::warn!("We do not yet handle ioctl request {request:?} on inotify files");
+                Err(Errno::EINVAL.into())
+            }
+        }
+    }
+
+    pub fn add_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
+        self.event_source.add_legacy_listener(ptr);
+    }
+
+    pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener) {
+        self.event_source.remove_legacy_listener(ptr);
+    }
+
+    pub fn state(&self) -> FileState {
+        self.state
+    }
+
+    fn refresh_state(&mut self, cb_queue: &mut CallbackQueue) {
+        if self.state.contains(FileState::CLOSED) {
+            return;
+        }
+
+        let readable = if self.events.is_empty() {
+            FileState::empty()
+        } else {
+            FileState::READABLE
+        };
+
+        self.copy_state(FileState::READABLE, readable, cb_queue);
+    }
+
+    fn copy_state(&mut self, mask: FileState, state: FileState, cb_queue: &mut CallbackQueue) {
+        let old_state = self.state;
+
+        // remove the masked flags, then copy the masked flags
+        self.state.remove(mask);
+        self.state.insert(state & mask);
+
+        self.handle_state_change(old_state, cb_queue);
+    }
+
+    fn handle_state_change(&mut self, old_state: FileState, cb_queue: &mut CallbackQueue) {
+        let states_changed = self.state ^ old_state;
+
+        // if nothing changed
+        if states_changed.is_empty() {
+            return;
+        }
+
+        self.event_source
+            .notify_listeners(self.state, states_changed, cb_queue);
+    }
+}
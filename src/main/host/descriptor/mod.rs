@@ -16,9 +16,12 @@ use crate::utility::callback_queue::{CallbackQueue, EventSource, Handle};
 use crate::utility::{HostTreePointer, IsSend, IsSync};
 
 pub mod descriptor_table;
+pub mod epoll;
 pub mod eventfd;
+pub mod inotify;
 pub mod pipe;
 pub mod shared_buf;
+pub mod signalfd;
 pub mod socket;
 pub mod timerfd;
 
@@ -120,6 +123,23 @@ bitflags::bitflags! {
         /// A listening socket is allowing connections. Only applicable to connection-oriented unix
         /// sockets.
         const SOCKET_ALLOWING_CONNECT = c::_Status_STATUS_SOCKET_ALLOWING_CONNECT;
+        /// A child of the process has exited. Only applicable to a process's
+        /// [`ChildEventSource`](crate::host::process::ChildEventSource).
+        const CHILD_EXITED = c::_Status_STATUS_CHILD_EXITED;
+        /// The file has been hung up. For example the peer of a connected socket has closed, or
+        /// all of a pipe's writers have closed.
+        const HUP = c::_Status_STATUS_FILE_HUP;
+        /// The socket will not receive any more data, either because the peer shut down its
+        /// writing half or because the socket was shut down for reading. Only applicable to
+        /// connection-oriented sockets.
+        const SOCKET_RDHUP = c::_Status_STATUS_SOCKET_RDHUP;
+        /// The socket has a pending error (for example from an ICMP error message), or errors in
+        /// its error queue.
+        const SOCKET_ERROR = c::_Status_STATUS_SOCKET_ERROR;
+        /// A child of the process created with `CLONE_VFORK` has released its parent by calling
+        /// `execve` or exiting. Only applicable to a process's
+        /// [`ChildEventSource`](crate::host::process::ChildEventSource).
+        const CHILD_VFORK_DONE = c::_Status_STATUS_CHILD_VFORK_DONE;
     }
 }
 
@@ -245,23 +265,7 @@ impl StateEventSource {
         notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static,
     ) -> Handle<(FileState, FileState)> {
         self.inner.add_listener(move |(state, changed), cb_queue| {
-            // true if any of the bits we're monitoring have changed
-            let flipped = monitoring.intersects(changed);
-
-            // true if any of the bits we're monitoring are set
-            let on = monitoring.intersects(state);
-
-            let notify = match filter {
-                // at least one monitored bit is on, and at least one has changed
-                StateListenerFilter::OffToOn => flipped && on,
-                // all monitored bits are off, and at least one has changed
-                StateListenerFilter::OnToOff => flipped && !on,
-                // at least one monitored bit has changed
-                StateListenerFilter::Always => flipped,
-                StateListenerFilter::Never => false,
-            };
-
-            if !notify {
+            if !Self::should_notify(monitoring, &filter, state, changed) {
                 return;
             }
 
@@ -269,6 +273,48 @@ impl StateEventSource {
         })
     }
 
+    /// Add a listener that is notified after all non-exclusive listeners, and only if no
+    /// previously-added exclusive listener returned `true` for the same state change. Used to
+    /// avoid waking more listeners than necessary (for example for `EPOLLEXCLUSIVE`).
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.inner
+            .add_exclusive_listener(move |(state, changed), cb_queue| {
+                if !Self::should_notify(monitoring, &filter, state, changed) {
+                    return false;
+                }
+
+                (notify_fn)(state, changed, cb_queue)
+            })
+    }
+
+    fn should_notify(
+        monitoring: FileState,
+        filter: &StateListenerFilter,
+        state: FileState,
+        changed: FileState,
+    ) -> bool {
+        // true if any of the bits we're monitoring have changed
+        let flipped = monitoring.intersects(changed);
+
+        // true if any of the bits we're monitoring are set
+        let on = monitoring.intersects(state);
+
+        match filter {
+            // at least one monitored bit is on, and at least one has changed
+            StateListenerFilter::OffToOn => flipped && on,
+            // all monitored bits are off, and at least one has changed
+            StateListenerFilter::OnToOff => flipped && !on,
+            // at least one monitored bit has changed
+            StateListenerFilter::Always => flipped,
+            StateListenerFilter::Never => false,
+        }
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         self.legacy_helper.add_listener(ptr, &mut self.inner);
     }
@@ -277,6 +323,12 @@ impl StateEventSource {
         self.legacy_helper.remove_listener(ptr);
     }
 
+    /// Returns true if any legacy listeners (for example the syscall conditions of blocked
+    /// threads) are listening.
+    pub fn has_legacy_listeners(&self) -> bool {
+        !self.legacy_helper.handles.is_empty()
+    }
+
     pub fn notify_listeners(
         &mut self,
         state: FileState,
@@ -300,6 +352,9 @@ pub enum File {
     EventFd(Arc<AtomicRefCell<eventfd::EventFd>>),
     Socket(Socket),
     TimerFd(Arc<AtomicRefCell<timerfd::TimerFd>>),
+    Epoll(Arc<AtomicRefCell<epoll::Epoll>>),
+    SignalFd(Arc<AtomicRefCell<signalfd::SignalFd>>),
+    Inotify(Arc<AtomicRefCell<inotify::Inotify>>),
 }
 
 // will not compile if `File` is not Send + Sync
@@ -313,6 +368,9 @@ impl File {
             Self::EventFd(ref f) => FileRef::EventFd(f.borrow()),
             Self::Socket(ref f) => FileRef::Socket(f.borrow()),
             Self::TimerFd(ref f) => FileRef::TimerFd(f.borrow()),
+            Self::Epoll(ref f) => FileRef::Epoll(f.borrow()),
+            Self::SignalFd(ref f) => FileRef::SignalFd(f.borrow()),
+            Self::Inotify(ref f) => FileRef::Inotify(f.borrow()),
         }
     }
 
@@ -322,6 +380,9 @@ impl File {
             Self::EventFd(ref f) => FileRef::EventFd(f.try_borrow()?),
             Self::Socket(ref f) => FileRef::Socket(f.try_borrow()?),
             Self::TimerFd(ref f) => FileRef::TimerFd(f.try_borrow()?),
+            Self::Epoll(ref f) => FileRef::Epoll(f.try_borrow()?),
+            Self::SignalFd(ref f) => FileRef::SignalFd(f.try_borrow()?),
+            Self::Inotify(ref f) => FileRef::Inotify(f.try_borrow()?),
         })
     }
 
@@ -331,6 +392,9 @@ impl File {
             Self::EventFd(ref f) => FileRefMut::EventFd(f.borrow_mut()),
             Self::Socket(ref f) => FileRefMut::Socket(f.borrow_mut()),
             Self::TimerFd(ref f) => FileRefMut::TimerFd(f.borrow_mut()),
+            Self::Epoll(ref f) => FileRefMut::Epoll(f.borrow_mut()),
+            Self::SignalFd(ref f) => FileRefMut::SignalFd(f.borrow_mut()),
+            Self::Inotify(ref f) => FileRefMut::Inotify(f.borrow_mut()),
         }
     }
 
@@ -340,6 +404,9 @@ impl File {
             Self::EventFd(ref f) => FileRefMut::EventFd(f.try_borrow_mut()?),
             Self::Socket(ref f) => FileRefMut::Socket(f.try_borrow_mut()?),
             Self::TimerFd(ref f) => FileRefMut::TimerFd(f.try_borrow_mut()?),
+            Self::Epoll(ref f) => FileRefMut::Epoll(f.try_borrow_mut()?),
+            Self::SignalFd(ref f) => FileRefMut::SignalFd(f.try_borrow_mut()?),
+            Self::Inotify(ref f) => FileRefMut::Inotify(f.try_borrow_mut()?),
         })
     }
 
@@ -349,6 +416,9 @@ impl File {
             Self::EventFd(f) => Arc::as_ptr(f) as usize,
             Self::Socket(ref f) => f.canonical_handle(),
             Self::TimerFd(f) => Arc::as_ptr(f) as usize,
+            Self::Epoll(f) => Arc::as_ptr(f) as usize,
+            Self::SignalFd(f) => Arc::as_ptr(f) as usize,
+            Self::Inotify(f) => Arc::as_ptr(f) as usize,
         }
     }
 }
@@ -360,6 +430,9 @@ impl std::fmt::Debug for File {
             Self::EventFd(_) => write!(f, "EventFd")?,
             Self::Socket(_) => write!(f, "Socket")?,
             Self::TimerFd(_) => write!(f, "TimerFd")?,
+            Self::Epoll(_) => write!(f, "Epoll")?,
+            Self::SignalFd(_) => write!(f, "SignalFd")?,
+            Self::Inotify(_) => write!(f, "Inotify")?,
         }
 
         if let Ok(file) = self.try_borrow() {
@@ -380,6 +453,9 @@ pub enum FileRef<'a> {
     EventFd(atomic_refcell::AtomicRef<'a, eventfd::EventFd>),
     Socket(SocketRef<'a>),
     TimerFd(atomic_refcell::AtomicRef<'a, timerfd::TimerFd>),
+    Epoll(atomic_refcell::AtomicRef<'a, epoll::Epoll>),
+    SignalFd(atomic_refcell::AtomicRef<'a, signalfd::SignalFd>),
+    Inotify(atomic_refcell::AtomicRef<'a, inotify::Inotify>),
 }
 
 pub enum FileRefMut<'a> {
@@ -387,65 +463,78 @@ pub enum FileRefMut<'a> {
     EventFd(atomic_refcell::AtomicRefMut<'a, eventfd::EventFd>),
     Socket(SocketRefMut<'a>),
     TimerFd(atomic_refcell::AtomicRefMut<'a, timerfd::TimerFd>),
+    Epoll(atomic_refcell::AtomicRefMut<'a, epoll::Epoll>),
+    SignalFd(atomic_refcell::AtomicRefMut<'a, signalfd::SignalFd>),
+    Inotify(atomic_refcell::AtomicRefMut<'a, inotify::Inotify>),
 }
 
 impl FileRef<'_> {
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn state(&self) -> FileState
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn mode(&self) -> FileMode
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn get_status(&self) -> FileStatus
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn has_open_file(&self) -> bool
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn supports_sa_restart(&self) -> bool
     );
 }
 
 impl FileRefMut<'_> {
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn state(&self) -> FileState
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn mode(&self) -> FileMode
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn get_status(&self) -> FileStatus
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn has_open_file(&self) -> bool
     );
-    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn supports_sa_restart(&self) -> bool
     );
-    enum_passthrough!(self, (val), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (val), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn set_has_open_file(&mut self, val: bool)
     );
-    enum_passthrough!(self, (cb_queue), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (cb_queue), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError>
     );
-    enum_passthrough!(self, (status), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (status), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn set_status(&mut self, status: FileStatus)
     );
-    enum_passthrough!(self, (request, arg_ptr, memory_manager), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (request, arg_ptr, memory_manager), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn ioctl(&mut self, request: IoctlRequest, arg_ptr: ForeignPtr<()>, memory_manager: &mut MemoryManager) -> SyscallResult
     );
-    enum_passthrough!(self, (ptr), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (monitoring, filter, notify_fn), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
+        pub fn add_listener(&mut self, monitoring: FileState, filter: StateListenerFilter,
+                            notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static)
+                            -> Handle<(FileState, FileState)>
+    );
+    enum_passthrough!(self, (monitoring, filter, notify_fn), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
+        pub fn add_exclusive_listener(&mut self, monitoring: FileState, filter: StateListenerFilter,
+                                      notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static)
+                                      -> Handle<(FileState, FileState)>
+    );
+    enum_passthrough!(self, (ptr), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>)
     );
-    enum_passthrough!(self, (ptr), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (ptr), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener)
     );
-    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn readv(&mut self, iovs: &[IoVec], offset: Option<libc::off_t>, flags: libc::c_int,
                      mem: &mut MemoryManager, cb_queue: &mut CallbackQueue) -> Result<libc::ssize_t, SyscallError>
     );
-    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), Pipe, EventFd, Socket, TimerFd;
+    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), Pipe, EventFd, Socket, TimerFd, Epoll, SignalFd, Inotify;
         pub fn writev(&mut self, iovs: &[IoVec], offset: Option<libc::off_t>, flags: libc::c_int,
                       mem: &mut MemoryManager, cb_queue: &mut CallbackQueue) -> Result<libc::ssize_t, SyscallError>
     );
@@ -458,6 +547,9 @@ impl std::fmt::Debug for FileRef<'_> {
             Self::EventFd(_) => write!(f, "EventFd")?,
             Self::Socket(_) => write!(f, "Socket")?,
             Self::TimerFd(_) => write!(f, "TimerFd")?,
+            Self::Epoll(_) => write!(f, "Epoll")?,
+            Self::SignalFd(_) => write!(f, "SignalFd")?,
+            Self::Inotify(_) => write!(f, "Inotify")?,
         }
 
         write!(
@@ -476,6 +568,9 @@ impl std::fmt::Debug for FileRefMut<'_> {
             Self::EventFd(_) => write!(f, "EventFd")?,
             Self::Socket(_) => write!(f, "Socket")?,
             Self::TimerFd(_) => write!(f, "TimerFd")?,
+            Self::Epoll(_) => write!(f, "Epoll")?,
+            Self::SignalFd(_) => write!(f, "SignalFd")?,
+            Self::Inotify(_) => write!(f, "Inotify")?,
         }
 
         write!(
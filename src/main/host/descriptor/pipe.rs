@@ -110,9 +110,13 @@ impl Pipe {
         // no need to hold on to the buffer anymore
         self.buffer = None;
 
-        // set the closed flag and remove the active, readable, and writable flags
+        // set the closed flag and remove the active, readable, writable, and hup flags
         self.copy_state(
-            FileState::CLOSED | FileState::ACTIVE | FileState::READABLE | FileState::WRITABLE,
+            FileState::CLOSED
+                | FileState::ACTIVE
+                | FileState::READABLE
+                | FileState::WRITABLE
+                | FileState::HUP,
             FileState::CLOSED,
             cb_queue,
         );
@@ -176,6 +180,95 @@ impl Pipe {
             return Err(linux_api::errno::Errno::ESPIPE.into());
         }
 
+        let len: libc::size_t = iovs.iter().map(|x| x.len).sum();
+
+        let mut reader = IoVecReader::new(iovs, mem);
+
+        let num_copied = self.write_from(&mut reader, len, cb_queue)?;
+
+        Ok(num_copied.try_into().unwrap())
+    }
+
+    /// Write bytes from shadow's memory to the pipe. Returns the number of bytes written, or
+    /// `EWOULDBLOCK` if the pipe is full.
+    pub fn write_bytes(
+        &mut self,
+        bytes: &[u8],
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<usize, SyscallError> {
+        self.write_from(bytes, bytes.len(), cb_queue)
+    }
+
+    /// Copy bytes from the front of the pipe into `bytes` without removing them from the pipe.
+    /// Returns `EWOULDBLOCK` if the pipe is empty but there are still writers.
+    pub fn peek(&self, bytes: &mut [u8]) -> Result<usize, SyscallError> {
+        // if the file is not open for reading, return EBADF
+        if !self.mode.contains(FileMode::READ) {
+            return Err(linux_api::errno::Errno::EBADF.into());
+        }
+
+        let buffer = self.buffer.as_ref().unwrap().borrow();
+
+        let num_copied = buffer.peek(&mut *bytes)?;
+
+        // same conditions as in `readv()`
+        if num_copied == 0 && !bytes.is_empty() && buffer.num_writers() > 0 {
+            Err(Errno::EWOULDBLOCK.into())
+        } else {
+            Ok(num_copied)
+        }
+    }
+
+    /// Remove up to `len` bytes from the front of the pipe, for example after they were copied
+    /// using [`peek()`](Self::peek). Returns the number of bytes removed.
+    pub fn discard(&mut self, len: usize, cb_queue: &mut CallbackQueue) -> usize {
+        let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+        let mut scratch = vec![0; len];
+        let mut num_discarded = 0;
+
+        while num_discarded < len && buffer.has_data() {
+            let (num_copied, _num_removed_from_buf) = buffer
+                .read(&mut scratch[num_discarded..], cb_queue)
+                .unwrap();
+            if num_copied == 0 {
+                break;
+            }
+            num_discarded += num_copied;
+        }
+
+        num_discarded
+    }
+
+    /// The number of bytes that can be written to the pipe without blocking. Returns `EBADF` if
+    /// the pipe isn't open for writing, and `EPIPE` if there are no readers.
+    pub fn space_available(&self) -> Result<usize, SyscallError> {
+        if !self.mode.contains(FileMode::WRITE) {
+            return Err(linux_api::errno::Errno::EBADF.into());
+        }
+
+        let buffer = self.buffer.as_ref().unwrap().borrow();
+
+        if buffer.num_readers() == 0 {
+            return Err(linux_api::errno::Errno::EPIPE.into());
+        }
+
+        Ok(buffer.space_available())
+    }
+
+    /// Returns true if both pipe objects are ends of the same pipe.
+    pub fn same_pipe(&self, other: &Pipe) -> bool {
+        Arc::ptr_eq(
+            self.buffer.as_ref().unwrap(),
+            other.buffer.as_ref().unwrap(),
+        )
+    }
+
+    fn write_from<R: std::io::Read>(
+        &mut self,
+        mut reader: R,
+        len: usize,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<usize, SyscallError> {
         // if the file is not open for writing, return EBADF
         if !self.mode.contains(FileMode::WRITE) {
             return Err(linux_api::errno::Errno::EBADF.into());
@@ -199,10 +292,6 @@ impl Pipe {
             }
         }
 
-        let len: libc::size_t = iovs.iter().map(|x| x.len).sum();
-
-        let mut reader = IoVecReader::new(iovs, mem);
-
         let num_copied = match self.write_mode {
             WriteMode::Stream => buffer.write_stream(&mut reader, len, cb_queue)?,
             WriteMode::Packet => {
@@ -233,7 +322,7 @@ impl Pipe {
             }
         };
 
-        Ok(num_copied.try_into().unwrap())
+        Ok(num_copied)
     }
 
     pub fn ioctl(
@@ -323,6 +412,16 @@ impl Pipe {
             .add_listener(monitoring, filter, notify_fn)
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         self.event_source.add_legacy_listener(ptr);
     }
@@ -349,11 +448,15 @@ impl Pipe {
 
         // only update the readable state if the file is open for reading
         if self.mode.contains(FileMode::READ) {
-            mask.insert(FileState::READABLE);
+            mask.insert(FileState::READABLE | FileState::HUP);
             // file is readable if the buffer is readable or there are no writers
             if buffer_state.intersects(BufferState::READABLE | BufferState::NO_WRITERS) {
                 file_state.insert(FileState::READABLE);
             }
+            // the read end is hung up once there are no writers
+            if buffer_state.contains(BufferState::NO_WRITERS) {
+                file_state.insert(FileState::HUP);
+            }
         }
 
         // only update the writable state if the file is open for writing
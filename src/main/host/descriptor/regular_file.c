@@ -13,6 +13,7 @@
 #include <stdlib.h>
 #include <string.h>
 #include <sys/file.h>
+#include <sys/inotify.h>
 #include <sys/ioctl.h>
 #include <sys/stat.h>
 #include <sys/syscall.h>
@@ -122,6 +123,25 @@ static inline bool _fd_isValid(int fd) { return fd >= 0; }
 
 int regularfile_getOSBackedFD(RegularFile* file) { return _regularfile_getOSBackedFD(file); }
 
+/* Generates an inotify event for the file at the absolute path `abspath`. */
+static void _regularfile_notifyInotify(const Host* host, const char* abspath, uint32_t mask) {
+    if (host && abspath) {
+        host_notifyInotify(host, abspath, mask);
+    }
+}
+
+/* Converts the result of a write to the os-backed file, and generates an inotify event if
+ * anything was written. Must be called before errno is changed. */
+static ssize_t _regularfile_handleWriteResult(RegularFile* file, ssize_t result) {
+    if (result < 0) {
+        return -errno;
+    }
+    if (result > 0) {
+        _regularfile_notifyInotify(worker_getCurrentHost(), file->osfile.absPathAtOpen, IN_MODIFY);
+    }
+    return result;
+}
+
 static void _regularfile_closeHelper(RegularFile* file) {
     if(file && file->type != FILE_TYPE_IN_MEMORY) {
         if (file && _fd_isValid(file->osfile.fd)) {
@@ -141,6 +161,11 @@ static void _regularfile_close(LegacyFile* desc, const Host* host) {
 
     trace("Closing file %p with os-backed file %i", file, _regularfile_getOSBackedFD(file));
 
+    if (file->type == FILE_TYPE_REGULAR && _fd_isValid(file->osfile.fd) &&
+        (file->osfile.flagsAtOpen & O_ACCMODE) != O_RDONLY) {
+        _regularfile_notifyInotify(host, file->osfile.absPathAtOpen, IN_CLOSE_WRITE);
+    }
+
     /* Make sure we mimic the close on the OS-backed file now. */
     _regularfile_closeHelper(file);
 }
@@ -217,6 +242,14 @@ static char* _regularfile_getAbsolutePath(RegularFile* dir, const char* pathname
     return abspath;
 }
 
+/* Generates an inotify event for the file at `pathname`, which is relative to `dir`. */
+static void _regularfile_notifyInotifyAt(RegularFile* dir, const char* pathname,
+                                         const char* workingDir, uint32_t mask) {
+    char* abspath = _regularfile_getAbsolutePath(dir, pathname, workingDir);
+    _regularfile_notifyInotify(worker_getCurrentHost(), abspath, mask);
+    free(abspath);
+}
+
 #ifdef DEBUG
 #define CHECK_FLAG(flag)                                                                           \
     if (flags & flag) {                                                                            \
@@ -334,6 +367,9 @@ int regularfile_openat(RegularFile* file, RegularFile* dir, const char* pathname
     // we should always use O_CLOEXEC for files opened in shadow
     flags |= O_CLOEXEC;
 
+    /* Check if the file will be created, so that we can generate an inotify event. */
+    bool existed = !(flags & O_CREAT) || access(abspath, F_OK) == 0;
+
     // TODO: we should open the os-backed file in non-blocking mode even if a
     // non-block is not requested, and then properly handle the io by, e.g.,
     // epolling on all such files with a shadow support thread.
@@ -359,6 +395,14 @@ int regularfile_openat(RegularFile* file, RegularFile* dir, const char* pathname
     trace("RegularFile %p opened os-backed file %i at absolute path %s", file,
           _regularfile_getOSBackedFD(file), file->osfile.absPathAtOpen);
 
+    if (file->type == FILE_TYPE_REGULAR) {
+        if (!existed) {
+            _regularfile_notifyInotify(worker_getCurrentHost(), abspath, IN_CREATE);
+        } else if ((originalFlags & O_TRUNC) && (flags & O_ACCMODE) != O_RDONLY) {
+            _regularfile_notifyInotify(worker_getCurrentHost(), abspath, IN_MODIFY);
+        }
+    }
+
     /* The os-backed file is now ready. */
     legacyfile_adjustStatus(&file->super, STATUS_FILE_ACTIVE, TRUE);
 
@@ -547,7 +591,7 @@ ssize_t regularfile_write(RegularFile* file, const void* buf, size_t bufSize) {
     /* TODO: this may block the shadow thread until we properly handle
      * os-backed files in non-blocking mode. */
     ssize_t result = write(_regularfile_getOSBackedFD(file), buf, bufSize);
-    return (result < 0) ? -errno : result;
+    return _regularfile_handleWriteResult(file, result);
 }
 
 ssize_t regularfile_pwrite(RegularFile* file, const void* buf, size_t bufSize, off_t offset) {
@@ -567,7 +611,7 @@ ssize_t regularfile_pwrite(RegularFile* file, const void* buf, size_t bufSize, o
     /* TODO: this may block the shadow thread until we properly handle
      * os-backed files in non-blocking mode. */
     ssize_t result = pwrite(_regularfile_getOSBackedFD(file), buf, bufSize, offset);
-    return (result < 0) ? -errno : result;
+    return _regularfile_handleWriteResult(file, result);
 }
 
 ssize_t regularfile_pwritev(RegularFile* file, const struct iovec* iov, int iovcnt, off_t offset) {
@@ -587,7 +631,7 @@ ssize_t regularfile_pwritev(RegularFile* file, const struct iovec* iov, int iovc
     /* TODO: this may block the shadow thread until we properly handle
      * os-backed files in non-blocking mode. */
     ssize_t result = pwritev(_regularfile_getOSBackedFD(file), iov, iovcnt, offset);
-    return (result < 0) ? -errno : result;
+    return _regularfile_handleWriteResult(file, result);
 }
 
 #ifdef SYS_pwritev2
@@ -610,7 +654,7 @@ ssize_t regularfile_pwritev2(RegularFile* file, const struct iovec* iov, int iov
      * os-backed files in non-blocking mode. */
     ssize_t result =
         pwritev2(_regularfile_getOSBackedFD(file), iov, iovcnt, offset, flags);
-    return (result < 0) ? -errno : result;
+    return _regularfile_handleWriteResult(file, result);
 }
 #endif
 
@@ -689,7 +733,12 @@ int regularfile_ftruncate(RegularFile* file, off_t length) {
     trace("RegularFile %p ftruncate os-backed file %i", file, _regularfile_getOSBackedFD(file));
 
     int result = ftruncate(_regularfile_getOSBackedFD(file), length);
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errno;
+    }
+
+    _regularfile_notifyInotify(worker_getCurrentHost(), file->osfile.absPathAtOpen, IN_MODIFY);
+    return result;
 }
 
 int regularfile_fallocate(RegularFile* file, int mode, off_t offset, off_t length) {
@@ -702,7 +751,12 @@ int regularfile_fallocate(RegularFile* file, int mode, off_t offset, off_t lengt
     trace("RegularFile %p fallocate os-backed file %i", file, _regularfile_getOSBackedFD(file));
 
     int result = fallocate(_regularfile_getOSBackedFD(file), mode, offset, length);
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errno;
+    }
+
+    _regularfile_notifyInotify(worker_getCurrentHost(), file->osfile.absPathAtOpen, IN_MODIFY);
+    return result;
 }
 
 int regularfile_fadvise(RegularFile* file, off_t offset, off_t len, int advice) {
@@ -1074,12 +1128,18 @@ int regularfile_mkdirat(RegularFile* dir, const char* pathname, mode_t mode,
     }
 
     int result = mkdirat(osFd, pathnameTmp, mode);
+    int errcode = errno;
 
     if (pathnameTmp != pathname) {
         free((char*)pathnameTmp);
     }
 
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errcode;
+    }
+
+    _regularfile_notifyInotifyAt(dir, pathname, workingDir, IN_CREATE | IN_ISDIR);
+    return result;
 }
 
 int regularfile_mknodat(RegularFile* dir, const char* pathname, mode_t mode, dev_t dev,
@@ -1095,12 +1155,18 @@ int regularfile_mknodat(RegularFile* dir, const char* pathname, mode_t mode, dev
     }
 
     int result = mknodat(osFd, pathnameTmp, mode, dev);
+    int errcode = errno;
 
     if (pathnameTmp != pathname) {
         free((char*)pathnameTmp);
     }
 
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errcode;
+    }
+
+    _regularfile_notifyInotifyAt(dir, pathname, workingDir, IN_CREATE);
+    return result;
 }
 
 int regularfile_linkat(RegularFile* oldDir, const char* oldPath, RegularFile* newDir,
@@ -1122,6 +1188,7 @@ int regularfile_linkat(RegularFile* oldDir, const char* oldPath, RegularFile* ne
     }
 
     int result = linkat(oldOsFd, oldPathTmp, newOsFd, newPathTmp, flags);
+    int errcode = errno;
 
     if (oldPathTmp != oldPath) {
         free((char*)oldPathTmp);
@@ -1130,7 +1197,12 @@ int regularfile_linkat(RegularFile* oldDir, const char* oldPath, RegularFile* ne
         free((char*)newPathTmp);
     }
 
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errcode;
+    }
+
+    _regularfile_notifyInotifyAt(newDir, newPath, workingDir, IN_CREATE);
+    return result;
 }
 
 int regularfile_unlinkat(RegularFile* dir, const char* pathname, int flags,
@@ -1146,12 +1218,18 @@ int regularfile_unlinkat(RegularFile* dir, const char* pathname, int flags,
     }
 
     int result = unlinkat(osFd, pathnameTmp, flags);
+    int errcode = errno;
 
     if (pathnameTmp != pathname) {
         free((char*)pathnameTmp);
     }
 
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errcode;
+    }
+
+    _regularfile_notifyInotifyAt(dir, pathname, workingDir, (flags & AT_REMOVEDIR) ? (IN_DELETE | IN_ISDIR) : IN_DELETE);
+    return result;
 }
 
 int regularfile_symlinkat(RegularFile* dir, const char* linkpath, const char* target,
@@ -1167,12 +1245,18 @@ int regularfile_symlinkat(RegularFile* dir, const char* linkpath, const char* ta
     }
 
     int result = symlinkat(target, osFd, linkpathTmp);
+    int errcode = errno;
 
     if (linkpathTmp != linkpath) {
         free((char*)linkpathTmp);
     }
 
-    return (result < 0) ? -errno : result;
+    if (result < 0) {
+        return -errcode;
+    }
+
+    _regularfile_notifyInotifyAt(dir, linkpath, workingDir, IN_CREATE);
+    return result;
 }
 
 ssize_t regularfile_readlinkat(RegularFile* dir, const char* pathname, char* buf, size_t bufsize,
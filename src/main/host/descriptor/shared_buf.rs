@@ -91,6 +91,15 @@ impl SharedBuf {
         Ok((num_copied, num_removed_from_buf))
     }
 
+    /// Copy bytes from the front of the buffer without removing them. Returns the number of bytes
+    /// copied.
+    pub fn peek<W: std::io::Write>(&self, bytes: W) -> Result<usize, std::io::Error> {
+        Ok(match self.queue.peek(bytes)? {
+            Some((num_copied, _num_bytes_in_chunk, _chunk_type)) => num_copied,
+            None => 0,
+        })
+    }
+
     pub fn write_stream<R: std::io::Read>(
         &mut self,
         bytes: R,
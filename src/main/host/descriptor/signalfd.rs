@@ -0,0 +1,338 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use atomic_refcell::AtomicRefCell;
+use linux_api::errno::Errno;
+use linux_api::ioctls::IoctlRequest;
+use linux_api::signal::{siginfo_t, sigset_t, SigInfoDetails};
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+
+use crate::core::worker::Worker;
+use crate::cshadow as c;
+use crate::host::descriptor::{
+    FileMode, FileState, FileStatus, StateEventSource, StateListenerFilter,
+};
+use crate::host::memory_manager::MemoryManager;
+use crate::host::process::SignalEventSource;
+use crate::host::syscall::io::{IoVec, IoVecWriter};
+use crate::host::syscall_types::{SyscallError, SyscallResult};
+use crate::utility::callback_queue::{CallbackQueue, Handle};
+use crate::utility::HostTreePointer;
+
+pub struct SignalFd {
+    /// The signals that can be read from this signalfd.
+    mask: sigset_t,
+    /// Our listener on the process's signal events, which is removed when dropped.
+    signal_listener: Option<Handle<sigset_t>>,
+    event_source: StateEventSource,
+    state: FileState,
+    status: FileStatus,
+    // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
+    // this file
+    has_open_file: bool,
+}
+
+impl SignalFd {
+    /// Creates a new [`SignalFd`] that reads the signals in `mask`, which are pending for the
+    /// process that owns `signal_events`. `pending` is the set of signals currently pending for
+    /// that process or its threads.
+    ///
+    /// Like Linux, signals are always read from the calling thread and its process. If the
+    /// signalfd is shared with another process, its readiness will still follow the signals of
+    /// the process that created it.
+    pub fn new(
+        mask: sigset_t,
+        status: FileStatus,
+        signal_events: &SignalEventSource,
+        pending: sigset_t,
+    ) -> Arc<AtomicRefCell<Self>> {
+        Arc::new_cyclic(|weak| {
+            let weak = weak.clone();
+            let signal_listener = signal_events.add_listener(move |pending, cb_queue| {
+                if let Some(signalfd) = weak.upgrade() {
+                    signalfd.borrow_mut().refresh_state(pending, cb_queue);
+                }
+            });
+
+            let mut state = FileState::ACTIVE;
+            state.set(FileState::READABLE, !(pending & mask).is_empty());
+
+            AtomicRefCell::new(Self {
+                mask,
+                signal_listener: Some(signal_listener),
+                event_source: StateEventSource::new(),
+                state,
+                status,
+                has_open_file: false,
+            })
+        })
+    }
+
+    pub fn get_status(&self) -> FileStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: FileStatus) {
+        self.status = status;
+    }
+
+    pub fn mode(&self) -> FileMode {
+        FileMode::READ
+    }
+
+    pub fn has_open_file(&self) -> bool {
+        self.has_open_file
+    }
+
+    pub fn supports_sa_restart(&self) -> bool {
+        false
+    }
+
+    pub fn set_has_open_file(&mut self, val: bool) {
+        self.has_open_file = val;
+    }
+
+    /// Replace the signal mask, as in `signalfd(2)` when given an existing signalfd. `pending` is
+    /// the set of signals currently pending for the process or its threads.
+    pub fn set_mask(&mut self, mask: sigset_t, pending: sigset_t, cb_queue: &mut CallbackQueue) {
+        self.mask = mask;
+        self.refresh_state(pending, cb_queue);
+    }
+
+    pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
+        // stop listening for signals
+        self.signal_listener = None;
+
+        // set the closed flag and remove the active and readable flags
+        self.copy_state(
+            FileState::CLOSED | FileState::ACTIVE | FileState::READABLE,
+            FileState::CLOSED,
+            cb_queue,
+        );
+
+        Ok(())
+    }
+
+    pub fn readv(
+        &mut self,
+        iovs: &[IoVec],
+        offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        mem: &mut MemoryManager,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // signalfds don't support seeking
+        if offset.is_some() {
+            return Err(Errno::ESPIPE.into());
+        }
+
+        const RECORD_SIZE: usize = std::mem::size_of::<libc::signalfd_siginfo>();
+
+        let len: libc::size_t = iovs.iter().map(|x| x.len).sum();
+
+        // signalfd(2): "The buffer given to read(2) must be at least sizeof(struct
+        // signalfd_siginfo) bytes"
+        let max_records = len / RECORD_SIZE;
+        if max_records == 0 {
+            log::trace!("Reading from signalfd requires a buffer of at least {RECORD_SIZE} bytes");
+            return Err(Errno::EINVAL.into());
+        }
+
+        let mask = self.mask;
+
+        // take pending signals directed at the calling thread first, and then signals directed at
+        // its process
+        let records = Worker::with_active_host(|host| {
+            Worker::with_active_process(|process| {
+                Worker::with_active_thread(|thread| {
+                    let mut records = Vec::new();
+
+                    {
+                        let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+                        let thread_shmem = thread.shmem();
+                        let process_shmem = process.shmem();
+                        let mut thread_protected =
+                            thread_shmem.protected.borrow_mut(&host_shmem.root);
+                        let mut process_protected =
+                            process_shmem.protected.borrow_mut(&host_shmem.root);
+
+                        while records.len() < max_records {
+                            let Some((_, info)) = thread_protected
+                                .take_pending_signal_in(mask)
+                                .or_else(|| process_protected.take_pending_signal_in(mask))
+                            else {
+                                break;
+                            };
+                            records.push(signalfd_siginfo_from(&info));
+                        }
+                    }
+
+                    // the pending signals may have changed, or may have been delivered without our
+                    // knowledge, so update this and any other signalfds
+                    process
+                        .borrow_runnable()
+                        .unwrap()
+                        .notify_signals_changed(host, cb_queue);
+
+                    records
+                })
+                .unwrap()
+            })
+            .unwrap()
+        })
+        .unwrap();
+
+        if records.is_empty() {
+            log::trace!("No signals in the signalfd's mask are pending");
+            return Err(Errno::EWOULDBLOCK.into());
+        }
+
+        let mut writer = IoVecWriter::new(iovs, mem);
+
+        for record in &records {
+            // SAFETY: `signalfd_siginfo` has no implicit padding, and all of its bytes were
+            // initialized
+            let bytes: [u8; RECORD_SIZE] = unsafe { std::mem::transmute(*record) };
+            writer.write_all(&bytes)?;
+        }
+
+        Ok((records.len() * RECORD_SIZE).try_into().unwrap())
+    }
+
+    pub fn writev(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // signalfds don't support writing
+        Err(Errno::EINVAL.into())
+    }
+
+    pub fn ioctl(
+        &mut self,
+        request: IoctlRequest,
+        _arg_ptr: ForeignPtr<()>,
+        _memory_manager: &mut MemoryManager,
+    ) -> SyscallResult {
+        log::warn!("We do not yet handle ioctl request {request:?} on signalfds");
+        Err(Errno::EINVAL.into())
+    }
+
+    pub fn add_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
+        self.event_source.add_legacy_listener(ptr);
+    }
+
+    pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener) {
+        self.event_source.remove_legacy_listener(ptr);
+    }
+
+    pub fn state(&self) -> FileState {
+        self.state
+    }
+
+    /// Update the readable state given the signals pending for the process or its threads.
+    fn refresh_state(&mut self, pending: sigset_t, cb_queue: &mut CallbackQueue) {
+        if self.state.contains(FileState::CLOSED) {
+            return;
+        }
+
+        let readable = if (pending & self.mask).is_empty() {
+            FileState::empty()
+        } else {
+            FileState::READABLE
+        };
+
+        self.copy_state(FileState::READABLE, readable, cb_queue);
+    }
+
+    fn copy_state(&mut self, mask: FileState, state: FileState, cb_queue: &mut CallbackQueue) {
+        let old_state = self.state;
+
+        // remove the masked flags, then copy the masked flags
+        self.state.remove(mask);
+        self.state.insert(state & mask);
+
+        self.handle_state_change(old_state, cb_queue);
+    }
+
+    fn handle_state_change(&mut self, old_state: FileState, cb_queue: &mut CallbackQueue) {
+        let states_changed = self.state ^ old_state;
+
+        // if nothing changed
+        if states_changed.is_empty() {
+            return;
+        }
+
+        self.event_source
+            .notify_listeners(self.state, states_changed, cb_queue);
+    }
+}
+
+/// Convert a pending signal's info to the record that is read from a signalfd.
+fn signalfd_siginfo_from(info: &siginfo_t) -> libc::signalfd_siginfo {
+    let mut record: libc::signalfd_siginfo = shadow_pod::zeroed();
+
+    record.ssi_signo = info.signal().map(i32::from).unwrap_or(0) as u32;
+    record.ssi_errno = info.errno_raw();
+    record.ssi_code = info.code_raw();
+
+    // SAFETY: We don't dereference any pointers; they're only copied to the record.
+    match unsafe { info.details() } {
+        Some(SigInfoDetails::Kill(x)) => {
+            record.ssi_pid = x.l_pid as u32;
+            record.ssi_uid = x.l_uid;
+        }
+        Some(SigInfoDetails::Timer(x)) => {
+            record.ssi_tid = x.l_tid as u32;
+            record.ssi_overrun = x.l_overrun as u32;
+            record.ssi_int = unsafe { x.l_sigval.sival_int };
+            record.ssi_ptr = unsafe { x.l_sigval.sival_ptr } as u64;
+        }
+        Some(SigInfoDetails::Rt(x)) => {
+            record.ssi_pid = x.l_pid as u32;
+            record.ssi_uid = x.l_uid;
+            record.ssi_int = unsafe { x.l_sigval.sival_int };
+            record.ssi_ptr = unsafe { x.l_sigval.sival_ptr } as u64;
+        }
+        Some(SigInfoDetails::SigChld(x)) => {
+            record.ssi_pid = x.l_pid as u32;
+            record.ssi_uid = x.l_uid;
+            record.ssi_status = x.l_status;
+            record.ssi_utime = x.l_utime as u64;
+            record.ssi_stime = x.l_stime as u64;
+        }
+        Some(SigInfoDetails::SigFault(x)) => {
+            record.ssi_addr = x.l_addr as u64;
+        }
+        Some(SigInfoDetails::SigPoll(x)) => {
+            record.ssi_band = x.l_band as u32;
+            record.ssi_fd = x.l_fd;
+        }
+        Some(SigInfoDetails::SigSys(_)) | None => {}
+    }
+
+    record
+}
@@ -0,0 +1,1027 @@
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+
+use atomic_refcell::AtomicRefCell;
+use bytes::{Bytes, BytesMut};
+use linux_api::errno::Errno;
+use linux_api::ioctls::IoctlRequest;
+use nix::sys::socket::{AddressFamily, MsgFlags, Shutdown};
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+
+use crate::core::worker::Worker;
+use crate::cshadow as c;
+use crate::host::descriptor::socket::inet::udp::MessageBuffer;
+use crate::host::descriptor::socket::inet::InetSocket;
+use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs, ShutdownFlags};
+use crate::host::descriptor::{
+    File, FileMode, FileState, FileStatus, OpenFile, Socket, StateEventSource, StateListenerFilter,
+    SyscallResult,
+};
+use crate::host::memory_manager::MemoryManager;
+use crate::host::network::icmp::{ICMP_ECHO, ICMP_ECHOREPLY};
+use crate::host::network::interface::{FifoPacketPriority, ReuseOptions};
+use crate::host::network::namespace::{AssociationHandle, NetworkNamespace};
+use crate::host::syscall::io::{write_partial, IoVec, IoVecReader, IoVecWriter};
+use crate::host::syscall_types::SyscallError;
+use crate::network::packet::{IcmpHeader, PacketRc, PacketStatus};
+use crate::utility::callback_queue::{CallbackQueue, Handle};
+use crate::utility::pcap_writer::PacketDisplay;
+use crate::utility::sockaddr::SockaddrStorage;
+use crate::utility::{HostTreePointer, ObjectCounter};
+
+/// Maximum size of an ICMP message (including the ICMP header) we are allowed to send out over the
+/// network.
+// 65,535 (2^16 - 1) - 20 (ip header)
+const CONFIG_ICMP_MAX_SIZE: usize = 65515;
+
+/// The size of an IPv4 header, which raw sockets include in received messages.
+const IP_HEADER_SIZE: usize = c::CONFIG_HEADER_SIZE_IP as usize;
+
+/// The size of an ICMP header, which both kinds of sockets include in sent and received messages.
+const ICMP_HEADER_SIZE: usize = c::CONFIG_HEADER_SIZE_ICMP as usize;
+
+/// The `ICMP_FILTER` option at the `SOL_RAW` level (not exported by the libc crate).
+const ICMP_FILTER: libc::c_int = 1;
+
+/// The kind of ICMP socket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IcmpSocketKind {
+    /// A `SOCK_RAW` socket, which sends any ICMP message and receives a copy of every ICMP message
+    /// that arrives at the host, including the IP header.
+    Raw,
+    /// An unprivileged `SOCK_DGRAM` "ping" socket, which can only send echo requests and only
+    /// receives the echo replies with its identifier.
+    Ping,
+}
+
+/// An `AF_INET` socket using the `IPPROTO_ICMP` protocol.
+pub struct IcmpSocket {
+    event_source: StateEventSource,
+    status: FileStatus,
+    state: FileState,
+    shutdown_status: ShutdownFlags,
+    kind: IcmpSocketKind,
+    send_buffer: MessageBuffer<MessageSendHeader>,
+    recv_buffer: MessageBuffer<MessageRecvHeader>,
+    peer_addr: Option<Ipv4Addr>,
+    /// For a ping socket, the port is the echo identifier. For a raw socket, the port is unused.
+    bound_addr: Option<SocketAddrV4>,
+    /// Only ping sockets are associated with an interface. Raw sockets receive packets from the
+    /// host's network stack instead.
+    association: Option<AssociationHandle>,
+    /// The `IP_TTL` socket option, or `None` to use the default TTL.
+    ttl: Option<u8>,
+    /// The `ICMP_FILTER` socket option, a bitmask of ICMP types that a raw socket won't receive.
+    icmp_filter: u32,
+    /// The receive time of the last packet returned to the managed process during a call to
+    /// `recvmsg()`. Used for `SIOCGSTAMP`.
+    recv_time_of_last_read_packet: Option<EmulatedTime>,
+    // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
+    // this file
+    has_open_file: bool,
+    _counter: ObjectCounter,
+}
+
+impl IcmpSocket {
+    pub fn new(
+        status: FileStatus,
+        kind: IcmpSocketKind,
+        send_buf_size: usize,
+        recv_buf_size: usize,
+    ) -> Arc<AtomicRefCell<Self>> {
+        let mut socket = Self {
+            event_source: StateEventSource::new(),
+            status,
+            state: FileState::ACTIVE,
+            shutdown_status: ShutdownFlags::empty(),
+            kind,
+            send_buffer: MessageBuffer::new(send_buf_size),
+            recv_buffer: MessageBuffer::new(recv_buf_size),
+            peer_addr: None,
+            bound_addr: None,
+            association: None,
+            ttl: None,
+            icmp_filter: 0,
+            recv_time_of_last_read_packet: None,
+            has_open_file: false,
+            _counter: ObjectCounter::new("IcmpSocket"),
+        };
+
+        CallbackQueue::queue_and_run(|cb_queue| socket.refresh_readable_writable(cb_queue));
+
+        Arc::new(AtomicRefCell::new(socket))
+    }
+
+    pub fn get_status(&self) -> FileStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: FileStatus) {
+        self.status = status;
+    }
+
+    pub fn mode(&self) -> FileMode {
+        FileMode::READ | FileMode::WRITE
+    }
+
+    pub fn has_open_file(&self) -> bool {
+        self.has_open_file
+    }
+
+    pub fn supports_sa_restart(&self) -> bool {
+        true
+    }
+
+    pub fn set_has_open_file(&mut self, val: bool) {
+        self.has_open_file = val;
+    }
+
+    pub fn push_in_packet(
+        &mut self,
+        mut packet: PacketRc,
+        cb_queue: &mut CallbackQueue,
+        recv_time: EmulatedTime,
+    ) {
+        packet.add_status(PacketStatus::RcvSocketProcessed);
+
+        let header = packet.get_icmp();
+
+        let accepted = match self.kind {
+            // the interface only gives us packets with our identifier
+            IcmpSocketKind::Ping => header.icmp_type == ICMP_ECHOREPLY,
+            IcmpSocketKind::Raw => {
+                let bound_ip = self.bound_addr.map(|x| *x.ip());
+                let filtered =
+                    header.icmp_type < 32 && self.icmp_filter & (1 << header.icmp_type) != 0;
+
+                !filtered && bound_ip.map_or(true, |ip| ip.is_unspecified() || ip == header.dst)
+            }
+        };
+
+        // like a connected UDP socket, we only receive packets from the peer
+        let from_peer = self.peer_addr.map_or(true, |ip| ip == header.src);
+
+        // don't bother copying the bytes if we know the push will fail
+        if !accepted || !from_peer || !self.recv_buffer.has_space() {
+            packet.add_status(PacketStatus::RcvSocketDropped);
+            return;
+        }
+
+        let mut message = Vec::new();
+        packet.display_bytes(&mut message).unwrap();
+
+        // raw sockets receive the IP header, but ping sockets don't
+        let message = match self.kind {
+            IcmpSocketKind::Raw => Bytes::from(message),
+            IcmpSocketKind::Ping => Bytes::from(message).slice(IP_HEADER_SIZE..),
+        };
+
+        let header = MessageRecvHeader {
+            src: header.src,
+            recv_time,
+        };
+
+        // push the message to the receive buffer (shouldn't fail since we checked for available
+        // space above)
+        self.recv_buffer.push_message(message, header).unwrap();
+
+        log::trace!("Added a packet to the ICMP socket's recv buffer");
+        packet.add_status(PacketStatus::RcvSocketBuffered);
+
+        self.refresh_readable_writable(cb_queue);
+    }
+
+    pub fn pull_out_packet(&mut self, cb_queue: &mut CallbackQueue) -> Option<PacketRc> {
+        // pop the message from the send buffer
+        let Some((message, header)) = self.send_buffer.pop_message() else {
+            log::debug!(
+                "Attempted to remove a message from the ICMP socket's send buffer, but none available"
+            );
+
+            return None;
+        };
+
+        log::trace!("Removed a message from the ICMP socket's send buffer");
+
+        let mut packet = PacketRc::new();
+
+        packet.set_icmp(&header.icmp);
+        packet.set_ttl(header.ttl);
+        packet.set_payload(&message, header.packet_priority);
+        packet.add_status(PacketStatus::SndCreated);
+
+        self.refresh_readable_writable(cb_queue);
+
+        Some(packet)
+    }
+
+    pub fn peek_next_packet_priority(&self) -> Option<FifoPacketPriority> {
+        self.send_buffer.peek_message().map(|x| x.1.packet_priority)
+    }
+
+    pub fn has_data_to_send(&self) -> bool {
+        !self.send_buffer.is_empty()
+    }
+
+    pub fn update_packet_header(&self, _packet: &mut PacketRc) {
+        // do nothing for ICMP
+    }
+
+    pub fn is_listening(&self) -> bool {
+        false
+    }
+
+    pub fn kind(&self) -> IcmpSocketKind {
+        self.kind
+    }
+
+    /// The TTL of the IP packets sent by this socket.
+    fn effective_ttl(&self) -> u8 {
+        self.ttl
+            .unwrap_or(c::CONFIG_IP_DEFAULT_TTL.try_into().unwrap())
+    }
+
+    pub fn getsockname(&self) -> Result<Option<SocketAddr>, SyscallError> {
+        let mut addr = self
+            .bound_addr
+            .unwrap_or(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+
+        // if we are bound to INADDR_ANY, we should instead return the IP used to communicate with
+        // the connected peer (if we have one)
+        if *addr.ip() == Ipv4Addr::UNSPECIFIED {
+            if let Some(peer_addr) = self.peer_addr {
+                addr.set_ip(peer_addr);
+            }
+        }
+
+        Ok(Some(addr.into()))
+    }
+
+    pub fn getpeername(&self) -> Result<Option<SocketAddr>, SyscallError> {
+        let peer_addr = self.peer_addr.ok_or(Errno::ENOTCONN)?;
+        Ok(Some(SocketAddrV4::new(peer_addr, 0).into()))
+    }
+
+    pub fn address_family(&self) -> AddressFamily {
+        AddressFamily::Inet
+    }
+
+    pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
+        // drop the existing association handle to disassociate the socket
+        self.association = None;
+
+        self.copy_state(
+            /* mask= */ FileState::all(),
+            FileState::CLOSED,
+            cb_queue,
+        );
+        Ok(())
+    }
+
+    pub fn bind(
+        socket: &Arc<AtomicRefCell<Self>>,
+        addr: Option<&SockaddrStorage>,
+        net_ns: &NetworkNamespace,
+        rng: impl rand::Rng,
+    ) -> SyscallResult {
+        // if the address pointer was NULL
+        let Some(addr) = addr else {
+            return Err(Errno::EFAULT.into());
+        };
+
+        // if not an address of the socket's family
+        let Some(addr) = addr.as_inet() else {
+            return Err(Errno::EINVAL.into());
+        };
+        let addr = SocketAddrV4::from(*addr);
+
+        {
+            let socket = socket.borrow();
+
+            // if the socket is already bound
+            if socket.bound_addr.is_some() {
+                return Err(Errno::EINVAL.into());
+            }
+        }
+
+        if !addr.ip().is_unspecified() && net_ns.interface_borrow(*addr.ip()).is_none() {
+            log::debug!(
+                "No network interface exists for the bind address {}",
+                addr.ip()
+            );
+            return Err(Errno::EADDRNOTAVAIL.into());
+        }
+
+        let kind = socket.borrow().kind;
+
+        match kind {
+            IcmpSocketKind::Ping => Self::associate(socket, addr, net_ns, rng)?,
+            // the port isn't used by raw sockets
+            IcmpSocketKind::Raw => {
+                socket.borrow_mut().bound_addr = Some(SocketAddrV4::new(*addr.ip(), 0))
+            }
+        }
+
+        Ok(0.into())
+    }
+
+    /// Associate a ping socket with the network interface so that it receives echo replies. The
+    /// port of `local_addr` is the echo identifier, and a random identifier is chosen if it's 0.
+    fn associate(
+        socket: &Arc<AtomicRefCell<Self>>,
+        local_addr: SocketAddrV4,
+        net_ns: &NetworkNamespace,
+        rng: impl rand::Rng,
+    ) -> Result<(), SyscallError> {
+        assert_eq!(socket.borrow().kind, IcmpSocketKind::Ping);
+
+        // must not have been associated with the network interface
+        assert!(socket.borrow().association.is_none());
+
+        // this will allow us to receive packets from any peer
+        let unspecified_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+
+        let (local_addr, handle) = super::associate_socket(
+            InetSocket::Icmp(Arc::clone(socket)),
+            local_addr,
+            unspecified_addr,
+            ReuseOptions::default(),
+            net_ns,
+            rng,
+        )?;
+
+        let mut socket = socket.borrow_mut();
+        socket.bound_addr = Some(local_addr);
+        socket.association = Some(handle);
+
+        Ok(())
+    }
+
+    pub fn readv(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // the readv() syscall handler should have called IcmpSocket::recvmsg() instead
+        panic!("Called IcmpSocket::readv() on an ICMP socket");
+    }
+
+    pub fn writev(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // the writev() syscall handler should have called IcmpSocket::sendmsg() instead
+        panic!("Called IcmpSocket::writev() on an ICMP socket");
+    }
+
+    pub fn sendmsg(
+        socket: &Arc<AtomicRefCell<Self>>,
+        args: SendmsgArgs,
+        mem: &mut MemoryManager,
+        net_ns: &NetworkNamespace,
+        rng: impl rand::Rng,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // if the file's writing has been shut down, return EPIPE
+        if socket
+            .borrow()
+            .shutdown_status
+            .contains(ShutdownFlags::WRITE)
+        {
+            return Err(Errno::EPIPE.into());
+        }
+
+        let Some(mut flags) = MsgFlags::from_bits(args.flags) else {
+            log::debug!("Unrecognized send flags: {:#b}", args.flags);
+            return Err(Errno::EINVAL.into());
+        };
+
+        // the port of the destination address is ignored
+        let dst_ip = match args.addr {
+            Some(addr) => match addr.as_inet() {
+                Some(x) => *SocketAddrV4::from(*x).ip(),
+                None => return Err(Errno::EAFNOSUPPORT.into()),
+            },
+            // no destination address provided
+            None => match socket.borrow().peer_addr {
+                Some(x) => x,
+                None => return Err(Errno::EDESTADDRREQ.into()),
+            },
+        };
+
+        let dst_ip = if dst_ip.is_unspecified() {
+            Ipv4Addr::LOCALHOST
+        } else {
+            dst_ip
+        };
+
+        // make sure we will be able to route this later
+        if dst_ip != Ipv4Addr::LOCALHOST
+            && !Worker::is_routable(net_ns.default_ip.into(), dst_ip.into())
+        {
+            log::debug!("Attempting to send to address '{dst_ip}' for which no host exists");
+            return Err(Errno::EHOSTUNREACH.into());
+        }
+
+        if socket.borrow().get_status().contains(FileStatus::NONBLOCK) {
+            flags.insert(MsgFlags::MSG_DONTWAIT);
+        }
+
+        let len: libc::size_t = args.iovs.iter().map(|x| x.len).sum();
+
+        // the message must contain at least the ICMP header
+        if len < ICMP_HEADER_SIZE {
+            return Err(Errno::EINVAL.into());
+        }
+
+        // TODO: should use IP fragmentation to make sure packets fit within the MTU
+        if len > CONFIG_ICMP_MAX_SIZE {
+            return Err(Errno::EMSGSIZE.into());
+        }
+
+        // a ping socket needs an identifier, so make sure that we're bound
+        let (kind, association) = {
+            let socket = socket.borrow();
+            (socket.kind, socket.association.is_some())
+        };
+
+        if kind == IcmpSocketKind::Ping && !association {
+            // implicit bind (use default interface unless the remote peer is on loopback)
+            let local_ip = if dst_ip == Ipv4Addr::LOCALHOST {
+                Ipv4Addr::LOCALHOST
+            } else {
+                net_ns.default_ip
+            };
+
+            Self::associate(socket, SocketAddrV4::new(local_ip, 0), net_ns, rng)?;
+        }
+
+        let mut socket_ref = socket.borrow_mut();
+
+        // run in a closure so that an early return doesn't skip checking if we should block
+        let result = (|| {
+            // don't bother copying the bytes if we know the push will fail
+            if !socket_ref.send_buffer.has_space() {
+                return Err(Errno::EWOULDBLOCK);
+            }
+
+            // read the iovs into a message
+            let mut reader = IoVecReader::new(args.iovs, mem);
+            let mut message = BytesMut::zeroed(len);
+            reader
+                .read_exact(&mut message[..])
+                .map_err(|e| Errno::try_from(e).unwrap())?;
+
+            let mut header = IcmpHeader {
+                src: Ipv4Addr::UNSPECIFIED,
+                dst: dst_ip,
+                icmp_type: message[0],
+                code: message[1],
+                identifier: u16::from_be_bytes(message[4..6].try_into().unwrap()),
+                sequence: u16::from_be_bytes(message[6..8].try_into().unwrap()),
+            };
+
+            if socket_ref.kind == IcmpSocketKind::Ping {
+                // ping sockets can only send echo requests
+                if header.icmp_type != ICMP_ECHO || header.code != 0 {
+                    return Err(Errno::EINVAL);
+                }
+
+                // the kernel replaces the identifier with the socket's identifier
+                header.identifier = socket_ref.bound_addr.unwrap().port();
+            }
+
+            // use the bound address, otherwise the interface that we'll send the packet from
+            header.src = match socket_ref.bound_addr.map(|x| *x.ip()) {
+                Some(ip) if !ip.is_unspecified() => ip,
+                _ if dst_ip == Ipv4Addr::LOCALHOST => Ipv4Addr::LOCALHOST,
+                _ => net_ns.default_ip,
+            };
+
+            // get the priority that we'll assign to the eventual packet
+            let packet_priority =
+                Worker::with_active_host(|host| host.get_next_packet_priority()).unwrap();
+
+            let send_header = MessageSendHeader {
+                icmp: header,
+                ttl: socket_ref.effective_ttl(),
+                packet_priority,
+            };
+
+            // the ICMP header is stored in the message header, so the buffer only needs the payload
+            let payload = message.freeze().slice(ICMP_HEADER_SIZE..);
+
+            // push the message to the send buffer (shouldn't fail since we checked for available
+            // space above)
+            socket_ref
+                .send_buffer
+                .push_message(payload, send_header)
+                .unwrap();
+
+            // notify the host that this socket has packets to send
+            let socket = Arc::clone(socket);
+            let interface_ip = header.src;
+            cb_queue.add(move |_cb_queue| {
+                Worker::with_active_host(|host| {
+                    let inet_socket = InetSocket::Icmp(socket);
+                    let compat_socket = unsafe { c::compatsocket_fromInetSocket(&inet_socket) };
+                    host.notify_socket_has_packets(interface_ip, &compat_socket);
+                })
+                .unwrap();
+            });
+
+            Ok(len)
+        })();
+
+        socket_ref.refresh_readable_writable(cb_queue);
+
+        // if the syscall would block and we don't have the MSG_DONTWAIT flag
+        if result == Err(Errno::EWOULDBLOCK) && !flags.contains(MsgFlags::MSG_DONTWAIT) {
+            return Err(SyscallError::new_blocked(
+                File::Socket(Socket::Inet(InetSocket::Icmp(socket.clone()))),
+                FileState::WRITABLE,
+                socket_ref.supports_sa_restart(),
+            ));
+        }
+
+        Ok(result?.try_into().unwrap())
+    }
+
+    pub fn recvmsg(
+        socket: &Arc<AtomicRefCell<Self>>,
+        args: RecvmsgArgs,
+        mem: &mut MemoryManager,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<RecvmsgReturn, SyscallError> {
+        let socket_ref = &mut *socket.borrow_mut();
+
+        let Some(mut flags) = MsgFlags::from_bits(args.flags) else {
+            log::debug!("Unrecognized recv flags: {:#b}", args.flags);
+            return Err(Errno::EINVAL.into());
+        };
+
+        if socket_ref.get_status().contains(FileStatus::NONBLOCK) {
+            flags.insert(MsgFlags::MSG_DONTWAIT);
+        }
+
+        let len: libc::size_t = args.iovs.iter().map(|x| x.len).sum();
+
+        // run in a closure so that an early return doesn't skip checking if we should block
+        let result = (|| {
+            // a temporary location to store the message and header if we popped them
+            let message_storage;
+            let header_storage;
+
+            let (message, header) = if !flags.contains(MsgFlags::MSG_PEEK) {
+                // pop the message from the receive buffer
+                (message_storage, header_storage) = socket_ref
+                    .recv_buffer
+                    .pop_message()
+                    .ok_or(Errno::EWOULDBLOCK)?;
+                (&message_storage, &header_storage)
+            } else {
+                // peek the message from the receive buffer
+                let (message, header) = socket_ref
+                    .recv_buffer
+                    .peek_message()
+                    .ok_or(Errno::EWOULDBLOCK)?;
+                (message, header)
+            };
+
+            // truncate the message if the message is larger than the user-provided buffers
+            let truncated_message = &message[..std::cmp::min(len, message.len())];
+
+            // write the truncated message to the iovs
+            let mut writer = IoVecWriter::new(args.iovs, mem);
+            writer
+                .write_all(truncated_message)
+                .map_err(|e| Errno::try_from(e).unwrap())?;
+
+            let return_val = if flags.contains(MsgFlags::MSG_TRUNC) {
+                message.len()
+            } else {
+                // the number of bytes written
+                truncated_message.len()
+            };
+
+            let mut return_flags = MsgFlags::empty();
+            return_flags.set(MsgFlags::MSG_TRUNC, truncated_message.len() < message.len());
+
+            // update the cache of the last recv time
+            socket_ref.recv_time_of_last_read_packet = Some(header.recv_time);
+
+            Ok(RecvmsgReturn {
+                return_val: return_val.try_into().unwrap(),
+                addr: Some(SocketAddr::from(SocketAddrV4::new(header.src, 0)).into()),
+                msg_flags: return_flags.bits(),
+                control_len: 0,
+            })
+        })();
+
+        socket_ref.refresh_readable_writable(cb_queue);
+
+        // if the syscall would block and we don't have the MSG_DONTWAIT flag
+        if result.as_ref().err() == Some(&Errno::EWOULDBLOCK)
+            && !flags.contains(MsgFlags::MSG_DONTWAIT)
+        {
+            // if the syscall would block but the file's reading has been shut down, return EOF
+            if socket_ref.shutdown_status.contains(ShutdownFlags::READ) {
+                return Ok(RecvmsgReturn {
+                    return_val: 0,
+                    addr: None,
+                    msg_flags: 0,
+                    control_len: 0,
+                });
+            }
+
+            return Err(SyscallError::new_blocked(
+                File::Socket(Socket::Inet(InetSocket::Icmp(socket.clone()))),
+                FileState::READABLE,
+                socket_ref.supports_sa_restart(),
+            ));
+        }
+
+        Ok(result?)
+    }
+
+    pub fn ioctl(
+        &mut self,
+        request: IoctlRequest,
+        arg_ptr: ForeignPtr<()>,
+        mem: &mut MemoryManager,
+    ) -> SyscallResult {
+        match request {
+            // equivalent to SIOCINQ
+            IoctlRequest::FIONREAD => {
+                let len = self
+                    .recv_buffer
+                    .peek_message()
+                    .map(|m| m.0.len())
+                    .unwrap_or(0)
+                    .try_into()
+                    .unwrap();
+
+                let arg_ptr = arg_ptr.cast::<libc::c_int>();
+                mem.write(arg_ptr, &len)?;
+
+                Ok(0.into())
+            }
+            // equivalent to SIOCOUTQ
+            IoctlRequest::TIOCOUTQ => {
+                let len = self.send_buffer.len_bytes().try_into().unwrap();
+
+                let arg_ptr = arg_ptr.cast::<libc::c_int>();
+                mem.write(arg_ptr, &len)?;
+
+                Ok(0.into())
+            }
+            IoctlRequest::SIOCGSTAMP => {
+                let Some(last_recv_time) = self.recv_time_of_last_read_packet else {
+                    return Err(Errno::ENOENT.into());
+                };
+
+                // the timestamp is measured by the host's realtime clock
+                let last_recv_time =
+                    Worker::with_active_host(|host| host.clock().realtime(last_recv_time)).unwrap();
+                let last_recv_time = (last_recv_time - EmulatedTime::UNIX_EPOCH)
+                    .try_into()
+                    .unwrap();
+
+                let arg_ptr = arg_ptr.cast::<libc::timeval>();
+                mem.write(arg_ptr, &last_recv_time)?;
+
+                Ok(0.into())
+            }
+            IoctlRequest::FIONBIO => {
+                panic!("This should have been handled by the ioctl syscall handler");
+            }
+            IoctlRequest::TCGETS
+            | IoctlRequest::TCSETS
+            | IoctlRequest::TCSETSW
+            | IoctlRequest::TCSETSF
+            | IoctlRequest::TCGETA
+            | IoctlRequest::TCSETA
+            | IoctlRequest::TCSETAW
+            | IoctlRequest::TCSETAF
+            | IoctlRequest::TIOCGWINSZ
+            | IoctlRequest::TIOCSWINSZ => {
+                // not a terminal
+                Err(Errno::ENOTTY.into())
+            }
+            request => {
+                warn_once_then_debug!(
+                    "(LOG_ONCE) We do not yet handle ioctl request {request:?} on icmp sockets"
+                );
+                Err(Errno::EINVAL.into())
+            }
+        }
+    }
+
+    pub fn listen(
+        _socket: &Arc<AtomicRefCell<Self>>,
+        _backlog: i32,
+        _net_ns: &NetworkNamespace,
+        _rng: impl rand::Rng,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        Err(Errno::EOPNOTSUPP.into())
+    }
+
+    pub fn connect(
+        socket: &Arc<AtomicRefCell<Self>>,
+        peer_addr: &SockaddrStorage,
+        net_ns: &NetworkNamespace,
+        rng: impl rand::Rng,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        // if not an address of the socket's family
+        let Some(peer_addr) = peer_addr.as_inet() else {
+            return Err(Errno::EINVAL.into());
+        };
+        let mut peer_ip = *SocketAddrV4::from(*peer_addr).ip();
+
+        if peer_ip.is_unspecified() {
+            peer_ip = Ipv4Addr::LOCALHOST;
+        }
+
+        // make sure we will be able to route this later
+        if peer_ip != Ipv4Addr::LOCALHOST
+            && !Worker::is_routable(net_ns.default_ip.into(), peer_ip.into())
+        {
+            log::debug!("Attempting to connect to address '{peer_ip}' for which no host exists");
+            return Err(Errno::EHOSTUNREACH.into());
+        }
+
+        let (kind, association) = {
+            let socket = socket.borrow();
+            (socket.kind, socket.association.is_some())
+        };
+
+        // implicit bind (use default interface unless the remote peer is on loopback)
+        if kind == IcmpSocketKind::Ping && !association {
+            let local_ip = if peer_ip == Ipv4Addr::LOCALHOST {
+                Ipv4Addr::LOCALHOST
+            } else {
+                net_ns.default_ip
+            };
+
+            Self::associate(socket, SocketAddrV4::new(local_ip, 0), net_ns, rng)?;
+        }
+
+        socket.borrow_mut().peer_addr = Some(peer_ip);
+
+        Ok(())
+    }
+
+    pub fn accept(&mut self, _cb_queue: &mut CallbackQueue) -> Result<OpenFile, SyscallError> {
+        Err(Errno::EOPNOTSUPP.into())
+    }
+
+    pub fn shutdown(
+        &mut self,
+        how: Shutdown,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        if self.peer_addr.is_none() {
+            return Err(Errno::ENOTCONN.into());
+        }
+
+        if how == Shutdown::Write || how == Shutdown::Both {
+            // writing has been shut down
+            self.shutdown_status.insert(ShutdownFlags::WRITE)
+        }
+
+        if how == Shutdown::Read || how == Shutdown::Both {
+            // reading has been shut down
+            self.shutdown_status.insert(ShutdownFlags::READ)
+        }
+
+        Ok(())
+    }
+
+    pub fn getsockopt(
+        &self,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::socklen_t, SyscallError> {
+        let val: libc::c_int = match (level, optname) {
+            (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
+                self.send_buffer.soft_limit_bytes().try_into().unwrap()
+            }
+            (libc::SOL_SOCKET, libc::SO_RCVBUF) => {
+                self.recv_buffer.soft_limit_bytes().try_into().unwrap()
+            }
+            (libc::SOL_SOCKET, libc::SO_ERROR) => 0,
+            (libc::SOL_SOCKET, libc::SO_DOMAIN) => libc::AF_INET,
+            (libc::SOL_SOCKET, libc::SO_TYPE) => match self.kind {
+                IcmpSocketKind::Raw => libc::SOCK_RAW,
+                IcmpSocketKind::Ping => libc::SOCK_DGRAM,
+            },
+            (libc::SOL_SOCKET, libc::SO_PROTOCOL) => libc::IPPROTO_ICMP,
+            (libc::SOL_SOCKET, libc::SO_ACCEPTCONN) => 0,
+            (libc::SOL_SOCKET, _) => {
+                log::debug!("getsockopt called with unsupported level {level} and opt {optname}");
+                return Err(Errno::ENOPROTOOPT.into());
+            }
+            (libc::IPPROTO_IP, libc::IP_TTL) => self.effective_ttl().into(),
+            (libc::SOL_RAW, ICMP_FILTER) if self.kind == IcmpSocketKind::Raw => {
+                let optval_ptr = optval_ptr.cast::<u32>();
+                let bytes_written =
+                    write_partial(mem, &self.icmp_filter, optval_ptr, optlen as usize)?;
+
+                return Ok(bytes_written as libc::socklen_t);
+            }
+            _ => {
+                log::debug!("getsockopt called with unsupported level {level} and opt {optname}");
+                return Err(Errno::EOPNOTSUPP.into());
+            }
+        };
+
+        let optval_ptr = optval_ptr.cast::<libc::c_int>();
+        let bytes_written = write_partial(mem, &val, optval_ptr, optlen as usize)?;
+
+        Ok(bytes_written as libc::socklen_t)
+    }
+
+    pub fn setsockopt(
+        &mut self,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        mem: &MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        match (level, optname) {
+            (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                let val: u64 = mem.read(optval_ptr)?.try_into().or(Err(Errno::EINVAL))?;
+
+                // linux kernel doubles this value upon setting, and we use the same limits as UDP
+                let val = (val * 2).clamp(4096, 268435456);
+
+                self.send_buffer
+                    .set_soft_limit_bytes(val.try_into().unwrap());
+            }
+            (libc::SOL_SOCKET, libc::SO_RCVBUF) => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                let val: u64 = mem.read(optval_ptr)?.try_into().or(Err(Errno::EINVAL))?;
+
+                // linux kernel doubles this value upon setting, and we use the same limits as UDP
+                let val = (val * 2).clamp(2048, 268435456);
+
+                self.recv_buffer
+                    .set_soft_limit_bytes(val.try_into().unwrap());
+            }
+            (libc::IPPROTO_IP, libc::IP_TTL) => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.ttl = parse_ttl(mem.read(optval_ptr)?)?;
+            }
+            (libc::SOL_RAW, ICMP_FILTER) if self.kind == IcmpSocketKind::Raw => {
+                type OptType = u32;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.icmp_filter = mem.read(optval_ptr)?;
+            }
+            _ => {
+                log::debug!("setsockopt called with unsupported level {level} and opt {optname}");
+                return Err(Errno::ENOPROTOOPT.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
+        self.event_source.add_legacy_listener(ptr);
+    }
+
+    pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener) {
+        self.event_source.remove_legacy_listener(ptr);
+    }
+
+    pub fn state(&self) -> FileState {
+        self.state
+    }
+
+    fn refresh_readable_writable(&mut self, cb_queue: &mut CallbackQueue) {
+        let readable = !self.recv_buffer.is_empty();
+        let writable = self.send_buffer.has_space();
+
+        let readable = readable.then_some(FileState::READABLE).unwrap_or_default();
+        let writable = writable.then_some(FileState::WRITABLE).unwrap_or_default();
+
+        self.copy_state(
+            /* mask= */ FileState::READABLE | FileState::WRITABLE,
+            readable | writable,
+            cb_queue,
+        );
+    }
+
+    fn copy_state(&mut self, mask: FileState, state: FileState, cb_queue: &mut CallbackQueue) {
+        let old_state = self.state;
+
+        // remove the masked flags, then copy the masked flags
+        self.state.remove(mask);
+        self.state.insert(state & mask);
+
+        self.handle_state_change(old_state, cb_queue);
+    }
+
+    fn handle_state_change(&mut self, old_state: FileState, cb_queue: &mut CallbackQueue) {
+        let states_changed = self.state ^ old_state;
+
+        // if nothing changed
+        if states_changed.is_empty() {
+            return;
+        }
+
+        self.event_source
+            .notify_listeners(self.state, states_changed, cb_queue);
+    }
+}
+
+/// Parse the value of an `IP_TTL` socket option. A value of -1 resets the socket to the default
+/// TTL, which is returned as `None`.
+pub(super) fn parse_ttl(val: libc::c_int) -> Result<Option<u8>, Errno> {
+    match val {
+        -1 => Ok(None),
+        1..=255 => Ok(Some(val.try_into().unwrap())),
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// Non-payload data for a message in the send buffer.
+#[derive(Debug)]
+struct MessageSendHeader {
+    /// The ICMP header, which is not included in the buffered message.
+    icmp: IcmpHeader,
+    /// The TTL of the IP packet.
+    ttl: u8,
+    /// The priority for the packet that we'll create in the future, given to us by the host.
+    packet_priority: FifoPacketPriority,
+}
+
+/// Non-payload data for a message in the receive buffer.
+#[derive(Debug)]
+struct MessageRecvHeader {
+    /// The source address of the IP packet.
+    src: Ipv4Addr,
+    /// The time when the network interface received the message.
+    recv_time: EmulatedTime,
+}
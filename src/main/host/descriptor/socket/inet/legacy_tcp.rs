@@ -1,24 +1,24 @@
-use std::ffi::CStr;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::ffi::{CStr, CString};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::Arc;
 
 use atomic_refcell::AtomicRefCell;
 use linux_api::errno::Errno;
 use linux_api::ioctls::IoctlRequest;
-use nix::sys::socket::{MsgFlags, Shutdown, SockaddrIn};
+use nix::sys::socket::{AddressFamily, MsgFlags, Shutdown};
 use shadow_shim_helper_rs::emulated_time::EmulatedTime;
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 
 use crate::core::worker::Worker;
 use crate::cshadow as c;
-use crate::host::descriptor::socket::inet::{self, InetSocket};
+use crate::host::descriptor::socket::inet::{self, InetSocket, IpVersion};
 use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs, Socket};
 use crate::host::descriptor::{
     CompatFile, File, FileMode, FileState, FileStatus, OpenFile, StateListenerFilter, SyscallResult,
 };
 use crate::host::host::Host;
 use crate::host::memory_manager::MemoryManager;
-use crate::host::network::interface::FifoPacketPriority;
+use crate::host::network::interface::{FifoPacketPriority, ReuseOptions};
 use crate::host::network::namespace::NetworkNamespace;
 use crate::host::syscall::io::{write_partial, IoVec};
 use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
@@ -30,6 +30,15 @@ use crate::utility::{HostTreePointer, ObjectCounter};
 
 pub struct LegacyTcpSocket {
     socket: HostTreePointer<c::TCP>,
+    /// Either `AF_INET` or `AF_INET6`.
+    domain: AddressFamily,
+    /// The `IPV6_V6ONLY` socket option.
+    ipv6_only: bool,
+    /// The IP version of packets that the socket can receive, which is updated when the socket is
+    /// bound or connected. The legacy TCP socket keeps its own copy.
+    ip_version: IpVersion,
+    /// The `SO_REUSEADDR` and `SO_REUSEPORT` socket options.
+    reuse: ReuseOptions,
     // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
     // this file
     has_open_file: bool,
@@ -39,19 +48,25 @@ pub struct LegacyTcpSocket {
 }
 
 impl LegacyTcpSocket {
-    pub fn new(status: FileStatus, host: &Host) -> Arc<AtomicRefCell<Self>> {
+    pub fn new(status: FileStatus, domain: AddressFamily, host: &Host) -> Arc<AtomicRefCell<Self>> {
         let recv_buf_size = host.params.init_sock_recv_buf_size.try_into().unwrap();
         let send_buf_size = host.params.init_sock_send_buf_size.try_into().unwrap();
 
         let tcp = unsafe { c::tcp_new(host, recv_buf_size, send_buf_size) };
         let tcp = unsafe { Self::new_from_legacy(tcp) };
 
-        tcp.borrow_mut().set_status(status);
+        {
+            let mut tcp = tcp.borrow_mut();
+            tcp.set_status(status);
+            tcp.domain = domain;
+            let ip_version = tcp.unbound_ip_version();
+            tcp.set_ip_version(ip_version);
+        }
 
         tcp
     }
 
-    /// Takes ownership of the [`TCP`](c::TCP) reference.
+    /// Takes ownership of the [`TCP`](c::TCP) reference. The socket will be an `AF_INET` socket.
     ///
     /// # Safety
     ///
@@ -61,6 +76,10 @@ impl LegacyTcpSocket {
 
         let socket = Self {
             socket: HostTreePointer::new(legacy_tcp),
+            domain: AddressFamily::Inet,
+            ipv6_only: false,
+            ip_version: IpVersion::V4,
+            reuse: ReuseOptions::default(),
             has_open_file: false,
             thread_of_blocked_connect: None,
             _counter: ObjectCounter::new("LegacyTcpSocket"),
@@ -96,6 +115,28 @@ impl LegacyTcpSocket {
         self.as_legacy_tcp() as *mut c::LegacyFile
     }
 
+    /// Set the IP versions of the packets that the legacy TCP socket sends and receives.
+    fn set_ip_version(&mut self, version: IpVersion) {
+        self.ip_version = version;
+        unsafe {
+            c::tcp_setIPVersions(
+                self.as_legacy_tcp(),
+                version.accepts(false),
+                version.accepts(true),
+            )
+        };
+    }
+
+    /// The IP version of a socket bound to the unspecified address.
+    fn unbound_ip_version(&self) -> IpVersion {
+        match self.domain {
+            AddressFamily::Inet => IpVersion::V4,
+            AddressFamily::Inet6 if self.ipv6_only => IpVersion::V6,
+            AddressFamily::Inet6 => IpVersion::Any,
+            domain => unreachable!("Not an inet socket domain: {domain:?}"),
+        }
+    }
+
     pub fn get_status(&self) -> FileStatus {
         let o_flags = unsafe { c::legacyfile_getFlags(self.as_legacy_file()) };
         let o_flags =
@@ -178,6 +219,14 @@ impl LegacyTcpSocket {
         self.peek_packet().is_some()
     }
 
+    pub fn is_listening(&self) -> bool {
+        unsafe { c::tcp_isValidListener(self.as_legacy_tcp()) == 1 }
+    }
+
+    fn reuse_options(&self) -> ReuseOptions {
+        self.reuse
+    }
+
     pub fn update_packet_header(&self, packet: &mut PacketRc) {
         Worker::with_active_host(|host| unsafe {
             c::tcp_networkInterfaceIsAboutToSendPacket(
@@ -189,7 +238,7 @@ impl LegacyTcpSocket {
         .unwrap();
     }
 
-    pub fn getsockname(&self) -> Result<Option<SockaddrIn>, SyscallError> {
+    pub fn getsockname(&self) -> Result<Option<SocketAddr>, SyscallError> {
         let mut ip: libc::in_addr_t = 0;
         let mut port: libc::in_port_t = 0;
 
@@ -197,17 +246,21 @@ impl LegacyTcpSocket {
         let okay =
             unsafe { c::legacysocket_getSocketName(self.as_legacy_socket(), &mut ip, &mut port) };
         if okay != 1 {
-            return Ok(Some(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0).into()));
+            let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+            // an unbound `AF_INET6` socket has the address `::`
+            let is_ipv6 = self.domain == AddressFamily::Inet6;
+            return Ok(Some(inet::from_internal_addr(addr, is_ipv6, self.domain)));
         }
 
         let ip = Ipv4Addr::from(u32::from_be(ip));
         let port = u16::from_be(port);
         let addr = SocketAddrV4::new(ip, port);
 
-        Ok(Some(addr.into()))
+        let is_ipv6 = self.ip_version.is_ipv6();
+        Ok(Some(inet::from_internal_addr(addr, is_ipv6, self.domain)))
     }
 
-    pub fn getpeername(&self) -> Result<Option<SockaddrIn>, SyscallError> {
+    pub fn getpeername(&self) -> Result<Option<SocketAddr>, SyscallError> {
         let mut ip: libc::in_addr_t = 0;
         let mut port: libc::in_port_t = 0;
 
@@ -222,11 +275,12 @@ impl LegacyTcpSocket {
         let port = u16::from_be(port);
         let addr = SocketAddrV4::new(ip, port);
 
-        Ok(Some(addr.into()))
+        let is_ipv6 = self.ip_version.is_ipv6();
+        Ok(Some(inet::from_internal_addr(addr, is_ipv6, self.domain)))
     }
 
-    pub fn address_family(&self) -> nix::sys::socket::AddressFamily {
-        nix::sys::socket::AddressFamily::Inet
+    pub fn address_family(&self) -> AddressFamily {
+        self.domain
     }
 
     pub fn close(&mut self, _cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
@@ -248,12 +302,22 @@ impl LegacyTcpSocket {
             return Err(Errno::EFAULT.into());
         };
 
-        // if not an inet socket address
-        let Some(addr) = addr.as_inet() else {
+        let (domain, ipv6_only) = {
+            let socket = socket.borrow();
+            (socket.domain, socket.ipv6_only)
+        };
+
+        // if not an address of the socket's family
+        let Some(addr) = inet::inet_sockaddr(addr, domain) else {
             return Err(Errno::EINVAL.into());
         };
 
-        let addr: SocketAddrV4 = (*addr).into();
+        let ip_version = IpVersion::of_bind_addr(addr, ipv6_only);
+
+        // an IPv6 address that doesn't belong to any host
+        let Some((addr, _)) = inet::to_internal_addr(addr) else {
+            return Err(Errno::EADDRNOTAVAIL.into());
+        };
 
         // if the socket is already bound
         {
@@ -278,21 +342,26 @@ impl LegacyTcpSocket {
         // this will allow us to receive packets from any peer
         let peer_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
 
+        // the socket can share the address with other sockets if their options allow it
+        let reuse = socket.borrow().reuse_options();
+
         // associate the socket
         let (addr, handle) = inet::associate_socket(
             InetSocket::LegacyTcp(Arc::clone(socket)),
             addr,
             peer_addr,
+            reuse,
             net_ns,
             rng,
         )?;
 
         // the handle normally disassociates the socket when dropped, but the C TCP code does it's
         // own manual disassociation, so we'll just let it do its own thing
-        std::mem::forget(handle);
+        handle.forget();
 
         // update the socket's local address
-        let socket = socket.borrow_mut();
+        let mut socket = socket.borrow_mut();
+        socket.set_ip_version(ip_version);
         let socket = socket.as_legacy_socket();
         unsafe {
             c::legacysocket_setSocketName(
@@ -623,7 +692,7 @@ impl LegacyTcpSocket {
         rng: impl rand::Rng,
         _cb_queue: &mut CallbackQueue,
     ) -> Result<(), SyscallError> {
-        let socket_ref = socket.borrow();
+        let mut socket_ref = socket.borrow_mut();
 
         // only listen on the socket if it is not used for other functions
         let is_listening_allowed =
@@ -646,6 +715,9 @@ impl LegacyTcpSocket {
         if !is_bound {
             log::trace!("Implicitly binding listener socket");
 
+            let ip_version = socket_ref.unbound_ip_version();
+            socket_ref.set_ip_version(ip_version);
+
             // implicit bind: bind to all interfaces at an ephemeral port
             let local_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
 
@@ -657,13 +729,14 @@ impl LegacyTcpSocket {
                 super::InetSocket::LegacyTcp(socket.clone()),
                 local_addr,
                 peer_addr,
+                socket_ref.reuse_options(),
                 net_ns,
                 rng,
             )?;
 
             // the handle normally disassociates the socket when dropped, but the C TCP code does
             // it's own manual disassociation, so we'll just let it do its own thing
-            std::mem::forget(handle);
+            handle.forget();
 
             unsafe {
                 c::legacysocket_setSocketName(
@@ -711,17 +784,27 @@ impl LegacyTcpSocket {
             }
         }
 
-        let Some(peer_addr) = peer_addr.as_inet() else {
+        let Some(peer_addr) = inet::inet_sockaddr(peer_addr, socket_ref.domain) else {
             return Err(Errno::EINVAL.into());
         };
 
-        let mut peer_addr: std::net::SocketAddrV4 = (*peer_addr).into();
+        // an IPv6 address that doesn't belong to any host
+        let Some((mut peer_addr, is_ipv6)) = inet::to_internal_addr(peer_addr) else {
+            return Err(Errno::ENETUNREACH.into());
+        };
 
         // https://stackoverflow.com/a/22425796
         if peer_addr.ip().is_unspecified() {
             peer_addr.set_ip(std::net::Ipv4Addr::LOCALHOST);
         }
 
+        // for example an `IPV6_V6ONLY` socket connecting to an IPv4-mapped address
+        if !socket_ref.ip_version.accepts(is_ipv6) {
+            return Err(Errno::ENETUNREACH.into());
+        }
+
+        inet::check_ipv6_route(net_ns, peer_addr, is_ipv6)?;
+
         let host_default_ip = net_ns.default_ip;
 
         // NOTE: it would be nice to use `Ipv4Addr::is_loopback` in this code rather than comparing
@@ -760,13 +843,14 @@ impl LegacyTcpSocket {
                 super::InetSocket::LegacyTcp(socket.clone()),
                 local_addr,
                 peer_addr,
+                socket_ref.reuse_options(),
                 net_ns,
                 rng,
             )?;
 
             // the handle normally disassociates the socket when dropped, but the C TCP code does
             // it's own manual disassociation, so we'll just let it do its own thing
-            std::mem::forget(handle);
+            handle.forget();
 
             unsafe {
                 c::legacysocket_setSocketName(
@@ -777,6 +861,8 @@ impl LegacyTcpSocket {
             };
         }
 
+        socket_ref.set_ip_version(IpVersion::of_peer(is_ipv6));
+
         unsafe {
             c::legacysocket_setPeerName(
                 socket_ref.as_legacy_socket(),
@@ -918,6 +1004,21 @@ impl LegacyTcpSocket {
             assert_eq!(port, peer_addr.sin_port);
         }
 
+        // the TCP code created the new socket as an `AF_INET` socket, but it should have the
+        // listening socket's domain and the IP version of the connection
+        {
+            let File::Socket(Socket::Inet(InetSocket::LegacyTcp(new_socket))) = open_file.inner_file() else {
+                panic!("Expected this to be a LegacyTcpSocket");
+            };
+
+            let mut new_socket = new_socket.borrow_mut();
+            let is_ipv6 = unsafe { c::tcp_isIPv6(new_socket.as_legacy_tcp()) };
+
+            new_socket.domain = self.domain;
+            new_socket.ipv6_only = self.ipv6_only;
+            new_socket.set_ip_version(IpVersion::of_peer(is_ipv6));
+        }
+
         Ok(open_file)
     }
 
@@ -953,6 +1054,7 @@ impl LegacyTcpSocket {
         optval_ptr: ForeignPtr<()>,
         optlen: libc::socklen_t,
         memory_manager: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
     ) -> Result<libc::socklen_t, SyscallError> {
         match (level, optname) {
             (libc::SOL_TCP, libc::TCP_INFO) => {
@@ -1046,7 +1148,7 @@ impl LegacyTcpSocket {
                 Ok(bytes_written as libc::socklen_t)
             }
             (libc::SOL_SOCKET, libc::SO_DOMAIN) => {
-                let domain = libc::AF_INET;
+                let domain = self.domain as libc::c_int;
 
                 let optval_ptr = optval_ptr.cast::<libc::c_int>();
                 let bytes_written =
@@ -1081,6 +1183,33 @@ impl LegacyTcpSocket {
 
                 Ok(bytes_written as libc::socklen_t)
             }
+            (libc::SOL_SOCKET, libc::SO_REUSEADDR) => {
+                let reuse_addr = self.reuse.reuse_addr as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written =
+                    write_partial(memory_manager, &reuse_addr, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            (libc::SOL_SOCKET, libc::SO_REUSEPORT) => {
+                let reuse_port = self.reuse.reuse_port as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written =
+                    write_partial(memory_manager, &reuse_port, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) if self.domain == AddressFamily::Inet6 => {
+                let ipv6_only = self.ipv6_only as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written =
+                    write_partial(memory_manager, &ipv6_only, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
             _ => {
                 log::warn!("getsockopt called with unsupported level {level} and opt {optname}");
                 Err(Errno::ENOPROTOOPT.into())
@@ -1095,6 +1224,7 @@ impl LegacyTcpSocket {
         optval_ptr: ForeignPtr<()>,
         optlen: libc::socklen_t,
         memory_manager: &MemoryManager,
+        _cb_queue: &mut CallbackQueue,
     ) -> Result<(), SyscallError> {
         match (level, optname) {
             (libc::SOL_TCP, libc::TCP_NODELAY) => {
@@ -1142,14 +1272,13 @@ impl LegacyTcpSocket {
                     .map(|x| &name[..x])
                     .unwrap_or(name);
 
-                let reno = unsafe { CStr::from_ptr(c::TCP_CONG_RENO_NAME) }.to_bytes();
+                // the name was truncated at the first NUL, so it can't contain one
+                let name = CString::new(name).unwrap();
 
-                if name != reno {
-                    log::warn!("Shadow sockets only support '{reno:?}' for TCP_CONGESTION");
+                if !unsafe { c::tcp_setCongestionControl(self.as_legacy_tcp(), name.as_ptr()) } {
+                    log::warn!("Shadow sockets don't support {name:?} for TCP_CONGESTION");
                     return Err(Errno::ENOENT.into());
                 }
-
-                // shadow doesn't support other congestion types, so do nothing
             }
             (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
                 type OptType = libc::c_int;
@@ -1210,12 +1339,26 @@ impl LegacyTcpSocket {
                 unsafe { c::tcp_disableReceiveBufferAutotuning(self.as_legacy_tcp()) };
             }
             (libc::SOL_SOCKET, libc::SO_REUSEADDR) => {
-                // TODO: implement this, tor and tgen use it
-                log::trace!("setsockopt SO_REUSEADDR not yet implemented");
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                // like Linux, this only affects future calls to `bind()`
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.reuse.reuse_addr = memory_manager.read(optval_ptr)? != 0;
             }
             (libc::SOL_SOCKET, libc::SO_REUSEPORT) => {
-                // TODO: implement this, tgen uses it
-                log::trace!("setsockopt SO_REUSEPORT not yet implemented");
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                // like Linux, this only affects future calls to `bind()`
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.reuse.reuse_port = memory_manager.read(optval_ptr)? != 0;
             }
             (libc::SOL_SOCKET, libc::SO_KEEPALIVE) => {
                 // TODO: implement this, libevent uses it in
@@ -1226,6 +1369,24 @@ impl LegacyTcpSocket {
                 // TODO: implement this, pkg.go.dev/net uses it
                 log::trace!("setsockopt SO_BROADCAST not yet implemented");
             }
+            (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) if self.domain == AddressFamily::Inet6 => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                // can't be changed after the socket has been bound
+                if unsafe { c::legacysocket_isBound(self.as_legacy_socket()) } == 1 {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.ipv6_only = memory_manager.read(optval_ptr)? != 0;
+
+                let ip_version = self.unbound_ip_version();
+                self.set_ip_version(ip_version);
+            }
             _ => {
                 log::warn!("setsockopt called with unsupported level {level} and opt {optname}");
                 return Err(Errno::ENOPROTOOPT.into());
@@ -1251,6 +1412,22 @@ impl LegacyTcpSocket {
         .unwrap()
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        let event_source = unsafe { c::legacyfile_getEventSource(self.as_legacy_file()) };
+        let event_source = unsafe { event_source.as_ref() }.unwrap();
+
+        Worker::with_active_host(|host| {
+            let mut event_source = event_source.borrow_mut(host.root());
+            event_source.add_exclusive_listener(monitoring, filter, notify_fn)
+        })
+        .unwrap()
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         unsafe { c::legacyfile_addListener(self.as_legacy_file(), ptr.ptr()) };
     }
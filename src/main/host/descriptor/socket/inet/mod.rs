@@ -1,71 +1,102 @@
-use std::net::SocketAddrV4;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::sync::{Arc, Weak};
 
 use atomic_refcell::AtomicRefCell;
 use linux_api::errno::Errno;
 use linux_api::ioctls::IoctlRequest;
-use nix::sys::socket::Shutdown;
+use nix::sys::socket::{AddressFamily, Shutdown};
 use shadow_shim_helper_rs::emulated_time::EmulatedTime;
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 
+use crate::core::worker::Worker;
 use crate::cshadow as c;
 use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs};
-use crate::host::descriptor::{FileMode, FileState, FileStatus, OpenFile, SyscallResult};
+use crate::host::descriptor::{
+    FileMode, FileState, FileStatus, OpenFile, StateListenerFilter, SyscallResult,
+};
 use crate::host::memory_manager::MemoryManager;
-use crate::host::network::interface::FifoPacketPriority;
+use crate::host::network::interface::{FifoPacketPriority, ReuseOptions};
 use crate::host::network::namespace::{AssociationHandle, NetworkNamespace};
 use crate::host::syscall::io::IoVec;
 use crate::host::syscall_types::SyscallError;
 use crate::network::packet::PacketRc;
-use crate::utility::callback_queue::CallbackQueue;
+use crate::utility::callback_queue::{CallbackQueue, Handle};
 use crate::utility::sockaddr::SockaddrStorage;
 use crate::utility::HostTreePointer;
 
+use self::icmp::IcmpSocket;
 use self::legacy_tcp::LegacyTcpSocket;
+use self::tcp::TcpSocket;
 use self::udp::UdpSocket;
 
+pub mod icmp;
 pub mod legacy_tcp;
+pub mod tcp;
 pub mod udp;
 
 #[derive(Clone)]
 pub enum InetSocket {
     LegacyTcp(Arc<AtomicRefCell<LegacyTcpSocket>>),
+    Tcp(Arc<AtomicRefCell<TcpSocket>>),
     Udp(Arc<AtomicRefCell<UdpSocket>>),
+    Icmp(Arc<AtomicRefCell<IcmpSocket>>),
 }
 
 impl InetSocket {
     pub fn borrow(&self) -> InetSocketRef {
         match self {
             Self::LegacyTcp(ref f) => InetSocketRef::LegacyTcp(f.borrow()),
+            Self::Tcp(ref f) => InetSocketRef::Tcp(f.borrow()),
             Self::Udp(ref f) => InetSocketRef::Udp(f.borrow()),
+            Self::Icmp(ref f) => InetSocketRef::Icmp(f.borrow()),
         }
     }
 
     pub fn try_borrow(&self) -> Result<InetSocketRef, atomic_refcell::BorrowError> {
         Ok(match self {
             Self::LegacyTcp(ref f) => InetSocketRef::LegacyTcp(f.try_borrow()?),
+            Self::Tcp(ref f) => InetSocketRef::Tcp(f.try_borrow()?),
             Self::Udp(ref f) => InetSocketRef::Udp(f.try_borrow()?),
+            Self::Icmp(ref f) => InetSocketRef::Icmp(f.try_borrow()?),
         })
     }
 
     pub fn borrow_mut(&self) -> InetSocketRefMut {
         match self {
             Self::LegacyTcp(ref f) => InetSocketRefMut::LegacyTcp(f.borrow_mut()),
+            Self::Tcp(ref f) => InetSocketRefMut::Tcp(f.borrow_mut()),
             Self::Udp(ref f) => InetSocketRefMut::Udp(f.borrow_mut()),
+            Self::Icmp(ref f) => InetSocketRefMut::Icmp(f.borrow_mut()),
         }
     }
 
     pub fn try_borrow_mut(&self) -> Result<InetSocketRefMut, atomic_refcell::BorrowMutError> {
         Ok(match self {
             Self::LegacyTcp(ref f) => InetSocketRefMut::LegacyTcp(f.try_borrow_mut()?),
+            Self::Tcp(ref f) => InetSocketRefMut::Tcp(f.try_borrow_mut()?),
             Self::Udp(ref f) => InetSocketRefMut::Udp(f.try_borrow_mut()?),
+            Self::Icmp(ref f) => InetSocketRefMut::Icmp(f.try_borrow_mut()?),
         })
     }
 
     pub fn downgrade(&self) -> InetSocketWeak {
         match self {
             Self::LegacyTcp(x) => InetSocketWeak::LegacyTcp(Arc::downgrade(x)),
+            Self::Tcp(x) => InetSocketWeak::Tcp(Arc::downgrade(x)),
             Self::Udp(x) => InetSocketWeak::Udp(Arc::downgrade(x)),
+            Self::Icmp(x) => InetSocketWeak::Icmp(Arc::downgrade(x)),
+        }
+    }
+
+    /// Returns true if both refer to the same socket object. Unlike
+    /// [`canonical_handle`](Self::canonical_handle), this doesn't need to borrow the socket.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::LegacyTcp(a), Self::LegacyTcp(b)) => Arc::ptr_eq(a, b),
+            (Self::Tcp(a), Self::Tcp(b)) => Arc::ptr_eq(a, b),
+            (Self::Udp(a), Self::Udp(b)) => Arc::ptr_eq(a, b),
+            (Self::Icmp(a), Self::Icmp(b)) => Arc::ptr_eq(a, b),
+            _ => false,
         }
     }
 
@@ -74,7 +105,9 @@ impl InetSocket {
             // usually we'd use `Arc::as_ptr()`, but we want to use the handle for the C `TCP`
             // object for consistency with the handle for the `LegacySocket`
             Self::LegacyTcp(f) => f.borrow().canonical_handle(),
+            Self::Tcp(f) => Arc::as_ptr(f) as usize,
             Self::Udp(f) => Arc::as_ptr(f) as usize,
+            Self::Icmp(f) => Arc::as_ptr(f) as usize,
         }
     }
 
@@ -86,7 +119,9 @@ impl InetSocket {
     ) -> SyscallResult {
         match self {
             Self::LegacyTcp(socket) => LegacyTcpSocket::bind(socket, addr, net_ns, rng),
+            Self::Tcp(socket) => TcpSocket::bind(socket, addr, net_ns, rng),
             Self::Udp(socket) => UdpSocket::bind(socket, addr, net_ns, rng),
+            Self::Icmp(socket) => IcmpSocket::bind(socket, addr, net_ns, rng),
         }
     }
 
@@ -101,7 +136,9 @@ impl InetSocket {
             Self::LegacyTcp(socket) => {
                 LegacyTcpSocket::listen(socket, backlog, net_ns, rng, cb_queue)
             }
+            Self::Tcp(socket) => TcpSocket::listen(socket, backlog, net_ns, rng, cb_queue),
             Self::Udp(socket) => UdpSocket::listen(socket, backlog, net_ns, rng, cb_queue),
+            Self::Icmp(socket) => IcmpSocket::listen(socket, backlog, net_ns, rng, cb_queue),
         }
     }
 
@@ -116,7 +153,9 @@ impl InetSocket {
             Self::LegacyTcp(socket) => {
                 LegacyTcpSocket::connect(socket, addr, net_ns, rng, cb_queue)
             }
+            Self::Tcp(socket) => TcpSocket::connect(socket, addr, net_ns, rng, cb_queue),
             Self::Udp(socket) => UdpSocket::connect(socket, addr, net_ns, rng, cb_queue),
+            Self::Icmp(socket) => IcmpSocket::connect(socket, addr, net_ns, rng, cb_queue),
         }
     }
 
@@ -132,9 +171,15 @@ impl InetSocket {
             Self::LegacyTcp(socket) => {
                 LegacyTcpSocket::sendmsg(socket, args, memory_manager, net_ns, rng, cb_queue)
             }
+            Self::Tcp(socket) => {
+                TcpSocket::sendmsg(socket, args, memory_manager, net_ns, rng, cb_queue)
+            }
             Self::Udp(socket) => {
                 UdpSocket::sendmsg(socket, args, memory_manager, net_ns, rng, cb_queue)
             }
+            Self::Icmp(socket) => {
+                IcmpSocket::sendmsg(socket, args, memory_manager, net_ns, rng, cb_queue)
+            }
         }
     }
 
@@ -148,7 +193,9 @@ impl InetSocket {
             Self::LegacyTcp(socket) => {
                 LegacyTcpSocket::recvmsg(socket, args, memory_manager, cb_queue)
             }
+            Self::Tcp(socket) => TcpSocket::recvmsg(socket, args, memory_manager, cb_queue),
             Self::Udp(socket) => UdpSocket::recvmsg(socket, args, memory_manager, cb_queue),
+            Self::Icmp(socket) => IcmpSocket::recvmsg(socket, args, memory_manager, cb_queue),
         }
     }
 }
@@ -157,7 +204,9 @@ impl std::fmt::Debug for InetSocket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::LegacyTcp(_) => write!(f, "LegacyTcp")?,
+            Self::Tcp(_) => write!(f, "Tcp")?,
             Self::Udp(_) => write!(f, "Udp")?,
+            Self::Icmp(_) => write!(f, "Icmp")?,
         }
 
         if let Ok(file) = self.try_borrow() {
@@ -175,29 +224,33 @@ impl std::fmt::Debug for InetSocket {
 
 pub enum InetSocketRef<'a> {
     LegacyTcp(atomic_refcell::AtomicRef<'a, LegacyTcpSocket>),
+    Tcp(atomic_refcell::AtomicRef<'a, TcpSocket>),
     Udp(atomic_refcell::AtomicRef<'a, UdpSocket>),
+    Icmp(atomic_refcell::AtomicRef<'a, IcmpSocket>),
 }
 
 pub enum InetSocketRefMut<'a> {
     LegacyTcp(atomic_refcell::AtomicRefMut<'a, LegacyTcpSocket>),
+    Tcp(atomic_refcell::AtomicRefMut<'a, TcpSocket>),
     Udp(atomic_refcell::AtomicRefMut<'a, UdpSocket>),
+    Icmp(atomic_refcell::AtomicRefMut<'a, IcmpSocket>),
 }
 
 // file functions
 impl InetSocketRef<'_> {
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn state(&self) -> FileState
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn mode(&self) -> FileMode
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn get_status(&self) -> FileStatus
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn has_open_file(&self) -> bool
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn supports_sa_restart(&self) -> bool
     );
 }
@@ -207,81 +260,92 @@ impl InetSocketRef<'_> {
     pub fn getpeername(&self) -> Result<Option<SockaddrStorage>, SyscallError> {
         match self {
             Self::LegacyTcp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
+            Self::Tcp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
             Self::Udp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
+            Self::Icmp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
         }
     }
 
     pub fn getsockname(&self) -> Result<Option<SockaddrStorage>, SyscallError> {
         match self {
             Self::LegacyTcp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
+            Self::Tcp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
             Self::Udp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
+            Self::Icmp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
         }
     }
 
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn address_family(&self) -> nix::sys::socket::AddressFamily
     );
-
-    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager), LegacyTcp, Udp;
-        pub fn getsockopt(&self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
-                          optlen: libc::socklen_t, memory_manager: &mut MemoryManager)
-        -> Result<libc::socklen_t, SyscallError>
-    );
 }
 
 // inet socket-specific functions
 impl InetSocketRef<'_> {
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn peek_next_packet_priority(&self) -> Option<FifoPacketPriority>
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn has_data_to_send(&self) -> bool
     );
-    enum_passthrough!(self, (packet), LegacyTcp, Udp;
+    enum_passthrough!(self, (packet), LegacyTcp, Tcp, Udp, Icmp;
         pub fn update_packet_header(&self, packet: &mut PacketRc)
     );
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
+        pub fn is_listening(&self) -> bool
+    );
 }
 
 // file functions
 impl InetSocketRefMut<'_> {
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn state(&self) -> FileState
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn mode(&self) -> FileMode
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn get_status(&self) -> FileStatus
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn has_open_file(&self) -> bool
     );
-    enum_passthrough!(self, (val), LegacyTcp, Udp;
+    enum_passthrough!(self, (val), LegacyTcp, Tcp, Udp, Icmp;
         pub fn set_has_open_file(&mut self, val: bool)
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn supports_sa_restart(&self) -> bool
     );
-    enum_passthrough!(self, (cb_queue), LegacyTcp, Udp;
+    enum_passthrough!(self, (cb_queue), LegacyTcp, Tcp, Udp, Icmp;
         pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError>
     );
-    enum_passthrough!(self, (status), LegacyTcp, Udp;
+    enum_passthrough!(self, (status), LegacyTcp, Tcp, Udp, Icmp;
         pub fn set_status(&mut self, status: FileStatus)
     );
-    enum_passthrough!(self, (request, arg_ptr, memory_manager), LegacyTcp, Udp;
+    enum_passthrough!(self, (request, arg_ptr, memory_manager), LegacyTcp, Tcp, Udp, Icmp;
         pub fn ioctl(&mut self, request: IoctlRequest, arg_ptr: ForeignPtr<()>, memory_manager: &mut MemoryManager) -> SyscallResult
     );
-    enum_passthrough!(self, (ptr), LegacyTcp, Udp;
+    enum_passthrough!(self, (monitoring, filter, notify_fn), LegacyTcp, Tcp, Udp, Icmp;
+        pub fn add_listener(&mut self, monitoring: FileState, filter: StateListenerFilter,
+                            notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static)
+                            -> Handle<(FileState, FileState)>
+    );
+    enum_passthrough!(self, (monitoring, filter, notify_fn), LegacyTcp, Tcp, Udp, Icmp;
+        pub fn add_exclusive_listener(&mut self, monitoring: FileState, filter: StateListenerFilter,
+                                      notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static)
+                                      -> Handle<(FileState, FileState)>
+    );
+    enum_passthrough!(self, (ptr), LegacyTcp, Tcp, Udp, Icmp;
         pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>)
     );
-    enum_passthrough!(self, (ptr), LegacyTcp, Udp;
+    enum_passthrough!(self, (ptr), LegacyTcp, Tcp, Udp, Icmp;
         pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener)
     );
-    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), LegacyTcp, Udp;
+    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), LegacyTcp, Tcp, Udp, Icmp;
         pub fn readv(&mut self, iovs: &[IoVec], offset: Option<libc::off_t>, flags: libc::c_int,
                      mem: &mut MemoryManager, cb_queue: &mut CallbackQueue) -> Result<libc::ssize_t, SyscallError>
     );
-    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), LegacyTcp, Udp;
+    enum_passthrough!(self, (iovs, offset, flags, mem, cb_queue), LegacyTcp, Tcp, Udp, Icmp;
         pub fn writev(&mut self, iovs: &[IoVec], offset: Option<libc::off_t>, flags: libc::c_int,
                       mem: &mut MemoryManager, cb_queue: &mut CallbackQueue) -> Result<libc::ssize_t, SyscallError>
     );
@@ -292,60 +356,68 @@ impl InetSocketRefMut<'_> {
     pub fn getpeername(&self) -> Result<Option<SockaddrStorage>, SyscallError> {
         match self {
             Self::LegacyTcp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
+            Self::Tcp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
             Self::Udp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
+            Self::Icmp(socket) => socket.getpeername().map(|opt| opt.map(Into::into)),
         }
     }
 
     pub fn getsockname(&self) -> Result<Option<SockaddrStorage>, SyscallError> {
         match self {
             Self::LegacyTcp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
+            Self::Tcp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
             Self::Udp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
+            Self::Icmp(socket) => socket.getsockname().map(|opt| opt.map(Into::into)),
         }
     }
 
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn address_family(&self) -> nix::sys::socket::AddressFamily
     );
 
-    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager), LegacyTcp, Udp;
-        pub fn getsockopt(&self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
-                          optlen: libc::socklen_t, memory_manager: &mut MemoryManager)
+    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager, cb_queue), LegacyTcp, Tcp, Udp, Icmp;
+        pub fn getsockopt(&mut self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
+                          optlen: libc::socklen_t, memory_manager: &mut MemoryManager,
+                          cb_queue: &mut CallbackQueue)
         -> Result<libc::socklen_t, SyscallError>
     );
 
-    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager), LegacyTcp, Udp;
+    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager, cb_queue), LegacyTcp, Tcp, Udp, Icmp;
         pub fn setsockopt(&mut self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
-                          optlen: libc::socklen_t, memory_manager: &MemoryManager)
+                          optlen: libc::socklen_t, memory_manager: &MemoryManager,
+                          cb_queue: &mut CallbackQueue)
         -> Result<(), SyscallError>
     );
 
     pub fn accept(&mut self, cb_queue: &mut CallbackQueue) -> Result<OpenFile, SyscallError> {
         match self {
             Self::LegacyTcp(socket) => socket.accept(cb_queue),
+            Self::Tcp(socket) => socket.accept(cb_queue),
             Self::Udp(socket) => socket.accept(cb_queue),
+            Self::Icmp(socket) => socket.accept(cb_queue),
         }
     }
 
-    enum_passthrough!(self, (how, cb_queue), LegacyTcp, Udp;
+    enum_passthrough!(self, (how, cb_queue), LegacyTcp, Tcp, Udp, Icmp;
         pub fn shutdown(&mut self, how: Shutdown, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError>
     );
 }
 
 // inet socket-specific functions
 impl InetSocketRefMut<'_> {
-    enum_passthrough!(self, (packet, cb_queue, recv_time), LegacyTcp, Udp;
+    enum_passthrough!(self, (packet, cb_queue, recv_time), LegacyTcp, Tcp, Udp, Icmp;
         pub fn push_in_packet(&mut self, packet: PacketRc, cb_queue: &mut CallbackQueue, recv_time: EmulatedTime)
     );
-    enum_passthrough!(self, (cb_queue), LegacyTcp, Udp;
+    enum_passthrough!(self, (cb_queue), LegacyTcp, Tcp, Udp, Icmp;
         pub fn pull_out_packet(&mut self, cb_queue: &mut CallbackQueue) -> Option<PacketRc>
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn peek_next_packet_priority(&self) -> Option<FifoPacketPriority>
     );
-    enum_passthrough!(self, (), LegacyTcp, Udp;
+    enum_passthrough!(self, (), LegacyTcp, Tcp, Udp, Icmp;
         pub fn has_data_to_send(&self) -> bool
     );
-    enum_passthrough!(self, (packet), LegacyTcp, Udp;
+    enum_passthrough!(self, (packet), LegacyTcp, Tcp, Udp, Icmp;
         pub fn update_packet_header(&self, packet: &mut PacketRc)
     );
 }
@@ -354,7 +426,9 @@ impl std::fmt::Debug for InetSocketRef<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::LegacyTcp(_) => write!(f, "LegacyTcp")?,
+            Self::Tcp(_) => write!(f, "Tcp")?,
             Self::Udp(_) => write!(f, "Udp")?,
+            Self::Icmp(_) => write!(f, "Icmp")?,
         }
 
         write!(
@@ -370,7 +444,9 @@ impl std::fmt::Debug for InetSocketRefMut<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::LegacyTcp(_) => write!(f, "LegacyTcp")?,
+            Self::Tcp(_) => write!(f, "Tcp")?,
             Self::Udp(_) => write!(f, "Udp")?,
+            Self::Icmp(_) => write!(f, "Icmp")?,
         }
 
         write!(
@@ -385,14 +461,18 @@ impl std::fmt::Debug for InetSocketRefMut<'_> {
 #[derive(Clone)]
 pub enum InetSocketWeak {
     LegacyTcp(Weak<AtomicRefCell<LegacyTcpSocket>>),
+    Tcp(Weak<AtomicRefCell<TcpSocket>>),
     Udp(Weak<AtomicRefCell<UdpSocket>>),
+    Icmp(Weak<AtomicRefCell<IcmpSocket>>),
 }
 
 impl InetSocketWeak {
     pub fn upgrade(&self) -> Option<InetSocket> {
         match self {
             Self::LegacyTcp(x) => x.upgrade().map(InetSocket::LegacyTcp),
+            Self::Tcp(x) => x.upgrade().map(InetSocket::Tcp),
             Self::Udp(x) => x.upgrade().map(InetSocket::Udp),
+            Self::Icmp(x) => x.upgrade().map(InetSocket::Icmp),
         }
     }
 }
@@ -401,11 +481,13 @@ impl InetSocketWeak {
 /// will be associated with every available interface. If the local address has a port of 0, a
 /// non-zero port will be chosen. The final local address will be returned. If the peer address is
 /// unspecified and has a port of 0, the socket will receive packets from every peer address. The
+/// socket can share the local address with other sockets if their `reuse` options allow it. The
 /// socket will be automatically disassociated when the returned [`AssociationHandle`] is dropped.
 fn associate_socket(
     socket: InetSocket,
     local_addr: SocketAddrV4,
     peer_addr: SocketAddrV4,
+    reuse: ReuseOptions,
     net_ns: &NetworkNamespace,
     rng: impl rand::Rng,
 ) -> Result<(SocketAddrV4, AssociationHandle), SyscallError> {
@@ -420,8 +502,9 @@ fn associate_socket(
     };
 
     let protocol = match socket {
-        InetSocket::LegacyTcp(_) => c::_ProtocolType_PTCP,
+        InetSocket::LegacyTcp(_) | InetSocket::Tcp(_) => c::_ProtocolType_PTCP,
         InetSocket::Udp(_) => c::_ProtocolType_PUDP,
+        InetSocket::Icmp(_) => c::_ProtocolType_PICMP,
     };
 
     // get a free ephemeral port if they didn't specify one
@@ -440,21 +523,121 @@ fn associate_socket(
     };
 
     // make sure the port is available at this address for this protocol
-    if !net_ns.is_interface_available(protocol, local_addr, peer_addr) {
+    if !net_ns.is_interface_available(protocol, local_addr, peer_addr, reuse) {
         log::debug!(
             "The provided addresses (local={local_addr}, peer={peer_addr}) are not available"
         );
         return Err(Errno::EADDRINUSE.into());
     }
 
-    let socket = unsafe { c::compatsocket_fromInetSocket(&socket) };
-
     // associate the interfaces corresponding to addr with socket
-    let handle = unsafe { net_ns.associate_interface(&socket, protocol, local_addr, peer_addr) };
+    let handle = net_ns.associate_interface(&socket, protocol, local_addr, peer_addr, reuse);
 
     Ok((local_addr, handle))
 }
 
+/// The IP version of the packets that an inet socket sends and receives. Shadow routes packets
+/// using the IPv4 addresses of hosts, so `AF_INET6` sockets store their addresses as IPv4 addresses
+/// and track the IP version separately.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+    /// An `AF_INET6` socket bound to `::` without `IPV6_V6ONLY` can receive both.
+    Any,
+}
+
+impl IpVersion {
+    /// The IP version of a connection with the peer `addr`.
+    fn of_peer(is_ipv6: bool) -> Self {
+        if is_ipv6 {
+            Self::V6
+        } else {
+            Self::V4
+        }
+    }
+
+    /// The IP version of a socket bound to `addr`.
+    fn of_bind_addr(addr: SocketAddr, ipv6_only: bool) -> Self {
+        match addr {
+            SocketAddr::V4(_) => Self::V4,
+            SocketAddr::V6(addr) if addr.ip().to_ipv4_mapped().is_some() => Self::V4,
+            SocketAddr::V6(addr) if addr.ip().is_unspecified() && !ipv6_only => Self::Any,
+            SocketAddr::V6(_) => Self::V6,
+        }
+    }
+
+    /// Can a socket with this IP version receive packets of the given version?
+    fn accepts(&self, is_ipv6: bool) -> bool {
+        match self {
+            Self::V4 => !is_ipv6,
+            Self::V6 => is_ipv6,
+            Self::Any => true,
+        }
+    }
+
+    /// Are the socket's addresses shown as IPv6 (rather than IPv4-mapped) addresses?
+    fn is_ipv6(&self) -> bool {
+        *self != Self::V4
+    }
+}
+
+/// Get the socket address from `addr` if it belongs to the socket's address family.
+fn inet_sockaddr(addr: &SockaddrStorage, domain: AddressFamily) -> Option<SocketAddr> {
+    match domain {
+        AddressFamily::Inet => addr.as_inet().map(|x| SocketAddrV4::from(*x).into()),
+        AddressFamily::Inet6 => addr.as_inet6().map(|x| SocketAddrV6::from(*x).into()),
+        _ => unreachable!("Not an inet socket domain: {domain:?}"),
+    }
+}
+
+/// Convert a socket address given by the application to the IPv4 socket address that Shadow uses
+/// internally, and whether packets to or from the address are sent over IPv6. Returns `None` if it
+/// is an IPv6 address that doesn't belong to any host.
+fn to_internal_addr(addr: SocketAddr) -> Option<(SocketAddrV4, bool)> {
+    match addr {
+        SocketAddr::V4(addr) => Some((addr, false)),
+        SocketAddr::V6(addr) => {
+            let is_ipv6 = addr.ip().to_ipv4_mapped().is_none();
+            let ip = Worker::ipv6_to_ipv4(*addr.ip())?;
+            Some((SocketAddrV4::new(ip, addr.port()), is_ipv6))
+        }
+    }
+}
+
+/// The inverse of [`to_internal_addr`] for a socket of the given address family. Addresses that
+/// aren't used over IPv6 are shown to `AF_INET6` sockets as IPv4-mapped addresses.
+fn from_internal_addr(addr: SocketAddrV4, is_ipv6: bool, domain: AddressFamily) -> SocketAddr {
+    match domain {
+        AddressFamily::Inet => {
+            debug_assert!(!is_ipv6);
+            addr.into()
+        }
+        AddressFamily::Inet6 => {
+            let ip = if is_ipv6 {
+                Worker::ipv4_to_ipv6(*addr.ip())
+            } else {
+                addr.ip().to_ipv6_mapped()
+            };
+            SocketAddrV6::new(ip, addr.port(), 0, 0).into()
+        }
+        _ => unreachable!("Not an inet socket domain: {domain:?}"),
+    }
+}
+
+/// Packets to another host can only be sent over IPv6 if this host has an IPv6 address.
+fn check_ipv6_route(
+    net_ns: &NetworkNamespace,
+    peer_addr: SocketAddrV4,
+    is_ipv6: bool,
+) -> Result<(), Errno> {
+    if is_ipv6 && !peer_addr.ip().is_loopback() && net_ns.default_ipv6.is_none() {
+        log::debug!("Host has no IPv6 address to reach {peer_addr} over IPv6");
+        return Err(Errno::ENETUNREACH);
+    }
+    Ok(())
+}
+
 mod export {
     use super::*;
 
@@ -492,6 +675,24 @@ mod export {
         socket.canonical_handle()
     }
 
+    /// Returns true if both `InetSocket` objects refer to the same socket.
+    #[no_mangle]
+    pub extern "C" fn inetsocket_ptrEq(
+        socket: *const InetSocket,
+        other: *const InetSocket,
+    ) -> bool {
+        let socket = unsafe { socket.as_ref() }.unwrap();
+        let other = unsafe { other.as_ref() }.unwrap();
+        socket.ptr_eq(other)
+    }
+
+    /// Returns true if the socket is a listening TCP socket.
+    #[no_mangle]
+    pub extern "C" fn inetsocket_isListening(socket: *const InetSocket) -> bool {
+        let socket = unsafe { socket.as_ref() }.unwrap();
+        socket.borrow().is_listening()
+    }
+
     #[no_mangle]
     pub extern "C" fn inetsocket_pushInPacket(
         socket: *const InetSocket,
@@ -0,0 +1,446 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use bytes::{Buf, Bytes, BytesMut};
+
+use super::seq::Seq;
+
+/// Bytes written by the application that haven't yet been acknowledged by the peer. The first byte
+/// in the buffer has the sequence number [`start_seq`](Self::start_seq).
+#[derive(Debug)]
+pub struct SendBuffer {
+    chunks: VecDeque<Bytes>,
+    start_seq: Seq,
+    len: usize,
+    capacity: usize,
+}
+
+impl SendBuffer {
+    pub fn new(start_seq: Seq, capacity: usize) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            start_seq,
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// The sequence number of the first byte in the buffer.
+    pub fn start_seq(&self) -> Seq {
+        self.start_seq
+    }
+
+    /// The sequence number following the last byte in the buffer.
+    pub fn end_seq(&self) -> Seq {
+        self.start_seq + u32::try_from(self.len).unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// The number of bytes that can be added to the buffer.
+    pub fn space(&self) -> usize {
+        self.capacity.saturating_sub(self.len)
+    }
+
+    /// Add bytes to the end of the buffer. This does not check the buffer's capacity.
+    pub fn push(&mut self, bytes: Bytes) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.len += bytes.len();
+        self.chunks.push_back(bytes);
+    }
+
+    /// Get up to `len` bytes starting at sequence number `seq`, which must be within the buffer.
+    pub fn get(&self, seq: Seq, len: usize) -> Bytes {
+        let mut offset = usize::try_from(seq.since(self.start_seq)).unwrap();
+        assert!(offset <= self.len);
+        let len = std::cmp::min(len, self.len - offset);
+
+        let mut rv = BytesMut::with_capacity(len);
+        for chunk in &self.chunks {
+            if rv.len() == len {
+                break;
+            }
+            if offset >= chunk.len() {
+                offset -= chunk.len();
+                continue;
+            }
+            let end = std::cmp::min(chunk.len(), offset + len - rv.len());
+            rv.extend_from_slice(&chunk[offset..end]);
+            offset = 0;
+        }
+
+        rv.freeze()
+    }
+
+    /// Remove all bytes before sequence number `seq`, which must be within the buffer.
+    pub fn advance_to(&mut self, seq: Seq) {
+        let mut remaining = usize::try_from(seq.since(self.start_seq)).unwrap();
+        assert!(remaining <= self.len);
+
+        self.start_seq = seq;
+        self.len -= remaining;
+
+        while remaining > 0 {
+            let chunk = self.chunks.front_mut().unwrap();
+            if chunk.len() <= remaining {
+                remaining -= chunk.len();
+                self.chunks.pop_front();
+            } else {
+                chunk.advance(remaining);
+                remaining = 0;
+            }
+        }
+    }
+}
+
+/// Bytes received from the peer. Bytes that were received in order can be read by the application,
+/// and bytes that were received out of order are held until the missing bytes arrive.
+#[derive(Debug)]
+pub struct RecvBuffer {
+    /// The in-order bytes that the application can read.
+    readable: VecDeque<Bytes>,
+    readable_len: usize,
+    /// Out-of-order segments, sorted by sequence number and non-overlapping.
+    out_of_order: Vec<(Seq, Bytes)>,
+    out_of_order_len: usize,
+    /// The sequence number of the next in-order byte that we expect to receive.
+    next_seq: Seq,
+    /// The sequence number of the most recently received out-of-order segment, which should be
+    /// reported in the first SACK block.
+    last_out_of_order: Option<Seq>,
+    capacity: usize,
+}
+
+impl RecvBuffer {
+    pub fn new(next_seq: Seq, capacity: usize) -> Self {
+        Self {
+            readable: VecDeque::new(),
+            readable_len: 0,
+            out_of_order: Vec::new(),
+            out_of_order_len: 0,
+            next_seq,
+            last_out_of_order: None,
+            capacity,
+        }
+    }
+
+    /// The sequence number of the next in-order byte that we expect to receive.
+    pub fn next_seq(&self) -> Seq {
+        self.next_seq
+    }
+
+    /// Skip over a sequence number that doesn't contain data (a SYN or FIN).
+    pub fn skip_seq(&mut self) {
+        debug_assert!(self.out_of_order.is_empty());
+        self.next_seq += 1;
+    }
+
+    /// Has any data been received out of order?
+    pub fn has_out_of_order(&self) -> bool {
+        !self.out_of_order.is_empty()
+    }
+
+    /// The number of bytes that the application can read.
+    pub fn readable_len(&self) -> usize {
+        self.readable_len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// The receive window that we can advertise to the peer.
+    pub fn window(&self) -> u32 {
+        let used = self.readable_len + self.out_of_order_len;
+        self.capacity
+            .saturating_sub(used)
+            .try_into()
+            .unwrap_or(u32::MAX)
+    }
+
+    /// Add a segment's bytes to the buffer. Bytes that we've already received or that are outside of
+    /// the receive window are ignored. Returns `true` if the segment was received in order.
+    pub fn insert(&mut self, mut seq: Seq, mut bytes: Bytes) -> bool {
+        // trim bytes that we've already received
+        if seq < self.next_seq {
+            let dup = usize::try_from(self.next_seq.since(seq)).unwrap();
+            if dup >= bytes.len() {
+                return false;
+            }
+            bytes.advance(dup);
+            seq = self.next_seq;
+        }
+
+        // trim bytes that are outside of the window
+        let window_end = self.next_seq + self.window();
+        if seq >= window_end {
+            return false;
+        }
+        let max_len = usize::try_from(window_end.since(seq)).unwrap();
+        bytes.truncate(max_len);
+
+        if bytes.is_empty() {
+            return false;
+        }
+
+        if seq != self.next_seq {
+            self.insert_out_of_order(seq, bytes);
+            self.last_out_of_order = Some(seq);
+            return false;
+        }
+
+        self.next_seq += u32::try_from(bytes.len()).unwrap();
+        self.readable_len += bytes.len();
+        self.readable.push_back(bytes);
+
+        // move any out-of-order segments that are now in order
+        while let Some((seq, _)) = self.out_of_order.first() {
+            let seq = *seq;
+            if seq > self.next_seq {
+                break;
+            }
+
+            let (_, mut bytes) = self.out_of_order.remove(0);
+            self.out_of_order_len -= bytes.len();
+
+            let dup = usize::try_from(self.next_seq.since(seq)).unwrap();
+            if dup >= bytes.len() {
+                continue;
+            }
+            bytes.advance(dup);
+
+            self.next_seq += u32::try_from(bytes.len()).unwrap();
+            self.readable_len += bytes.len();
+            self.readable.push_back(bytes);
+        }
+
+        if self.out_of_order.is_empty() {
+            self.last_out_of_order = None;
+        }
+
+        true
+    }
+
+    fn insert_out_of_order(&mut self, mut seq: Seq, mut bytes: Bytes) {
+        let mut i = 0;
+        while i < self.out_of_order.len() && !bytes.is_empty() {
+            let (other_seq, other_bytes) = &self.out_of_order[i];
+            let other_end = *other_seq + u32::try_from(other_bytes.len()).unwrap();
+
+            if other_end <= seq {
+                // the existing segment is entirely before the new bytes
+                i += 1;
+                continue;
+            }
+
+            if *other_seq <= seq {
+                // the start of the new bytes overlap the existing segment
+                let dup = usize::try_from(other_end.since(seq)).unwrap();
+                bytes.advance(std::cmp::min(dup, bytes.len()));
+                seq = other_end;
+                i += 1;
+                continue;
+            }
+
+            // the existing segment starts after the new bytes, so insert the new bytes up to the
+            // start of the existing segment
+            let len = usize::try_from(other_seq.since(seq)).unwrap();
+            let head = bytes.split_to(std::cmp::min(len, bytes.len()));
+            self.out_of_order_len += head.len();
+            self.out_of_order.insert(i, (seq, head));
+            seq += u32::try_from(len).unwrap();
+            i += 1;
+        }
+
+        if !bytes.is_empty() {
+            self.out_of_order_len += bytes.len();
+            self.out_of_order.insert(i, (seq, bytes));
+        }
+    }
+
+    /// Write up to `max_len` readable bytes to `writer`. The bytes are removed from the buffer unless
+    /// `peek` is true. Returns the number of bytes written, which may be fewer than requested if
+    /// the writer returns an error after writing some bytes.
+    pub fn read(
+        &mut self,
+        mut writer: impl Write,
+        max_len: usize,
+        peek: bool,
+    ) -> std::io::Result<usize> {
+        let mut written = 0;
+
+        for chunk in &self.readable {
+            if written == max_len {
+                break;
+            }
+            let len = std::cmp::min(chunk.len(), max_len - written);
+            match writer.write(&chunk[..len]) {
+                Ok(0) => break,
+                Ok(n) => written += n,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+            if len < chunk.len() {
+                break;
+            }
+        }
+
+        if !peek {
+            self.consume(written);
+        }
+
+        Ok(written)
+    }
+
+    fn consume(&mut self, mut len: usize) {
+        self.readable_len -= len;
+        while len > 0 {
+            let chunk = self.readable.front_mut().unwrap();
+            if chunk.len() <= len {
+                len -= chunk.len();
+                self.readable.pop_front();
+            } else {
+                chunk.advance(len);
+                len = 0;
+            }
+        }
+    }
+
+    /// The SACK blocks describing the out-of-order data, with the block containing the most
+    /// recently received segment first ([RFC 2018](https://www.rfc-editor.org/rfc/rfc2018)).
+    pub fn sack_blocks(&self, max_blocks: usize) -> Vec<(Seq, Seq)> {
+        let mut blocks: Vec<(Seq, Seq)> = Vec::new();
+
+        for (seq, bytes) in &self.out_of_order {
+            let end = *seq + u32::try_from(bytes.len()).unwrap();
+            match blocks.last_mut() {
+                Some(last) if last.1 == *seq => last.1 = end,
+                _ => blocks.push((*seq, end)),
+            }
+        }
+
+        if let Some(recent) = self.last_out_of_order {
+            if let Some(pos) = blocks.iter().position(|b| recent.in_range(b.0, b.1)) {
+                let block = blocks.remove(pos);
+                blocks.insert(0, block);
+            }
+        }
+
+        blocks.truncate(max_blocks);
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(buf: &mut RecvBuffer) -> Vec<u8> {
+        let mut out = Vec::new();
+        let len = buf.readable_len();
+        assert_eq!(buf.read(&mut out, len, false).unwrap(), len);
+        out
+    }
+
+    #[test]
+    fn test_send_buffer() {
+        let mut buf = SendBuffer::new(Seq::new(u32::MAX - 2), 10);
+        buf.push(Bytes::from_static(b"hello"));
+        buf.push(Bytes::from_static(b"world"));
+        assert_eq!(buf.len(), 10);
+        assert_eq!(buf.space(), 0);
+        assert_eq!(buf.end_seq(), Seq::new(7));
+
+        assert_eq!(&buf.get(Seq::new(u32::MAX), 5)[..], b"llowo");
+        assert_eq!(&buf.get(Seq::new(5), 100)[..], b"ld");
+
+        buf.advance_to(Seq::new(1));
+        assert_eq!(buf.len(), 6);
+        assert_eq!(&buf.get(Seq::new(1), 100)[..], b"oworld");
+
+        buf.advance_to(Seq::new(7));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_recv_in_order() {
+        let mut buf = RecvBuffer::new(Seq::new(100), 1000);
+        assert!(buf.insert(Seq::new(100), Bytes::from_static(b"abc")));
+        assert!(buf.insert(Seq::new(103), Bytes::from_static(b"def")));
+        assert_eq!(buf.next_seq(), Seq::new(106));
+        assert_eq!(buf.window(), 994);
+
+        let mut out = Vec::new();
+        assert_eq!(buf.read(&mut out, 4, true).unwrap(), 4);
+        assert_eq!(out, b"abcd");
+        assert_eq!(read_all(&mut buf), b"abcdef");
+        assert_eq!(buf.window(), 1000);
+    }
+
+    #[test]
+    fn test_recv_out_of_order() {
+        let mut buf = RecvBuffer::new(Seq::new(0), 1000);
+        assert!(!buf.insert(Seq::new(6), Bytes::from_static(b"ghi")));
+        assert!(!buf.insert(Seq::new(3), Bytes::from_static(b"def")));
+        assert_eq!(buf.readable_len(), 0);
+        assert_eq!(buf.sack_blocks(3), [(Seq::new(3), Seq::new(9))]);
+
+        assert!(!buf.insert(Seq::new(12), Bytes::from_static(b"mn")));
+        assert_eq!(
+            buf.sack_blocks(3),
+            [(Seq::new(12), Seq::new(14)), (Seq::new(3), Seq::new(9))]
+        );
+
+        // overlaps the out-of-order segment
+        assert!(buf.insert(Seq::new(0), Bytes::from_static(b"abcde")));
+        assert_eq!(buf.next_seq(), Seq::new(9));
+        assert_eq!(buf.sack_blocks(3), [(Seq::new(12), Seq::new(14))]);
+
+        assert!(buf.insert(Seq::new(8), Bytes::from_static(b"ijkl")));
+        assert_eq!(buf.next_seq(), Seq::new(14));
+        assert!(buf.sack_blocks(3).is_empty());
+        assert_eq!(read_all(&mut buf), b"abcdefghijklmn");
+    }
+
+    #[test]
+    fn test_recv_window() {
+        let mut buf = RecvBuffer::new(Seq::new(0), 4);
+        assert!(buf.insert(Seq::new(0), Bytes::from_static(b"abcdef")));
+        assert_eq!(buf.next_seq(), Seq::new(4));
+        assert_eq!(buf.window(), 0);
+        assert!(!buf.insert(Seq::new(4), Bytes::from_static(b"ef")));
+        assert_eq!(read_all(&mut buf), b"abcd");
+    }
+
+    #[test]
+    fn test_recv_duplicate() {
+        let mut buf = RecvBuffer::new(Seq::new(0), 100);
+        assert!(buf.insert(Seq::new(0), Bytes::from_static(b"abc")));
+        assert!(!buf.insert(Seq::new(0), Bytes::from_static(b"abc")));
+        assert!(!buf.insert(Seq::new(5), Bytes::from_static(b"fg")));
+        assert!(!buf.insert(Seq::new(4), Bytes::from_static(b"efgh")));
+        assert_eq!(buf.sack_blocks(3), [(Seq::new(4), Seq::new(8))]);
+        assert!(buf.insert(Seq::new(2), Bytes::from_static(b"cd")));
+        assert_eq!(read_all(&mut buf), b"abcdefgh");
+    }
+}
@@ -0,0 +1,1656 @@
+use std::io::Write;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use bytes::Bytes;
+use linux_api::errno::Errno;
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+use shadow_shim_helper_rs::simulation_time::SimulationTime;
+
+use super::buffer::{RecvBuffer, SendBuffer};
+use super::seq::Seq;
+use crate::network::packet::{TcpFlags, TcpHeader};
+
+/// The initial retransmission timeout ([RFC 6298](https://www.rfc-editor.org/rfc/rfc6298)).
+const RTO_INIT: SimulationTime = SimulationTime::from_duration(Duration::from_secs(1));
+/// Linux's `TCP_RTO_MIN`.
+const RTO_MIN: SimulationTime = SimulationTime::from_duration(Duration::from_millis(200));
+/// Linux's `TCP_RTO_MAX`.
+const RTO_MAX: SimulationTime = SimulationTime::from_duration(Duration::from_secs(120));
+/// Linux's `TCP_DELACK_MIN`.
+const DELAYED_ACK: SimulationTime = SimulationTime::from_duration(Duration::from_millis(40));
+/// Linux's `TCP_TIMEWAIT_LEN` (2*MSL), which is also its default `tcp_fin_timeout`.
+const TIME_WAIT: SimulationTime = SimulationTime::from_duration(Duration::from_secs(60));
+
+/// Linux's default `tcp_syn_retries`.
+const MAX_SYN_RETRIES: u32 = 6;
+/// Linux's default `tcp_retries2`.
+const MAX_RETRIES: u32 = 15;
+/// The initial congestion window in segments ([RFC 6928](https://www.rfc-editor.org/rfc/rfc6928)).
+const INIT_CWND_SEGMENTS: u32 = 10;
+/// The maximum number of SACK blocks in a segment when the timestamp option is not used.
+const MAX_SACK_BLOCKS: usize = 3;
+/// The number of duplicate ACKs that trigger a fast retransmit.
+const DUP_ACK_THRESHOLD: u32 = 3;
+
+/// The TCP connection states ([RFC 793](https://www.rfc-editor.org/rfc/rfc793)). Listening
+/// sockets don't have a connection, so there's no `LISTEN` state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+impl State {
+    /// The state's value in Linux's `tcp_info.tcpi_state`.
+    pub fn linux_state(&self) -> u8 {
+        match self {
+            Self::Established => 1,
+            Self::SynSent => 2,
+            Self::SynReceived => 3,
+            Self::FinWait1 => 4,
+            Self::FinWait2 => 5,
+            Self::TimeWait => 6,
+            Self::Closed => 7,
+            Self::CloseWait => 8,
+            Self::LastAck => 9,
+            Self::Closing => 11,
+        }
+    }
+
+    /// Have we received the peer's SYN and had our SYN acknowledged?
+    fn is_synchronized(&self) -> bool {
+        !matches!(self, Self::SynSent | Self::SynReceived | Self::Closed)
+    }
+
+    /// Can we receive data in this state?
+    fn can_recv(&self) -> bool {
+        matches!(self, Self::Established | Self::FinWait1 | Self::FinWait2)
+    }
+
+    /// Can we send data in this state (ignoring any data that has already been sent)?
+    fn can_send(&self) -> bool {
+        matches!(
+            self,
+            Self::Established | Self::CloseWait | Self::FinWait1 | Self::Closing | Self::LastAck
+        )
+    }
+}
+
+/// Keepalive options (`TCP_KEEPIDLE`, `TCP_KEEPINTVL` and `TCP_KEEPCNT`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    pub idle: SimulationTime,
+    pub interval: SimulationTime,
+    pub count: u32,
+}
+
+impl Default for KeepaliveConfig {
+    /// Linux's default `tcp_keepalive_time`, `tcp_keepalive_intvl` and `tcp_keepalive_probes`.
+    fn default() -> Self {
+        Self {
+            idle: SimulationTime::from_secs(7200),
+            interval: SimulationTime::from_secs(75),
+            count: 9,
+        }
+    }
+}
+
+/// Loss recovery state ([RFC 6675](https://www.rfc-editor.org/rfc/rfc6675)).
+#[derive(Copy, Clone, Debug)]
+struct Recovery {
+    /// Recovery ends when this sequence number is acknowledged.
+    recover: Seq,
+    /// Holes before this sequence number have already been retransmitted.
+    rexmit_next: Seq,
+    /// Holes before this sequence number are considered lost.
+    lost_end: Seq,
+    /// Retransmit the first hole immediately, regardless of the congestion window.
+    fast_rexmit: bool,
+}
+
+/// The state machine of a single TCP connection. This has no knowledge of sockets, the network, or
+/// the event queue; the socket passes in received segments and the current time, and pulls out
+/// segments to send.
+#[derive(Debug)]
+pub struct Connection {
+    state: State,
+    local_addr: SocketAddrV4,
+    peer_addr: SocketAddrV4,
+    /// The maximum segment size.
+    mss: u32,
+    /// Disables Nagle's algorithm (`TCP_NODELAY`).
+    nodelay: bool,
+    keepalive: Option<KeepaliveConfig>,
+    /// The application has closed the socket, so nothing will read from the receive buffer.
+    orphaned: bool,
+
+    /// Our initial sequence number.
+    iss: Seq,
+    /// The oldest unacknowledged sequence number.
+    snd_una: Seq,
+    /// The next sequence number to send. This is moved back after a retransmission timeout.
+    snd_nxt: Seq,
+    /// The sequence number following the highest sequence number sent.
+    snd_max: Seq,
+    /// The peer's receive window.
+    snd_wnd: u32,
+    /// The segment sequence number used for the last window update.
+    snd_wl1: Seq,
+    /// The segment acknowledgement number used for the last window update.
+    snd_wl2: Seq,
+    send_buf: SendBuffer,
+    /// The application has finished writing, so a FIN follows the data in the send buffer.
+    fin_queued: bool,
+    /// We need to send (or resend) a SYN.
+    syn_pending: bool,
+    /// We need to send a RST.
+    rst_pending: bool,
+    /// We need to send a probe (a zero window probe or keepalive probe).
+    probe_pending: bool,
+
+    recv_buf: RecvBuffer,
+    /// The sequence number of the peer's FIN, which may have been received out of order.
+    peer_fin: Option<Seq>,
+    /// We have received all data up to and including the peer's FIN.
+    fin_received: bool,
+    /// We need to send an ACK immediately.
+    ack_now: bool,
+    /// When we need to send a delayed ACK.
+    delayed_ack: Option<EmulatedTime>,
+    /// Segments received since we last sent an ACK.
+    segments_since_ack: u32,
+    /// The receive window that we last advertised.
+    advertised_window: u32,
+
+    cwnd: u32,
+    ssthresh: u32,
+    dup_acks: u32,
+    recovery: Option<Recovery>,
+    /// The blocks that the peer has selectively acknowledged, sorted and non-overlapping.
+    sacked: Vec<(Seq, Seq)>,
+
+    srtt: Option<SimulationTime>,
+    rttvar: SimulationTime,
+    rto: SimulationTime,
+    /// A sequence number and the time that it was sent, used to measure the RTT. Following
+    /// Karn's algorithm, retransmitted segments are never measured.
+    rtt_probe: Option<(Seq, EmulatedTime)>,
+    rexmit_deadline: Option<EmulatedTime>,
+    /// The number of consecutive retransmission timeouts.
+    rexmit_count: u32,
+    /// The total number of retransmitted segments.
+    total_rexmits: u32,
+
+    persist_deadline: Option<EmulatedTime>,
+    persist_backoff: u32,
+
+    last_recv_time: EmulatedTime,
+    keepalive_probes: u32,
+
+    /// When the TIME_WAIT state (or an orphaned FIN_WAIT_2 state) ends.
+    close_deadline: Option<EmulatedTime>,
+    /// An error that hasn't yet been reported to the application.
+    error: Option<Errno>,
+}
+
+impl Connection {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        state: State,
+        now: EmulatedTime,
+        local_addr: SocketAddrV4,
+        peer_addr: SocketAddrV4,
+        iss: Seq,
+        mss: u32,
+        send_buf_size: usize,
+        recv_buf_size: usize,
+    ) -> Self {
+        Self {
+            state,
+            local_addr,
+            peer_addr,
+            mss,
+            nodelay: false,
+            keepalive: None,
+            orphaned: false,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_max: iss,
+            snd_wnd: 0,
+            snd_wl1: Seq::new(0),
+            snd_wl2: iss,
+            send_buf: SendBuffer::new(iss + 1, send_buf_size),
+            fin_queued: false,
+            syn_pending: true,
+            rst_pending: false,
+            probe_pending: false,
+            recv_buf: RecvBuffer::new(Seq::new(0), recv_buf_size),
+            peer_fin: None,
+            fin_received: false,
+            ack_now: false,
+            delayed_ack: None,
+            segments_since_ack: 0,
+            advertised_window: 0,
+            cwnd: INIT_CWND_SEGMENTS * mss,
+            ssthresh: u32::MAX,
+            dup_acks: 0,
+            recovery: None,
+            sacked: Vec::new(),
+            srtt: None,
+            rttvar: SimulationTime::ZERO,
+            rto: RTO_INIT,
+            rtt_probe: None,
+            rexmit_deadline: None,
+            rexmit_count: 0,
+            total_rexmits: 0,
+            persist_deadline: None,
+            persist_backoff: 0,
+            last_recv_time: now,
+            keepalive_probes: 0,
+            close_deadline: None,
+            error: None,
+        }
+    }
+
+    /// A connection that will send a SYN to the peer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_active(
+        now: EmulatedTime,
+        local_addr: SocketAddrV4,
+        peer_addr: SocketAddrV4,
+        iss: Seq,
+        mss: u32,
+        send_buf_size: usize,
+        recv_buf_size: usize,
+    ) -> Self {
+        Self::new(
+            State::SynSent,
+            now,
+            local_addr,
+            peer_addr,
+            iss,
+            mss,
+            send_buf_size,
+            recv_buf_size,
+        )
+    }
+
+    /// A connection that will respond to a SYN received by a listening socket.
+    pub fn new_passive(
+        now: EmulatedTime,
+        syn: &TcpHeader,
+        iss: Seq,
+        mss: u32,
+        send_buf_size: usize,
+        recv_buf_size: usize,
+    ) -> Self {
+        debug_assert!(syn.flags.contains(TcpFlags::SYN));
+        debug_assert!(!syn.flags.contains(TcpFlags::ACK));
+
+        let mut conn = Self::new(
+            State::SynReceived,
+            now,
+            syn.dst,
+            syn.src,
+            iss,
+            mss,
+            send_buf_size,
+            recv_buf_size,
+        );
+
+        conn.recv_buf = RecvBuffer::new(Seq::new(syn.seq) + 1, recv_buf_size);
+        conn.snd_wnd = syn.window;
+        conn.snd_wl1 = Seq::new(syn.seq);
+        conn
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.local_addr
+    }
+
+    pub fn peer_addr(&self) -> SocketAddrV4 {
+        self.peer_addr
+    }
+
+    pub fn mss(&self) -> u32 {
+        self.mss
+    }
+
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) {
+        self.keepalive = keepalive;
+        self.keepalive_probes = 0;
+    }
+
+    pub fn send_buf(&self) -> &SendBuffer {
+        &self.send_buf
+    }
+
+    pub fn set_send_buf_size(&mut self, size: usize) {
+        self.send_buf.set_capacity(size);
+    }
+
+    pub fn recv_buf(&self) -> &RecvBuffer {
+        &self.recv_buf
+    }
+
+    pub fn set_recv_buf_size(&mut self, size: usize) {
+        self.recv_buf.set_capacity(size);
+    }
+
+    /// The error that the application should be notified of, if any. The error is cleared.
+    pub fn take_error(&mut self) -> Option<Errno> {
+        self.error.take()
+    }
+
+    /// The error that the application should be notified of, if any. The error is not cleared.
+    pub fn error(&self) -> Option<Errno> {
+        self.error
+    }
+
+    pub fn has_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// The peer will never send more data.
+    pub fn is_recv_finished(&self) -> bool {
+        self.fin_received || self.state == State::Closed
+    }
+
+    /// The application can add more data to the send buffer.
+    pub fn can_send(&self) -> bool {
+        matches!(self.state, State::Established | State::CloseWait) && !self.fin_queued
+    }
+
+    pub fn cwnd(&self) -> u32 {
+        self.cwnd
+    }
+
+    pub fn ssthresh(&self) -> u32 {
+        self.ssthresh
+    }
+
+    pub fn srtt(&self) -> Option<SimulationTime> {
+        self.srtt
+    }
+
+    pub fn rttvar(&self) -> SimulationTime {
+        self.rttvar
+    }
+
+    pub fn rto(&self) -> SimulationTime {
+        self.rto
+    }
+
+    pub fn total_rexmits(&self) -> u32 {
+        self.total_rexmits
+    }
+
+    /// The number of sequence numbers that have been sent but not acknowledged.
+    pub fn unacked(&self) -> u32 {
+        self.snd_max.since(self.snd_una)
+    }
+
+    /// Add data to the send buffer. The caller should check
+    /// [`send_buf().space()`](SendBuffer::space) first.
+    pub fn send(&mut self, bytes: Bytes) {
+        assert!(self.can_send());
+        self.send_buf.push(bytes);
+    }
+
+    /// Read data from the receive buffer. See [`RecvBuffer::read`].
+    pub fn recv(
+        &mut self,
+        writer: impl Write,
+        max_len: usize,
+        peek: bool,
+    ) -> std::io::Result<usize> {
+        let old_window = self.recv_buf.window();
+        let len = self.recv_buf.read(writer, max_len, peek)?;
+
+        // send a window update if the window has opened significantly
+        // ([RFC 1122 4.2.3.3](https://www.rfc-editor.org/rfc/rfc1122#page-97))
+        let new_window = self.recv_buf.window();
+        let threshold = std::cmp::min(
+            u32::try_from(self.recv_buf.capacity() / 2).unwrap_or(u32::MAX),
+            2 * self.mss,
+        );
+        if self.state.can_recv()
+            && new_window > old_window
+            && new_window - self.advertised_window.min(new_window) >= threshold
+        {
+            self.ack_now = true;
+        }
+
+        Ok(len)
+    }
+
+    /// The application won't send any more data (`shutdown(SHUT_WR)`).
+    pub fn shutdown_write(&mut self) {
+        match self.state {
+            State::SynSent => self.set_closed(),
+            State::SynReceived | State::Established => {
+                self.fin_queued = true;
+                self.state = State::FinWait1;
+            }
+            State::CloseWait => {
+                self.fin_queued = true;
+                self.state = State::LastAck;
+            }
+            _ => {}
+        }
+    }
+
+    /// The application closed the socket. The connection will continue in the background until it
+    /// has been closed gracefully.
+    pub fn close(&mut self, now: EmulatedTime) {
+        self.orphaned = true;
+        self.shutdown_write();
+
+        if self.state == State::FinWait2 {
+            self.close_deadline.get_or_insert(now + TIME_WAIT);
+        }
+    }
+
+    /// Close the connection immediately, sending a RST if the peer might still have state for the
+    /// connection.
+    pub fn abort(&mut self) {
+        if self.state != State::Closed && self.state != State::TimeWait {
+            self.rst_pending = self.state != State::SynSent;
+        }
+        self.set_closed();
+    }
+
+    fn set_closed(&mut self) {
+        self.state = State::Closed;
+        self.syn_pending = false;
+        self.probe_pending = false;
+        self.ack_now = false;
+        self.delayed_ack = None;
+        self.rexmit_deadline = None;
+        self.persist_deadline = None;
+        self.close_deadline = None;
+    }
+
+    fn enter_time_wait(&mut self, now: EmulatedTime) {
+        self.state = State::TimeWait;
+        self.rexmit_deadline = None;
+        self.persist_deadline = None;
+        self.close_deadline = Some(now + TIME_WAIT);
+    }
+
+    /// The next sequence number that we expect to receive.
+    fn rcv_nxt(&self) -> Seq {
+        self.recv_buf.next_seq()
+    }
+
+    /// Has the peer acknowledged our FIN?
+    fn fin_acked(&self) -> bool {
+        self.fin_queued && self.snd_una == self.send_buf.end_seq() + 1
+    }
+
+    fn sacked_between(&self, start: Seq, end: Seq) -> u32 {
+        self.sacked
+            .iter()
+            .filter(|b| b.1 > start && b.0 < end)
+            .map(|b| b.1.min(end).since(b.0.max(start)))
+            .sum()
+    }
+
+    /// The number of bytes that we estimate are still in the network
+    /// ([RFC 6675](https://www.rfc-editor.org/rfc/rfc6675) "pipe").
+    fn pipe(&self) -> u32 {
+        if self.snd_nxt <= self.snd_una {
+            return 0;
+        }
+
+        let mut pipe = self.snd_nxt.since(self.snd_una);
+        pipe -= self.sacked_between(self.snd_una, self.snd_nxt);
+
+        if let Some(rec) = self.recovery {
+            // holes that are lost and haven't been retransmitted yet
+            let start = rec.rexmit_next.max(self.snd_una);
+            let end = rec.lost_end.min(self.snd_nxt);
+            if start < end {
+                pipe -= end.since(start) - self.sacked_between(start, end);
+            }
+        }
+
+        pipe
+    }
+
+    fn header(&mut self, mut flags: TcpFlags, seq: Seq) -> TcpHeader {
+        let window = self.recv_buf.window();
+        let mut ack = 0;
+        let mut sack = Vec::new();
+
+        if self.state != State::SynSent || flags.contains(TcpFlags::RST) {
+            flags.insert(TcpFlags::ACK);
+            ack = self.rcv_nxt().get();
+            sack = self
+                .recv_buf
+                .sack_blocks(MAX_SACK_BLOCKS)
+                .into_iter()
+                .map(|(a, b)| (a.get(), b.get()))
+                .collect();
+            if !sack.is_empty() {
+                flags.insert(TcpFlags::SACK);
+            }
+
+            self.ack_now = false;
+            self.delayed_ack = None;
+            self.segments_since_ack = 0;
+            self.advertised_window = window;
+        }
+
+        TcpHeader {
+            src: self.local_addr,
+            dst: self.peer_addr,
+            flags,
+            seq: seq.get(),
+            ack,
+            window,
+            sack,
+        }
+    }
+
+    fn arm_rexmit_timer(&mut self, now: EmulatedTime) {
+        if self.rexmit_deadline.is_none() {
+            self.rexmit_deadline = Some(now + self.rto);
+        }
+    }
+
+    /// Get the next segment to send, if any. This should be called repeatedly until it returns
+    /// `None`, and after every other call that modifies the connection.
+    pub fn next_segment(&mut self, now: EmulatedTime) -> Option<(TcpHeader, Bytes)> {
+        if self.rst_pending {
+            self.rst_pending = false;
+            let header = self.header(TcpFlags::RST, self.snd_max);
+            return Some((header, Bytes::new()));
+        }
+
+        if self.state == State::Closed {
+            return None;
+        }
+
+        if self.syn_pending {
+            self.syn_pending = false;
+
+            let flags = match self.state {
+                State::SynSent => TcpFlags::SYN,
+                State::SynReceived => TcpFlags::SYN | TcpFlags::ACK,
+                _ => unreachable!(),
+            };
+
+            self.snd_nxt = self.iss + 1;
+            self.snd_max = self.snd_max.max(self.snd_nxt);
+            if self.rexmit_count == 0 {
+                self.rtt_probe = Some((self.snd_nxt, now));
+            }
+            self.arm_rexmit_timer(now);
+
+            let header = self.header(flags, self.iss);
+            return Some((header, Bytes::new()));
+        }
+
+        if self.state.can_send() {
+            if let Some(segment) = self.next_data_segment(now) {
+                return Some(segment);
+            }
+        }
+
+        if self.probe_pending {
+            self.probe_pending = false;
+            // an old sequence number, so the peer will respond with an ACK
+            let header = self.header(TcpFlags::ACK, self.snd_una - 1);
+            return Some((header, Bytes::new()));
+        }
+
+        if self.ack_now && self.state != State::SynSent && self.state != State::SynReceived {
+            let header = self.header(TcpFlags::ACK, self.snd_max);
+            return Some((header, Bytes::new()));
+        }
+
+        None
+    }
+
+    /// Build a segment starting at `seq` with up to `max_len` bytes of data, and a FIN if `seq` is
+    /// the end of the data and a FIN is queued.
+    fn data_segment(&mut self, seq: Seq, max_len: u32) -> (TcpHeader, Bytes, Seq) {
+        let data_end = self.send_buf.end_seq();
+        let len = if seq < data_end {
+            std::cmp::min(max_len, data_end.since(seq))
+        } else {
+            0
+        };
+
+        let data = self.send_buf.get(seq, len.try_into().unwrap());
+        let mut end = seq + len;
+
+        let mut flags = TcpFlags::ACK;
+        if self.fin_queued && end == data_end {
+            flags.insert(TcpFlags::FIN);
+            end += 1;
+        }
+
+        (self.header(flags, seq), data, end)
+    }
+
+    fn next_data_segment(&mut self, now: EmulatedTime) -> Option<(TcpHeader, Bytes)> {
+        // retransmit lost segments first
+        if let Some((seq, len)) = self.next_hole() {
+            let rec = self.recovery.as_mut().unwrap();
+            let fast_rexmit = rec.fast_rexmit;
+
+            if fast_rexmit || self.pipe() + len <= self.cwnd {
+                let (header, data, end) = self.data_segment(seq, len);
+
+                let rec = self.recovery.as_mut().unwrap();
+                rec.fast_rexmit = false;
+                rec.rexmit_next = end;
+
+                self.total_rexmits += 1;
+                self.rtt_probe = None;
+                self.arm_rexmit_timer(now);
+                return Some((header, data));
+            }
+
+            return None;
+        }
+
+        // after a retransmission timeout, skip any data that the peer already has
+        while let Some(end) = self
+            .sacked
+            .iter()
+            .find(|b| self.snd_nxt.in_range(b.0, b.1))
+            .map(|b| b.1)
+        {
+            self.snd_nxt = end;
+        }
+
+        let data_end = self.send_buf.end_seq();
+        let fin_end = data_end + u32::from(self.fin_queued);
+
+        if self.snd_nxt >= fin_end {
+            // nothing left to send
+            return None;
+        }
+
+        let available = if self.snd_nxt < data_end {
+            data_end.since(self.snd_nxt)
+        } else {
+            0
+        };
+
+        let window_end = self.snd_una + self.snd_wnd;
+        let window = if self.snd_nxt < window_end {
+            window_end.since(self.snd_nxt)
+        } else {
+            0
+        };
+
+        let mut len = [
+            self.mss,
+            available,
+            window,
+            self.cwnd.saturating_sub(self.pipe()),
+        ]
+        .into_iter()
+        .min()
+        .unwrap();
+
+        // don't resend data that the peer already has
+        if let Some(block) = self.sacked.iter().find(|b| b.0 > self.snd_nxt) {
+            len = std::cmp::min(len, block.0.since(self.snd_nxt));
+        }
+
+        let is_rexmit = self.snd_nxt < self.snd_max;
+        let sends_fin = self.fin_queued && self.snd_nxt + len == data_end;
+
+        if len == 0 && !sends_fin {
+            if window == 0 && available > 0 && self.snd_una == self.snd_max {
+                // the peer's window is closed and nothing is in flight, so we need to probe the
+                // window
+                self.persist_deadline.get_or_insert_with(|| {
+                    let backoff = self.rto.saturating_mul(1 << self.persist_backoff.min(16));
+                    now + std::cmp::min(backoff, RTO_MAX)
+                });
+            }
+            return None;
+        }
+
+        // Nagle's algorithm: don't send a small segment while there is unacknowledged data
+        if !self.nodelay
+            && !is_rexmit
+            && !sends_fin
+            && len < self.mss
+            && len == available
+            && self.snd_max > self.snd_una
+        {
+            return None;
+        }
+
+        let seq = self.snd_nxt;
+        let (header, data, end) = self.data_segment(seq, len);
+
+        self.snd_nxt = end;
+        if end > self.snd_max {
+            self.snd_max = end;
+            if self.rtt_probe.is_none() && !is_rexmit {
+                self.rtt_probe = Some((end, now));
+            }
+        }
+        if is_rexmit {
+            self.total_rexmits += 1;
+            self.rtt_probe = None;
+        }
+        self.arm_rexmit_timer(now);
+
+        Some((header, data))
+    }
+
+    /// The next lost sequence range that should be retransmitted during loss recovery.
+    fn next_hole(&self) -> Option<(Seq, u32)> {
+        let rec = self.recovery?;
+
+        let mut start = rec.rexmit_next.max(self.snd_una);
+        while let Some(end) = self
+            .sacked
+            .iter()
+            .find(|b| start.in_range(b.0, b.1))
+            .map(|b| b.1)
+        {
+            start = end;
+        }
+
+        let end = rec.lost_end.min(self.snd_max);
+        if start >= end {
+            return None;
+        }
+
+        let mut len = std::cmp::min(end.since(start), self.mss);
+        if let Some(block) = self.sacked.iter().find(|b| b.0 > start) {
+            len = std::cmp::min(len, block.0.since(start));
+        }
+
+        Some((start, len))
+    }
+
+    /// Process a segment received from the peer.
+    pub fn on_segment(&mut self, now: EmulatedTime, header: &TcpHeader, payload: Bytes) {
+        if self.state == State::Closed {
+            return;
+        }
+
+        self.last_recv_time = now;
+        self.keepalive_probes = 0;
+
+        if self.state == State::SynSent {
+            self.on_segment_syn_sent(now, header);
+            return;
+        }
+
+        let seq = Seq::new(header.seq);
+        let flags = header.flags;
+        let payload_len = u32::try_from(payload.len()).unwrap();
+
+        // is any part of the segment within the receive window?
+        let rcv_nxt = self.rcv_nxt();
+        let window_end = rcv_nxt + self.recv_buf.window();
+        let acceptable = if payload_len == 0 {
+            rcv_nxt <= seq && seq <= window_end
+        } else {
+            seq + payload_len > rcv_nxt && seq <= window_end
+        };
+
+        if flags.contains(TcpFlags::RST) {
+            if acceptable {
+                self.error = match self.state {
+                    // the peer rejected our SYN-ACK
+                    State::SynReceived | State::TimeWait => None,
+                    _ => Some(Errno::ECONNRESET),
+                };
+                self.set_closed();
+            }
+            return;
+        }
+
+        if flags.contains(TcpFlags::SYN) {
+            if self.state == State::SynReceived && seq + 1 == rcv_nxt {
+                // the peer retransmitted its SYN, so it didn't receive our SYN-ACK
+                self.syn_pending = true;
+            } else {
+                // a challenge ACK ([RFC 5961](https://www.rfc-editor.org/rfc/rfc5961))
+                self.ack_now = true;
+            }
+            return;
+        }
+
+        if !acceptable {
+            // respond to old segments (including keepalive and window probes) with an ACK
+            self.ack_now = true;
+            if self.state == State::TimeWait && flags.contains(TcpFlags::FIN) {
+                // the peer didn't receive our ACK of its FIN
+                self.close_deadline = Some(now + TIME_WAIT);
+            }
+            return;
+        }
+
+        if !flags.contains(TcpFlags::ACK) {
+            return;
+        }
+
+        if self.state == State::SynReceived {
+            let ack = Seq::new(header.ack);
+            if !ack.in_range(self.snd_una + 1, self.snd_max + 1) {
+                return;
+            }
+            self.state = State::Established;
+            self.syn_pending = false;
+        }
+
+        self.on_ack(now, header, payload_len);
+
+        if !self.state.can_recv() {
+            return;
+        }
+
+        if payload_len > 0 {
+            if self.orphaned {
+                // the application closed the socket, so no one will read the data
+                self.abort();
+                return;
+            }
+            self.on_data(now, seq, payload);
+        }
+
+        if flags.contains(TcpFlags::FIN) && self.peer_fin.is_none() {
+            self.peer_fin = Some(seq + payload_len);
+        }
+
+        self.check_peer_fin(now);
+    }
+
+    fn on_segment_syn_sent(&mut self, now: EmulatedTime, header: &TcpHeader) {
+        let seq = Seq::new(header.seq);
+        let ack = Seq::new(header.ack);
+        let flags = header.flags;
+
+        let has_ack = flags.contains(TcpFlags::ACK);
+        if has_ack && ack != self.iss + 1 {
+            // not a response to our SYN
+            return;
+        }
+
+        if flags.contains(TcpFlags::RST) {
+            if has_ack {
+                self.error = Some(Errno::ECONNREFUSED);
+                self.set_closed();
+            }
+            return;
+        }
+
+        if !flags.contains(TcpFlags::SYN) {
+            return;
+        }
+
+        let capacity = self.recv_buf.capacity();
+        self.recv_buf = RecvBuffer::new(seq + 1, capacity);
+        self.snd_wnd = header.window;
+        self.snd_wl1 = seq;
+        self.snd_wl2 = ack;
+
+        if has_ack {
+            self.snd_una = ack;
+            self.on_rtt_ack(now, ack);
+            self.rexmit_deadline = None;
+            self.rexmit_count = 0;
+            self.state = State::Established;
+            self.syn_pending = false;
+            self.ack_now = true;
+        } else {
+            // simultaneous open
+            self.state = State::SynReceived;
+            self.syn_pending = true;
+        }
+    }
+
+    fn on_ack(&mut self, now: EmulatedTime, header: &TcpHeader, payload_len: u32) {
+        let seq = Seq::new(header.seq);
+        let ack = Seq::new(header.ack);
+
+        if ack > self.snd_max {
+            // acknowledges something that we haven't sent
+            self.ack_now = true;
+            return;
+        }
+
+        self.update_sacked(header);
+
+        if ack > self.snd_una {
+            self.on_new_ack(now, ack);
+        } else if ack == self.snd_una
+            && payload_len == 0
+            && !header.flags.contains(TcpFlags::FIN)
+            && self.snd_max > self.snd_una
+            && (header.window == self.snd_wnd || header.flags.contains(TcpFlags::SACK))
+        {
+            self.dup_acks += 1;
+            if self.recovery.is_none() && self.dup_acks >= DUP_ACK_THRESHOLD {
+                self.enter_recovery();
+            }
+        }
+
+        // update the send window
+        if self.snd_wl1 < seq || (self.snd_wl1 == seq && self.snd_wl2 <= ack) {
+            self.snd_wnd = header.window;
+            self.snd_wl1 = seq;
+            self.snd_wl2 = ack;
+
+            if self.snd_wnd > 0 {
+                self.persist_deadline = None;
+                self.persist_backoff = 0;
+            }
+        }
+
+        if self.fin_acked() {
+            match self.state {
+                State::FinWait1 => {
+                    self.state = State::FinWait2;
+                    if self.orphaned {
+                        // don't wait forever for the peer's FIN
+                        self.close_deadline = Some(now + TIME_WAIT);
+                    }
+                }
+                State::Closing => self.enter_time_wait(now),
+                State::LastAck => self.set_closed(),
+                _ => {}
+            }
+        }
+    }
+
+    fn on_new_ack(&mut self, now: EmulatedTime, ack: Seq) {
+        let acked = ack.since(self.snd_una);
+
+        self.send_buf.advance_to(ack.min(self.send_buf.end_seq()));
+        self.snd_una = ack;
+        self.snd_nxt = self.snd_nxt.max(ack);
+
+        self.sacked.retain(|b| b.1 > ack);
+        if let Some(block) = self.sacked.first_mut() {
+            block.0 = block.0.max(ack);
+        }
+
+        self.on_rtt_ack(now, ack);
+
+        self.rexmit_count = 0;
+        self.dup_acks = 0;
+        self.rexmit_deadline = (self.snd_una < self.snd_max).then(|| now + self.rto);
+
+        match self.recovery {
+            Some(rec) if ack >= rec.recover => {
+                self.recovery = None;
+                self.cwnd = self.ssthresh;
+            }
+            Some(mut rec) => {
+                // a partial ACK, so the next segment was probably also lost
+                rec.rexmit_next = rec.rexmit_next.max(ack);
+                rec.lost_end = rec.lost_end.max((ack + self.mss).min(self.snd_max));
+                self.recovery = Some(rec);
+            }
+            None if self.cwnd < self.ssthresh => {
+                // slow start
+                self.cwnd = self.cwnd.saturating_add(std::cmp::min(acked, self.mss));
+            }
+            None => {
+                // congestion avoidance
+                let inc = u64::from(self.mss) * u64::from(self.mss) / u64::from(self.cwnd);
+                let inc = u32::try_from(inc).unwrap_or(u32::MAX).max(1);
+                self.cwnd = self.cwnd.saturating_add(inc);
+            }
+        }
+    }
+
+    /// Enter fast retransmit and fast recovery.
+    fn enter_recovery(&mut self) {
+        self.ssthresh = std::cmp::max(self.pipe() / 2, 2 * self.mss);
+        self.cwnd = self.ssthresh;
+
+        // the holes before the highest SACK block are considered lost, and the first segment is
+        // always considered lost
+        let first_segment_end = (self.snd_una + self.mss).min(self.snd_max);
+        let lost_end = match self.sacked.last() {
+            Some(block) => block.0.max(first_segment_end),
+            None => first_segment_end,
+        };
+
+        self.recovery = Some(Recovery {
+            recover: self.snd_max,
+            rexmit_next: self.snd_una,
+            lost_end,
+            fast_rexmit: true,
+        });
+    }
+
+    fn update_sacked(&mut self, header: &TcpHeader) {
+        for &(start, end) in &header.sack {
+            let mut block = (Seq::new(start).max(self.snd_una), Seq::new(end));
+            if block.0 >= block.1 || block.1 > self.snd_max {
+                continue;
+            }
+
+            // merge any overlapping blocks
+            self.sacked.retain(|b| {
+                if b.1 < block.0 || b.0 > block.1 {
+                    return true;
+                }
+                block = (block.0.min(b.0), block.1.max(b.1));
+                false
+            });
+
+            let pos = self
+                .sacked
+                .iter()
+                .position(|b| b.0 > block.0)
+                .unwrap_or(self.sacked.len());
+            self.sacked.insert(pos, block);
+        }
+
+        if let (Some(rec), Some(block)) = (self.recovery.as_mut(), self.sacked.last()) {
+            rec.lost_end = rec.lost_end.max(block.0);
+        }
+    }
+
+    /// Update the RTT estimate and retransmission timeout
+    /// ([RFC 6298](https://www.rfc-editor.org/rfc/rfc6298)).
+    fn on_rtt_ack(&mut self, now: EmulatedTime, ack: Seq) {
+        let Some((seq, sent)) = self.rtt_probe else {
+            return;
+        };
+
+        if ack < seq {
+            return;
+        }
+
+        self.rtt_probe = None;
+        let rtt = now.saturating_duration_since(&sent);
+
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+
+        self.rto = (self.srtt.unwrap() + self.rttvar * 4).clamp(RTO_MIN, RTO_MAX);
+    }
+
+    fn on_data(&mut self, now: EmulatedTime, seq: Seq, mut payload: Bytes) {
+        if let Some(fin) = self.peer_fin {
+            // ignore any data after the peer's FIN
+            if seq >= fin {
+                return;
+            }
+            payload.truncate(fin.since(seq).try_into().unwrap());
+        }
+
+        let had_out_of_order = self.recv_buf.has_out_of_order();
+
+        if !self.recv_buf.insert(seq, payload) || had_out_of_order {
+            // send a duplicate ACK for out-of-order data, and an immediate ACK when a hole is
+            // filled ([RFC 5681 4.2](https://www.rfc-editor.org/rfc/rfc5681#section-4.2))
+            self.ack_now = true;
+            return;
+        }
+
+        // acknowledge at least every second segment, otherwise delay the ACK
+        self.segments_since_ack += 1;
+        if self.segments_since_ack >= 2 {
+            self.ack_now = true;
+        } else {
+            self.delayed_ack.get_or_insert(now + DELAYED_ACK);
+        }
+    }
+
+    fn check_peer_fin(&mut self, now: EmulatedTime) {
+        if self.fin_received || self.peer_fin != Some(self.recv_buf.next_seq()) {
+            return;
+        }
+
+        self.recv_buf.skip_seq();
+        self.fin_received = true;
+        self.ack_now = true;
+
+        match self.state {
+            State::Established => self.state = State::CloseWait,
+            State::FinWait1 => self.state = State::Closing,
+            State::FinWait2 => self.enter_time_wait(now),
+            _ => {}
+        }
+    }
+
+    fn keepalive_deadline(&self) -> Option<EmulatedTime> {
+        let config = self.keepalive?;
+
+        // only probe idle connections
+        if !self.state.is_synchronized()
+            || self.state == State::TimeWait
+            || self.snd_una != self.snd_max
+            || !self.send_buf.is_empty()
+        {
+            return None;
+        }
+
+        let probes = u64::from(self.keepalive_probes);
+        Some(self.last_recv_time + config.idle + config.interval * probes)
+    }
+
+    /// The next time that [`on_timer`](Self::on_timer) should be called.
+    pub fn next_deadline(&self) -> Option<EmulatedTime> {
+        [
+            self.rexmit_deadline,
+            self.delayed_ack,
+            self.persist_deadline,
+            self.keepalive_deadline(),
+            self.close_deadline,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Handle any timers that have expired.
+    pub fn on_timer(&mut self, now: EmulatedTime) {
+        if self.state == State::Closed {
+            return;
+        }
+
+        if self.close_deadline.is_some_and(|t| now >= t) {
+            self.set_closed();
+            return;
+        }
+
+        if self.rexmit_deadline.is_some_and(|t| now >= t) {
+            self.rexmit_deadline = None;
+            self.on_rexmit_timeout(now);
+            if self.state == State::Closed {
+                return;
+            }
+        }
+
+        if self.delayed_ack.is_some_and(|t| now >= t) {
+            self.delayed_ack = None;
+            self.ack_now = true;
+        }
+
+        if self.persist_deadline.is_some_and(|t| now >= t) {
+            // the next deadline will be set when the window is still closed after the probe
+            self.persist_deadline = None;
+            self.persist_backoff += 1;
+            self.probe_pending = true;
+        }
+
+        if self.keepalive_deadline().is_some_and(|t| now >= t) {
+            if self.keepalive_probes >= self.keepalive.unwrap().count {
+                self.abort();
+                self.error = Some(Errno::ETIMEDOUT);
+                return;
+            }
+            self.keepalive_probes += 1;
+            self.probe_pending = true;
+        }
+    }
+
+    fn on_rexmit_timeout(&mut self, now: EmulatedTime) {
+        self.rexmit_count += 1;
+        self.rto = std::cmp::min(self.rto * 2, RTO_MAX);
+        self.rtt_probe = None;
+
+        if matches!(self.state, State::SynSent | State::SynReceived) {
+            if self.rexmit_count > MAX_SYN_RETRIES {
+                self.error = Some(Errno::ETIMEDOUT);
+                self.set_closed();
+                return;
+            }
+            self.total_rexmits += 1;
+            self.syn_pending = true;
+            return;
+        }
+
+        if self.snd_una == self.snd_max {
+            return;
+        }
+
+        if self.rexmit_count > MAX_RETRIES {
+            self.error = Some(Errno::ETIMEDOUT);
+            self.set_closed();
+            return;
+        }
+
+        // go back and resend everything that hasn't been acknowledged
+        self.ssthresh = std::cmp::max(self.pipe() / 2, 2 * self.mss);
+        self.cwnd = self.mss;
+        self.recovery = None;
+        self.dup_acks = 0;
+        self.sacked.clear();
+        self.snd_nxt = self.snd_una;
+        self.rexmit_deadline = Some(now + self.rto);
+    }
+}
+
+/// The RST to send in response to a segment that doesn't belong to any connection, if any
+/// ([RFC 793 3.4](https://www.rfc-editor.org/rfc/rfc793#page-36)).
+pub fn reset_for(header: &TcpHeader, payload_len: u32) -> Option<TcpHeader> {
+    // never respond to a RST
+    if header.flags.contains(TcpFlags::RST) {
+        return None;
+    }
+
+    let (flags, seq, ack) = if header.flags.contains(TcpFlags::ACK) {
+        (TcpFlags::RST, header.ack, 0)
+    } else {
+        let mut seg_len = payload_len;
+        if header.flags.contains(TcpFlags::SYN) {
+            seg_len += 1;
+        }
+        if header.flags.contains(TcpFlags::FIN) {
+            seg_len += 1;
+        }
+        (
+            TcpFlags::RST | TcpFlags::ACK,
+            0,
+            header.seq.wrapping_add(seg_len),
+        )
+    };
+
+    Some(TcpHeader {
+        src: header.dst,
+        dst: header.src,
+        flags,
+        seq,
+        ack,
+        window: 0,
+        sack: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const MSS: u32 = 1000;
+
+    fn time(millis: u64) -> EmulatedTime {
+        EmulatedTime::SIMULATION_START + SimulationTime::from_millis(millis)
+    }
+
+    fn client_addr() -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::new(11, 0, 0, 1), 40000)
+    }
+
+    fn server_addr() -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::new(11, 0, 0, 2), 80)
+    }
+
+    /// Get all of the segments that the connection wants to send.
+    fn segments(conn: &mut Connection, now: EmulatedTime) -> Vec<(TcpHeader, Bytes)> {
+        std::iter::from_fn(|| conn.next_segment(now)).collect()
+    }
+
+    /// Deliver all of the segments from `from` to `to`. Returns the number of segments.
+    fn deliver(from: &mut Connection, to: &mut Connection, now: EmulatedTime) -> usize {
+        let segments = segments(from, now);
+        let num = segments.len();
+        for (header, payload) in segments {
+            to.on_segment(now, &header, payload);
+        }
+        num
+    }
+
+    fn read_all(conn: &mut Connection) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let len = conn.recv_buf().readable_len();
+        assert_eq!(conn.recv(&mut buf, len, false).unwrap(), len);
+        buf
+    }
+
+    /// Perform the handshake and return the client and server connections.
+    fn connect(
+        client_iss: u32,
+        server_iss: u32,
+        server_recv_buf: usize,
+    ) -> (Connection, Connection) {
+        let now = time(0);
+        let mut client = Connection::new_active(
+            now,
+            client_addr(),
+            server_addr(),
+            Seq::new(client_iss),
+            MSS,
+            100_000,
+            100_000,
+        );
+
+        let syn = segments(&mut client, now);
+        assert_eq!(syn.len(), 1);
+        assert_eq!(syn[0].0.flags, TcpFlags::SYN);
+        assert_eq!(client.state(), State::SynSent);
+
+        let mut server = Connection::new_passive(
+            now,
+            &syn[0].0,
+            Seq::new(server_iss),
+            MSS,
+            100_000,
+            server_recv_buf,
+        );
+        assert_eq!(server.state(), State::SynReceived);
+
+        // SYN-ACK
+        assert_eq!(deliver(&mut server, &mut client, now), 1);
+        assert_eq!(client.state(), State::Established);
+
+        // ACK
+        assert_eq!(deliver(&mut client, &mut server, now), 1);
+        assert_eq!(server.state(), State::Established);
+
+        (client, server)
+    }
+
+    #[test]
+    fn test_handshake() {
+        let (client, server) = connect(u32::MAX - 10, 5, 100_000);
+        assert_eq!(client.local_addr(), server.peer_addr());
+        assert_eq!(client.peer_addr(), server.local_addr());
+        assert_eq!(client.next_deadline(), None);
+        assert_eq!(server.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_syn_retransmit() {
+        let now = time(0);
+        let mut client = Connection::new_active(
+            now,
+            client_addr(),
+            server_addr(),
+            Seq::new(1),
+            MSS,
+            100_000,
+            100_000,
+        );
+        assert_eq!(segments(&mut client, now).len(), 1);
+
+        let mut deadline = time(1000);
+        for _ in 0..MAX_SYN_RETRIES {
+            assert_eq!(client.next_deadline(), Some(deadline));
+            client.on_timer(deadline);
+            let syn = segments(&mut client, deadline);
+            assert_eq!(syn.len(), 1);
+            assert_eq!(syn[0].0.flags, TcpFlags::SYN);
+            deadline = deadline + client.rto();
+        }
+
+        client.on_timer(deadline);
+        assert_eq!(client.state(), State::Closed);
+        assert_eq!(client.take_error(), Some(Errno::ETIMEDOUT));
+    }
+
+    #[test]
+    fn test_transfer() {
+        let (mut client, mut server) = connect(100, 200, 100_000);
+        client.set_nodelay(true);
+
+        let data: Vec<u8> = (0..5000u32).map(|x| x as u8).collect();
+        client.send(Bytes::from(data.clone()));
+
+        let now = time(10);
+        assert_eq!(deliver(&mut client, &mut server, now), 5);
+        assert_eq!(read_all(&mut server), data);
+
+        assert_eq!(deliver(&mut server, &mut client, now), 1);
+        assert_eq!(client.unacked(), 0);
+        assert!(client.send_buf().is_empty());
+        assert_eq!(client.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_close() {
+        let (mut client, mut server) = connect(1, 2, 100_000);
+
+        client.shutdown_write();
+        assert_eq!(client.state(), State::FinWait1);
+        assert_eq!(deliver(&mut client, &mut server, time(0)), 1);
+        assert_eq!(server.state(), State::CloseWait);
+        assert!(server.is_recv_finished());
+
+        assert_eq!(deliver(&mut server, &mut client, time(0)), 1);
+        assert_eq!(client.state(), State::FinWait2);
+
+        server.close(time(0));
+        assert_eq!(server.state(), State::LastAck);
+        assert_eq!(deliver(&mut server, &mut client, time(0)), 1);
+        assert_eq!(client.state(), State::TimeWait);
+        assert!(client.is_recv_finished());
+
+        assert_eq!(deliver(&mut client, &mut server, time(0)), 1);
+        assert_eq!(server.state(), State::Closed);
+        assert_eq!(server.take_error(), None);
+
+        assert_eq!(client.next_deadline(), Some(time(60_000)));
+        client.on_timer(time(60_000));
+        assert_eq!(client.state(), State::Closed);
+        assert_eq!(client.take_error(), None);
+    }
+
+    #[test]
+    fn test_retransmit_timeout() {
+        let (mut client, mut server) = connect(1, 2, 100_000);
+        client.set_nodelay(true);
+
+        client.send(Bytes::from_static(b"hello"));
+        let lost = segments(&mut client, time(0));
+        assert_eq!(lost.len(), 1);
+
+        let deadline = client.next_deadline().unwrap();
+        assert_eq!(deadline, time(0) + RTO_MIN);
+        client.on_timer(deadline);
+        assert_eq!(client.rto(), RTO_MIN * 2);
+
+        let rexmit = segments(&mut client, deadline);
+        assert_eq!(rexmit.len(), 1);
+        assert_eq!(rexmit[0].0.seq, lost[0].0.seq);
+        assert_eq!(&rexmit[0].1[..], b"hello");
+        assert_eq!(client.total_rexmits(), 1);
+
+        server.on_segment(deadline, &rexmit[0].0, rexmit[0].1.clone());
+        assert_eq!(read_all(&mut server), b"hello");
+    }
+
+    #[test]
+    fn test_fast_retransmit() {
+        let (mut client, mut server) = connect(1000, 2000, 100_000);
+        client.set_nodelay(true);
+
+        let data = vec![7u8; 5 * MSS as usize];
+        client.send(Bytes::from(data.clone()));
+
+        let now = time(0);
+        let mut sent = segments(&mut client, now);
+        assert_eq!(sent.len(), 5);
+
+        // lose the first segment
+        let lost = sent.remove(0);
+
+        // each out-of-order segment is acknowledged immediately with a SACK block
+        let mut acks = Vec::new();
+        for (header, payload) in sent {
+            server.on_segment(now, &header, payload);
+            acks.extend(segments(&mut server, now));
+        }
+        assert_eq!(acks.len(), 4);
+        for (header, _) in &acks {
+            assert_eq!(header.ack, lost.0.seq);
+            assert!(header.flags.contains(TcpFlags::SACK));
+        }
+        assert_eq!(acks[3].0.sack, [(lost.0.seq + MSS, lost.0.seq + 5 * MSS)]);
+
+        for (header, payload) in acks {
+            client.on_segment(now, &header, payload);
+        }
+
+        // only the lost segment is retransmitted
+        let rexmit = segments(&mut client, now);
+        assert_eq!(rexmit.len(), 1);
+        assert_eq!(rexmit[0].0.seq, lost.0.seq);
+        assert_eq!(client.total_rexmits(), 1);
+        assert_eq!(client.ssthresh(), 2 * MSS);
+
+        server.on_segment(now, &rexmit[0].0, rexmit[0].1.clone());
+        assert_eq!(read_all(&mut server), data);
+
+        assert_eq!(deliver(&mut server, &mut client, now), 1);
+        assert_eq!(client.unacked(), 0);
+        assert_eq!(client.cwnd(), client.ssthresh());
+    }
+
+    #[test]
+    fn test_nagle() {
+        let (mut client, mut server) = connect(1, 2, 100_000);
+
+        client.send(Bytes::from_static(b"a"));
+        assert_eq!(deliver(&mut client, &mut server, time(0)), 1);
+
+        // the second small segment waits for the first to be acknowledged
+        client.send(Bytes::from_static(b"b"));
+        assert_eq!(segments(&mut client, time(0)).len(), 0);
+
+        // the server delays its ACK
+        assert_eq!(segments(&mut server, time(0)).len(), 0);
+        let deadline = server.next_deadline().unwrap();
+        assert_eq!(deadline, time(0) + DELAYED_ACK);
+        server.on_timer(deadline);
+
+        assert_eq!(deliver(&mut server, &mut client, deadline), 1);
+        assert_eq!(deliver(&mut client, &mut server, deadline), 1);
+        assert_eq!(read_all(&mut server), b"ab");
+
+        // small segments are sent immediately with TCP_NODELAY
+        client.set_nodelay(true);
+        client.send(Bytes::from_static(b"c"));
+        assert_eq!(segments(&mut client, deadline).len(), 1);
+    }
+
+    #[test]
+    fn test_zero_window() {
+        let (mut client, mut server) = connect(1, 2, 2 * MSS as usize);
+        client.set_nodelay(true);
+
+        client.send(Bytes::from(vec![1u8; 3 * MSS as usize]));
+        assert_eq!(deliver(&mut client, &mut server, time(0)), 2);
+        assert_eq!(deliver(&mut server, &mut client, time(0)), 1);
+
+        // the window is closed, so the client probes it
+        assert_eq!(segments(&mut client, time(0)).len(), 0);
+        let deadline = client.next_deadline().unwrap();
+        assert_eq!(deadline, time(0) + RTO_MIN);
+        client.on_timer(deadline);
+        assert_eq!(deliver(&mut client, &mut server, deadline), 1);
+        assert_eq!(deliver(&mut server, &mut client, deadline), 1);
+
+        // reading opens the window
+        assert_eq!(read_all(&mut server).len(), 2 * MSS as usize);
+        assert_eq!(deliver(&mut server, &mut client, deadline), 1);
+        assert_eq!(deliver(&mut client, &mut server, deadline), 1);
+        assert_eq!(server.recv_buf().readable_len(), MSS as usize);
+    }
+
+    #[test]
+    fn test_reset() {
+        let (mut client, mut server) = connect(1, 2, 100_000);
+
+        client.abort();
+        assert_eq!(client.state(), State::Closed);
+        assert_eq!(deliver(&mut client, &mut server, time(0)), 1);
+
+        assert_eq!(server.state(), State::Closed);
+        assert_eq!(server.take_error(), Some(Errno::ECONNRESET));
+        assert_eq!(server.take_error(), None);
+    }
+
+    #[test]
+    fn test_refused() {
+        let now = time(0);
+        let mut client = Connection::new_active(
+            now,
+            client_addr(),
+            server_addr(),
+            Seq::new(1),
+            MSS,
+            100_000,
+            100_000,
+        );
+        let syn = segments(&mut client, now);
+
+        // the server has no listening socket
+        let rst = reset_for(&syn[0].0, 0).unwrap();
+        assert_eq!(rst.flags, TcpFlags::RST | TcpFlags::ACK);
+        assert_eq!(rst.ack, syn[0].0.seq + 1);
+        assert!(reset_for(&rst, 0).is_none());
+
+        client.on_segment(now, &rst, Bytes::new());
+
+        assert_eq!(client.state(), State::Closed);
+        assert_eq!(client.take_error(), Some(Errno::ECONNREFUSED));
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let (mut client, mut server) = connect(1, 2, 100_000);
+        client.set_keepalive(Some(KeepaliveConfig {
+            idle: SimulationTime::from_secs(10),
+            interval: SimulationTime::from_secs(1),
+            count: 2,
+        }));
+        assert_eq!(client.next_deadline(), Some(time(10_000)));
+
+        // the server responds to the probe
+        client.on_timer(time(10_000));
+        assert_eq!(deliver(&mut client, &mut server, time(10_000)), 1);
+        assert_eq!(deliver(&mut server, &mut client, time(10_000)), 1);
+        assert_eq!(client.next_deadline(), Some(time(20_000)));
+
+        // the server stops responding
+        for t in [20_000, 21_000] {
+            client.on_timer(time(t));
+            assert_eq!(segments(&mut client, time(t)).len(), 1);
+        }
+
+        client.on_timer(time(22_000));
+        assert_eq!(client.state(), State::Closed);
+        assert_eq!(client.take_error(), Some(Errno::ETIMEDOUT));
+    }
+}
@@ -0,0 +1,1611 @@
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::io::Read;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Weak};
+
+use atomic_refcell::AtomicRefCell;
+use bytes::{Bytes, BytesMut};
+use linux_api::errno::Errno;
+use linux_api::ioctls::IoctlRequest;
+use nix::sys::socket::{AddressFamily, MsgFlags, Shutdown};
+use rand::Rng;
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+use shadow_shim_helper_rs::simulation_time::SimulationTime;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+
+use self::connection::{Connection, KeepaliveConfig, State};
+use self::seq::Seq;
+use crate::core::work::task::TaskRef;
+use crate::core::worker::Worker;
+use crate::cshadow as c;
+use crate::host::descriptor::socket::inet::{self, InetSocket, IpVersion};
+use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs};
+use crate::host::descriptor::{
+    File, FileMode, FileState, FileStatus, OpenFile, Socket, StateEventSource, StateListenerFilter,
+    SyscallResult,
+};
+use crate::host::memory_manager::MemoryManager;
+use crate::host::network::interface::{FifoPacketPriority, ReuseOptions};
+use crate::host::network::namespace::{AssociationHandle, NetworkNamespace};
+use crate::host::syscall::io::{write_partial, IoVec, IoVecReader, IoVecWriter};
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
+use crate::host::thread::ThreadId;
+use crate::network::packet::{PacketRc, PacketStatus, TcpFlags, TcpHeader};
+use crate::utility::callback_queue::{CallbackQueue, Handle};
+use crate::utility::sockaddr::SockaddrStorage;
+use crate::utility::{HostTreePointer, ObjectCounter};
+
+mod buffer;
+mod connection;
+mod seq;
+
+/// A TCP socket implemented in Rust. This is experimental, and is only used if the
+/// `use_new_tcp` experimental option is enabled.
+pub struct TcpSocket {
+    event_source: StateEventSource,
+    status: FileStatus,
+    state: FileState,
+    /// Either `AF_INET` or `AF_INET6`.
+    domain: AddressFamily,
+    /// The `IPV6_V6ONLY` socket option.
+    ipv6_only: bool,
+    /// The IP version of packets that the socket can receive, which is updated when the socket is
+    /// bound or connected.
+    ip_version: IpVersion,
+    bound_addr: Option<SocketAddrV4>,
+    association: Option<AssociationHandle>,
+    /// Set once `listen()` has been called.
+    listener: Option<Listener>,
+    /// Set once `connect()` has been called, or for sockets created by a listening socket.
+    connection: Option<Connection>,
+    /// The listening socket that created this socket. Cleared once the handshake has completed.
+    parent: Option<Weak<AtomicRefCell<Self>>>,
+    weak_self: Weak<AtomicRefCell<Self>>,
+    /// Segments that are waiting to be pulled by the network interface.
+    out_queue: VecDeque<OutSegment>,
+    /// The time of the earliest timer task that has been scheduled.
+    timer: Option<EmulatedTime>,
+    options: SocketOptions,
+    /// Was `shutdown(SHUT_RD)` called?
+    read_shutdown: bool,
+    /// Did the last connect() call block, and if so what thread?
+    thread_of_blocked_connect: Option<ThreadId>,
+    // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
+    // this file
+    has_open_file: bool,
+    _counter: ObjectCounter,
+}
+
+/// The state of a listening socket.
+struct Listener {
+    /// The maximum number of connections that can be pending or waiting to be accepted.
+    queue_limit: u32,
+    /// Connections that are still performing the handshake.
+    pending: Vec<Arc<AtomicRefCell<TcpSocket>>>,
+    /// Connections that have completed the handshake and are waiting to be accepted.
+    accept_queue: VecDeque<Arc<AtomicRefCell<TcpSocket>>>,
+}
+
+/// Socket options that are applied to the connection, and are inherited by accepted sockets.
+#[derive(Copy, Clone, Debug)]
+struct SocketOptions {
+    send_buf_size: usize,
+    recv_buf_size: usize,
+    nodelay: bool,
+    keepalive: bool,
+    keepalive_config: KeepaliveConfig,
+    reuse_addr: bool,
+    reuse_port: bool,
+}
+
+/// A segment in the socket's send queue.
+#[derive(Debug)]
+struct OutSegment {
+    header: TcpHeader,
+    payload: Bytes,
+    is_ipv6: bool,
+    /// The priority for the packet, given to us by the host when the segment was queued.
+    priority: FifoPacketPriority,
+}
+
+impl TcpSocket {
+    pub fn new(
+        status: FileStatus,
+        domain: AddressFamily,
+        send_buf_size: usize,
+        recv_buf_size: usize,
+    ) -> Arc<AtomicRefCell<Self>> {
+        let options = SocketOptions {
+            send_buf_size,
+            recv_buf_size,
+            nodelay: false,
+            keepalive: false,
+            keepalive_config: KeepaliveConfig::default(),
+            reuse_addr: false,
+            reuse_port: false,
+        };
+
+        let socket = Self::new_inner(status, domain, false, options);
+
+        CallbackQueue::queue_and_run(|cb_queue| socket.borrow_mut().refresh_state(cb_queue));
+
+        socket
+    }
+
+    fn new_inner(
+        status: FileStatus,
+        domain: AddressFamily,
+        ipv6_only: bool,
+        options: SocketOptions,
+    ) -> Arc<AtomicRefCell<Self>> {
+        Arc::new_cyclic(|weak| {
+            AtomicRefCell::new(Self {
+                event_source: StateEventSource::new(),
+                status,
+                state: FileState::ACTIVE,
+                domain,
+                ipv6_only,
+                ip_version: Self::unbound_ip_version(domain, ipv6_only),
+                bound_addr: None,
+                association: None,
+                listener: None,
+                connection: None,
+                parent: None,
+                weak_self: weak.clone(),
+                out_queue: VecDeque::new(),
+                timer: None,
+                options,
+                read_shutdown: false,
+                thread_of_blocked_connect: None,
+                has_open_file: false,
+                _counter: ObjectCounter::new("TcpSocket"),
+            })
+        })
+    }
+
+    /// The IP version of an unconnected socket bound to the unspecified address.
+    fn unbound_ip_version(domain: AddressFamily, ipv6_only: bool) -> IpVersion {
+        match domain {
+            AddressFamily::Inet => IpVersion::V4,
+            AddressFamily::Inet6 if ipv6_only => IpVersion::V6,
+            AddressFamily::Inet6 => IpVersion::Any,
+            _ => unreachable!("Not an inet socket domain: {domain:?}"),
+        }
+    }
+
+    /// The maximum segment size for a connection with the given IP version.
+    fn mss(is_ipv6: bool) -> u32 {
+        let ip_header_size = if is_ipv6 {
+            c::CONFIG_HEADER_SIZE_IPV6
+        } else {
+            c::CONFIG_HEADER_SIZE_IP
+        };
+        c::CONFIG_MTU - ip_header_size - c::CONFIG_HEADER_SIZE_TCP
+    }
+
+    fn keepalive_config(&self) -> Option<KeepaliveConfig> {
+        self.options
+            .keepalive
+            .then_some(self.options.keepalive_config)
+    }
+
+    pub fn get_status(&self) -> FileStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: FileStatus) {
+        self.status = status;
+    }
+
+    pub fn mode(&self) -> FileMode {
+        FileMode::READ | FileMode::WRITE
+    }
+
+    pub fn has_open_file(&self) -> bool {
+        self.has_open_file
+    }
+
+    pub fn supports_sa_restart(&self) -> bool {
+        true
+    }
+
+    pub fn set_has_open_file(&mut self, val: bool) {
+        self.has_open_file = val;
+    }
+
+    pub fn push_in_packet(
+        &mut self,
+        mut packet: PacketRc,
+        cb_queue: &mut CallbackQueue,
+        _recv_time: EmulatedTime,
+    ) {
+        packet.add_status(PacketStatus::RcvSocketProcessed);
+
+        if !self.ip_version.accepts(packet.is_ipv6()) {
+            // an IPv4 socket can't receive IPv6 packets, and an `IPV6_V6ONLY` socket can't receive
+            // IPv4 packets
+            packet.add_status(PacketStatus::RcvSocketDropped);
+            return;
+        }
+
+        let header = packet.get_tcp();
+
+        // in the future, the packet could contain the `Bytes` object itself and we could simply
+        // transfer the `Bytes` directly from the packet to the buffer without copying the bytes
+        let mut payload = BytesMut::zeroed(packet.payload_size());
+        let num_bytes_copied = packet.get_payload(&mut payload);
+        assert_eq!(num_bytes_copied, packet.payload_size());
+        let payload = payload.freeze();
+
+        let now = Worker::current_time().unwrap();
+
+        let is_from_peer = self
+            .connection
+            .as_ref()
+            .is_some_and(|conn| conn.peer_addr() == header.src);
+
+        if is_from_peer {
+            let conn = self.connection.as_mut().unwrap();
+            conn.on_segment(now, &header, payload);
+            packet.add_status(PacketStatus::RcvSocketBuffered);
+        } else if self.listener.is_some() {
+            if !self.push_to_listener(&header, packet.is_ipv6(), cb_queue) {
+                packet.add_status(PacketStatus::RcvSocketDropped);
+            }
+        } else {
+            // a socket bound to the same port may have received a segment for a connection that
+            // doesn't exist
+            self.queue_reset(&header, payload.len(), packet.is_ipv6());
+            packet.add_status(PacketStatus::RcvSocketDropped);
+        }
+
+        self.flush(cb_queue);
+    }
+
+    /// Handle a segment received by a listening socket. Returns `false` if the segment was
+    /// dropped.
+    fn push_to_listener(
+        &mut self,
+        header: &TcpHeader,
+        is_ipv6: bool,
+        cb_queue: &mut CallbackQueue,
+    ) -> bool {
+        if header.flags.contains(TcpFlags::RST) {
+            return false;
+        }
+
+        if header.flags.contains(TcpFlags::ACK) || !header.flags.contains(TcpFlags::SYN) {
+            // not a connection attempt, so the connection must no longer exist
+            self.queue_reset(header, 0, is_ipv6);
+            return false;
+        }
+
+        let listener = self.listener.as_ref().unwrap();
+        let num_queued = listener.pending.len() + listener.accept_queue.len();
+        if num_queued >= listener.queue_limit.try_into().unwrap() {
+            // like linux, drop the SYN and let the peer retry later
+            log::debug!("Listening socket's queue is full; dropping SYN");
+            return false;
+        }
+
+        let now = Worker::current_time().unwrap();
+        let iss = Worker::with_active_host(|host| host.random_mut().gen()).unwrap();
+
+        let mut conn = Connection::new_passive(
+            now,
+            header,
+            Seq::new(iss),
+            Self::mss(is_ipv6),
+            self.options.send_buf_size,
+            self.options.recv_buf_size,
+        );
+        conn.set_nodelay(self.options.nodelay);
+        conn.set_keepalive(self.keepalive_config());
+
+        let child = Self::new_inner(
+            FileStatus::empty(),
+            self.domain,
+            self.ipv6_only,
+            self.options,
+        );
+
+        // the child receives all future segments from this peer
+        let handle = {
+            let inet_socket = InetSocket::Tcp(Arc::clone(&child));
+            Worker::with_active_host(|host| {
+                host.network_namespace_borrow().associate_interface(
+                    &inet_socket,
+                    c::_ProtocolType_PTCP,
+                    header.dst,
+                    header.src,
+                    self.reuse_options(),
+                )
+            })
+            .unwrap()
+        };
+
+        {
+            let mut child_ref = child.borrow_mut();
+            child_ref.bound_addr = Some(header.dst);
+            child_ref.association = Some(handle);
+            child_ref.ip_version = IpVersion::of_peer(is_ipv6);
+            child_ref.connection = Some(conn);
+            child_ref.parent = Some(self.weak_self.clone());
+
+            // send the SYN-ACK
+            child_ref.flush(cb_queue);
+        }
+
+        self.listener.as_mut().unwrap().pending.push(child);
+
+        true
+    }
+
+    /// Called when a connection created by this listening socket is no longer performing the
+    /// handshake.
+    fn on_child_handshake_done(
+        &mut self,
+        child: Arc<AtomicRefCell<Self>>,
+        cb_queue: &mut CallbackQueue,
+    ) {
+        // the listening socket may have been closed
+        let Some(listener) = self.listener.as_mut() else {
+            return;
+        };
+
+        let Some(index) = listener.pending.iter().position(|x| Arc::ptr_eq(x, &child)) else {
+            return;
+        };
+        listener.pending.remove(index);
+
+        let is_closed = {
+            let child = child.borrow();
+            child.connection.as_ref().unwrap().state() == State::Closed
+        };
+
+        if !is_closed {
+            listener.accept_queue.push_back(child);
+        }
+
+        self.refresh_state(cb_queue);
+    }
+
+    /// Queue a RST in response to a segment that doesn't belong to any connection.
+    fn queue_reset(&mut self, header: &TcpHeader, payload_len: usize, is_ipv6: bool) {
+        let Some(header) = connection::reset_for(header, payload_len.try_into().unwrap()) else {
+            return;
+        };
+
+        let priority = Worker::with_active_host(|host| host.get_next_packet_priority()).unwrap();
+
+        self.out_queue.push_back(OutSegment {
+            header,
+            payload: Bytes::new(),
+            is_ipv6,
+            priority,
+        });
+    }
+
+    /// Queue any segments that the connection wants to send, reschedule the connection's timer, and
+    /// update the socket's state. This should be called after every change to the connection.
+    fn flush(&mut self, cb_queue: &mut CallbackQueue) {
+        let now = Worker::current_time().unwrap();
+        let is_ipv6 = self.ip_version == IpVersion::V6;
+
+        if let Some(conn) = self.connection.as_mut() {
+            while let Some((header, payload)) = conn.next_segment(now) {
+                // get the priority that we'll assign to the eventual packet
+                let priority =
+                    Worker::with_active_host(|host| host.get_next_packet_priority()).unwrap();
+
+                self.out_queue.push_back(OutSegment {
+                    header,
+                    payload,
+                    is_ipv6,
+                    priority,
+                });
+            }
+        }
+
+        if let Some(segment) = self.out_queue.front() {
+            // notify the host that this socket has packets to send
+            let socket = self.weak_self.upgrade().unwrap();
+            let interface_ip = *segment.header.src.ip();
+            cb_queue.add(move |_cb_queue| {
+                Worker::with_active_host(|host| {
+                    let inet_socket = InetSocket::Tcp(socket);
+                    let compat_socket = unsafe { c::compatsocket_fromInetSocket(&inet_socket) };
+                    host.notify_socket_has_packets(interface_ip, &compat_socket);
+                })
+                .unwrap();
+            });
+        }
+
+        if let Some(conn) = self.connection.as_ref() {
+            let conn_state = conn.state();
+            let deadline = conn.next_deadline();
+
+            if conn_state == State::Closed {
+                // drop the association handle to disassociate the socket
+                self.association = None;
+            } else if let Some(deadline) = deadline {
+                self.schedule_timer(deadline);
+            }
+
+            if conn_state != State::SynReceived {
+                if let Some(parent) = self.parent.take() {
+                    let child = self.weak_self.upgrade().unwrap();
+                    cb_queue.add(move |cb_queue| {
+                        if let Some(parent) = parent.upgrade() {
+                            parent.borrow_mut().on_child_handshake_done(child, cb_queue);
+                        }
+                    });
+                }
+            }
+        }
+
+        self.refresh_state(cb_queue);
+    }
+
+    /// Schedule a task to run the connection's timers, unless an earlier task has already been
+    /// scheduled.
+    fn schedule_timer(&mut self, deadline: EmulatedTime) {
+        let now = Worker::current_time().unwrap();
+        let deadline = std::cmp::max(deadline, now);
+
+        if self.timer.is_some_and(|t| t <= deadline) {
+            return;
+        }
+        self.timer = Some(deadline);
+
+        // use a weak reference so that the task doesn't keep the socket alive
+        let weak_self = self.weak_self.clone();
+        let task = TaskRef::new(move |_host| Self::run_timer_task(&weak_self, deadline));
+
+        Worker::with_active_host(|host| host.schedule_task_at_emulated_time(task, deadline))
+            .unwrap();
+    }
+
+    fn run_timer_task(weak_self: &Weak<AtomicRefCell<Self>>, deadline: EmulatedTime) {
+        let Some(socket) = weak_self.upgrade() else {
+            log::trace!("TCP socket no longer exists; skipping timer task");
+            return;
+        };
+
+        CallbackQueue::queue_and_run(|cb_queue| {
+            let mut socket = socket.borrow_mut();
+
+            if socket.timer == Some(deadline) {
+                socket.timer = None;
+            }
+
+            let now = Worker::current_time().unwrap();
+            if let Some(conn) = socket.connection.as_mut() {
+                conn.on_timer(now);
+            }
+
+            socket.flush(cb_queue);
+        });
+    }
+
+    pub fn pull_out_packet(&mut self, _cb_queue: &mut CallbackQueue) -> Option<PacketRc> {
+        let Some(segment) = self.out_queue.pop_front() else {
+            log::debug!("Attempted to remove a segment from the TCP socket, but none available");
+            return None;
+        };
+
+        let mut packet = PacketRc::new();
+
+        packet.set_tcp(&segment.header);
+        packet.set_ipv6(segment.is_ipv6);
+        packet.set_payload(&segment.payload, segment.priority);
+        packet.add_status(PacketStatus::SndCreated);
+
+        Some(packet)
+    }
+
+    pub fn peek_next_packet_priority(&self) -> Option<FifoPacketPriority> {
+        self.out_queue.front().map(|x| x.priority)
+    }
+
+    pub fn has_data_to_send(&self) -> bool {
+        !self.out_queue.is_empty()
+    }
+
+    pub fn update_packet_header(&self, _packet: &mut PacketRc) {
+        // the header was already set when the segment was queued
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    fn reuse_options(&self) -> ReuseOptions {
+        ReuseOptions {
+            reuse_addr: self.options.reuse_addr,
+            reuse_port: self.options.reuse_port,
+        }
+    }
+
+    pub fn getsockname(&self) -> Result<Option<SocketAddr>, SyscallError> {
+        let addr = match &self.connection {
+            Some(conn) => conn.local_addr(),
+            None => self
+                .bound_addr
+                .unwrap_or(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
+        };
+
+        let is_ipv6 = self.ip_version.is_ipv6();
+        Ok(Some(inet::from_internal_addr(addr, is_ipv6, self.domain)))
+    }
+
+    pub fn getpeername(&self) -> Result<Option<SocketAddr>, SyscallError> {
+        let conn = self.connection.as_ref().ok_or(Errno::ENOTCONN)?;
+
+        if conn.state() == State::SynSent {
+            return Err(Errno::ENOTCONN.into());
+        }
+
+        let is_ipv6 = self.ip_version.is_ipv6();
+        Ok(Some(inet::from_internal_addr(
+            conn.peer_addr(),
+            is_ipv6,
+            self.domain,
+        )))
+    }
+
+    pub fn address_family(&self) -> AddressFamily {
+        self.domain
+    }
+
+    pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
+        // reset any connections that haven't been accepted
+        if let Some(listener) = self.listener.take() {
+            for child in listener.pending.into_iter().chain(listener.accept_queue) {
+                let mut child = child.borrow_mut();
+                child.parent = None;
+                child.connection.as_mut().unwrap().abort();
+                child.flush(cb_queue);
+            }
+        }
+
+        if let Some(conn) = self.connection.as_mut() {
+            if conn.recv_buf().readable_len() > 0 {
+                // the application won't read the remaining data, so the peer should be told
+                // ([RFC 2525 2.17](https://www.rfc-editor.org/rfc/rfc2525#page-50))
+                conn.abort();
+            } else {
+                conn.close(Worker::current_time().unwrap());
+            }
+
+            // the socket stays associated until the connection has finished closing
+            self.flush(cb_queue);
+        } else {
+            // drop the existing association handle to disassociate the socket
+            self.association = None;
+        }
+
+        self.copy_state(
+            /* mask= */ FileState::all(),
+            FileState::CLOSED,
+            cb_queue,
+        );
+        Ok(())
+    }
+
+    pub fn bind(
+        socket: &Arc<AtomicRefCell<Self>>,
+        addr: Option<&SockaddrStorage>,
+        net_ns: &NetworkNamespace,
+        rng: impl rand::Rng,
+    ) -> SyscallResult {
+        // if the address pointer was NULL
+        let Some(addr) = addr else {
+            return Err(Errno::EFAULT.into());
+        };
+
+        let (domain, ipv6_only) = {
+            let socket = socket.borrow();
+            (socket.domain, socket.ipv6_only)
+        };
+
+        // if not an address of the socket's family
+        let Some(addr) = inet::inet_sockaddr(addr, domain) else {
+            return Err(Errno::EINVAL.into());
+        };
+
+        let ip_version = IpVersion::of_bind_addr(addr, ipv6_only);
+
+        // an IPv6 address that doesn't belong to any host
+        let Some((addr, _)) = inet::to_internal_addr(addr) else {
+            return Err(Errno::EADDRNOTAVAIL.into());
+        };
+
+        {
+            let socket = socket.borrow();
+
+            // if the socket is already bound
+            if socket.bound_addr.is_some() {
+                return Err(Errno::EINVAL.into());
+            }
+
+            // an unbound socket can't be listening or connected
+            assert!(socket.listener.is_none());
+            assert!(socket.connection.is_none());
+            assert!(socket.association.is_none());
+        }
+
+        // this will allow us to receive packets from any peer
+        let unspecified_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+
+        // the socket can share the address with other sockets if their options allow it
+        let reuse = socket.borrow().reuse_options();
+
+        // associate the socket
+        let (addr, handle) = inet::associate_socket(
+            InetSocket::Tcp(Arc::clone(socket)),
+            addr,
+            unspecified_addr,
+            reuse,
+            net_ns,
+            rng,
+        )?;
+
+        // update the socket's local address
+        {
+            let mut socket = socket.borrow_mut();
+            socket.bound_addr = Some(addr);
+            socket.ip_version = ip_version;
+            socket.association = Some(handle);
+        }
+
+        Ok(0.into())
+    }
+
+    pub fn readv(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // we could call TcpSocket::recvmsg() here, but for now we expect that there are no code
+        // paths that would call TcpSocket::readv() since the readv() syscall handler should have
+        // called TcpSocket::recvmsg() instead
+        panic!("Called TcpSocket::readv() on a TCP socket");
+    }
+
+    pub fn writev(
+        &mut self,
+        _iovs: &[IoVec],
+        _offset: Option<libc::off_t>,
+        _flags: libc::c_int,
+        _mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // we could call TcpSocket::sendmsg() here, but for now we expect that there are no code
+        // paths that would call TcpSocket::writev() since the writev() syscall handler should have
+        // called TcpSocket::sendmsg() instead
+        panic!("Called TcpSocket::writev() on a TCP socket");
+    }
+
+    pub fn sendmsg(
+        socket: &Arc<AtomicRefCell<Self>>,
+        args: SendmsgArgs,
+        mem: &mut MemoryManager,
+        _net_ns: &NetworkNamespace,
+        _rng: impl rand::Rng,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        let socket_ref = &mut *socket.borrow_mut();
+
+        let Some(mut flags) = MsgFlags::from_bits(args.flags) else {
+            log::debug!("Unrecognized send flags: {:#b}", args.flags);
+            return Err(Errno::EINVAL.into());
+        };
+
+        if socket_ref.get_status().contains(FileStatus::NONBLOCK) {
+            flags.insert(MsgFlags::MSG_DONTWAIT);
+        }
+
+        // a destination address is ignored for connected TCP sockets, so we don't check
+        // `args.addr`
+
+        let len: libc::size_t = args.iovs.iter().map(|x| x.len).sum();
+
+        // run in a closure so that an early return doesn't skip checking if we should block
+        let result = (|| {
+            let Some(conn) = socket_ref.connection.as_mut() else {
+                return Err(Errno::EPIPE);
+            };
+
+            if let Some(err) = conn.take_error() {
+                return Err(err);
+            }
+
+            // wait for the handshake to complete
+            if matches!(conn.state(), State::SynSent | State::SynReceived) {
+                return Err(Errno::EWOULDBLOCK);
+            }
+
+            if !conn.can_send() {
+                return Err(Errno::EPIPE);
+            }
+
+            let space = conn.send_buf().space();
+            if space == 0 {
+                return Err(Errno::EWOULDBLOCK);
+            }
+
+            let len = std::cmp::min(len, space);
+
+            // copy the bytes from the iovs
+            let mut reader = IoVecReader::new(args.iovs, mem);
+            let mut bytes = BytesMut::zeroed(len);
+            reader
+                .read_exact(&mut bytes[..])
+                .map_err(|e| Errno::try_from(e).unwrap())?;
+
+            conn.send(bytes.freeze());
+
+            Ok(len)
+        })();
+
+        socket_ref.flush(cb_queue);
+
+        // if the syscall would block and we don't have the MSG_DONTWAIT flag
+        if result == Err(Errno::EWOULDBLOCK) && !flags.contains(MsgFlags::MSG_DONTWAIT) {
+            return Err(SyscallError::new_blocked(
+                File::Socket(Socket::Inet(InetSocket::Tcp(socket.clone()))),
+                FileState::WRITABLE,
+                socket_ref.supports_sa_restart(),
+            ));
+        }
+
+        Ok(result?.try_into().unwrap())
+    }
+
+    pub fn recvmsg(
+        socket: &Arc<AtomicRefCell<Self>>,
+        args: RecvmsgArgs,
+        mem: &mut MemoryManager,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<RecvmsgReturn, SyscallError> {
+        let socket_ref = &mut *socket.borrow_mut();
+
+        let Some(mut flags) = MsgFlags::from_bits(args.flags) else {
+            log::debug!("Unrecognized recv flags: {:#b}", args.flags);
+            return Err(Errno::EINVAL.into());
+        };
+
+        if socket_ref.get_status().contains(FileStatus::NONBLOCK) {
+            flags.insert(MsgFlags::MSG_DONTWAIT);
+        }
+
+        let len: libc::size_t = args.iovs.iter().map(|x| x.len).sum();
+        let peek = flags.contains(MsgFlags::MSG_PEEK);
+        let wait_all = flags.contains(MsgFlags::MSG_WAITALL)
+            && !flags.contains(MsgFlags::MSG_DONTWAIT)
+            && !peek;
+        let read_shutdown = socket_ref.read_shutdown;
+
+        // run in a closure so that an early return doesn't skip checking if we should block
+        let result = (|| {
+            let Some(conn) = socket_ref.connection.as_mut() else {
+                return Err(Errno::ENOTCONN);
+            };
+
+            if matches!(conn.state(), State::SynSent | State::SynReceived) {
+                return Err(Errno::EWOULDBLOCK);
+            }
+
+            let readable_len = conn.recv_buf().readable_len();
+
+            if readable_len == 0 {
+                if let Some(err) = conn.take_error() {
+                    return Err(err);
+                }
+
+                if conn.is_recv_finished() || read_shutdown {
+                    // EOF
+                    return Ok(0);
+                }
+
+                return Err(Errno::EWOULDBLOCK);
+            }
+
+            // with MSG_WAITALL, wait until we can fill the buffer (or the receive buffer is full,
+            // in which case we'd never be able to fill it)
+            if wait_all
+                && readable_len < len
+                && readable_len < conn.recv_buf().capacity()
+                && !conn.is_recv_finished()
+                && !conn.has_error()
+            {
+                return Err(Errno::EWOULDBLOCK);
+            }
+
+            let mut writer = IoVecWriter::new(args.iovs, mem);
+            conn.recv(&mut writer, len, peek)
+                .map_err(|e| Errno::try_from(e).unwrap())
+        })();
+
+        socket_ref.flush(cb_queue);
+
+        // if the syscall would block and we don't have the MSG_DONTWAIT flag
+        if result.as_ref().err() == Some(&Errno::EWOULDBLOCK)
+            && !flags.contains(MsgFlags::MSG_DONTWAIT)
+        {
+            return Err(SyscallError::new_blocked(
+                File::Socket(Socket::Inet(InetSocket::Tcp(socket.clone()))),
+                FileState::READABLE,
+                socket_ref.supports_sa_restart(),
+            ));
+        }
+
+        Ok(RecvmsgReturn {
+            return_val: result?.try_into().unwrap(),
+            addr: None,
+            msg_flags: 0,
+            control_len: 0,
+        })
+    }
+
+    pub fn ioctl(
+        &mut self,
+        request: IoctlRequest,
+        arg_ptr: ForeignPtr<()>,
+        mem: &mut MemoryManager,
+    ) -> SyscallResult {
+        match request {
+            // equivalent to SIOCINQ
+            IoctlRequest::FIONREAD => {
+                if self.listener.is_some() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let len = self
+                    .connection
+                    .as_ref()
+                    .map(|x| x.recv_buf().readable_len())
+                    .unwrap_or(0)
+                    .try_into()
+                    .unwrap();
+
+                let arg_ptr = arg_ptr.cast::<libc::c_int>();
+                mem.write(arg_ptr, &len)?;
+
+                Ok(0.into())
+            }
+            // equivalent to SIOCOUTQ
+            IoctlRequest::TIOCOUTQ => {
+                if self.listener.is_some() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let len = self
+                    .connection
+                    .as_ref()
+                    .map(|x| x.send_buf().len())
+                    .unwrap_or(0)
+                    .try_into()
+                    .unwrap();
+
+                let arg_ptr = arg_ptr.cast::<libc::c_int>();
+                mem.write(arg_ptr, &len)?;
+
+                Ok(0.into())
+            }
+            // this isn't supported by tcp
+            IoctlRequest::SIOCGSTAMP => Err(Errno::ENOENT.into()),
+            IoctlRequest::FIONBIO => {
+                panic!("This should have been handled by the ioctl syscall handler");
+            }
+            IoctlRequest::TCGETS
+            | IoctlRequest::TCSETS
+            | IoctlRequest::TCSETSW
+            | IoctlRequest::TCSETSF
+            | IoctlRequest::TCGETA
+            | IoctlRequest::TCSETA
+            | IoctlRequest::TCSETAW
+            | IoctlRequest::TCSETAF
+            | IoctlRequest::TIOCGWINSZ
+            | IoctlRequest::TIOCSWINSZ => {
+                // not a terminal
+                Err(Errno::ENOTTY.into())
+            }
+            request => {
+                warn_once_then_debug!(
+                    "(LOG_ONCE) We do not yet handle ioctl request {request:?} on tcp sockets"
+                );
+                Err(Errno::EINVAL.into())
+            }
+        }
+    }
+
+    pub fn listen(
+        socket: &Arc<AtomicRefCell<Self>>,
+        backlog: i32,
+        net_ns: &NetworkNamespace,
+        rng: impl rand::Rng,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        let mut socket_ref = socket.borrow_mut();
+
+        // only listen on the socket if it is not used for other functions
+        if socket_ref.connection.is_some() {
+            log::debug!("Cannot listen on previously used socket");
+            return Err(Errno::EINVAL.into());
+        }
+
+        let queue_limit = backlog_to_queue_size(backlog);
+
+        // if we are already listening, just update the backlog and return 0
+        if let Some(listener) = socket_ref.listener.as_mut() {
+            log::trace!("Socket already set up as a listener; updating backlog");
+            listener.queue_limit = queue_limit;
+            return Ok(());
+        }
+
+        // a listening socket must be bound
+        if socket_ref.bound_addr.is_none() {
+            log::trace!("Implicitly binding listener socket");
+
+            // implicit bind: bind to all interfaces at an ephemeral port
+            let local_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+
+            // this will allow us to receive packets from any peer address
+            let peer_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+
+            let (local_addr, handle) = inet::associate_socket(
+                InetSocket::Tcp(Arc::clone(socket)),
+                local_addr,
+                peer_addr,
+                socket_ref.reuse_options(),
+                net_ns,
+                rng,
+            )?;
+
+            socket_ref.bound_addr = Some(local_addr);
+            socket_ref.association = Some(handle);
+        }
+
+        socket_ref.listener = Some(Listener {
+            queue_limit,
+            pending: Vec::new(),
+            accept_queue: VecDeque::new(),
+        });
+
+        socket_ref.refresh_state(cb_queue);
+
+        Ok(())
+    }
+
+    pub fn connect(
+        socket: &Arc<AtomicRefCell<Self>>,
+        peer_addr: &SockaddrStorage,
+        net_ns: &NetworkNamespace,
+        mut rng: impl rand::Rng,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        let mut socket_ref = socket.borrow_mut();
+
+        if let Some(tid) = socket_ref.thread_of_blocked_connect {
+            // check if there is already a blocking connect() call on another thread
+            if tid != Worker::active_thread_id().unwrap() {
+                // connect(2) says "Generally,  connection-based protocol sockets may successfully
+                // connect() only once", but the application is attempting to call connect() in two
+                // threads on a blocking socket at the same time. Let's just return an error and
+                // hope no one ever does this.
+                log::warn!("Two threads are attempting to connect() on a blocking socket");
+                return Err(Errno::EBADFD.into());
+            }
+        }
+
+        if socket_ref.listener.is_some() {
+            return Err(Errno::EINVAL.into());
+        }
+
+        if socket_ref.connection.is_some() {
+            return socket_ref.connect_again(socket);
+        }
+
+        let Some(peer_addr) = inet::inet_sockaddr(peer_addr, socket_ref.domain) else {
+            return Err(Errno::EINVAL.into());
+        };
+
+        // an IPv6 address that doesn't belong to any host
+        let Some((mut peer_addr, is_ipv6)) = inet::to_internal_addr(peer_addr) else {
+            return Err(Errno::ENETUNREACH.into());
+        };
+
+        // https://stackoverflow.com/a/22425796
+        if peer_addr.ip().is_unspecified() {
+            peer_addr.set_ip(std::net::Ipv4Addr::LOCALHOST);
+        }
+
+        // for example an `IPV6_V6ONLY` socket connecting to an IPv4-mapped address
+        if !socket_ref.ip_version.accepts(is_ipv6) {
+            return Err(Errno::ENETUNREACH.into());
+        }
+
+        inet::check_ipv6_route(net_ns, peer_addr, is_ipv6)?;
+
+        // NOTE: it would be nice to use `Ipv4Addr::is_loopback` in this code rather than comparing
+        // to `Ipv4Addr::LOCALHOST`, but the rest of Shadow probably can't handle other loopback
+        // addresses (ex: 127.0.0.2) and it's probably best not to change this behaviour
+
+        // make sure we will be able to route this later
+        if peer_addr.ip() != &std::net::Ipv4Addr::LOCALHOST {
+            let is_routable =
+                Worker::is_routable(net_ns.default_ip.into(), (*peer_addr.ip()).into());
+
+            if !is_routable {
+                // can't route it - there is no node with this address
+                log::warn!(
+                    "Attempting to connect to address '{peer_addr}' for which no host exists"
+                );
+                return Err(Errno::ECONNREFUSED.into());
+            }
+        }
+
+        // the local IP for the connection (use default interface unless the remote peer is on
+        // loopback)
+        let local_ip = if peer_addr.ip() == &std::net::Ipv4Addr::LOCALHOST {
+            Ipv4Addr::LOCALHOST
+        } else {
+            net_ns.default_ip
+        };
+
+        let local_addr = if let Some(bound_addr) = socket_ref.bound_addr {
+            // we must have an association since we're bound
+            assert!(socket_ref.association.is_some());
+
+            if bound_addr.ip().is_unspecified() {
+                SocketAddrV4::new(local_ip, bound_addr.port())
+            } else if (bound_addr.ip() == &Ipv4Addr::LOCALHOST) == (local_ip == Ipv4Addr::LOCALHOST)
+            {
+                bound_addr
+            } else {
+                // the peer isn't reachable from the bound interface
+                return Err(Errno::EINVAL.into());
+            }
+        } else {
+            assert!(socket_ref.association.is_none());
+
+            // implicit bind: bind to an ephemeral port
+            let (local_addr, handle) = inet::associate_socket(
+                InetSocket::Tcp(Arc::clone(socket)),
+                SocketAddrV4::new(local_ip, 0),
+                peer_addr,
+                socket_ref.reuse_options(),
+                net_ns,
+                &mut rng,
+            )?;
+
+            socket_ref.bound_addr = Some(local_addr);
+            socket_ref.association = Some(handle);
+            local_addr
+        };
+
+        let mut conn = Connection::new_active(
+            Worker::current_time().unwrap(),
+            local_addr,
+            peer_addr,
+            Seq::new(rng.gen()),
+            Self::mss(is_ipv6),
+            socket_ref.options.send_buf_size,
+            socket_ref.options.recv_buf_size,
+        );
+        conn.set_nodelay(socket_ref.options.nodelay);
+        conn.set_keepalive(socket_ref.keepalive_config());
+
+        socket_ref.connection = Some(conn);
+        socket_ref.ip_version = IpVersion::of_peer(is_ipv6);
+
+        // send the SYN
+        socket_ref.flush(cb_queue);
+
+        if socket_ref.get_status().contains(FileStatus::NONBLOCK) {
+            return Err(Errno::EINPROGRESS.into());
+        }
+
+        // This is a blocking connect call, so we need to wait for the 3-way handshake to complete.
+        // We will wait indefinitely for a success or failure.
+        socket_ref.thread_of_blocked_connect = Some(Worker::active_thread_id().unwrap());
+
+        Err(SyscallError::new_blocked(
+            File::Socket(Socket::Inet(InetSocket::Tcp(Arc::clone(socket)))),
+            FileState::READABLE | FileState::WRITABLE,
+            socket_ref.supports_sa_restart(),
+        ))
+    }
+
+    /// A `connect()` call for a socket that has already started connecting.
+    fn connect_again(&mut self, socket: &Arc<AtomicRefCell<Self>>) -> Result<(), SyscallError> {
+        let conn = self.connection.as_mut().unwrap();
+
+        let errcode = if let Some(err) = conn.take_error() {
+            err
+        } else if matches!(conn.state(), State::SynSent | State::SynReceived) {
+            Errno::EALREADY
+        } else {
+            Errno::EISCONN
+        };
+
+        let was_blocked = self.thread_of_blocked_connect.take().is_some();
+
+        if errcode == Errno::EALREADY && !self.get_status().contains(FileStatus::NONBLOCK) {
+            // still waiting for the handshake to complete
+            self.thread_of_blocked_connect = Some(Worker::active_thread_id().unwrap());
+
+            return Err(SyscallError::new_blocked(
+                File::Socket(Socket::Inet(InetSocket::Tcp(Arc::clone(socket)))),
+                FileState::READABLE | FileState::WRITABLE,
+                self.supports_sa_restart(),
+            ));
+        }
+
+        match errcode {
+            // it was EINPROGRESS, but is now a successful blocking connect
+            Errno::EISCONN if was_blocked => Ok(()),
+            // EALREADY is well defined in man page, but Linux returns EINPROGRESS
+            Errno::EALREADY => Err(Errno::EINPROGRESS.into()),
+            errcode => Err(errcode.into()),
+        }
+    }
+
+    pub fn accept(&mut self, cb_queue: &mut CallbackQueue) -> Result<OpenFile, SyscallError> {
+        // we must be listening in order to accept
+        let Some(listener) = self.listener.as_mut() else {
+            log::debug!("Socket is not listening");
+            return Err(Errno::EINVAL.into());
+        };
+
+        let Some(child) = listener.accept_queue.pop_front() else {
+            return Err(Errno::EWOULDBLOCK.into());
+        };
+
+        self.refresh_state(cb_queue);
+
+        Ok(OpenFile::new(File::Socket(Socket::Inet(InetSocket::Tcp(
+            child,
+        )))))
+    }
+
+    pub fn shutdown(
+        &mut self,
+        how: Shutdown,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        let Some(conn) = self.connection.as_mut() else {
+            return Err(Errno::ENOTCONN.into());
+        };
+
+        if how == Shutdown::Write || how == Shutdown::Both {
+            conn.shutdown_write();
+        }
+
+        if how == Shutdown::Read || how == Shutdown::Both {
+            self.read_shutdown = true;
+        }
+
+        self.flush(cb_queue);
+
+        Ok(())
+    }
+
+    pub fn getsockopt(
+        &self,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        mem: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<libc::socklen_t, SyscallError> {
+        let val: libc::c_int = match (level, optname) {
+            (libc::SOL_TCP, libc::TCP_INFO) => {
+                let info = self.tcp_info();
+
+                let optval_ptr = optval_ptr.cast::<crate::cshadow::tcp_info>();
+                let bytes_written = write_partial(mem, &info, optval_ptr, optlen as usize)?;
+
+                return Ok(bytes_written as libc::socklen_t);
+            }
+            (libc::SOL_TCP, libc::TCP_CONGESTION) => {
+                // the value of TCP_CA_NAME_MAX in linux
+                const CONG_NAME_MAX: usize = 16;
+
+                if optval_ptr.is_null() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let name = unsafe { CStr::from_ptr(c::TCP_CONG_RENO_NAME) };
+                let name = name.to_bytes_with_nul();
+
+                let bytes_to_copy = *[optlen as usize, CONG_NAME_MAX, name.len()]
+                    .iter()
+                    .min()
+                    .unwrap();
+
+                let name = &name[..bytes_to_copy];
+                let optval_ptr = optval_ptr.cast::<u8>();
+                let optval_ptr = ForeignArrayPtr::new(optval_ptr, bytes_to_copy);
+
+                mem.copy_to_ptr(optval_ptr, name)?;
+
+                // the len value returned by linux seems to be independent from the actual string length
+                return Ok(std::cmp::min(optlen as usize, CONG_NAME_MAX) as libc::socklen_t);
+            }
+            (libc::SOL_TCP, libc::TCP_NODELAY) => self.options.nodelay.into(),
+            (libc::SOL_TCP, libc::TCP_MAXSEG) => {
+                let mss = match &self.connection {
+                    Some(conn) => conn.mss(),
+                    None => Self::mss(self.ip_version == IpVersion::V6),
+                };
+                mss.try_into().unwrap()
+            }
+            (libc::SOL_TCP, libc::TCP_KEEPIDLE) => self
+                .options
+                .keepalive_config
+                .idle
+                .as_secs()
+                .try_into()
+                .unwrap(),
+            (libc::SOL_TCP, libc::TCP_KEEPINTVL) => self
+                .options
+                .keepalive_config
+                .interval
+                .as_secs()
+                .try_into()
+                .unwrap(),
+            (libc::SOL_TCP, libc::TCP_KEEPCNT) => {
+                self.options.keepalive_config.count.try_into().unwrap()
+            }
+            (libc::SOL_SOCKET, libc::SO_SNDBUF) => self.options.send_buf_size.try_into().unwrap(),
+            (libc::SOL_SOCKET, libc::SO_RCVBUF) => self.options.recv_buf_size.try_into().unwrap(),
+            (libc::SOL_SOCKET, libc::SO_ERROR) => {
+                // return error for failed connect() attempts
+                self.connection
+                    .as_ref()
+                    .and_then(|x| x.error())
+                    .map(i32::from)
+                    .unwrap_or(0)
+            }
+            (libc::SOL_SOCKET, libc::SO_DOMAIN) => self.domain as libc::c_int,
+            (libc::SOL_SOCKET, libc::SO_TYPE) => libc::SOCK_STREAM,
+            (libc::SOL_SOCKET, libc::SO_PROTOCOL) => libc::IPPROTO_TCP,
+            (libc::SOL_SOCKET, libc::SO_ACCEPTCONN) => self.listener.is_some().into(),
+            (libc::SOL_SOCKET, libc::SO_REUSEADDR) => self.options.reuse_addr.into(),
+            (libc::SOL_SOCKET, libc::SO_REUSEPORT) => self.options.reuse_port.into(),
+            (libc::SOL_SOCKET, libc::SO_KEEPALIVE) => self.options.keepalive.into(),
+            (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) if self.domain == AddressFamily::Inet6 => {
+                self.ipv6_only.into()
+            }
+            _ => {
+                log::debug!("getsockopt called with unsupported level {level} and opt {optname}");
+                return Err(Errno::ENOPROTOOPT.into());
+            }
+        };
+
+        let optval_ptr = optval_ptr.cast::<libc::c_int>();
+        let bytes_written = write_partial(mem, &val, optval_ptr, optlen as usize)?;
+
+        Ok(bytes_written as libc::socklen_t)
+    }
+
+    fn tcp_info(&self) -> crate::cshadow::tcp_info {
+        let mut info: crate::cshadow::tcp_info = shadow_pod::zeroed();
+
+        let Some(conn) = &self.connection else {
+            // the values of TCP_LISTEN and TCP_CLOSE in linux
+            info.tcpi_state = if self.listener.is_some() { 10 } else { 7 };
+            return info;
+        };
+
+        let mss = conn.mss();
+        let as_micros = |x: SimulationTime| u32::try_from(x.as_micros()).unwrap_or(u32::MAX);
+
+        info.tcpi_state = conn.state().linux_state();
+        info.tcpi_rto = as_micros(conn.rto());
+        info.tcpi_snd_mss = mss;
+        info.tcpi_rcv_mss = mss;
+        info.tcpi_advmss = mss;
+        info.tcpi_pmtu = c::CONFIG_MTU;
+        info.tcpi_unacked = conn.unacked();
+        info.tcpi_total_retrans = conn.total_rexmits();
+        info.tcpi_rtt = conn.srtt().map(as_micros).unwrap_or(0);
+        info.tcpi_rttvar = as_micros(conn.rttvar());
+        info.tcpi_snd_ssthresh = conn.ssthresh() / mss;
+        info.tcpi_snd_cwnd = conn.cwnd() / mss;
+
+        info
+    }
+
+    pub fn setsockopt(
+        &mut self,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        mem: &MemoryManager,
+        _cb_queue: &mut CallbackQueue,
+    ) -> Result<(), SyscallError> {
+        match (level, optname) {
+            (libc::SOL_TCP, libc::TCP_CONGESTION) => {
+                // the value of TCP_CA_NAME_MAX in linux
+                const CONG_NAME_MAX: usize = 16;
+
+                let mut name = [0u8; CONG_NAME_MAX];
+
+                let optlen = std::cmp::min(optlen as usize, CONG_NAME_MAX);
+                let name = &mut name[..optlen];
+
+                let optval_ptr = optval_ptr.cast::<u8>();
+                let optval_ptr = ForeignArrayPtr::new(optval_ptr, optlen);
+                mem.copy_from_ptr(name, optval_ptr)?;
+
+                // truncate the name at the first NUL character if there is one, but don't include
+                // the NUL since in linux the strings don't need a NUL
+                let name = name
+                    .iter()
+                    .position(|x| *x == 0)
+                    .map(|x| &name[..x])
+                    .unwrap_or(name);
+
+                let reno = unsafe { CStr::from_ptr(c::TCP_CONG_RENO_NAME) }.to_bytes();
+
+                if name != reno {
+                    log::warn!("Shadow sockets only support '{reno:?}' for TCP_CONGESTION");
+                    return Err(Errno::ENOENT.into());
+                }
+            }
+            (libc::SOL_TCP, libc::TCP_NODELAY) => {
+                self.options.nodelay = read_int_opt(optval_ptr, optlen, mem)? != 0;
+            }
+            (libc::SOL_TCP, libc::TCP_KEEPIDLE) => {
+                // the value of MAX_TCP_KEEPIDLE in linux
+                let val = read_int_opt(optval_ptr, optlen, mem)?;
+                if !(1..=32767).contains(&val) {
+                    return Err(Errno::EINVAL.into());
+                }
+                self.options.keepalive_config.idle = SimulationTime::from_secs(val as u64);
+            }
+            (libc::SOL_TCP, libc::TCP_KEEPINTVL) => {
+                // the value of MAX_TCP_KEEPINTVL in linux
+                let val = read_int_opt(optval_ptr, optlen, mem)?;
+                if !(1..=32767).contains(&val) {
+                    return Err(Errno::EINVAL.into());
+                }
+                self.options.keepalive_config.interval = SimulationTime::from_secs(val as u64);
+            }
+            (libc::SOL_TCP, libc::TCP_KEEPCNT) => {
+                // the value of MAX_TCP_KEEPCNT in linux
+                let val = read_int_opt(optval_ptr, optlen, mem)?;
+                if !(1..=127).contains(&val) {
+                    return Err(Errno::EINVAL.into());
+                }
+                self.options.keepalive_config.count = val as u32;
+            }
+            (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
+                let val: u64 = read_int_opt(optval_ptr, optlen, mem)?
+                    .try_into()
+                    .or(Err(Errno::EINVAL))?;
+
+                // linux kernel doubles this value upon setting
+                let val = val * 2;
+
+                // Linux also has limits SOCK_MIN_SNDBUF (slightly greater than 4096) and the sysctl
+                // max limit. We choose a reasonable lower limit for Shadow. The minimum limit in
+                // man 7 socket is incorrect.
+                let val = std::cmp::max(val, 4096);
+
+                // This upper limit was added as an arbitrarily high number so that we don't change
+                // Shadow's behaviour, but also prevents an application from setting this to
+                // something unnecessarily large like INT_MAX.
+                let val = std::cmp::min(val, 268435456); // 2^28 = 256 MiB
+
+                self.options.send_buf_size = val.try_into().unwrap();
+            }
+            (libc::SOL_SOCKET, libc::SO_RCVBUF) => {
+                let val: u64 = read_int_opt(optval_ptr, optlen, mem)?
+                    .try_into()
+                    .or(Err(Errno::EINVAL))?;
+
+                // linux kernel doubles this value upon setting
+                let val = val * 2;
+
+                // Linux also has limits SOCK_MIN_RCVBUF (slightly greater than 2048) and the sysctl
+                // max limit. We choose a reasonable lower limit for Shadow. The minimum limit in
+                // man 7 socket is incorrect.
+                let val = std::cmp::max(val, 2048);
+
+                // This upper limit was added as an arbitrarily high number so that we don't change
+                // Shadow's behaviour, but also prevents an application from setting this to
+                // something unnecessarily large like INT_MAX.
+                let val = std::cmp::min(val, 268435456); // 2^28 = 256 MiB
+
+                self.options.recv_buf_size = val.try_into().unwrap();
+            }
+            (libc::SOL_SOCKET, libc::SO_REUSEADDR) => {
+                // Connections in TIME_WAIT are associated with their peer's address, so they never
+                // prevent a socket from binding to the same local address. This allows the socket
+                // to share an address with other bound sockets that aren't listening.
+                self.options.reuse_addr = read_int_opt(optval_ptr, optlen, mem)? != 0;
+            }
+            (libc::SOL_SOCKET, libc::SO_KEEPALIVE) => {
+                self.options.keepalive = read_int_opt(optval_ptr, optlen, mem)? != 0;
+            }
+            (libc::SOL_SOCKET, libc::SO_REUSEPORT) => {
+                self.options.reuse_port = read_int_opt(optval_ptr, optlen, mem)? != 0;
+            }
+            (libc::SOL_SOCKET, libc::SO_BROADCAST) => {
+                // TODO: implement this, pkg.go.dev/net uses it
+                log::trace!("setsockopt SO_BROADCAST not yet implemented");
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) if self.domain == AddressFamily::Inet6 => {
+                let val = read_int_opt(optval_ptr, optlen, mem)?;
+
+                // can't be changed after the socket has been bound
+                if self.bound_addr.is_some() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                self.ipv6_only = val != 0;
+                self.ip_version = Self::unbound_ip_version(self.domain, self.ipv6_only);
+            }
+            _ => {
+                log::debug!("setsockopt called with unsupported level {level} and opt {optname}");
+                return Err(Errno::ENOPROTOOPT.into());
+            }
+        }
+
+        // apply the options to an existing connection
+        let keepalive = self.keepalive_config();
+        if let Some(conn) = self.connection.as_mut() {
+            conn.set_nodelay(self.options.nodelay);
+            conn.set_keepalive(keepalive);
+            conn.set_send_buf_size(self.options.send_buf_size);
+            conn.set_recv_buf_size(self.options.recv_buf_size);
+
+            // disabling Nagle's algorithm or enabling keepalive may have changed what we need to
+            // send or when
+            CallbackQueue::queue_and_run(|cb_queue| self.flush(cb_queue));
+        }
+
+        Ok(())
+    }
+
+    pub fn add_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
+    pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
+        self.event_source.add_legacy_listener(ptr);
+    }
+
+    pub fn remove_legacy_listener(&mut self, ptr: *mut c::StatusListener) {
+        self.event_source.remove_legacy_listener(ptr);
+    }
+
+    pub fn state(&self) -> FileState {
+        self.state
+    }
+
+    fn refresh_state(&mut self, cb_queue: &mut CallbackQueue) {
+        // the socket may still be sending data in the background after it has been closed
+        if self.state.contains(FileState::CLOSED) {
+            return;
+        }
+
+        let mut readable = false;
+        let mut writable = false;
+        let mut rdhup = false;
+        let mut hup = false;
+
+        if let Some(listener) = &self.listener {
+            readable = !listener.accept_queue.is_empty();
+        }
+
+        if let Some(conn) = &self.connection {
+            let is_connecting = matches!(conn.state(), State::SynSent | State::SynReceived);
+            let is_closed = conn.state() == State::Closed || conn.has_error();
+
+            readable = is_closed
+                || conn.recv_buf().readable_len() > 0
+                || conn.is_recv_finished()
+                || self.read_shutdown;
+
+            // a socket that can no longer send is writable so that a send() doesn't block
+            writable =
+                is_closed || (!is_connecting && (!conn.can_send() || conn.send_buf().space() > 0));
+
+            // won't receive any more data
+            rdhup = is_closed || conn.is_recv_finished() || self.read_shutdown;
+            // can neither send nor receive
+            hup = is_closed || (rdhup && !is_connecting && !conn.can_send());
+        }
+
+        let mut new_state = FileState::empty();
+        new_state.set(FileState::READABLE, readable);
+        new_state.set(FileState::WRITABLE, writable);
+        new_state.set(FileState::SOCKET_RDHUP, rdhup);
+        new_state.set(FileState::HUP, hup);
+
+        self.copy_state(
+            /* mask= */
+            FileState::READABLE | FileState::WRITABLE | FileState::SOCKET_RDHUP | FileState::HUP,
+            new_state,
+            cb_queue,
+        );
+    }
+
+    fn copy_state(&mut self, mask: FileState, state: FileState, cb_queue: &mut CallbackQueue) {
+        let old_state = self.state;
+
+        // remove the masked flags, then copy the masked flags
+        self.state.remove(mask);
+        self.state.insert(state & mask);
+
+        self.handle_state_change(old_state, cb_queue);
+    }
+
+    fn handle_state_change(&mut self, old_state: FileState, cb_queue: &mut CallbackQueue) {
+        let states_changed = self.state ^ old_state;
+
+        // if nothing changed
+        if states_changed.is_empty() {
+            return;
+        }
+
+        self.event_source
+            .notify_listeners(self.state, states_changed, cb_queue);
+    }
+}
+
+/// Read an `int` socket option.
+fn read_int_opt(
+    optval_ptr: ForeignPtr<()>,
+    optlen: libc::socklen_t,
+    mem: &MemoryManager,
+) -> Result<libc::c_int, SyscallError> {
+    type OptType = libc::c_int;
+
+    if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+        return Err(Errno::EINVAL.into());
+    }
+
+    let optval_ptr = optval_ptr.cast::<OptType>();
+    Ok(mem.read(optval_ptr)?)
+}
+
+fn backlog_to_queue_size(backlog: i32) -> u32 {
+    // linux also makes this cast, so negative backlogs wrap around to large positive backlogs
+    let backlog = backlog as u32;
+
+    // the linux '__sys_listen()' applies the somaxconn max to all protocols
+    let queue_limit = std::cmp::min(backlog, c::SHADOW_SOMAXCONN);
+
+    // linux uses a limit of one greater than the provided backlog (ex: a backlog value of 0 allows
+    // for one incoming connection at a time)
+    queue_limit.saturating_add(1)
+}
@@ -0,0 +1,116 @@
+use std::cmp::Ordering;
+
+/// A TCP sequence number. Sequence numbers wrap around, so they're compared using serial number
+/// arithmetic ([RFC 1982](https://www.rfc-editor.org/rfc/rfc1982)): a sequence number is less than
+/// another if it's fewer than 2^31 numbers behind it. This ordering isn't transitive over the whole
+/// sequence space, so `Seq` only implements [`PartialOrd`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Seq(u32);
+
+impl Seq {
+    pub const fn new(val: u32) -> Self {
+        Self(val)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// The number of sequence numbers from `other` to `self`. Will panic in debug builds if `self`
+    /// is before `other`.
+    pub fn since(self, other: Self) -> u32 {
+        debug_assert!(self >= other, "{self:?} is before {other:?}");
+        self.0.wrapping_sub(other.0)
+    }
+
+    /// Is `self` within the range `[start, end)`?
+    pub fn in_range(self, start: Self, end: Self) -> bool {
+        start <= self && self < end
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        if self >= other {
+            self
+        } else {
+            other
+        }
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        if self <= other {
+            self
+        } else {
+            other
+        }
+    }
+}
+
+impl PartialOrd for Seq {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some((self.0.wrapping_sub(other.0) as i32).cmp(&0))
+    }
+}
+
+impl std::ops::Add<u32> for Seq {
+    type Output = Self;
+
+    fn add(self, rhs: u32) -> Self {
+        Self(self.0.wrapping_add(rhs))
+    }
+}
+
+impl std::ops::AddAssign<u32> for Seq {
+    fn add_assign(&mut self, rhs: u32) {
+        *self = *self + rhs;
+    }
+}
+
+impl std::ops::Sub<u32> for Seq {
+    type Output = Self;
+
+    fn sub(self, rhs: u32) -> Self {
+        Self(self.0.wrapping_sub(rhs))
+    }
+}
+
+impl std::fmt::Display for Seq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ordering() {
+        assert!(Seq::new(1) < Seq::new(2));
+        assert!(Seq::new(2) > Seq::new(1));
+        assert!(Seq::new(5) <= Seq::new(5));
+
+        // wrapping
+        assert!(Seq::new(u32::MAX) < Seq::new(0));
+        assert!(Seq::new(u32::MAX - 10) < Seq::new(10));
+        assert!(Seq::new(10) > Seq::new(u32::MAX - 10));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(Seq::new(u32::MAX) + 1, Seq::new(0));
+        assert_eq!(Seq::new(0) - 1, Seq::new(u32::MAX));
+        assert_eq!(Seq::new(5).since(Seq::new(u32::MAX - 4)), 10);
+
+        let mut x = Seq::new(u32::MAX - 1);
+        x += 3;
+        assert_eq!(x, Seq::new(1));
+    }
+
+    #[test]
+    fn test_range() {
+        assert!(Seq::new(0).in_range(Seq::new(u32::MAX), Seq::new(1)));
+        assert!(!Seq::new(1).in_range(Seq::new(u32::MAX), Seq::new(1)));
+        assert_eq!(Seq::new(u32::MAX).max(Seq::new(3)), Seq::new(3));
+        assert_eq!(Seq::new(u32::MAX).min(Seq::new(3)), Seq::new(u32::MAX));
+    }
+}
@@ -1,26 +1,29 @@
 use std::collections::LinkedList;
 use std::io::{Read, Write};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::Arc;
 
 use atomic_refcell::AtomicRefCell;
 use bytes::{Bytes, BytesMut};
 use linux_api::errno::Errno;
 use linux_api::ioctls::IoctlRequest;
-use nix::sys::socket::{AddressFamily, MsgFlags, Shutdown, SockaddrIn};
+use nix::sys::socket::{AddressFamily, MsgFlags, Shutdown};
 use shadow_shim_helper_rs::emulated_time::EmulatedTime;
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 
 use crate::core::worker::Worker;
 use crate::cshadow as c;
-use crate::host::descriptor::socket::inet::{self, InetSocket};
-use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs, ShutdownFlags};
+use crate::host::descriptor::socket::inet::{self, icmp, InetSocket, IpVersion};
+use crate::host::descriptor::socket::{
+    ControlMsgWriter, RecvmsgArgs, RecvmsgReturn, SendmsgArgs, ShutdownFlags,
+};
 use crate::host::descriptor::{
     File, FileMode, FileState, FileStatus, OpenFile, Socket, StateEventSource, StateListenerFilter,
     SyscallResult,
 };
 use crate::host::memory_manager::MemoryManager;
-use crate::host::network::interface::FifoPacketPriority;
+use crate::host::network;
+use crate::host::network::interface::{FifoPacketPriority, ReuseOptions};
 use crate::host::network::namespace::{AssociationHandle, NetworkNamespace};
 use crate::host::syscall::io::{write_partial, IoVec, IoVecReader, IoVecWriter};
 use crate::host::syscall_types::SyscallError;
@@ -40,9 +43,28 @@ pub struct UdpSocket {
     shutdown_status: ShutdownFlags,
     send_buffer: MessageBuffer<MessageSendHeader>,
     recv_buffer: MessageBuffer<MessageRecvHeader>,
+    /// Either `AF_INET` or `AF_INET6`.
+    domain: AddressFamily,
+    /// The `IPV6_V6ONLY` socket option.
+    ipv6_only: bool,
+    /// The IP version of packets that the socket can receive, which is updated when the socket is
+    /// bound or connected.
+    ip_version: IpVersion,
     peer_addr: Option<SocketAddrV4>,
     bound_addr: Option<SocketAddrV4>,
     association: Option<AssociationHandle>,
+    /// The `SO_REUSEADDR` and `SO_REUSEPORT` socket options.
+    reuse: ReuseOptions,
+    /// The `IP_TTL` socket option, or `None` to use the default TTL.
+    ttl: Option<u8>,
+    /// The `IP_RECVERR` socket option.
+    recv_err: bool,
+    /// An error from an ICMP error message that will be returned by the next syscall. Linux calls
+    /// this `sk_err`.
+    error: Option<Errno>,
+    /// Errors from ICMP error messages that can be read with `MSG_ERRQUEUE`. Errors are only
+    /// queued if `IP_RECVERR` is enabled.
+    error_queue: MessageBuffer<MessageErrorHeader>,
     /// The receive time of the last packet returned to the managed process during a call to
     /// `recvmsg()`. Used for `SIOCGSTAMP`.
     recv_time_of_last_read_packet: Option<EmulatedTime>,
@@ -55,6 +77,7 @@ pub struct UdpSocket {
 impl UdpSocket {
     pub fn new(
         status: FileStatus,
+        domain: AddressFamily,
         send_buf_size: usize,
         recv_buf_size: usize,
     ) -> Arc<AtomicRefCell<Self>> {
@@ -65,9 +88,17 @@ impl UdpSocket {
             shutdown_status: ShutdownFlags::empty(),
             send_buffer: MessageBuffer::new(send_buf_size),
             recv_buffer: MessageBuffer::new(recv_buf_size),
+            domain,
+            ipv6_only: false,
+            ip_version: Self::unbound_ip_version(domain, false),
             peer_addr: None,
             bound_addr: None,
             association: None,
+            reuse: ReuseOptions::default(),
+            ttl: None,
+            recv_err: false,
+            error: None,
+            error_queue: MessageBuffer::new(recv_buf_size),
             recv_time_of_last_read_packet: None,
             has_open_file: false,
             _counter: ObjectCounter::new("UdpSocket"),
@@ -78,6 +109,16 @@ impl UdpSocket {
         Arc::new(AtomicRefCell::new(socket))
     }
 
+    /// The IP version of an unconnected socket bound to the unspecified address.
+    fn unbound_ip_version(domain: AddressFamily, ipv6_only: bool) -> IpVersion {
+        match domain {
+            AddressFamily::Inet => IpVersion::V4,
+            AddressFamily::Inet6 if ipv6_only => IpVersion::V6,
+            AddressFamily::Inet6 => IpVersion::Any,
+            _ => unreachable!("Not an inet socket domain: {domain:?}"),
+        }
+    }
+
     pub fn get_status(&self) -> FileStatus {
         self.status
     }
@@ -110,6 +151,19 @@ impl UdpSocket {
     ) {
         packet.add_status(PacketStatus::RcvSocketProcessed);
 
+        if packet.protocol() == c::_ProtocolType_PICMP {
+            // an ICMP error message for a datagram that we sent
+            self.push_in_error(packet, cb_queue);
+            return;
+        }
+
+        if !self.ip_version.accepts(packet.is_ipv6()) {
+            // an IPv4 socket can't receive IPv6 packets, and an `IPV6_V6ONLY` socket can't receive
+            // IPv4 packets
+            packet.add_status(PacketStatus::RcvSocketDropped);
+            return;
+        }
+
         if let Some(peer_addr) = self.peer_addr {
             if peer_addr != packet.src_address() {
                 // connect(2): "If the socket sockfd is of type SOCK_DGRAM, then addr is the address
@@ -149,6 +203,7 @@ impl UdpSocket {
         let header = MessageRecvHeader {
             src: packet.src_address(),
             dst: packet.dst_address(),
+            is_ipv6: packet.is_ipv6(),
             recv_time,
         };
 
@@ -164,6 +219,51 @@ impl UdpSocket {
         self.refresh_readable_writable(cb_queue);
     }
 
+    /// Handle an ICMP error message for a datagram that this socket sent, like Linux's
+    /// `__udp4_lib_err()`.
+    fn push_in_error(&mut self, mut packet: PacketRc, cb_queue: &mut CallbackQueue) {
+        let header = packet.get_icmp();
+        let quote = network::icmp::parse_error_quote(&packet);
+        let error = network::icmp::error_errno(header.icmp_type, header.code);
+
+        let (Some(quote), Some((error, is_hard))) = (quote, error) else {
+            packet.add_status(PacketStatus::RcvSocketDropped);
+            return;
+        };
+
+        // a connected socket only receives errors for datagrams sent to its peer
+        if self.peer_addr.is_some_and(|peer| peer != quote.dst) {
+            packet.add_status(PacketStatus::RcvSocketDropped);
+            return;
+        }
+
+        if self.recv_err {
+            let error_header = MessageErrorHeader {
+                error,
+                icmp_type: header.icmp_type,
+                icmp_code: header.code,
+                offender: header.src,
+                dst: quote.dst,
+            };
+
+            // like Linux, the error is reported even if the error queue is full
+            if self
+                .error_queue
+                .push_message(quote.data.into(), error_header)
+                .is_ok()
+            {
+                packet.add_status(PacketStatus::RcvSocketBuffered);
+            }
+        } else if !is_hard || self.peer_addr.is_none() {
+            // without `IP_RECVERR`, only hard errors are reported, and only to connected sockets
+            packet.add_status(PacketStatus::RcvSocketDropped);
+            return;
+        }
+
+        self.error = Some(error);
+        self.refresh_readable_writable(cb_queue);
+    }
+
     pub fn pull_out_packet(&mut self, cb_queue: &mut CallbackQueue) -> Option<PacketRc> {
         // pop the message from the send buffer
         let Some((message, header)) = self.send_buffer.pop_message() else {
@@ -183,6 +283,10 @@ impl UdpSocket {
         // transfer the `Bytes` directly from the buffer to the packet without copying the bytes
 
         packet.set_udp(header.src, header.dst);
+        packet.set_ipv6(header.is_ipv6);
+        if let Some(ttl) = header.ttl {
+            packet.set_ttl(ttl);
+        }
         packet.set_payload(&message, priority);
         packet.add_status(PacketStatus::SndCreated);
 
@@ -203,7 +307,15 @@ impl UdpSocket {
         // do nothing for UDP
     }
 
-    pub fn getsockname(&self) -> Result<Option<SockaddrIn>, SyscallError> {
+    pub fn is_listening(&self) -> bool {
+        false
+    }
+
+    fn reuse_options(&self) -> ReuseOptions {
+        self.reuse
+    }
+
+    pub fn getsockname(&self) -> Result<Option<SocketAddr>, SyscallError> {
         let mut addr = self
             .bound_addr
             .unwrap_or(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
@@ -216,15 +328,22 @@ impl UdpSocket {
             }
         }
 
-        Ok(Some(addr.into()))
+        let is_ipv6 = self.ip_version.is_ipv6();
+        Ok(Some(inet::from_internal_addr(addr, is_ipv6, self.domain)))
     }
 
-    pub fn getpeername(&self) -> Result<Option<SockaddrIn>, SyscallError> {
-        Ok(Some(self.peer_addr.ok_or(Errno::ENOTCONN)?.into()))
+    pub fn getpeername(&self) -> Result<Option<SocketAddr>, SyscallError> {
+        let peer_addr = self.peer_addr.ok_or(Errno::ENOTCONN)?;
+        let is_ipv6 = self.ip_version.is_ipv6();
+        Ok(Some(inet::from_internal_addr(
+            peer_addr,
+            is_ipv6,
+            self.domain,
+        )))
     }
 
     pub fn address_family(&self) -> AddressFamily {
-        AddressFamily::Inet
+        self.domain
     }
 
     pub fn close(&mut self, cb_queue: &mut CallbackQueue) -> Result<(), SyscallError> {
@@ -250,12 +369,22 @@ impl UdpSocket {
             return Err(Errno::EFAULT.into());
         };
 
-        // if not an inet socket address
-        let Some(addr) = addr.as_inet() else {
+        let (domain, ipv6_only) = {
+            let socket = socket.borrow();
+            (socket.domain, socket.ipv6_only)
+        };
+
+        // if not an address of the socket's family
+        let Some(addr) = inet::inet_sockaddr(addr, domain) else {
             return Err(Errno::EINVAL.into());
         };
 
-        let addr: SocketAddrV4 = (*addr).into();
+        let ip_version = IpVersion::of_bind_addr(addr, ipv6_only);
+
+        // an IPv6 address that doesn't belong to any host
+        let Some((addr, _)) = inet::to_internal_addr(addr) else {
+            return Err(Errno::EADDRNOTAVAIL.into());
+        };
 
         {
             let socket = socket.borrow();
@@ -276,11 +405,15 @@ impl UdpSocket {
         // this will allow us to receive packets from any peer
         let unspecified_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
 
+        // the socket can share the address with other sockets if their options allow it
+        let reuse = socket.borrow().reuse_options();
+
         // associate the socket
         let (addr, handle) = inet::associate_socket(
             InetSocket::Udp(Arc::clone(socket)),
             addr,
             unspecified_addr,
+            reuse,
             net_ns,
             rng,
         )?;
@@ -289,6 +422,7 @@ impl UdpSocket {
         {
             let mut socket = socket.borrow_mut();
             socket.bound_addr = Some(addr);
+            socket.ip_version = ip_version;
             socket.association = Some(handle);
         }
 
@@ -343,20 +477,27 @@ impl UdpSocket {
             return Err(Errno::EINVAL.into());
         };
 
-        let dst_addr = match args.addr {
-            Some(addr) => match addr.as_inet() {
+        let (dst_addr, is_ipv6) = match args.addr {
+            Some(addr) => match inet::inet_sockaddr(&addr, socket_ref.domain) {
                 // an inet socket address
-                Some(x) => (*x).into(),
+                Some(x) => inet::to_internal_addr(x).ok_or(Errno::ENETUNREACH)?,
                 // not an inet socket address
                 None => return Err(Errno::EAFNOSUPPORT.into()),
             },
             // no destination address provided
             None => match socket_ref.peer_addr {
-                Some(x) => x,
+                Some(x) => (x, socket_ref.ip_version == IpVersion::V6),
                 None => return Err(Errno::EDESTADDRREQ.into()),
             },
         };
 
+        // for example an `IPV6_V6ONLY` socket sending to an IPv4-mapped address
+        if !socket_ref.ip_version.accepts(is_ipv6) {
+            return Err(Errno::ENETUNREACH.into());
+        }
+
+        inet::check_ipv6_route(net_ns, dst_addr, is_ipv6)?;
+
         if socket_ref.get_status().contains(FileStatus::NONBLOCK) {
             flags.insert(MsgFlags::MSG_DONTWAIT);
         }
@@ -392,6 +533,7 @@ impl UdpSocket {
                 InetSocket::Udp(Arc::clone(socket)),
                 local_addr,
                 unspecified_addr,
+                socket_ref.reuse_options(),
                 net_ns,
                 rng,
             )?;
@@ -402,6 +544,11 @@ impl UdpSocket {
 
         // run in a closure so that an early return doesn't skip checking if we should block
         let result = (|| {
+            // report an error from an earlier ICMP error message
+            if let Some(error) = socket_ref.error.take() {
+                return Err(error);
+            }
+
             // don't bother copying the bytes if we know the push will fail
             if !socket_ref.send_buffer.has_space() {
                 return Err(Errno::EWOULDBLOCK);
@@ -421,6 +568,8 @@ impl UdpSocket {
             let header = MessageSendHeader {
                 src: socket_ref.bound_addr.unwrap(),
                 dst: dst_addr,
+                is_ipv6,
+                ttl: socket_ref.ttl,
                 packet_priority,
             };
 
@@ -477,10 +626,21 @@ impl UdpSocket {
             flags.insert(MsgFlags::MSG_DONTWAIT);
         }
 
+        if flags.contains(MsgFlags::MSG_ERRQUEUE) {
+            let result = socket_ref.recv_error(args, mem);
+            socket_ref.refresh_readable_writable(cb_queue);
+            return result;
+        }
+
         let len: libc::size_t = args.iovs.iter().map(|x| x.len).sum();
 
         // run in a closure so that an early return doesn't skip checking if we should block
         let result = (|| {
+            // report an error from an earlier ICMP error message
+            if let Some(error) = socket_ref.error.take() {
+                return Err(error);
+            }
+
             // a temporary location to store the message and header if we popped them
             let message_storage;
             let header_storage;
@@ -525,7 +685,9 @@ impl UdpSocket {
 
             Ok(RecvmsgReturn {
                 return_val: return_val.try_into().unwrap(),
-                addr: Some(header.src.into()),
+                addr: Some(
+                    inet::from_internal_addr(header.src, header.is_ipv6, socket_ref.domain).into(),
+                ),
                 msg_flags: return_flags.bits(),
                 control_len: 0,
             })
@@ -547,9 +709,16 @@ impl UdpSocket {
                 });
             }
 
+            // also wake up for new errors, unless the error queue isn't empty (which would
+            // already set `SOCKET_ERROR`)
+            let mut wait_for = FileState::READABLE;
+            if socket_ref.error_queue.is_empty() {
+                wait_for.insert(FileState::SOCKET_ERROR);
+            }
+
             return Err(SyscallError::new_blocked(
                 File::Socket(Socket::Inet(InetSocket::Udp(socket.clone()))),
-                FileState::READABLE,
+                wait_for,
                 socket_ref.supports_sa_restart(),
             ));
         }
@@ -557,6 +726,74 @@ impl UdpSocket {
         Ok(result?)
     }
 
+    /// Read an error from the error queue for `recvmsg()` with `MSG_ERRQUEUE`, like Linux's
+    /// `ip_recv_error()`. This never blocks.
+    fn recv_error(
+        &mut self,
+        args: RecvmsgArgs,
+        mem: &mut MemoryManager,
+    ) -> Result<RecvmsgReturn, SyscallError> {
+        // like Linux, `MSG_PEEK` is ignored
+        let Some((message, header)) = self.error_queue.pop_message() else {
+            return Err(Errno::EAGAIN.into());
+        };
+
+        // the next queued error becomes the pending error
+        self.error = self.error_queue.peek_message().map(|(_, x)| x.error);
+
+        let len: libc::size_t = args.iovs.iter().map(|x| x.len).sum();
+
+        // truncate the payload if the payload is larger than the user-provided buffers
+        let truncated_message = &message[..std::cmp::min(len, message.len())];
+
+        let mut writer = IoVecWriter::new(args.iovs, mem);
+        writer
+            .write_all(truncated_message)
+            .map_err(|e| Errno::try_from(e).unwrap())?;
+
+        // a `struct sock_extended_err`, followed by the address of the host that sent the ICMP
+        // message (`SO_EE_OFFENDER()`)
+        let offender = SocketAddrV4::new(header.offender, 0);
+        let offender = inet::from_internal_addr(offender, false, self.domain);
+        let data: Vec<u8> = [
+            &u32::from(header.error).to_ne_bytes()[..],
+            &[
+                libc::SO_EE_ORIGIN_ICMP,
+                header.icmp_type,
+                header.icmp_code,
+                /* ee_pad= */ 0,
+            ],
+            &0u32.to_ne_bytes(), // ee_info
+            &0u32.to_ne_bytes(), // ee_data
+            &sockaddr_bytes(offender),
+        ]
+        .concat();
+
+        let (level, ty) = match self.domain {
+            AddressFamily::Inet6 => (libc::SOL_IPV6, libc::IPV6_RECVERR),
+            _ => (libc::SOL_IP, libc::IP_RECVERR),
+        };
+
+        let mut control = ControlMsgWriter::new(args.control_ptr.len());
+        control.push(level, ty, &data);
+
+        let mut return_flags = MsgFlags::MSG_ERRQUEUE;
+        return_flags.set(MsgFlags::MSG_TRUNC, truncated_message.len() < message.len());
+        return_flags.set(MsgFlags::MSG_CTRUNC, control.truncated);
+
+        let control = control.buf;
+        if !control.is_empty() {
+            mem.copy_to_ptr(args.control_ptr.slice(..control.len()), &control)?;
+        }
+
+        Ok(RecvmsgReturn {
+            return_val: truncated_message.len().try_into().unwrap(),
+            addr: Some(inet::from_internal_addr(header.dst, false, self.domain).into()),
+            msg_flags: return_flags.bits(),
+            control_len: control.len(),
+        })
+    }
+
     pub fn ioctl(
         &mut self,
         request: IoctlRequest,
@@ -599,6 +836,9 @@ impl UdpSocket {
                     return Err(Errno::ENOENT.into());
                 };
 
+                // the timestamp is measured by the host's realtime clock
+                let last_recv_time =
+                    Worker::with_active_host(|host| host.clock().realtime(last_recv_time)).unwrap();
                 let last_recv_time = (last_recv_time - EmulatedTime::UNIX_EPOCH)
                     .try_into()
                     .unwrap();
@@ -650,13 +890,25 @@ impl UdpSocket {
         rng: impl rand::Rng,
         _cb_queue: &mut CallbackQueue,
     ) -> Result<(), SyscallError> {
-        // if not an inet socket address
+        let domain = socket.borrow().domain;
+
+        // if not an address of the socket's family
         // TODO: handle an AF_UNSPEC socket address
-        let Some(peer_addr) = peer_addr.as_inet() else {
+        let Some(peer_addr) = inet::inet_sockaddr(peer_addr, domain) else {
             return Err(Errno::EINVAL.into());
         };
 
-        let mut peer_addr: std::net::SocketAddrV4 = (*peer_addr).into();
+        // an IPv6 address that doesn't belong to any host
+        let Some((mut peer_addr, is_ipv6)) = inet::to_internal_addr(peer_addr) else {
+            return Err(Errno::ENETUNREACH.into());
+        };
+
+        // for example an `IPV6_V6ONLY` socket connecting to an IPv4-mapped address
+        if !socket.borrow().ip_version.accepts(is_ipv6) {
+            return Err(Errno::ENETUNREACH.into());
+        }
+
+        inet::check_ipv6_route(net_ns, peer_addr, is_ipv6)?;
 
         // https://stackoverflow.com/a/22425796
         if peer_addr.ip().is_unspecified() {
@@ -722,6 +974,7 @@ impl UdpSocket {
                     InetSocket::Udp(Arc::clone(socket)),
                     local_addr,
                     unspecified_addr,
+                    socket_ref.reuse_options(),
                     net_ns,
                     rng,
                 )?;
@@ -731,6 +984,7 @@ impl UdpSocket {
             }
 
             socket_ref.peer_addr = Some(peer_addr);
+            socket_ref.ip_version = IpVersion::of_peer(is_ipv6);
         }
 
         Ok(())
@@ -764,12 +1018,13 @@ impl UdpSocket {
     }
 
     pub fn getsockopt(
-        &self,
+        &mut self,
         level: libc::c_int,
         optname: libc::c_int,
         optval_ptr: ForeignPtr<()>,
         optlen: libc::socklen_t,
         mem: &mut MemoryManager,
+        cb_queue: &mut CallbackQueue,
     ) -> Result<libc::socklen_t, SyscallError> {
         match (level, optname) {
             (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
@@ -789,7 +1044,8 @@ impl UdpSocket {
                 Ok(bytes_written as libc::socklen_t)
             }
             (libc::SOL_SOCKET, libc::SO_ERROR) => {
-                let error = 0;
+                let error = self.error.take().map(i32::from).unwrap_or(0);
+                self.refresh_readable_writable(cb_queue);
 
                 let optval_ptr = optval_ptr.cast::<libc::c_int>();
                 let bytes_written = write_partial(mem, &error, optval_ptr, optlen as usize)?;
@@ -797,7 +1053,7 @@ impl UdpSocket {
                 Ok(bytes_written as libc::socklen_t)
             }
             (libc::SOL_SOCKET, libc::SO_DOMAIN) => {
-                let domain = libc::AF_INET;
+                let domain = self.domain as libc::c_int;
 
                 let optval_ptr = optval_ptr.cast::<libc::c_int>();
                 let bytes_written = write_partial(mem, &domain, optval_ptr, optlen as usize)?;
@@ -826,10 +1082,52 @@ impl UdpSocket {
 
                 Ok(bytes_written as libc::socklen_t)
             }
+            (libc::SOL_SOCKET, libc::SO_REUSEADDR) => {
+                let reuse_addr = self.reuse.reuse_addr as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written = write_partial(mem, &reuse_addr, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            (libc::SOL_SOCKET, libc::SO_REUSEPORT) => {
+                let reuse_port = self.reuse.reuse_port as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written = write_partial(mem, &reuse_port, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
             (libc::SOL_SOCKET, _) => {
                 log::debug!("getsockopt called with unsupported level {level} and opt {optname}");
                 Err(Errno::ENOPROTOOPT.into())
             }
+            (libc::IPPROTO_IP, libc::IP_RECVERR) => {
+                let recv_err = self.recv_err as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written = write_partial(mem, &recv_err, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            (libc::IPPROTO_IP, libc::IP_TTL) => {
+                let ttl = self
+                    .ttl
+                    .map_or(c::CONFIG_IP_DEFAULT_TTL as libc::c_int, libc::c_int::from);
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written = write_partial(mem, &ttl, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) if self.domain == AddressFamily::Inet6 => {
+                let ipv6_only = self.ipv6_only as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written = write_partial(mem, &ipv6_only, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
             _ => {
                 log::debug!("getsockopt called with unsupported level {level} and opt {optname}");
                 Err(Errno::EOPNOTSUPP.into())
@@ -844,6 +1142,7 @@ impl UdpSocket {
         optval_ptr: ForeignPtr<()>,
         optlen: libc::socklen_t,
         mem: &MemoryManager,
+        cb_queue: &mut CallbackQueue,
     ) -> Result<(), SyscallError> {
         match (level, optname) {
             (libc::SOL_SOCKET, libc::SO_SNDBUF) => {
@@ -897,14 +1196,30 @@ impl UdpSocket {
 
                 self.recv_buffer
                     .set_soft_limit_bytes(val.try_into().unwrap());
+                self.error_queue
+                    .set_soft_limit_bytes(val.try_into().unwrap());
             }
             (libc::SOL_SOCKET, libc::SO_REUSEADDR) => {
-                // TODO: implement this, tor and tgen use it
-                log::warn!("setsockopt SO_REUSEADDR not yet implemented");
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                // like Linux, this only affects future calls to `bind()`
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.reuse.reuse_addr = mem.read(optval_ptr)? != 0;
             }
             (libc::SOL_SOCKET, libc::SO_REUSEPORT) => {
-                // TODO: implement this, tgen uses it
-                log::warn!("setsockopt SO_REUSEPORT not yet implemented");
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                // like Linux, this only affects future calls to `bind()`
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.reuse.reuse_port = mem.read(optval_ptr)? != 0;
             }
             (libc::SOL_SOCKET, libc::SO_KEEPALIVE) => {
                 // TODO: implement this, libevent uses it in
@@ -915,6 +1230,48 @@ impl UdpSocket {
                 // TODO: implement this, pkg.go.dev/net uses it
                 log::warn!("setsockopt SO_BROADCAST not yet implemented");
             }
+            (libc::IPPROTO_IP, libc::IP_TTL) => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.ttl = icmp::parse_ttl(mem.read(optval_ptr)?)?;
+            }
+            (libc::IPPROTO_IP, libc::IP_RECVERR) => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.recv_err = mem.read(optval_ptr)? != 0;
+
+                // like Linux, disabling the option discards any queued errors
+                if !self.recv_err {
+                    self.error_queue = MessageBuffer::new(self.error_queue.soft_limit_bytes());
+                    self.refresh_readable_writable(cb_queue);
+                }
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_V6ONLY) if self.domain == AddressFamily::Inet6 => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                // can't be changed after the socket has been bound
+                if self.bound_addr.is_some() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.ipv6_only = mem.read(optval_ptr)? != 0;
+                self.ip_version = Self::unbound_ip_version(self.domain, self.ipv6_only);
+            }
             _ => {
                 log::debug!("setsockopt called with unsupported level {level} and opt {optname}");
                 return Err(Errno::ENOPROTOOPT.into());
@@ -934,6 +1291,16 @@ impl UdpSocket {
             .add_listener(monitoring, filter, notify_fn)
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         self.event_source.add_legacy_listener(ptr);
     }
@@ -949,13 +1316,15 @@ impl UdpSocket {
     fn refresh_readable_writable(&mut self, cb_queue: &mut CallbackQueue) {
         let readable = !self.recv_buffer.is_empty();
         let writable = self.send_buffer.has_space();
+        let error = self.error.is_some() || !self.error_queue.is_empty();
 
         let readable = readable.then_some(FileState::READABLE).unwrap_or_default();
         let writable = writable.then_some(FileState::WRITABLE).unwrap_or_default();
+        let error = error.then_some(FileState::SOCKET_ERROR).unwrap_or_default();
 
         self.copy_state(
-            /* mask= */ FileState::READABLE | FileState::WRITABLE,
-            readable | writable,
+            /* mask= */ FileState::READABLE | FileState::WRITABLE | FileState::SOCKET_ERROR,
+            readable | writable | error,
             cb_queue,
         );
     }
@@ -991,6 +1360,10 @@ struct MessageSendHeader {
     src: SocketAddrV4,
     /// The destination address (for example the peer).
     dst: SocketAddrV4,
+    /// Whether the message will be sent in an IPv6 packet.
+    is_ipv6: bool,
+    /// The `IP_TTL` of the socket when the message was sent.
+    ttl: Option<u8>,
     /// The priority for the packet that we'll create in the future, given to us by the host.
     packet_priority: FifoPacketPriority,
 }
@@ -1004,13 +1377,29 @@ struct MessageRecvHeader {
     /// `IP_PKTINFO` to get the packet destination address.
     #[allow(dead_code)]
     dst: SocketAddrV4,
+    /// Whether the message was received in an IPv6 packet.
+    is_ipv6: bool,
     /// The time when the network interface received the message.
     recv_time: EmulatedTime,
 }
 
-/// A buffer of UDP messages and message headers.
+/// Non-payload data for a message in the error queue. The message is the quoted payload of the
+/// datagram that caused the error.
+#[derive(Debug)]
+struct MessageErrorHeader {
+    /// The error reported by the ICMP message.
+    error: Errno,
+    icmp_type: u8,
+    icmp_code: u8,
+    /// The address of the host or router that sent the ICMP message.
+    offender: Ipv4Addr,
+    /// The destination address of the datagram that caused the error.
+    dst: SocketAddrV4,
+}
+
+/// A buffer of datagram messages and message headers.
 #[derive(Debug)]
-struct MessageBuffer<Hdr> {
+pub(super) struct MessageBuffer<Hdr> {
     /// The message payloads and headers.
     // use a `LinkedList` so that socket buffers can shrink when they're empty (as opposed to
     // `VecDeque`)
@@ -1087,3 +1476,24 @@ impl<Hdr> MessageBuffer<Hdr> {
         self.soft_limit_bytes = soft_limit_bytes;
     }
 }
+
+/// The bytes of the `sockaddr_in` or `sockaddr_in6` for `addr`.
+fn sockaddr_bytes(addr: SocketAddr) -> Vec<u8> {
+    match addr {
+        SocketAddr::V4(addr) => [
+            &(libc::AF_INET as libc::sa_family_t).to_ne_bytes()[..],
+            &addr.port().to_be_bytes(),
+            &addr.ip().octets(),
+            &[0u8; 8],
+        ]
+        .concat(),
+        SocketAddr::V6(addr) => [
+            &(libc::AF_INET6 as libc::sa_family_t).to_ne_bytes()[..],
+            &addr.port().to_be_bytes(),
+            &addr.flowinfo().to_be_bytes(),
+            &addr.ip().octets(),
+            &addr.scope_id().to_ne_bytes(),
+        ]
+        .concat(),
+    }
+}
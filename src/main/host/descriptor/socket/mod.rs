@@ -8,12 +8,14 @@ use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 use unix::UnixSocket;
 
 use crate::cshadow as c;
-use crate::host::descriptor::{FileMode, FileState, FileStatus, OpenFile, SyscallResult};
+use crate::host::descriptor::{
+    FileMode, FileState, FileStatus, OpenFile, StateListenerFilter, SyscallResult,
+};
 use crate::host::memory_manager::MemoryManager;
 use crate::host::network::namespace::NetworkNamespace;
 use crate::host::syscall::io::IoVec;
 use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
-use crate::utility::callback_queue::CallbackQueue;
+use crate::utility::callback_queue::{CallbackQueue, Handle};
 use crate::utility::sockaddr::SockaddrStorage;
 use crate::utility::HostTreePointer;
 
@@ -210,12 +212,6 @@ impl SocketRef<'_> {
     enum_passthrough!(self, (), Unix, Inet;
         pub fn address_family(&self) -> nix::sys::socket::AddressFamily
     );
-
-    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager), Unix, Inet;
-        pub fn getsockopt(&self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
-                          optlen: libc::socklen_t, memory_manager: &mut MemoryManager)
-        -> Result<libc::socklen_t, SyscallError>
-    );
 }
 
 // file functions
@@ -247,6 +243,16 @@ impl SocketRefMut<'_> {
     enum_passthrough!(self, (request, arg_ptr, memory_manager), Unix, Inet;
         pub fn ioctl(&mut self, request: IoctlRequest, arg_ptr: ForeignPtr<()>, memory_manager: &mut MemoryManager) -> SyscallResult
     );
+    enum_passthrough!(self, (monitoring, filter, notify_fn), Unix, Inet;
+        pub fn add_listener(&mut self, monitoring: FileState, filter: StateListenerFilter,
+                            notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) + Send + Sync + 'static)
+                            -> Handle<(FileState, FileState)>
+    );
+    enum_passthrough!(self, (monitoring, filter, notify_fn), Unix, Inet;
+        pub fn add_exclusive_listener(&mut self, monitoring: FileState, filter: StateListenerFilter,
+                                      notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static)
+                                      -> Handle<(FileState, FileState)>
+    );
     enum_passthrough!(self, (ptr), Unix, Inet;
         pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>)
     );
@@ -283,15 +289,17 @@ impl SocketRefMut<'_> {
         pub fn address_family(&self) -> nix::sys::socket::AddressFamily
     );
 
-    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager), Unix, Inet;
-        pub fn getsockopt(&self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
-                          optlen: libc::socklen_t, memory_manager: &mut MemoryManager)
+    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager, cb_queue), Unix, Inet;
+        pub fn getsockopt(&mut self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
+                          optlen: libc::socklen_t, memory_manager: &mut MemoryManager,
+                          cb_queue: &mut CallbackQueue)
         -> Result<libc::socklen_t, SyscallError>
     );
 
-    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager), Unix, Inet;
+    enum_passthrough!(self, (level, optname, optval_ptr, optlen, memory_manager, cb_queue), Unix, Inet;
         pub fn setsockopt(&mut self, level: libc::c_int, optname: libc::c_int, optval_ptr: ForeignPtr<()>,
-                          optlen: libc::socklen_t, memory_manager: &MemoryManager)
+                          optlen: libc::socklen_t, memory_manager: &MemoryManager,
+                          cb_queue: &mut CallbackQueue)
         -> Result<(), SyscallError>
     );
 
@@ -373,3 +381,66 @@ pub struct RecvmsgReturn {
     /// The number of control data bytes read.
     pub control_len: libc::size_t,
 }
+
+/// The length of a control message header.
+const CMSG_HDR_LEN: usize = std::mem::size_of::<libc::cmsghdr>();
+
+// we read and write the control message headers as bytes
+const _: () =
+    assert!(CMSG_HDR_LEN == std::mem::size_of::<usize>() + 2 * std::mem::size_of::<libc::c_int>());
+
+/// Round up to the alignment of control messages (linux's `CMSG_ALIGN`).
+fn cmsg_align(len: usize) -> usize {
+    let align = std::mem::size_of::<usize>();
+    (len + align - 1) & !(align - 1)
+}
+
+/// Builds the control messages of a received message, following the truncation rules of linux's
+/// `put_cmsg()`.
+struct ControlMsgWriter {
+    buf: Vec<u8>,
+    /// The length of the plugin's control buffer.
+    capacity: usize,
+    /// Whether any control data didn't fit in the buffer.
+    truncated: bool,
+}
+
+impl ControlMsgWriter {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            capacity,
+            truncated: false,
+        }
+    }
+
+    /// The number of data bytes that would fit in the next control message.
+    fn data_space(&self) -> usize {
+        (self.capacity - self.buf.len()).saturating_sub(CMSG_HDR_LEN)
+    }
+
+    /// Add a control message, truncating it if there isn't enough room.
+    fn push(&mut self, level: libc::c_int, ty: libc::c_int, data: &[u8]) {
+        let remaining = self.capacity - self.buf.len();
+
+        if remaining < CMSG_HDR_LEN {
+            self.truncated = true;
+            return;
+        }
+
+        let mut cmsg_len = CMSG_HDR_LEN + data.len();
+        if cmsg_len > remaining {
+            self.truncated = true;
+            cmsg_len = remaining;
+        }
+
+        self.buf.extend(cmsg_len.to_ne_bytes());
+        self.buf.extend(level.to_ne_bytes());
+        self.buf.extend(ty.to_ne_bytes());
+        self.buf.extend(&data[..cmsg_len - CMSG_HDR_LEN]);
+
+        // pad to the start of the next control message
+        let space = std::cmp::min(cmsg_align(CMSG_HDR_LEN + data.len()), remaining);
+        self.buf.resize(self.buf.len() + space - cmsg_len, 0);
+    }
+}
@@ -5,24 +5,29 @@ use std::sync::{Arc, Weak};
 
 use atomic_refcell::AtomicRefCell;
 use linux_api::errno::Errno;
+use linux_api::fcntl::DescriptorFlags;
 use linux_api::ioctls::IoctlRequest;
 use nix::sys::socket::{MsgFlags, Shutdown};
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 
+use crate::core::worker::Worker;
 use crate::cshadow as c;
+use crate::host::descriptor::descriptor_table::DescriptorHandle;
 use crate::host::descriptor::shared_buf::{
     BufferHandle, BufferState, ReaderHandle, SharedBuf, WriterHandle,
 };
 use crate::host::descriptor::socket::abstract_unix_ns::AbstractUnixNamespace;
-use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs, Socket};
+use crate::host::descriptor::socket::{
+    cmsg_align, ControlMsgWriter, RecvmsgArgs, RecvmsgReturn, SendmsgArgs, Socket, CMSG_HDR_LEN,
+};
 use crate::host::descriptor::{
-    File, FileMode, FileState, FileStatus, OpenFile, StateEventSource, StateListenerFilter,
-    SyscallResult,
+    CompatFile, Descriptor, File, FileMode, FileState, FileStatus, OpenFile, StateEventSource,
+    StateListenerFilter, SyscallResult,
 };
 use crate::host::memory_manager::MemoryManager;
 use crate::host::network::namespace::NetworkNamespace;
-use crate::host::syscall::io::{IoVec, IoVecReader, IoVecWriter};
-use crate::host::syscall_types::SyscallError;
+use crate::host::syscall::io::{write_partial, IoVec, IoVecReader, IoVecWriter};
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
 use crate::utility::callback_queue::{CallbackQueue, Handle};
 use crate::utility::sockaddr::{SockaddrStorage, SockaddrUnix};
 use crate::utility::HostTreePointer;
@@ -60,6 +65,11 @@ impl UnixSocket {
                 socket_type,
                 namespace: Arc::clone(namespace),
                 has_open_file: false,
+                recv_ancillary: VecDeque::new(),
+                recv_write_pos: 0,
+                recv_read_pos: 0,
+                pass_cred: false,
+                peer_cred: None,
             };
 
             // may generate new events
@@ -254,26 +264,28 @@ impl UnixSocket {
 
     pub fn getsockopt(
         &self,
-        _level: libc::c_int,
-        _optname: libc::c_int,
-        _optval_ptr: ForeignPtr<()>,
-        _optlen: libc::socklen_t,
-        _memory_manager: &mut MemoryManager,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        memory_manager: &mut MemoryManager,
+        _cb_queue: &mut CallbackQueue,
     ) -> Result<libc::socklen_t, SyscallError> {
-        log::warn!("getsockopt() syscall not yet supported for unix sockets; Returning ENOSYS");
-        Err(Errno::ENOSYS.into())
+        self.common
+            .getsockopt(level, optname, optval_ptr, optlen, memory_manager)
     }
 
     pub fn setsockopt(
         &mut self,
-        _level: libc::c_int,
-        _optname: libc::c_int,
-        _optval_ptr: ForeignPtr<()>,
-        _optlen: libc::socklen_t,
-        _memory_manager: &MemoryManager,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        memory_manager: &MemoryManager,
+        _cb_queue: &mut CallbackQueue,
     ) -> Result<(), SyscallError> {
-        log::warn!("setsockopt() syscall not yet supported for unix sockets; Returning ENOSYS");
-        Err(Errno::ENOSYS.into())
+        self.common
+            .setsockopt(level, optname, optval_ptr, optlen, memory_manager)
     }
 
     pub fn pair(
@@ -285,6 +297,11 @@ impl UnixSocket {
         let socket_1 = UnixSocket::new(status, socket_type, namespace);
         let socket_2 = UnixSocket::new(status, socket_type, namespace);
 
+        // both sockets were created by the current process
+        let cred = current_cred();
+        socket_1.borrow_mut().common.peer_cred = Some(cred);
+        socket_2.borrow_mut().common.peer_cred = Some(cred);
+
         {
             let socket_1_ref = &mut *socket_1.borrow_mut();
             socket_1_ref
@@ -325,6 +342,17 @@ impl UnixSocket {
             .add_listener(monitoring, filter, notify_fn)
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.common
+            .event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         self.common.event_source.add_legacy_listener(ptr);
     }
@@ -1070,6 +1098,10 @@ impl Protocol for ConnOrientedInitial {
             queue_limit: backlog_to_queue_size(backlog),
         };
 
+        // like linux, connecting sockets will receive the credentials of the process that called
+        // listen()
+        common.peer_cred = Some(current_cred());
+
         // refresh the socket's file state
         new_state.refresh_file_state(common, cb_queue);
 
@@ -1103,6 +1135,7 @@ impl Protocol for ConnOrientedInitial {
         // inform the server socket of the incoming connection and get the server socket's new child
         // socket
         let server_mut = &mut *server.borrow_mut();
+        let server_cred = server_mut.common.peer_cred;
         let peer = match server_mut.protocol_state.queue_incoming_conn(
             &mut server_mut.common,
             self.bound_addr,
@@ -1159,6 +1192,8 @@ impl Protocol for ConnOrientedInitial {
         // increment the buffer's reader count
         let reader_handle = common.recv_buffer.borrow_mut().add_reader(cb_queue);
 
+        common.peer_cred = server_cred;
+
         let new_state = ConnOrientedConnected {
             bound_addr: self.bound_addr,
             peer_addr: Some(addr.into_owned()),
@@ -1292,6 +1327,7 @@ impl Protocol for ConnOrientedListening {
         cb_queue: &mut CallbackQueue,
     ) -> (ProtocolState, Result<(), SyscallError>) {
         self.queue_limit = backlog_to_queue_size(backlog);
+        common.peer_cred = Some(current_cred());
 
         // refresh the socket's file state
         self.refresh_file_state(common, cb_queue);
@@ -1339,6 +1375,9 @@ impl Protocol for ConnOrientedListening {
 
         let child_recv_buffer = Arc::clone(&child_socket.borrow_mut().common.recv_buffer);
 
+        // the child socket's peer is the connecting socket of the current process
+        child_socket.borrow_mut().common.peer_cred = Some(current_cred());
+
         let weak = Arc::downgrade(&child_socket);
         let send_buffer_handle = child_send_buffer.borrow_mut().add_listener(
             BufferState::WRITABLE | BufferState::NO_READERS,
@@ -1423,6 +1462,12 @@ impl Protocol for ConnOrientedConnected {
                 FileState::WRITABLE,
                 common.sent_len < common.send_limit || send_buffer.num_readers() == 0,
             );
+            // the peer has closed and won't send any more data
+            new_state.set(FileState::SOCKET_RDHUP, recv_buffer.num_writers() == 0);
+            new_state.set(
+                FileState::HUP,
+                recv_buffer.num_writers() == 0 && send_buffer.num_readers() == 0,
+            );
         }
 
         common.copy_state(/* mask= */ FileState::all(), new_state, cb_queue);
@@ -1459,13 +1504,18 @@ impl Protocol for ConnOrientedConnected {
         mem: &mut MemoryManager,
         cb_queue: &mut CallbackQueue,
     ) -> Result<libc::ssize_t, SyscallError> {
-        if !args.control_ptr.ptr().is_null() {
-            log::debug!("Unix sockets don't yet support control data for sendmsg()");
-            return Err(Errno::EINVAL.into());
-        }
+        let ancillary = read_control_msgs(args.control_ptr, mem)?;
 
         let recv_socket = common.resolve_destination(Some(&self.peer), args.addr)?;
-        let rv = common.sendmsg(socket, args.iovs, args.flags, &recv_socket, mem, cb_queue)?;
+        let rv = common.sendmsg(
+            socket,
+            args.iovs,
+            args.flags,
+            ancillary,
+            &recv_socket,
+            mem,
+            cb_queue,
+        )?;
 
         self.refresh_file_state(common, cb_queue);
 
@@ -1480,12 +1530,7 @@ impl Protocol for ConnOrientedConnected {
         mem: &mut MemoryManager,
         cb_queue: &mut CallbackQueue,
     ) -> Result<RecvmsgReturn, SyscallError> {
-        if !args.control_ptr.ptr().is_null() {
-            log::debug!("Unix sockets don't yet support control data for recvmsg()");
-            return Err(Errno::EINVAL.into());
-        }
-
-        let (rv, num_removed_from_buf, msg_flags) =
+        let (rv, num_removed_from_buf, msg_flags, ancillary) =
             common.recvmsg(socket, args.iovs, args.flags, mem, cb_queue)?;
         let num_removed_from_buf = u64::try_from(num_removed_from_buf).unwrap();
 
@@ -1500,11 +1545,14 @@ impl Protocol for ConnOrientedConnected {
 
         self.refresh_file_state(common, cb_queue);
 
+        let (control_len, control_flags) =
+            common.write_control_msgs(ancillary, args.control_ptr, args.flags, mem, cb_queue)?;
+
         Ok(RecvmsgReturn {
             return_val: rv.try_into().unwrap(),
             addr: self.peer_addr.map(Into::into),
-            msg_flags,
-            control_len: 0,
+            msg_flags: msg_flags | control_flags,
+            control_len,
         })
     }
 
@@ -1649,13 +1697,18 @@ impl Protocol for ConnLessInitial {
         mem: &mut MemoryManager,
         cb_queue: &mut CallbackQueue,
     ) -> Result<libc::ssize_t, SyscallError> {
-        if !args.control_ptr.ptr().is_null() {
-            log::debug!("Unix sockets don't yet support control data for sendmsg()");
-            return Err(Errno::EINVAL.into());
-        }
+        let ancillary = read_control_msgs(args.control_ptr, mem)?;
 
         let recv_socket = common.resolve_destination(self.peer.as_ref(), args.addr)?;
-        let rv = common.sendmsg(socket, args.iovs, args.flags, &recv_socket, mem, cb_queue)?;
+        let rv = common.sendmsg(
+            socket,
+            args.iovs,
+            args.flags,
+            ancillary,
+            &recv_socket,
+            mem,
+            cb_queue,
+        )?;
 
         let byte_data = ByteData {
             from_socket: self.this_socket.upgrade().unwrap(),
@@ -1686,12 +1739,7 @@ impl Protocol for ConnLessInitial {
         mem: &mut MemoryManager,
         cb_queue: &mut CallbackQueue,
     ) -> Result<RecvmsgReturn, SyscallError> {
-        if !args.control_ptr.ptr().is_null() {
-            log::debug!("Unix sockets don't yet support control data for recvmsg()");
-            return Err(Errno::EINVAL.into());
-        }
-
-        let (rv, num_removed_from_buf, msg_flags) =
+        let (rv, num_removed_from_buf, msg_flags, ancillary) =
             common.recvmsg(socket, args.iovs, args.flags, mem, cb_queue)?;
         let num_removed_from_buf = u64::try_from(num_removed_from_buf).unwrap();
 
@@ -1708,11 +1756,14 @@ impl Protocol for ConnLessInitial {
 
         self.refresh_file_state(common, cb_queue);
 
+        let (control_len, control_flags) =
+            common.write_control_msgs(ancillary, args.control_ptr, args.flags, mem, cb_queue)?;
+
         Ok(RecvmsgReturn {
             return_val: rv.try_into().unwrap(),
             addr: byte_data.from_addr.map(Into::into),
-            msg_flags,
-            control_len: 0,
+            msg_flags: msg_flags | control_flags,
+            control_len,
         })
     }
 
@@ -1839,6 +1890,17 @@ struct UnixSocketCommon {
     // should only be used by `OpenFile` to make sure there is only ever one `OpenFile` instance for
     // this file
     has_open_file: bool,
+    /// Ancillary data for messages in the receive buffer, in the order they were sent.
+    recv_ancillary: VecDeque<QueuedAncillary>,
+    /// The position in the receive buffer that the next message will be written to. For stream
+    /// sockets this is a byte offset, and for message-based sockets it's a message index.
+    recv_write_pos: u64,
+    /// The position in the receive buffer that the next message will be read from.
+    recv_read_pos: u64,
+    /// Whether `SO_PASSCRED` is set.
+    pass_cred: bool,
+    /// The peer's credentials, as returned by `SO_PEERCRED`.
+    peer_cred: Option<libc::ucred>,
 }
 
 impl UnixSocketCommon {
@@ -1861,6 +1923,13 @@ impl UnixSocketCommon {
             debug_panic!("When closing a unix socket, the CLOSED flag was not set");
         }
 
+        // release any files that were sent to us but never received; this is deferred since
+        // closing one of these files may require borrowing this socket
+        let ancillary = std::mem::take(&mut self.recv_ancillary);
+        if !ancillary.is_empty() {
+            cb_queue.add(move |_| drop(ancillary));
+        }
+
         Ok(())
     }
 
@@ -1967,6 +2036,7 @@ impl UnixSocketCommon {
         socket: &Arc<AtomicRefCell<UnixSocket>>,
         iovs: &[IoVec],
         flags: libc::c_int,
+        mut ancillary: Ancillary,
         peer: &Arc<AtomicRefCell<UnixSocket>>,
         mem: &mut MemoryManager,
         cb_queue: &mut CallbackQueue,
@@ -1996,7 +2066,7 @@ impl UnixSocketCommon {
 
         // run in a closure so that an early return doesn't return from the syscall handler
         let result = (|| {
-            let peer_ref = peer.borrow();
+            let mut peer_ref = peer.borrow_mut();
             let mut send_buffer = peer_ref.recv_buffer().borrow_mut();
 
             // if the buffer has no readers, the destination socket is closed
@@ -2062,6 +2132,19 @@ impl UnixSocketCommon {
             // if we successfully sent bytes, update the sent count
             self.sent_len += u64::try_from(num_copied).unwrap();
 
+            drop(send_buffer);
+
+            // linux attaches the sender's credentials if either socket has `SO_PASSCRED` set
+            if ancillary.cred.is_none() && (self.pass_cred || peer_ref.common.pass_cred) {
+                ancillary.cred = Some(current_cred());
+            }
+
+            // a stream socket doesn't send anything (including ancillary data) if no bytes were
+            // written
+            if self.socket_type != UnixSocketType::Stream || num_copied > 0 {
+                peer_ref.common.add_recv_message(num_copied, ancillary);
+            }
+
             Ok(num_copied)
         })();
 
@@ -2086,8 +2169,9 @@ impl UnixSocketCommon {
         flags: libc::c_int,
         mem: &mut MemoryManager,
         cb_queue: &mut CallbackQueue,
-    ) -> Result<(usize, usize, libc::c_int), SyscallError> {
-        let supported_flags = MsgFlags::MSG_DONTWAIT | MsgFlags::MSG_TRUNC;
+    ) -> Result<(usize, usize, libc::c_int, Ancillary), SyscallError> {
+        let supported_flags =
+            MsgFlags::MSG_DONTWAIT | MsgFlags::MSG_TRUNC | MsgFlags::MSG_CMSG_CLOEXEC;
 
         // if there's a flag we don't support, it's probably best to raise an error rather than do
         // the wrong thing
@@ -2119,12 +2203,49 @@ impl UnixSocketCommon {
                 return Err(Errno::EWOULDBLOCK);
             }
 
+            // a stream read never combines bytes that were sent with different ancillary data, so
+            // that the ancillary data is returned with the bytes it was sent with
+            let next_ancillary = self.recv_ancillary.front();
+            let limit = match (self.socket_type, next_ancillary) {
+                (UnixSocketType::Stream, Some(x)) if x.pos > self.recv_read_pos => {
+                    Some(x.pos - self.recv_read_pos)
+                }
+                (UnixSocketType::Stream, Some(x)) => Some(x.len),
+                _ => None,
+            };
+
+            let limited_iovs;
+            let iovs = match limit {
+                Some(limit) => {
+                    limited_iovs = truncate_iovs(iovs, limit.try_into().unwrap());
+                    &limited_iovs[..]
+                }
+                None => iovs,
+            };
+
+            // a connection-oriented socket may have reached EOF rather than having a message
+            let has_message = recv_buffer.has_data();
+
             let writer = IoVecWriter::new(iovs, mem);
 
             let (num_copied, num_removed_from_buf) = recv_buffer
                 .read(writer, cb_queue)
                 .map_err(|e| Errno::try_from(e).unwrap())?;
 
+            let read_start = self.recv_read_pos;
+            self.recv_read_pos += match self.socket_type {
+                UnixSocketType::Stream => u64::try_from(num_removed_from_buf).unwrap(),
+                UnixSocketType::Dgram | UnixSocketType::SeqPacket => has_message.into(),
+            };
+
+            // the ancillary data is returned with the first read that includes any of its bytes
+            let ancillary = match self.recv_ancillary.front() {
+                Some(x) if x.pos >= read_start && x.pos < self.recv_read_pos => {
+                    self.recv_ancillary.pop_front().unwrap().data
+                }
+                _ => Ancillary::default(),
+            };
+
             let mut msg_flags = 0;
 
             if flags.contains(MsgFlags::MSG_TRUNC)
@@ -2136,11 +2257,16 @@ impl UnixSocketCommon {
 
                 // we're a message-based socket and MSG_TRUNC is set, so return the total size of
                 // the message, not the number of bytes we read
-                Ok((num_removed_from_buf, num_removed_from_buf, msg_flags))
+                Ok((
+                    num_removed_from_buf,
+                    num_removed_from_buf,
+                    msg_flags,
+                    ancillary,
+                ))
             } else {
                 // We're a stream-based socket. Unlike TCP sockets, unix stream sockets ignore the
                 // MSG_TRUNC flag.
-                Ok((num_copied, num_removed_from_buf, msg_flags))
+                Ok((num_copied, num_removed_from_buf, msg_flags, ancillary))
             }
         })();
 
@@ -2158,6 +2284,175 @@ impl UnixSocketCommon {
         Ok(result?)
     }
 
+    /// Record that a message of `len` bytes was written to our receive buffer, along with its
+    /// ancillary data.
+    fn add_recv_message(&mut self, len: usize, ancillary: Ancillary) {
+        let len = u64::try_from(len).unwrap();
+        let pos = self.recv_write_pos;
+
+        self.recv_write_pos += match self.socket_type {
+            UnixSocketType::Stream => len,
+            UnixSocketType::Dgram | UnixSocketType::SeqPacket => 1,
+        };
+
+        if !ancillary.is_empty() {
+            self.recv_ancillary.push_back(QueuedAncillary {
+                pos,
+                len,
+                data: ancillary,
+            });
+        }
+    }
+
+    /// Write the ancillary data of a received message to the control buffer in plugin memory.
+    /// Received files are added to the active process' descriptor table. Returns the number of
+    /// control bytes written and any message flags.
+    fn write_control_msgs(
+        &self,
+        ancillary: Ancillary,
+        control_ptr: ForeignArrayPtr<u8>,
+        flags: libc::c_int,
+        mem: &mut MemoryManager,
+        cb_queue: &mut CallbackQueue,
+    ) -> Result<(libc::size_t, libc::c_int), SyscallError> {
+        let Ancillary { rights, cred } = ancillary;
+        let mut control = ControlMsgWriter::new(control_ptr.len());
+
+        // credentials are only received if `SO_PASSCRED` is set, even if the sender sent them
+        if self.pass_cred {
+            let cred = cred.unwrap_or(UNKNOWN_CRED);
+            let data: Vec<u8> = [
+                cred.pid.to_ne_bytes(),
+                cred.uid.to_ne_bytes(),
+                cred.gid.to_ne_bytes(),
+            ]
+            .concat();
+            control.push(libc::SOL_SOCKET, libc::SCM_CREDENTIALS, &data);
+        }
+
+        if !rights.is_empty() {
+            let mut descriptor_flags = DescriptorFlags::empty();
+            if flags & libc::MSG_CMSG_CLOEXEC != 0 {
+                descriptor_flags.insert(DescriptorFlags::FD_CLOEXEC);
+            }
+
+            // like linux, only install as many files as there is room for in the control buffer
+            let max_fds = control.data_space() / std::mem::size_of::<libc::c_int>();
+
+            let mut rights = rights.into_iter();
+            let mut discarded = Vec::new();
+            let mut fds = Vec::new();
+
+            Worker::with_active_process(|process| {
+                let mut desc_table = process.descriptor_table_borrow_mut();
+                for file in rights.by_ref().take(max_fds) {
+                    let mut desc = Descriptor::new(file);
+                    desc.set_flags(descriptor_flags);
+                    match desc_table.register_descriptor(desc) {
+                        Ok(fd) => fds.push(libc::c_int::from(fd)),
+                        Err(desc) => {
+                            discarded.push(desc.into_file());
+                            break;
+                        }
+                    }
+                }
+            })
+            .unwrap();
+
+            let data: Vec<u8> = fds.iter().flat_map(|fd| fd.to_ne_bytes()).collect();
+            if !data.is_empty() {
+                control.push(libc::SOL_SOCKET, libc::SCM_RIGHTS, &data);
+            }
+
+            // any remaining files are closed; this is deferred since closing one of these files
+            // may require borrowing this socket
+            discarded.extend(rights);
+            if !discarded.is_empty() {
+                control.truncated = true;
+                cb_queue.add(move |_| drop(discarded));
+            }
+        }
+
+        let msg_flags = if control.truncated {
+            libc::MSG_CTRUNC
+        } else {
+            0
+        };
+
+        let control = control.buf;
+        if !control.is_empty() {
+            mem.copy_to_ptr(control_ptr.slice(..control.len()), &control)?;
+        }
+
+        Ok((control.len(), msg_flags))
+    }
+
+    pub fn getsockopt(
+        &self,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        mem: &mut MemoryManager,
+    ) -> Result<libc::socklen_t, SyscallError> {
+        match (level, optname) {
+            (libc::SOL_SOCKET, libc::SO_PASSCRED) => {
+                let pass_cred = self.pass_cred as libc::c_int;
+
+                let optval_ptr = optval_ptr.cast::<libc::c_int>();
+                let bytes_written = write_partial(mem, &pass_cred, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            (libc::SOL_SOCKET, libc::SO_PEERCRED) => {
+                let peer_cred = self.peer_cred.unwrap_or(NO_PEER_CRED);
+
+                let optval_ptr = optval_ptr.cast::<libc::ucred>();
+                let bytes_written = write_partial(mem, &peer_cred, optval_ptr, optlen as usize)?;
+
+                Ok(bytes_written as libc::socklen_t)
+            }
+            _ => {
+                log::warn!(
+                    "getsockopt() option {optname} at level {level} not yet supported for unix \
+                     sockets; Returning ENOSYS"
+                );
+                Err(Errno::ENOSYS.into())
+            }
+        }
+    }
+
+    pub fn setsockopt(
+        &mut self,
+        level: libc::c_int,
+        optname: libc::c_int,
+        optval_ptr: ForeignPtr<()>,
+        optlen: libc::socklen_t,
+        mem: &MemoryManager,
+    ) -> Result<(), SyscallError> {
+        match (level, optname) {
+            (libc::SOL_SOCKET, libc::SO_PASSCRED) => {
+                type OptType = libc::c_int;
+
+                if usize::try_from(optlen).unwrap() < std::mem::size_of::<OptType>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let optval_ptr = optval_ptr.cast::<OptType>();
+                self.pass_cred = mem.read(optval_ptr)? != 0;
+
+                Ok(())
+            }
+            _ => {
+                log::warn!(
+                    "setsockopt() option {optname} at level {level} not yet supported for unix \
+                     sockets; Returning ENOSYS"
+                );
+                Err(Errno::ENOSYS.into())
+            }
+        }
+    }
+
     pub fn ioctl(
         &mut self,
         request: IoctlRequest,
@@ -2267,3 +2562,183 @@ struct ByteData {
     from_addr: Option<SockaddrUnix<libc::sockaddr_un>>,
     num_bytes: u64,
 }
+
+/// Ancillary data sent along with a message.
+#[derive(Default)]
+struct Ancillary {
+    /// Files sent with `SCM_RIGHTS`.
+    rights: Vec<CompatFile>,
+    /// The sender's credentials. These are only attached if they were sent with `SCM_CREDENTIALS`,
+    /// or if either socket had `SO_PASSCRED` set.
+    cred: Option<libc::ucred>,
+}
+
+impl Ancillary {
+    fn is_empty(&self) -> bool {
+        self.rights.is_empty() && self.cred.is_none()
+    }
+}
+
+/// Ancillary data for a message in a socket's receive buffer.
+struct QueuedAncillary {
+    /// The position of the message in the receive buffer. For stream sockets this is a byte
+    /// offset, and for message-based sockets it's a message index.
+    pos: u64,
+    /// The number of bytes in the message.
+    len: u64,
+    data: Ancillary,
+}
+
+/// The maximum number of files that can be sent in a single message (linux's `SCM_MAX_FD`).
+const SCM_MAX_FD: usize = 253;
+
+/// The maximum length of a control buffer for a sent message (linux's default
+/// `net.core.optmem_max`).
+const MAX_CONTROL_LEN: usize = 20480;
+
+/// The credentials received for a message that was sent without credentials, using linux's
+/// default "overflow" uid and gid.
+const UNKNOWN_CRED: libc::ucred = libc::ucred {
+    pid: 0,
+    uid: 65534,
+    gid: 65534,
+};
+
+/// The `SO_PEERCRED` credentials of a socket that has no peer.
+const NO_PEER_CRED: libc::ucred = libc::ucred {
+    pid: 0,
+    uid: libc::uid_t::MAX,
+    gid: libc::gid_t::MAX,
+};
+
+/// The credentials of the active process. Shadow doesn't emulate user or group ids, so these are
+/// the same as shadow's.
+fn current_cred() -> libc::ucred {
+    libc::ucred {
+        pid: Worker::active_process_id().unwrap().into(),
+        uid: nix::unistd::getuid().as_raw(),
+        gid: nix::unistd::getgid().as_raw(),
+    }
+}
+
+/// Returns true if the active process is allowed to send the credentials with `SCM_CREDENTIALS`.
+/// Like an unprivileged process in linux, a process can only send its own pid and its real or
+/// effective uid and gid.
+fn may_send_cred(cred: &libc::ucred) -> bool {
+    let current = current_cred();
+    let uids = [current.uid, nix::unistd::geteuid().as_raw()];
+    let gids = [current.gid, nix::unistd::getegid().as_raw()];
+
+    cred.pid == current.pid && uids.contains(&cred.uid) && gids.contains(&cred.gid)
+}
+
+/// Read the control messages of a message being sent. Files sent with `SCM_RIGHTS` are looked up
+/// in the active process' descriptor table.
+fn read_control_msgs(
+    control_ptr: ForeignArrayPtr<u8>,
+    mem: &MemoryManager,
+) -> Result<Ancillary, SyscallError> {
+    let mut ancillary = Ancillary::default();
+
+    if control_ptr.is_empty() {
+        return Ok(ancillary);
+    }
+
+    if control_ptr.len() > MAX_CONTROL_LEN {
+        return Err(Errno::ENOBUFS.into());
+    }
+
+    let mut control = vec![0u8; control_ptr.len()];
+    mem.copy_from_ptr(&mut control, control_ptr)?;
+
+    let mut offset = 0;
+
+    // like linux's `CMSG_NXTHDR()`, stop when there isn't room for another header
+    while control.len().saturating_sub(offset) >= CMSG_HDR_LEN {
+        let (len_bytes, rest) = control[offset..].split_at(std::mem::size_of::<usize>());
+        let (level_bytes, rest) = rest.split_at(std::mem::size_of::<libc::c_int>());
+        let type_bytes = &rest[..std::mem::size_of::<libc::c_int>()];
+
+        let cmsg_len = usize::from_ne_bytes(len_bytes.try_into().unwrap());
+        let cmsg_level = libc::c_int::from_ne_bytes(level_bytes.try_into().unwrap());
+        let cmsg_type = libc::c_int::from_ne_bytes(type_bytes.try_into().unwrap());
+
+        if cmsg_len < CMSG_HDR_LEN || cmsg_len > control.len() - offset {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let data = &control[offset + CMSG_HDR_LEN..offset + cmsg_len];
+        offset += cmsg_align(cmsg_len);
+
+        // linux ignores control messages for other levels
+        if cmsg_level != libc::SOL_SOCKET {
+            continue;
+        }
+
+        match cmsg_type {
+            libc::SCM_RIGHTS => {
+                let fds = data
+                    .chunks_exact(std::mem::size_of::<libc::c_int>())
+                    .map(|x| libc::c_int::from_ne_bytes(x.try_into().unwrap()));
+
+                if ancillary.rights.len() + fds.len() > SCM_MAX_FD {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let files = Worker::with_active_process(|process| {
+                    let desc_table = process.descriptor_table_borrow();
+                    fds.map(|fd| {
+                        let desc = DescriptorHandle::try_from(fd)
+                            .ok()
+                            .and_then(|fd| desc_table.get(fd))
+                            .ok_or(Errno::EBADF)?;
+                        Ok(desc.file().clone())
+                    })
+                    .collect::<Result<Vec<_>, Errno>>()
+                })
+                .unwrap()?;
+
+                ancillary.rights.extend(files);
+            }
+            libc::SCM_CREDENTIALS => {
+                if data.len() != std::mem::size_of::<libc::ucred>() {
+                    return Err(Errno::EINVAL.into());
+                }
+
+                let field = |i: usize| -> [u8; 4] { data[i * 4..(i + 1) * 4].try_into().unwrap() };
+                let cred = libc::ucred {
+                    pid: libc::pid_t::from_ne_bytes(field(0)),
+                    uid: libc::uid_t::from_ne_bytes(field(1)),
+                    gid: libc::gid_t::from_ne_bytes(field(2)),
+                };
+
+                if !may_send_cred(&cred) {
+                    return Err(Errno::EPERM.into());
+                }
+
+                ancillary.cred = Some(cred);
+            }
+            _ => {
+                log::debug!("Unsupported control message type {cmsg_type} for unix sockets");
+                return Err(Errno::EINVAL.into());
+            }
+        }
+    }
+
+    Ok(ancillary)
+}
+
+/// Return a copy of `iovs` that holds at most `limit` bytes.
+fn truncate_iovs(iovs: &[IoVec], limit: usize) -> Vec<IoVec> {
+    let mut remaining = limit;
+    iovs.iter()
+        .map(|iov| {
+            let len = std::cmp::min(iov.len, remaining);
+            remaining -= len;
+            IoVec {
+                base: iov.base,
+                len,
+            }
+        })
+        .collect()
+}
@@ -26,7 +26,6 @@
 #include "main/host/descriptor/descriptor.h"
 #include "main/host/descriptor/socket.h"
 #include "main/host/descriptor/tcp_cong.h"
-#include "main/host/descriptor/tcp_cong_reno.h"
 #include "main/host/descriptor/tcp_retransmit_tally.h"
 #include "main/host/protocol.h"
 #include "main/host/tracker.h"
@@ -244,6 +243,11 @@ struct _TCP {
     /* if I am a multiplexed child, I have a pointer to my parent */
     TCPChild* child;
 
+    /* the IP versions of the packets that we send and receive. a listening
+     * AF_INET6 socket may accept both, but a connection only ever uses one. */
+    bool allowIPv4;
+    bool allowIPv6;
+
     MAGIC_DECLARE;
 };
 
@@ -390,6 +394,32 @@ struct TCPCong_ *tcp_cong(TCP *tcp) {
     return &tcp->cong;
 }
 
+bool tcp_setCongestionControl(TCP* tcp, const char* name) {
+    MAGIC_ASSERT(tcp);
+
+    if (!tcpcong_isSupported(name)) {
+        return false;
+    }
+
+    if (tcp->cong.hooks == NULL) {
+        tcpcong_init(tcp, name);
+        return true;
+    }
+
+    /* keep the current window when switching algorithms on an existing socket */
+    guint32 cwnd = tcp->cong.cwnd;
+    tcp->cong.hooks->tcp_cong_delete(tcp);
+    tcpcong_init(tcp, name);
+    tcp->cong.cwnd = cwnd;
+
+    return true;
+}
+
+guint32 tcp_getPacketsInFlight(TCP* tcp) {
+    MAGIC_ASSERT(tcp);
+    return tcp->send.next - tcp->send.unacked;
+}
+
 void tcp_clearAllChildrenIfServer(TCP* tcp) {
     MAGIC_ASSERT(tcp);
     if(tcp->server && tcp->server->children) {
@@ -657,6 +687,30 @@ void tcp_disableReceiveBufferAutotuning(TCP* tcp) {
 static void _tcp_runCloseTimerExpiredTask(const Host* host, gpointer tcp, gpointer userData);
 static void _tcp_clearRetransmit(TCP* tcp, guint sequence);
 
+/* Disassociate the socket from the network interface. The address and ports must be in network byte
+ * order. */
+static void _tcp_disassociate(TCP* tcp, const Host* host, in_addr_t ip, in_port_t port,
+                              in_addr_t peerIP, in_port_t peerPort) {
+    MAGIC_ASSERT(tcp);
+
+    /* only sockets with a rust socket object were associated (children of servers aren't) */
+    if (tcp->rustSocket == NULL) {
+        return;
+    }
+
+    /* the interface holds a reference to the socket while it's associated, so if the socket is
+     * being dropped then it isn't associated */
+    const InetSocket* inetSocket = inetsocketweak_upgrade(tcp->rustSocket);
+    if (inetSocket == NULL) {
+        return;
+    }
+
+    CompatSocket socket = compatsocket_fromInetSocket(inetSocket);
+    host_disassociateInterface(host, &socket, PTCP, ip, port, peerIP, peerPort);
+
+    inetsocket_drop(inetSocket);
+}
+
 static void _tcp_setState(TCP* tcp, const Host* host, enum TCPState state) {
     MAGIC_ASSERT(tcp);
 
@@ -727,15 +781,14 @@ static void _tcp_setState(TCP* tcp, const Host* host, enum TCPState state) {
                     if((parent->state == TCPS_CLOSED) && (g_hash_table_size(parent->server->children) <= 0)) {
                         if (disassociate) {
                             /* this will unbind from the network interface and free socket */
-                            host_disassociateInterface(
-                                host, PTCP, sock_ip, sock_port, peer_ip, peer_port);
+                            _tcp_disassociate(parent, host, sock_ip, sock_port, peer_ip, peer_port);
                         }
                     }
                 }
 
                 if (disassociate) {
                     /* TODO: we should only be disassociating non-child sockets */
-                    host_disassociateInterface(host, PTCP, sock_ip, sock_port, peer_ip, peer_port);
+                    _tcp_disassociate(tcp, host, sock_ip, sock_port, peer_ip, peer_port);
                 }
             }
             break;
@@ -920,6 +973,7 @@ static Packet* _tcp_createPacketWithoutPayload(TCP* tcp, const Host* host,
     /* create the TCP packet. the ack, window, and timestamps will be set in _tcp_flush */
     Packet* packet = packet_new(host);
     packet_setTCP(packet, flags, sourceIP, sourcePort, destinationIP, destinationPort, sequence);
+    packet_setIPv6(packet, tcp_isIPv6(tcp));
     packet_addDeliveryStatus(packet, PDS_SND_CREATED);
 
     /* update sequence number */
@@ -1142,6 +1196,10 @@ static void _tcp_updateRTTEstimate(TCP* tcp, const Host* host, CSimulationTime t
         rtt = 1;
     }
 
+    if (tcp->cong.hooks->tcp_cong_rtt_sample_ev != NULL) {
+        tcp->cong.hooks->tcp_cong_rtt_sample_ev(tcp, now - timestamp);
+    }
+
     /* RFC 6298 (http://tools.ietf.org/html/rfc6298) */
     if(!tcp->timing.rttSmoothed) {
         /* first RTT measurement */
@@ -1429,6 +1487,13 @@ static void _tcp_flush(TCP* tcp, const Host* host) {
             tcp->error |= TCPE_RECEIVE_EOF;
             legacyfile_adjustStatus((LegacyFile*)tcp, STATUS_FILE_READABLE, TRUE);
         }
+        /* we won't receive any more data */
+        legacyfile_adjustStatus((LegacyFile*)tcp, STATUS_SOCKET_RDHUP, TRUE);
+
+        /* and if we can't send either, the connection is hung up */
+        if((tcp->flags & TCPF_LOCAL_CLOSED_WR) || (tcp->error & TCPE_CONNECTION_RESET)) {
+            legacyfile_adjustStatus((LegacyFile*)tcp, STATUS_FILE_HUP, TRUE);
+        }
     }
 
     if((tcp->error & TCPE_CONNECTION_RESET) && (tcp->flags & TCPF_RESET_SIGNALED)) {
@@ -2017,6 +2082,14 @@ static void _tcp_processPacket(LegacySocket* socket, const Host* host, Packet* p
     MAGIC_ASSERT(tcp);
     PacketTCPHeader* header = packet_getTCPHeader(packet);
 
+    /* a listening socket only accepts connections using the IP versions it's bound to */
+    bool isIPv6 = packet_isIPv6(packet);
+    if ((isIPv6 && !tcp->allowIPv6) || (!isIPv6 && !tcp->allowIPv4)) {
+        trace("dropping %s packet that the socket doesn't accept", isIPv6 ? "IPv6" : "IPv4");
+        packet_addDeliveryStatus(packet, PDS_RCV_SOCKET_DROPPED);
+        return;
+    }
+
     /* if packet is reset, don't process */
     if(header->flags & PTCP_RST) {
         /* @todo: not sure if this is handled correctly */
@@ -2085,11 +2158,14 @@ static void _tcp_processPacket(LegacySocket* socket, const Host* host, Packet* p
 
                 /* we need to multiplex a new child */
                 TCP* multiplexed = tcp_new(host, recvBufSize, sendBufSize);
+                /* children use the listener's congestion control, like linux */
+                tcp_setCongestionControl(multiplexed, tcpcong_nameStr(&tcp->cong));
                 Descriptor* desc = descriptor_fromLegacyTcp(multiplexed, /* flags= */ 0);
                 int handle = process_registerDescriptor(registerInProcess, desc);
 
                 multiplexed->child =
                     _tcpchild_new(multiplexed, tcp, handle, header->sourceIP, header->sourcePort);
+                tcp_setIPVersions(multiplexed, !isIPv6, isIPv6);
                 utility_debugAssert(
                     g_hash_table_lookup(tcp->server->children, &(multiplexed->child->key)) == NULL);
 
@@ -2821,6 +2897,18 @@ void tcp_setRustSocket(TCP* tcp, InetSocketWeak* rustSocket) {
     tcp->rustSocket = rustSocket;
 }
 
+void tcp_setIPVersions(TCP* tcp, bool allowIPv4, bool allowIPv6) {
+    MAGIC_ASSERT(tcp);
+    utility_debugAssert(allowIPv4 || allowIPv6);
+    tcp->allowIPv4 = allowIPv4;
+    tcp->allowIPv6 = allowIPv6;
+}
+
+bool tcp_isIPv6(TCP* tcp) {
+    MAGIC_ASSERT(tcp);
+    return tcp->allowIPv6;
+}
+
 TCP* tcp_new(const Host* host, guint receiveBufferSize, guint sendBufferSize) {
     TCP* tcp = g_new0(TCP, 1);
     MAGIC_INIT(tcp);
@@ -2831,9 +2919,8 @@ TCP* tcp_new(const Host* host, guint receiveBufferSize, guint sendBufferSize) {
     guint32 initial_window = 10;
     gint tcpSSThresh = 0;
 
-    /* in the future we'd like to support more congestion control types
-     * and allow it to be set as a host option */
-    tcp_cong_reno_init(tcp);
+    bool congSupported = tcp_setCongestionControl(tcp, host_getDefaultTcpCongestion(host));
+    utility_alwaysAssert(congSupported);
 
     tcp->send.window = initial_window;
     tcp->send.lastWindow = initial_window;
@@ -2855,6 +2942,10 @@ TCP* tcp_new(const Host* host, guint receiveBufferSize, guint sendBufferSize) {
 
     tcp->autotune.isEnabled = TRUE;
 
+    /* AF_INET sockets only ever use IPv4 */
+    tcp->allowIPv4 = true;
+    tcp->allowIPv6 = false;
+
     tcp->throttledOutput =
             priorityqueue_new((GCompareDataFunc)packet_compareTCPSequence, NULL, (GDestroyNotify)packet_unref);
     tcp->unorderedInput =
@@ -10,6 +10,7 @@
 #include <glib.h>
 #include <netinet/in.h>
 #include <netinet/tcp.h>
+#include <stdbool.h>
 #include <sys/un.h>
 
 #include "main/core/support/definitions.h"
@@ -44,6 +45,15 @@ TCP* tcp_new(const Host* host, guint receiveBufferSize, guint sendBufferSize);
 
 void tcp_setRustSocket(TCP* tcp, InetSocketWeak* rustSocket);
 
+/* Set the IP versions of the packets that the socket sends and receives. A
+ * connection uses exactly one version, but a listening AF_INET6 socket may
+ * accept connections using either. Child sockets use the version of the SYN
+ * packet that created them. */
+void tcp_setIPVersions(TCP* tcp, bool allowIPv4, bool allowIPv6);
+/* Returns true if the socket uses IPv6. A connected socket that uses IPv6
+ * sends IPv6 packets; a listening socket may also accept IPv4 connections. */
+bool tcp_isIPv6(TCP* tcp);
+
 // clang-format off
 /* Returns a positive number to indicate that we have not yet sent a SYN
  * packet, i.e., connect() has not been called.
@@ -72,6 +82,11 @@ gint tcp_acceptServerPeer(TCP* tcp, const Host* host, in_addr_t* ip, in_port_t*
                           gint* acceptedHandle);
 
 struct TCPCong_ *tcp_cong(TCP *tcp);
+/* Switch to the named congestion control algorithm. Returns false and leaves
+ * the current algorithm in place if the name isn't supported. */
+bool tcp_setCongestionControl(TCP* tcp, const char* name);
+/* The number of packets that have been sent but not yet acknowledged. */
+guint32 tcp_getPacketsInFlight(TCP* tcp);
 
 void tcp_clearAllChildrenIfServer(TCP* tcp);
 
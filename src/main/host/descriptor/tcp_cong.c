@@ -1,5 +1,47 @@
 #include "main/host/descriptor/tcp_cong.h"
 
+#include <stddef.h>
+#include <string.h>
+
+#include "main/host/descriptor/tcp_cong_bbr.h"
+#include "main/host/descriptor/tcp_cong_cubic.h"
+#include "main/host/descriptor/tcp_cong_reno.h"
+#include "main/utility/utility.h"
+
+typedef void (*TCPCongInit)(TCP *tcp);
+
+typedef struct TCPCongAlgorithm_ {
+    const char **name;
+    TCPCongInit init;
+} TCPCongAlgorithm;
+
+static const TCPCongAlgorithm algorithms_[] = {
+    {&TCP_CONG_RENO_NAME, tcp_cong_reno_init},
+    {&TCP_CONG_CUBIC_NAME, tcp_cong_cubic_init},
+    {&TCP_CONG_BBR_NAME, tcp_cong_bbr_init},
+};
+
+static const TCPCongAlgorithm *find_algorithm_(const char *name) {
+    for (size_t i = 0; i < sizeof(algorithms_) / sizeof(algorithms_[0]); i++) {
+        if (strcmp(*algorithms_[i].name, name) == 0) {
+            return &algorithms_[i];
+        }
+    }
+    return NULL;
+}
+
 const char* tcpcong_nameStr(const TCPCong *cong) {
     return cong->hooks->tcp_cong_name_str();
 }
+
+bool tcpcong_isSupported(const char* name) {
+    return find_algorithm_(name) != NULL;
+}
+
+void tcpcong_init(TCP *tcp, const char* name) {
+    const TCPCongAlgorithm *algorithm = find_algorithm_(name);
+    if (algorithm == NULL) {
+        utility_panic("Unsupported congestion control algorithm '%s'", name);
+    }
+    algorithm->init(tcp);
+}
@@ -3,6 +3,7 @@
 
 #include <stdbool.h>
 
+#include "lib/shadow-shim-helper-rs/shim_helper.h"
 #include "main/host/descriptor/tcp.h"
 
 // congestion event hooks
@@ -14,6 +15,7 @@ typedef void (*TCPCongNewAckEv)(TCP *tcp, guint32 n);
 typedef void (*TCPCongTimeoutEv)(TCP *tcp);
 typedef guint32 (*TCPCongSSThresh)(TCP *tcp);
 typedef const char* (*TCPCongNameStr)();
+typedef void (*TCPCongRTTSampleEv)(TCP *tcp, CSimulationTime rtt);
 
 typedef struct TCPCongHooks_ {
     TCPCongDelete tcp_cong_delete;
@@ -23,6 +25,8 @@ typedef struct TCPCongHooks_ {
     TCPCongTimeoutEv tcp_cong_timeout_ev;
     TCPCongSSThresh tcp_cong_ssthresh;
     TCPCongNameStr tcp_cong_name_str;
+    // optional, may be NULL
+    TCPCongRTTSampleEv tcp_cong_rtt_sample_ev;
 } TCPCongHooks;
 
 typedef struct TCPCong_ {
@@ -33,4 +37,12 @@ typedef struct TCPCong_ {
 
 const char* tcpcong_nameStr(const TCPCong *cong);
 
+// returns true if there is a congestion control algorithm with the given
+// linux name (for example "reno")
+bool tcpcong_isSupported(const char* name);
+
+// initialize the congestion control algorithm with the given linux name,
+// which must be supported
+void tcpcong_init(TCP *tcp, const char* name);
+
 #endif // SHD_TCP_CONG_H_
@@ -0,0 +1,349 @@
+#include "main/host/descriptor/tcp_cong_bbr.h"
+
+#include <math.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#include "lib/logger/logger.h"
+#include "main/core/worker.h"
+#include "main/host/descriptor/descriptor.h"
+#include "main/host/descriptor/tcp.h"
+#include "main/host/descriptor/tcp_cong.h"
+
+const char* TCP_CONG_BBR_NAME = "bbr";
+
+/*
+ * An approximation of BBR version 1
+ * (https://datatracker.ietf.org/doc/html/draft-cardwell-iccrg-bbr-congestion-control-00).
+ * The legacy TCP code doesn't pace packets, so the pacing gain is applied to
+ * the congestion window instead. Bandwidth is measured in packets per second
+ * and sampled once per round trip.
+ */
+
+/* 2/ln(2), the smallest gain that doubles the sending rate each round */
+#define BBR_HIGH_GAIN 2.885
+#define BBR_DRAIN_GAIN (1.0 / BBR_HIGH_GAIN)
+/* the number of rounds that the bandwidth max filter covers */
+#define BBR_BW_FILTER_ROUNDS 10
+/* how long a min_rtt measurement is valid for */
+#define BBR_MIN_RTT_WINDOW (10 * SIMTIME_ONE_SECOND)
+/* how long to stay in PROBE_RTT once the window has drained */
+#define BBR_PROBE_RTT_DURATION (200 * SIMTIME_ONE_MILLISECOND)
+#define BBR_MIN_CWND 4
+/* startup ends when the bandwidth grows less than this factor ... */
+#define BBR_FULL_BW_THRESH 1.25
+/* ... for this many rounds */
+#define BBR_FULL_BW_ROUNDS 3
+
+static const double pacing_gain_cycle_[] = {1.25, 0.75, 1, 1, 1, 1, 1, 1};
+#define BBR_CYCLE_LEN (sizeof(pacing_gain_cycle_) / sizeof(pacing_gain_cycle_[0]))
+
+typedef enum BBRMode_ {
+    BBR_STARTUP,
+    BBR_DRAIN,
+    BBR_PROBE_BW,
+    BBR_PROBE_RTT,
+} BBRMode;
+
+typedef struct CABBR_ {
+
+    BBRMode mode;
+
+    /* the bandwidth sample of each of the last BBR_BW_FILTER_ROUNDS rounds */
+    double bw_samples[BBR_BW_FILTER_ROUNDS];
+    /* the max of bw_samples */
+    double btl_bw;
+
+    /* the smallest rtt within BBR_MIN_RTT_WINDOW, or 0 if none */
+    CSimulationTime min_rtt;
+    CSimulationTime min_rtt_stamp;
+
+    /* round trip tracking */
+    guint64 delivered;
+    guint64 round_count;
+    guint64 next_round_delivered;
+    guint64 round_start_delivered;
+    CSimulationTime round_start_time;
+
+    /* startup full pipe detection */
+    double full_bw;
+    size_t full_bw_count;
+    bool filled_pipe;
+
+    /* PROBE_BW gain cycling */
+    size_t cycle_index;
+    CSimulationTime cycle_stamp;
+
+    /* the end of PROBE_RTT, or 0 if we are still waiting for the window to
+     * drain */
+    CSimulationTime probe_rtt_done_stamp;
+
+    /* the window to restore after PROBE_RTT or loss recovery */
+    guint32 prior_cwnd;
+
+    size_t duplicate_ack_n;
+    bool in_recovery;
+
+} CABBR;
+
+/* HELPERS *******************************************************/
+
+static double bbr_pacing_gain_(CABBR *bbr) {
+    switch (bbr->mode) {
+        case BBR_STARTUP: return BBR_HIGH_GAIN;
+        case BBR_DRAIN: return BBR_DRAIN_GAIN;
+        case BBR_PROBE_BW: return pacing_gain_cycle_[bbr->cycle_index];
+        case BBR_PROBE_RTT: return 1;
+    }
+    return 1;
+}
+
+/*
+ * The estimated bandwidth-delay product scaled by gain, in packets.
+ */
+static guint32 bbr_target_cwnd_(CABBR *bbr, double gain) {
+    if (bbr->btl_bw == 0 || bbr->min_rtt == 0) {
+        // no estimate yet
+        return TCP_MIN_CWND;
+    }
+
+    double bdp = bbr->btl_bw * bbr->min_rtt / SIMTIME_ONE_SECOND;
+    return MAX((guint32)ceil(gain * bdp), BBR_MIN_CWND);
+}
+
+/*
+ * Returns true if the ack ended a round trip.
+ */
+static bool bbr_update_round_(TCP *tcp, CABBR *bbr, guint32 n, CSimulationTime now) {
+    bbr->delivered += n;
+
+    if (bbr->delivered < bbr->next_round_delivered) {
+        return false;
+    }
+
+    if (bbr->round_start_time != 0 && now > bbr->round_start_time) {
+        double bw = ((double)(bbr->delivered - bbr->round_start_delivered)) * SIMTIME_ONE_SECOND /
+                    (now - bbr->round_start_time);
+        bbr->bw_samples[bbr->round_count % BBR_BW_FILTER_ROUNDS] = bw;
+
+        bbr->btl_bw = 0;
+        for (size_t i = 0; i < BBR_BW_FILTER_ROUNDS; i++) {
+            bbr->btl_bw = MAX(bbr->btl_bw, bbr->bw_samples[i]);
+        }
+    }
+
+    bbr->round_count++;
+    bbr->round_start_delivered = bbr->delivered;
+    bbr->round_start_time = now;
+    bbr->next_round_delivered = bbr->delivered + tcp_getPacketsInFlight(tcp);
+
+    return true;
+}
+
+static void bbr_check_full_pipe_(CABBR *bbr) {
+    if (bbr->filled_pipe) {
+        return;
+    }
+
+    if (bbr->btl_bw >= bbr->full_bw * BBR_FULL_BW_THRESH) {
+        // still growing
+        bbr->full_bw = bbr->btl_bw;
+        bbr->full_bw_count = 0;
+        return;
+    }
+
+    bbr->full_bw_count++;
+    if (bbr->full_bw_count >= BBR_FULL_BW_ROUNDS) {
+        bbr->filled_pipe = true;
+    }
+}
+
+static void bbr_enter_probe_bw_(CABBR *bbr, CSimulationTime now) {
+    bbr->mode = BBR_PROBE_BW;
+    // the next phase will be the probing phase
+    bbr->cycle_index = BBR_CYCLE_LEN - 1;
+    bbr->cycle_stamp = now;
+}
+
+static void bbr_enter_probe_rtt_(TCP *tcp, CABBR *bbr) {
+    debug("[CONG] desc %p transition_to_probe_rtt", (LegacyFile*)tcp);
+    bbr->mode = BBR_PROBE_RTT;
+    bbr->prior_cwnd = MAX(bbr->prior_cwnd, tcp_cong(tcp)->cwnd);
+    bbr->probe_rtt_done_stamp = 0;
+}
+
+static void bbr_update_mode_(TCP *tcp, CABBR *bbr, CSimulationTime now) {
+    guint32 inflight = tcp_getPacketsInFlight(tcp);
+
+    if (bbr->mode == BBR_STARTUP && bbr->filled_pipe) {
+        debug("[CONG] desc %p transition_to_drain", (LegacyFile*)tcp);
+        bbr->mode = BBR_DRAIN;
+    }
+
+    if (bbr->mode == BBR_DRAIN && inflight <= bbr_target_cwnd_(bbr, 1)) {
+        debug("[CONG] desc %p transition_to_probe_bw", (LegacyFile*)tcp);
+        bbr_enter_probe_bw_(bbr, now);
+    }
+
+    if (bbr->mode == BBR_PROBE_BW && now - bbr->cycle_stamp > bbr->min_rtt) {
+        bbr->cycle_index = (bbr->cycle_index + 1) % BBR_CYCLE_LEN;
+        bbr->cycle_stamp = now;
+    }
+
+    if (bbr->mode != BBR_PROBE_RTT && bbr->min_rtt != 0 &&
+        now > bbr->min_rtt_stamp + BBR_MIN_RTT_WINDOW) {
+        bbr_enter_probe_rtt_(tcp, bbr);
+    }
+
+    if (bbr->mode == BBR_PROBE_RTT) {
+        if (bbr->probe_rtt_done_stamp == 0 && inflight <= BBR_MIN_CWND) {
+            bbr->probe_rtt_done_stamp = now + BBR_PROBE_RTT_DURATION;
+        } else if (bbr->probe_rtt_done_stamp != 0 && now >= bbr->probe_rtt_done_stamp) {
+            bbr->min_rtt_stamp = now;
+            tcp_cong(tcp)->cwnd = MAX(tcp_cong(tcp)->cwnd, bbr->prior_cwnd);
+            bbr->prior_cwnd = 0;
+
+            if (bbr->filled_pipe) {
+                debug("[CONG] desc %p transition_to_probe_bw", (LegacyFile*)tcp);
+                bbr_enter_probe_bw_(bbr, now);
+            } else {
+                debug("[CONG] desc %p transition_to_startup", (LegacyFile*)tcp);
+                bbr->mode = BBR_STARTUP;
+            }
+        }
+    }
+}
+
+static void bbr_set_cwnd_(TCP *tcp, CABBR *bbr, guint32 n) {
+    guint32 cwnd = tcp_cong(tcp)->cwnd;
+
+    if (bbr->mode == BBR_PROBE_RTT) {
+        tcp_cong(tcp)->cwnd = MIN(cwnd, BBR_MIN_CWND);
+        return;
+    }
+
+    guint32 target = bbr_target_cwnd_(bbr, bbr_pacing_gain_(bbr));
+
+    if (bbr->filled_pipe) {
+        cwnd = MIN(cwnd + n, target);
+    } else if (cwnd < target || bbr->delivered < TCP_MIN_CWND) {
+        cwnd = cwnd + n;
+    }
+
+    tcp_cong(tcp)->cwnd = MAX(cwnd, BBR_MIN_CWND);
+}
+
+/*******************************************************************/
+
+static void tcp_cong_bbr_delete_(TCP *tcp) {
+    free(tcp_cong(tcp)->ca);
+}
+
+static void tcp_cong_bbr_duplicate_ack_ev_(TCP *tcp) {
+    CABBR *bbr = tcp_cong(tcp)->ca;
+
+    if (bbr->in_recovery) {
+        // keep the ack clock going, like reno's window inflation
+        tcp_cong(tcp)->cwnd += 1;
+        return;
+    }
+
+    bbr->duplicate_ack_n++;
+
+    if (bbr->duplicate_ack_n == 3) { // transition to fast recovery
+        debug("[CONG] desc %p three duplicate acks transition_to_fast_recovery", (LegacyFile*)tcp);
+
+        // bbr doesn't treat loss as a congestion signal, so the window isn't
+        // reduced; it is restored once recovery ends
+        bbr->prior_cwnd = MAX(bbr->prior_cwnd, tcp_cong(tcp)->cwnd);
+        bbr->in_recovery = true;
+    }
+}
+
+static bool tcp_cong_bbr_fast_recovery_(TCP *tcp) {
+    CABBR *bbr = tcp_cong(tcp)->ca;
+    return bbr->in_recovery;
+}
+
+static void tcp_cong_bbr_new_ack_ev_(TCP *tcp, guint32 n) {
+    CABBR *bbr = tcp_cong(tcp)->ca;
+    CSimulationTime now = worker_getCurrentSimulationTime();
+
+    bbr->duplicate_ack_n = 0;
+
+    if (bbr->in_recovery) {
+        bbr->in_recovery = false;
+        if (bbr->mode != BBR_PROBE_RTT) {
+            tcp_cong(tcp)->cwnd = MAX(tcp_cong(tcp)->cwnd, bbr->prior_cwnd);
+            bbr->prior_cwnd = 0;
+        }
+    }
+
+    if (bbr_update_round_(tcp, bbr, n, now)) {
+        bbr_check_full_pipe_(bbr);
+    }
+
+    bbr_update_mode_(tcp, bbr, now);
+    bbr_set_cwnd_(tcp, bbr, n);
+}
+
+static void tcp_cong_bbr_timeout_ev_(TCP *tcp) {
+    CABBR *bbr = tcp_cong(tcp)->ca;
+
+    bbr->duplicate_ack_n = 0;
+
+    // only send the retransmission until it's acked, then restore the window
+    bbr->prior_cwnd = MAX(bbr->prior_cwnd, tcp_cong(tcp)->cwnd);
+    bbr->in_recovery = true;
+    tcp_cong(tcp)->cwnd = BBR_MIN_CWND;
+
+    debug("[CONG] desc %p timeout", (LegacyFile*)tcp);
+}
+
+static guint32 tcp_cong_bbr_ssthresh_(TCP *tcp) {
+    // bbr doesn't use a slow start threshold
+    return INT32_MAX;
+}
+
+static const char* tcp_cong_bbr_name_str_() {
+    return TCP_CONG_BBR_NAME;
+}
+
+static void tcp_cong_bbr_rtt_sample_ev_(TCP *tcp, CSimulationTime rtt) {
+    CABBR *bbr = tcp_cong(tcp)->ca;
+    CSimulationTime now = worker_getCurrentSimulationTime();
+
+    bool expired = bbr->min_rtt != 0 && now > bbr->min_rtt_stamp + BBR_MIN_RTT_WINDOW;
+
+    if (bbr->min_rtt == 0 || rtt <= bbr->min_rtt || expired) {
+        bbr->min_rtt = rtt;
+        bbr->min_rtt_stamp = now;
+    }
+
+    if (expired && bbr->mode != BBR_PROBE_RTT) {
+        bbr_enter_probe_rtt_(tcp, bbr);
+    }
+}
+
+static const struct TCPCongHooks_ bbr_hooks_ = {
+    .tcp_cong_delete = tcp_cong_bbr_delete_,
+    .tcp_cong_duplicate_ack_ev = tcp_cong_bbr_duplicate_ack_ev_,
+    .tcp_cong_fast_recovery = tcp_cong_bbr_fast_recovery_,
+    .tcp_cong_new_ack_ev = tcp_cong_bbr_new_ack_ev_,
+    .tcp_cong_timeout_ev = tcp_cong_bbr_timeout_ev_,
+    .tcp_cong_ssthresh = tcp_cong_bbr_ssthresh_,
+    .tcp_cong_name_str = tcp_cong_bbr_name_str_,
+    .tcp_cong_rtt_sample_ev = tcp_cong_bbr_rtt_sample_ev_,
+};
+
+void tcp_cong_bbr_init(TCP *tcp) {
+    CABBR *bbr = calloc(1, sizeof(CABBR));
+    bbr->mode = BBR_STARTUP;
+    bbr->min_rtt_stamp = worker_getCurrentSimulationTime();
+
+    tcp_cong(tcp)->cwnd = TCP_MIN_CWND;
+    tcp_cong(tcp)->hooks = &bbr_hooks_;
+    tcp_cong(tcp)->ca = bbr;
+}
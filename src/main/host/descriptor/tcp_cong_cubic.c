@@ -0,0 +1,250 @@
+#include "main/host/descriptor/tcp_cong_cubic.h"
+
+#include <math.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#include "lib/logger/logger.h"
+#include "main/core/worker.h"
+#include "main/host/descriptor/descriptor.h"
+#include "main/host/descriptor/tcp.h"
+#include "main/host/descriptor/tcp_cong.h"
+
+const char* TCP_CONG_CUBIC_NAME = "cubic";
+
+/*
+ * CUBIC as described in RFC 8312 (https://tools.ietf.org/html/rfc8312). The
+ * window is measured in packets rather than bytes, like the rest of the legacy
+ * TCP code.
+ */
+
+/* the scaling constant that determines how aggressively the window grows */
+#define CUBIC_C 0.4
+/* the multiplicative decrease factor */
+#define CUBIC_BETA 0.7
+
+typedef enum CubicState_ {
+    CUBIC_SLOW_START,
+    CUBIC_CONG_AVOID,
+    CUBIC_FAST_RECOVERY,
+} CubicState;
+
+typedef struct CACubic_ {
+
+    CubicState state;
+
+    size_t duplicate_ack_n;
+
+    guint32 ssthresh;
+
+    /* the window size just before the last reduction */
+    double w_max;
+    /* the window size that the cubic function grows from and back to */
+    double origin_point;
+    /* the time it takes the cubic function to grow back to origin_point, in
+     * seconds */
+    double k;
+    /* the window that standard TCP would have, for the TCP-friendly region */
+    double w_est;
+    /* the start of the current congestion avoidance epoch, or 0 if a new
+     * epoch should be started on the next ack */
+    CSimulationTime epoch_start;
+
+    /* fractional window increases that haven't been applied yet */
+    double cong_avoid_increase;
+
+    /* the smallest rtt that we've measured, or 0 if none */
+    CSimulationTime min_rtt;
+
+} CACubic;
+
+/* HELPERS *******************************************************/
+
+/*
+ * Called when congestion is detected, before the window is reduced.
+ */
+static void cubic_on_congestion_(TCP *tcp, CACubic *cubic) {
+    double cwnd = tcp_cong(tcp)->cwnd;
+
+    cubic->epoch_start = 0;
+
+    /* fast convergence: if the window didn't grow back to the previous
+     * maximum, release some bandwidth for new flows */
+    if (cwnd < cubic->w_max) {
+        cubic->w_max = cwnd * (1.0 + CUBIC_BETA) / 2.0;
+    } else {
+        cubic->w_max = cwnd;
+    }
+
+    cubic->ssthresh = MAX((guint32)(cwnd * CUBIC_BETA), 2);
+}
+
+static void cubic_cong_avoid_(TCP *tcp, CACubic *cubic, guint32 n) {
+    if (n == 0) {
+        return;
+    }
+
+    CSimulationTime now = worker_getCurrentSimulationTime();
+    double cwnd = tcp_cong(tcp)->cwnd;
+
+    if (cubic->epoch_start == 0) {
+        /* start a new epoch */
+        cubic->epoch_start = now;
+        cubic->cong_avoid_increase = 0;
+        cubic->w_est = cwnd;
+
+        if (cwnd < cubic->w_max) {
+            cubic->k = cbrt((cubic->w_max - cwnd) / CUBIC_C);
+            cubic->origin_point = cubic->w_max;
+        } else {
+            cubic->k = 0;
+            cubic->origin_point = cwnd;
+        }
+    }
+
+    /* the target is the window one rtt from now */
+    double t = ((double)(now - cubic->epoch_start + cubic->min_rtt)) / SIMTIME_ONE_SECOND;
+    double target = CUBIC_C * pow(t - cubic->k, 3) + cubic->origin_point;
+
+    /* the TCP-friendly region: grow at least as fast as standard TCP would */
+    cubic->w_est += (3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA)) * n / cwnd;
+    target = MAX(target, cubic->w_est);
+
+    double increase_per_ack;
+    if (target > cwnd) {
+        /* like linux, grow by at most one packet for every two acked */
+        increase_per_ack = MIN((target - cwnd) / cwnd, 0.5);
+    } else {
+        /* grow very slowly near the plateau */
+        increase_per_ack = 1.0 / (100.0 * cwnd);
+    }
+
+    cubic->cong_avoid_increase += n * increase_per_ack;
+
+    while (cubic->cong_avoid_increase >= 1) {
+        cubic->cong_avoid_increase -= 1;
+        tcp_cong(tcp)->cwnd += 1;
+    }
+}
+
+/*******************************************************************/
+
+static void tcp_cong_cubic_delete_(TCP *tcp) {
+    free(tcp_cong(tcp)->ca);
+}
+
+static void tcp_cong_cubic_duplicate_ack_ev_(TCP *tcp) {
+    CACubic *cubic = tcp_cong(tcp)->ca;
+
+    if (cubic->state == CUBIC_FAST_RECOVERY) {
+        tcp_cong(tcp)->cwnd += 1;
+        return;
+    }
+
+    cubic->duplicate_ack_n++;
+
+    if (cubic->duplicate_ack_n == 3) { // transition to fast recovery
+        debug("[CONG] desc %p three duplicate acks transition_to_fast_recovery", (LegacyFile*)tcp);
+
+        cubic_on_congestion_(tcp, cubic);
+        tcp_cong(tcp)->cwnd = cubic->ssthresh + 3;
+
+        cubic->state = CUBIC_FAST_RECOVERY;
+    }
+}
+
+static bool tcp_cong_cubic_fast_recovery_(TCP *tcp) {
+    CACubic *cubic = tcp_cong(tcp)->ca;
+    return cubic->state == CUBIC_FAST_RECOVERY;
+}
+
+static void tcp_cong_cubic_new_ack_ev_(TCP *tcp, guint32 n) {
+    CACubic *cubic = tcp_cong(tcp)->ca;
+
+    cubic->duplicate_ack_n = 0;
+
+    switch (cubic->state) {
+        case CUBIC_SLOW_START: {
+            guint32 new_cwnd = tcp_cong(tcp)->cwnd + n;
+
+            if (new_cwnd >= cubic->ssthresh) {
+                // up the cwnd to ssthresh and then transition into congestion
+                // avoidance with the leftover acks
+                guint32 nleft = new_cwnd - cubic->ssthresh;
+                tcp_cong(tcp)->cwnd = cubic->ssthresh;
+                cubic->state = CUBIC_CONG_AVOID;
+                debug("[CONG] desc=%p transition_to_cong_avoid", (LegacyFile*)tcp);
+                cubic_cong_avoid_(tcp, cubic, nleft);
+            } else {
+                tcp_cong(tcp)->cwnd = new_cwnd;
+            }
+            break;
+        }
+        case CUBIC_FAST_RECOVERY: {
+            tcp_cong(tcp)->cwnd = cubic->ssthresh;
+            cubic->state = CUBIC_CONG_AVOID;
+            debug("[CONG] desc=%p transition_to_cong_avoid", (LegacyFile*)tcp);
+            cubic_cong_avoid_(tcp, cubic, n);
+            break;
+        }
+        case CUBIC_CONG_AVOID: {
+            cubic_cong_avoid_(tcp, cubic, n);
+            break;
+        }
+    }
+}
+
+static void tcp_cong_cubic_timeout_ev_(TCP *tcp) {
+    CACubic *cubic = tcp_cong(tcp)->ca;
+
+    cubic->duplicate_ack_n = 0;
+    cubic_on_congestion_(tcp, cubic);
+
+    // use the same window as the reno implementation after a timeout
+    tcp_cong(tcp)->cwnd = TCP_MIN_CWND;
+
+    // transition to slow start
+    cubic->state = CUBIC_SLOW_START;
+    debug("[CONG] desc %p transition_to_slow_start", (LegacyFile*)tcp);
+}
+
+static guint32 tcp_cong_cubic_ssthresh_(TCP *tcp) {
+    CACubic *cubic = tcp_cong(tcp)->ca;
+    return cubic->ssthresh;
+}
+
+static const char* tcp_cong_cubic_name_str_() {
+    return TCP_CONG_CUBIC_NAME;
+}
+
+static void tcp_cong_cubic_rtt_sample_ev_(TCP *tcp, CSimulationTime rtt) {
+    CACubic *cubic = tcp_cong(tcp)->ca;
+
+    if (cubic->min_rtt == 0 || rtt < cubic->min_rtt) {
+        cubic->min_rtt = rtt;
+    }
+}
+
+static const struct TCPCongHooks_ cubic_hooks_ = {
+    .tcp_cong_delete = tcp_cong_cubic_delete_,
+    .tcp_cong_duplicate_ack_ev = tcp_cong_cubic_duplicate_ack_ev_,
+    .tcp_cong_fast_recovery = tcp_cong_cubic_fast_recovery_,
+    .tcp_cong_new_ack_ev = tcp_cong_cubic_new_ack_ev_,
+    .tcp_cong_timeout_ev = tcp_cong_cubic_timeout_ev_,
+    .tcp_cong_ssthresh = tcp_cong_cubic_ssthresh_,
+    .tcp_cong_name_str = tcp_cong_cubic_name_str_,
+    .tcp_cong_rtt_sample_ev = tcp_cong_cubic_rtt_sample_ev_,
+};
+
+void tcp_cong_cubic_init(TCP *tcp) {
+    CACubic *cubic = calloc(1, sizeof(CACubic));
+    cubic->state = CUBIC_SLOW_START;
+    cubic->ssthresh = INT32_MAX;
+
+    // start with the same window as the reno implementation
+    tcp_cong(tcp)->cwnd = 1;
+    tcp_cong(tcp)->hooks = &cubic_hooks_;
+    tcp_cong(tcp)->ca = cubic;
+}
@@ -179,6 +179,7 @@ static const struct TCPCongHooks_ reno_hooks_ = {
     .tcp_cong_timeout_ev = tcp_cong_reno_timeout_ev_,
     .tcp_cong_ssthresh = tcp_cong_reno_ssthresh_,
     .tcp_cong_name_str = tcp_cong_reno_name_str_,
+    .tcp_cong_rtt_sample_ev = NULL,
 };
 
 void tcp_cong_reno_init(TCP *tcp) {
@@ -198,6 +199,7 @@ static const struct TCPCongHooks_ slow_start_hooks__ = {
     .tcp_cong_timeout_ev = NULL,
     .tcp_cong_ssthresh = NULL,
     .tcp_cong_name_str = NULL,
+    .tcp_cong_rtt_sample_ev = NULL,
 };
 
 static const struct TCPCongHooks_ fast_recovery_hooks__ = {
@@ -208,6 +210,7 @@ static const struct TCPCongHooks_ fast_recovery_hooks__ = {
     .tcp_cong_timeout_ev = NULL,
     .tcp_cong_ssthresh = NULL,
     .tcp_cong_name_str = NULL,
+    .tcp_cong_rtt_sample_ev = NULL,
 };
 
 /* slow start and cong avoidance have the same dupl act behavior */
@@ -219,6 +222,7 @@ static const struct TCPCongHooks_ cong_avoid_hooks__ = {
     .tcp_cong_timeout_ev = NULL,
     .tcp_cong_ssthresh = NULL,
     .tcp_cong_name_str = NULL,
+    .tcp_cong_rtt_sample_ev = NULL,
 };
 
 static inline const struct TCPCongHooks_ *slow_start_hooks_() {
@@ -5,6 +5,7 @@ use atomic_refcell::AtomicRefCell;
 use linux_api::errno::Errno;
 use linux_api::ioctls::IoctlRequest;
 use linux_api::posix_types::kernel_off_t;
+use linux_api::time::ClockId;
 use shadow_shim_helper_rs::{
     emulated_time::EmulatedTime, simulation_time::SimulationTime, syscall_types::ForeignPtr,
 };
@@ -23,6 +24,7 @@ use crate::utility::HostTreePointer;
 
 pub struct TimerFd {
     timer: Timer,
+    clock_id: ClockId,
     event_source: StateEventSource,
     status: FileStatus,
     state: FileState,
@@ -38,13 +40,14 @@ impl TimerFd {
     /// We wrap the new [`TimerFd`] in an [`Arc<AtomicRefCell>`] because we need to use a weak
     /// reference to internally support setting up callback functions that reference the [`TimerFd`]
     /// on timer expiration.
-    pub fn new(status: FileStatus) -> Arc<AtomicRefCell<Self>> {
+    pub fn new(clock_id: ClockId, status: FileStatus) -> Arc<AtomicRefCell<Self>> {
         // We need a circular reference here, so that the inner Timer can refer back to the outer
         // TimerFd when executing a callback that will mutate the TimerFd when the timer expires.
         Arc::new_cyclic(|weak| {
             let weak_cloned = weak.clone();
             AtomicRefCell::new(Self {
                 timer: Timer::new(move |_host| Self::timer_expired(&weak_cloned)),
+                clock_id,
                 event_source: StateEventSource::new(),
                 state: FileState::ACTIVE,
                 status,
@@ -68,15 +71,20 @@ impl TimerFd {
         });
     }
 
+    /// The clock that the timer's absolute expiration times are measured by.
+    pub fn clock_id(&self) -> ClockId {
+        self.clock_id
+    }
+
     /// Returns the number of expirations that have occured since the timer was last armed.
     fn get_timer_count(&self) -> u64 {
         self.timer.expiration_count()
     }
 
     /// Returns the relative duration until the next expiration event occurs if the timer is armed,
-    /// and `None` if the timer is disarmed.
-    pub fn get_timer_remaining(&self) -> Option<SimulationTime> {
-        self.timer.remaining_time()
+    /// and `None` if the timer is disarmed. The duration is measured by the host's monotonic clock.
+    pub fn get_timer_remaining(&self, host: &Host) -> Option<SimulationTime> {
+        self.timer.remaining_time(host)
     }
 
     /// Returns the relative duration over which the timer has been configured to periodically
@@ -223,6 +231,16 @@ impl TimerFd {
             .add_listener(monitoring, filter, notify_fn)
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        monitoring: FileState,
+        filter: StateListenerFilter,
+        notify_fn: impl Fn(FileState, FileState, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<(FileState, FileState)> {
+        self.event_source
+            .add_exclusive_listener(monitoring, filter, notify_fn)
+    }
+
     pub fn add_legacy_listener(&mut self, ptr: HostTreePointer<c::StatusListener>) {
         self.event_source.add_legacy_listener(ptr);
     }
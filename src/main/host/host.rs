@@ -1,21 +1,24 @@
 use std::cell::{Cell, Ref, RefCell, RefMut, UnsafeCell};
-use std::collections::BTreeMap;
-use std::ffi::{CStr, CString, OsString};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4};
 use std::num::NonZeroU8;
 use std::ops::{Deref, DerefMut};
 use std::os::unix::prelude::OsStringExt;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 
 use atomic_refcell::AtomicRefCell;
 use linux_api::signal::{siginfo_t, Signal};
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use logger::LogLevel;
 use once_cell::unsync::OnceCell;
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
 use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+use shadow_shim_helper_rs::host_clock::HostClock;
 use shadow_shim_helper_rs::rootedcell::rc::RootedRc;
 use shadow_shim_helper_rs::rootedcell::refcell::RootedRefCell;
 use shadow_shim_helper_rs::rootedcell::Root;
@@ -28,21 +31,28 @@ use shadow_tsc::Tsc;
 use vasi_sync::scmutex::SelfContainedMutexGuard;
 
 use crate::core::sim_config::PcapConfig;
-use crate::core::support::configuration::{ProcessFinalState, QDiscMode};
+use crate::core::support::configuration::{
+    ProcessFinalState, QDiscMode, RouterQueueOptions, TcpCongestion,
+};
 use crate::core::work::event::{Event, EventData};
 use crate::core::work::event_queue::EventQueue;
 use crate::core::work::task::TaskRef;
 use crate::core::worker::Worker;
 use crate::cshadow;
+use crate::host::descriptor::inotify::Inotify;
 use crate::host::descriptor::socket::abstract_unix_ns::AbstractUnixNamespace;
-use crate::host::network::interface::{FifoPacketPriority, NetworkInterface, PcapOptions};
+use crate::host::network::interface::{
+    FifoPacketPriority, NetworkInterface, PcapOptions, ReuseOptions,
+};
 use crate::host::network::namespace::NetworkNamespace;
 use crate::host::process::Process;
 use crate::host::thread::ThreadId;
+use crate::network::gilbert_elliott::{GilbertElliott, GilbertElliottState};
 use crate::network::relay::{RateLimit, Relay};
-use crate::network::router::Router;
+use crate::network::router::{self, Router};
 use crate::network::PacketDevice;
 use crate::utility;
+use crate::utility::callback_queue::CallbackQueue;
 #[cfg(feature = "perf_timers")]
 use crate::utility::perf_timer::PerfTimer;
 
@@ -54,6 +64,8 @@ pub struct HostParameters {
     pub hostname: CString,
     pub node_id: u32,
     pub ip_addr: libc::in_addr_t,
+    pub ipv6_addr: Option<Ipv6Addr>,
+    pub clock: HostClock,
     pub sim_end_time: EmulatedTime,
     pub requested_bw_down_bits: u64,
     pub requested_bw_up_bits: u64,
@@ -65,11 +77,15 @@ pub struct HostParameters {
     pub heartbeat_log_info: cshadow::LogInfoFlags,
     pub log_level: LogLevel,
     pub pcap_config: Option<PcapConfig>,
+    pub event_trace_enabled: bool,
+    pub tcp_congestion: TcpCongestion,
+    pub router_queue: RouterQueueOptions,
     pub qdisc: QDiscMode,
     pub init_sock_recv_buf_size: u64,
     pub autotune_recv_buf: bool,
     pub init_sock_send_buf_size: u64,
     pub autotune_send_buf: bool,
+    pub use_new_tcp: bool,
     pub native_tsc_frequency: u64,
     pub model_unblocked_syscall_latency: bool,
     pub max_unapplied_cpu_latency: SimulationTime,
@@ -112,6 +128,10 @@ pub struct Host {
 
     random: RefCell<Xoshiro256PlusPlus>,
 
+    // The state of the bursty loss model for each destination that we send
+    // packets to over a path with a Gilbert-Elliott loss model.
+    gilbert_elliott_states: RefCell<HashMap<IpAddr, GilbertElliottState>>,
+
     // The upstream router that will queue packets until we can receive them.
     // This only applies to the internet interface; the localhost interface
     // does not receive packets from a router.
@@ -130,6 +150,9 @@ pub struct Host {
     // map address to futex objects
     futex_table: RefCell<SyncSendPointer<cshadow::FutexTable>>,
 
+    // the inotify instances that receive filesystem events from this host's processes
+    inotify_instances: RefCell<Vec<Weak<AtomicRefCell<Inotify>>>>,
+
     #[cfg(feature = "perf_timers")]
     execution_timer: RefCell<PerfTimer>,
 
@@ -147,6 +170,9 @@ pub struct Host {
     data_dir_path: PathBuf,
     data_dir_path_cstring: CString,
 
+    // the trace of simulation events, if enabled
+    event_trace: RefCell<Option<event_trace::Writer<BufWriter<File>>>>,
+
     // virtual process and event id counter
     thread_id_counter: Cell<libc::pid_t>,
     event_id_counter: Cell<u64>,
@@ -233,6 +259,7 @@ impl Host {
             params.unblocked_vdso_latency,
             nix::unistd::getpid().as_raw(),
             params.native_tsc_frequency,
+            params.clock,
             params.shim_log_level,
             manager_shmem,
         );
@@ -250,6 +277,11 @@ impl Host {
 
         std::fs::create_dir_all(&data_dir_path).unwrap();
 
+        let event_trace = params.event_trace_enabled.then(|| {
+            let file = File::create(data_dir_path.join("events.jsonl")).unwrap();
+            event_trace::Writer::new(BufWriter::new(file))
+        });
+
         // Register using the param hints.
         // We already checked that the addresses are available, so fail if they are not.
 
@@ -272,6 +304,7 @@ impl Host {
                 params.id,
                 hostname,
                 public_ip,
+                params.ipv6_addr,
                 pcap_options,
                 params.qdisc,
                 dns,
@@ -281,7 +314,8 @@ impl Host {
         // Packets that are not for localhost or our public ip go to the router.
         // Use `Ipv4Addr::UNSPECIFIED` for the router to encode this for our
         // routing table logic inside of `Host::get_packet_device()`.
-        let router = Router::new(Ipv4Addr::UNSPECIFIED);
+        let router_queue = router::new_router_queue(&params.router_queue, &mut random.borrow_mut());
+        let router = Router::new(Ipv4Addr::UNSPECIFIED, router_queue);
         let relay_inet_out = Relay::new(
             RateLimit::BytesPerSecond(params.requested_bw_up_bits / 8),
             net_ns.internet.borrow().get_address(),
@@ -306,13 +340,16 @@ impl Host {
             relay_loopback: Arc::new(relay_loopback),
             tracker: RefCell::new(None),
             futex_table: RefCell::new(unsafe { SyncSendPointer::new(cshadow::futextable_new()) }),
+            inotify_instances: RefCell::new(Vec::new()),
             random,
+            gilbert_elliott_states: RefCell::new(HashMap::new()),
             shim_shmem,
             shim_shmem_lock: RefCell::new(None),
             cpu,
             net_ns,
             data_dir_path,
             data_dir_path_cstring,
+            event_trace: RefCell::new(event_trace),
             thread_id_counter,
             event_id_counter,
             packet_id_counter,
@@ -371,6 +408,30 @@ impl Host {
         &self.data_dir_path
     }
 
+    /// Add an event to the host's event trace at the current simulation time. `f` is only called
+    /// if the trace is enabled.
+    pub fn trace_event(&self, f: impl FnOnce() -> event_trace::Event) {
+        let mut writer = self.event_trace.borrow_mut();
+        let Some(writer) = writer.as_mut() else {
+            return;
+        };
+
+        // packets may be freed outside of the simulation, for example when the host is dropped
+        let Some(now) = Worker::current_time() else {
+            return;
+        };
+
+        let record = event_trace::Record {
+            time_ns: now.to_abs_simtime().as_nanos().try_into().unwrap(),
+            host_id: self.id().into(),
+            event: f(),
+        };
+
+        if let Err(e) = writer.write(&record) {
+            warn_once_then_debug!("(LOG_ONCE) Unable to write to the event trace: {e}");
+        }
+    }
+
     pub fn add_application(
         &self,
         start_time: SimulationTime,
@@ -436,21 +497,120 @@ impl Host {
     }
 
     pub fn resume(&self, pid: ProcessId, tid: ThreadId) {
-        let remove_process = {
-            let Some(processrc) = self.process_borrow(pid) else {
-                trace!("{pid:?} doesn't exist");
-                return;
-            };
+        // Clone the process reference, so that we don't hold a dynamically
+        // borrowed reference to the process list while running the process.
+        // e.g. `fork` adds to the list.
+        let Some(processrc) = self
+            .process_borrow(pid)
+            .map(|processrc| processrc.clone(self.root()))
+        else {
+            trace!("{pid:?} doesn't exist");
+            return;
+        };
+        let (exited, parent_id) = {
             Worker::set_active_process(&processrc);
             let process = processrc.borrow(self.root());
             process.resume(self, tid);
             Worker::clear_active_process();
-            process.borrow_zombie().is_some() && process.ppid().is_none()
+            (process.borrow_zombie().is_some(), process.ppid())
+        };
+        processrc.safely_drop(self.root());
+        if !exited {
+            return;
+        }
+        self.orphan_children(pid);
+        let remove_process = match parent_id {
+            Some(parent_id) => self.notify_parent_of_exit(parent_id, pid),
+            None => true,
         };
         if remove_process {
             trace!("Dropping orphan zombie process {pid:?}");
-            let process = self.processes.borrow_mut().remove(&pid).unwrap();
-            RootedRc::safely_drop(process, self.root());
+            self.reap_process(pid);
+        }
+    }
+
+    /// Notifies `parent_id` that its child `child_id` has exited. Returns
+    /// `true` if the child should be dropped immediately rather than waiting to
+    /// be reaped by the parent.
+    fn notify_parent_of_exit(&self, parent_id: ProcessId, child_id: ProcessId) -> bool {
+        let Some(parentrc) = self
+            .process_borrow(parent_id)
+            .map(|processrc| processrc.clone(self.root()))
+        else {
+            // The parent's children are orphaned when it exits, so it should
+            // still be here.
+            warn!("Parent {parent_id:?} of {child_id:?} doesn't exist");
+            return true;
+        };
+        let childrc = self
+            .process_borrow(child_id)
+            .map(|processrc| processrc.clone(self.root()))
+            .unwrap();
+        let reap = parentrc
+            .borrow(self.root())
+            .handle_child_exit(self, &childrc.borrow(self.root()));
+        parentrc.safely_drop(self.root());
+        childrc.safely_drop(self.root());
+        reap
+    }
+
+    /// Removes and drops `pid`, which must have exited. e.g. after it's been
+    /// reaped by its parent with `wait4`.
+    pub fn reap_process(&self, pid: ProcessId) {
+        let processrc = self.processes.borrow_mut().remove(&pid).unwrap();
+        {
+            let process = processrc.borrow(self.root());
+            debug_assert!(process.borrow_zombie().is_some());
+            process.clear_ppid();
+        }
+        RootedRc::safely_drop(processrc, self.root());
+    }
+
+    /// Ids of the processes whose parent is `parent_id`.
+    pub fn child_process_ids(&self, parent_id: ProcessId) -> Vec<ProcessId> {
+        self.processes
+            .borrow()
+            .iter()
+            .filter(|(_id, processrc)| processrc.borrow(self.root()).ppid() == Some(parent_id))
+            .map(|(id, _processrc)| *id)
+            .collect()
+    }
+
+    /// Adds `process`, newly created by `fork`, to the host, and schedules its
+    /// only thread to start.
+    pub fn add_and_schedule_forked_process(&self, process: RootedRc<RootedRefCell<Process>>) {
+        let (process_id, thread_id) = {
+            let process = process.borrow(self.root());
+            (process.id(), process.thread_group_leader_id())
+        };
+        let prev = self.processes.borrow_mut().insert(process_id, process);
+        assert!(prev.is_none());
+
+        let task = TaskRef::new(move |host| {
+            host.resume(process_id, thread_id);
+        });
+        self.schedule_task_with_delay(task, SimulationTime::ZERO);
+    }
+
+    /// Clears the parent of each child of `parent_id`, which has exited. Children
+    /// that have already exited are dropped, since nothing can reap them anymore.
+    fn orphan_children(&self, parent_id: ProcessId) {
+        let zombie_children: Vec<ProcessId> = self
+            .processes
+            .borrow()
+            .iter()
+            .filter_map(|(id, processrc)| {
+                let process = processrc.borrow(self.root());
+                if process.ppid() != Some(parent_id) {
+                    return None;
+                }
+                process.clear_ppid();
+                process.borrow_zombie().is_some().then_some(*id)
+            })
+            .collect();
+        for id in zombie_children {
+            trace!("Dropping orphan zombie process {id:?}");
+            self.reap_process(id);
         }
     }
 
@@ -499,6 +659,11 @@ impl Host {
         u32::from_be(addr).into()
     }
 
+    /// The host's IPv6 address, if it was configured with one.
+    pub fn default_ipv6(&self) -> Option<Ipv6Addr> {
+        self.net_ns.default_ipv6
+    }
+
     pub fn abstract_unix_namespace(
         &self,
     ) -> impl Deref<Target = Arc<AtomicRefCell<AbstractUnixNamespace>>> + '_ {
@@ -542,6 +707,49 @@ impl Host {
         RefMut::map(futex_table_ref, |r| unsafe { &mut *r.ptr() })
     }
 
+    /// Register an inotify instance so that it receives the filesystem events generated on this
+    /// host. The host only keeps a weak reference to it.
+    pub fn add_inotify(&self, inotify: &Arc<AtomicRefCell<Inotify>>) {
+        self.inotify_instances
+            .borrow_mut()
+            .push(Arc::downgrade(inotify));
+    }
+
+    /// Notify this host's inotify instances that the event `mask` (an `IN_*` event, possibly with
+    /// `IN_ISDIR`) occurred on the file at the absolute path `path`. Events are queued immediately,
+    /// so they are ordered by the simulated time of the file operations that caused them.
+    pub fn notify_inotify(&self, path: &Path, mask: u32) {
+        let instances: Vec<_> = {
+            let mut instances = self.inotify_instances.borrow_mut();
+            instances.retain(|x| x.strong_count() > 0);
+            instances.iter().filter_map(Weak::upgrade).collect()
+        };
+
+        if instances.is_empty() {
+            return;
+        }
+
+        // watches are on canonical paths, but the file itself may no longer exist
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        let Ok(parent) = parent.canonicalize() else {
+            return;
+        };
+        let path = parent.join(name);
+
+        trace!(
+            "inotify event {mask:#x} for {path:?} at {:?}",
+            Worker::current_time()
+        );
+
+        CallbackQueue::queue_and_run(|cb_queue| {
+            for inotify in instances {
+                inotify.borrow_mut().notify(&path, mask, cb_queue);
+            }
+        });
+    }
+
     #[allow(non_snake_case)]
     pub fn bw_up_kiBps(&self) -> u64 {
         self.params.requested_bw_up_bits / (8 * 1024)
@@ -577,6 +785,19 @@ impl Host {
         self.random.borrow_mut()
     }
 
+    /// Advance the Gilbert-Elliott loss model for the path to `dst` by one
+    /// packet, and return true if the packet is lost. The model's state is
+    /// kept per destination and uses the host's random source, so that the
+    /// losses are deterministic.
+    pub fn gilbert_elliott_is_lost(&self, dst: IpAddr, model: &GilbertElliott) -> bool {
+        let mut states = self.gilbert_elliott_states.borrow_mut();
+        let mut random = self.random.borrow_mut();
+        let state = states
+            .entry(dst)
+            .or_insert_with(|| GilbertElliottState::initial(model, &mut *random));
+        state.next_is_lost(model, &mut *random)
+    }
+
     pub fn get_new_event_id(&self) -> u64 {
         let res = self.event_id_counter.get();
         self.event_id_counter.set(res + 1);
@@ -669,6 +890,12 @@ impl Host {
 
         assert!(self.processes.borrow().is_empty());
 
+        if let Some(writer) = self.event_trace.borrow_mut().as_mut() {
+            if let Err(e) = writer.flush() {
+                warn!("Unable to flush the event trace: {e}");
+            }
+        }
+
         self.stop_execution_timer();
         #[cfg(feature = "perf_timers")]
         debug!(
@@ -687,6 +914,8 @@ impl Host {
                 let process = processrc.borrow(self.root());
                 process.stop(self);
                 Worker::clear_active_process();
+                // Its parent, if any, is also being freed, and won't reap it.
+                process.clear_ppid();
             }
 
             processrc.safely_drop(self.root());
@@ -764,6 +993,11 @@ impl Host {
         unsafe { &*self.shim_shmem.get() }
     }
 
+    /// The model of the error of the host's clocks relative to the simulation time.
+    pub fn clock(&self) -> HostClock {
+        self.shim_shmem().clock
+    }
+
     /// Returns `true` if the host has a process that contains the specified thread.
     pub fn has_thread(&self, virtual_tid: ThreadId) -> bool {
         for process in self.processes.borrow().values() {
@@ -875,12 +1109,18 @@ impl Host {
     ) {
         if let Some(iface) = self.interface_borrow(addr) {
             iface.add_data_source(socket_ptr);
-            match addr {
-                Ipv4Addr::LOCALHOST => self.relay_loopback.notify(self),
-                _ => self.relay_inet_out.notify(self),
-            };
+            self.notify_interface_has_packets(addr);
         }
     }
+
+    /// Call to trigger the forwarding of packets from the network interface with the given
+    /// address, for example after the host's network stack queued a packet on it.
+    pub fn notify_interface_has_packets(&self, addr: Ipv4Addr) {
+        match addr {
+            Ipv4Addr::LOCALHOST => self.relay_loopback.notify(self),
+            _ => self.relay_inet_out.notify(self),
+        };
+    }
 }
 
 impl Drop for Host {
@@ -904,7 +1144,7 @@ impl Drop for Host {
 mod export {
     use std::{
         ops::{Deref, DerefMut},
-        os::raw::c_char,
+        os::{raw::c_char, unix::ffi::OsStrExt},
         time::Duration,
     };
 
@@ -998,6 +1238,18 @@ mod export {
         hostrc.params.autotune_send_buf
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn host_getDefaultTcpCongestion(
+        hostrc: *const Host,
+    ) -> *const libc::c_char {
+        let hostrc = unsafe { hostrc.as_ref().unwrap() };
+        match hostrc.params.tcp_congestion {
+            TcpCongestion::Reno => unsafe { cshadow::TCP_CONG_RENO_NAME },
+            TcpCongestion::Cubic => unsafe { cshadow::TCP_CONG_CUBIC_NAME },
+            TcpCongestion::Bbr => unsafe { cshadow::TCP_CONG_BBR_NAME },
+        }
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn host_getConfiguredRecvBufSize(hostrc: *const Host) -> u64 {
         let hostrc = unsafe { hostrc.as_ref().unwrap() };
@@ -1052,6 +1304,19 @@ mod export {
         hostrc.data_dir_path_cstring.as_ptr()
     }
 
+    /// Notify the host's inotify instances of a filesystem event. `path` must be an absolute path.
+    #[no_mangle]
+    pub unsafe extern "C" fn host_notifyInotify(
+        hostrc: *const Host,
+        path: *const c_char,
+        mask: u32,
+    ) {
+        let hostrc = unsafe { hostrc.as_ref().unwrap() };
+        let path = unsafe { CStr::from_ptr(path) };
+        let path = Path::new(OsStr::from_bytes(path.to_bytes()));
+        hostrc.notify_inotify(path, mask);
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn host_doesInterfaceExist(
         hostrc: *const Host,
@@ -1082,12 +1347,13 @@ mod export {
         );
         hostrc
             .net_ns
-            .is_interface_available(protocol_type, src, dst)
+            .is_interface_available(protocol_type, src, dst, ReuseOptions::default())
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn host_disassociateInterface(
         hostrc: *const Host,
+        socket: *const cshadow::CompatSocket,
         protocol: cshadow::ProtocolType,
         bind_ip: in_addr_t,
         bind_port: in_port_t,
@@ -1104,10 +1370,12 @@ mod export {
         let bind_addr = SocketAddrV4::new(bind_ip, bind_port);
         let peer_addr = SocketAddrV4::new(peer_ip, peer_port);
 
-        // associate the interfaces corresponding to bind_addr with socket
-        hostrc
-            .net_ns
-            .disassociate_interface(protocol, bind_addr, peer_addr);
+        // disassociate the interfaces corresponding to bind_addr from socket
+        unsafe {
+            hostrc
+                .net_ns
+                .disassociate_interface(socket, protocol, bind_addr, peer_addr)
+        };
     }
 
     #[no_mangle]
@@ -9,8 +9,8 @@ use nix::fcntl::OFlag;
 use nix::sys::stat::Mode;
 use shadow_shim_helper_rs::ipc::IPCData;
 use shadow_shim_helper_rs::shim_event::{
-    ShimEventAddThreadParentRes, ShimEventAddThreadReq, ShimEventSyscall, ShimEventSyscallComplete,
-    ShimEventToShadow, ShimEventToShim,
+    ShimEventAddThreadParentRes, ShimEventAddThreadReq, ShimEventPrivatizeRegion, ShimEventSyscall,
+    ShimEventSyscallComplete, ShimEventToShadow, ShimEventToShim,
 };
 use shadow_shim_helper_rs::syscall_types::{ForeignPtr, SysCallArgs, SysCallReg};
 use shadow_shmem::allocator::ShMemBlock;
@@ -22,7 +22,7 @@ use super::syscall_condition::SysCallCondition;
 use crate::core::scheduler;
 use crate::core::worker::{Worker, WORKER_SHARED};
 use crate::cshadow;
-use crate::host::syscall_types::SyscallReturn;
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallReturn};
 use crate::utility::syscall;
 
 /// The ManagedThread's state after having been allowed to execute some code.
@@ -35,6 +35,9 @@ pub enum ResumeResult {
     ExitedThread(i32),
     /// The thread's process has exited.
     ExitedProcess,
+    /// The thread's process image was replaced by `execve`. The native
+    /// thread no longer exists.
+    ExecedProcess,
 }
 
 pub struct ManagedThread {
@@ -225,6 +228,16 @@ impl ManagedThread {
                         return ResumeResult::ExitedThread(return_code);
                     }
 
+                    let pid = u32::from(ctx.process.id());
+                    let tid = u32::from(ctx.thread.id());
+                    let number = syscall.syscall_args.number;
+                    ctx.host.trace_event(|| event_trace::Event::SyscallEnter {
+                        pid,
+                        tid,
+                        number,
+                        args: syscall.syscall_args.args.map(u64::from),
+                    });
+
                     let scr = unsafe {
                         cshadow::syscallhandler_make_syscall(
                             ctx.thread.csyscallhandler(),
@@ -232,10 +245,31 @@ impl ManagedThread {
                         )
                     };
 
+                    ctx.host.trace_event(|| event_trace::Event::SyscallExit {
+                        pid,
+                        tid,
+                        number,
+                        result: match scr {
+                            SyscallReturn::Done(d) => {
+                                event_trace::SyscallResult::Done(d.retval.into())
+                            }
+                            SyscallReturn::Block(_) => event_trace::SyscallResult::Blocked,
+                            SyscallReturn::Native => event_trace::SyscallResult::Native,
+                        },
+                    });
+
                     // remove the mthread's old syscall condition since it's no longer needed
                     ctx.thread.cleanup_syscall_condition();
 
-                    assert!(self.is_running());
+                    if !self.is_running() {
+                        // The syscall (`execve`) replaced the process image. The
+                        // native thread is gone and there's nobody to return a
+                        // result to; the new image runs on a new mthread.
+                        if let SyscallReturn::Block(b) = scr {
+                            drop(unsafe { SysCallCondition::consume_from_c(b.cond) });
+                        }
+                        return ResumeResult::ExecedProcess;
+                    }
 
                     // Flush any writes that legacy C syscallhandlers may have
                     // made.
@@ -302,7 +336,70 @@ impl ManagedThread {
     ) -> Result<ManagedThread, linux_api::errno::Errno> {
         let child_ipc_shmem =
             Arc::new(shadow_shmem::allocator::Allocator::global().alloc(IPCData::new()));
-        {
+        let req = ShimEventAddThreadReq {
+            ipc_block: child_ipc_shmem.serialize(),
+            flags,
+            child_stack,
+            ptid: ptid.cast::<()>(),
+            ctid: ctid.cast::<()>(),
+            newtls,
+            privatize_regions: ForeignPtr::null(),
+            privatize_regions_len: 0,
+        };
+        self.native_clone_internal(ctx, child_ipc_shmem, req)
+            .map(|(mthread, _parent_res)| mthread)
+    }
+
+    /// Execute the specified `clone` syscall in `self`, creating a new native
+    /// *process* (i.e. `flags` doesn't include `CLONE_VM`), and create a new
+    /// `ManagedThread` object to manage its only thread. The new thread will be
+    /// managed by Shadow, and suitable for use with `Thread::wrap_mthread`.
+    ///
+    /// `privatize_regions` must list all regions of `self`'s address space
+    /// that are shared with Shadow, and must itself be in memory that isn't
+    /// shared. The child makes its own private copies of these regions before
+    /// either process continues.
+    ///
+    /// Also returns the shim's description of the calling thread at the time of
+    /// the `clone` syscall, such as its stack pointer.
+    ///
+    /// If the `clone` syscall fails, the native error is returned.
+    pub fn native_fork(
+        &self,
+        ctx: &ThreadContext,
+        flags: libc::c_ulong,
+        child_stack: ForeignPtr<()>,
+        newtls: libc::c_ulong,
+        privatize_regions: ForeignArrayPtr<ShimEventPrivatizeRegion>,
+    ) -> Result<(ManagedThread, ShimEventAddThreadParentRes), linux_api::errno::Errno> {
+        assert_eq!(flags & (libc::CLONE_VM as libc::c_ulong), 0);
+        let child_ipc_shmem =
+            Arc::new(shadow_shmem::allocator::Allocator::global().alloc(IPCData::new()));
+        let req = ShimEventAddThreadReq {
+            ipc_block: child_ipc_shmem.serialize(),
+            flags,
+            child_stack,
+            ptid: ForeignPtr::null(),
+            ctid: ForeignPtr::null(),
+            newtls,
+            privatize_regions: privatize_regions.ptr().cast::<()>(),
+            privatize_regions_len: privatize_regions.len().try_into().unwrap(),
+        };
+        self.native_clone_internal(ctx, child_ipc_shmem, req)
+    }
+
+    /// Helper for `native_clone` and `native_fork`. `req.ipc_block` must be
+    /// the serialized `child_ipc_shmem`.
+    fn native_clone_internal(
+        &self,
+        ctx: &ThreadContext,
+        child_ipc_shmem: Arc<ShMemBlock<'static, IPCData>>,
+        req: ShimEventAddThreadReq,
+    ) -> Result<(ManagedThread, ShimEventAddThreadParentRes), linux_api::errno::Errno> {
+        let is_new_process = req.flags & (libc::CLONE_VM as libc::c_ulong) == 0;
+
+        // Until the child exists, close its IPC channel if *this* process dies.
+        let parent_watch_handle = {
             let child_ipc_shmem = child_ipc_shmem.clone();
             WORKER_SHARED
                 .borrow()
@@ -315,26 +412,35 @@ impl ManagedThread {
         };
 
         // Send the IPC block for the new mthread to use.
-        let clone_res: i64 = match self.continue_plugin(
-            ctx.host,
-            &ShimEventToShim::AddThreadReq(ShimEventAddThreadReq {
-                ipc_block: child_ipc_shmem.serialize(),
-                flags,
-                child_stack,
-                ptid: ptid.cast::<()>(),
-                ctid: ctid.cast::<()>(),
-                newtls,
-            }),
-        ) {
-            ShimEventToShadow::AddThreadParentRes(ShimEventAddThreadParentRes { clone_res }) => {
-                clone_res
-            }
+        let parent_res = match self.continue_plugin(ctx.host, &ShimEventToShim::AddThreadReq(req)) {
+            ShimEventToShadow::AddThreadParentRes(res) => res,
             r => panic!("Unexpected result: {r:?}"),
         };
-        let clone_res: SysCallReg = syscall::raw_return_value_to_result(clone_res)?;
-        let child_native_tid = libc::pid_t::from(clone_res);
+        let clone_res: SysCallReg = syscall::raw_return_value_to_result(parent_res.clone_res)?;
+        let child_native_tid = nix::unistd::Pid::from_raw(libc::pid_t::from(clone_res));
         trace!("native clone treated tid {child_native_tid}");
 
+        let child_native_pid = if is_new_process {
+            // The child is a new process, which will outlive this one if this
+            // one exits first; track it separately. As with processes created
+            // by `spawn`, the child is a child of Shadow itself (`CLONE_PARENT`),
+            // so won't be reaped until we do so.
+            let worker_shared = WORKER_SHARED.borrow();
+            let child_pid_watcher = worker_shared.as_ref().unwrap().child_pid_watcher();
+            child_pid_watcher.register_pid(child_native_tid);
+            {
+                let child_ipc_shmem = child_ipc_shmem.clone();
+                child_pid_watcher.register_callback(child_native_tid, move |_pid| {
+                    child_ipc_shmem.from_plugin().close_writer();
+                });
+            }
+            child_pid_watcher.unregister_callback(self.native_pid(), parent_watch_handle);
+            // In Linux, the PID is equal to the TID of its first thread.
+            child_native_tid
+        } else {
+            self.native_pid
+        };
+
         trace!(
             "waiting for start event from shim with native tid {}",
             child_native_tid
@@ -345,16 +451,17 @@ impl ManagedThread {
             other => panic!("Unexpected result from shim: {other:?}"),
         };
 
-        Ok(Self {
+        let mthread = Self {
             ipc_shmem: child_ipc_shmem,
             is_running: Cell::new(true),
             return_code: Cell::new(None),
             current_event: RefCell::new(start_req),
-            native_pid: self.native_pid,
-            native_tid: nix::unistd::Pid::from_raw(child_native_tid),
+            native_pid: child_native_pid,
+            native_tid: child_native_tid,
             // TODO: can we assume it's inherited from the current thread affinity?
             affinity: Cell::new(cshadow::AFFINITY_UNINIT),
-        })
+        };
+        Ok((mthread, parent_res))
     }
 
     #[must_use]
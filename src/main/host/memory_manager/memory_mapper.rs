@@ -17,6 +17,7 @@ use nix::unistd::Pid;
 use nix::{fcntl, sys};
 use shadow_pod::Pod;
 use shadow_shim_helper_rs::notnull::*;
+use shadow_shim_helper_rs::shim_event::ShimEventPrivatizeRegion;
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 
 use crate::host::context::ProcessContext;
@@ -526,6 +527,21 @@ impl MemoryMapper {
         }
     }
 
+    /// Regions of the plugin's address space that have been remapped into the shared memory
+    /// file. A child process created by `fork` would otherwise continue sharing these with the
+    /// plugin.
+    pub fn shared_regions(&self) -> Vec<ShimEventPrivatizeRegion> {
+        self.regions
+            .iter()
+            .filter(|(_, region)| !region.shadow_base.is_null())
+            .map(|(interval, region)| ShimEventPrivatizeRegion {
+                start: interval.start,
+                len: interval.end - interval.start,
+                prot: region.prot,
+            })
+            .collect()
+    }
+
     /// Shadow should delegate a plugin's call to mmap to this method.  The caller is responsible
     /// for ensuring that `fd` is open and pointing to the right file in the plugin process.
     ///
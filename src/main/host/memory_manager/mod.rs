@@ -26,6 +26,7 @@ use memory_copier::MemoryCopier;
 use memory_mapper::MemoryMapper;
 use nix::unistd::Pid;
 use shadow_pod::Pod;
+use shadow_shim_helper_rs::shim_event::ShimEventPrivatizeRegion;
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 
 use super::context::ThreadContext;
@@ -604,6 +605,15 @@ impl MemoryManager {
         self.memory_mapper.is_some()
     }
 
+    /// Regions of the process's address space that are shared with Shadow,
+    /// and that must be made private in a child process created by `fork`.
+    pub fn shared_regions(&self) -> Vec<ShimEventPrivatizeRegion> {
+        match &self.memory_mapper {
+            Some(mm) => mm.shared_regions(),
+            None => Vec::new(),
+        }
+    }
+
     /// Create a write accessor for the specified plugin memory.
     pub fn writer(&mut self, ptr: ForeignArrayPtr<u8>) -> MemoryWriterCursor<'_> {
         MemoryWriterCursor {
@@ -0,0 +1,243 @@
+//! The parts of the host's network stack that generate and respond to ICMP messages on behalf of
+//! the host, rather than on behalf of any socket.
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use linux_api::errno::Errno;
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+
+use crate::cshadow as c;
+use crate::host::host::Host;
+use crate::host::network::interface::{FifoPacketPriority, NetworkInterface};
+use crate::network::packet::{IcmpHeader, PacketRc, PacketStatus};
+use crate::network::PacketDevice;
+use crate::utility::callback_queue::CallbackQueue;
+use crate::utility::pcap_writer::PacketDisplay;
+
+pub const ICMP_ECHOREPLY: u8 = 0;
+pub const ICMP_DEST_UNREACH: u8 = 3;
+pub const ICMP_ECHO: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+/// Code for [`ICMP_DEST_UNREACH`].
+pub const ICMP_PORT_UNREACH: u8 = 3;
+/// Code for [`ICMP_TIME_EXCEEDED`].
+pub const ICMP_EXC_TTL: u8 = 0;
+
+/// The maximum number of bytes of the original packet that are included in an ICMP error message.
+/// Like Linux, we quote as much as fits in a 576 byte error message (RFC 1812 4.3.2.3), after the
+/// 20 byte IP header and 8 byte ICMP header.
+const MAX_ERROR_QUOTE_LEN: usize = 576 - 20 - 8;
+
+/// The length of the IP header and start of the transport header that an ICMP error message must
+/// quote (RFC 792).
+const MIN_ERROR_QUOTE_LEN: usize = 28;
+
+/// Returns true if the ICMP message type is an error message.
+pub fn is_error(icmp_type: u8) -> bool {
+    // destination unreachable, source quench, redirect, time exceeded, parameter problem
+    matches!(icmp_type, 3 | 4 | 5 | 11 | 12)
+}
+
+/// Returns the error that an ICMP error message reports to a socket, and whether it's a "hard" error
+/// that is reported even if the socket didn't enable `IP_RECVERR`. Based on Linux's
+/// `__udp4_lib_err()` and `icmp_err_convert`. Returns `None` if the message should be ignored.
+pub fn error_errno(icmp_type: u8, code: u8) -> Option<(Errno, bool)> {
+    match icmp_type {
+        ICMP_DEST_UNREACH => Some(match code {
+            0 => (Errno::ENETUNREACH, false),
+            1 => (Errno::EHOSTUNREACH, false),
+            2 => (Errno::ENOPROTOOPT, true),
+            ICMP_PORT_UNREACH => (Errno::ECONNREFUSED, true),
+            4 => (Errno::EMSGSIZE, true),
+            5 => (Errno::EOPNOTSUPP, false),
+            6 | 9 => (Errno::ENETUNREACH, true),
+            7 => (Errno::EHOSTDOWN, true),
+            8 => (Errno::ENONET, true),
+            11 => (Errno::ENETUNREACH, false),
+            12 => (Errno::EHOSTUNREACH, false),
+            10 | 13..=15 => (Errno::EHOSTUNREACH, true),
+            _ => (Errno::EHOSTUNREACH, false),
+        }),
+        ICMP_TIME_EXCEEDED => Some((Errno::EHOSTUNREACH, false)),
+        // parameter problem
+        12 => Some((Errno::EPROTO, true)),
+        // source quench and redirect
+        _ => None,
+    }
+}
+
+/// The original packet quoted in an ICMP error message.
+pub struct ErrorQuote {
+    /// The IP protocol number of the original packet.
+    pub protocol: u8,
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    /// The quoted bytes following the first 8 bytes of the transport header. For UDP this is the
+    /// start of the original datagram's payload.
+    pub data: Vec<u8>,
+}
+
+/// Parse the original packet quoted in the ICMP error message `packet`. The ports are taken from
+/// the first 4 bytes of the transport header, which is where both TCP and UDP store them. Returns
+/// `None` if the quote is too short.
+pub fn parse_error_quote(packet: &PacketRc) -> Option<ErrorQuote> {
+    let mut quote = vec![0u8; packet.payload_size()];
+    packet.get_payload(&mut quote);
+
+    if quote.len() < MIN_ERROR_QUOTE_LEN {
+        return None;
+    }
+
+    // the IP header length is given in 32-bit words
+    let ip_header_len = usize::from(quote[0] & 0x0f) * 4;
+    if quote.len() < ip_header_len + 8 {
+        return None;
+    }
+
+    let ip = |i: usize| Ipv4Addr::new(quote[i], quote[i + 1], quote[i + 2], quote[i + 3]);
+    let port = |i: usize| u16::from_be_bytes([quote[i], quote[i + 1]]);
+
+    Some(ErrorQuote {
+        protocol: quote[9],
+        src: SocketAddrV4::new(ip(12), port(ip_header_len)),
+        dst: SocketAddrV4::new(ip(16), port(ip_header_len + 2)),
+        data: quote[ip_header_len + 8..].to_vec(),
+    })
+}
+
+/// Returns true if the packet is an ICMP error message.
+fn is_error_packet(packet: &PacketRc) -> bool {
+    packet.protocol() == c::_ProtocolType_PICMP && is_error(packet.get_icmp().icmp_type)
+}
+
+/// Build an ICMP error message from `src` in response to `packet`, which is sent back to the
+/// source of `packet`. Returns `None` if no error should be generated, for example if `packet` is
+/// itself an ICMP error message (RFC 1122 3.2.2) or was sent over IPv6 (we don't support ICMPv6).
+pub fn new_error_packet(
+    packet: &PacketRc,
+    src: Ipv4Addr,
+    icmp_type: u8,
+    code: u8,
+    priority: FifoPacketPriority,
+) -> Option<PacketRc> {
+    if packet.is_ipv6() || is_error_packet(packet) {
+        return None;
+    }
+
+    // the original IP header and the start of the original transport header
+    let mut quote = Vec::new();
+    packet.display_bytes(&mut quote).unwrap();
+    quote.truncate(MAX_ERROR_QUOTE_LEN);
+
+    let header = IcmpHeader {
+        src,
+        dst: *packet.src_address().ip(),
+        icmp_type,
+        code,
+        identifier: 0,
+        sequence: 0,
+    };
+
+    let mut error = PacketRc::new();
+    error.set_icmp(&header);
+    error.set_payload(&quote, priority);
+    error.add_status(PacketStatus::SndCreated);
+
+    Some(error)
+}
+
+/// Process a packet that arrived at the interface `iface`. Raw ICMP sockets receive a copy of any
+/// ICMP packet, ICMP errors are passed to the socket that caused them, echo requests are answered,
+/// and UDP packets that weren't `delivered` to a socket
+/// are answered with a port unreachable error.
+pub fn process_incoming(
+    host: &Host,
+    iface: &NetworkInterface,
+    packet: &PacketRc,
+    delivered: bool,
+    recv_time: EmulatedTime,
+) {
+    if packet.is_corrupted() {
+        return;
+    }
+
+    let response = match packet.protocol() {
+        c::_ProtocolType_PICMP => {
+            for socket in host.network_namespace_borrow().raw_icmp_sockets() {
+                crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+                    CallbackQueue::queue_and_run(|cb_queue| {
+                        socket
+                            .borrow_mut()
+                            .push_in_packet(packet.clone(), cb_queue, recv_time)
+                    })
+                });
+            }
+
+            let header = packet.get_icmp();
+            if is_error(header.icmp_type) {
+                push_error(iface, packet, recv_time);
+            }
+
+            (header.icmp_type == ICMP_ECHO && header.code == 0)
+                .then(|| new_echo_reply(host, packet))
+        }
+        c::_ProtocolType_PUDP if !delivered => new_error_packet(
+            packet,
+            *packet.dst_address().ip(),
+            ICMP_DEST_UNREACH,
+            ICMP_PORT_UNREACH,
+            host.get_next_packet_priority(),
+        ),
+        _ => None,
+    };
+
+    if let Some(response) = response {
+        iface.send_packet(&response);
+        host.notify_interface_has_packets(iface.get_address());
+    }
+}
+
+/// Deliver the ICMP error message `error` to the socket that sent the quoted packet. Only UDP sockets
+/// receive errors. Shadow doesn't send ICMP errors for TCP packets to closed ports, and Linux TCP
+/// sockets mostly ignore the "soft" time exceeded errors that routers send.
+fn push_error(iface: &NetworkInterface, error: &PacketRc, recv_time: EmulatedTime) {
+    let Some(quote) = parse_error_quote(error) else {
+        return;
+    };
+
+    if quote.protocol != libc::IPPROTO_UDP as u8 {
+        return;
+    }
+
+    // the quoted packet was sent from this host, so its source is the local address
+    iface.push_error(
+        error,
+        c::_ProtocolType_PUDP,
+        quote.src.port(),
+        quote.dst,
+        recv_time,
+    );
+}
+
+/// Build an echo reply for the echo request `request`.
+fn new_echo_reply(host: &Host, request: &PacketRc) -> PacketRc {
+    let header = request.get_icmp();
+
+    let mut payload = vec![0u8; request.payload_size()];
+    request.get_payload(&mut payload);
+
+    let header = IcmpHeader {
+        src: header.dst,
+        dst: header.src,
+        icmp_type: ICMP_ECHOREPLY,
+        ..header
+    };
+
+    let mut reply = PacketRc::new();
+    reply.set_icmp(&header);
+    reply.set_payload(&payload, host.get_next_packet_priority());
+    reply.add_status(PacketStatus::SndCreated);
+
+    reply
+}
@@ -9,6 +9,7 @@ use shadow_shim_helper_rs::HostId;
 use crate::core::support::configuration::QDiscMode;
 use crate::core::worker::Worker;
 use crate::cshadow as c;
+use crate::host::network::icmp;
 use crate::network::packet::PacketRc;
 use crate::network::PacketDevice;
 use crate::utility::{self, HostTreePointer};
@@ -16,6 +17,14 @@ use crate::utility::{self, HostTreePointer};
 /// The priority used by the fifo qdisc to choose the next socket to send a packet from.
 pub type FifoPacketPriority = u64;
 
+/// The `SO_REUSEADDR` and `SO_REUSEPORT` options of a socket, which control whether it can share an
+/// association with other sockets.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ReuseOptions {
+    pub reuse_addr: bool,
+    pub reuse_port: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct PcapOptions {
     pub path: PathBuf,
@@ -82,6 +91,7 @@ impl NetworkInterface {
         protocol_type: c::ProtocolType,
         port: u16,
         peer_addr: SocketAddrV4,
+        reuse: ReuseOptions,
     ) {
         let port = port.to_be();
         let peer_ip = u32::from(*peer_addr.ip()).to_be();
@@ -95,11 +105,21 @@ impl NetworkInterface {
                 port,
                 peer_ip,
                 peer_port,
+                reuse.reuse_addr,
+                reuse.reuse_port,
             )
         };
     }
 
-    pub fn disassociate(&self, protocol_type: c::ProtocolType, port: u16, peer_addr: SocketAddrV4) {
+    /// Disassociate the socket from the given port and peer. Other sockets sharing the association
+    /// will remain associated.
+    pub fn disassociate(
+        &self,
+        socket_ptr: *const c::CompatSocket,
+        protocol_type: c::ProtocolType,
+        port: u16,
+        peer_addr: SocketAddrV4,
+    ) {
         let port = port.to_be();
         let peer_ip = u32::from(*peer_addr.ip()).to_be();
         let peer_port = peer_addr.port().to_be();
@@ -107,6 +127,7 @@ impl NetworkInterface {
         unsafe {
             c::networkinterface_disassociate(
                 self.c_ptr.ptr(),
+                socket_ptr,
                 protocol_type,
                 port,
                 peer_ip,
@@ -115,13 +136,29 @@ impl NetworkInterface {
         };
     }
 
-    pub fn is_associated(&self, protocol: c::ProtocolType, port: u16, peer: SocketAddrV4) -> bool {
+    /// Returns true if an associated socket would prevent a new socket with the given reuse options
+    /// from being associated with the port and peer.
+    pub fn is_associated(
+        &self,
+        protocol: c::ProtocolType,
+        port: u16,
+        peer: SocketAddrV4,
+        reuse: ReuseOptions,
+    ) -> bool {
         let port = port.to_be();
         let peer_ip = u32::from(*peer.ip()).to_be();
         let peer_port = peer.port().to_be();
 
         (unsafe {
-            c::networkinterface_isAssociated(self.c_ptr.ptr(), protocol, port, peer_ip, peer_port)
+            c::networkinterface_isAssociated(
+                self.c_ptr.ptr(),
+                protocol,
+                port,
+                peer_ip,
+                peer_port,
+                reuse.reuse_addr,
+                reuse.reuse_port,
+            )
         }) != 0
     }
 
@@ -129,6 +166,43 @@ impl NetworkInterface {
         unsafe { c::networkinterface_wantsSend(self.c_ptr.ptr(), socket_ptr) };
     }
 
+    /// Queue a packet generated by the host's network stack (rather than by a socket) to be sent.
+    /// The host must be notified with [`Host::notify_interface_has_packets`] so that the packet is
+    /// forwarded.
+    ///
+    /// [`Host::notify_interface_has_packets`]: crate::host::host::Host::notify_interface_has_packets
+    pub fn send_packet(&self, packet: &PacketRc) {
+        unsafe { c::networkinterface_sendPacket(self.c_ptr.ptr(), packet.borrow_inner()) };
+    }
+
+    /// Deliver the ICMP error message `error` to the socket that sent the packet quoted in the
+    /// error. The protocol, local port, and peer address are those of the quoted packet. Returns
+    /// true if the error was delivered to a socket.
+    pub fn push_error(
+        &self,
+        error: &PacketRc,
+        protocol: c::ProtocolType,
+        port: u16,
+        peer: SocketAddrV4,
+        recv_time: EmulatedTime,
+    ) -> bool {
+        let port = port.to_be();
+        let peer_ip = u32::from(*peer.ip()).to_be();
+        let peer_port = peer.port().to_be();
+
+        unsafe {
+            c::networkinterface_pushError(
+                self.c_ptr.ptr(),
+                error.borrow_inner(),
+                protocol,
+                port,
+                peer_ip,
+                peer_port,
+                EmulatedTime::to_c_emutime(Some(recv_time)),
+            )
+        }
+    }
+
     /// Disassociate all bound sockets and remove sockets from the sending queue. This should be
     /// called as part of the host's cleanup procedure.
     pub fn remove_all_sockets(&self) {
@@ -157,15 +231,19 @@ impl PacketDevice for NetworkInterface {
     }
 
     fn push(&self, packet: PacketRc) {
-        let packet_ptr = packet.into_inner();
         let current_time = Worker::current_time().unwrap();
-        unsafe {
+        let delivered = unsafe {
             c::networkinterface_push(
                 self.c_ptr.ptr(),
-                packet_ptr,
+                packet.borrow_inner(),
                 EmulatedTime::to_c_emutime(Some(current_time)),
             )
         };
-        unsafe { c::packet_unref(packet_ptr) };
+
+        // the host's network stack may also need to process or respond to the packet
+        Worker::with_active_host(|host| {
+            icmp::process_incoming(host, self, &packet, delivered, current_time)
+        })
+        .unwrap();
     }
 }
@@ -1,2 +1,3 @@
+pub mod icmp;
 pub mod interface;
 pub mod namespace;
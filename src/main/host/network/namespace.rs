@@ -1,9 +1,9 @@
 use std::cell::{Cell, RefCell};
 use std::ffi::{CString, OsStr};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4};
 use std::num::NonZeroU8;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use atomic_refcell::AtomicRefCell;
 use shadow_shim_helper_rs::util::SyncSendPointer;
@@ -13,7 +13,10 @@ use crate::core::support::configuration::QDiscMode;
 use crate::core::worker::Worker;
 use crate::cshadow;
 use crate::host::descriptor::socket::abstract_unix_ns::AbstractUnixNamespace;
-use crate::host::network::interface::{NetworkInterface, PcapOptions};
+use crate::host::descriptor::socket::inet::icmp::IcmpSocket;
+use crate::host::descriptor::socket::inet::{InetSocket, InetSocketWeak};
+use crate::host::descriptor::FileState;
+use crate::host::network::interface::{NetworkInterface, PcapOptions, ReuseOptions};
 
 // The start of our random port range in host order, used if application doesn't
 // specify the port it wants to bind to, and for client connections.
@@ -33,6 +36,12 @@ pub struct NetworkNamespace {
     // TODO: use a Rust address type
     pub default_address: SyncSendPointer<cshadow::Address>,
     pub default_ip: Ipv4Addr,
+    /// The host's IPv6 address, if any. Packets to and from this address are routed using
+    /// `default_ip`.
+    pub default_ipv6: Option<Ipv6Addr>,
+
+    // raw ICMP sockets, which receive a copy of every ICMP packet that arrives at the host
+    raw_icmp_sockets: RefCell<Vec<Weak<AtomicRefCell<IcmpSocket>>>>,
 
     // used for debugging to make sure we've cleaned up before being dropped
     has_run_cleanup: Cell<bool>,
@@ -46,6 +55,7 @@ impl NetworkNamespace {
         host_id: HostId,
         hostname: Vec<NonZeroU8>,
         public_ip: Ipv4Addr,
+        public_ipv6: Option<Ipv6Addr>,
         pcap: Option<PcapOptions>,
         qdisc: QDiscMode,
         dns: *mut cshadow::DNS,
@@ -80,12 +90,20 @@ impl NetworkNamespace {
             )
         };
 
+        if let Some(ipv6) = public_ipv6 {
+            let registered =
+                unsafe { cshadow::dns_registerIPv6(dns, public_addr, ipv6.octets().as_ptr()) };
+            assert!(registered != 0, "Could not register IPv6 address {ipv6}");
+        }
+
         Self {
             unix: Arc::new(AtomicRefCell::new(AbstractUnixNamespace::new())),
             localhost: RefCell::new(localhost),
             internet: RefCell::new(internet),
             default_address: unsafe { SyncSendPointer::new(public_addr) },
             default_ip: public_ip,
+            default_ipv6: public_ipv6,
+            raw_icmp_sockets: RefCell::new(Vec::new()),
             has_run_cleanup: Cell::new(false),
         }
     }
@@ -135,10 +153,30 @@ impl NetworkNamespace {
         // to access the global host and panic since there is no host
         self.localhost.borrow().remove_all_sockets();
         self.internet.borrow().remove_all_sockets();
+        self.raw_icmp_sockets.borrow_mut().clear();
 
         self.has_run_cleanup.set(true);
     }
 
+    /// Register a raw ICMP socket so that it receives ICMP packets arriving at the host. The socket
+    /// is unregistered when it's closed or dropped.
+    pub fn register_raw_icmp_socket(&self, socket: &Arc<AtomicRefCell<IcmpSocket>>) {
+        self.raw_icmp_sockets
+            .borrow_mut()
+            .push(Arc::downgrade(socket));
+    }
+
+    /// Get the raw ICMP sockets that are still open.
+    pub fn raw_icmp_sockets(&self) -> Vec<Arc<AtomicRefCell<IcmpSocket>>> {
+        let mut sockets = self.raw_icmp_sockets.borrow_mut();
+
+        let is_open =
+            |x: &Arc<AtomicRefCell<IcmpSocket>>| !x.borrow().state().contains(FileState::CLOSED);
+        sockets.retain(|x| x.upgrade().is_some_and(|x| is_open(&x)));
+
+        sockets.iter().filter_map(Weak::upgrade).collect()
+    }
+
     /// Returns `None` if there is no such interface.
     #[track_caller]
     pub fn interface_borrow(
@@ -169,26 +207,29 @@ impl NetworkNamespace {
         }
     }
 
+    /// Returns true if a socket with the given reuse options can be associated with the local and
+    /// remote addresses.
     pub fn is_interface_available(
         &self,
         protocol_type: cshadow::ProtocolType,
         src: SocketAddrV4,
         dst: SocketAddrV4,
+        reuse: ReuseOptions,
     ) -> bool {
         if src.ip().is_unspecified() {
             // Check that all interfaces are available.
             !self
                 .localhost
                 .borrow()
-                .is_associated(protocol_type, src.port(), dst)
+                .is_associated(protocol_type, src.port(), dst, reuse)
                 && !self
                     .internet
                     .borrow()
-                    .is_associated(protocol_type, src.port(), dst)
+                    .is_associated(protocol_type, src.port(), dst, reuse)
         } else {
             // The interface is not available if it does not exist.
             match self.interface_borrow(*src.ip()) {
-                Some(i) => !i.is_associated(protocol_type, src.port(), dst),
+                Some(i) => !i.is_associated(protocol_type, src.port(), dst, reuse),
                 None => false,
             }
         }
@@ -202,7 +243,10 @@ impl NetworkNamespace {
         peer: SocketAddrV4,
         mut rng: impl rand::Rng,
     ) -> Option<u16> {
-        // we need a random port that is free everywhere we need it to be.
+        // we need a random port that is free everywhere we need it to be, and ephemeral ports are
+        // never shared with other sockets.
+        let reuse = ReuseOptions::default();
+
         // we have two modes here: first we just try grabbing a random port until we
         // get a free one. if we cannot find one fast enough, then as a fallback we
         // do an inefficient linear search that is guaranteed to succeed or fail.
@@ -217,6 +261,7 @@ impl NetworkNamespace {
                 protocol_type,
                 SocketAddrV4::new(interface_ip, random_port),
                 peer,
+                reuse,
             ) {
                 return Some(random_port);
             }
@@ -231,6 +276,7 @@ impl NetworkNamespace {
                 protocol_type,
                 SocketAddrV4::new(interface_ip, port),
                 peer,
+                reuse,
             ) {
                 return Some(port);
             }
@@ -240,35 +286,44 @@ impl NetworkNamespace {
         None
     }
 
-    /// Associate the socket with any applicable network interfaces. The socket will be
-    /// automatically disassociated when the returned handle is dropped.
-    ///
-    /// # Safety
-    ///
-    /// Pointer args must be safely dereferenceable.
-    pub unsafe fn associate_interface(
+    /// Associate the socket with any applicable network interfaces. Sockets can share an
+    /// association if their reuse options allow it. The socket will be automatically disassociated
+    /// when the returned handle is dropped.
+    pub fn associate_interface(
         &self,
-        socket: *const cshadow::CompatSocket,
+        socket: &InetSocket,
         protocol: cshadow::ProtocolType,
         bind_addr: SocketAddrV4,
         peer_addr: SocketAddrV4,
+        reuse: ReuseOptions,
     ) -> AssociationHandle {
+        let compat_socket = unsafe { cshadow::compatsocket_fromInetSocket(socket) };
+
         if bind_addr.ip().is_unspecified() {
             // need to associate all interfaces
-            self.localhost
-                .borrow()
-                .associate(socket, protocol, bind_addr.port(), peer_addr);
-            self.internet
-                .borrow()
-                .associate(socket, protocol, bind_addr.port(), peer_addr);
+            self.localhost.borrow().associate(
+                &compat_socket,
+                protocol,
+                bind_addr.port(),
+                peer_addr,
+                reuse,
+            );
+            self.internet.borrow().associate(
+                &compat_socket,
+                protocol,
+                bind_addr.port(),
+                peer_addr,
+                reuse,
+            );
         } else {
             // TODO: return error if interface does not exist
             if let Some(iface) = self.interface_borrow(*bind_addr.ip()) {
-                iface.associate(socket, protocol, bind_addr.port(), peer_addr);
+                iface.associate(&compat_socket, protocol, bind_addr.port(), peer_addr, reuse);
             }
         }
 
         AssociationHandle {
+            socket: Some(socket.downgrade()),
             protocol,
             local_addr: bind_addr,
             remote_addr: peer_addr,
@@ -280,8 +335,13 @@ impl NetworkNamespace {
     ///
     /// Is only public so that it can be called from `host_disassociateInterface`. Normally this
     /// should only be called from the [`AssociationHandle`].
-    pub fn disassociate_interface(
+    ///
+    /// # Safety
+    ///
+    /// `socket` must be safely dereferenceable.
+    pub unsafe fn disassociate_interface(
         &self,
+        socket: *const cshadow::CompatSocket,
         protocol: cshadow::ProtocolType,
         bind_addr: SocketAddrV4,
         peer_addr: SocketAddrV4,
@@ -290,15 +350,15 @@ impl NetworkNamespace {
             // need to disassociate all interfaces
             self.localhost
                 .borrow()
-                .disassociate(protocol, bind_addr.port(), peer_addr);
+                .disassociate(socket, protocol, bind_addr.port(), peer_addr);
 
             self.internet
                 .borrow()
-                .disassociate(protocol, bind_addr.port(), peer_addr);
+                .disassociate(socket, protocol, bind_addr.port(), peer_addr);
         } else {
             // TODO: return error if interface does not exist
             if let Some(iface) = self.interface_borrow(*bind_addr.ip()) {
-                iface.disassociate(protocol, bind_addr.port(), peer_addr);
+                iface.disassociate(socket, protocol, bind_addr.port(), peer_addr);
             }
         }
     }
@@ -325,17 +385,45 @@ struct InterfaceOptions {
 /// A handle for a socket association with a network interface(s). The network association will be
 /// dissolved when this handle is dropped (similar to
 /// [`callback_queue::Handle`](crate::utility::callback_queue::Handle)).
-#[derive(Debug)]
 pub struct AssociationHandle {
+    /// A weak reference since the socket owns this handle. Is `None` if the handle was forgotten.
+    socket: Option<InetSocketWeak>,
     protocol: cshadow::ProtocolType,
     local_addr: SocketAddrV4,
     remote_addr: SocketAddrV4,
 }
 
+impl AssociationHandle {
+    /// Drop the handle without disassociating the socket. The socket's owner then becomes
+    /// responsible for disassociating it.
+    pub fn forget(mut self) {
+        self.socket = None;
+    }
+}
+
+impl std::fmt::Debug for AssociationHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AssociationHandle")
+            .field("protocol", &self.protocol)
+            .field("local_addr", &self.local_addr)
+            .field("remote_addr", &self.remote_addr)
+            .finish_non_exhaustive()
+    }
+}
+
 impl std::ops::Drop for AssociationHandle {
     fn drop(&mut self) {
-        Worker::with_active_host(|host| {
+        // the network interface holds a reference to the socket while it's associated, so if the
+        // socket is being dropped then it has already been disassociated
+        let Some(socket) = self.socket.as_ref().and_then(|x| x.upgrade()) else {
+            return;
+        };
+
+        let compat_socket = unsafe { cshadow::compatsocket_fromInetSocket(&socket) };
+
+        Worker::with_active_host(|host| unsafe {
             host.network_namespace_borrow().disassociate_interface(
+                &compat_socket,
                 self.protocol,
                 self.local_addr,
                 self.remote_addr,
@@ -33,30 +33,66 @@ struct _NetworkInterface {
     /* The address associated with this interface */
     Address* address;
 
-    /* (protocol,port)-to-socket bindings. Stores CompatSocket objects as tagged pointers. */
+    /* (protocol,port)-to-socket bindings. Each value is a GArray of BoundSocket objects, since
+     * sockets using SO_REUSEADDR or SO_REUSEPORT can share an association. */
     GHashTable* boundSockets;
 
     /* Transports wanting to send data out. */
     RrSocketQueue rrQueue;
     FifoSocketQueue fifoQueue;
 
+    /* Packets generated by the host's network stack rather than by a socket, such as ICMP
+     * replies. These are sent before any socket packets. */
+    GQueue* stackQueue;
+
     /* To support capturing incoming and outgoing packets */
     PcapWriter_BufWriter_File* pcap;
 
     MAGIC_DECLARE;
 };
 
-static void _compatsocket_unrefTaggedVoid(void* taggedSocketPtr) {
-    utility_debugAssert(taggedSocketPtr != NULL);
-    if (taggedSocketPtr == NULL) {
-        return;
-    }
+/* A socket associated with the interface, and the reuse options it was associated with. */
+typedef struct _BoundSocket {
+    /* Stores a CompatSocket object as a tagged pointer. */
+    uintptr_t taggedSocket;
+    bool reuseAddr;
+    bool reusePort;
+} BoundSocket;
+
+static void _boundsocket_clear(void* boundSocketPtr) {
+    BoundSocket* boundSocket = boundSocketPtr;
+    utility_debugAssert(boundSocket->taggedSocket != 0);
 
-    uintptr_t taggedSocket = (uintptr_t)taggedSocketPtr;
-    CompatSocket socket = compatsocket_fromTagged(taggedSocket);
+    CompatSocket socket = compatsocket_fromTagged(boundSocket->taggedSocket);
     compatsocket_unref(&socket);
 }
 
+static void _boundsockets_free(void* group) { g_array_unref(group); }
+
+/* Returns TRUE if the existing association prevents a new socket with the given reuse options from
+ * being associated with the same key. This follows Linux's rules: sockets can share a port if they
+ * both set SO_REUSEPORT, or if they both set SO_REUSEADDR and the existing socket isn't a listening
+ * TCP socket. The latter allows a server to bind over connections that are still in TIME_WAIT. */
+static bool _boundsocket_conflicts(const BoundSocket* existing, ProtocolType type, bool reuseAddr,
+                                   bool reusePort) {
+    if (reusePort && existing->reusePort) {
+        return false;
+    }
+
+    if (reuseAddr && existing->reuseAddr) {
+        if (type != PTCP) {
+            return false;
+        }
+
+        CompatSocket socket = compatsocket_fromTagged(existing->taggedSocket);
+        if (!compatsocket_isListening(&socket)) {
+            return false;
+        }
+    }
+
+    return true;
+}
+
 /* The address and ports must be in network byte order. */
 static gchar* _networkinterface_getAssociationKey(NetworkInterface* interface,
         ProtocolType type, in_port_t port, in_addr_t peerAddr, in_port_t peerPort) {
@@ -72,23 +108,42 @@ static gchar* _networkinterface_getAssociationKey(NetworkInterface* interface,
     return g_string_free(strBuffer, FALSE);
 }
 
+/* Returns TRUE if any socket associated using `key` conflicts with a new association. */
+static bool _networkinterface_keyConflicts(NetworkInterface* interface, const gchar* key,
+                                           ProtocolType type, bool reuseAddr, bool reusePort) {
+    GArray* group = g_hash_table_lookup(interface->boundSockets, key);
+    if (group == NULL) {
+        return false;
+    }
+
+    for (guint i = 0; i < group->len; i++) {
+        const BoundSocket* existing = &g_array_index(group, BoundSocket, i);
+        if (_boundsocket_conflicts(existing, type, reuseAddr, reusePort)) {
+            return true;
+        }
+    }
+
+    return false;
+}
+
 /* The address and ports must be in network byte order. */
 gboolean networkinterface_isAssociated(NetworkInterface* interface, ProtocolType type,
-        in_port_t port, in_addr_t peerAddr, in_port_t peerPort) {
+                                       in_port_t port, in_addr_t peerAddr, in_port_t peerPort,
+                                       bool reuseAddr, bool reusePort) {
     MAGIC_ASSERT(interface);
 
     gboolean isFound = FALSE;
 
     /* we need to check the general key too (ie the ones listening sockets use) */
     gchar* general = _networkinterface_getAssociationKey(interface, type, port, 0, 0);
-    if(g_hash_table_contains(interface->boundSockets, general)) {
+    if (_networkinterface_keyConflicts(interface, general, type, reuseAddr, reusePort)) {
         isFound = TRUE;
     }
     g_free(general);
 
     if(!isFound) {
         gchar* specific = _networkinterface_getAssociationKey(interface, type, port, peerAddr, peerPort);
-        if(g_hash_table_contains(interface->boundSockets, specific)) {
+        if (_networkinterface_keyConflicts(interface, specific, type, reuseAddr, reusePort)) {
             isFound = TRUE;
         }
         g_free(specific);
@@ -99,28 +154,46 @@ gboolean networkinterface_isAssociated(NetworkInterface* interface, ProtocolType
 
 void networkinterface_associate(NetworkInterface* interface, const CompatSocket* socket,
                                 ProtocolType type, in_port_t port, in_addr_t peerIP,
-                                in_port_t peerPort) {
+                                in_port_t peerPort, bool reuseAddr, bool reusePort) {
     MAGIC_ASSERT(interface);
 
     gchar* key = _networkinterface_getAssociationKey(interface, type, port, peerIP, peerPort);
 
     /* make sure there is no collision */
-    utility_debugAssert(!g_hash_table_contains(interface->boundSockets, key));
+    utility_debugAssert(
+        !_networkinterface_keyConflicts(interface, key, type, reuseAddr, reusePort));
+
+    GArray* group = g_hash_table_lookup(interface->boundSockets, key);
+    bool isNewGroup = group == NULL;
+
+    if (isNewGroup) {
+        group = g_array_new(FALSE, FALSE, sizeof(BoundSocket));
+        g_array_set_clear_func(group, _boundsocket_clear);
+
+        /* insert to our storage, key is now owned by table */
+        g_hash_table_insert(interface->boundSockets, key, group);
+    }
 
     /* need to store our own reference to the socket object */
     CompatSocket newSocketRef = compatsocket_refAs(socket);
 
-    /* insert to our storage, key is now owned by table */
-    bool key_did_not_exist = g_hash_table_replace(
-        interface->boundSockets, key, (void*)compatsocket_toTagged(&newSocketRef));
+    BoundSocket boundSocket = {
+        .taggedSocket = compatsocket_toTagged(&newSocketRef),
+        .reuseAddr = reuseAddr,
+        .reusePort = reusePort,
+    };
+    g_array_append_val(group, boundSocket);
 
-    utility_debugAssert(key_did_not_exist);
+    trace("associated socket key %s (%u sockets)", key, group->len);
 
-    trace("associated socket key %s", key);
+    if (!isNewGroup) {
+        g_free(key);
+    }
 }
 
-void networkinterface_disassociate(NetworkInterface* interface, ProtocolType type, in_port_t port,
-                                   in_addr_t peerIP, in_port_t peerPort) {
+void networkinterface_disassociate(NetworkInterface* interface, const CompatSocket* socket,
+                                   ProtocolType type, in_port_t port, in_addr_t peerIP,
+                                   in_port_t peerPort) {
     MAGIC_ASSERT(interface);
 
     gchar* key = _networkinterface_getAssociationKey(interface, type, port, peerIP, peerPort);
@@ -133,7 +206,22 @@ void networkinterface_disassociate(NetworkInterface* interface, ProtocolType typ
      * (including ones that have never been associated) and will try to
      * disassociate the same socket multiple times, so we can't just add an assert
      * here. */
-    g_hash_table_remove(interface->boundSockets, key);
+    GArray* group = g_hash_table_lookup(interface->boundSockets, key);
+    if (group != NULL) {
+        for (guint i = 0; i < group->len; i++) {
+            CompatSocket existing =
+                compatsocket_fromTagged(g_array_index(group, BoundSocket, i).taggedSocket);
+            if (compatsocket_isSameSocket(&existing, socket)) {
+                /* keep the remaining sockets in the order they were associated */
+                g_array_remove_index(group, i);
+                break;
+            }
+        }
+
+        if (group->len == 0) {
+            g_hash_table_remove(interface->boundSockets, key);
+        }
+    }
 
     trace("disassociated socket key %s", key);
     g_free(key);
@@ -156,56 +244,131 @@ static void _networkinterface_capturePacket(NetworkInterface* interface, Packet*
     }
 }
 
-static CompatSocket _boundsockets_lookup(GHashTable* table, gchar* key) {
-    void* ptr = g_hash_table_lookup(table, key);
+/* A deterministic hash of the packet's source, used to choose a socket from a SO_REUSEPORT group
+ * so that all packets of a flow are delivered to the same socket. The address and port must be in
+ * network byte order. */
+static guint32 _boundsockets_flowHash(in_addr_t peerIP, in_port_t peerPort) {
+    guint32 hash = ntohl(peerIP) ^ ((guint32)ntohs(peerPort) << 16) ^ ntohs(peerPort);
+
+    /* murmur3 finalizer */
+    hash ^= hash >> 16;
+    hash *= 0x85ebca6b;
+    hash ^= hash >> 13;
+    hash *= 0xc2b2ae35;
+    hash ^= hash >> 16;
 
-    if (ptr == NULL) {
+    return hash;
+}
+
+/* Choose the socket that should receive a packet from the given peer. The address and port must be
+ * in network byte order. */
+static CompatSocket _boundsockets_lookup(GHashTable* table, gchar* key, ProtocolType type,
+                                         in_addr_t peerIP, in_port_t peerPort) {
+    GArray* group = g_hash_table_lookup(table, key);
+
+    if (group == NULL) {
         CompatSocket compatSocket = {0};
         compatSocket.type = CST_NONE;
         return compatSocket;
     }
 
-    return compatsocket_fromTagged((uintptr_t)ptr);
-}
+    utility_debugAssert(group->len > 0);
 
-void networkinterface_push(NetworkInterface* interface, Packet* packet, CEmulatedTime recvTime) {
-    MAGIC_ASSERT(interface);
+    /* like Linux, the most recently associated socket takes precedence */
+    const BoundSocket* last = &g_array_index(group, BoundSocket, group->len - 1);
 
-    const Host* host = worker_getCurrentHost();
+    if (group->len == 1 || !last->reusePort) {
+        return compatsocket_fromTagged(last->taggedSocket);
+    }
 
-    /* get the next packet */
-    utility_debugAssert(packet);
+    /* the sockets in the SO_REUSEPORT group that can accept new flows */
+    guint* candidates = g_new(guint, group->len);
+    guint numCandidates = 0;
 
-    /* successfully received */
-    packet_addDeliveryStatus(packet, PDS_RCV_INTERFACE_RECEIVED);
+    for (guint i = 0; i < group->len; i++) {
+        const BoundSocket* boundSocket = &g_array_index(group, BoundSocket, i);
+        if (!boundSocket->reusePort) {
+            continue;
+        }
 
-    /* hand it off to the correct socket layer */
-    ProtocolType ptype = packet_getProtocol(packet);
-    in_port_t bindPort = packet_getDestinationPort(packet);
-    in_addr_t peerIP = packet_getSourceIP(packet);
-    in_port_t peerPort = packet_getSourcePort(packet);
+        /* only listening TCP sockets can accept new connections */
+        CompatSocket socket = compatsocket_fromTagged(boundSocket->taggedSocket);
+        if (type == PTCP && !compatsocket_isListening(&socket)) {
+            continue;
+        }
+
+        candidates[numCandidates++] = i;
+    }
 
+    guint index = group->len - 1;
+    if (numCandidates > 0) {
+        guint32 hash = _boundsockets_flowHash(peerIP, peerPort);
+        /* scale the hash to the number of candidates, similar to Linux's reciprocal_scale() */
+        index = candidates[((guint64)hash * numCandidates) >> 32];
+    }
+
+    g_free(candidates);
+
+    return compatsocket_fromTagged(g_array_index(group, BoundSocket, index).taggedSocket);
+}
+
+/* Returns the socket that should receive a packet with the given protocol, destination port, and
+ * source address, or a socket of type CST_NONE if there is no such socket. */
+static CompatSocket _networkinterface_lookupSocket(NetworkInterface* interface, ProtocolType ptype,
+                                                  in_port_t bindPort, in_addr_t peerIP,
+                                                  in_port_t peerPort) {
     /* first check for a socket with the specific association */
     gchar* key = _networkinterface_getAssociationKey(interface, ptype, bindPort, peerIP, peerPort);
     trace("looking for socket associated with specific key %s", key);
 
-    CompatSocket socket = _boundsockets_lookup(interface->boundSockets, key);
+    CompatSocket socket =
+        _boundsockets_lookup(interface->boundSockets, key, ptype, peerIP, peerPort);
     g_free(key);
 
     if (socket.type == CST_NONE) {
         /* then check for a socket with a wildcard association */
         key = _networkinterface_getAssociationKey(interface, ptype, bindPort, 0, 0);
         trace("looking for socket associated with general key %s", key);
-        socket = _boundsockets_lookup(interface->boundSockets, key);
+        socket = _boundsockets_lookup(interface->boundSockets, key, ptype, peerIP, peerPort);
         g_free(key);
     }
 
+    return socket;
+}
+
+bool networkinterface_push(NetworkInterface* interface, Packet* packet, CEmulatedTime recvTime) {
+    MAGIC_ASSERT(interface);
+
+    const Host* host = worker_getCurrentHost();
+
+    /* get the next packet */
+    utility_debugAssert(packet);
+
+    /* successfully received */
+    packet_addDeliveryStatus(packet, PDS_RCV_INTERFACE_RECEIVED);
+
+    /* hand it off to the correct socket layer */
+    ProtocolType ptype = packet_getProtocol(packet);
+    in_port_t bindPort = packet_getDestinationPort(packet);
+    in_addr_t peerIP = packet_getSourceIP(packet);
+    in_port_t peerPort = packet_getSourcePort(packet);
+
+    CompatSocket socket =
+        _networkinterface_lookupSocket(interface, ptype, bindPort, peerIP, peerPort);
+
     /* record the packet before we process it, otherwise we may send more packets before we
        record this one and the order will be incorrect */
     if (interface->pcap) {
         _networkinterface_capturePacket(interface, packet);
     }
 
+    /* a corrupted packet would fail checksum validation, so drop it before it reaches a socket */
+    if (packet_isCorrupted(packet)) {
+        trace("dropping packet corrupted by the network");
+        packet_addDeliveryStatus(packet, PDS_RCV_INTERFACE_DROPPED);
+        return false;
+    }
+
     /* pushing a packet to the socket may cause the socket to be disassociated and freed and cause
      * our socket pointer to become dangling while we're using it, so we need to increase its ref
      * count */
@@ -228,7 +391,31 @@ void networkinterface_push(NetworkInterface* interface, Packet* packet, CEmulate
 
     if (socket.type != CST_NONE) {
         compatsocket_unref(&socket);
+        return true;
     }
+
+    return false;
+}
+
+bool networkinterface_pushError(NetworkInterface* interface, Packet* error, ProtocolType type,
+                                in_port_t port, in_addr_t peerIP, in_port_t peerPort,
+                                CEmulatedTime recvTime) {
+    MAGIC_ASSERT(interface);
+    utility_debugAssert(error);
+
+    CompatSocket socket = _networkinterface_lookupSocket(interface, type, port, peerIP, peerPort);
+
+    /* legacy sockets don't handle ICMP errors */
+    if (socket.type != CST_INET_SOCKET) {
+        return false;
+    }
+
+    /* the socket may be disassociated and freed while handling the error */
+    socket = compatsocket_refAs(&socket);
+    compatsocket_pushInPacket(&socket, worker_getCurrentHost(), error, recvTime);
+    compatsocket_unref(&socket);
+
+    return true;
 }
 
 /* round robin queuing discipline ($ man tc)*/
@@ -337,8 +524,11 @@ Packet* networkinterface_pop(NetworkInterface* interface) {
     // We will have an owned reference, so need to deref later.
     CompatSocket socket = {0};
 
-    // Now actually pop and send the packet.
-    Packet* packet = _networkinterface_pop_next_packet_out(interface, src, &socket);
+    // Now actually pop and send the packet. Packets from the network stack go first.
+    Packet* packet = g_queue_pop_head(interface->stackQueue);
+    if (packet == NULL) {
+        packet = _networkinterface_pop_next_packet_out(interface, src, &socket);
+    }
 
     if (packet != NULL) {
         packet_addDeliveryStatus(packet, PDS_SND_INTERFACE_SENT);
@@ -391,6 +581,14 @@ void networkinterface_wantsSend(NetworkInterface* interface, const CompatSocket*
     }
 }
 
+void networkinterface_sendPacket(NetworkInterface* interface, Packet* packet) {
+    MAGIC_ASSERT(interface);
+    utility_debugAssert(packet);
+
+    packet_ref(packet);
+    g_queue_push_tail(interface->stackQueue, packet);
+}
+
 void networkinterface_removeAllSockets(NetworkInterface* interface) {
     /* we want to unref all sockets, but also want to keep the network interface in a valid state */
 
@@ -400,6 +598,11 @@ void networkinterface_removeAllSockets(NetworkInterface* interface) {
     rrsocketqueue_init(&interface->rrQueue);
     fifosocketqueue_init(&interface->fifoQueue);
 
+    Packet* packet = NULL;
+    while ((packet = g_queue_pop_head(interface->stackQueue)) != NULL) {
+        packet_unref(packet);
+    }
+
     g_hash_table_remove_all(interface->boundSockets);
 }
 
@@ -413,12 +616,15 @@ NetworkInterface* networkinterface_new(Address* address, const char* name, const
 
     /* incoming packets get passed along to sockets */
     interface->boundSockets =
-        g_hash_table_new_full(g_str_hash, g_str_equal, g_free, _compatsocket_unrefTaggedVoid);
+        g_hash_table_new_full(g_str_hash, g_str_equal, g_free, _boundsockets_free);
 
     /* sockets tell us when they want to start sending */
     rrsocketqueue_init(&interface->rrQueue);
     fifosocketqueue_init(&interface->fifoQueue);
 
+    /* the host's network stack can also send packets */
+    interface->stackQueue = g_queue_new();
+
     /* parse queuing discipline */
     interface->qdisc = qdisc;
 
@@ -452,6 +658,8 @@ void networkinterface_free(NetworkInterface* interface) {
     rrsocketqueue_destroy(&interface->rrQueue, compatsocket_unref);
     fifosocketqueue_destroy(&interface->fifoQueue, compatsocket_unref);
 
+    g_queue_free_full(interface->stackQueue, (GDestroyNotify)packet_unref);
+
     g_hash_table_destroy(interface->boundSockets);
 
     address_unref(interface->address);
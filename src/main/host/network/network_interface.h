@@ -23,20 +23,36 @@ NetworkInterface* networkinterface_new(Address* address, const char* name, const
                                        guint32 pcapCaptureSize, QDiscMode qdisc);
 void networkinterface_free(NetworkInterface* interface);
 
-/* The address and ports must be in network byte order. */
+/* Returns TRUE if an associated socket prevents a new socket with the given SO_REUSEADDR and
+ * SO_REUSEPORT options from being associated. The address and ports must be in network byte
+ * order. */
 gboolean networkinterface_isAssociated(NetworkInterface* interface, ProtocolType type,
-        in_port_t port, in_addr_t peerAddr, in_port_t peerPort);
+                                       in_port_t port, in_addr_t peerAddr, in_port_t peerPort,
+                                       bool reuseAddr, bool reusePort);
 
 void networkinterface_associate(NetworkInterface* interface, const CompatSocket* socket,
                                 ProtocolType type, in_port_t port, in_addr_t peerIP,
-                                in_port_t peerPort);
-void networkinterface_disassociate(NetworkInterface* interface, ProtocolType type, in_port_t port,
-                                   in_addr_t peerIP, in_port_t peerPort);
+                                in_port_t peerPort, bool reuseAddr, bool reusePort);
+void networkinterface_disassociate(NetworkInterface* interface, const CompatSocket* socket,
+                                   ProtocolType type, in_port_t port, in_addr_t peerIP,
+                                   in_port_t peerPort);
 
 void networkinterface_wantsSend(NetworkInterface* interface, const CompatSocket* socket);
 
 Packet* networkinterface_pop(NetworkInterface* interface);
-void networkinterface_push(NetworkInterface* interface, Packet* packet, CEmulatedTime recvTime);
+/* Returns true if the packet was delivered to an associated socket. */
+bool networkinterface_push(NetworkInterface* interface, Packet* packet, CEmulatedTime recvTime);
+
+/* Deliver an ICMP error message to the socket that sent the packet quoted in the error. The
+ * protocol, local port, and peer address (in network byte order) are those of the quoted packet.
+ * Returns true if the error was delivered to a socket. */
+bool networkinterface_pushError(NetworkInterface* interface, Packet* error, ProtocolType type,
+                                in_port_t port, in_addr_t peerIP, in_port_t peerPort,
+                                CEmulatedTime recvTime);
+
+/* Queue a packet generated by the host's network stack (not by a socket) to be sent. Takes a new
+ * reference to the packet. */
+void networkinterface_sendPacket(NetworkInterface* interface, Packet* packet);
 
 /* Disassociate all bound sockets and remove sockets from the sending queue. */
 void networkinterface_removeAllSockets(NetworkInterface* interface);
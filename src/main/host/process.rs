@@ -3,17 +3,22 @@ use std::collections::BTreeMap;
 use std::ffi::{c_char, c_void, CStr, CString};
 use std::fmt::Write;
 use std::num::TryFromIntError;
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Range};
 use std::os::fd::{AsRawFd, RawFd};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::FromRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
 #[cfg(feature = "perf_timers")]
 use std::time::Duration;
 
+use atomic_refcell::AtomicRefCell;
 use linux_api::errno::Errno;
-use linux_api::signal::{defaultaction, siginfo_t, LinuxDefaultAction, Signal, SignalFromI32Error};
+use linux_api::signal::{
+    defaultaction, siginfo_t, sigset_t, LinuxDefaultAction, SigActionFlags, Signal,
+    SignalFromI32Error, SignalHandler,
+};
 use log::{debug, trace, warn};
 use nix::fcntl::OFlag;
 use nix::sys::signal as nixsignal;
@@ -22,7 +27,7 @@ use nix::unistd::Pid;
 use shadow_shim_helper_rs::rootedcell::rc::RootedRc;
 use shadow_shim_helper_rs::rootedcell::refcell::RootedRefCell;
 use shadow_shim_helper_rs::rootedcell::Root;
-use shadow_shim_helper_rs::shim_shmem::ProcessShmem;
+use shadow_shim_helper_rs::shim_shmem::{HostShmemProtected, ProcessShmem};
 use shadow_shim_helper_rs::simulation_time::SimulationTime;
 use shadow_shim_helper_rs::syscall_types::{ForeignPtr, ManagedPhysicalMemoryAddr};
 use shadow_shim_helper_rs::HostId;
@@ -40,13 +45,14 @@ use crate::core::work::task::TaskRef;
 use crate::core::worker::Worker;
 use crate::cshadow;
 use crate::host::context::ProcessContext;
-use crate::host::descriptor::{CompatFile, Descriptor};
+use crate::host::descriptor::{CompatFile, Descriptor, FileState, StateEventSource};
 use crate::host::managed_thread::ManagedThread;
 use crate::host::syscall::formatter::FmtOptions;
 use crate::utility;
-use crate::utility::callback_queue::CallbackQueue;
+use crate::utility::callback_queue::{CallbackQueue, EventSource, Handle};
 #[cfg(feature = "perf_timers")]
 use crate::utility::perf_timer::PerfTimer;
+use crate::utility::HostTreePointer;
 
 /// Virtual pid of a shadow process
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Ord, PartialOrd)]
@@ -110,12 +116,101 @@ pub enum ExitStatus {
     StoppedByShadow,
 }
 
+/// Notifies listeners, such as threads blocked in `wait4`, when a child of a
+/// process exits. Cloning it returns another reference to the same source.
+#[derive(Clone)]
+pub struct ChildEventSource(Arc<AtomicRefCell<StateEventSource>>);
+
+impl ChildEventSource {
+    fn new() -> Self {
+        Self(Arc::new(AtomicRefCell::new(StateEventSource::new())))
+    }
+
+    pub fn add_legacy_listener(&self, ptr: HostTreePointer<cshadow::StatusListener>) {
+        self.0.borrow_mut().add_legacy_listener(ptr)
+    }
+
+    pub fn remove_legacy_listener(&self, ptr: *mut cshadow::StatusListener) {
+        self.0.borrow_mut().remove_legacy_listener(ptr)
+    }
+
+    fn notify_child_exited(&self) {
+        CallbackQueue::queue_and_run(|cb_queue| {
+            self.0.borrow_mut().notify_listeners(
+                FileState::CHILD_EXITED,
+                FileState::CHILD_EXITED,
+                cb_queue,
+            )
+        });
+    }
+
+    fn notify_vfork_done(&self) {
+        CallbackQueue::queue_and_run(|cb_queue| {
+            self.0.borrow_mut().notify_listeners(
+                FileState::CHILD_VFORK_DONE,
+                FileState::CHILD_VFORK_DONE,
+                cb_queue,
+            )
+        });
+    }
+}
+
+/// The parent of a process created with `CLONE_VFORK`, which is suspended until
+/// the process releases it by calling `execve` or exiting.
+pub struct VforkParent {
+    pub id: ProcessId,
+    /// Regions of the parent's address space that it shares with the child in
+    /// Linux (`CLONE_VM`), and that are copied back to the parent when it's
+    /// released. The child otherwise only has a copy of the parent's memory.
+    pub shared: Vec<Range<usize>>,
+}
+
+/// Notifies listeners, such as signalfds, when the signals pending for a
+/// process may have changed. Listeners are given the signals pending for the
+/// process or for any of its threads. Cloning it returns another reference to
+/// the same source.
+#[derive(Clone)]
+pub struct SignalEventSource(Arc<AtomicRefCell<EventSource<sigset_t>>>);
+
+impl SignalEventSource {
+    fn new() -> Self {
+        Self(Arc::new(AtomicRefCell::new(EventSource::new())))
+    }
+
+    pub fn add_listener(
+        &self,
+        notify_fn: impl Fn(sigset_t, &mut CallbackQueue) + Send + Sync + 'static,
+    ) -> Handle<sigset_t> {
+        self.0.borrow_mut().add_listener(notify_fn)
+    }
+
+    fn notify_signals_changed(&self, pending: sigset_t, cb_queue: &mut CallbackQueue) {
+        self.0.borrow_mut().notify_listeners(pending, cb_queue);
+    }
+}
+
 #[derive(Debug)]
 struct StraceLogging {
     file: RefCell<std::fs::File>,
     options: FmtOptions,
 }
 
+impl StraceLogging {
+    /// Creates (or truncates) the strace file for the process whose output
+    /// files are named starting with `file_basename`.
+    fn new(file_basename: &Path, options: FmtOptions) -> Self {
+        let oflag = { OFlag::O_CREAT | OFlag::O_TRUNC | OFlag::O_WRONLY | OFlag::O_CLOEXEC };
+        let mode = { Mode::S_IRUSR | Mode::S_IWUSR | Mode::S_IRGRP | Mode::S_IROTH };
+        let filename = Process::static_output_file_name(file_basename, "strace");
+        let fd = nix::fcntl::open(&filename, oflag, mode).unwrap();
+
+        Self {
+            file: RefCell::new(unsafe { std::fs::File::from_raw_fd(fd) }),
+            options,
+        }
+    }
+}
+
 /// Parts of the process that are present in all states.
 struct Common {
     id: ProcessId,
@@ -132,6 +227,20 @@ struct Common {
     // by disallowing `chdir`.
     // See https://github.com/shadow/shadow/issues/2960
     working_dir: CString,
+
+    // The process that created this one via `fork`, if it's still alive.
+    parent_id: Cell<Option<ProcessId>>,
+
+    // Processes spawned from Shadow's config file lead their own process
+    // group. Since changing groups isn't supported, processes created via
+    // `fork` stay in their parent's group.
+    process_group_id: ProcessId,
+
+    // The signal sent to the parent when this process exits, as passed to
+    // `clone`. `None` for processes without a parent, and for processes created
+    // with an exit signal of 0, which the parent can still reap with `__WALL`
+    // or `__WCLONE`.
+    exit_signal: Option<Signal>,
 }
 
 impl Common {
@@ -203,7 +312,12 @@ pub struct RunnableProcess {
     // and PR_GET_DUMPABLE.
     dumpable: Cell<u32>,
 
-    native_pid: Pid,
+    // Changes when the process image is replaced via `execve`.
+    native_pid: Cell<Pid>,
+
+    // The `LD_PRELOAD` value the process was originally spawned with. Used to
+    // re-inject the shim into new process images after `execve`.
+    shim_ld_preload: Option<CString>,
 
     // timer that tracks the amount of CPU time we spend on plugin execution and processing
     #[cfg(feature = "perf_timers")]
@@ -214,6 +328,16 @@ pub struct RunnableProcess {
     desc_table: RefCell<DescriptorTable>,
     itimer_real: RefCell<Timer>,
 
+    // Notified when a child of this process exits.
+    child_events: ChildEventSource,
+
+    // Set if this process was created with `CLONE_VFORK` and hasn't yet
+    // released its parent.
+    vfork_parent: RefCell<Option<VforkParent>>,
+
+    // Notified when the signals pending for this process or its threads change.
+    signal_events: SignalEventSource,
+
     // The `RootedRc` lets us hold a reference to a thread without holding a
     // reference to the thread list. e.g. this lets us implement the `clone`
     // syscall, which adds a thread to the list while we have a reference to the
@@ -340,7 +464,7 @@ impl RunnableProcess {
     }
 
     pub fn native_pid(&self) -> Pid {
-        self.native_pid
+        self.native_pid.get()
     }
 
     #[track_caller]
@@ -419,6 +543,24 @@ impl RunnableProcess {
         delta
     }
 
+    /// Returns true if every thread of the process has `signal` blocked.
+    fn all_threads_block(
+        &self,
+        host: &Host,
+        host_shmem: &HostShmemProtected,
+        signal: Signal,
+    ) -> bool {
+        self.threads.borrow().values().all(|thread| {
+            let thread = thread.borrow(host.root());
+            thread
+                .shmem()
+                .protected
+                .borrow(&host_shmem.root)
+                .blocked_signals
+                .has(signal)
+        })
+    }
+
     fn interrupt_with_signal(&self, host: &Host, signal: Signal) {
         let threads = self.threads.borrow();
         for thread in threads.values() {
@@ -441,8 +583,9 @@ impl RunnableProcess {
                 warn!("thread {:?} has no syscall_condition. How?", thread.id());
                 continue;
             };
-            cond.wakeup_for_signal(host, signal);
-            break;
+            if cond.wakeup_for_signal(host, signal) {
+                break;
+            }
         }
     }
 
@@ -470,15 +613,20 @@ impl RunnableProcess {
                 .borrow_mut(&host_shmem.root);
             // SAFETY: We don't try to call any of the function pointers.
             let action = unsafe { process_shmem_protected.signal_action(signal) };
-            match unsafe { action.handler() } {
-                linux_api::signal::SignalHandler::Handler(_) => (),
-                linux_api::signal::SignalHandler::Action(_) => (),
-                linux_api::signal::SignalHandler::SigIgn => return,
+            let ignored = match unsafe { action.handler() } {
+                linux_api::signal::SignalHandler::Handler(_) => false,
+                linux_api::signal::SignalHandler::Action(_) => false,
+                linux_api::signal::SignalHandler::SigIgn => true,
                 linux_api::signal::SignalHandler::SigDfl => {
-                    if defaultaction(signal) == LinuxDefaultAction::IGN {
-                        return;
-                    }
+                    defaultaction(signal) == LinuxDefaultAction::IGN
                 }
+            };
+
+            // Like Linux, a signal that is blocked by all threads is left pending even if it's
+            // ignored, since the action may change before it's unblocked, or it may be read from
+            // a signalfd.
+            if ignored && !self.all_threads_block(host, &host_shmem, signal) {
+                return;
             }
 
             if process_shmem_protected.pending_signals.has(signal) {
@@ -492,6 +640,8 @@ impl RunnableProcess {
             process_shmem_protected.set_pending_standard_siginfo(signal, siginfo_t);
         }
 
+        CallbackQueue::queue_and_run(|cb_queue| self.notify_signals_changed(host, cb_queue));
+
         if let Some(thread) = current_thread {
             if thread.process_id() == self.common.id() {
                 let host_shmem = host.shim_shmem_lock_borrow().unwrap();
@@ -528,6 +678,438 @@ impl RunnableProcess {
     pub fn shmem(&self) -> impl Deref<Target = ShMemBlock<'static, ProcessShmem>> + '_ {
         &self.shim_shared_mem_block
     }
+
+    /// Notified when a child of this process exits.
+    pub fn child_events(&self) -> &ChildEventSource {
+        &self.child_events
+    }
+
+    /// Suspend `parent` until this process calls `execve` or exits.
+    pub fn set_vfork_parent(&self, parent: VforkParent) {
+        let prev = self.vfork_parent.borrow_mut().replace(parent);
+        assert!(prev.is_none());
+    }
+
+    /// Whether this process was created with `CLONE_VFORK` and hasn't yet
+    /// released its parent.
+    pub fn is_vfork_child(&self) -> bool {
+        self.vfork_parent.borrow().is_some()
+    }
+
+    /// If this process was created with `CLONE_VFORK`, copy the memory it
+    /// shares with its parent back to the parent, and let the parent continue.
+    /// Must be called before the process's memory is replaced or released.
+    pub fn release_vfork_parent(&self, host: &Host) {
+        let Some(parent) = self.vfork_parent.borrow_mut().take() else {
+            return;
+        };
+        let Some(parentrc) = host.process_borrow(parent.id) else {
+            // The parent has already been reaped.
+            return;
+        };
+        let parent_process = parentrc.borrow(host.root());
+        let Some(parent_runnable) = parent_process.borrow_runnable() else {
+            // The parent has already exited.
+            return;
+        };
+
+        {
+            let memory = self.memory_borrow();
+            let mut parent_memory = parent_runnable.memory_borrow_mut();
+            for region in &parent.shared {
+                let ptr = ForeignArrayPtr::new(
+                    ForeignPtr::<()>::from(region.start).cast::<u8>(),
+                    region.len(),
+                );
+                let mut buf = vec![0u8; region.len()];
+                let res = memory
+                    .copy_from_ptr(&mut buf, ptr)
+                    .and_then(|()| parent_memory.copy_to_ptr(ptr, &buf));
+                if let Err(e) = res {
+                    warn!(
+                        "Couldn't copy {region:x?} from process {:?} to its vfork parent: {e:?}",
+                        self.common.id(),
+                    );
+                }
+            }
+        }
+
+        parent_runnable.child_events().notify_vfork_done();
+    }
+
+    /// Notified when the signals pending for this process or its threads
+    /// change.
+    pub fn signal_events(&self) -> &SignalEventSource {
+        &self.signal_events
+    }
+
+    /// The signals pending for this process or for any of its threads.
+    pub fn pending_signals(&self, host: &Host) -> sigset_t {
+        let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+
+        let mut pending = self
+            .shim_shared_mem_block
+            .protected
+            .borrow(&host_shmem.root)
+            .pending_signals;
+
+        for thread in self.threads.borrow().values() {
+            let thread = thread.borrow(host.root());
+            pending |= thread
+                .shmem()
+                .protected
+                .borrow(&host_shmem.root)
+                .pending_signals;
+        }
+
+        pending
+    }
+
+    /// Notify listeners, such as signalfds, that the signals pending for this
+    /// process or its threads may have changed.
+    pub fn notify_signals_changed(&self, host: &Host, cb_queue: &mut CallbackQueue) {
+        let pending = self.pending_signals(host);
+        self.signal_events.notify_signals_changed(pending, cb_queue);
+    }
+
+    /// Notifies `self` that its child `child` has exited, by sending the
+    /// child's exit signal and waking any threads waiting on the child.
+    ///
+    /// Returns `true` if the child should be reaped immediately instead of
+    /// being left as a zombie. From wait(2): this is the case if the parent
+    /// "explicitly ignores SIGCHLD by setting its handler to SIG_IGN" or sets
+    /// the SA_NOCLDWAIT flag.
+    fn handle_child_exit(&self, host: &Host, child: &ZombieProcess) -> bool {
+        let no_zombie = child.common.exit_signal == Some(Signal::SIGCHLD) && {
+            let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+            let process_shmem_protected = self
+                .shim_shared_mem_block
+                .protected
+                .borrow(&host_shmem.root);
+            // SAFETY: We don't try to call any of the function pointers.
+            let action = unsafe { process_shmem_protected.signal_action(Signal::SIGCHLD) };
+            matches!(unsafe { action.handler() }, SignalHandler::SigIgn)
+                || action.flags_retain().contains(SigActionFlags::SA_NOCLDWAIT)
+        };
+
+        // `clone` only accepts `SIGCHLD` as a new process's exit signal, so
+        // the usual `SIGCHLD` siginfo applies.
+        if child.common.exit_signal.is_some() {
+            self.signal(host, None, &child.exit_siginfo());
+        }
+
+        self.child_events.notify_child_exited();
+
+        no_zombie
+    }
+
+    /// Replace the process image, as for a successful `execve`. `exec_tid` is
+    /// the thread that called `execve`.
+    ///
+    /// A new native process is spawned to run `plugin_path` (with the shim
+    /// re-injected), and the old native process is killed. As in Linux, the
+    /// simulated process keeps its `ProcessId`, its descriptors other than
+    /// those with `FD_CLOEXEC` set, its pending signals, its signal mask, and
+    /// its interval timers. Signal handlers are reset to their defaults, and
+    /// all other threads are destroyed.
+    pub fn exec(
+        &self,
+        host: &Host,
+        exec_tid: ThreadId,
+        plugin_path: &CStr,
+        argv: Vec<CString>,
+        envv: Vec<CString>,
+    ) {
+        let pid = self.common.id();
+
+        // The parent of a vfork'd process continues once its child has called
+        // `execve`. The old process image is still in place, so the memory the
+        // child shares with the parent can be copied back first.
+        self.release_vfork_parent(host);
+
+        // The signal mask of the calling thread is preserved across execve.
+        let blocked_signals = {
+            let threads = self.threads.borrow();
+            let thread = threads.get(&exec_tid).unwrap().borrow(host.root());
+            let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+            let blocked_signals = thread
+                .shmem()
+                .protected
+                .borrow(&host_shmem.root)
+                .blocked_signals;
+            blocked_signals
+        };
+
+        let exe_name = Path::new(std::ffi::OsStr::from_bytes(plugin_path.to_bytes()))
+            .file_name()
+            .unwrap()
+            .to_owned();
+        let shimlog_path = {
+            let mut path = PathBuf::from(host.data_dir_path());
+            path.push(format!(
+                "{exe_name}.{id}.shimlog",
+                exe_name = exe_name.to_string_lossy(),
+                id = u32::from(pid)
+            ));
+            utility::pathbuf_to_nul_term_cstring(path)
+        };
+
+        let mthread = ManagedThread::spawn(
+            plugin_path,
+            argv,
+            self.envv_with_shim_ld_preload(envv),
+            &self.common.working_dir,
+            self.strace_logging
+                .as_ref()
+                .map(|s| s.file.borrow().as_raw_fd()),
+            &shimlog_path,
+        );
+        let new_native_pid = mthread.native_pid();
+        let old_native_pid = self.native_pid.replace(new_native_pid);
+        debug!(
+            "process {pid} replaced native process {old_native_pid} with {new_native_pid} ({:?})",
+            exe_name
+        );
+
+        // Tear down the old process image, including all of its threads.
+        if let Err(err) = nixsignal::kill(old_native_pid, nixsignal::Signal::SIGKILL) {
+            warn!("kill: {:?}", err);
+        }
+        let old_threads = std::mem::take(&mut *self.threads.borrow_mut());
+        for (_tid, threadrc) in old_threads.into_iter() {
+            threadrc.borrow(host.root()).handle_process_exit();
+            threadrc.safely_drop(host.root());
+        }
+        if let Err(err) = nix::sys::wait::waitpid(old_native_pid, None) {
+            warn!("waitpid: {:?}", err);
+        }
+
+        // There shouldn't be any outstanding references into the old address
+        // space.
+        assert!(self.unsafe_borrow_mut.borrow().is_none());
+        assert!(self.unsafe_borrows.borrow().is_empty());
+        *self.memory_manager.borrow_mut() = unsafe { MemoryManager::new(new_native_pid) };
+
+        let cloexec_descriptors = self.desc_table.borrow_mut().remove_cloexec();
+        crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+            CallbackQueue::queue_and_run(|cb_queue| {
+                for desc in cloexec_descriptors {
+                    desc.close(host, cb_queue);
+                }
+            })
+        });
+
+        // From execve(2): "The dispositions of any signals that are being caught
+        // are reset to the default". Ignored signals stay ignored.
+        {
+            let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+            let mut process_shmem_protected = self
+                .shim_shared_mem_block
+                .protected
+                .borrow_mut(&host_shmem.root);
+            for signo in i32::from(Signal::MIN)..=i32::from(Signal::MAX) {
+                let signal = Signal::try_from(signo).unwrap();
+                // SAFETY: We don't try to call any of the function pointers.
+                let action = unsafe { process_shmem_protected.signal_action_mut(signal) };
+                if !matches!(
+                    unsafe { action.handler() },
+                    linux_api::signal::SignalHandler::SigIgn
+                ) {
+                    *action = linux_api::signal::sigaction::default();
+                }
+            }
+        }
+
+        self.dumpable.set(cshadow::SUID_DUMP_USER);
+
+        // The new image starts with a single thread, whose tid is the pid.
+        let thread = Thread::wrap_mthread(host, mthread, pid, ThreadId::from(pid)).unwrap();
+        {
+            let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+            thread
+                .shmem()
+                .protected
+                .borrow_mut(&host_shmem.root)
+                .blocked_signals = blocked_signals;
+        }
+        self.add_thread(
+            host,
+            RootedRc::new(host.root(), RootedRefCell::new(host.root(), thread)),
+        );
+    }
+
+    /// Create a new process as a child of `self`, as for `fork`. `child_thread`
+    /// is the new process's only thread, created by
+    /// [`ManagedThread::native_fork`], and whose id is used as the new
+    /// process's id.
+    ///
+    /// As in Linux, the child gets a copy of the descriptor table, sharing the
+    /// underlying open file descriptions with `self`, and of the signal
+    /// dispositions. It starts with no pending signals or interval timers.
+    /// `exit_signal` is sent to `self` when the child exits.
+    ///
+    /// The child must still be added to the host with
+    /// [`Host::add_and_schedule_forked_process`].
+    pub fn new_forked_process(
+        &self,
+        host: &Host,
+        child_thread: Thread,
+        exit_signal: Option<Signal>,
+    ) -> RootedRc<RootedRefCell<Process>> {
+        let process_id = child_thread.process_id();
+        assert_eq!(ThreadId::from(process_id), child_thread.id());
+        let native_pid = child_thread.native_pid();
+
+        let plugin_name = self.common.plugin_name.clone();
+        let name = CString::new(format!(
+            "{host_name}.{exe_name}.{id}",
+            host_name = host.name(),
+            exe_name = plugin_name.to_str().unwrap(),
+            id = u32::from(process_id)
+        ))
+        .unwrap();
+        debug!(
+            "process '{}' forked new process '{}'",
+            self.common.name(),
+            name.to_str().unwrap()
+        );
+
+        host.trace_event(|| event_trace::Event::ProcessStart {
+            pid: process_id.into(),
+            parent_pid: Some(self.common.id().into()),
+            name: plugin_name.to_string_lossy().into_owned(),
+        });
+
+        let mut file_basename = PathBuf::new();
+        file_basename.push(host.data_dir_path());
+        file_basename.push(format!(
+            "{exe_name}.{id}",
+            exe_name = plugin_name.to_str().unwrap(),
+            id = u32::from(process_id)
+        ));
+        let strace_logging = self
+            .strace_logging_options()
+            .map(|options| StraceLogging::new(&file_basename, options));
+
+        let shim_shared_mem = ProcessShmem::new(
+            &host.shim_shmem_lock_borrow().unwrap().root,
+            host.shim_shmem().serialize(),
+            host.id(),
+            strace_logging.as_ref().map(|x| x.file.borrow().as_raw_fd()),
+        );
+        let shim_shared_mem_block =
+            shadow_shmem::allocator::Allocator::global().alloc(shim_shared_mem);
+        {
+            let host_shmem = host.shim_shmem_lock_borrow().unwrap();
+            let parent_protected = self
+                .shim_shared_mem_block
+                .protected
+                .borrow(&host_shmem.root);
+            let mut child_protected = shim_shared_mem_block.protected.borrow_mut(&host_shmem.root);
+            for signo in i32::from(Signal::MIN)..=i32::from(Signal::MAX) {
+                let signal = Signal::try_from(signo).unwrap();
+                // SAFETY: We don't try to call any of the function pointers.
+                unsafe {
+                    *child_protected.signal_action_mut(signal) =
+                        *parent_protected.signal_action(signal)
+                };
+            }
+        }
+
+        let mut desc_table = DescriptorTable::new();
+        for (fd, desc) in self.desc_table.borrow().iter() {
+            let prev = desc_table.register_descriptor_with_fd(desc.dup(desc.flags()), *fd);
+            assert!(prev.is_none());
+        }
+
+        let itimer_real = RefCell::new(Timer::new(move |host| {
+            itimer_real_expiration(host, process_id)
+        }));
+
+        #[cfg(feature = "perf_timers")]
+        let cpu_delay_timer = {
+            let mut t = PerfTimer::new();
+            t.stop();
+            RefCell::new(t)
+        };
+
+        let common = Common {
+            id: process_id,
+            host_id: host.id(),
+            name,
+            plugin_name,
+            working_dir: self.common.working_dir.clone(),
+            parent_id: Cell::new(Some(self.common.id())),
+            process_group_id: self.common.process_group_id,
+            exit_signal,
+        };
+        let threads = RefCell::new(BTreeMap::from([(
+            child_thread.id(),
+            RootedRc::new(host.root(), RootedRefCell::new(host.root(), child_thread)),
+        )]));
+
+        RootedRc::new(
+            host.root(),
+            RootedRefCell::new(
+                host.root(),
+                Process {
+                    state: RefCell::new(Some(ProcessState::Runnable(RunnableProcess {
+                        common,
+                        // The parent is responsible for reaping and interpreting
+                        // the exit status.
+                        expected_final_state: None,
+                        shim_shared_mem_block,
+                        memory_manager: Box::new(RefCell::new(unsafe {
+                            MemoryManager::new(native_pid)
+                        })),
+                        desc_table: RefCell::new(desc_table),
+                        itimer_real,
+                        child_events: ChildEventSource::new(),
+                        vfork_parent: RefCell::new(None),
+                        signal_events: SignalEventSource::new(),
+                        strace_logging,
+                        dumpable: Cell::new(self.dumpable.get()),
+                        native_pid: Cell::new(native_pid),
+                        shim_ld_preload: self.shim_ld_preload.clone(),
+                        unsafe_borrow_mut: RefCell::new(None),
+                        unsafe_borrows: RefCell::new(Vec::new()),
+                        threads,
+                        #[cfg(feature = "perf_timers")]
+                        cpu_delay_timer,
+                        #[cfg(feature = "perf_timers")]
+                        total_run_time: Cell::new(Duration::ZERO),
+                    }))),
+                },
+            ),
+        )
+    }
+
+    /// Ensures that the `LD_PRELOAD` entry in `envv` injects the shim, as it
+    /// did when the process was originally spawned. Any libraries preloaded by
+    /// the managed process itself are kept after the shim's.
+    fn envv_with_shim_ld_preload(&self, mut envv: Vec<CString>) -> Vec<CString> {
+        let Some(shim_ld_preload) = &self.shim_ld_preload else {
+            return envv;
+        };
+        let shim_ld_preload = shim_ld_preload.as_bytes();
+
+        let mut preload = shim_ld_preload.to_vec();
+        if let Some(idx) = envv.iter().position(|x| Process::is_ld_preload_entry(x)) {
+            let entry = envv.remove(idx);
+            let value = &entry.as_bytes()[b"LD_PRELOAD=".len()..];
+            if value.starts_with(shim_ld_preload) {
+                preload = value.to_vec();
+            } else if !value.is_empty() {
+                preload.push(b':');
+                preload.extend_from_slice(value);
+            }
+        }
+
+        let mut entry = b"LD_PRELOAD=".to_vec();
+        entry.extend(preload);
+        envv.push(CString::new(entry).unwrap());
+        envv
+    }
 }
 
 /// A process that has exited.
@@ -541,6 +1123,35 @@ impl ZombieProcess {
     pub fn exit_status(&self) -> ExitStatus {
         self.exit_status
     }
+
+    /// The exit status in the encoding used by `wait4`; see waitpid(2).
+    pub fn wait_status(&self) -> libc::c_int {
+        match self.exit_status {
+            ExitStatus::Normal(code) => (code & 0xff) << 8,
+            ExitStatus::Signaled(signal) => signal as libc::c_int,
+            // The process was killed with `SIGKILL` at the end of the simulation.
+            ExitStatus::StoppedByShadow => Signal::SIGKILL.into(),
+        }
+    }
+
+    /// Describes how the process exited, as delivered along with `SIGCHLD` and
+    /// returned by `waitid`.
+    pub fn exit_siginfo(&self) -> siginfo_t {
+        let pid = libc::pid_t::from(self.common.id());
+        match self.exit_status {
+            ExitStatus::Normal(code) => siginfo_t::new_for_sigchld_exited(pid, 0, code, 0, 0),
+            ExitStatus::Signaled(signal) => siginfo_t::new_for_sigchld_killed(
+                pid,
+                0,
+                Signal::try_from(signal as i32).unwrap(),
+                0,
+                0,
+            ),
+            ExitStatus::StoppedByShadow => {
+                siginfo_t::new_for_sigchld_killed(pid, 0, Signal::SIGKILL, 0, 0)
+            }
+        }
+    }
 }
 
 /// Inner implementation of a simulated process.
@@ -666,17 +1277,8 @@ impl Process {
             id = u32::from(process_id)
         ));
 
-        let strace_logging = strace_logging_options.map(|options| {
-            let oflag = { OFlag::O_CREAT | OFlag::O_TRUNC | OFlag::O_WRONLY | OFlag::O_CLOEXEC };
-            let mode = { Mode::S_IRUSR | Mode::S_IWUSR | Mode::S_IRGRP | Mode::S_IROTH };
-            let filename = Self::static_output_file_name(&file_basename, "strace");
-            let fd = nix::fcntl::open(&filename, oflag, mode).unwrap();
-
-            StraceLogging {
-                file: RefCell::new(unsafe { std::fs::File::from_raw_fd(fd) }),
-                options,
-            }
-        });
+        let strace_logging =
+            strace_logging_options.map(|options| StraceLogging::new(&file_basename, options));
 
         let shim_shared_mem = ProcessShmem::new(
             &host.shim_shmem_lock_borrow().unwrap().root,
@@ -735,6 +1337,8 @@ impl Process {
         )
         .unwrap();
 
+        let shim_ld_preload = Self::ld_preload_value(&envv);
+
         let mthread = ManagedThread::spawn(
             plugin_path,
             argv,
@@ -748,6 +1352,12 @@ impl Process {
 
         debug!("process '{:?}' started", plugin_name);
 
+        host.trace_event(|| event_trace::Event::ProcessStart {
+            pid: process_id.into(),
+            parent_pid: None,
+            name: plugin_name.to_string_lossy().into_owned(),
+        });
+
         if pause_for_debugging {
             // will block until logger output has been flushed
             // there is a race condition where other threads may log between the
@@ -785,6 +1395,9 @@ impl Process {
             working_dir,
             name,
             plugin_name,
+            parent_id: Cell::new(None),
+            process_group_id: process_id,
+            exit_signal: None,
         };
         RootedRc::new(
             host.root(),
@@ -798,9 +1411,13 @@ impl Process {
                         memory_manager: Box::new(RefCell::new(memory_manager)),
                         desc_table,
                         itimer_real,
+                        child_events: ChildEventSource::new(),
+                        vfork_parent: RefCell::new(None),
+                        signal_events: SignalEventSource::new(),
                         strace_logging,
                         dumpable: Cell::new(cshadow::SUID_DUMP_USER),
-                        native_pid,
+                        native_pid: Cell::new(native_pid),
+                        shim_ld_preload,
                         unsafe_borrow_mut: RefCell::new(None),
                         unsafe_borrows: RefCell::new(Vec::new()),
                         threads,
@@ -918,6 +1535,14 @@ impl Process {
                 );
                 self.handle_process_exit(host, false);
             }
+            crate::host::thread::ResumeResult::ExecedProcess => {
+                // The thread was already removed from the thread list, and
+                // the new process image was scheduled to run, by `exec`.
+                debug!(
+                    "thread {tid} in process '{}' replaced the process image",
+                    &*self.name(),
+                );
+            }
         };
 
         Worker::clear_active_thread();
@@ -1010,6 +1635,17 @@ impl Process {
         );
     }
 
+    fn is_ld_preload_entry(env_entry: &CStr) -> bool {
+        env_entry.to_bytes().starts_with(b"LD_PRELOAD=")
+    }
+
+    /// Returns the value of the `LD_PRELOAD` entry in `envv`, if any.
+    fn ld_preload_value(envv: &[CString]) -> Option<CString> {
+        envv.iter()
+            .find(|x| Self::is_ld_preload_entry(x))
+            .map(|x| CString::new(&x.as_bytes()[b"LD_PRELOAD=".len()..]).unwrap())
+    }
+
     // Needed during early init, before `Self` is created.
     fn static_output_file_name(file_basename: &Path, extension: &str) -> PathBuf {
         let mut path = file_basename.to_owned().into_os_string();
@@ -1026,6 +1662,10 @@ impl Process {
         Ref::map(self.common(), |c| c.plugin_name.to_str().unwrap())
     }
 
+    pub fn working_dir(&self) -> impl Deref<Target = CStr> + '_ {
+        Ref::map(self.common(), |c| c.working_dir.as_c_str())
+    }
+
     /// Deprecated wrapper for `RunnableProcess::memory_borrow_mut`
     #[track_caller]
     pub fn memory_borrow_mut(&self) -> impl Deref<Target = MemoryManager> + DerefMut + '_ {
@@ -1072,7 +1712,7 @@ impl Process {
 
     /// Deprecated wrapper for `RunnableProcess::native_pid`
     pub fn native_pid(&self) -> Pid {
-        self.runnable().unwrap().native_pid
+        self.runnable().unwrap().native_pid()
     }
 
     /// Deprecated wrapper for `RunnableProcess::realtime_timer_borrow`
@@ -1182,7 +1822,7 @@ impl Process {
         use nix::sys::wait::WaitStatus;
         let exit_status = match (
             killed_by_shadow,
-            nix::sys::wait::waitpid(runnable.native_pid, None),
+            nix::sys::wait::waitpid(runnable.native_pid(), None),
         ) {
             (true, Ok(WaitStatus::Signaled(_pid, nixsignal::Signal::SIGKILL, _core_dump))) => {
                 ExitStatus::StoppedByShadow
@@ -1227,6 +1867,15 @@ impl Process {
         };
         log::log!(log_level, "{}", main_result_string);
 
+        host.trace_event(|| event_trace::Event::ProcessExit {
+            pid: runnable.common.id().into(),
+            status: match exit_status {
+                ExitStatus::Normal(code) => event_trace::ExitStatus::Exited(code),
+                ExitStatus::Signaled(signal) => event_trace::ExitStatus::Signaled(signal as i32),
+                ExitStatus::StoppedByShadow => event_trace::ExitStatus::StoppedByShadow,
+            },
+        });
+
         *opt_state = Some(ProcessState::Zombie(ZombieProcess {
             common: runnable.into_common(),
             exit_status,
@@ -1253,10 +1902,36 @@ impl Process {
         Ref::map(self.runnable().unwrap(), |r| &r.shim_shared_mem_block)
     }
 
-    /// The parent of this process.
+    /// The parent of this process, if it was created by `fork` and the parent
+    /// hasn't yet exited.
     pub fn ppid(&self) -> Option<ProcessId> {
-        // We don't yet support child processes, so there is never a ppid.
-        None
+        self.common().parent_id.get()
+    }
+
+    /// Forget the parent of this process; e.g. because the parent exited, or
+    /// has reaped `self`.
+    pub fn clear_ppid(&self) {
+        self.common().parent_id.set(None)
+    }
+
+    /// The id of the process group that this process belongs to.
+    pub fn process_group_id(&self) -> ProcessId {
+        self.common().process_group_id
+    }
+
+    /// The signal sent to the parent when this process exits, if any.
+    pub fn exit_signal(&self) -> Option<Signal> {
+        self.common().exit_signal
+    }
+
+    /// See `RunnableProcess::handle_child_exit`. Also returns `true` if `self`
+    /// is no longer running, since then nothing can reap `child`.
+    pub fn handle_child_exit(&self, host: &Host, child: &Process) -> bool {
+        let Some(runnable) = self.runnable() else {
+            return true;
+        };
+        let child = child.zombie().unwrap();
+        runnable.handle_child_exit(host, &child)
     }
 }
 
@@ -1264,9 +1939,8 @@ impl Drop for Process {
     fn drop(&mut self) {
         // Should only be dropped in the zombie state.
         debug_assert!(self.zombie().is_some());
-        // Shouldn't be dropped while a parent exists.
-        // Assuming for now that once we implement parent processes, we'll clear
-        // the parent id after the child has been reaped or the parent exits.
+        // Shouldn't be dropped while a parent exists. The parent id is cleared
+        // after the child has been reaped or the parent exits.
         debug_assert!(self.ppid().is_none());
     }
 }
@@ -1875,6 +2549,48 @@ mod export {
         proc.id().into()
     }
 
+    /// Returns the id of the parent process, or 0 if there isn't one.
+    #[no_mangle]
+    pub unsafe extern "C" fn process_getParentProcessID(proc: *const Process) -> libc::pid_t {
+        let proc = unsafe { proc.as_ref().unwrap() };
+        proc.ppid().map(libc::pid_t::from).unwrap_or(0)
+    }
+
+    /// Drop a reference to the `ChildEventSource`. The pointer must not be used
+    /// after calling this function.
+    #[no_mangle]
+    pub extern "C" fn childeventsource_drop(source: *const ChildEventSource) {
+        assert!(!source.is_null());
+
+        unsafe { Box::from_raw(source as *mut ChildEventSource) };
+    }
+
+    /// Add a status listener to the `ChildEventSource`. This will increment the
+    /// status listener's ref count, and will decrement the ref count when this
+    /// status listener is removed or when the source is dropped.
+    #[no_mangle]
+    pub unsafe extern "C" fn childeventsource_addListener(
+        source: *const ChildEventSource,
+        listener: *mut cshadow::StatusListener,
+    ) {
+        assert!(!listener.is_null());
+        let source = unsafe { source.as_ref() }.unwrap();
+
+        source.add_legacy_listener(HostTreePointer::new(listener));
+    }
+
+    /// Remove a listener from the `ChildEventSource`.
+    #[no_mangle]
+    pub unsafe extern "C" fn childeventsource_removeListener(
+        source: *const ChildEventSource,
+        listener: *mut cshadow::StatusListener,
+    ) {
+        assert!(!listener.is_null());
+        let source = unsafe { source.as_ref() }.unwrap();
+
+        source.remove_legacy_listener(listener);
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn process_getHostId(proc: *const Process) -> HostId {
         let proc = unsafe { proc.as_ref().unwrap() };
@@ -2031,4 +2747,21 @@ mod export {
         Worker::with_active_host(|host| target_proc.signal(host, current_running_thread, siginfo_t))
             .unwrap()
     }
+
+    /// Notify listeners, such as signalfds, that the signals pending for the
+    /// process or its threads may have changed. Should be called after making
+    /// a signal pending for a thread.
+    #[no_mangle]
+    pub unsafe extern "C" fn process_notifySignalsChanged(proc: *const Process) {
+        let proc = unsafe { proc.as_ref().unwrap() };
+        Worker::with_active_host(|host| {
+            let Some(runnable) = proc.runnable() else {
+                return;
+            };
+            CallbackQueue::queue_and_run(|cb_queue| {
+                runnable.notify_signals_changed(host, cb_queue)
+            });
+        })
+        .unwrap()
+    }
 }
@@ -8,7 +8,7 @@
 #define SHD_PROTOCOL_H_
 
 typedef enum _ProtocolType ProtocolType;
-enum _ProtocolType { PNONE, PLOCAL, PTCP, PUDP, PMOCK };
+enum _ProtocolType { PNONE, PLOCAL, PTCP, PUDP, PMOCK, PICMP };
 
 enum ProtocolLocalFlags {
     PLOCAL_NONE = 0,
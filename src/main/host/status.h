@@ -25,6 +25,19 @@ enum _Status {
     /* a listening socket is allowing connections; only applicable to connection-oriented unix
      * sockets */
     STATUS_SOCKET_ALLOWING_CONNECT = 1 << 5,
+    /* a child of the process has exited */
+    STATUS_CHILD_EXITED = 1 << 6,
+    /* the file has been hung up, e.g. the peer of a connected socket has closed, or all of a
+     * pipe's writers have closed */
+    STATUS_FILE_HUP = 1 << 7,
+    /* the peer of a connected socket has shut down its writing half, or the socket has been shut
+     * down for reading */
+    STATUS_SOCKET_RDHUP = 1 << 8,
+    /* the socket has a pending error, or errors in its error queue */
+    STATUS_SOCKET_ERROR = 1 << 9,
+    /* a child of the process created with vfork has released its parent by calling execve or
+     * exiting */
+    STATUS_CHILD_VFORK_DONE = 1 << 10,
 };
 
 #endif // SRC_MAIN_HOST_STATUS_H
@@ -319,6 +319,24 @@ SyscallReturn syscallhandler_mkdirat(SysCallHandler* sys, const SysCallArgs* arg
     return syscallreturn_makeDoneI64(regularfile_mkdirat(dir_desc, pathname, mode, plugin_cwd));
 }
 
+SyscallReturn syscallhandler_mkdir(SysCallHandler* sys, const SysCallArgs* args) {
+    UntypedForeignPtr pathnamePtr = args->args[0].as_ptr; // const char*
+    mode_t mode = args->args[1].as_u64;
+
+    /* Validate params. */
+    const char* pathname;
+
+    int errcode =
+        _syscallhandler_validateDirAndPathnameHelper(sys, AT_FDCWD, pathnamePtr, NULL, &pathname);
+    if (errcode < 0) {
+        return syscallreturn_makeDoneErrno(-errcode);
+    }
+
+    const char* plugin_cwd = process_getWorkingDir(_syscallhandler_getProcess(sys));
+
+    return syscallreturn_makeDoneI64(regularfile_mkdirat(NULL, pathname, mode, plugin_cwd));
+}
+
 SyscallReturn syscallhandler_mknodat(SysCallHandler* sys, const SysCallArgs* args) {
     int dirfd = args->args[0].as_i64;
     UntypedForeignPtr pathnamePtr = args->args[1].as_ptr; // const char*
@@ -393,6 +411,41 @@ SyscallReturn syscallhandler_unlinkat(SysCallHandler* sys, const SysCallArgs* ar
     return syscallreturn_makeDoneI64(regularfile_unlinkat(dir_desc, pathname, flags, plugin_cwd));
 }
 
+SyscallReturn syscallhandler_unlink(SysCallHandler* sys, const SysCallArgs* args) {
+    UntypedForeignPtr pathnamePtr = args->args[0].as_ptr; // const char*
+
+    /* Validate params. */
+    const char* pathname;
+
+    int errcode =
+        _syscallhandler_validateDirAndPathnameHelper(sys, AT_FDCWD, pathnamePtr, NULL, &pathname);
+    if (errcode < 0) {
+        return syscallreturn_makeDoneErrno(-errcode);
+    }
+
+    const char* plugin_cwd = process_getWorkingDir(_syscallhandler_getProcess(sys));
+
+    return syscallreturn_makeDoneI64(regularfile_unlinkat(NULL, pathname, 0, plugin_cwd));
+}
+
+SyscallReturn syscallhandler_rmdir(SysCallHandler* sys, const SysCallArgs* args) {
+    UntypedForeignPtr pathnamePtr = args->args[0].as_ptr; // const char*
+
+    /* Validate params. */
+    const char* pathname;
+
+    int errcode =
+        _syscallhandler_validateDirAndPathnameHelper(sys, AT_FDCWD, pathnamePtr, NULL, &pathname);
+    if (errcode < 0) {
+        return syscallreturn_makeDoneErrno(-errcode);
+    }
+
+    const char* plugin_cwd = process_getWorkingDir(_syscallhandler_getProcess(sys));
+
+    return syscallreturn_makeDoneI64(
+        regularfile_unlinkat(NULL, pathname, AT_REMOVEDIR, plugin_cwd));
+}
+
 SyscallReturn syscallhandler_symlinkat(SysCallHandler* sys, const SysCallArgs* args) {
     UntypedForeignPtr targetpathPtr = args->args[0].as_ptr; // const char*
     int dirfd = args->args[1].as_i64;
@@ -13,6 +13,7 @@ SYSCALL_HANDLER(fchmodat);
 SYSCALL_HANDLER(fchownat);
 SYSCALL_HANDLER(futimesat);
 SYSCALL_HANDLER(linkat);
+SYSCALL_HANDLER(mkdir);
 SYSCALL_HANDLER(mkdirat);
 SYSCALL_HANDLER(mknodat);
 SYSCALL_HANDLER(newfstatat);
@@ -21,8 +22,10 @@ SYSCALL_HANDLER(openat);
 SYSCALL_HANDLER(readlinkat);
 SYSCALL_HANDLER(renameat);
 SYSCALL_HANDLER(renameat2);
+SYSCALL_HANDLER(rmdir);
 SYSCALL_HANDLER(statx);
 SYSCALL_HANDLER(symlinkat);
+SYSCALL_HANDLER(unlink);
 SYSCALL_HANDLER(unlinkat);
 SYSCALL_HANDLER(utimensat);
 
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use linux_api::errno::Errno;
 use linux_api::posix_types::kernel_pid_t;
 use linux_api::sched::CloneFlags;
@@ -5,16 +7,145 @@ use log::{debug, trace, warn};
 use nix::sys::signal::Signal;
 use shadow_shim_helper_rs::rootedcell::rc::RootedRc;
 use shadow_shim_helper_rs::rootedcell::refcell::RootedRefCell;
+use shadow_shim_helper_rs::shim_event::{ShimEventAddThreadParentRes, ShimEventPrivatizeRegion};
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 use syscall_logger::log_syscall;
 
-use crate::host::process::ProcessId;
-use crate::host::syscall_types::SyscallError;
+use crate::host::descriptor::FileState;
+use crate::host::managed_thread::ManagedThread;
+use crate::host::process::{ProcessId, VforkParent};
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
 use crate::host::thread::Thread;
+use crate::utility::proc_maps;
 
 use super::{SyscallContext, SyscallHandler};
 
 impl SyscallHandler {
+    /// Natively fork the current process, as for `clone` without `CLONE_VM`,
+    /// with the given native clone arguments. Also returns the shim's
+    /// description of the calling thread.
+    fn native_fork(
+        ctx: &mut SyscallContext,
+        flags: libc::c_ulong,
+        child_stack: ForeignPtr<()>,
+        newtls: libc::c_ulong,
+    ) -> Result<(ManagedThread, ShimEventAddThreadParentRes), SyscallError> {
+        let (pctx, thread) = ctx.objs.split_thread();
+
+        // Memory shared with Shadow has to be made private in the child. The
+        // list of such regions is passed in memory that *isn't* shared, so
+        // that the child sees its own copy.
+        let regions = pctx.process.memory_borrow().shared_regions();
+        let regions_ptr = if regions.is_empty() {
+            ForeignArrayPtr::new(ForeignPtr::null(), 0)
+        } else {
+            let size = regions.len() * std::mem::size_of::<ShimEventPrivatizeRegion>();
+            let ptr = thread.malloc_foreign_ptr(&pctx, size)?;
+            let ptr = ForeignArrayPtr::new(ptr.cast::<ShimEventPrivatizeRegion>(), regions.len());
+            pctx.process
+                .memory_borrow_mut()
+                .copy_to_ptr(ptr, &regions)?;
+            ptr
+        };
+
+        let res = thread
+            .mthread()
+            .native_fork(ctx.objs, flags, child_stack, newtls, regions_ptr);
+
+        if !regions.is_empty() {
+            thread.free_foreign_ptr(
+                &pctx,
+                regions_ptr.ptr().cast::<u8>(),
+                regions_ptr.len() * std::mem::size_of::<ShimEventPrivatizeRegion>(),
+            )?;
+        }
+
+        Ok(res?)
+    }
+
+    /// The memory of the calling thread that a child process created with
+    /// `CLONE_VM | CLONE_VFORK` shares with it in Linux, and that glibc's
+    /// `posix_spawn` relies on to report errors: the thread's stack from the
+    /// stack pointer up. The shim's thread local storage is excluded, since
+    /// it's private to each process.
+    fn vfork_shared_stack(
+        ctx: &SyscallContext,
+        parent_res: &ShimEventAddThreadParentRes,
+    ) -> Vec<Range<usize>> {
+        let sp = usize::from(parent_res.stack_pointer);
+        let native_pid = ctx.objs.process.native_pid();
+        let mappings = match proc_maps::mappings_for_pid(native_pid.as_raw()) {
+            Ok(mappings) => mappings,
+            Err(e) => {
+                warn!("Couldn't read the memory mappings of {native_pid}: {e}");
+                return Vec::new();
+            }
+        };
+        let Some(stack) = mappings.iter().find(|m| m.begin <= sp && sp < m.end) else {
+            warn!("Couldn't find the mapping containing stack pointer {sp:#x}");
+            return Vec::new();
+        };
+
+        let tls_begin = usize::from(parent_res.shim_tls);
+        let tls_end = tls_begin + usize::try_from(parent_res.shim_tls_len).unwrap();
+        [
+            sp..tls_begin.clamp(sp, stack.end),
+            tls_end.clamp(sp, stack.end)..stack.end,
+        ]
+        .into_iter()
+        .filter(|region| !region.is_empty())
+        .collect()
+    }
+
+    /// Completes a `clone` with `CLONE_VFORK` once the child process `child_id`
+    /// has released the calling thread by calling `execve` or exiting, and
+    /// otherwise blocks until it does.
+    fn vfork_wait(
+        ctx: &mut SyscallContext,
+        child_id: ProcessId,
+        do_parent_settid: bool,
+        ptid: ForeignPtr<kernel_pid_t>,
+    ) -> Result<kernel_pid_t, SyscallError> {
+        let host = ctx.objs.host;
+        let released = match host.process_borrow(child_id) {
+            Some(childrc) => !childrc
+                .borrow(host.root())
+                .borrow_runnable()
+                .is_some_and(|child| child.is_vfork_child()),
+            // Already reaped by another thread.
+            None => true,
+        };
+
+        if !released {
+            let child_events = ctx
+                .objs
+                .process
+                .borrow_runnable()
+                .unwrap()
+                .child_events()
+                .clone();
+            let mut err = SyscallError::new_blocked_on_child(
+                child_events,
+                FileState::CHILD_VFORK_DONE | FileState::CHILD_EXITED,
+                /* restartable= */ false,
+            );
+            // As in Linux, signals stay pending until the child releases us.
+            err.blocked_condition().unwrap().set_uninterruptible(true);
+            return Err(err);
+        }
+
+        ctx.objs.thread.set_vfork_child(None);
+
+        if do_parent_settid {
+            ctx.objs
+                .process
+                .memory_borrow_mut()
+                .write(ptid, &kernel_pid_t::from(child_id))?;
+        }
+
+        Ok(kernel_pid_t::from(child_id))
+    }
+
     fn clone_internal(
         ctx: &mut SyscallContext,
         flags: CloneFlags,
@@ -24,6 +155,17 @@ impl SyscallHandler {
         ctid: ForeignPtr<kernel_pid_t>,
         newtls: u64,
     ) -> Result<kernel_pid_t, SyscallError> {
+        // The calling thread was suspended by a previous call with
+        // `CLONE_VFORK`, and has been woken up.
+        if let Some(child_id) = ctx.objs.thread.vfork_child() {
+            return Self::vfork_wait(
+                ctx,
+                child_id,
+                flags.contains(CloneFlags::CLONE_PARENT_SETTID),
+                ptid,
+            );
+        }
+
         // We use this for a consistency check to validate that we've inspected
         // and emulated all of the provided flags.
         let mut handled_flags = CloneFlags::empty();
@@ -42,15 +184,15 @@ impl SyscallHandler {
         // We use the managed-code provided newtls.
         let native_newtls = newtls;
 
-        if exit_signal.is_some() {
-            warn!("Exit signal is unimplemented");
-            return Err(Errno::ENOTSUP.into());
-        }
         // We use an i8 here because it needs to fit into the lowest 8 bits of
         // the flags parameter to the native clone call.
-        let native_raw_exit_signal: i8 = 0;
+        let mut native_raw_exit_signal: i8 = 0;
 
         if flags.contains(CloneFlags::CLONE_THREAD) {
+            if exit_signal.is_some() {
+                warn!("Exit signal is unimplemented");
+                return Err(Errno::ENOTSUP.into());
+            }
             // From clone(2):
             // > Since Linux 2.5.35, the flags mask must also include
             // > CLONE_SIGHAND if CLONE_THREAD is specified
@@ -84,8 +226,40 @@ impl SyscallHandler {
 
             handled_flags.insert(CloneFlags::CLONE_THREAD);
         } else {
-            warn!("Failing clone: we don't support creating a new process (e.g. fork, vfork) yet");
-            return Err(Errno::ENOTSUP.into());
+            match exit_signal {
+                // We only construct the siginfo for `SIGCHLD` when notifying
+                // the parent that the child exited. Other exit signals are
+                // rarely used.
+                None | Some(Signal::SIGCHLD) => (),
+                Some(s) => {
+                    warn!("Exit signal {s:?} for new process is unimplemented");
+                    return Err(Errno::ENOTSUP.into());
+                }
+            }
+            if flags.contains(CloneFlags::CLONE_VM) {
+                if !flags.contains(CloneFlags::CLONE_VFORK) {
+                    warn!("Failing clone: we don't support a new process sharing virtual memory");
+                    return Err(Errno::ENOTSUP.into());
+                }
+                // With CLONE_VFORK, the parent is suspended until the child
+                // calls `execve` or exits, so it can't observe whether memory
+                // is actually shared while the child runs. We create the child
+                // with a copy of the parent's memory instead, as for `fork`,
+                // and copy the calling thread's stack back to the parent when
+                // the child releases it; see `vfork_shared_stack`.
+                debug!("Treating CLONE_VM | CLONE_VFORK as a fork");
+            }
+            if flags.contains(CloneFlags::CLONE_SIGHAND) {
+                warn!("Failing clone: we don't support a new process sharing signal handlers");
+                return Err(Errno::ENOTSUP.into());
+            }
+            // The native clone call will:
+            // - create a process whose native parent is Shadow, so that Shadow
+            //   can reap it as it does for processes it spawns directly.
+            native_flags.insert(CloneFlags::CLONE_PARENT);
+            // - notify Shadow when it exits, as required to reap it with
+            //   `waitpid`.
+            native_raw_exit_signal = Signal::SIGCHLD as i8;
         }
 
         if flags.contains(CloneFlags::CLONE_SIGHAND) {
@@ -123,10 +297,23 @@ impl SyscallHandler {
         }
 
         if flags.contains(CloneFlags::CLONE_VM) {
-            native_flags.insert(CloneFlags::CLONE_VM);
+            // Only passed through for new threads; see above.
+            if flags.contains(CloneFlags::CLONE_THREAD) {
+                native_flags.insert(CloneFlags::CLONE_VM);
+            }
             handled_flags.insert(CloneFlags::CLONE_VM);
         }
 
+        if flags.contains(CloneFlags::CLONE_VFORK) {
+            // Only supported for new processes, where we suspend the calling
+            // thread after the native clone; see above.
+            if flags.contains(CloneFlags::CLONE_THREAD) {
+                warn!("Failing clone: CLONE_VFORK for a new thread is unimplemented");
+                return Err(Errno::ENOTSUP.into());
+            }
+            handled_flags.insert(CloneFlags::CLONE_VFORK);
+        }
+
         if flags.contains(CloneFlags::CLONE_SYSVSEM) {
             // Currently a no-op since we don't support sysv semaphores.
             handled_flags.insert(CloneFlags::CLONE_SYSVSEM);
@@ -150,14 +337,22 @@ impl SyscallHandler {
             return Err(Errno::ENOTSUP.into());
         }
 
-        let child_mthread = ctx.objs.thread.mthread().native_clone(
-            ctx.objs,
-            native_flags.bits() | (native_raw_exit_signal as u64),
-            native_child_stack,
-            native_ptid,
-            native_ctid,
-            native_newtls,
-        )?;
+        let native_flags = native_flags.bits() | (native_raw_exit_signal as u64);
+        let (child_mthread, fork_parent_res) = if flags.contains(CloneFlags::CLONE_THREAD) {
+            let child_mthread = ctx.objs.thread.mthread().native_clone(
+                ctx.objs,
+                native_flags,
+                native_child_stack,
+                native_ptid,
+                native_ctid,
+                native_newtls,
+            )?;
+            (child_mthread, None)
+        } else {
+            let (child_mthread, parent_res) =
+                Self::native_fork(ctx, native_flags, native_child_stack, native_newtls)?;
+            (child_mthread, Some(parent_res))
+        };
 
         let child_tid = ctx.objs.host.get_new_thread_id();
         let child_pid = if flags.contains(CloneFlags::CLONE_THREAD) {
@@ -169,6 +364,10 @@ impl SyscallHandler {
         let child_thread =
             Thread::wrap_mthread(ctx.objs.host, child_mthread, child_pid, child_tid)?;
 
+        if do_child_cleartid {
+            child_thread.set_tid_address(ctid);
+        }
+
         if flags.contains(CloneFlags::CLONE_THREAD) {
             let childrc = RootedRc::new(
                 ctx.objs.host.root(),
@@ -176,7 +375,66 @@ impl SyscallHandler {
             );
             ctx.objs.process.add_thread(ctx.objs.host, childrc);
         } else {
-            unreachable!("Should have already bailed above");
+            // The child inherits the calling thread's signal mask.
+            {
+                let host_shmem = ctx.objs.host.shim_shmem_lock_borrow().unwrap();
+                let blocked_signals = ctx
+                    .objs
+                    .thread
+                    .shmem()
+                    .protected
+                    .borrow(&host_shmem.root)
+                    .blocked_signals;
+                child_thread
+                    .shmem()
+                    .protected
+                    .borrow_mut(&host_shmem.root)
+                    .blocked_signals = blocked_signals;
+            }
+            let childrc = ctx
+                .objs
+                .process
+                .borrow_runnable()
+                .unwrap()
+                .new_forked_process(
+                    ctx.objs.host,
+                    child_thread,
+                    exit_signal.map(|s| linux_api::signal::Signal::try_from(s as i32).unwrap()),
+                );
+            if do_child_settid {
+                // Written in the child's copy of memory. As in Linux, failure
+                // isn't reported to the caller.
+                if let Err(e) = childrc
+                    .borrow(ctx.objs.host.root())
+                    .memory_borrow_mut()
+                    .write(ctid, &kernel_pid_t::from(child_tid))
+                {
+                    debug!("Couldn't write child tid to {ctid:?} in new process: {e:?}");
+                }
+            }
+            if flags.contains(CloneFlags::CLONE_VFORK) {
+                let shared = if flags.contains(CloneFlags::CLONE_VM) {
+                    Self::vfork_shared_stack(ctx, fork_parent_res.as_ref().unwrap())
+                } else {
+                    Vec::new()
+                };
+                childrc
+                    .borrow(ctx.objs.host.root())
+                    .borrow_runnable()
+                    .unwrap()
+                    .set_vfork_parent(VforkParent {
+                        id: ctx.objs.process.id(),
+                        shared,
+                    });
+                ctx.objs.thread.set_vfork_child(Some(child_pid));
+            }
+            ctx.objs.host.add_and_schedule_forked_process(childrc);
+        }
+
+        if flags.contains(CloneFlags::CLONE_VFORK) {
+            // Suspend the calling thread until the child releases it. `ptid`
+            // is written then, after the stack has been copied back.
+            return Self::vfork_wait(ctx, child_pid, do_parent_settid, ptid);
         }
 
         if do_parent_settid {
@@ -186,21 +444,13 @@ impl SyscallHandler {
                 .write(ptid, &kernel_pid_t::from(child_tid))?;
         }
 
-        if do_child_settid {
-            // FIXME: handle the case where child doesn't share virtual memory.
-            assert!(flags.contains(CloneFlags::CLONE_VM));
+        if do_child_settid && flags.contains(CloneFlags::CLONE_THREAD) {
             ctx.objs
                 .process
                 .memory_borrow_mut()
                 .write(ctid, &kernel_pid_t::from(child_tid))?;
         }
 
-        if do_child_cleartid {
-            let childrc = ctx.objs.process.thread_borrow(child_tid).unwrap();
-            let child = childrc.borrow(ctx.objs.host.root());
-            child.set_tid_address(ctid);
-        }
-
         Ok(kernel_pid_t::from(child_tid))
     }
 
@@ -284,8 +534,6 @@ impl SyscallHandler {
 
     #[log_syscall(/* rv */kernel_pid_t)]
     pub fn fork(ctx: &mut SyscallContext) -> Result<kernel_pid_t, SyscallError> {
-        // This should be the correct call to `clone_internal`, but `clone_internal`
-        // will currently return an error.
         Self::clone_internal(
             ctx,
             CloneFlags::empty(),
@@ -299,8 +547,6 @@ impl SyscallHandler {
 
     #[log_syscall(/* rv */kernel_pid_t)]
     pub fn vfork(ctx: &mut SyscallContext) -> Result<kernel_pid_t, SyscallError> {
-        // This should be the correct call to `clone_internal`, but `clone_internal`
-        // will currently return an error.
         Self::clone_internal(
             ctx,
             CloneFlags::CLONE_VFORK | CloneFlags::CLONE_VM,
@@ -0,0 +1,285 @@
+use std::sync::Arc;
+
+use linux_api::errno::Errno;
+use linux_api::fcntl::DescriptorFlags;
+use nix::sys::epoll::{EpollCreateFlags, EpollFlags};
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+use shadow_shim_helper_rs::simulation_time::SimulationTime;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+use syscall_logger::log_syscall;
+
+use crate::core::worker::Worker;
+use crate::host::descriptor::epoll::{Epoll, EpollCtlOp};
+use crate::host::descriptor::{CompatFile, Descriptor, File, FileState, FileStatus, OpenFile};
+use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
+use crate::utility::callback_queue::CallbackQueue;
+
+impl SyscallHandler {
+    #[log_syscall(/* rv */ std::ffi::c_int, /* size */ std::ffi::c_int)]
+    pub fn epoll_create(
+        ctx: &mut SyscallContext,
+        size: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        // epoll_create(2): "the size argument is ignored, but must be greater than zero"
+        if size <= 0 {
+            log::trace!("Invalid epoll_create size {size}");
+            return Err(Errno::EINVAL.into());
+        }
+
+        Self::epoll_create_helper(ctx, 0)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* flags */ std::ffi::c_int)]
+    pub fn epoll_create1(
+        ctx: &mut SyscallContext,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        Self::epoll_create_helper(ctx, flags)
+    }
+
+    fn epoll_create_helper(
+        ctx: &mut SyscallContext,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let Some(flags) = EpollCreateFlags::from_bits(flags) else {
+            log::trace!("Invalid epoll_create1 flags: {flags}");
+            return Err(Errno::EINVAL.into());
+        };
+
+        let mut desc_flags = DescriptorFlags::empty();
+
+        if flags.contains(EpollCreateFlags::EPOLL_CLOEXEC) {
+            desc_flags.insert(DescriptorFlags::FD_CLOEXEC);
+        }
+
+        let epoll = Epoll::new(FileStatus::empty());
+        let mut desc = Descriptor::new(CompatFile::New(OpenFile::new(File::Epoll(epoll))));
+        desc.set_flags(desc_flags);
+
+        let fd = ctx
+            .objs
+            .process
+            .descriptor_table_borrow_mut()
+            .register_descriptor(desc)
+            .or(Err(Errno::ENFILE))?;
+
+        log::trace!("Created epoll fd {fd}");
+
+        Ok(fd.val().try_into().unwrap())
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* epfd */ std::ffi::c_int, /* op */ std::ffi::c_int,
+                  /* fd */ std::ffi::c_int, /* event */ *const std::ffi::c_void)]
+    pub fn epoll_ctl(
+        ctx: &mut SyscallContext,
+        epfd: std::ffi::c_int,
+        op: std::ffi::c_int,
+        fd: std::ffi::c_int,
+        event_ptr: ForeignPtr<libc::epoll_event>,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        // the event is ignored for EPOLL_CTL_DEL, so it may be NULL
+        let (events, data) = if op != libc::EPOLL_CTL_DEL {
+            let event = ctx.objs.process.memory_borrow().read(event_ptr)?;
+            (
+                EpollFlags::from_bits_truncate(event.events as i32),
+                event.u64,
+            )
+        } else {
+            (EpollFlags::empty(), 0)
+        };
+
+        let (epoll, target) = {
+            let desc_table = ctx.objs.process.descriptor_table_borrow();
+            let epoll_desc = Self::get_descriptor(&desc_table, epfd)?;
+            let target_desc = Self::get_descriptor(&desc_table, fd)?;
+
+            let CompatFile::New(target) = target_desc.file() else {
+                // the only legacy files are regular files, which don't support polling
+                log::trace!("Can't monitor fd {fd} with epoll");
+                return Err(Errno::EPERM.into());
+            };
+
+            let epoll = match epoll_desc.file() {
+                CompatFile::New(file) => match file.inner_file() {
+                    File::Epoll(epoll) => Arc::clone(epoll),
+                    _ => {
+                        log::trace!("fd {epfd} is not an epoll");
+                        return Err(Errno::EINVAL.into());
+                    }
+                },
+                CompatFile::Legacy(_) => {
+                    log::trace!("fd {epfd} is not an epoll");
+                    return Err(Errno::EINVAL.into());
+                }
+            };
+
+            (epoll, target.inner_file().clone())
+        };
+
+        let Ok(op) = EpollCtlOp::try_from(op) else {
+            log::trace!("Invalid epoll_ctl operation {op}");
+            return Err(Errno::EINVAL.into());
+        };
+
+        log::trace!("Calling epoll_ctl({op:?}) on epoll {epfd} with fd {fd} and events {events:?}");
+
+        CallbackQueue::queue_and_run(|cb_queue| {
+            epoll
+                .borrow_mut()
+                .ctl(op, fd, target, events, data, cb_queue)
+        })?;
+
+        Ok(0)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* epfd */ std::ffi::c_int,
+                  /* events */ *const std::ffi::c_void, /* max_events */ std::ffi::c_int,
+                  /* timeout */ std::ffi::c_int)]
+    pub fn epoll_wait(
+        ctx: &mut SyscallContext,
+        epfd: std::ffi::c_int,
+        events_ptr: ForeignPtr<libc::epoll_event>,
+        max_events: std::ffi::c_int,
+        timeout_ms: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let timeout = timeout_from_millis(timeout_ms);
+        Self::epoll_wait_helper(ctx, epfd, events_ptr, max_events, timeout)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* epfd */ std::ffi::c_int,
+                  /* events */ *const std::ffi::c_void, /* max_events */ std::ffi::c_int,
+                  /* timeout */ std::ffi::c_int, /* sigmask */ *const std::ffi::c_void)]
+    pub fn epoll_pwait(
+        ctx: &mut SyscallContext,
+        epfd: std::ffi::c_int,
+        events_ptr: ForeignPtr<libc::epoll_event>,
+        max_events: std::ffi::c_int,
+        timeout_ms: std::ffi::c_int,
+        sigmask_ptr: ForeignPtr<()>,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        if !sigmask_ptr.is_null() {
+            log::error!(
+                "epoll_pwait called with non-null sigmask, which is not yet supported by shadow; \
+                returning EINVAL"
+            );
+            return Err(Errno::EINVAL.into());
+        }
+
+        let timeout = timeout_from_millis(timeout_ms);
+        Self::epoll_wait_helper(ctx, epfd, events_ptr, max_events, timeout)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* epfd */ std::ffi::c_int,
+                  /* events */ *const std::ffi::c_void, /* max_events */ std::ffi::c_int,
+                  /* timeout */ *const linux_api::time::timespec,
+                  /* sigmask */ *const std::ffi::c_void)]
+    pub fn epoll_pwait2(
+        ctx: &mut SyscallContext,
+        epfd: std::ffi::c_int,
+        events_ptr: ForeignPtr<libc::epoll_event>,
+        max_events: std::ffi::c_int,
+        timeout_ptr: ForeignPtr<linux_api::time::timespec>,
+        sigmask_ptr: ForeignPtr<()>,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        if !sigmask_ptr.is_null() {
+            log::error!(
+                "epoll_pwait2 called with non-null sigmask, which is not yet supported by shadow; \
+                returning EINVAL"
+            );
+            return Err(Errno::EINVAL.into());
+        }
+
+        // epoll_wait(2): "If timeout is NULL, then epoll_pwait2() can block indefinitely"
+        let timeout = if timeout_ptr.is_null() {
+            None
+        } else {
+            let timeout = ctx.objs.process.memory_borrow().read(timeout_ptr)?;
+            let timeout = SimulationTime::try_from(timeout).or(Err(Errno::EINVAL))?;
+            Some(timeout)
+        };
+
+        Self::epoll_wait_helper(ctx, epfd, events_ptr, max_events, timeout)
+    }
+
+    /// Wait for events on the epoll. A `timeout` of `None` blocks indefinitely.
+    fn epoll_wait_helper(
+        ctx: &mut SyscallContext,
+        epfd: std::ffi::c_int,
+        events_ptr: ForeignPtr<libc::epoll_event>,
+        max_events: std::ffi::c_int,
+        timeout: Option<SimulationTime>,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        // linux limits the number of events so that the size of the buffer fits in an int
+        const MAX_EVENTS: usize = i32::MAX as usize / std::mem::size_of::<libc::epoll_event>();
+
+        let max_events = match usize::try_from(max_events) {
+            Ok(x) if x > 0 && x <= MAX_EVENTS => x,
+            _ => {
+                log::trace!("Invalid maxevents {max_events}");
+                return Err(Errno::EINVAL.into());
+            }
+        };
+
+        let epoll = {
+            let desc_table = ctx.objs.process.descriptor_table_borrow();
+            let desc = Self::get_descriptor(&desc_table, epfd)?;
+
+            match desc.file() {
+                CompatFile::New(file) => match file.inner_file() {
+                    File::Epoll(epoll) => Arc::clone(epoll),
+                    _ => return Err(Errno::EINVAL.into()),
+                },
+                CompatFile::Legacy(_) => return Err(Errno::EINVAL.into()),
+            }
+        };
+
+        let events = CallbackQueue::queue_and_run(|cb_queue| {
+            epoll
+                .borrow_mut()
+                .collect_ready_events(max_events, cb_queue)
+        });
+
+        if !events.is_empty() {
+            log::trace!("Found {} ready events on epoll {epfd}", events.len());
+
+            ctx.objs
+                .process
+                .memory_borrow_mut()
+                .copy_to_ptr(ForeignArrayPtr::new(events_ptr, events.len()), &events)?;
+
+            return Ok(events.len().try_into().unwrap());
+        }
+
+        let now = Worker::current_time().unwrap();
+
+        // if we were woken up without any events, keep the deadline from when we started waiting
+        let deadline: Option<EmulatedTime> = match ctx.objs.thread.syscall_condition() {
+            Some(cond) => cond.timeout(),
+            None => match timeout {
+                Some(timeout) => Some(now.checked_add(timeout).ok_or(Errno::EINVAL)?),
+                None => None,
+            },
+        };
+
+        if deadline.is_some_and(|deadline| deadline <= now) {
+            log::trace!("No events are ready on epoll {epfd} and the timeout has expired");
+            return Ok(0);
+        }
+
+        log::trace!("No events are ready on epoll {epfd}, so blocking");
+
+        // an epoll is readable when it has events
+        let mut err = SyscallError::new_blocked(File::Epoll(epoll), FileState::READABLE, false);
+        err.blocked_condition().unwrap().set_timeout(deadline);
+
+        Err(err)
+    }
+}
+
+/// Convert an `epoll_wait(2)` timeout in milliseconds. A negative timeout blocks indefinitely.
+fn timeout_from_millis(timeout_ms: std::ffi::c_int) -> Option<SimulationTime> {
+    u64::try_from(timeout_ms)
+        .ok()
+        .map(SimulationTime::from_millis)
+}
@@ -0,0 +1,166 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use atomic_refcell::AtomicRefCell;
+use linux_api::errno::Errno;
+use linux_api::fcntl::DescriptorFlags;
+use nix::sys::inotify::InitFlags;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+use syscall_logger::log_syscall;
+
+use crate::host::descriptor::inotify::Inotify;
+use crate::host::descriptor::{CompatFile, Descriptor, File, FileStatus, OpenFile};
+use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
+use crate::host::syscall::type_formatting::SyscallStringArg;
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
+use crate::utility::callback_queue::CallbackQueue;
+
+impl SyscallHandler {
+    #[log_syscall(/* rv */ std::ffi::c_int)]
+    pub fn inotify_init(ctx: &mut SyscallContext) -> Result<std::ffi::c_int, SyscallError> {
+        Self::inotify_init_helper(ctx, 0)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* flags */ nix::sys::inotify::InitFlags)]
+    pub fn inotify_init1(
+        ctx: &mut SyscallContext,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        Self::inotify_init_helper(ctx, flags)
+    }
+
+    fn inotify_init_helper(
+        ctx: &mut SyscallContext,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let Some(flags) = InitFlags::from_bits(flags) else {
+            log::debug!("Invalid inotify flags: {flags}");
+            return Err(Errno::EINVAL.into());
+        };
+
+        let mut file_flags = FileStatus::empty();
+        let mut descriptor_flags = DescriptorFlags::empty();
+
+        if flags.contains(InitFlags::IN_NONBLOCK) {
+            file_flags.insert(FileStatus::NONBLOCK);
+        }
+
+        if flags.contains(InitFlags::IN_CLOEXEC) {
+            descriptor_flags.insert(DescriptorFlags::FD_CLOEXEC);
+        }
+
+        let file = Arc::new(AtomicRefCell::new(Inotify::new(file_flags)));
+
+        // receive events from file operations on this host
+        ctx.objs.host.add_inotify(&file);
+
+        let mut desc = Descriptor::new(CompatFile::New(OpenFile::new(File::Inotify(file))));
+        desc.set_flags(descriptor_flags);
+
+        let fd = ctx
+            .objs
+            .process
+            .descriptor_table_borrow_mut()
+            .register_descriptor(desc)
+            .or(Err(Errno::ENFILE))?;
+
+        log::trace!("inotify_init() returning fd {fd}");
+
+        Ok(fd.val().try_into().unwrap())
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* fd */ std::ffi::c_int,
+                  /* pathname */ SyscallStringArg, /* mask */ std::ffi::c_uint)]
+    pub fn inotify_add_watch(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        pathname_ptr: ForeignPtr<u8>,
+        mask: u32,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let inotify = Self::get_inotify(ctx, fd)?;
+
+        // inotify_add_watch(2): "EINVAL: The given event mask contains no valid events; or mask
+        // contains both IN_MASK_ADD and IN_MASK_CREATE"
+        if mask & libc::IN_ALL_EVENTS == 0
+            || (mask & libc::IN_MASK_ADD != 0 && mask & libc::IN_MASK_CREATE != 0)
+        {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let path = {
+            let mem = ctx.objs.process.memory_borrow();
+            let mut buf = vec![0u8; libc::PATH_MAX as usize];
+            let path_ptr = ForeignArrayPtr::new(pathname_ptr, libc::PATH_MAX as usize);
+            mem.copy_str_from_ptr(&mut buf, path_ptr)?.to_owned()
+        };
+
+        if path.to_bytes().is_empty() {
+            return Err(Errno::ENOENT.into());
+        }
+
+        // relative paths are resolved against the process' working directory
+        let path = Path::new(OsStr::from_bytes(path.to_bytes()));
+        let path = if path.is_relative() {
+            let working_dir = ctx.objs.process.working_dir();
+            Path::new(OsStr::from_bytes(working_dir.to_bytes())).join(path)
+        } else {
+            path.to_path_buf()
+        };
+
+        // watches are identified by their canonical path; with IN_DONT_FOLLOW a symbolic link is
+        // watched rather than its target
+        let dont_follow = mask & libc::IN_DONT_FOLLOW != 0;
+        let metadata = if dont_follow {
+            std::fs::symlink_metadata(&path)?
+        } else {
+            std::fs::metadata(&path)?
+        };
+        let path = match (dont_follow, path.parent(), path.file_name()) {
+            (true, Some(parent), Some(name)) => parent.canonicalize()?.join(name),
+            _ => path.canonicalize()?,
+        };
+
+        if mask & libc::IN_ONLYDIR != 0 && !metadata.is_dir() {
+            return Err(Errno::ENOTDIR.into());
+        }
+
+        let wd = inotify.borrow_mut().add_watch(path, mask)?;
+
+        Ok(wd)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* fd */ std::ffi::c_int, /* wd */ std::ffi::c_int)]
+    pub fn inotify_rm_watch(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        wd: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let inotify = Self::get_inotify(ctx, fd)?;
+
+        CallbackQueue::queue_and_run(|cb_queue| inotify.borrow_mut().rm_watch(wd, cb_queue))?;
+
+        Ok(0)
+    }
+
+    /// Get the inotify file for `fd`, or `EINVAL` if it isn't an inotify file.
+    fn get_inotify(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+    ) -> Result<Arc<AtomicRefCell<Inotify>>, SyscallError> {
+        let desc_table = ctx.objs.process.descriptor_table_borrow();
+        let desc = Self::get_descriptor(&desc_table, fd)?;
+
+        let CompatFile::New(file) = desc.file() else {
+            return Err(Errno::EINVAL.into());
+        };
+
+        let File::Inotify(inotify) = file.inner_file() else {
+            log::debug!("fd {fd} is not an inotify file");
+            return Err(Errno::EINVAL.into());
+        };
+
+        Ok(inotify.clone())
+    }
+}
@@ -10,19 +10,24 @@ use crate::host::syscall_types::SyscallReturn;
 use crate::host::syscall_types::{SyscallError, SyscallResult};
 
 mod clone;
+mod epoll;
 mod eventfd;
 mod fcntl;
 mod file;
+mod inotify;
 mod ioctl;
 mod mman;
 mod random;
 mod sched;
+mod signalfd;
 mod socket;
+mod splice;
 mod sysinfo;
 mod time;
 mod timerfd;
 mod uio;
 mod unistd;
+mod wait;
 
 type LegacySyscallFn =
     unsafe extern "C" fn(*mut c::SysCallHandler, *const SysCallArgs) -> SyscallReturn;
@@ -49,11 +54,20 @@ impl SyscallHandler {
             libc::SYS_clone3 => SyscallHandlerFn::call(Self::clone3, &mut ctx),
             libc::SYS_close => SyscallHandlerFn::call(Self::close, &mut ctx),
             libc::SYS_connect => SyscallHandlerFn::call(Self::connect, &mut ctx),
+            libc::SYS_copy_file_range => SyscallHandlerFn::call(Self::copy_file_range, &mut ctx),
             libc::SYS_dup => SyscallHandlerFn::call(Self::dup, &mut ctx),
             libc::SYS_dup2 => SyscallHandlerFn::call(Self::dup2, &mut ctx),
             libc::SYS_dup3 => SyscallHandlerFn::call(Self::dup3, &mut ctx),
+            libc::SYS_epoll_create => SyscallHandlerFn::call(Self::epoll_create, &mut ctx),
+            libc::SYS_epoll_create1 => SyscallHandlerFn::call(Self::epoll_create1, &mut ctx),
+            libc::SYS_epoll_ctl => SyscallHandlerFn::call(Self::epoll_ctl, &mut ctx),
+            libc::SYS_epoll_pwait => SyscallHandlerFn::call(Self::epoll_pwait, &mut ctx),
+            libc::SYS_epoll_pwait2 => SyscallHandlerFn::call(Self::epoll_pwait2, &mut ctx),
+            libc::SYS_epoll_wait => SyscallHandlerFn::call(Self::epoll_wait, &mut ctx),
             libc::SYS_eventfd => SyscallHandlerFn::call(Self::eventfd, &mut ctx),
             libc::SYS_eventfd2 => SyscallHandlerFn::call(Self::eventfd2, &mut ctx),
+            libc::SYS_execve => SyscallHandlerFn::call(Self::execve, &mut ctx),
+            libc::SYS_exit_group => SyscallHandlerFn::call(Self::exit_group, &mut ctx),
             libc::SYS_fcntl => SyscallHandlerFn::call(Self::fcntl, &mut ctx),
             libc::SYS_fork => SyscallHandlerFn::call(Self::fork, &mut ctx),
             libc::SYS_getitimer => SyscallHandlerFn::call(Self::getitimer, &mut ctx),
@@ -62,6 +76,12 @@ impl SyscallHandler {
             libc::SYS_getsockname => SyscallHandlerFn::call(Self::getsockname, &mut ctx),
             libc::SYS_getsockopt => SyscallHandlerFn::call(Self::getsockopt, &mut ctx),
             libc::SYS_gettid => SyscallHandlerFn::call(Self::gettid, &mut ctx),
+            libc::SYS_inotify_add_watch => {
+                SyscallHandlerFn::call(Self::inotify_add_watch, &mut ctx)
+            }
+            libc::SYS_inotify_init => SyscallHandlerFn::call(Self::inotify_init, &mut ctx),
+            libc::SYS_inotify_init1 => SyscallHandlerFn::call(Self::inotify_init1, &mut ctx),
+            libc::SYS_inotify_rm_watch => SyscallHandlerFn::call(Self::inotify_rm_watch, &mut ctx),
             libc::SYS_ioctl => SyscallHandlerFn::call(Self::ioctl, &mut ctx),
             libc::SYS_listen => SyscallHandlerFn::call(Self::listen, &mut ctx),
             libc::SYS_mmap => SyscallHandlerFn::call(Self::mmap, &mut ctx),
@@ -83,6 +103,7 @@ impl SyscallHandler {
             libc::SYS_read => SyscallHandlerFn::call(Self::read, &mut ctx),
             libc::SYS_readv => SyscallHandlerFn::call(Self::readv, &mut ctx),
             libc::SYS_recvfrom => SyscallHandlerFn::call(Self::recvfrom, &mut ctx),
+            libc::SYS_recvmmsg => SyscallHandlerFn::call(Self::recvmmsg, &mut ctx),
             libc::SYS_recvmsg => SyscallHandlerFn::call(Self::recvmsg, &mut ctx),
             libc::SYS_sched_getaffinity => {
                 SyscallHandlerFn::call(Self::sched_getaffinity, &mut ctx)
@@ -91,18 +112,27 @@ impl SyscallHandler {
                 SyscallHandlerFn::call(Self::sched_setaffinity, &mut ctx)
             }
             libc::SYS_sched_yield => SyscallHandlerFn::call(Self::sched_yield, &mut ctx),
+            libc::SYS_sendfile => SyscallHandlerFn::call(Self::sendfile, &mut ctx),
+            libc::SYS_sendmmsg => SyscallHandlerFn::call(Self::sendmmsg, &mut ctx),
             libc::SYS_sendmsg => SyscallHandlerFn::call(Self::sendmsg, &mut ctx),
             libc::SYS_sendto => SyscallHandlerFn::call(Self::sendto, &mut ctx),
             libc::SYS_setitimer => SyscallHandlerFn::call(Self::setitimer, &mut ctx),
             libc::SYS_setsockopt => SyscallHandlerFn::call(Self::setsockopt, &mut ctx),
             libc::SYS_shutdown => SyscallHandlerFn::call(Self::shutdown, &mut ctx),
+            libc::SYS_signalfd => SyscallHandlerFn::call(Self::signalfd, &mut ctx),
+            libc::SYS_signalfd4 => SyscallHandlerFn::call(Self::signalfd4, &mut ctx),
             libc::SYS_socket => SyscallHandlerFn::call(Self::socket, &mut ctx),
             libc::SYS_socketpair => SyscallHandlerFn::call(Self::socketpair, &mut ctx),
+            libc::SYS_splice => SyscallHandlerFn::call(Self::splice, &mut ctx),
             libc::SYS_sysinfo => SyscallHandlerFn::call(Self::sysinfo, &mut ctx),
+            libc::SYS_tee => SyscallHandlerFn::call(Self::tee, &mut ctx),
             libc::SYS_timerfd_create => SyscallHandlerFn::call(Self::timerfd_create, &mut ctx),
             libc::SYS_timerfd_gettime => SyscallHandlerFn::call(Self::timerfd_gettime, &mut ctx),
             libc::SYS_timerfd_settime => SyscallHandlerFn::call(Self::timerfd_settime, &mut ctx),
             libc::SYS_vfork => SyscallHandlerFn::call(Self::vfork, &mut ctx),
+            libc::SYS_vmsplice => SyscallHandlerFn::call(Self::vmsplice, &mut ctx),
+            libc::SYS_wait4 => SyscallHandlerFn::call(Self::wait4, &mut ctx),
+            libc::SYS_waitid => SyscallHandlerFn::call(Self::waitid, &mut ctx),
             libc::SYS_write => SyscallHandlerFn::call(Self::write, &mut ctx),
             libc::SYS_writev => SyscallHandlerFn::call(Self::writev, &mut ctx),
             _ => {
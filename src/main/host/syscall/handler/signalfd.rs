@@ -0,0 +1,126 @@
+use linux_api::errno::Errno;
+use linux_api::fcntl::DescriptorFlags;
+use linux_api::signal::{sigset_t, Signal};
+use nix::sys::signalfd::SfdFlags;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+use syscall_logger::log_syscall;
+
+use crate::host::descriptor::signalfd::SignalFd;
+use crate::host::descriptor::{CompatFile, Descriptor, File, FileStatus, OpenFile};
+use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
+use crate::host::syscall_types::SyscallError;
+use crate::utility::callback_queue::CallbackQueue;
+
+impl SyscallHandler {
+    #[log_syscall(/* rv */ std::ffi::c_int, /* fd */ std::ffi::c_int,
+                  /* mask */ *const std::ffi::c_void, /* sizemask */ libc::size_t)]
+    pub fn signalfd(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        mask_ptr: ForeignPtr<sigset_t>,
+        mask_size: libc::size_t,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        Self::signalfd_helper(ctx, fd, mask_ptr, mask_size, 0)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* fd */ std::ffi::c_int,
+                  /* mask */ *const std::ffi::c_void, /* sizemask */ libc::size_t,
+                  /* flags */ nix::sys::signalfd::SfdFlags)]
+    pub fn signalfd4(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        mask_ptr: ForeignPtr<sigset_t>,
+        mask_size: libc::size_t,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        Self::signalfd_helper(ctx, fd, mask_ptr, mask_size, flags)
+    }
+
+    fn signalfd_helper(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        mask_ptr: ForeignPtr<sigset_t>,
+        mask_size: libc::size_t,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let Some(flags) = SfdFlags::from_bits(flags) else {
+            log::debug!("Invalid signalfd flags: {flags}");
+            return Err(Errno::EINVAL.into());
+        };
+
+        // the kernel's sigset size, not libc's
+        if mask_size != std::mem::size_of::<sigset_t>() {
+            log::debug!("Invalid signalfd mask size: {mask_size}");
+            return Err(Errno::EINVAL.into());
+        }
+
+        let mut mask = ctx.objs.process.memory_borrow().read(mask_ptr)?;
+
+        // signalfd(2): "It is not possible to receive SIGKILL or SIGSTOP signals via a signalfd
+        // file descriptor; these signals are silently ignored if specified in mask."
+        mask.del(Signal::SIGKILL);
+        mask.del(Signal::SIGSTOP);
+
+        let pending = ctx
+            .objs
+            .process
+            .borrow_runnable()
+            .unwrap()
+            .pending_signals(ctx.objs.host);
+
+        // an fd of -1 creates a new signalfd, otherwise we change the mask of an existing signalfd
+        if fd != -1 {
+            let file = {
+                let desc_table = ctx.objs.process.descriptor_table_borrow();
+                let desc = Self::get_descriptor(&desc_table, fd)?;
+
+                let CompatFile::New(file) = desc.file() else {
+                    return Err(Errno::EINVAL.into());
+                };
+
+                file.inner_file().clone()
+            };
+
+            let File::SignalFd(signalfd) = file else {
+                log::debug!("fd {fd} is not a signalfd");
+                return Err(Errno::EINVAL.into());
+            };
+
+            CallbackQueue::queue_and_run(|cb_queue| {
+                signalfd.borrow_mut().set_mask(mask, pending, cb_queue)
+            });
+
+            return Ok(fd);
+        }
+
+        let mut file_flags = FileStatus::empty();
+        let mut descriptor_flags = DescriptorFlags::empty();
+
+        if flags.contains(SfdFlags::SFD_NONBLOCK) {
+            file_flags.insert(FileStatus::NONBLOCK);
+        }
+
+        if flags.contains(SfdFlags::SFD_CLOEXEC) {
+            descriptor_flags.insert(DescriptorFlags::FD_CLOEXEC);
+        }
+
+        let signalfd = {
+            let runnable = ctx.objs.process.borrow_runnable().unwrap();
+            SignalFd::new(mask, file_flags, runnable.signal_events(), pending)
+        };
+
+        let mut desc = Descriptor::new(CompatFile::New(OpenFile::new(File::SignalFd(signalfd))));
+        desc.set_flags(descriptor_flags);
+
+        let fd = ctx
+            .objs
+            .process
+            .descriptor_table_borrow_mut()
+            .register_descriptor(desc)
+            .or(Err(Errno::ENFILE))?;
+
+        log::trace!("signalfd() returning fd {fd}");
+
+        Ok(fd.val().try_into().unwrap())
+    }
+}
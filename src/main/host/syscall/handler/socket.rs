@@ -1,11 +1,15 @@
 use linux_api::errno::Errno;
 use linux_api::fcntl::DescriptorFlags;
 use log::*;
-use nix::sys::socket::{Shutdown, SockFlag};
+use nix::sys::socket::{AddressFamily, Shutdown, SockFlag};
+use shadow_shim_helper_rs::simulation_time::SimulationTime;
 use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 use syscall_logger::log_syscall;
 
+use crate::core::worker::Worker;
+use crate::host::descriptor::socket::inet::icmp::{IcmpSocket, IcmpSocketKind};
 use crate::host::descriptor::socket::inet::legacy_tcp::LegacyTcpSocket;
+use crate::host::descriptor::socket::inet::tcp::TcpSocket;
 use crate::host::descriptor::socket::inet::udp::UdpSocket;
 use crate::host::descriptor::socket::inet::InetSocket;
 use crate::host::descriptor::socket::unix::{UnixSocket, UnixSocketType};
@@ -68,16 +72,59 @@ impl SyscallHandler {
                     &ctx.objs.host.abstract_unix_namespace(),
                 ))
             }
-            libc::AF_INET => match socket_type {
+            libc::AF_INET | libc::AF_INET6 => match socket_type {
                 libc::SOCK_STREAM => {
                     if protocol != 0 && protocol != libc::IPPROTO_TCP {
                         log::debug!("Unsupported inet stream socket protocol {protocol}");
                         return Err(Errno::EPROTONOSUPPORT.into());
                     }
-                    Socket::Inet(InetSocket::LegacyTcp(LegacyTcpSocket::new(
+                    if ctx.objs.host.params.use_new_tcp {
+                        let send_buf_size = ctx.objs.host.params.init_sock_send_buf_size;
+                        let recv_buf_size = ctx.objs.host.params.init_sock_recv_buf_size;
+                        Socket::Inet(InetSocket::Tcp(TcpSocket::new(
+                            file_flags,
+                            AddressFamily::from_i32(domain).unwrap(),
+                            send_buf_size.try_into().unwrap(),
+                            recv_buf_size.try_into().unwrap(),
+                        )))
+                    } else {
+                        Socket::Inet(InetSocket::LegacyTcp(LegacyTcpSocket::new(
+                            file_flags,
+                            AddressFamily::from_i32(domain).unwrap(),
+                            ctx.objs.host,
+                        )))
+                    }
+                }
+                libc::SOCK_RAW | libc::SOCK_DGRAM if protocol == libc::IPPROTO_ICMP => {
+                    // we don't support ICMPv6
+                    if domain != libc::AF_INET {
+                        log::debug!("Unsupported inet6 socket protocol {protocol}");
+                        return Err(Errno::EPROTONOSUPPORT.into());
+                    }
+                    let kind = match socket_type {
+                        libc::SOCK_RAW => IcmpSocketKind::Raw,
+                        _ => IcmpSocketKind::Ping,
+                    };
+                    let send_buf_size = ctx.objs.host.params.init_sock_send_buf_size;
+                    let recv_buf_size = ctx.objs.host.params.init_sock_recv_buf_size;
+                    let socket = IcmpSocket::new(
                         file_flags,
-                        ctx.objs.host,
-                    )))
+                        kind,
+                        send_buf_size.try_into().unwrap(),
+                        recv_buf_size.try_into().unwrap(),
+                    );
+                    // raw sockets receive a copy of every ICMP packet that arrives at the host
+                    if kind == IcmpSocketKind::Raw {
+                        ctx.objs
+                            .host
+                            .network_namespace_borrow()
+                            .register_raw_icmp_socket(&socket);
+                    }
+                    Socket::Inet(InetSocket::Icmp(socket))
+                }
+                libc::SOCK_RAW => {
+                    log::debug!("Unsupported inet raw socket protocol {protocol}");
+                    return Err(Errno::EPROTONOSUPPORT.into());
                 }
                 libc::SOCK_DGRAM => {
                     if protocol != 0 && protocol != libc::IPPROTO_UDP {
@@ -88,6 +135,7 @@ impl SyscallHandler {
                     let recv_buf_size = ctx.objs.host.params.init_sock_recv_buf_size;
                     Socket::Inet(InetSocket::Udp(UdpSocket::new(
                         file_flags,
+                        AddressFamily::from_i32(domain).unwrap(),
                         send_buf_size.try_into().unwrap(),
                         recv_buf_size.try_into().unwrap(),
                     )))
@@ -261,6 +309,100 @@ impl SyscallHandler {
             return Err(Errno::ENOTSOCK.into());
         };
 
+        let mut result = Self::sendmsg_helper(ctx, socket, msg_ptr, flags);
+
+        // if the syscall will block, keep the file open until the syscall restarts
+        if let Some(err) = result.as_mut().err() {
+            if let Some(cond) = err.blocked_condition() {
+                cond.set_active_file(file);
+            }
+        }
+
+        let bytes_written = result?;
+        Ok(bytes_written)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* sockfd */ std::ffi::c_int,
+                  /* msgvec */ *const libc::mmsghdr, /* vlen */ std::ffi::c_uint,
+                  /* flags */ nix::sys::socket::MsgFlags)]
+    pub fn sendmmsg(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        msgvec_ptr: ForeignPtr<libc::mmsghdr>,
+        vlen: std::ffi::c_uint,
+        flags: std::ffi::c_int,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        // if we were previously blocked, get the active file from the last syscall handler
+        // invocation since it may no longer exist in the descriptor table
+        let file = ctx
+            .objs
+            .thread
+            .syscall_condition()
+            // if this was for a C descriptor, then there won't be an active file object
+            .and_then(|x| x.active_file().cloned());
+
+        let file = match file {
+            // we were previously blocked, so re-use the file from the previous syscall invocation
+            Some(x) => x,
+            // get the file from the descriptor table, or return early if it doesn't exist
+            None => {
+                let desc_table = ctx.objs.process.descriptor_table_borrow();
+                match Self::get_descriptor(&desc_table, fd)?.file() {
+                    CompatFile::New(file) => file.clone(),
+                    CompatFile::Legacy(_file) => {
+                        return Err(Errno::ENOTSOCK.into());
+                    }
+                }
+            }
+        };
+
+        let File::Socket(ref socket) = file.inner_file() else {
+            return Err(Errno::ENOTSOCK.into());
+        };
+
+        // linux silently limits the number of messages
+        let vlen = std::cmp::min(vlen, libc::UIO_MAXIOV.try_into().unwrap());
+
+        let mut num_sent = 0;
+
+        while num_sent < vlen {
+            let mmsg_ptr = msgvec_ptr.add(num_sent.try_into().unwrap());
+
+            let result = Self::sendmsg_helper(ctx, socket, mmsg_ptr.cast::<libc::msghdr>(), flags);
+
+            let bytes_written = match result {
+                Ok(x) => x,
+                // if we've already sent messages, return those instead of the error; we can't
+                // block since a restarted syscall would send them again
+                Err(_) if num_sent > 0 => break,
+                Err(mut err) => {
+                    // if the syscall will block, keep the file open until the syscall restarts
+                    if let Some(cond) = err.blocked_condition() {
+                        cond.set_active_file(file);
+                    }
+                    return Err(err);
+                }
+            };
+
+            io::update_mmsghdr_len(
+                &mut ctx.objs.process.memory_borrow_mut(),
+                mmsg_ptr,
+                bytes_written.try_into().unwrap(),
+            )?;
+
+            num_sent += 1;
+        }
+
+        Ok(num_sent.try_into().unwrap())
+    }
+
+    /// Send the message at `msg_ptr` on the socket.
+    fn sendmsg_helper(
+        ctx: &mut SyscallContext,
+        socket: &Socket,
+        msg_ptr: ForeignPtr<libc::msghdr>,
+        flags: std::ffi::c_int,
+    ) -> Result<libc::ssize_t, SyscallError> {
         let mut mem = ctx.objs.process.memory_borrow_mut();
         let mut rng = ctx.objs.host.random_mut();
         let net_ns = ctx.objs.host.network_namespace_borrow();
@@ -276,21 +418,11 @@ impl SyscallHandler {
         };
 
         // call the socket's sendmsg(), and run any resulting events
-        let mut result = crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+        crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
             CallbackQueue::queue_and_run(|cb_queue| {
                 Socket::sendmsg(socket, args, &mut mem, &net_ns, &mut *rng, cb_queue)
             })
-        });
-
-        // if the syscall will block, keep the file open until the syscall restarts
-        if let Some(err) = result.as_mut().err() {
-            if let Some(cond) = err.blocked_condition() {
-                cond.set_active_file(file);
-            }
-        }
-
-        let bytes_written = result?;
-        Ok(bytes_written)
+        })
     }
 
     #[log_syscall(/* rv */ libc::ssize_t, /* sockfd */ std::ffi::c_int, /* buf */ *const std::ffi::c_void,
@@ -411,6 +543,167 @@ impl SyscallHandler {
             return Err(Errno::ENOTSOCK.into());
         };
 
+        let mut result = Self::recvmsg_helper(ctx, socket, msg_ptr, flags);
+
+        // if the syscall will block, keep the file open until the syscall restarts
+        if let Some(err) = result.as_mut().err() {
+            if let Some(cond) = err.blocked_condition() {
+                cond.set_active_file(file);
+            }
+        }
+
+        let bytes_read = result?;
+        Ok(bytes_read)
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* sockfd */ std::ffi::c_int,
+                  /* msgvec */ *const libc::mmsghdr, /* vlen */ std::ffi::c_uint,
+                  /* flags */ nix::sys::socket::MsgFlags,
+                  /* timeout */ *const linux_api::time::timespec)]
+    pub fn recvmmsg(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        msgvec_ptr: ForeignPtr<libc::mmsghdr>,
+        vlen: std::ffi::c_uint,
+        flags: std::ffi::c_int,
+        timeout_ptr: ForeignPtr<linux_api::time::timespec>,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        // if we were previously blocked, get the active file from the last syscall handler
+        // invocation since it may no longer exist in the descriptor table
+        let file = ctx
+            .objs
+            .thread
+            .syscall_condition()
+            // if this was for a C descriptor, then there won't be an active file object
+            .and_then(|x| x.active_file().cloned());
+
+        let file = match file {
+            // we were previously blocked, so re-use the file from the previous syscall invocation
+            Some(x) => x,
+            // get the file from the descriptor table, or return early if it doesn't exist
+            None => {
+                let desc_table = ctx.objs.process.descriptor_table_borrow();
+                match Self::get_descriptor(&desc_table, fd)?.file() {
+                    CompatFile::New(file) => file.clone(),
+                    CompatFile::Legacy(_file) => {
+                        return Err(Errno::ENOTSOCK.into());
+                    }
+                }
+            }
+        };
+
+        let File::Socket(ref socket) = file.inner_file() else {
+            return Err(Errno::ENOTSOCK.into());
+        };
+
+        let now = Worker::current_time().unwrap();
+
+        // Like linux, the deadline is set when the syscall starts, but is only checked after a
+        // message is received, so it doesn't limit how long we wait for the first message. While
+        // blocked, the deadline is kept as the condition's timeout until it has passed.
+        let deadline = if timeout_ptr.is_null() {
+            None
+        } else if let Some(cond) = ctx.objs.thread.syscall_condition() {
+            // if the condition has no timeout, the deadline had already passed when we blocked
+            Some(cond.timeout().unwrap_or(now))
+        } else {
+            let timeout = ctx.objs.process.memory_borrow().read(timeout_ptr)?;
+            let timeout = SimulationTime::try_from(timeout).or(Err(Errno::EINVAL))?;
+            Some(now.checked_add(timeout).ok_or(Errno::EINVAL)?)
+        };
+
+        // if we were previously blocked, continue after the messages that we already received
+        let mut num_received: std::ffi::c_uint = ctx
+            .objs
+            .thread
+            .syscall_condition()
+            .map(|x| x.progress().try_into().unwrap())
+            .unwrap_or(0);
+
+        // linux silently limits the number of messages
+        let vlen = std::cmp::min(vlen, libc::UIO_MAXIOV.try_into().unwrap());
+
+        // the socket doesn't understand MSG_WAITFORONE
+        let mut msg_flags = flags & !libc::MSG_WAITFORONE;
+
+        while num_received < vlen {
+            if num_received > 0 {
+                if deadline.is_some_and(|deadline| deadline <= now) {
+                    break;
+                }
+
+                // with MSG_WAITFORONE, only the first message can block
+                if flags & libc::MSG_WAITFORONE != 0 {
+                    msg_flags |= libc::MSG_DONTWAIT;
+                }
+            }
+
+            let mmsg_ptr = msgvec_ptr.add(num_received.try_into().unwrap());
+
+            let result =
+                Self::recvmsg_helper(ctx, socket, mmsg_ptr.cast::<libc::msghdr>(), msg_flags);
+
+            let bytes_read = match result {
+                Ok(x) => x,
+                Err(mut err) => {
+                    if let Some(cond) = err.blocked_condition() {
+                        // a signal would interrupt the syscall, so return the messages that we've
+                        // already received instead
+                        if num_received > 0 {
+                            let host_shmem = ctx.objs.host.shim_shmem_lock_borrow().unwrap();
+                            if ctx
+                                .objs
+                                .thread
+                                .unblocked_signal_pending(ctx.objs.process, &host_shmem)
+                            {
+                                break;
+                            }
+                        }
+
+                        // keep the file open and remember our progress until the syscall restarts
+                        cond.set_active_file(file);
+                        cond.set_progress(num_received.try_into().unwrap());
+                        cond.set_timeout(deadline.filter(|deadline| *deadline > now));
+                        return Err(err);
+                    }
+
+                    // if we've already received messages, return those instead of the error
+                    if num_received > 0 {
+                        break;
+                    }
+                    return Err(err);
+                }
+            };
+
+            io::update_mmsghdr_len(
+                &mut ctx.objs.process.memory_borrow_mut(),
+                mmsg_ptr,
+                bytes_read.try_into().unwrap(),
+            )?;
+
+            num_received += 1;
+        }
+
+        // linux updates the timeout with the time remaining
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(&now);
+            let remaining = linux_api::time::timespec::try_from(remaining).unwrap();
+            ctx.objs
+                .process
+                .memory_borrow_mut()
+                .write(timeout_ptr, &remaining)?;
+        }
+
+        Ok(num_received.try_into().unwrap())
+    }
+
+    /// Receive a message from the socket into the message at `msg_ptr`.
+    fn recvmsg_helper(
+        ctx: &mut SyscallContext,
+        socket: &Socket,
+        msg_ptr: ForeignPtr<libc::msghdr>,
+        flags: std::ffi::c_int,
+    ) -> Result<libc::ssize_t, SyscallError> {
         let mut mem = ctx.objs.process.memory_borrow_mut();
 
         let mut msg = io::read_msghdr(&mem, msg_ptr)?;
@@ -422,20 +715,11 @@ impl SyscallHandler {
         };
 
         // call the socket's recvmsg(), and run any resulting events
-        let mut result = crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+        let result = crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
             CallbackQueue::queue_and_run(|cb_queue| {
                 Socket::recvmsg(socket, args, &mut mem, cb_queue)
             })
-        });
-
-        // if the syscall will block, keep the file open until the syscall restarts
-        if let Some(err) = result.as_mut().err() {
-            if let Some(cond) = err.blocked_condition() {
-                cond.set_active_file(file);
-            }
-        }
-
-        let result = result?;
+        })?;
 
         // write the socket address to the plugin and update the length in msg
         if !msg.name.is_null() {
@@ -959,9 +1243,11 @@ impl SyscallHandler {
         // get the provided optlen
         let optlen = mem.read(optlen_ptr)?;
 
-        let mut optlen_new = socket
-            .borrow()
-            .getsockopt(level, optname, optval_ptr, optlen, &mut mem)?;
+        let mut optlen_new = CallbackQueue::queue_and_run(|cb_queue| {
+            socket
+                .borrow_mut()
+                .getsockopt(level, optname, optval_ptr, optlen, &mut mem, cb_queue)
+        })?;
 
         if optlen_new > optlen {
             // this is probably a bug in the socket's getsockopt implementation
@@ -1006,9 +1292,11 @@ impl SyscallHandler {
 
         let mem = ctx.objs.process.memory_borrow();
 
-        socket
-            .borrow_mut()
-            .setsockopt(level, optname, optval_ptr, optlen, &mem)?;
+        CallbackQueue::queue_and_run(|cb_queue| {
+            socket
+                .borrow_mut()
+                .setsockopt(level, optname, optval_ptr, optlen, &mem, cb_queue)
+        })?;
 
         Ok(0.into())
     }
@@ -0,0 +1,757 @@
+use std::sync::Arc;
+
+use atomic_refcell::AtomicRefCell;
+use linux_api::errno::Errno;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+use syscall_logger::log_syscall;
+
+use crate::cshadow as c;
+use crate::host::descriptor::pipe::Pipe;
+use crate::host::descriptor::socket::{RecvmsgArgs, RecvmsgReturn, SendmsgArgs, Socket};
+use crate::host::descriptor::{CompatFile, File, FileMode, FileState, FileStatus};
+use crate::host::memory_manager::AllocdMem;
+use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
+use crate::host::syscall::io::{self, IoVec};
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError};
+use crate::utility::callback_queue::CallbackQueue;
+
+/// The maximum number of bytes that Linux will transfer in a single read or write syscall.
+const MAX_RW_COUNT: usize = (i32::MAX as usize) & !4095;
+
+/// The maximum number of bytes that we move through shadow's memory at a time.
+const CHUNK_LEN: usize = 64 * 1024;
+
+impl SyscallHandler {
+    #[log_syscall(/* rv */ libc::ssize_t, /* out_fd */ std::ffi::c_int, /* in_fd */ std::ffi::c_int,
+                  /* offset */ *const libc::off_t, /* count */ libc::size_t)]
+    pub fn sendfile(
+        ctx: &mut SyscallContext,
+        out_fd: std::ffi::c_int,
+        in_fd: std::ffi::c_int,
+        offset_ptr: ForeignPtr<libc::off_t>,
+        count: libc::size_t,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        let mut src = Self::get_endpoint(ctx, in_fd)?;
+        let mut dst = Self::get_endpoint(ctx, out_fd)?;
+
+        src.check_readable()?;
+        dst.check_writable()?;
+
+        // the source must be a file that supports mmap-like operations, which in shadow means a
+        // regular file
+        let Endpoint::RegularFile { ref mut offset, .. } = src else {
+            return Err(Errno::EINVAL.into());
+        };
+
+        if dst.is_append() {
+            return Err(Errno::EINVAL.into());
+        }
+
+        if !offset_ptr.is_null() {
+            *offset = Some(Self::read_offset(ctx, offset_ptr)?);
+        }
+
+        let options = TransferOptions {
+            consume: true,
+            nonblock: false,
+            single_chunk: false,
+        };
+
+        let num_transferred = Self::transfer(ctx, &mut src, &mut dst, count, options)?;
+
+        // if an offset was given, the file offset is unchanged and the new offset is returned to
+        // the caller instead
+        if let Endpoint::RegularFile {
+            offset: Some(offset),
+            ..
+        } = src
+        {
+            ctx.objs
+                .process
+                .memory_borrow_mut()
+                .write(offset_ptr, &offset)?;
+        }
+
+        Ok(num_transferred.try_into().unwrap())
+    }
+
+    #[log_syscall(/* rv */ libc::ssize_t, /* fd_in */ std::ffi::c_int,
+                  /* off_in */ *const libc::loff_t, /* fd_out */ std::ffi::c_int,
+                  /* off_out */ *const libc::loff_t, /* len */ libc::size_t,
+                  /* flags */ std::ffi::c_uint)]
+    pub fn splice(
+        ctx: &mut SyscallContext,
+        fd_in: std::ffi::c_int,
+        off_in_ptr: ForeignPtr<libc::loff_t>,
+        fd_out: std::ffi::c_int,
+        off_out_ptr: ForeignPtr<libc::loff_t>,
+        len: libc::size_t,
+        flags: std::ffi::c_uint,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        let nonblock = Self::check_splice_flags(flags)?;
+
+        let mut src = Self::get_endpoint(ctx, fd_in)?;
+        let mut dst = Self::get_endpoint(ctx, fd_out)?;
+
+        src.check_readable()?;
+        dst.check_writable()?;
+
+        // at least one of the files must be a pipe
+        match (&src, &dst) {
+            (Endpoint::Pipe(src_pipe), Endpoint::Pipe(dst_pipe)) => {
+                if src_pipe.borrow().same_pipe(&dst_pipe.borrow()) {
+                    return Err(Errno::EINVAL.into());
+                }
+            }
+            (Endpoint::Pipe(_), _) | (_, Endpoint::Pipe(_)) => {}
+            _ => return Err(Errno::EINVAL.into()),
+        }
+
+        if dst.is_append() {
+            return Err(Errno::EINVAL.into());
+        }
+
+        for (endpoint, offset_ptr) in [(&mut src, off_in_ptr), (&mut dst, off_out_ptr)] {
+            match endpoint {
+                Endpoint::RegularFile { offset, .. } => {
+                    if !offset_ptr.is_null() {
+                        *offset = Some(Self::read_offset(ctx, offset_ptr)?);
+                    }
+                }
+                // pipes and sockets don't support offsets
+                Endpoint::Pipe(_) | Endpoint::Socket(_) => {
+                    if !offset_ptr.is_null() {
+                        return Err(Errno::ESPIPE.into());
+                    }
+                }
+            }
+        }
+
+        let options = TransferOptions {
+            consume: true,
+            nonblock,
+            single_chunk: false,
+        };
+
+        let num_transferred = Self::transfer(ctx, &mut src, &mut dst, len, options)?;
+
+        // update the offsets given by the caller
+        for (endpoint, offset_ptr) in [(&src, off_in_ptr), (&dst, off_out_ptr)] {
+            if let Endpoint::RegularFile {
+                offset: Some(offset),
+                ..
+            } = endpoint
+            {
+                ctx.objs
+                    .process
+                    .memory_borrow_mut()
+                    .write(offset_ptr, offset)?;
+            }
+        }
+
+        Ok(num_transferred.try_into().unwrap())
+    }
+
+    #[log_syscall(/* rv */ libc::ssize_t, /* fd_in */ std::ffi::c_int,
+                  /* fd_out */ std::ffi::c_int, /* len */ libc::size_t,
+                  /* flags */ std::ffi::c_uint)]
+    pub fn tee(
+        ctx: &mut SyscallContext,
+        fd_in: std::ffi::c_int,
+        fd_out: std::ffi::c_int,
+        len: libc::size_t,
+        flags: std::ffi::c_uint,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        let nonblock = Self::check_splice_flags(flags)?;
+
+        let mut src = Self::get_endpoint(ctx, fd_in)?;
+        let mut dst = Self::get_endpoint(ctx, fd_out)?;
+
+        // both files must be different pipes
+        let (Endpoint::Pipe(src_pipe), Endpoint::Pipe(dst_pipe)) = (&src, &dst) else {
+            return Err(Errno::EINVAL.into());
+        };
+
+        if src_pipe.borrow().same_pipe(&dst_pipe.borrow()) {
+            return Err(Errno::EINVAL.into());
+        }
+
+        src.check_readable()?;
+        dst.check_writable()?;
+
+        // since the data isn't consumed, we can only copy it once
+        let options = TransferOptions {
+            consume: false,
+            nonblock,
+            single_chunk: true,
+        };
+
+        let num_transferred = Self::transfer(ctx, &mut src, &mut dst, len, options)?;
+
+        Ok(num_transferred.try_into().unwrap())
+    }
+
+    #[log_syscall(/* rv */ libc::ssize_t, /* fd */ std::ffi::c_int, /* iov */ *const libc::iovec,
+                  /* nr_segs */ libc::c_ulong, /* flags */ std::ffi::c_uint)]
+    pub fn vmsplice(
+        ctx: &mut SyscallContext,
+        fd: std::ffi::c_int,
+        iov_ptr: ForeignPtr<libc::iovec>,
+        iov_count: libc::c_ulong,
+        flags: std::ffi::c_uint,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        let nonblock = Self::check_splice_flags(flags)?;
+
+        let file = {
+            let desc_table = ctx.objs.process.descriptor_table_borrow();
+            match Self::get_descriptor(&desc_table, fd)?.file() {
+                CompatFile::New(file) => file.inner_file().clone(),
+                CompatFile::Legacy(_) => return Err(Errno::EBADF.into()),
+            }
+        };
+
+        // the file must be a pipe
+        let File::Pipe(ref pipe) = file else {
+            return Err(Errno::EBADF.into());
+        };
+
+        let iov_count = iov_count.try_into().or(Err(Errno::EINVAL))?;
+
+        let iovs = {
+            let mem = ctx.objs.process.memory_borrow();
+            io::read_iovecs(&mem, iov_ptr, iov_count)?
+        };
+
+        // vmsplice() copies from the iovecs to a pipe's write end, or from a pipe's read end to
+        // the iovecs
+        let result = if pipe.borrow().mode().contains(FileMode::WRITE) {
+            Self::writev_helper(ctx, &file, &iovs, None, 0)
+        } else {
+            Self::readv_helper(ctx, &file, &iovs, None, 0)
+        };
+
+        match result {
+            Err(SyscallError::Blocked(_)) if nonblock => Err(Errno::EAGAIN.into()),
+            x => x,
+        }
+    }
+
+    #[log_syscall(/* rv */ libc::ssize_t, /* fd_in */ std::ffi::c_int,
+                  /* off_in */ *const libc::loff_t, /* fd_out */ std::ffi::c_int,
+                  /* off_out */ *const libc::loff_t, /* len */ libc::size_t,
+                  /* flags */ std::ffi::c_uint)]
+    pub fn copy_file_range(
+        ctx: &mut SyscallContext,
+        fd_in: std::ffi::c_int,
+        off_in_ptr: ForeignPtr<libc::loff_t>,
+        fd_out: std::ffi::c_int,
+        off_out_ptr: ForeignPtr<libc::loff_t>,
+        len: libc::size_t,
+        flags: std::ffi::c_uint,
+    ) -> Result<libc::ssize_t, SyscallError> {
+        // no flags are currently defined
+        if flags != 0 {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let mut src = Self::get_endpoint(ctx, fd_in)?;
+        let mut dst = Self::get_endpoint(ctx, fd_out)?;
+
+        src.check_readable()?;
+        dst.check_writable()?;
+
+        if dst.is_append() {
+            return Err(Errno::EBADF.into());
+        }
+
+        // both files must be regular files
+        let (
+            Endpoint::RegularFile {
+                file: src_file,
+                offset: src_offset,
+            },
+            Endpoint::RegularFile {
+                file: dst_file,
+                offset: dst_offset,
+            },
+        ) = (&mut src, &mut dst)
+        else {
+            return Err(Errno::EINVAL.into());
+        };
+
+        if !off_in_ptr.is_null() {
+            *src_offset = Some(Self::read_offset(ctx, off_in_ptr)?);
+        }
+        if !off_out_ptr.is_null() {
+            *dst_offset = Some(Self::read_offset(ctx, off_out_ptr)?);
+        }
+
+        // the source and destination ranges can't overlap within the same file
+        if *src_file == *dst_file {
+            let src_pos = src_offset.or_else(|| regular_file_position(*src_file));
+            let dst_pos = dst_offset.or_else(|| regular_file_position(*dst_file));
+
+            if let (Some(src_pos), Some(dst_pos)) = (src_pos, dst_pos) {
+                let len = libc::off_t::try_from(len).unwrap_or(libc::off_t::MAX);
+                if src_pos < dst_pos.saturating_add(len) && dst_pos < src_pos.saturating_add(len) {
+                    return Err(Errno::EINVAL.into());
+                }
+            }
+        }
+
+        let options = TransferOptions {
+            consume: true,
+            nonblock: false,
+            single_chunk: false,
+        };
+
+        let num_transferred = Self::transfer(ctx, &mut src, &mut dst, len, options)?;
+
+        // update the offsets given by the caller
+        for (endpoint, offset_ptr) in [(&src, off_in_ptr), (&dst, off_out_ptr)] {
+            if let Endpoint::RegularFile {
+                offset: Some(offset),
+                ..
+            } = endpoint
+            {
+                ctx.objs
+                    .process
+                    .memory_borrow_mut()
+                    .write(offset_ptr, offset)?;
+            }
+        }
+
+        Ok(num_transferred.try_into().unwrap())
+    }
+
+    /// Validate the flags for `splice()`, `tee()`, and `vmsplice()`. Returns whether the
+    /// `SPLICE_F_NONBLOCK` flag was set.
+    fn check_splice_flags(flags: std::ffi::c_uint) -> Result<bool, Errno> {
+        let valid_flags = libc::SPLICE_F_MOVE
+            | libc::SPLICE_F_NONBLOCK
+            | libc::SPLICE_F_MORE
+            | libc::SPLICE_F_GIFT;
+
+        if flags & !valid_flags != 0 {
+            log::debug!("Invalid splice flags: {flags:#x}");
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(flags & libc::SPLICE_F_NONBLOCK != 0)
+    }
+
+    /// Read a file offset from plugin memory. Returns `EINVAL` if it's negative.
+    fn read_offset(
+        ctx: &mut SyscallContext,
+        offset_ptr: ForeignPtr<libc::off_t>,
+    ) -> Result<libc::off_t, Errno> {
+        let offset = ctx.objs.process.memory_borrow().read(offset_ptr)?;
+
+        if offset < 0 {
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(offset)
+    }
+
+    /// Get the file for `fd` as an endpoint for a transfer. Returns `EINVAL` if the file type
+    /// doesn't support transfers.
+    fn get_endpoint(ctx: &mut SyscallContext, fd: std::ffi::c_int) -> Result<Endpoint, Errno> {
+        let desc_table = ctx.objs.process.descriptor_table_borrow();
+
+        let endpoint = match Self::get_descriptor(&desc_table, fd)?.file() {
+            CompatFile::New(file) => match file.inner_file() {
+                File::Pipe(pipe) => Endpoint::Pipe(Arc::clone(pipe)),
+                File::Socket(socket) => Endpoint::Socket(socket.clone()),
+                _ => return Err(Errno::EINVAL),
+            },
+            CompatFile::Legacy(file) => {
+                if unsafe { c::legacyfile_getType(file.ptr()) } != c::_LegacyFileType_DT_FILE {
+                    return Err(Errno::EINVAL);
+                }
+
+                // the descriptor table holds a reference to the file, and the descriptor can't be
+                // closed during this syscall
+                Endpoint::RegularFile {
+                    file: file.ptr() as *mut c::RegularFile,
+                    offset: None,
+                }
+            }
+        };
+
+        Ok(endpoint)
+    }
+
+    /// Move up to `len` bytes from `src` to `dst`. Data is only removed from `src` once it's been
+    /// written to `dst`, so if the syscall blocks no data is lost and the syscall can be restarted
+    /// from the beginning. Returns the number of bytes transferred.
+    fn transfer(
+        ctx: &mut SyscallContext,
+        src: &mut Endpoint,
+        dst: &mut Endpoint,
+        len: usize,
+        options: TransferOptions,
+    ) -> Result<usize, SyscallError> {
+        let len = std::cmp::min(len, MAX_RW_COUNT);
+
+        // scratch memory in the plugin, for sockets which can only access plugin memory
+        let mut plugin_buf = None;
+        let mut num_transferred = 0;
+
+        let result = loop {
+            if num_transferred >= len {
+                break Ok(num_transferred);
+            }
+
+            let chunk_len = std::cmp::min(len - num_transferred, CHUNK_LEN);
+
+            match Self::transfer_chunk(ctx, src, dst, chunk_len, options, &mut plugin_buf) {
+                // end of file
+                Ok(0) => break Ok(num_transferred),
+                Ok(n) => num_transferred += n,
+                // if we've already transferred some data, return that instead of the error
+                Err(_) if num_transferred > 0 => break Ok(num_transferred),
+                Err(e) => break Err(e),
+            }
+
+            if options.single_chunk {
+                break Ok(num_transferred);
+            }
+        };
+
+        if let Some(plugin_buf) = plugin_buf {
+            plugin_buf.free(ctx.objs);
+        }
+
+        result
+    }
+
+    /// Move up to `len` bytes from `src` to `dst`, returning the number of bytes moved.
+    fn transfer_chunk(
+        ctx: &mut SyscallContext,
+        src: &mut Endpoint,
+        dst: &mut Endpoint,
+        mut len: usize,
+        options: TransferOptions,
+        plugin_buf: &mut Option<AllocdMem<u8>>,
+    ) -> Result<usize, SyscallError> {
+        // data read from a socket can't be put back, so only read as much as the pipe has space
+        // for
+        if let (Endpoint::Socket(_), Endpoint::Pipe(pipe)) = (&*src, &*dst) {
+            let space = pipe.borrow().space_available()?;
+            if space == 0 {
+                return Err(pipe_would_block(
+                    pipe,
+                    FileState::WRITABLE,
+                    options.nonblock,
+                ));
+            }
+            len = std::cmp::min(len, space);
+        }
+
+        let mut bytes = vec![0; len];
+
+        // the position of a regular file source, if it's seekable
+        let mut src_pos = None;
+
+        let num_read = match src {
+            Endpoint::RegularFile { file, offset } => {
+                src_pos = offset.or_else(|| regular_file_position(*file));
+
+                let rv = match src_pos {
+                    Some(pos) => unsafe {
+                        c::regularfile_pread(
+                            *file,
+                            ctx.objs.host,
+                            bytes.as_mut_ptr() as *mut libc::c_void,
+                            bytes.len(),
+                            pos,
+                        )
+                    },
+                    // a non-seekable file like "/dev/urandom"; any bytes that we read but can't
+                    // write will be lost
+                    None => unsafe {
+                        c::regularfile_read(
+                            *file,
+                            ctx.objs.host,
+                            bytes.as_mut_ptr() as *mut libc::c_void,
+                            bytes.len(),
+                        )
+                    },
+                };
+
+                legacy_result(rv)?
+            }
+            Endpoint::Pipe(pipe) => match pipe.borrow().peek(&mut bytes) {
+                Err(e) if e == Errno::EWOULDBLOCK.into() => {
+                    return Err(pipe_would_block(
+                        pipe,
+                        FileState::READABLE,
+                        options.nonblock,
+                    ));
+                }
+                x => x?,
+            },
+            Endpoint::Socket(socket) => {
+                let plugin_buf =
+                    plugin_buf.get_or_insert_with(|| AllocdMem::new(ctx.objs, CHUNK_LEN));
+                let iov = IoVec {
+                    base: plugin_buf.ptr().ptr(),
+                    len,
+                };
+
+                let mut mem = ctx.objs.process.memory_borrow_mut();
+
+                let args = RecvmsgArgs {
+                    iovs: &[iov],
+                    control_ptr: ForeignArrayPtr::new(ForeignPtr::null(), 0),
+                    flags: socket_flags(options.nonblock),
+                };
+
+                // call the socket's recvmsg(), and run any resulting events
+                let RecvmsgReturn { return_val, .. } =
+                    crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+                        CallbackQueue::queue_and_run(|cb_queue| {
+                            Socket::recvmsg(socket, args, &mut mem, cb_queue)
+                        })
+                    })?;
+
+                let num_read = usize::try_from(return_val).unwrap();
+                mem.copy_from_ptr(&mut bytes[..num_read], plugin_buf.ptr().slice(..num_read))?;
+                num_read
+            }
+        };
+
+        let bytes = &bytes[..num_read];
+
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+
+        let num_written = match dst {
+            Endpoint::RegularFile { file, offset } => {
+                let rv = match *offset {
+                    Some(offset) => unsafe {
+                        c::regularfile_pwrite(
+                            *file,
+                            bytes.as_ptr() as *const libc::c_void,
+                            bytes.len(),
+                            offset,
+                        )
+                    },
+                    None => unsafe {
+                        c::regularfile_write(
+                            *file,
+                            bytes.as_ptr() as *const libc::c_void,
+                            bytes.len(),
+                        )
+                    },
+                };
+
+                let num_written = legacy_result(rv)?;
+
+                if let Some(offset) = offset {
+                    *offset += libc::off_t::try_from(num_written).unwrap();
+                }
+
+                num_written
+            }
+            Endpoint::Pipe(pipe) => {
+                let result = crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+                    CallbackQueue::queue_and_run(|cb_queue| {
+                        pipe.borrow_mut().write_bytes(bytes, cb_queue)
+                    })
+                });
+
+                match result {
+                    Err(e) if e == Errno::EWOULDBLOCK.into() => {
+                        return Err(pipe_would_block(
+                            pipe,
+                            FileState::WRITABLE,
+                            options.nonblock,
+                        ));
+                    }
+                    x => x?,
+                }
+            }
+            Endpoint::Socket(socket) => {
+                let plugin_buf =
+                    plugin_buf.get_or_insert_with(|| AllocdMem::new(ctx.objs, CHUNK_LEN));
+                let iov = IoVec {
+                    base: plugin_buf.ptr().ptr(),
+                    len: bytes.len(),
+                };
+
+                let mut mem = ctx.objs.process.memory_borrow_mut();
+                let mut rng = ctx.objs.host.random_mut();
+                let net_ns = ctx.objs.host.network_namespace_borrow();
+
+                mem.copy_to_ptr(plugin_buf.ptr().slice(..bytes.len()), bytes)?;
+
+                let args = SendmsgArgs {
+                    addr: None,
+                    iovs: &[iov],
+                    control_ptr: ForeignArrayPtr::new(ForeignPtr::null(), 0),
+                    flags: socket_flags(options.nonblock),
+                };
+
+                // call the socket's sendmsg(), and run any resulting events; the socket will
+                // block if its send buffer is full
+                let num_written =
+                    crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+                        CallbackQueue::queue_and_run(|cb_queue| {
+                            Socket::sendmsg(socket, args, &mut mem, &net_ns, &mut *rng, cb_queue)
+                        })
+                    })?;
+
+                usize::try_from(num_written).unwrap()
+            }
+        };
+
+        // now that the data has been written, remove it from the source
+        match src {
+            Endpoint::RegularFile { file, offset } => match (offset, src_pos) {
+                (Some(offset), _) => *offset += libc::off_t::try_from(num_written).unwrap(),
+                (None, Some(pos)) => {
+                    let new_pos = pos + libc::off_t::try_from(num_written).unwrap();
+                    legacy_result(unsafe { c::regularfile_lseek(*file, new_pos, libc::SEEK_SET) })?;
+                }
+                // the data was already consumed when read
+                (None, None) => {}
+            },
+            Endpoint::Pipe(pipe) => {
+                if options.consume {
+                    crate::utility::legacy_callback_queue::with_global_cb_queue(|| {
+                        CallbackQueue::queue_and_run(|cb_queue| {
+                            pipe.borrow_mut().discard(num_written, cb_queue)
+                        })
+                    });
+                }
+            }
+            Endpoint::Socket(_) => {
+                // we only read as much as the pipe had space for
+                debug_assert_eq!(num_written, bytes.len());
+            }
+        }
+
+        Ok(num_written)
+    }
+}
+
+/// One end of a transfer between files.
+enum Endpoint {
+    /// A legacy regular file. If `offset` is set, it's used instead of the file's offset, and the
+    /// file's offset is not changed.
+    RegularFile {
+        file: *mut c::RegularFile,
+        offset: Option<libc::off_t>,
+    },
+    Pipe(Arc<AtomicRefCell<Pipe>>),
+    Socket(Socket),
+}
+
+impl Endpoint {
+    /// Returns `EBADF` if the file isn't open for reading.
+    fn check_readable(&self) -> Result<(), Errno> {
+        let readable = match self {
+            Self::RegularFile { file, .. } => {
+                let flags = unsafe { c::regularfile_getFlagsAtOpen(*file) };
+                flags & libc::O_ACCMODE != libc::O_WRONLY
+            }
+            Self::Pipe(pipe) => pipe.borrow().mode().contains(FileMode::READ),
+            Self::Socket(_) => true,
+        };
+
+        if !readable {
+            return Err(Errno::EBADF);
+        }
+
+        Ok(())
+    }
+
+    /// Returns `EBADF` if the file isn't open for writing.
+    fn check_writable(&self) -> Result<(), Errno> {
+        let writable = match self {
+            Self::RegularFile { file, .. } => {
+                let flags = unsafe { c::regularfile_getFlagsAtOpen(*file) };
+                flags & libc::O_ACCMODE != libc::O_RDONLY
+            }
+            Self::Pipe(pipe) => pipe.borrow().mode().contains(FileMode::WRITE),
+            Self::Socket(_) => true,
+        };
+
+        if !writable {
+            return Err(Errno::EBADF);
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the file is a regular file opened with `O_APPEND`.
+    fn is_append(&self) -> bool {
+        match self {
+            Self::RegularFile { file, .. } => {
+                let flags = unsafe { c::regularfile_getFlagsAtOpen(*file) };
+                flags & libc::O_APPEND != 0
+            }
+            Self::Pipe(_) | Self::Socket(_) => false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct TransferOptions {
+    /// Should data be removed from the source once it's written?
+    consume: bool,
+    /// Should pipe (and socket) operations return `EAGAIN` rather than block?
+    nonblock: bool,
+    /// Should we stop after transferring a single chunk of data?
+    single_chunk: bool,
+}
+
+/// The error to return when a pipe operation would block.
+fn pipe_would_block(
+    pipe: &Arc<AtomicRefCell<Pipe>>,
+    state: FileState,
+    nonblock: bool,
+) -> SyscallError {
+    let (status, restartable) = {
+        let pipe = pipe.borrow();
+        (pipe.get_status(), pipe.supports_sa_restart())
+    };
+
+    if nonblock || status.contains(FileStatus::NONBLOCK) {
+        return Errno::EWOULDBLOCK.into();
+    }
+
+    SyscallError::new_blocked(File::Pipe(Arc::clone(pipe)), state, restartable)
+}
+
+fn socket_flags(nonblock: bool) -> libc::c_int {
+    if nonblock {
+        libc::MSG_DONTWAIT
+    } else {
+        0
+    }
+}
+
+/// Get the current offset of a regular file, or `None` if the file isn't seekable.
+fn regular_file_position(file: *mut c::RegularFile) -> Option<libc::off_t> {
+    let rv = unsafe { c::regularfile_lseek(file, 0, libc::SEEK_CUR) };
+    (rv >= 0).then_some(rv)
+}
+
+/// Convert the return value of a legacy regular file function to a `Result`.
+fn legacy_result<T>(rv: T) -> Result<usize, Errno>
+where
+    T: TryInto<i64>,
+    T::Error: std::fmt::Debug,
+{
+    let rv = rv.try_into().unwrap();
+
+    if rv < 0 {
+        return Err(Errno::try_from(-rv).unwrap_or(Errno::EINVAL));
+    }
+
+    Ok(rv.try_into().unwrap())
+}
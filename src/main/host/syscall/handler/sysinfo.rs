@@ -13,9 +13,13 @@ impl SyscallHandler {
         ctx: &mut SyscallContext,
         info_ptr: ForeignPtr<linux_api::sysinfo::sysinfo>,
     ) -> SyscallResult {
-        // Seconds are needed for uptime.
-        let seconds = Worker::current_time()
-            .unwrap()
+        // Seconds are needed for uptime, which is measured by the host's monotonic clock.
+        let now = Worker::current_time().unwrap();
+        let seconds = ctx
+            .objs
+            .host
+            .clock()
+            .monotonic(now)
             .duration_since(&EmulatedTime::SIMULATION_START)
             .as_secs();
 
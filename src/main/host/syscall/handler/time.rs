@@ -7,11 +7,12 @@ use shadow_shim_helper_rs::syscall_types::ForeignPtr;
 use syscall_logger::log_syscall;
 
 use crate::core::worker::Worker;
+use crate::host::host::Host;
 use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
 use crate::host::syscall_types::{SyscallError, SyscallResult};
 use crate::host::timer::Timer;
 
-fn itimerval_from_timer(timer: &Timer) -> linux_api::time::itimerval {
+fn itimerval_from_timer(host: &Host, timer: &Timer) -> linux_api::time::itimerval {
     linux_api::time::itimerval {
         it_interval: timer
             .expire_interval()
@@ -19,13 +20,38 @@ fn itimerval_from_timer(timer: &Timer) -> linux_api::time::itimerval {
             .try_into()
             .unwrap(),
         it_value: timer
-            .remaining_time()
+            .remaining_time(host)
             .unwrap_or(SimulationTime::ZERO)
             .try_into()
             .unwrap(),
     }
 }
 
+/// The simulation time at which the host's clock `clock_id` reaches `time` (measured from the unix
+/// epoch), assuming that the clock isn't stepped after `now`.
+pub fn host_clock_to_true(
+    host: &Host,
+    clock_id: ClockId,
+    now: EmulatedTime,
+    time: SimulationTime,
+) -> EmulatedTime {
+    let clock = host.clock();
+    let time = EmulatedTime::UNIX_EPOCH + time;
+
+    match clock_id {
+        ClockId::CLOCK_REALTIME
+        | ClockId::CLOCK_REALTIME_ALARM
+        | ClockId::CLOCK_REALTIME_COARSE
+        | ClockId::CLOCK_TAI => clock.realtime_to_true(now, time),
+        ClockId::CLOCK_MONOTONIC
+        | ClockId::CLOCK_MONOTONIC_COARSE
+        | ClockId::CLOCK_MONOTONIC_RAW
+        | ClockId::CLOCK_BOOTTIME
+        | ClockId::CLOCK_BOOTTIME_ALARM => clock.monotonic_to_true(time),
+        _ => time,
+    }
+}
+
 impl SyscallHandler {
     #[log_syscall(/* rv */ std::ffi::c_int, /* which */ linux_api::time::ITimerId, /*curr_value*/ *const std::ffi::c_void)]
     pub fn getitimer(
@@ -43,7 +69,8 @@ impl SyscallHandler {
             return Err(Errno::EINVAL.into());
         }
 
-        let itimerval = itimerval_from_timer(&ctx.objs.process.realtime_timer_borrow());
+        let itimerval =
+            itimerval_from_timer(ctx.objs.host, &ctx.objs.process.realtime_timer_borrow());
         ctx.objs
             .process
             .memory_borrow_mut()
@@ -71,7 +98,8 @@ impl SyscallHandler {
         }
 
         if !old_value_ptr.is_null() {
-            let itimerval = itimerval_from_timer(&ctx.objs.process.realtime_timer_borrow());
+            let itimerval =
+                itimerval_from_timer(ctx.objs.host, &ctx.objs.process.realtime_timer_borrow());
             ctx.objs
                 .process
                 .memory_borrow_mut()
@@ -87,9 +115,12 @@ impl SyscallHandler {
         if new_value_value == SimulationTime::ZERO {
             ctx.objs.process.realtime_timer_borrow_mut().disarm();
         } else {
+            // The timer's value is measured by the host's monotonic clock.
+            let now = Worker::current_time().unwrap();
+            let clock = ctx.objs.host.clock();
             ctx.objs.process.realtime_timer_borrow_mut().arm(
                 ctx.objs.host,
-                Worker::current_time().unwrap() + new_value_value,
+                clock.monotonic_after(now, new_value_value),
                 new_value_interval
                     .is_positive()
                     .then_some(new_value_interval),
@@ -149,7 +180,14 @@ impl SyscallHandler {
             // Simulated in Shadow; Linux allows unspec bitflags, but not for the *ALARM clocks.
             let allow_unspec_bitflags =
                 ![ClockId::CLOCK_REALTIME_ALARM, ClockId::CLOCK_BOOTTIME_ALARM].contains(&clock_id);
-            Self::nanosleep_helper(ctx, flags, request_ptr, remain_ptr, allow_unspec_bitflags)
+            Self::nanosleep_helper(
+                ctx,
+                clock_id,
+                flags,
+                request_ptr,
+                remain_ptr,
+                allow_unspec_bitflags,
+            )
         } else if [ClockId::CLOCK_THREAD_CPUTIME_ID].contains(&clock_id) {
             // Invalid in Linux.
             log::debug!("Invalid clock id {clock_id:?}.",);
@@ -181,11 +219,12 @@ impl SyscallHandler {
         req: ForeignPtr<linux_api::time::timespec>,
         rem: ForeignPtr<linux_api::time::timespec>,
     ) -> Result<std::ffi::c_int, SyscallError> {
-        Self::nanosleep_helper(ctx, 0, req, rem, false)
+        Self::nanosleep_helper(ctx, ClockId::CLOCK_MONOTONIC, 0, req, rem, false)
     }
 
     fn nanosleep_helper(
         ctx: &mut SyscallContext,
+        clock_id: ClockId,
         flags: std::ffi::c_int,
         request_ptr: ForeignPtr<linux_api::time::timespec>,
         remain_ptr: ForeignPtr<linux_api::time::timespec>,
@@ -201,11 +240,13 @@ impl SyscallHandler {
 
         let now = Worker::current_time().unwrap();
 
-        // The requested wakeup time may be absolute or relative.
+        // The requested wakeup time may be absolute or relative. An absolute time is measured by
+        // the host's clock, which may disagree with the simulation time. Like Linux, a relative
+        // time is measured by the host's monotonic clock for any clock id.
         let abs_wakeup_time = if flags.contains(ClockNanosleepFlags::TIMER_ABSTIME) {
-            EmulatedTime::UNIX_EPOCH + request_time
+            host_clock_to_true(ctx.objs.host, clock_id, now, request_time)
         } else {
-            now + request_time
+            ctx.objs.host.clock().monotonic_after(now, request_time)
         };
 
         // A wakeup time in the past means we return without sleeping.
@@ -228,8 +269,12 @@ impl SyscallHandler {
         } else {
             // Possibly write out the remaining time until the expected wakeup.
             if !remain_ptr.is_null() && !flags.contains(ClockNanosleepFlags::TIMER_ABSTIME) {
-                let remain_time =
-                    linux_api::time::timespec::try_from(expected_wakeup_time - now).unwrap();
+                let remain_time = ctx
+                    .objs
+                    .host
+                    .clock()
+                    .monotonic_elapsed(now, expected_wakeup_time);
+                let remain_time = linux_api::time::timespec::try_from(remain_time).unwrap();
                 ctx.objs
                     .process
                     .memory_borrow_mut()
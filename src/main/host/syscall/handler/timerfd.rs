@@ -15,7 +15,7 @@ use crate::host::descriptor::{
     timerfd::TimerFd, CompatFile, Descriptor, File, FileStatus, OpenFile,
 };
 use crate::host::{
-    syscall::handler::{SyscallContext, SyscallHandler},
+    syscall::handler::{time::host_clock_to_true, SyscallContext, SyscallHandler},
     syscall_types::SyscallError,
 };
 use crate::utility::callback_queue::CallbackQueue;
@@ -51,7 +51,7 @@ impl SyscallHandler {
             desc_flags.insert(DescriptorFlags::FD_CLOEXEC);
         }
 
-        let file = TimerFd::new(file_flags);
+        let file = TimerFd::new(clockid, file_flags);
         let mut desc = Descriptor::new(CompatFile::New(OpenFile::new(File::TimerFd(file))));
         desc.set_flags(desc_flags);
 
@@ -95,7 +95,7 @@ impl SyscallHandler {
 
             // We return a zero duration if the timer is disarmed.
             let remaining = borrowed
-                .get_timer_remaining()
+                .get_timer_remaining(ctx.objs.host)
                 .unwrap_or(SimulationTime::ZERO);
 
             // We return a zero duration if the timer is not configured with an interval, which
@@ -167,13 +167,19 @@ impl SyscallHandler {
             let now = Worker::current_time().unwrap();
 
             let expire_time = {
-                let base = match flags.contains(TimerSetTimeFlags::TFD_TIMER_ABSTIME) {
-                    true => EmulatedTime::UNIX_EPOCH,
-                    false => now,
+                // An absolute time is measured by the host's clock, which may disagree with the
+                // simulation time.
+                let expire_time = match flags.contains(TimerSetTimeFlags::TFD_TIMER_ABSTIME) {
+                    true => {
+                        let clock_id = timerfd.borrow().clock_id();
+                        host_clock_to_true(ctx.objs.host, clock_id, now, value)
+                    }
+                    // A relative time is measured by the host's monotonic clock.
+                    false => ctx.objs.host.clock().monotonic_after(now, value),
                 };
                 // The man page does not specify what happens if the configured time is in the past.
                 // On Linux, the result is an immediate timer expiration.
-                EmulatedTime::max(base + value, now)
+                EmulatedTime::max(expire_time, now)
             };
 
             CallbackQueue::queue_and_run(|cb_queue| {
@@ -1,3 +1,4 @@
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::sync::Arc;
 
 use atomic_refcell::AtomicRefCell;
@@ -12,12 +13,21 @@ use crate::cshadow as c;
 use crate::host::descriptor::pipe;
 use crate::host::descriptor::shared_buf::SharedBuf;
 use crate::host::descriptor::{CompatFile, Descriptor, File, FileMode, FileStatus, OpenFile};
+use crate::host::memory_manager::MemoryManager;
 use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
 use crate::host::syscall::io::IoVec;
-use crate::host::syscall::type_formatting::SyscallBufferArg;
-use crate::host::syscall_types::{SyscallError, SyscallResult};
+use crate::host::syscall::type_formatting::{SyscallBufferArg, SyscallStringArg};
+use crate::host::syscall_types::{ForeignArrayPtr, SyscallError, SyscallResult};
 use crate::utility::callback_queue::CallbackQueue;
 
+/// Maximum length of a single `execve` argument or environment string, including the nul
+/// terminator (linux's `MAX_ARG_STRLEN`).
+const EXECVE_MAX_ARG_STRLEN: usize = 32 * 4096;
+
+/// Maximum total size of the `execve` argument and environment strings, including their pointers.
+/// Linux derives this from the stack rlimit; we use the limit for the default 8 MiB stack.
+const EXECVE_MAX_ARG_TOTAL_LEN: usize = 2 * 1024 * 1024;
+
 impl SyscallHandler {
     #[log_syscall(/* rv */ std::ffi::c_int, /* fd */ std::ffi::c_int)]
     pub fn close(ctx: &mut SyscallContext, fd: std::ffi::c_int) -> SyscallResult {
@@ -478,4 +488,117 @@ impl SyscallHandler {
             }
         }
     }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* pathname */ SyscallStringArg,
+                  /* argv */ *const std::ffi::c_void, /* envp */ *const std::ffi::c_void)]
+    pub fn execve(
+        ctx: &mut SyscallContext,
+        pathname_ptr: ForeignPtr<u8>,
+        argv_ptr: ForeignPtr<ForeignPtr<u8>>,
+        envp_ptr: ForeignPtr<ForeignPtr<u8>>,
+    ) -> SyscallResult {
+        let (path, argv, envv) = {
+            let mem = ctx.objs.process.memory_borrow();
+            let mut buf = vec![0u8; EXECVE_MAX_ARG_STRLEN];
+
+            let path_ptr = ForeignArrayPtr::new(pathname_ptr, libc::PATH_MAX as usize);
+            let path = mem.copy_str_from_ptr(&mut buf, path_ptr)?.to_owned();
+            let argv = Self::execve_read_strings(&mem, &mut buf, argv_ptr)?;
+            let envv = Self::execve_read_strings(&mem, &mut buf, envp_ptr)?;
+
+            (path, argv, envv)
+        };
+
+        if path.to_bytes().is_empty() {
+            return Err(Errno::ENOENT.into());
+        }
+
+        // relative paths are resolved against the process' working directory
+        let path = {
+            let path = std::path::Path::new(std::ffi::OsStr::from_bytes(path.to_bytes()));
+            let path = if path.is_relative() {
+                let working_dir = ctx.objs.process.working_dir();
+                let working_dir = std::ffi::OsStr::from_bytes(working_dir.to_bytes());
+                std::path::Path::new(working_dir).join(path)
+            } else {
+                path.to_path_buf()
+            };
+            std::ffi::CString::new(path.into_os_string().into_vec()).unwrap()
+        };
+
+        let metadata = std::fs::metadata(std::ffi::OsStr::from_bytes(path.to_bytes()))?;
+        if !metadata.is_file() {
+            return Err(Errno::EACCES.into());
+        }
+        nix::unistd::access(path.as_c_str(), nix::unistd::AccessFlags::X_OK)
+            .map_err(|e| Errno::try_from(e as i32).unwrap())?;
+
+        ctx.objs.process.borrow_runnable().unwrap().exec(
+            ctx.objs.host,
+            ctx.objs.thread.id(),
+            &path,
+            argv,
+            envv,
+        );
+
+        // The calling thread no longer exists, so this return value is never seen by the plugin.
+        Ok(0.into())
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* status */ std::ffi::c_int)]
+    pub fn exit_group(ctx: &mut SyscallContext, status: std::ffi::c_int) -> SyscallResult {
+        trace!("Exit group with exit code {status}");
+
+        // The parent of a vfork'd process continues once its child exits. The
+        // process's memory is still in place until the native syscall runs.
+        ctx.objs
+            .process
+            .borrow_runnable()
+            .unwrap()
+            .release_vfork_parent(ctx.objs.host);
+
+        Err(SyscallError::Native)
+    }
+
+    /// Read a null-terminated array of string pointers (such as `argv` or `envp`) from plugin
+    /// memory.
+    fn execve_read_strings(
+        mem: &MemoryManager,
+        buf: &mut [u8],
+        array_ptr: ForeignPtr<ForeignPtr<u8>>,
+    ) -> Result<Vec<std::ffi::CString>, Errno> {
+        let mut strings = Vec::new();
+
+        // linux allows a null array pointer and treats it as an empty array
+        if array_ptr.is_null() {
+            return Ok(strings);
+        }
+
+        let array_ptr = array_ptr.cast::<usize>();
+        let mut total_len = 0;
+
+        for i in 0.. {
+            let str_ptr: usize = mem.read(array_ptr.add(i))?;
+            if str_ptr == 0 {
+                break;
+            }
+
+            let str_ptr = ForeignPtr::<()>::from(str_ptr).cast::<u8>();
+            let s = mem
+                .copy_str_from_ptr(buf, ForeignArrayPtr::new(str_ptr, buf.len()))
+                .map_err(|e| match e {
+                    Errno::ENAMETOOLONG => Errno::E2BIG,
+                    e => e,
+                })?;
+
+            total_len += s.to_bytes_with_nul().len() + std::mem::size_of::<usize>();
+            if total_len > EXECVE_MAX_ARG_TOTAL_LEN {
+                return Err(Errno::E2BIG);
+            }
+
+            strings.push(s.to_owned());
+        }
+
+        Ok(strings)
+    }
 }
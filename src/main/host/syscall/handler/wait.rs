@@ -0,0 +1,275 @@
+use linux_api::errno::Errno;
+use linux_api::posix_types::kernel_pid_t;
+use linux_api::signal::{siginfo_t, Signal};
+use log::*;
+use nix::sys::wait::WaitPidFlag;
+use shadow_shim_helper_rs::syscall_types::ForeignPtr;
+use syscall_logger::log_syscall;
+
+use crate::host::descriptor::FileState;
+use crate::host::process::{Process, ProcessId};
+use crate::host::syscall::handler::{SyscallContext, SyscallHandler};
+use crate::host::syscall_types::SyscallError;
+
+/// The children that a `wait4` or `waitid` call applies to.
+#[derive(Copy, Clone, Debug)]
+enum WaitTarget {
+    Any,
+    Pid(ProcessId),
+    ProcessGroup(ProcessId),
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &Process) -> bool {
+        match self {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(pid) => child.id() == *pid,
+            WaitTarget::ProcessGroup(pgid) => child.process_group_id() == *pgid,
+        }
+    }
+}
+
+/// A child that has been found in a waitable state.
+struct WaitedChild {
+    id: ProcessId,
+    wait_status: libc::c_int,
+    siginfo: siginfo_t,
+}
+
+impl SyscallHandler {
+    #[log_syscall(/* rv */ kernel_pid_t, /* pid */ kernel_pid_t, /* status */ *const std::ffi::c_int,
+                  /* options */ std::ffi::c_int, /* rusage */ *const std::ffi::c_void)]
+    pub fn wait4(
+        ctx: &mut SyscallContext,
+        pid: kernel_pid_t,
+        status_ptr: ForeignPtr<libc::c_int>,
+        options: std::ffi::c_int,
+        rusage_ptr: ForeignPtr<libc::rusage>,
+    ) -> Result<kernel_pid_t, SyscallError> {
+        let Some(options) = WaitPidFlag::from_bits(options) else {
+            debug!("Invalid options: {options:#x}");
+            return Err(Errno::EINVAL.into());
+        };
+        let allowed = WaitPidFlag::WNOHANG
+            | WaitPidFlag::WUNTRACED
+            | WaitPidFlag::WCONTINUED
+            | WaitPidFlag::__WNOTHREAD
+            | WaitPidFlag::__WCLONE
+            | WaitPidFlag::__WALL;
+        if !allowed.contains(options) {
+            debug!("Invalid options for wait4: {options:?}");
+            return Err(Errno::EINVAL.into());
+        }
+
+        // From wait(2):
+        // > < -1   meaning wait for any child process whose process group ID is
+        // >        equal to the absolute value of pid.
+        // > -1     meaning wait for any child process.
+        // > 0      meaning wait for any child process whose process group ID is
+        // >        equal to that of the calling process at the time of the call
+        // >        to waitpid().
+        // > > 0    meaning wait for the child whose process ID is equal to the
+        // >        value of pid.
+        let target = match pid {
+            -1 => WaitTarget::Any,
+            0 => WaitTarget::ProcessGroup(ctx.objs.process.process_group_id()),
+            pid if pid > 0 => WaitTarget::Pid(ProcessId::try_from(pid).unwrap()),
+            pid => {
+                // `-pid` overflows for `i32::MIN`, which Linux rejects with ESRCH.
+                let Some(pgid) = pid.checked_neg() else {
+                    return Err(Errno::ESRCH.into());
+                };
+                WaitTarget::ProcessGroup(ProcessId::try_from(pgid).unwrap())
+            }
+        };
+
+        // `wait4` always waits for children that have exited.
+        let Some(child) = Self::wait_internal(ctx, target, options | WaitPidFlag::WEXITED)? else {
+            // WNOHANG was specified, and no child has exited yet.
+            return Ok(0);
+        };
+
+        let mut mem = ctx.objs.process.memory_borrow_mut();
+        if !status_ptr.is_null() {
+            mem.write(status_ptr, &child.wait_status)?;
+        }
+        if !rusage_ptr.is_null() {
+            // We don't track resource usage of managed processes.
+            mem.write(rusage_ptr, &shadow_pod::zeroed::<libc::rusage>())?;
+        }
+
+        Ok(child.id.into())
+    }
+
+    #[log_syscall(/* rv */ std::ffi::c_int, /* idtype */ std::ffi::c_int, /* id */ kernel_pid_t,
+                  /* infop */ *const std::ffi::c_void, /* options */ std::ffi::c_int,
+                  /* rusage */ *const std::ffi::c_void)]
+    pub fn waitid(
+        ctx: &mut SyscallContext,
+        idtype: std::ffi::c_int,
+        id: kernel_pid_t,
+        infop: ForeignPtr<siginfo_t>,
+        options: std::ffi::c_int,
+        rusage_ptr: ForeignPtr<libc::rusage>,
+    ) -> Result<std::ffi::c_int, SyscallError> {
+        let Some(options) = WaitPidFlag::from_bits(options) else {
+            debug!("Invalid options: {options:#x}");
+            return Err(Errno::EINVAL.into());
+        };
+        let allowed = WaitPidFlag::WNOHANG
+            | WaitPidFlag::WNOWAIT
+            | WaitPidFlag::WEXITED
+            | WaitPidFlag::WSTOPPED
+            | WaitPidFlag::WCONTINUED
+            | WaitPidFlag::__WNOTHREAD
+            | WaitPidFlag::__WALL
+            | WaitPidFlag::__WCLONE;
+        if !allowed.contains(options) {
+            debug!("Invalid options for waitid: {options:?}");
+            return Err(Errno::EINVAL.into());
+        }
+        if !options
+            .intersects(WaitPidFlag::WEXITED | WaitPidFlag::WSTOPPED | WaitPidFlag::WCONTINUED)
+        {
+            debug!("waitid requires one of WEXITED, WSTOPPED, or WCONTINUED");
+            return Err(Errno::EINVAL.into());
+        }
+
+        let target = match libc::idtype_t::try_from(idtype) {
+            Ok(libc::P_ALL) => WaitTarget::Any,
+            Ok(libc::P_PID) => {
+                if id <= 0 {
+                    return Err(Errno::EINVAL.into());
+                }
+                WaitTarget::Pid(ProcessId::try_from(id).unwrap())
+            }
+            Ok(libc::P_PGID) => {
+                if id < 0 {
+                    return Err(Errno::EINVAL.into());
+                }
+                // From waitid(2):
+                // > Since Linux 5.4, if id is zero, then wait for any child that
+                // > is in the same process group as the caller's process group at
+                // > the time of the call.
+                if id == 0 {
+                    WaitTarget::ProcessGroup(ctx.objs.process.process_group_id())
+                } else {
+                    WaitTarget::ProcessGroup(ProcessId::try_from(id).unwrap())
+                }
+            }
+            Ok(libc::P_PIDFD) => {
+                warn!("waitid with P_PIDFD is unsupported");
+                return Err(Errno::ENOTSUP.into());
+            }
+            _ => {
+                debug!("Invalid idtype: {idtype}");
+                return Err(Errno::EINVAL.into());
+            }
+        };
+
+        let child = Self::wait_internal(ctx, target, options)?;
+
+        let mut mem = ctx.objs.process.memory_borrow_mut();
+        if !infop.is_null() {
+            // From waitid(2):
+            // > If WNOHANG was specified in options and there were no children
+            // > in a waitable state, then waitid() returns 0 immediately and the
+            // > state of the siginfo_t structure pointed to by infop depends on
+            // > the implementation. To (portably) distinguish this case from
+            // > that where a child was in a waitable state, zero out the si_pid
+            // > field before the call
+            //
+            // Linux zeroes the structure in that case.
+            let siginfo = match &child {
+                Some(child) => child.siginfo,
+                None => shadow_pod::zeroed::<siginfo_t>(),
+            };
+            mem.write(infop, &siginfo)?;
+        }
+        if child.is_some() && !rusage_ptr.is_null() {
+            // We don't track resource usage of managed processes.
+            mem.write(rusage_ptr, &shadow_pod::zeroed::<libc::rusage>())?;
+        }
+
+        Ok(0)
+    }
+
+    /// Looks for a child of the calling process matching `target` and
+    /// `options` that has exited, and unless `WNOWAIT` is specified, reaps it.
+    ///
+    /// Returns `None` if `WNOHANG` is specified and no matching child has exited
+    /// yet. Otherwise blocks until one does.
+    fn wait_internal(
+        ctx: &mut SyscallContext,
+        target: WaitTarget,
+        options: WaitPidFlag,
+    ) -> Result<Option<WaitedChild>, SyscallError> {
+        let host = ctx.objs.host;
+
+        let mut have_matching_child = false;
+        let mut waited_child = None;
+        for child_id in host.child_process_ids(ctx.objs.process.id()) {
+            let childrc = host.process_borrow(child_id).unwrap();
+            let child = childrc.borrow(host.root());
+
+            if !target.matches(&child) {
+                continue;
+            }
+
+            // From wait(2):
+            // > A "clone" child is one which delivers no signal, or a signal
+            // > other than SIGCHLD to its parent upon termination.
+            let is_clone_child = child.exit_signal() != Some(Signal::SIGCHLD);
+            if !options.contains(WaitPidFlag::__WALL)
+                && is_clone_child != options.contains(WaitPidFlag::__WCLONE)
+            {
+                continue;
+            }
+            have_matching_child = true;
+
+            // We don't emulate stopping and continuing processes, so exited
+            // children are the only ones that can be in a waitable state.
+            if !options.contains(WaitPidFlag::WEXITED) {
+                continue;
+            }
+            if let Some(zombie) = child.borrow_zombie() {
+                waited_child = Some(WaitedChild {
+                    id: child_id,
+                    wait_status: zombie.wait_status(),
+                    siginfo: zombie.exit_siginfo(),
+                });
+                break;
+            }
+        }
+
+        if let Some(waited_child) = waited_child {
+            if !options.contains(WaitPidFlag::WNOWAIT) {
+                trace!("Reaping child process {:?}", waited_child.id);
+                host.reap_process(waited_child.id);
+            }
+            return Ok(Some(waited_child));
+        }
+
+        if !have_matching_child {
+            return Err(Errno::ECHILD.into());
+        }
+
+        if options.contains(WaitPidFlag::WNOHANG) {
+            return Ok(None);
+        }
+
+        // Block until a child exits, and then check again.
+        let child_events = ctx
+            .objs
+            .process
+            .borrow_runnable()
+            .unwrap()
+            .child_events()
+            .clone();
+        Err(SyscallError::new_blocked_on_child(
+            child_events,
+            FileState::CHILD_EXITED,
+            /* restartable= */ true,
+        ))
+    }
+}
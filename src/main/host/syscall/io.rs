@@ -400,6 +400,22 @@ pub fn update_msghdr(
     Ok(())
 }
 
+/// Used to update the `msg_len` field of a `libc::mmsghdr`, which `sendmmsg()` and `recvmmsg()` set
+/// to the number of bytes sent or received for that message.
+pub fn update_mmsghdr_len(
+    mem: &mut MemoryManager,
+    mmsg_ptr: ForeignPtr<libc::mmsghdr>,
+    len: std::ffi::c_uint,
+) -> Result<(), Errno> {
+    let mmsg_ptr = ForeignArrayPtr::new(mmsg_ptr, 1);
+    let mut mem_ref = mem.memory_ref_mut(mmsg_ptr)?;
+    mem_ref.deref_mut()[0].msg_len = len;
+
+    mem_ref.flush()?;
+
+    Ok(())
+}
+
 /// Helper to read a plugin's [`libc::msghdr`] into a [`MsgHdr`]. While `msg` is a local struct, it
 /// should have been copied from plugin memory, meaning any pointers in the struct are pointers to
 /// plugin memory, not local memory.
@@ -1,5 +1,6 @@
 use crate::cshadow as c;
 use crate::host::descriptor::{File, FileState};
+use crate::host::process::ChildEventSource;
 
 pub mod formatter;
 pub mod handler;
@@ -32,4 +33,16 @@ impl Trigger {
             status: status.into(),
         })
     }
+
+    pub fn from_child_event_source(source: ChildEventSource, status: FileState) -> Self {
+        let source_ptr = Box::into_raw(Box::new(source));
+
+        Self(c::Trigger {
+            type_: c::_TriggerType_TRIGGER_CHILD,
+            object: c::TriggerObject {
+                as_child_event_source: source_ptr,
+            },
+            status: status.into(),
+        })
+    }
 }
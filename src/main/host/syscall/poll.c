@@ -58,6 +58,10 @@ static void _syscallhandler_getPollEventsHelper(const Descriptor* cdesc, struct
             (dstat & STATUS_FILE_WRITABLE)) {
             pfd->revents |= POLLOUT;
         }
+        /* POLLERR is always reported, even if it wasn't requested */
+        if ((dstat & STATUS_FILE_ACTIVE) && (dstat & STATUS_SOCKET_ERROR)) {
+            pfd->revents |= POLLERR;
+        }
     }
 }
 
@@ -103,13 +103,14 @@ static SyscallReturn _syscallhandler_select_helper(SysCallHandler* sys, int nfds
         }
 
         // The exceptional states listed in `man select` don't apply in Shadow,
-        // but POLLNVAL corresponds to an EBADF error.
-        if (pfd->revents & POLLIN) {
+        // but POLLNVAL corresponds to an EBADF error. Like Linux, a socket
+        // error makes the fd both readable and writable.
+        if ((pfd->revents & POLLIN) || ((pfd->events & POLLIN) && (pfd->revents & POLLERR))) {
             trace("select found fd %i readable", i);
             FD_SET(i, &readfds);
             num_set_bits++;
         }
-        if (pfd->revents & POLLOUT) {
+        if ((pfd->revents & POLLOUT) || ((pfd->events & POLLOUT) && (pfd->revents & POLLERR))) {
             trace("select found fd %i writeable", i);
             FD_SET(i, &writefds);
             num_set_bits++;
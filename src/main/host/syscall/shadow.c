@@ -9,6 +9,7 @@
 #include <netdb.h>
 #include <stdarg.h>
 #include <stdlib.h>
+#include <string.h>
 #include <strings.h>
 
 #include "lib/logger/logger.h"
@@ -21,6 +22,19 @@
 static bool _useMM = true;
 ADD_CONFIG_HANDLER(config_getUseMemoryManager, _useMM)
 
+// Returns the address of the host with the given name, or NULL if there is no such host.
+static const Address* _syscallhandler_resolveHostname(SysCallHandler* sys, const char* name,
+                                                      size_t name_len) {
+    if (strncasecmp(name, host_getName(_syscallhandler_getHost(sys)), MIN(name_len, NI_MAXHOST)) ==
+        0) {
+        trace("Using default address for my own hostname %s", name);
+        return host_getDefaultAddress(_syscallhandler_getHost(sys));
+    } else {
+        trace("Looking up name %s", name);
+        return worker_resolveNameToAddress(name);
+    }
+}
+
 SyscallReturn syscallhandler_shadow_hostname_to_addr_ipv4(SysCallHandler* sys,
                                                           const SysCallArgs* args) {
     utility_debugAssert(sys && args);
@@ -52,16 +66,7 @@ SyscallReturn syscallhandler_shadow_hostname_to_addr_ipv4(SysCallHandler* sys,
         return syscallreturn_makeDoneI64(0);
     }
 
-    const Address* address;
-
-    if (strncasecmp(name, host_getName(_syscallhandler_getHost(sys)), MIN(name_len, NI_MAXHOST)) ==
-        0) {
-        trace("Using default address for my own hostname %s", name);
-        address = host_getDefaultAddress(_syscallhandler_getHost(sys));
-    } else {
-        trace("Looking up name %s", name);
-        address = worker_resolveNameToAddress(name);
-    }
+    const Address* address = _syscallhandler_resolveHostname(sys, name, name_len);
 
     if (address) {
         trace("Found address %s for name %s", address_toString(address), name);
@@ -89,6 +94,63 @@ SyscallReturn syscallhandler_shadow_hostname_to_addr_ipv4(SysCallHandler* sys,
     }
 }
 
+SyscallReturn syscallhandler_shadow_hostname_to_addr_ipv6(SysCallHandler* sys,
+                                                          const SysCallArgs* args) {
+    utility_debugAssert(sys && args);
+    UntypedForeignPtr name_ptr = args->args[0].as_ptr;
+    size_t name_len = args->args[1].as_u64;
+    UntypedForeignPtr addr_ptr = args->args[2].as_ptr;
+    size_t addr_len = args->args[3].as_u64;
+
+    trace("Handling custom syscall shadow_hostname_to_addr_ipv6");
+
+    if (!name_ptr.val || !addr_ptr.val || addr_len < sizeof(struct in6_addr)) {
+        trace("Invalid argument detected, returning EINVAL");
+        return syscallreturn_makeDoneErrno(EINVAL);
+    }
+
+    const char* name;
+    int rv = process_getReadableString(
+        _syscallhandler_getProcess(sys), name_ptr, name_len + 1 /* NULL byte */, &name, &name_len);
+    if (rv != 0) {
+        return syscallreturn_makeDoneErrno(-rv);
+    }
+
+    struct in6_addr ip;
+
+    if (strcasecmp(name, "localhost") == 0) {
+        trace("Returning loopback address for localhost");
+        ip = in6addr_loopback;
+    } else {
+        const Address* address = _syscallhandler_resolveHostname(sys, name, name_len);
+        const uint8_t* ipv6 = address ? address_toNetworkIPv6(address) : NULL;
+
+        if (!ipv6) {
+            trace("Unable to find IPv6 address for name %s", name);
+            // return EFAULT like the IPv4 version
+            return syscallreturn_makeDoneErrno(EFAULT);
+        }
+
+        trace("Found address %s for name %s", address_toIPv6String(address), name);
+        memcpy(&ip, ipv6, sizeof(ip));
+    }
+
+    // Release the readable pointer so that we can get a writable pointer.
+    int res = process_flushPtrs(_syscallhandler_getProcess(sys));
+    if (res != 0) {
+        return syscallreturn_makeDoneErrno(res);
+    }
+
+    struct in6_addr* addr =
+        process_getWriteablePtr(_syscallhandler_getProcess(sys), addr_ptr, addr_len);
+    if (addr == NULL) {
+        return syscallreturn_makeDoneErrno(EFAULT);
+    }
+    *addr = ip;
+
+    return syscallreturn_makeDoneI64(0);
+}
+
 SyscallReturn syscallhandler_shadow_init_memory_manager(SysCallHandler* sys,
                                                         const SysCallArgs* args) {
     utility_debugAssert(sys && args);
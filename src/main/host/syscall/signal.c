@@ -95,6 +95,7 @@ static SyscallReturn _syscallhandler_signalThread(SysCallHandler* sys, const Thr
     linux_siginfo_t info = linux_siginfo_new_for_tkill(sig, sys->processId, 0);
     shimshmem_setThreadSiginfo(
         host_getShimShmemLock(_syscallhandler_getHost(sys)), thread_sharedMem(thread), sig, &info);
+    process_notifySignalsChanged(process);
 
     if (thread_getID(thread) == sys->threadId) {
         // Target is the current thread. It'll be handled synchronously when the
@@ -180,12 +180,15 @@ safe_pointer_impl!(std::ffi::c_void);
 safe_pointer_impl!(libc::sockaddr);
 safe_pointer_impl!(linux_api::sysinfo::sysinfo);
 safe_pointer_impl!(libc::iovec);
+safe_pointer_impl!(libc::mmsghdr);
 
 // nix still uses an old bitflags version which isn't supported by `bitflags_impl`
 simple_debug_impl!(linux_api::time::ITimerId);
 simple_debug_impl!(linux_api::time::ClockId);
 simple_debug_impl!(nix::sys::stat::Mode);
 simple_debug_impl!(nix::sys::eventfd::EfdFlags);
+simple_debug_impl!(nix::sys::inotify::InitFlags);
+simple_debug_impl!(nix::sys::signalfd::SfdFlags);
 simple_debug_impl!(nix::sys::socket::AddressFamily);
 simple_debug_impl!(nix::sys::socket::MsgFlags);
 
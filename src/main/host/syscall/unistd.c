@@ -223,11 +223,6 @@ SyscallReturn syscallhandler_pwrite64(SysCallHandler* sys, const SysCallArgs* ar
                                        args->args[2].as_u64, args->args[3].as_i64, true);
 }
 
-SyscallReturn syscallhandler_exit_group(SysCallHandler* sys, const SysCallArgs* args) {
-    trace("Exit group with exit code %ld", args->args[0].as_i64);
-    return syscallreturn_makeNative();
-}
-
 SyscallReturn syscallhandler_getpid(SysCallHandler* sys, const SysCallArgs* args) {
     // We can't handle this natively in the plugin if we want determinism
     pid_t pid = sys->processId;
@@ -236,8 +231,12 @@ SyscallReturn syscallhandler_getpid(SysCallHandler* sys, const SysCallArgs* args
 
 SyscallReturn syscallhandler_getppid(SysCallHandler* sys, const SysCallArgs* args) {
     // We can't handle this natively in the plugin if we want determinism
-    // Just return a constant
-    return syscallreturn_makeDoneI64(1);
+    pid_t ppid = process_getParentProcessID(_syscallhandler_getProcess(sys));
+    if (ppid == 0) {
+        // Processes started by Shadow, and orphans, are children of "init".
+        ppid = 1;
+    }
+    return syscallreturn_makeDoneI64(ppid);
 }
 
 SyscallReturn syscallhandler_set_tid_address(SysCallHandler* sys, const SysCallArgs* args) {
@@ -28,6 +28,12 @@ struct _SysCallCondition {
     Timer* timeout;
     // The active file in the blocked syscall. This is state used when resuming a blocked syscall.
     OpenFile* activeFile;
+    // How much of its work the blocked syscall has already completed, such as the number of
+    // messages received. This is state used when resuming a blocked syscall.
+    size_t progress;
+    // Whether signals are left pending until the condition is satisfied, instead of interrupting
+    // the blocked syscall.
+    bool uninterruptible;
     // Non-null if we are listening for status updates on a trigger object
     StatusListener* triggerListener;
     // The host
@@ -74,6 +80,11 @@ SysCallCondition* syscallcondition_new(Trigger trigger) {
                 futex_ref(cond->trigger.object.as_futex);
                 return cond;
             }
+            case TRIGGER_CHILD: {
+                /* As for files, the event source is an owned reference that
+                 * we drop later. */
+                return cond;
+            }
             case TRIGGER_NONE: {
                 return cond;
             }
@@ -134,6 +145,11 @@ static void _syscallcondition_cleanupListeners(SysCallCondition* cond) {
                 futex_removeListener(cond->trigger.object.as_futex, cond->triggerListener);
                 break;
             }
+            case TRIGGER_CHILD: {
+                childeventsource_removeListener(
+                    cond->trigger.object.as_child_event_source, cond->triggerListener);
+                break;
+            }
             case TRIGGER_NONE: {
                 break;
             }
@@ -182,6 +198,10 @@ static void _syscallcondition_free(SysCallCondition* cond) {
                 futex_unref(cond->trigger.object.as_futex);
                 break;
             }
+            case TRIGGER_CHILD: {
+                childeventsource_drop(cond->trigger.object.as_child_event_source);
+                break;
+            }
             case TRIGGER_NONE: {
                 break;
             }
@@ -243,6 +263,12 @@ static void _syscallcondition_logListeningState(SysCallCondition* cond, const Pr
                                        cond->timeoutExpiration != EMUTIME_INVALID ? " and " : "");
                 break;
             }
+            case TRIGGER_CHILD: {
+                g_string_append_printf(string, "status on children %p%s",
+                                       (void*)cond->trigger.object.as_child_event_source,
+                                       cond->timeoutExpiration != EMUTIME_INVALID ? " and " : "");
+                break;
+            }
             case TRIGGER_NONE: {
                 break;
             }
@@ -287,6 +313,12 @@ static bool _syscallcondition_statusIsValid(SysCallCondition* cond) {
             // Futex status doesn't change
             return true;
         }
+        case TRIGGER_CHILD: {
+            // Only notified when a child exits or releases its vfork parent.
+            // The syscall handler checks whether there's actually a child to
+            // reap, or a parent to release, when it runs again.
+            return true;
+        }
         case TRIGGER_NONE: {
             break;
         }
@@ -309,6 +341,9 @@ static bool _syscallcondition_satisfied(SysCallCondition* cond, const Host* host
         // Primary condition is satisfied.
         return true;
     }
+    if (cond->uninterruptible) {
+        return false;
+    }
     bool signalPending = thread_unblockedSignalPending(thread, host_getShimShmemLock(host));
     if (signalPending) {
         return true;
@@ -482,6 +517,16 @@ void syscallcondition_waitNonblock(SysCallCondition* cond, const Host* host, con
                 futex_addListener(cond->trigger.object.as_futex, cond->triggerListener);
                 break;
             }
+            case TRIGGER_CHILD: {
+                /* Monitor the requested status an every status change. */
+                statuslistener_setMonitorStatus(
+                    cond->triggerListener, cond->trigger.status, SLF_ALWAYS);
+
+                /* Attach the listener to the process's children. */
+                childeventsource_addListener(
+                    cond->trigger.object.as_child_event_source, cond->triggerListener);
+                break;
+            }
             case TRIGGER_NONE: {
                 break;
             }
@@ -505,6 +550,11 @@ void syscallcondition_cancel(SysCallCondition* cond) {
 bool syscallcondition_wakeupForSignal(SysCallCondition* cond, const Host* host, int signo) {
     MAGIC_ASSERT(cond);
 
+    if (cond->uninterruptible) {
+        // The syscall can't be interrupted. Leave the signal pending.
+        return false;
+    }
+
     ShimShmemHostLock* hostLock = host_getShimShmemLock(host);
     const Thread* thread = host_getThread(host, cond->threadId);
     linux_sigset_t blockedSignals = shimshmem_getBlockedSignals(hostLock, thread_sharedMem(thread));
@@ -527,3 +577,23 @@ CEmulatedTime syscallcondition_getTimeout(SysCallCondition* cond) {
 }
 
 OpenFile* syscallcondition_getActiveFile(SysCallCondition* cond) { return cond->activeFile; }
+
+void syscallcondition_setProgress(SysCallCondition* cond, size_t progress) {
+    MAGIC_ASSERT(cond);
+    cond->progress = progress;
+}
+
+size_t syscallcondition_getProgress(SysCallCondition* cond) {
+    MAGIC_ASSERT(cond);
+    return cond->progress;
+}
+
+void syscallcondition_setUninterruptible(SysCallCondition* cond, bool uninterruptible) {
+    MAGIC_ASSERT(cond);
+    cond->uninterruptible = uninterruptible;
+}
+
+bool syscallcondition_isUninterruptible(SysCallCondition* cond) {
+    MAGIC_ASSERT(cond);
+    return cond->uninterruptible;
+}
@@ -20,6 +20,7 @@ enum _TriggerType {
     TRIGGER_DESCRIPTOR,
     TRIGGER_FILE,
     TRIGGER_FUTEX,
+    TRIGGER_CHILD,
 };
 
 /* Pointer to the object whose status we monitor for changes */
@@ -29,6 +30,7 @@ union _TriggerObject {
     LegacyFile* as_legacy_file;
     const File* as_file;
     Futex* as_futex;
+    const ChildEventSource* as_child_event_source;
 };
 
 /* The spec of the condition that will cause us to unblock a process/thread waiting for the object
@@ -62,6 +64,15 @@ void syscallcondition_setTimeout(SysCallCondition* cond, CEmulatedTime t);
  * the descriptor table). */
 void syscallcondition_setActiveFile(SysCallCondition* cond, OpenFile* file);
 
+/* Record how much of its work the syscall has already completed before blocking, such as the
+ * number of messages received, so that the syscall handler can continue from there once it becomes
+ * unblocked. */
+void syscallcondition_setProgress(SysCallCondition* cond, size_t progress);
+
+/* Set whether signals should be left pending until the condition is satisfied, instead of
+ * interrupting the blocked syscall. */
+void syscallcondition_setUninterruptible(SysCallCondition* cond, bool uninterruptible);
+
 /* Increment the reference count on the given condition. */
 void syscallcondition_ref(SysCallCondition* cond);
 
@@ -86,7 +97,14 @@ CEmulatedTime syscallcondition_getTimeout(SysCallCondition* cond);
 /* Get the active file for the condition, or NULL if there isn't one. */
 OpenFile* syscallcondition_getActiveFile(SysCallCondition* cond);
 
-/* If the condition's thread doesn't have `signo` blocked, schedule a wakeup.
+/* Get the progress recorded for the condition, or 0 if none was recorded. */
+size_t syscallcondition_getProgress(SysCallCondition* cond);
+
+/* Whether signals are left pending until the condition is satisfied. */
+bool syscallcondition_isUninterruptible(SysCallCondition* cond);
+
+/* If the condition's thread doesn't have `signo` blocked, and the condition isn't
+ * uninterruptible, schedule a wakeup.
  *
  * Returns whether a wakeup was scheduled.
  */
@@ -45,6 +45,18 @@ impl<'a> SysCallConditionRef<'a> {
         let timeout = unsafe { cshadow::syscallcondition_getTimeout(self.c_ptr) };
         EmulatedTime::from_c_emutime(timeout)
     }
+
+    /// How much of its work the syscall completed before it blocked. See
+    /// [`SysCallConditionRefMut::set_progress`].
+    pub fn progress(&self) -> usize {
+        unsafe { cshadow::syscallcondition_getProgress(self.c_ptr) }
+    }
+
+    /// Whether signals are left pending until the condition is satisfied. See
+    /// [`SysCallConditionRefMut::set_uninterruptible`].
+    pub fn is_uninterruptible(&self) -> bool {
+        unsafe { cshadow::syscallcondition_isUninterruptible(self.c_ptr) }
+    }
 }
 
 /// A mutable reference to a syscall condition.
@@ -84,6 +96,18 @@ impl<'a> SysCallConditionRefMut<'a> {
         let timeout = EmulatedTime::to_c_emutime(timeout);
         unsafe { cshadow::syscallcondition_setTimeout(self.c_ptr, timeout) };
     }
+
+    /// Record how much of its work the syscall completed before it blocked, such as the number of
+    /// messages received, so that it can continue from there when it's restarted.
+    pub fn set_progress(&mut self, progress: usize) {
+        unsafe { cshadow::syscallcondition_setProgress(self.c_ptr, progress) };
+    }
+
+    /// Leave signals pending until the condition is satisfied, instead of interrupting the blocked
+    /// syscall, such as for the parent of a `vfork`.
+    pub fn set_uninterruptible(&mut self, uninterruptible: bool) {
+        unsafe { cshadow::syscallcondition_setUninterruptible(self.c_ptr, uninterruptible) };
+    }
 }
 
 impl<'a> std::ops::Deref for SysCallConditionRefMut<'a> {
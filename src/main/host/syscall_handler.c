@@ -22,7 +22,6 @@
 #include "main/core/worker.h"
 #include "main/host/descriptor/descriptor.h"
 #include "main/host/process.h"
-#include "main/host/syscall/epoll.h"
 #include "main/host/syscall/fcntl.h"
 #include "main/host/syscall/file.h"
 #include "main/host/syscall/fileat.h"
@@ -310,20 +309,21 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
 #endif
             HANDLE_RUST(close);
             HANDLE_RUST(connect);
+            HANDLE_RUST(copy_file_range);
             HANDLE_C(creat);
             HANDLE_RUST(dup);
             HANDLE_RUST(dup2);
             HANDLE_RUST(dup3);
-            HANDLE_C(epoll_create);
-            HANDLE_C(epoll_create1);
-            HANDLE_C(epoll_ctl);
-            HANDLE_C(epoll_pwait);
-            HANDLE_C(epoll_pwait2);
-            HANDLE_C(epoll_wait);
+            HANDLE_RUST(epoll_create);
+            HANDLE_RUST(epoll_create1);
+            HANDLE_RUST(epoll_ctl);
+            HANDLE_RUST(epoll_pwait);
+            HANDLE_RUST(epoll_pwait2);
+            HANDLE_RUST(epoll_wait);
             HANDLE_RUST(eventfd);
             HANDLE_RUST(eventfd2);
-            UNSUPPORTED(execve);
-            HANDLE_C(exit_group);
+            HANDLE_RUST(execve);
+            HANDLE_RUST(exit_group);
             HANDLE_C(faccessat);
             HANDLE_C(fadvise64);
             HANDLE_C(fallocate);
@@ -371,11 +371,16 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             HANDLE_RUST(getsockname);
             HANDLE_RUST(getsockopt);
             SHIM_ONLY(gettimeofday);
+            HANDLE_RUST(inotify_add_watch);
+            HANDLE_RUST(inotify_init);
+            HANDLE_RUST(inotify_init1);
+            HANDLE_RUST(inotify_rm_watch);
             HANDLE_RUST(ioctl);
             HANDLE_C(kill);
             HANDLE_C(linkat);
             HANDLE_RUST(listen);
             HANDLE_C(lseek);
+            HANDLE_C(mkdir);
             HANDLE_C(mkdirat);
             HANDLE_C(mknodat);
             HANDLE_RUST(mmap);
@@ -413,17 +418,22 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             HANDLE_C(readlinkat);
             HANDLE_RUST(readv);
             HANDLE_RUST(recvfrom);
+            HANDLE_RUST(recvmmsg);
             HANDLE_RUST(recvmsg);
             HANDLE_C(renameat);
             HANDLE_C(renameat2);
+            HANDLE_C(rmdir);
             HANDLE_RUST(rseq);
             HANDLE_RUST(sched_getaffinity);
             HANDLE_RUST(sched_setaffinity);
             SHIM_ONLY(sched_yield);
             HANDLE_C(shadow_hostname_to_addr_ipv4);
+            HANDLE_C(shadow_hostname_to_addr_ipv6);
             HANDLE_C(shadow_init_memory_manager);
             HANDLE_C(shadow_yield);
             HANDLE_C(select);
+            HANDLE_RUST(sendfile);
+            HANDLE_RUST(sendmmsg);
             HANDLE_RUST(sendmsg);
             HANDLE_RUST(sendto);
             HANDLE_RUST(setsockopt);
@@ -437,6 +447,8 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             // Superseded by sigaction in glibc 2.0
             UNSUPPORTED(signal);
 #endif
+            HANDLE_RUST(signalfd);
+            HANDLE_RUST(signalfd4);
 #ifdef SYS_sigprocmask
             // Superseded by rt_sigprocmask in Linux 2.2
             UNSUPPORTED(sigprocmask);
@@ -448,6 +460,7 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             HANDLE_RUST(shutdown);
             HANDLE_RUST(socket);
             HANDLE_RUST(socketpair);
+            HANDLE_RUST(splice);
 #ifdef SYS_statx
             HANDLE_C(statx);
 #endif
@@ -455,6 +468,7 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             HANDLE_C(sync_file_range);
             HANDLE_C(syncfs);
             HANDLE_RUST(sysinfo);
+            HANDLE_RUST(tee);
             HANDLE_C(tgkill);
             SHIM_ONLY(time);
             HANDLE_RUST(timerfd_create);
@@ -462,9 +476,13 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             HANDLE_RUST(timerfd_settime);
             HANDLE_C(tkill);
             HANDLE_C(uname);
+            HANDLE_C(unlink);
             HANDLE_C(unlinkat);
             HANDLE_C(utimensat);
             HANDLE_RUST(vfork);
+            HANDLE_RUST(vmsplice);
+            HANDLE_RUST(wait4);
+            HANDLE_RUST(waitid);
             HANDLE_RUST(write);
             HANDLE_RUST(writev);
 
@@ -478,20 +496,8 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             UNSUPPORTED(fchdir);
 
             UNSUPPORTED(io_getevents);
-            UNSUPPORTED(waitid);
             UNSUPPORTED(msync);
 
-            // copying data between various types of fds
-            UNSUPPORTED(copy_file_range);
-            UNSUPPORTED(sendfile);
-            UNSUPPORTED(splice);
-            UNSUPPORTED(vmsplice);
-            UNSUPPORTED(tee);
-
-            //// additional socket io
-            UNSUPPORTED(recvmmsg);
-            UNSUPPORTED(sendmmsg);
-
             // ***************************************
             // We think we don't need to handle these
             // (because the plugin can natively):
@@ -520,12 +526,10 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             NATIVE(lsetxattr);
             NATIVE(lstat);
             NATIVE(madvise);
-            NATIVE(mkdir);
             NATIVE(mknod);
             NATIVE(readlink);
             NATIVE(removexattr);
             NATIVE(rename);
-            NATIVE(rmdir);
             NATIVE(rt_sigreturn);
             NATIVE(setfsgid);
             NATIVE(setfsuid);
@@ -544,7 +548,6 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
             NATIVE(statfs);
             NATIVE(symlink);
             NATIVE(truncate);
-            NATIVE(unlink);
             NATIVE(utime);
             NATIVE(utimes);
 
@@ -594,6 +597,7 @@ SyscallReturn syscallhandler_make_syscall(SysCallHandler* sys, const SysCallArgs
     // call will return a success  status  (normally,  the  number of bytes
     // transferred)."
     if (scr.tag == SYSCALL_RETURN_BLOCK &&
+        !syscallcondition_isUninterruptible(syscallreturn_blocked(&scr)->cond) &&
         thread_unblockedSignalPending(thread, host_getShimShmemLock(host))) {
         SyscallReturnBlocked* blocked = syscallreturn_blocked(&scr);
         syscallcondition_unref(blocked->cond);
@@ -20,7 +20,8 @@ typedef enum {
     // debugging purposes, so that it doesn't appear that the managed code
     // issues a SYS_sched_yield.
     SYS_shadow_yield = 1005,
-    SYS_shadow_max = 1005,
+    SYS_shadow_hostname_to_addr_ipv6 = 1006,
+    SYS_shadow_max = 1006,
 } ShadowSyscallNum;
 
 static inline bool syscall_num_is_shadow(long n) {
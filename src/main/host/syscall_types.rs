@@ -10,6 +10,7 @@ use shadow_shim_helper_rs::syscall_types::{ForeignPtr, SysCallReg};
 
 use crate::cshadow as c;
 use crate::host::descriptor::{File, FileState};
+use crate::host::process::ChildEventSource;
 use crate::host::syscall::Trigger;
 use crate::host::syscall_condition::SysCallCondition;
 
@@ -220,6 +221,18 @@ impl SyscallError {
         })
     }
 
+    /// Block until a child of the process changes to `state`; e.g. `FileState::CHILD_EXITED`.
+    pub fn new_blocked_on_child(
+        source: ChildEventSource,
+        state: FileState,
+        restartable: bool,
+    ) -> Self {
+        Self::Blocked(Blocked {
+            condition: SysCallCondition::new(Trigger::from_child_event_source(source, state)),
+            restartable,
+        })
+    }
+
     pub fn new_blocked_until(unblock_time: EmulatedTime, restartable: bool) -> Self {
         Self::Blocked(Blocked {
             condition: SysCallCondition::new_from_wakeup_time(unblock_time),
@@ -27,6 +27,9 @@ pub enum ResumeResult {
     ExitedThread(i32),
     /// The process has exited.
     ExitedProcess,
+    /// The process image was replaced by `execve`, and this thread no longer
+    /// exists.
+    ExecedProcess,
 }
 
 /// A virtual Thread in Shadow. Currently a thin wrapper around the C Thread,
@@ -38,6 +41,9 @@ pub struct Thread {
     // If non-NULL, this address should be cleared and futex-awoken on thread exit.
     // See set_tid_address(2).
     tid_address: Cell<ForeignPtr<libc::pid_t>>,
+    // Set while this thread is suspended in `clone` until the child process it
+    // created with `CLONE_VFORK` releases it.
+    vfork_child: Cell<Option<ProcessId>>,
     shim_shared_memory: ShMemBlock<'static, ThreadShmem>,
     syscallhandler: SendPointer<c::SysCallHandler>,
     // TODO: convert to SysCallCondition (Rust wrapper for c::SysCallCondition).
@@ -311,6 +317,7 @@ impl Thread {
             host_id: host.id(),
             process_id: pid,
             tid_address: Cell::new(ForeignPtr::null()),
+            vfork_child: Cell::new(None),
             shim_shared_memory: Allocator::global().alloc(ThreadShmem::new(
                 &host.shim_shmem_lock_borrow().unwrap(),
                 tid.into(),
@@ -355,6 +362,7 @@ impl Thread {
             }
             managed_thread::ResumeResult::ExitedThread(c) => ResumeResult::ExitedThread(c),
             managed_thread::ResumeResult::ExitedProcess => ResumeResult::ExitedProcess,
+            managed_thread::ResumeResult::ExecedProcess => ResumeResult::ExecedProcess,
         }
     }
 
@@ -379,6 +387,16 @@ impl Thread {
         self.tid_address.set(ptr)
     }
 
+    /// The child process created with `CLONE_VFORK` that this thread is
+    /// waiting on, if any.
+    pub fn vfork_child(&self) -> Option<ProcessId> {
+        self.vfork_child.get()
+    }
+
+    pub fn set_vfork_child(&self, child: Option<ProcessId>) {
+        self.vfork_child.set(child)
+    }
+
     pub fn unblocked_signal_pending(
         &self,
         process: &Process,
@@ -432,6 +450,12 @@ impl From<ProcessId> for ThreadId {
     }
 }
 
+impl From<ThreadId> for u32 {
+    fn from(val: ThreadId) -> Self {
+        val.0
+    }
+}
+
 impl From<ThreadId> for libc::pid_t {
     fn from(val: ThreadId) -> Self {
         val.0.try_into().unwrap()
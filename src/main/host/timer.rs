@@ -88,12 +88,13 @@ impl Timer {
     }
 
     /// Returns the remaining time until the next expiration if the timer is
-    /// armed, or None otherwise.
-    pub fn remaining_time(&self) -> Option<SimulationTime> {
+    /// armed, or None otherwise. The time is measured by the host's monotonic
+    /// clock.
+    pub fn remaining_time(&self, host: &Host) -> Option<SimulationTime> {
         self.magic.debug_check();
         let t = self.internal.borrow().next_expire_time?;
         let now = Worker::current_time().unwrap();
-        Some(t.saturating_duration_since(&now))
+        Some(host.clock().monotonic_elapsed(now, t))
     }
 
     /// Deactivate the timer so that it does not issue `on_expire()` callback notifications.
@@ -141,10 +142,19 @@ impl Timer {
         if let Some(interval) = internal_brw.expire_interval {
             // The interval must be positive.
             debug_assert!(interval.is_positive());
-            internal_brw.next_expire_time = Some(next_expire_time + interval);
+            // The interval is measured by the host's monotonic clock.
+            let next_expire_time = host.clock().monotonic_after(next_expire_time, interval);
+            internal_brw.next_expire_time = Some(next_expire_time);
             Self::schedule_new_expire_event(&mut internal_brw, internal_weak.clone(), host);
         }
 
+        host.trace_event(|| event_trace::Event::TimerFired {
+            expiration_count: internal_brw.expiration_count,
+            interval_ns: internal_brw
+                .expire_interval
+                .map(|x| x.as_nanos().try_into().unwrap()),
+        });
+
         // Re-borrow as an immutable reference while executing the callback.
         drop(internal_brw);
         let internal_brw = internal.borrow();
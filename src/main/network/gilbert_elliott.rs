@@ -0,0 +1,185 @@
+//! A two-state Gilbert-Elliott packet loss model, which models the bursty
+//! losses of wireless and congested links.
+//!
+//!  More info:
+//!   - <https://en.wikipedia.org/wiki/Burst_error>
+//!   - <https://man7.org/linux/man-pages/man8/tc-netem.8.html>
+
+use rand::Rng;
+
+/// The parameters of a Gilbert-Elliott loss model. The model is a Markov chain
+/// with a "good" and a "bad" state, where each state has its own loss rate.
+/// The chain transitions once for every packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GilbertElliott {
+    /// The chance of moving from the good state to the bad state.
+    pub p: f32,
+    /// The chance of moving from the bad state to the good state.
+    pub r: f32,
+    /// The chance that a packet is lost in the good state.
+    pub loss_good: f32,
+    /// The chance that a packet is lost in the bad state.
+    pub loss_bad: f32,
+}
+
+impl GilbertElliott {
+    /// The long-run fraction of packets that are lost.
+    pub fn mean_loss(&self) -> f32 {
+        let bad = self.stationary_bad();
+        (1.0 - bad) * self.loss_good + bad * self.loss_bad
+    }
+
+    /// The long-run fraction of time spent in the bad state.
+    fn stationary_bad(&self) -> f32 {
+        if self.p + self.r == 0.0 {
+            // the chain never changes state, and we always start in the good state
+            return 0.0;
+        }
+        self.p / (self.p + self.r)
+    }
+
+    /// Combine the models of two independent links into a single model for a
+    /// path containing both links. A two-state model can't represent the
+    /// four-state chain exactly, so this is an approximation where the path is
+    /// in the bad state if either link is. It is exact when entering the bad
+    /// state from both links being good, and when leaving the bad state from
+    /// both links being bad.
+    pub fn combine(&self, other: &Self) -> Self {
+        let either = |a: f32, b: f32| 1.0 - (1.0 - a) * (1.0 - b);
+        Self {
+            p: either(self.p, other.p),
+            r: self.r * other.r,
+            loss_good: either(self.loss_good, other.loss_good),
+            loss_bad: either(self.loss_bad, other.loss_bad),
+        }
+    }
+}
+
+/// The state of a Gilbert-Elliott loss model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GilbertElliottState {
+    Good,
+    Bad,
+}
+
+impl GilbertElliottState {
+    /// Choose a starting state from the model's long-run distribution, so
+    /// that the first packets aren't biased towards the good state.
+    pub fn initial(model: &GilbertElliott, rng: &mut impl Rng) -> Self {
+        if rng.gen::<f32>() < model.stationary_bad() {
+            Self::Bad
+        } else {
+            Self::Good
+        }
+    }
+
+    /// Move to the state for the next packet, and return true if that packet
+    /// is lost.
+    pub fn next_is_lost(&mut self, model: &GilbertElliott, rng: &mut impl Rng) -> bool {
+        *self = match *self {
+            Self::Good if rng.gen::<f32>() < model.p => Self::Bad,
+            Self::Bad if rng.gen::<f32>() < model.r => Self::Good,
+            x => x,
+        };
+
+        let loss = match *self {
+            Self::Good => model.loss_good,
+            Self::Bad => model.loss_bad,
+        };
+
+        // don't draw a random value if the outcome is fixed
+        match loss {
+            x if x <= 0.0 => false,
+            x if x >= 1.0 => true,
+            x => rng.gen::<f32>() < x,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    use super::*;
+
+    fn model(p: f32, r: f32) -> GilbertElliott {
+        GilbertElliott {
+            p,
+            r,
+            loss_good: 0.0,
+            loss_bad: 1.0,
+        }
+    }
+
+    #[test]
+    fn mean_loss() {
+        assert_eq!(model(0.0, 0.0).mean_loss(), 0.0);
+        assert!((model(0.1, 0.3).mean_loss() - 0.25).abs() < 0.001);
+        assert_eq!(model(1.0, 0.0).mean_loss(), 1.0);
+    }
+
+    #[test]
+    fn combine() {
+        let a = model(0.1, 0.5);
+        let b = model(0.2, 0.5);
+        let c = a.combine(&b);
+        assert!((c.p - 0.28).abs() < 0.001);
+        assert!((c.r - 0.25).abs() < 0.001);
+        assert_eq!(c.loss_good, 0.0);
+        assert_eq!(c.loss_bad, 1.0);
+    }
+
+    #[test]
+    fn deterministic() {
+        let m = model(0.05, 0.3);
+        let run = |seed| {
+            let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+            let mut state = GilbertElliottState::initial(&m, &mut rng);
+            (0..1000)
+                .map(|_| state.next_is_lost(&m, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn bursty() {
+        let m = model(0.01, 0.1);
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+        let mut state = GilbertElliottState::Good;
+
+        const N: usize = 100_000;
+        let losses: Vec<_> = (0..N).map(|_| state.next_is_lost(&m, &mut rng)).collect();
+
+        // the loss rate is close to the long-run loss rate
+        let lost = losses.iter().filter(|x| **x).count();
+        let rate = lost as f32 / N as f32;
+        assert!((rate - m.mean_loss()).abs() < 0.02);
+
+        // losses are clustered: a loss is much more likely to follow a loss
+        // than it would be if losses were independent
+        let after_loss = losses.windows(2).filter(|x| x[0] && x[1]).count();
+        let conditional = after_loss as f32 / lost as f32;
+        assert!(conditional > 0.8);
+        assert!(rate < 0.2);
+    }
+
+    #[test]
+    fn fixed_state() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+
+        // never leaves the good state
+        let m = model(0.0, 1.0);
+        let mut state = GilbertElliottState::initial(&m, &mut rng);
+        assert_eq!(state, GilbertElliottState::Good);
+        assert!((0..100).all(|_| !state.next_is_lost(&m, &mut rng)));
+
+        // never leaves the bad state
+        let m = model(1.0, 0.0);
+        let mut state = GilbertElliottState::initial(&m, &mut rng);
+        assert_eq!(state, GilbertElliottState::Bad);
+        assert!((0..100).all(|_| state.next_is_lost(&m, &mut rng)));
+    }
+}
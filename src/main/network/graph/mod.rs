@@ -8,12 +8,15 @@ use std::hash::Hash;
 use anyhow::Context;
 use log::*;
 use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use shadow_shim_helper_rs::simulation_time::SimulationTime;
 
 use crate::core::support::configuration::{
     self, Compression, FileSource, GraphOptions, GraphSource,
 };
 use crate::core::support::{units, units::Unit};
+use crate::network::gilbert_elliott::GilbertElliott;
 use crate::network::graph::petgraph_wrapper::GraphWrapper;
 use crate::utility::tilde_expansion;
 
@@ -69,23 +72,78 @@ pub struct ShadowEdge {
     pub latency: units::Time<units::TimePrefix>,
     pub jitter: units::Time<units::TimePrefix>,
     pub packet_loss: f32,
+    pub packet_reorder: f32,
+    pub packet_reorder_gap: units::Time<units::TimePrefix>,
+    pub packet_duplicate: f32,
+    pub packet_corrupt: f32,
+    pub gilbert_elliott: Option<GilbertElliott>,
 }
 
 impl TryFrom<gml_parser::gml::Edge<'_>> for ShadowEdge {
     type Error = String;
 
     fn try_from(mut gml_edge: gml_parser::gml::Edge) -> Result<Self, Self::Error> {
+        let latency: units::Time<units::TimePrefix> = gml_edge
+            .other
+            .remove("latency")
+            .ok_or("Edge 'latency' was not provided")?
+            .as_str()
+            .ok_or("Edge 'latency' is not a string")?
+            .parse()
+            .map_err(|e| format!("Edge 'latency' is not a valid unit: {}", e))?;
+
+        // an optional probability, which must be in the range [0,1]
+        let mut probability = |name: &str| -> Result<Option<f32>, String> {
+            let Some(p) = gml_edge.other.remove(name) else {
+                return Ok(None);
+            };
+            let p = p
+                .as_float()
+                .ok_or_else(|| format!("Edge '{name}' is not a float"))?;
+            if !(0.0..=1.0).contains(&p) {
+                return Err(format!("Edge '{name}' is not in the range [0,1]"));
+            }
+            Ok(Some(p))
+        };
+
+        let packet_reorder = probability("packet_reorder")?.unwrap_or(0.0);
+        let packet_duplicate = probability("packet_duplicate")?.unwrap_or(0.0);
+        let packet_corrupt = probability("packet_corrupt")?.unwrap_or(0.0);
+
+        // the gilbert-elliott model is enabled by setting its transition probabilities
+        let gilbert_elliott_p = probability("gilbert_elliott_p")?;
+        let gilbert_elliott_r = probability("gilbert_elliott_r")?;
+        let gilbert_elliott_loss_good = probability("gilbert_elliott_loss_good")?;
+        let gilbert_elliott_loss_bad = probability("gilbert_elliott_loss_bad")?;
+
+        let gilbert_elliott = match (gilbert_elliott_p, gilbert_elliott_r) {
+            (Some(p), Some(r)) => Some(GilbertElliott {
+                p,
+                r,
+                loss_good: gilbert_elliott_loss_good.unwrap_or(0.0),
+                loss_bad: gilbert_elliott_loss_bad.unwrap_or(1.0),
+            }),
+            (None, None) => {
+                if gilbert_elliott_loss_good.is_some() || gilbert_elliott_loss_bad.is_some() {
+                    return Err(
+                        "Edge 'gilbert_elliott_loss_good' and 'gilbert_elliott_loss_bad' \
+                                require 'gilbert_elliott_p' and 'gilbert_elliott_r'"
+                            .into(),
+                    );
+                }
+                None
+            }
+            _ => {
+                return Err(
+                    "Edge 'gilbert_elliott_p' and 'gilbert_elliott_r' must be set together".into(),
+                )
+            }
+        };
+
         let rv = Self {
             source: gml_edge.source,
             target: gml_edge.target,
-            latency: gml_edge
-                .other
-                .remove("latency")
-                .ok_or("Edge 'latency' was not provided")?
-                .as_str()
-                .ok_or("Edge 'latency' is not a string")?
-                .parse()
-                .map_err(|e| format!("Edge 'latency' is not a valid unit: {}", e))?,
+            latency,
             jitter: match gml_edge.other.remove("jitter") {
                 Some(x) => x
                     .as_str()
@@ -98,6 +156,19 @@ impl TryFrom<gml_parser::gml::Edge<'_>> for ShadowEdge {
                 Some(x) => x.as_float().ok_or("Edge 'packet_loss' is not a float")?,
                 None => 0.0,
             },
+            packet_reorder,
+            // by default, reordered packets are delayed by an extra edge latency
+            packet_reorder_gap: match gml_edge.other.remove("packet_reorder_gap") {
+                Some(x) => x
+                    .as_str()
+                    .ok_or("Edge 'packet_reorder_gap' is not a string")?
+                    .parse()
+                    .map_err(|e| format!("Edge 'packet_reorder_gap' is not a valid unit: {}", e))?,
+                None => latency,
+            },
+            packet_duplicate,
+            packet_corrupt,
+            gilbert_elliott,
         };
 
         if rv.packet_loss < 0f32 || rv.packet_loss > 1f32 {
@@ -108,6 +179,12 @@ impl TryFrom<gml_parser::gml::Edge<'_>> for ShadowEdge {
             return Err("Edge 'latency' must not be 0".into());
         }
 
+        if rv.packet_reorder > 0.0 && rv.packet_reorder_gap.value() == 0 {
+            return Err(
+                "Edge 'packet_reorder_gap' must not be 0 if 'packet_reorder' is set".into(),
+            );
+        }
+
         Ok(rv)
     }
 }
@@ -182,32 +259,83 @@ impl NetworkGraph {
         })
     }
 
+    /// Returns true if the graph has an edge from node `src` to node `dst` (or in either
+    /// direction for undirected graphs).
+    pub fn has_edge(&self, src: u32, dst: u32) -> bool {
+        let (Some(src), Some(dst)) = (self.node_id_to_index(src), self.node_id_to_index(dst))
+        else {
+            return false;
+        };
+        self.graph.find_edge(*src, *dst).is_some()
+    }
+
+    /// Get the properties of an edge, taking into account any changes to the edge. Changes are
+    /// keyed by the gml source and target node ids.
+    fn edge_properties(&self, edge: &ShadowEdge, changes: &EdgeChanges) -> PathProperties {
+        let change = changes
+            .get(&(edge.source, edge.target))
+            .or_else(|| match &self.graph {
+                GraphWrapper::Directed(_) => None,
+                GraphWrapper::Undirected(_) => changes.get(&(edge.target, edge.source)),
+            });
+
+        let mut properties: PathProperties = edge.into();
+        if let Some(change) = change {
+            change.apply(&mut properties);
+        }
+        properties
+    }
+
+    /// Compute the shortest paths between all pairs of `nodes`, and the intermediate nodes that
+    /// each shortest path passes through.
     pub fn compute_shortest_paths(
         &self,
         nodes: &[NodeIndex],
-    ) -> Result<HashMap<(NodeIndex, NodeIndex), PathProperties>, NetGraphError> {
+        changes: &EdgeChanges,
+    ) -> Result<(Paths<NodeIndex>, PathVias<NodeIndex>), NetGraphError> {
         let start = std::time::Instant::now();
 
+        let edge_properties = |e: &ShadowEdge| self.edge_properties(e, changes);
+
         // calculate shortest paths
-        let mut paths: HashMap<(_, _), PathProperties> = nodes
+        let (mut paths, vias): (Paths<_>, PathVias<_>) = nodes
             .into_par_iter()
             .flat_map(|src| {
-                match &self.graph {
+                let (distances, vias) = match &self.graph {
                     GraphWrapper::Directed(graph) => {
-                        petgraph::algo::dijkstra(&graph, *src, None, |e| e.weight().into())
+                        let distances = petgraph::algo::dijkstra(&graph, *src, None, |e| {
+                            edge_properties(e.weight())
+                        });
+                        let vias: Vec<_> = nodes
+                            .iter()
+                            .map(|dst| path_vias(graph, &distances, *dst, edge_properties))
+                            .collect();
+                        (distances, vias)
                     }
                     GraphWrapper::Undirected(graph) => {
-                        petgraph::algo::dijkstra(&graph, *src, None, |e| e.weight().into())
+                        let distances = petgraph::algo::dijkstra(&graph, *src, None, |e| {
+                            edge_properties(e.weight())
+                        });
+                        let vias: Vec<_> = nodes
+                            .iter()
+                            .map(|dst| path_vias(graph, &distances, *dst, edge_properties))
+                            .collect();
+                        (distances, vias)
                     }
-                }
-                .into_iter()
-                // ignore nodes that aren't in use
-                .filter(|(dst, _)| nodes.contains(dst))
-                // include the src node
-                .map(|(dst, path)| ((*src, dst), path))
-                .collect::<HashMap<(_, _), _>>()
+                };
+
+                nodes
+                    .iter()
+                    .zip(vias)
+                    // ignore nodes that aren't reachable
+                    .filter_map(|(dst, vias)| {
+                        let path = *distances.get(dst)?;
+                        // include the src node
+                        Some((((*src, *dst), path), ((*src, *dst), vias)))
+                    })
+                    .collect::<Vec<_>>()
             })
-            .collect();
+            .unzip();
 
         // use the self-loop for paths from a node to itself
         for node in nodes {
@@ -215,7 +343,8 @@ impl NetworkGraph {
             assert_eq!(paths[&(*node, *node)], PathProperties::default());
 
             // there must be a single self-loop for each node
-            paths.insert((*node, *node), self.get_edge_weight(node, node)?.into());
+            let edge = self.get_edge_weight(node, node)?;
+            paths.insert((*node, *node), self.edge_properties(edge, changes));
         }
 
         assert_eq!(paths.len(), nodes.len().pow(2));
@@ -226,20 +355,24 @@ impl NetworkGraph {
             paths.len()
         );
 
-        Ok(paths)
+        Ok((paths, vias))
     }
 
     pub fn get_direct_paths(
         &self,
         nodes: &[NodeIndex],
-    ) -> Result<HashMap<(NodeIndex, NodeIndex), PathProperties>, NetGraphError> {
+        changes: &EdgeChanges,
+    ) -> Result<Paths<NodeIndex>, NetGraphError> {
         let start = std::time::Instant::now();
 
         let paths: HashMap<_, _> = nodes
             .iter()
             .flat_map(|src| nodes.iter().map(move |dst| (*src, *dst)))
             // we require the graph to be connected with exactly one edge between any two nodes
-            .map(|(src, dst)| Ok(((src, dst), self.get_edge_weight(&src, &dst)?.into())))
+            .map(|(src, dst)| {
+                let edge = self.get_edge_weight(&src, &dst)?;
+                Ok(((src, dst), self.edge_properties(edge, changes)))
+            })
             .collect::<Result<_, NetGraphError>>()?;
 
         assert_eq!(paths.len(), nodes.len().pow(2));
@@ -295,6 +428,92 @@ impl NetworkGraph {
     }
 }
 
+/// Walk backwards from `dst` along a shortest path computed by dijkstra, and return the nodes that
+/// the path passes through, excluding the start and end nodes. The predecessor of each node is a
+/// neighbour whose distance plus the connecting edge equals the node's distance.
+fn path_vias<Ty: petgraph::EdgeType>(
+    graph: &petgraph::graph::Graph<ShadowNode, ShadowEdge, Ty, u32>,
+    distances: &HashMap<NodeIndex, PathProperties>,
+    dst: NodeIndex,
+    edge_properties: impl Fn(&ShadowEdge) -> PathProperties,
+) -> Vec<NodeIndex> {
+    let mut vias = Vec::new();
+
+    let Some(mut dist) = distances.get(&dst).copied() else {
+        return vias;
+    };
+    let mut node = dst;
+
+    // the start node is the only node with a default (zero) distance; the length check guards
+    // against cycles of zero-latency edges
+    while dist != PathProperties::default() && vias.len() < graph.node_count() {
+        let prev = graph
+            .edges_directed(node, petgraph::Direction::Incoming)
+            .filter_map(|e| {
+                // for undirected graphs the edge may be reported in either direction
+                let other = if e.target() == node {
+                    e.source()
+                } else {
+                    e.target()
+                };
+                let other_dist = *distances.get(&other)?;
+                (other != node && other_dist + edge_properties(e.weight()) == dist)
+                    .then_some((other, other_dist))
+            })
+            .next();
+
+        let Some((prev, prev_dist)) = prev else {
+            break;
+        };
+
+        if prev_dist == PathProperties::default() {
+            // reached the start node
+            break;
+        }
+
+        vias.push(prev);
+        node = prev;
+        dist = prev_dist;
+    }
+
+    vias.reverse();
+    vias
+}
+
+/// Properties of the paths between pairs of nodes.
+pub type Paths<T> = HashMap<(T, T), PathProperties>;
+
+/// The intermediate nodes that the paths between pairs of nodes pass through, in order and
+/// excluding the start and end nodes.
+pub type PathVias<T> = HashMap<(T, T), Vec<T>>;
+
+/// Changes to the properties of graph edges, keyed by the gml ids of the edge's source and target
+/// nodes.
+pub type EdgeChanges = HashMap<(u32, u32), EdgeChange>;
+
+/// A change to the properties of a graph edge.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EdgeChange {
+    /// The edge is down and can't carry any packets.
+    pub is_down: bool,
+    /// Latency in nanoseconds, replacing the edge's latency.
+    pub latency_ns: Option<u64>,
+    /// Packet loss as fraction, replacing the edge's packet loss.
+    pub packet_loss: Option<f32>,
+}
+
+impl EdgeChange {
+    fn apply(&self, properties: &mut PathProperties) {
+        properties.is_down |= self.is_down;
+        if let Some(latency_ns) = self.latency_ns {
+            properties.latency_ns = latency_ns;
+        }
+        if let Some(packet_loss) = self.packet_loss {
+            properties.packet_loss = packet_loss;
+        }
+    }
+}
+
 /// Network characteristics for a path between two nodes.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct PathProperties {
@@ -302,11 +521,27 @@ pub struct PathProperties {
     pub latency_ns: u64,
     /// Packet loss as fraction.
     pub packet_loss: f32,
+    /// The path contains an edge that is down, so packets can't be sent along it.
+    pub is_down: bool,
+    /// Chance that a packet is reordered, as a fraction.
+    pub packet_reorder: f32,
+    /// Extra delay in nanoseconds added to reordered packets.
+    pub packet_reorder_gap_ns: u64,
+    /// Chance that a packet is duplicated, as a fraction.
+    pub packet_duplicate: f32,
+    /// Chance that a packet is corrupted, as a fraction.
+    pub packet_corrupt: f32,
+    /// A model of bursty packet loss, in addition to the independent `packet_loss`.
+    pub gilbert_elliott: Option<GilbertElliott>,
 }
 
 impl PartialOrd for PathProperties {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // order by lowest latency first, then by lowest packet loss
+        // order by paths that are up first, then by lowest latency, then by lowest packet loss
+        match self.is_down.cmp(&other.is_down) {
+            std::cmp::Ordering::Equal => {}
+            x => return Some(x),
+        }
         match self.latency_ns.cmp(&other.latency_ns) {
             std::cmp::Ordering::Equal => self.packet_loss.partial_cmp(&other.packet_loss),
             x => Some(x),
@@ -325,9 +560,25 @@ impl core::ops::Add for PathProperties {
     type Output = Self;
 
     fn add(self, other: Self) -> Self::Output {
+        // the chance that an impairment happens on at least one of the two paths
+        let either = |a: f32, b: f32| 1f32 - (1f32 - a) * (1f32 - b);
+
         Self {
             latency_ns: self.latency_ns + other.latency_ns,
-            packet_loss: 1f32 - (1f32 - self.packet_loss) * (1f32 - other.packet_loss),
+            packet_loss: either(self.packet_loss, other.packet_loss),
+            is_down: self.is_down || other.is_down,
+            packet_reorder: either(self.packet_reorder, other.packet_reorder),
+            // we only reorder a packet once per path, so use the largest gap
+            packet_reorder_gap_ns: std::cmp::max(
+                self.packet_reorder_gap_ns,
+                other.packet_reorder_gap_ns,
+            ),
+            packet_duplicate: either(self.packet_duplicate, other.packet_duplicate),
+            packet_corrupt: either(self.packet_corrupt, other.packet_corrupt),
+            gilbert_elliott: match (self.gilbert_elliott, other.gilbert_elliott) {
+                (Some(a), Some(b)) => Some(a.combine(&b)),
+                (a, b) => a.or(b),
+            },
         }
     }
 }
@@ -337,6 +588,20 @@ impl std::convert::From<&ShadowEdge> for PathProperties {
         Self {
             latency_ns: e.latency.convert(units::TimePrefix::Nano).unwrap().value(),
             packet_loss: e.packet_loss,
+            is_down: false,
+            packet_reorder: e.packet_reorder,
+            // the gap has no effect if packets on this edge are never reordered
+            packet_reorder_gap_ns: if e.packet_reorder > 0.0 {
+                e.packet_reorder_gap
+                    .convert(units::TimePrefix::Nano)
+                    .unwrap()
+                    .value()
+            } else {
+                0
+            },
+            packet_duplicate: e.packet_duplicate,
+            packet_corrupt: e.packet_corrupt,
+            gilbert_elliott: e.gilbert_elliott,
         }
     }
 }
@@ -427,24 +692,67 @@ impl<T: Copy + Eq + Hash + std::fmt::Display> Default for IpAssignment<T> {
     }
 }
 
+/// The paths between nodes, and the nodes that the paths pass through.
+#[derive(Debug)]
+struct Routes<T> {
+    paths: Paths<T>,
+    vias: PathVias<T>,
+}
+
 /// Routing information for paths between nodes.
 #[derive(Debug)]
 pub struct RoutingInfo<T: Eq + Hash + std::fmt::Display + Clone + Copy> {
-    paths: HashMap<(T, T), PathProperties>,
+    routes: Routes<T>,
+    /// Routes that replace all of the routes above starting at the given simulation time, sorted
+    /// by time.
+    changes: Vec<(SimulationTime, Routes<T>)>,
     packet_counters: std::sync::RwLock<HashMap<(T, T), u64>>,
 }
 
 impl<T: Eq + Hash + std::fmt::Display + Clone + Copy> RoutingInfo<T> {
-    pub fn new(paths: HashMap<(T, T), PathProperties>) -> Self {
+    /// Paths without an entry in `vias` are direct paths.
+    pub fn new(paths: Paths<T>, vias: PathVias<T>) -> Self {
         Self {
-            paths,
+            routes: Routes { paths, vias },
+            changes: Vec::new(),
             packet_counters: std::sync::RwLock::new(HashMap::new()),
         }
     }
 
-    /// Get properties for the path from one node to another.
-    pub fn path(&self, start: T, end: T) -> Option<PathProperties> {
-        self.paths.get(&(start, end)).copied()
+    /// Replace all paths with `paths` starting at simulation time `time`. Changes must be added
+    /// in order of increasing time.
+    pub fn add_change(&mut self, time: SimulationTime, paths: Paths<T>, vias: PathVias<T>) {
+        if let Some((last_time, _)) = self.changes.last() {
+            assert!(time > *last_time);
+        }
+        self.changes.push((time, Routes { paths, vias }));
+    }
+
+    /// Get the routes in effect at the given simulation time.
+    fn routes(&self, time: SimulationTime) -> &Routes<T> {
+        // find the last change that took effect at or before `time`
+        let num_applied = self.changes.partition_point(|(x, _)| *x <= time);
+        match num_applied {
+            0 => &self.routes,
+            x => &self.changes[x - 1].1,
+        }
+    }
+
+    /// Get properties for the path from one node to another at the given simulation time.
+    pub fn path(&self, start: T, end: T, time: SimulationTime) -> Option<PathProperties> {
+        self.routes(time).paths.get(&(start, end)).copied()
+    }
+
+    /// Get the nodes that the path from one node to another passes through at the given
+    /// simulation time, starting with `start` and ending with `end`. A path from a node to itself
+    /// only contains that node.
+    pub fn path_nodes(&self, start: T, end: T, time: SimulationTime) -> Vec<T> {
+        let vias = self.routes(time).vias.get(&(start, end));
+
+        std::iter::once(start)
+            .chain(vias.into_iter().flatten().copied())
+            .chain((start != end).then_some(end))
+            .collect()
     }
 
     /// Increment the number of packets sent from one node to another.
@@ -461,7 +769,7 @@ impl<T: Eq + Hash + std::fmt::Display + Clone + Copy> RoutingInfo<T> {
     pub fn log_packet_counts(&self) {
         // only logs paths that have transmitted at least one packet
         for ((start, end), count) in self.packet_counters.read().unwrap().iter() {
-            let path = self.paths.get(&(*start, *end)).unwrap();
+            let path = self.routes.paths.get(&(*start, *end)).unwrap();
             log::debug!(
                 "Found path {}->{}: latency={}ns, packet_loss={}, packet_count={}",
                 start,
@@ -474,7 +782,28 @@ impl<T: Eq + Hash + std::fmt::Display + Clone + Copy> RoutingInfo<T> {
     }
 
     pub fn get_smallest_latency_ns(&self) -> Option<u64> {
-        self.paths.values().map(|x| x.latency_ns).min()
+        self.all_routes()
+            .flat_map(|routes| routes.paths.values())
+            .map(|x| x.latency_ns)
+            .min()
+    }
+
+    /// Get the smallest latency of any path into each node, at any simulation time. Nodes without
+    /// any paths into them are not included.
+    pub fn get_smallest_latencies_to_ns(&self) -> HashMap<T, u64> {
+        let mut latencies = HashMap::new();
+        for ((_, end), path) in self.all_routes().flat_map(|routes| routes.paths.iter()) {
+            latencies
+                .entry(*end)
+                .and_modify(|x: &mut u64| *x = std::cmp::min(*x, path.latency_ns))
+                .or_insert(path.latency_ns);
+        }
+        latencies
+    }
+
+    /// Get the routes and all of their changes.
+    fn all_routes(&self) -> impl Iterator<Item = &Routes<T>> {
+        std::iter::once(&self.routes).chain(self.changes.iter().map(|(_, routes)| routes))
     }
 }
 
@@ -519,15 +848,155 @@ mod tests {
         let p1 = PathProperties {
             latency_ns: 23,
             packet_loss: 0.35,
+            is_down: false,
+
+            ..Default::default()
         };
         let p2 = PathProperties {
             latency_ns: 11,
             packet_loss: 0.85,
+            is_down: false,
+
+            ..Default::default()
         };
 
         let p3 = p1 + p2;
         assert_eq!(p3.latency_ns, 34);
         assert!((p3.packet_loss - 0.9025).abs() < 0.01);
+        assert!(!p3.is_down);
+
+        let p4 = PathProperties {
+            latency_ns: 1,
+            packet_loss: 0.0,
+            is_down: true,
+
+            ..Default::default()
+        };
+        assert!((p1 + p4).is_down);
+
+        // any path that is up is shorter than a path that is down
+        assert!(p3 < p4);
+    }
+
+    #[test]
+    fn test_path_add_impairments() {
+        let p1 = PathProperties {
+            latency_ns: 10,
+            packet_reorder: 0.5,
+            packet_reorder_gap_ns: 100,
+            packet_duplicate: 0.1,
+            ..Default::default()
+        };
+        let p2 = PathProperties {
+            latency_ns: 10,
+            packet_reorder: 0.5,
+            packet_reorder_gap_ns: 30,
+            packet_corrupt: 0.2,
+            ..Default::default()
+        };
+
+        let p3 = p1 + p2;
+        assert!((p3.packet_reorder - 0.75).abs() < 0.01);
+        assert_eq!(p3.packet_reorder_gap_ns, 100);
+        assert!((p3.packet_duplicate - 0.1).abs() < 0.01);
+        assert!((p3.packet_corrupt - 0.2).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_edge_impairments() {
+        let graph = |attrs: &str| {
+            format!(
+                r#"graph [
+                node [
+                  id 0
+                ]
+                edge [
+                  source 0
+                  target 0
+                  latency "5 ms"
+                  {attrs}
+                ]
+                ]"#
+            )
+        };
+
+        let parsed = NetworkGraph::parse(&graph("")).unwrap();
+        let node = *parsed.node_id_to_index(0).unwrap();
+        let path = PathProperties::from(parsed.get_edge_weight(&node, &node).unwrap());
+        assert_eq!(path.packet_reorder, 0.0);
+        assert_eq!(path.packet_reorder_gap_ns, 0);
+        assert_eq!(path.packet_duplicate, 0.0);
+        assert_eq!(path.packet_corrupt, 0.0);
+
+        // the reorder gap defaults to the edge latency
+        let parsed = NetworkGraph::parse(&graph("packet_reorder 0.5")).unwrap();
+        let path = PathProperties::from(parsed.get_edge_weight(&node, &node).unwrap());
+        assert_eq!(path.packet_reorder, 0.5);
+        assert_eq!(path.packet_reorder_gap_ns, 5_000_000);
+
+        let parsed = NetworkGraph::parse(&graph(
+            r#"packet_reorder 0.5 packet_reorder_gap "1 ms" packet_duplicate 0.1 packet_corrupt 0.2"#,
+        ))
+        .unwrap();
+        let path = PathProperties::from(parsed.get_edge_weight(&node, &node).unwrap());
+        assert_eq!(path.packet_reorder_gap_ns, 1_000_000);
+        assert_eq!(path.packet_duplicate, 0.1);
+        assert_eq!(path.packet_corrupt, 0.2);
+
+        NetworkGraph::parse(&graph("packet_duplicate 1.5")).unwrap_err();
+        NetworkGraph::parse(&graph("packet_corrupt -0.1")).unwrap_err();
+        NetworkGraph::parse(&graph(r#"packet_reorder 0.1 packet_reorder_gap "0 ms""#)).unwrap_err();
+    }
+
+    #[test]
+    fn test_edge_gilbert_elliott() {
+        let graph = |attrs: &str| {
+            format!(
+                r#"graph [
+                node [
+                  id 0
+                ]
+                edge [
+                  source 0
+                  target 0
+                  latency "5 ms"
+                  {attrs}
+                ]
+                ]"#
+            )
+        };
+
+        let parsed = NetworkGraph::parse(&graph("")).unwrap();
+        let node = *parsed.node_id_to_index(0).unwrap();
+        let path = PathProperties::from(parsed.get_edge_weight(&node, &node).unwrap());
+        assert!(path.gilbert_elliott.is_none());
+
+        // the state loss rates default to 0 and 1
+        let parsed =
+            NetworkGraph::parse(&graph("gilbert_elliott_p 0.1 gilbert_elliott_r 0.3")).unwrap();
+        let path = PathProperties::from(parsed.get_edge_weight(&node, &node).unwrap());
+        assert_eq!(
+            path.gilbert_elliott,
+            Some(GilbertElliott {
+                p: 0.1,
+                r: 0.3,
+                loss_good: 0.0,
+                loss_bad: 1.0,
+            })
+        );
+
+        let parsed = NetworkGraph::parse(&graph(
+            "gilbert_elliott_p 0.1 gilbert_elliott_r 0.3 gilbert_elliott_loss_good 0.01 gilbert_elliott_loss_bad 0.5",
+        ))
+        .unwrap();
+        let path = PathProperties::from(parsed.get_edge_weight(&node, &node).unwrap());
+        let model = path.gilbert_elliott.unwrap();
+        assert_eq!(model.loss_good, 0.01);
+        assert_eq!(model.loss_bad, 0.5);
+
+        NetworkGraph::parse(&graph("gilbert_elliott_p 0.1")).unwrap_err();
+        NetworkGraph::parse(&graph("gilbert_elliott_loss_bad 0.5")).unwrap_err();
+        NetworkGraph::parse(&graph("gilbert_elliott_p 1.1 gilbert_elliott_r 0.3")).unwrap_err();
     }
 
     #[test]
@@ -618,8 +1087,8 @@ mod tests {
             let node_1 = *graph.node_id_to_index(1).unwrap();
             let node_2 = *graph.node_id_to_index(2).unwrap();
 
-            let shortest_paths = graph
-                .compute_shortest_paths(&[node_0, node_1, node_2])
+            let (shortest_paths, vias) = graph
+                .compute_shortest_paths(&[node_0, node_1, node_2], &EdgeChanges::new())
                 .unwrap();
 
             let lookup_latency = |a, b| shortest_paths.get(&(a, b)).unwrap().latency_ns;
@@ -634,6 +1103,9 @@ mod tests {
                 assert_eq!(lookup_latency(node_2, node_0), 16);
                 assert_eq!(lookup_latency(node_2, node_1), 11);
                 assert_eq!(lookup_latency(node_2, node_2), 7777);
+
+                assert_eq!(vias[&(node_1, node_2)], [node_0]);
+                assert_eq!(vias[&(node_2, node_0)], [node_1]);
             } else {
                 assert_eq!(lookup_latency(node_0, node_0), 3333);
                 assert_eq!(lookup_latency(node_0, node_1), 3);
@@ -644,7 +1116,175 @@ mod tests {
                 assert_eq!(lookup_latency(node_2, node_0), 7);
                 assert_eq!(lookup_latency(node_2, node_1), 10);
                 assert_eq!(lookup_latency(node_2, node_2), 7777);
+
+                assert_eq!(vias[&(node_1, node_2)], [node_0]);
+                assert_eq!(vias[&(node_2, node_1)], [node_0]);
             }
+
+            // direct paths and paths to the node itself don't pass through any other nodes
+            assert!(vias[&(node_0, node_1)].is_empty());
+            assert!(vias[&(node_0, node_0)].is_empty());
         }
     }
+
+    // disabled under miri due to https://github.com/rayon-rs/rayon/issues/952
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_shortest_path_with_changes() {
+        let graph = r#"graph [
+          directed 0
+          node [
+            id 0
+          ]
+          node [
+            id 1
+          ]
+          node [
+            id 2
+          ]
+          edge [
+            source 0
+            target 0
+            latency "1 ns"
+          ]
+          edge [
+            source 1
+            target 1
+            latency "1 ns"
+          ]
+          edge [
+            source 2
+            target 2
+            latency "1 ns"
+          ]
+          edge [
+            source 0
+            target 1
+            latency "3 ns"
+          ]
+          edge [
+            source 0
+            target 2
+            latency "7 ns"
+          ]
+          edge [
+            source 2
+            target 1
+            latency "11 ns"
+          ]
+        ]"#;
+        let graph = NetworkGraph::parse(graph).unwrap();
+        let node_0 = *graph.node_id_to_index(0).unwrap();
+        let node_1 = *graph.node_id_to_index(1).unwrap();
+        let node_2 = *graph.node_id_to_index(2).unwrap();
+        let nodes = [node_0, node_1, node_2];
+
+        assert!(graph.has_edge(1, 0));
+        assert!(!graph.has_edge(0, 3));
+
+        // the edge is undirected, so the change applies in both directions
+        let changes = EdgeChanges::from([(
+            (1, 0),
+            EdgeChange {
+                latency_ns: Some(100),
+                ..Default::default()
+            },
+        )]);
+        let (paths, _) = graph.compute_shortest_paths(&nodes, &changes).unwrap();
+        assert_eq!(paths[&(node_0, node_1)].latency_ns, 18);
+        assert_eq!(paths[&(node_1, node_0)].latency_ns, 18);
+        assert!(!paths[&(node_0, node_1)].is_down);
+
+        let direct_paths = graph.get_direct_paths(&[node_0, node_1], &changes).unwrap();
+        assert_eq!(direct_paths[&(node_0, node_1)].latency_ns, 100);
+
+        // route around an edge that is down
+        let changes = EdgeChanges::from([(
+            (0, 2),
+            EdgeChange {
+                is_down: true,
+                ..Default::default()
+            },
+        )]);
+        let (paths, _) = graph.compute_shortest_paths(&nodes, &changes).unwrap();
+        assert_eq!(paths[&(node_0, node_2)].latency_ns, 14);
+        assert!(!paths[&(node_0, node_2)].is_down);
+
+        // a path to a node with no edges that are up is down
+        let changes = EdgeChanges::from([
+            (
+                (0, 2),
+                EdgeChange {
+                    is_down: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                (1, 2),
+                EdgeChange {
+                    is_down: true,
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let (paths, _) = graph.compute_shortest_paths(&nodes, &changes).unwrap();
+        assert!(paths[&(node_0, node_2)].is_down);
+        assert!(paths[&(node_2, node_1)].is_down);
+        assert!(!paths[&(node_0, node_1)].is_down);
+        assert!(!paths[&(node_2, node_2)].is_down);
+    }
+
+    #[test]
+    fn test_routing_info_changes() {
+        let path = |latency_ns| PathProperties {
+            latency_ns,
+            packet_loss: 0.0,
+            is_down: false,
+
+            ..Default::default()
+        };
+
+        let mut routing_info =
+            RoutingInfo::new(HashMap::from([((0, 1), path(10))]), HashMap::new());
+        routing_info.add_change(
+            SimulationTime::from_secs(5),
+            HashMap::from([((0, 1), path(20))]),
+            HashMap::from([((0, 1), vec![2, 3])]),
+        );
+        routing_info.add_change(
+            SimulationTime::from_secs(8),
+            HashMap::from([((0, 1), path(5))]),
+            HashMap::new(),
+        );
+
+        let latency = |secs| {
+            routing_info
+                .path(0, 1, SimulationTime::from_secs(secs))
+                .unwrap()
+                .latency_ns
+        };
+
+        assert_eq!(latency(0), 10);
+        assert_eq!(latency(4), 10);
+        assert_eq!(latency(5), 20);
+        assert_eq!(latency(7), 20);
+        assert_eq!(latency(8), 5);
+        assert_eq!(latency(100), 5);
+
+        assert_eq!(routing_info.get_smallest_latency_ns(), Some(5));
+        assert_eq!(
+            routing_info.get_smallest_latencies_to_ns(),
+            HashMap::from([(1, 5)])
+        );
+        assert!(routing_info
+            .path(1, 0, SimulationTime::from_secs(0))
+            .is_none());
+
+        let path_nodes = |secs| routing_info.path_nodes(0, 1, SimulationTime::from_secs(secs));
+
+        assert_eq!(path_nodes(0), [0, 1]);
+        assert_eq!(path_nodes(5), [0, 2, 3, 1]);
+        assert_eq!(path_nodes(8), [0, 1]);
+        assert_eq!(routing_info.path_nodes(0, 0, SimulationTime::ZERO), [0]);
+    }
 }
@@ -2,6 +2,7 @@ use std::net::Ipv4Addr;
 
 use crate::network::packet::PacketRc;
 
+pub mod gilbert_elliott;
 pub mod graph;
 pub mod packet;
 pub mod relay;
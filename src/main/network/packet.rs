@@ -1,5 +1,5 @@
 use std::io::Write;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
 
 use crate::core::worker::Worker;
 use crate::cshadow as c;
@@ -35,6 +35,49 @@ pub enum PacketStatus {
     Destroyed = c::_PacketDeliveryStatusFlags_PDS_DESTROYED,
     RelayCached = c::_PacketDeliveryStatusFlags_PDS_RELAY_CACHED,
     RelayForwarded = c::_PacketDeliveryStatusFlags_PDS_RELAY_FORWARDED,
+    InetReordered = c::_PacketDeliveryStatusFlags_PDS_INET_REORDERED,
+    InetDuplicated = c::_PacketDeliveryStatusFlags_PDS_INET_DUPLICATED,
+    InetCorrupted = c::_PacketDeliveryStatusFlags_PDS_INET_CORRUPTED,
+}
+
+bitflags::bitflags! {
+    /// The flags of a TCP header.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub struct TcpFlags: c::ProtocolTCPFlags {
+        const RST = c::ProtocolTCPFlags_PTCP_RST;
+        const SYN = c::ProtocolTCPFlags_PTCP_SYN;
+        const ACK = c::ProtocolTCPFlags_PTCP_ACK;
+        const SACK = c::ProtocolTCPFlags_PTCP_SACK;
+        const FIN = c::ProtocolTCPFlags_PTCP_FIN;
+        const DUPACK = c::ProtocolTCPFlags_PTCP_DUPACK;
+    }
+}
+
+/// A TCP header as used by the Rust TCP implementation. Unlike the legacy TCP implementation,
+/// sequence numbers count bytes rather than packets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TcpHeader {
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub flags: TcpFlags,
+    pub seq: u32,
+    pub ack: u32,
+    /// The receive window in bytes. This isn't scaled, so may be larger than `u16::MAX`.
+    pub window: u32,
+    /// Selective acknowledgement blocks, each given as the sequence numbers `[start, end)`.
+    pub sack: Vec<(u32, u32)>,
+}
+
+/// An ICMP header. The identifier and sequence fields hold the last four bytes of the ICMP header,
+/// which are only meaningful for echo messages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IcmpHeader {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub icmp_type: u8,
+    pub code: u8,
+    pub identifier: u16,
+    pub sequence: u16,
 }
 
 pub struct PacketRc {
@@ -94,6 +137,134 @@ impl PacketRc {
         };
     }
 
+    /// Set TCP headers for this packet. Will panic if the packet already has a header.
+    pub fn set_tcp(&mut self, header: &TcpHeader) {
+        unsafe {
+            c::packet_setTCP(
+                self.c_ptr.ptr(),
+                header.flags.bits(),
+                u32::from(*header.src.ip()).to_be(),
+                header.src.port().to_be(),
+                u32::from(*header.dst.ip()).to_be(),
+                header.dst.port().to_be(),
+                header.seq,
+            );
+            c::packet_updateTCP(
+                self.c_ptr.ptr(),
+                header.ack,
+                std::ptr::null_mut(),
+                header.window,
+                0,
+                0,
+            );
+        }
+
+        if !header.sack.is_empty() {
+            let sack: Vec<u32> = header.sack.iter().flat_map(|&(a, b)| [a, b]).collect();
+            unsafe {
+                c::packet_setTCPSelectiveACKs(
+                    self.c_ptr.ptr(),
+                    sack.as_ptr(),
+                    sack.len().try_into().unwrap(),
+                )
+            };
+        }
+    }
+
+    /// Get the TCP header of this packet. Will panic if the packet is not a TCP packet.
+    pub fn get_tcp(&self) -> TcpHeader {
+        assert_eq!(
+            unsafe { c::packet_getProtocol(self.c_ptr.ptr()) },
+            c::_ProtocolType_PTCP
+        );
+
+        let header = unsafe { c::packet_getTCPHeader(self.c_ptr.ptr()) };
+        let header = unsafe { header.as_ref() }.unwrap();
+
+        let num_sack =
+            unsafe { c::packet_getTCPSelectiveACKs(self.c_ptr.ptr(), std::ptr::null_mut(), 0) };
+        let mut sack = vec![0u32; num_sack.try_into().unwrap()];
+        unsafe {
+            c::packet_getTCPSelectiveACKs(self.c_ptr.ptr(), sack.as_mut_ptr(), num_sack);
+        }
+
+        TcpHeader {
+            src: self.src_address(),
+            dst: self.dst_address(),
+            flags: TcpFlags::from_bits_truncate(header.flags),
+            seq: header.sequence,
+            ack: header.acknowledgment,
+            window: header.window,
+            sack: sack.chunks_exact(2).map(|x| (x[0], x[1])).collect(),
+        }
+    }
+
+    /// Set ICMP headers for this packet. Will panic if the packet already has a header.
+    pub fn set_icmp(&mut self, header: &IcmpHeader) {
+        unsafe {
+            c::packet_setICMP(
+                self.c_ptr.ptr(),
+                u32::from(header.src).to_be(),
+                u32::from(header.dst).to_be(),
+                header.icmp_type,
+                header.code,
+                header.identifier,
+                header.sequence,
+            )
+        };
+    }
+
+    /// Get the ICMP header of this packet. Will panic if the packet is not an ICMP packet.
+    pub fn get_icmp(&self) -> IcmpHeader {
+        assert_eq!(self.protocol(), c::_ProtocolType_PICMP);
+
+        let header = unsafe { c::packet_getICMPHeader(self.c_ptr.ptr()) };
+        let header = unsafe { header.as_ref() }.unwrap();
+
+        IcmpHeader {
+            src: Ipv4Addr::from(u32::from_be(header.sourceIP)),
+            dst: Ipv4Addr::from(u32::from_be(header.destinationIP)),
+            icmp_type: header.type_,
+            code: header.code,
+            identifier: header.identifier,
+            sequence: header.sequence,
+        }
+    }
+
+    pub fn protocol(&self) -> c::ProtocolType {
+        unsafe { c::packet_getProtocol(self.c_ptr.ptr()) }
+    }
+
+    /// Mark the packet as being sent over IPv6. The packet is still routed using the IPv4
+    /// addresses in its header, which are the addresses that the hosts' IPv6 addresses map to.
+    pub fn set_ipv6(&mut self, is_ipv6: bool) {
+        unsafe { c::packet_setIPv6(self.c_ptr.ptr(), is_ipv6) };
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        unsafe { c::packet_isIPv6(self.c_ptr.ptr()) }
+    }
+
+    /// Mark the packet as corrupted by the network, flipping the bit at `bit_offset` in its
+    /// payload. The receiving host drops it as if it had failed checksum validation. Will panic if
+    /// the payload doesn't contain that bit.
+    pub fn set_corrupted(&mut self, bit_offset: usize) {
+        unsafe { c::packet_setCorrupted(self.c_ptr.ptr(), bit_offset.try_into().unwrap()) };
+    }
+
+    pub fn is_corrupted(&self) -> bool {
+        unsafe { c::packet_isCorrupted(self.c_ptr.ptr()) }
+    }
+
+    /// Set the IP time-to-live (or IPv6 hop limit) of the packet.
+    pub fn set_ttl(&mut self, ttl: u8) {
+        unsafe { c::packet_setTTL(self.c_ptr.ptr(), ttl) };
+    }
+
+    pub fn ttl(&self) -> u8 {
+        unsafe { c::packet_getTTL(self.c_ptr.ptr()) }
+    }
+
     /// Set the packet payload. Will panic if the packet already has a payload.
     pub fn set_payload(&mut self, payload: &[u8], priority: FifoPacketPriority) {
         unsafe {
@@ -254,50 +425,45 @@ impl PacketDisplay for *const c::Packet {
             .try_into()
             .unwrap();
         let protocol = unsafe { c::packet_getProtocol(*self) };
+        let ttl = unsafe { c::packet_getTTL(*self) };
 
         // write the IP header
 
-        let version_and_header_length: u8 = 0x45;
-        let fields: u8 = 0x0;
-        let total_length: u16 = header_len + payload_len;
-        let identification: u16 = 0x0;
-        let flags_and_fragment: u16 = 0x4000;
-        let time_to_live: u8 = 64;
         let iana_protocol: u8 = match protocol {
+            c::_ProtocolType_PICMP => 1,
             c::_ProtocolType_PTCP => 6,
             c::_ProtocolType_PUDP => 17,
             _ => panic!("Unexpected packet protocol"),
         };
-        let header_checksum: u16 = 0x0;
-        let source_ip: [u8; 4] =
-            u32::from_be(unsafe { c::packet_getSourceIP(*self) }).to_be_bytes();
-        let dest_ip: [u8; 4] =
-            u32::from_be(unsafe { c::packet_getDestinationIP(*self) }).to_be_bytes();
-
-        // version and header length: 1 byte
-        // DSCP + ECN: 1 byte
-        writer.write_all(&[version_and_header_length, fields])?;
-        // total length: 2 bytes
-        writer.write_all(&total_length.to_be_bytes())?;
-        // identification: 2 bytes
-        writer.write_all(&identification.to_be_bytes())?;
-        // flags + fragment offset: 2 bytes
-        writer.write_all(&flags_and_fragment.to_be_bytes())?;
-        // ttl: 1 byte
-        // protocol: 1 byte
-        writer.write_all(&[time_to_live, iana_protocol])?;
-        // header checksum: 2 bytes
-        writer.write_all(&header_checksum.to_be_bytes())?;
-        // source IP: 4 bytes
-        writer.write_all(&source_ip)?;
-        // destination IP: 4 bytes
-        writer.write_all(&dest_ip)?;
+        let source_ip = Ipv4Addr::from(u32::from_be(unsafe { c::packet_getSourceIP(*self) }));
+        let dest_ip = Ipv4Addr::from(u32::from_be(unsafe { c::packet_getDestinationIP(*self) }));
+
+        if unsafe { c::packet_isIPv6(*self) } {
+            display_ipv6_header(
+                Worker::ipv4_to_ipv6(source_ip),
+                Worker::ipv4_to_ipv6(dest_ip),
+                iana_protocol,
+                header_len + payload_len - u16::try_from(c::CONFIG_HEADER_SIZE_IPV6).unwrap(),
+                ttl,
+                &mut writer,
+            )?;
+        } else {
+            display_ipv4_header(
+                source_ip,
+                dest_ip,
+                iana_protocol,
+                header_len + payload_len,
+                ttl,
+                &mut writer,
+            )?;
+        }
 
         // write protocol-specific data
 
         match protocol {
             c::_ProtocolType_PTCP => display_tcp_bytes(*self, &mut writer)?,
             c::_ProtocolType_PUDP => display_udp_bytes(*self, &mut writer)?,
+            c::_ProtocolType_PICMP => display_icmp_bytes(*self, &mut writer)?,
             _ => panic!("Unexpected packet protocol"),
         }
 
@@ -328,6 +494,70 @@ impl PacketDisplay for *const c::Packet {
     }
 }
 
+/// Helper for writing the IPv4 header of the packet.
+fn display_ipv4_header(
+    source_ip: Ipv4Addr,
+    dest_ip: Ipv4Addr,
+    iana_protocol: u8,
+    total_length: u16,
+    time_to_live: u8,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    let version_and_header_length: u8 = 0x45;
+    let fields: u8 = 0x0;
+    let identification: u16 = 0x0;
+    let flags_and_fragment: u16 = 0x4000;
+    let header_checksum: u16 = 0x0;
+
+    // version and header length: 1 byte
+    // DSCP + ECN: 1 byte
+    writer.write_all(&[version_and_header_length, fields])?;
+    // total length: 2 bytes
+    writer.write_all(&total_length.to_be_bytes())?;
+    // identification: 2 bytes
+    writer.write_all(&identification.to_be_bytes())?;
+    // flags + fragment offset: 2 bytes
+    writer.write_all(&flags_and_fragment.to_be_bytes())?;
+    // ttl: 1 byte
+    // protocol: 1 byte
+    writer.write_all(&[time_to_live, iana_protocol])?;
+    // header checksum: 2 bytes
+    writer.write_all(&header_checksum.to_be_bytes())?;
+    // source IP: 4 bytes
+    writer.write_all(&source_ip.octets())?;
+    // destination IP: 4 bytes
+    writer.write_all(&dest_ip.octets())?;
+
+    Ok(())
+}
+
+/// Helper for writing the IPv6 header of the packet.
+fn display_ipv6_header(
+    source_ip: Ipv6Addr,
+    dest_ip: Ipv6Addr,
+    next_header: u8,
+    payload_length: u16,
+    hop_limit: u8,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    // version 6, with a traffic class and flow label of 0
+    let version_class_and_flow: u32 = 0x6000_0000;
+
+    // version + traffic class + flow label: 4 bytes
+    writer.write_all(&version_class_and_flow.to_be_bytes())?;
+    // payload length: 2 bytes
+    writer.write_all(&payload_length.to_be_bytes())?;
+    // next header: 1 byte
+    // hop limit: 1 byte
+    writer.write_all(&[next_header, hop_limit])?;
+    // source IP: 16 bytes
+    writer.write_all(&source_ip.octets())?;
+    // destination IP: 16 bytes
+    writer.write_all(&dest_ip.octets())?;
+
+    Ok(())
+}
+
 /// Helper for writing the tcp bytes of the packet.
 fn display_tcp_bytes(packet: *const c::Packet, mut writer: impl Write) -> std::io::Result<()> {
     assert_eq!(
@@ -376,7 +606,10 @@ fn display_tcp_bytes(packet: *const c::Packet, mut writer: impl Write) -> std::i
     if tcp_header.flags & c::ProtocolTCPFlags_PTCP_FIN != 0 {
         tcp_flags |= 0x01;
     }
-    let window: [u8; 2] = u16::try_from(tcp_header.window).unwrap().to_be_bytes();
+    // the rust tcp implementation doesn't scale its window, so saturate large windows
+    let window: [u8; 2] = u16::try_from(tcp_header.window)
+        .unwrap_or(u16::MAX)
+        .to_be_bytes();
     let checksum: u16 = 0x0;
     let urgent_pointer: u16 = 0x0;
 
@@ -431,3 +664,171 @@ fn display_udp_bytes(packet: *const c::Packet, mut writer: impl Write) -> std::i
 
     Ok(())
 }
+
+/// Helper for writing the icmp bytes of the packet.
+fn display_icmp_bytes(packet: *const c::Packet, mut writer: impl Write) -> std::io::Result<()> {
+    assert_eq!(
+        unsafe { c::packet_getProtocol(packet) },
+        c::_ProtocolType_PICMP
+    );
+
+    let icmp_header = unsafe { c::packet_getICMPHeader(packet) };
+    let icmp_header = unsafe { icmp_header.as_ref() }.unwrap();
+
+    let mut header = [0u8; c::CONFIG_HEADER_SIZE_ICMP as usize];
+    header[0] = icmp_header.type_;
+    header[1] = icmp_header.code;
+    header[4..6].copy_from_slice(&icmp_header.identifier.to_be_bytes());
+    header[6..8].copy_from_slice(&icmp_header.sequence.to_be_bytes());
+
+    // unlike the other protocols, applications such as ping read the ICMP checksum, so we write
+    // a valid one over the header and payload
+    let payload_len = unsafe { c::packet_getPayloadSize(packet) };
+    let mut payload = vec![0u8; payload_len.try_into().unwrap()];
+    unsafe {
+        c::packet_copyPayloadShadow(
+            packet,
+            0,
+            payload.as_mut_ptr() as *mut libc::c_void,
+            payload_len,
+        )
+    };
+    let checksum = internet_checksum(header.iter().chain(&payload).copied());
+    header[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    // type: 1 byte
+    // code: 1 byte
+    // checksum: 2 bytes
+    // rest of header (identifier and sequence): 4 bytes
+    writer.write_all(&header)?;
+
+    Ok(())
+}
+
+/// The 16-bit one's complement checksum used by IP, ICMP, UDP, and TCP (RFC 1071).
+pub fn internet_checksum(bytes: impl IntoIterator<Item = u8>) -> u16 {
+    let mut bytes = bytes.into_iter();
+    let mut sum: u32 = 0;
+
+    while let Some(hi) = bytes.next() {
+        let lo = bytes.next().unwrap_or(0);
+        sum += u32::from(u16::from_be_bytes([hi, lo]));
+    }
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// The event trace's packet status for a single delivery status flag.
+fn trace_status(status: c::PacketDeliveryStatusFlags) -> Option<event_trace::PacketStatus> {
+    use event_trace::PacketStatus as S;
+
+    Some(match status {
+        c::_PacketDeliveryStatusFlags_PDS_SND_CREATED => S::SndCreated,
+        c::_PacketDeliveryStatusFlags_PDS_SND_TCP_ENQUEUE_THROTTLED => S::SndTcpEnqueueThrottled,
+        c::_PacketDeliveryStatusFlags_PDS_SND_TCP_ENQUEUE_RETRANSMIT => S::SndTcpEnqueueRetransmit,
+        c::_PacketDeliveryStatusFlags_PDS_SND_TCP_DEQUEUE_RETRANSMIT => S::SndTcpDequeueRetransmit,
+        c::_PacketDeliveryStatusFlags_PDS_SND_TCP_RETRANSMITTED => S::SndTcpRetransmitted,
+        c::_PacketDeliveryStatusFlags_PDS_SND_SOCKET_BUFFERED => S::SndSocketBuffered,
+        c::_PacketDeliveryStatusFlags_PDS_SND_INTERFACE_SENT => S::SndInterfaceSent,
+        c::_PacketDeliveryStatusFlags_PDS_INET_SENT => S::InetSent,
+        c::_PacketDeliveryStatusFlags_PDS_INET_DROPPED => S::InetDropped,
+        c::_PacketDeliveryStatusFlags_PDS_ROUTER_ENQUEUED => S::RouterEnqueued,
+        c::_PacketDeliveryStatusFlags_PDS_ROUTER_DEQUEUED => S::RouterDequeued,
+        c::_PacketDeliveryStatusFlags_PDS_ROUTER_DROPPED => S::RouterDropped,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_INTERFACE_RECEIVED => S::RcvInterfaceReceived,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_INTERFACE_DROPPED => S::RcvInterfaceDropped,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_SOCKET_PROCESSED => S::RcvSocketProcessed,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_SOCKET_DROPPED => S::RcvSocketDropped,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_TCP_ENQUEUE_UNORDERED => S::RcvTcpEnqueueUnordered,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_SOCKET_BUFFERED => S::RcvSocketBuffered,
+        c::_PacketDeliveryStatusFlags_PDS_RCV_SOCKET_DELIVERED => S::RcvSocketDelivered,
+        c::_PacketDeliveryStatusFlags_PDS_DESTROYED => S::Destroyed,
+        c::_PacketDeliveryStatusFlags_PDS_RELAY_CACHED => S::RelayCached,
+        c::_PacketDeliveryStatusFlags_PDS_RELAY_FORWARDED => S::RelayForwarded,
+        c::_PacketDeliveryStatusFlags_PDS_INET_REORDERED => S::InetReordered,
+        c::_PacketDeliveryStatusFlags_PDS_INET_DUPLICATED => S::InetDuplicated,
+        c::_PacketDeliveryStatusFlags_PDS_INET_CORRUPTED => S::InetCorrupted,
+        _ => return None,
+    })
+}
+
+/// Describe the packet for the event trace.
+///
+/// # Safety
+///
+/// `packet` must be a valid pointer.
+unsafe fn trace_packet(packet: *const c::Packet) -> event_trace::Packet {
+    let protocol = match unsafe { c::packet_getProtocol(packet) } {
+        c::_ProtocolType_PLOCAL => event_trace::Protocol::Local,
+        c::_ProtocolType_PTCP => event_trace::Protocol::Tcp,
+        c::_ProtocolType_PUDP => event_trace::Protocol::Udp,
+        c::_ProtocolType_PICMP => event_trace::Protocol::Icmp,
+        _ => event_trace::Protocol::Unknown,
+    };
+
+    // packets without a known protocol don't have addresses
+    let (src, dst) = if protocol == event_trace::Protocol::Unknown {
+        let unspecified = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+        (unspecified, unspecified)
+    } else {
+        let (src_ip, src_port, dst_ip, dst_port) = unsafe {
+            (
+                Ipv4Addr::from(u32::from_be(c::packet_getSourceIP(packet))),
+                u16::from_be(c::packet_getSourcePort(packet)),
+                Ipv4Addr::from(u32::from_be(c::packet_getDestinationIP(packet))),
+                u16::from_be(c::packet_getDestinationPort(packet)),
+            )
+        };
+
+        // IPv6 packets use internal IPv4 addresses, so map them back to the hosts' IPv6 addresses
+        if unsafe { c::packet_isIPv6(packet) } {
+            (
+                SocketAddr::from((Worker::ipv4_to_ipv6(src_ip), src_port)),
+                SocketAddr::from((Worker::ipv4_to_ipv6(dst_ip), dst_port)),
+            )
+        } else {
+            (
+                SocketAddr::from((src_ip, src_port)),
+                SocketAddr::from((dst_ip, dst_port)),
+            )
+        }
+    };
+
+    event_trace::Packet {
+        host_id: unsafe { c::packet_getHostID(packet) },
+        id: unsafe { c::packet_getPacketID(packet) },
+        protocol,
+        src,
+        dst,
+        payload_size: unsafe { c::packet_getPayloadSize(packet) } as u64,
+    }
+}
+
+mod export {
+    use super::*;
+
+    /// Add the packet's new delivery status to the event trace of the active host, if there is an
+    /// active host and it has an event trace.
+    #[no_mangle]
+    pub unsafe extern "C" fn packet_traceDeliveryStatus(
+        packet: *const c::Packet,
+        status: c::PacketDeliveryStatusFlags,
+    ) {
+        assert!(!packet.is_null());
+
+        let Some(status) = trace_status(status) else {
+            return;
+        };
+
+        Worker::with_active_host(|host| {
+            host.trace_event(|| event_trace::Event::PacketStatus {
+                packet: unsafe { trace_packet(packet) },
+                status,
+            })
+        });
+    }
+}
@@ -1,7 +1,8 @@
 //! An active queue management (AQM) algorithm implementing CoDel.
 //! <https://tools.ietf.org/html/rfc8289>
 //!
-//!  The "Flow Queue" variant is not implemented.
+//!  The "Flow Queue" variant is implemented in the `fq_codel_queue` module
+//!  using one of these queues per flow.
 //!  <https://tools.ietf.org/html/rfc8290>
 //!
 //!  More info:
@@ -16,19 +17,20 @@ use shadow_shim_helper_rs::{emulated_time::EmulatedTime, simulation_time::Simula
 
 use crate::cshadow as c;
 use crate::network::packet::{PacketRc, PacketStatus};
+use crate::network::router::RouterQueue;
 
-/// The target minimum standing queue delay time, corresponding to the "TARGET"
-/// parameter in the RFC. This is recommended to be set to 5 milliseconds in
+/// The default target minimum standing queue delay time, corresponding to the
+/// "TARGET" parameter in the RFC. This is recommended to be set to 5 milliseconds in
 /// internet routers, but in Shadow we increase it to 10 milliseconds.
 const TARGET: SimulationTime = SimulationTime::from_duration(Duration::from_millis(10));
 
-/// The most recent time interval over which the standing delay is computed,
+/// The default time interval over which the standing delay is computed,
 /// corresponding to the "INTERVAL" parameter in the RFC. This is recommended to
 /// be set to 100 milliseconds in internet routers.
 const INTERVAL: SimulationTime = SimulationTime::from_duration(Duration::from_millis(100));
 
-/// The maximum number of packets we will store, corresponding to the "limit"
-/// parameter in the codel man page. This is recommended to be 1000 in internet
+/// The default maximum number of packets we will store, corresponding to the
+/// "limit" parameter in the codel man page. This is recommended to be 1000 in internet
 /// routers, but in Shadow we don't enforce a limit due to our batched sending.
 const LIMIT: usize = usize::MAX;
 
@@ -63,6 +65,12 @@ struct CoDelElement {
 /// occasionally shrinking the queue's capacity or using a backing that is more
 /// memory-efficient (e.g. a LinkedList).
 pub struct CoDelQueue {
+    /// The target minimum standing queue delay.
+    target: SimulationTime,
+    /// The interval over which the standing delay is computed.
+    interval: SimulationTime,
+    /// The maximum number of packets we will store.
+    limit: usize,
     /// A queue holding packets and insertion times.
     elements: VecDeque<CoDelElement>,
     /// The running sum of the sizes of packets stored in the queue.
@@ -81,9 +89,22 @@ pub struct CoDelQueue {
 }
 
 impl CoDelQueue {
-    /// Creates a new empty packet queue.
+    /// Creates a new empty packet queue using the default parameters.
     pub fn new() -> CoDelQueue {
+        CoDelQueue::with_params(TARGET, INTERVAL, LIMIT)
+    }
+
+    /// Creates a new empty packet queue with the given target standing delay,
+    /// interval, and maximum number of packets.
+    pub fn with_params(
+        target: SimulationTime,
+        interval: SimulationTime,
+        limit: usize,
+    ) -> CoDelQueue {
         CoDelQueue {
+            target,
+            interval,
+            limit,
             elements: VecDeque::new(),
             total_bytes_stored: 0,
             mode: CoDelMode::Store,
@@ -95,17 +116,20 @@ impl CoDelQueue {
     }
 
     /// Returns the total number of packets stored in the queue.
-    #[cfg(test)]
     pub fn len(&self) -> usize {
         self.elements.len()
     }
 
     /// Returns true if the queue is holding zero packets, false otherwise.
-    #[cfg(test)]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// Returns the total size of the packets stored in the queue.
+    pub fn bytes_stored(&self) -> usize {
+        self.total_bytes_stored
+    }
+
     /// Returns the packet at the front of the queue, or None if the queue is
     /// empty. Note that there is no gurantee that a subsequent `pop()`
     /// operation will return the same packet, since it could be dropped by the
@@ -164,7 +188,7 @@ impl CoDelQueue {
             true => delta,
             false => 1,
         };
-        self.drop_next = Some(CoDelQueue::apply_control_law(now, self.current_drop_count));
+        self.drop_next = Some(self.apply_control_law(now, self.current_drop_count));
         self.previous_drop_count = self.current_drop_count;
 
         next_item.map(|x| x.packet)
@@ -189,10 +213,9 @@ impl CoDelQueue {
                 true => {
                     // Set the next drop time based on CoDel control law.
                     // `self.drop_next` is already set in `drop_from_store_mode()`
-                    self.drop_next = Some(CoDelQueue::apply_control_law(
-                        &self.drop_next.unwrap(),
-                        self.current_drop_count,
-                    ));
+                    self.drop_next = Some(
+                        self.apply_control_law(&self.drop_next.unwrap(), self.current_drop_count),
+                    );
                 }
                 false => self.mode = CoDelMode::Store,
             }
@@ -235,7 +258,9 @@ impl CoDelQueue {
         now: &EmulatedTime,
         standing_delay: SimulationTime,
     ) -> bool {
-        if standing_delay < TARGET || self.total_bytes_stored <= c::CONFIG_MTU.try_into().unwrap() {
+        if standing_delay < self.target
+            || self.total_bytes_stored <= c::CONFIG_MTU.try_into().unwrap()
+        {
             // We are in a good state, i.e., below the target delay. We reset
             // the interval expiration, so that we wait for at least one full
             // interval if the delay exceeds the target again.
@@ -256,7 +281,7 @@ impl CoDelQueue {
                     // entered a bad state. If we stay in the bad state for a
                     // full interval, we will need to enter drop mode later.
                     // Mark the end of the interval now so we can track it.
-                    self.interval_end = Some(now.saturating_add(INTERVAL));
+                    self.interval_end = Some(now.saturating_add(self.interval));
                     false
                 }
             }
@@ -276,7 +301,7 @@ impl CoDelQueue {
         match self.drop_next {
             Some(drop_next) => {
                 // now < drop_next + interval*16
-                now.saturating_duration_since(&drop_next) < INTERVAL.saturating_mul(16)
+                now.saturating_duration_since(&drop_next) < self.interval.saturating_mul(16)
             }
             None => false, // Have not yet dropped a packet
         }
@@ -284,9 +309,9 @@ impl CoDelQueue {
 
     /// Apply the CoDel control law using the inverse sqrt of the drop count,
     /// i.e., `time + (INTERVAL / sqrt(count));`.
-    fn apply_control_law(time: &EmulatedTime, count: usize) -> EmulatedTime {
+    fn apply_control_law(&self, time: &EmulatedTime, count: usize) -> EmulatedTime {
         let increment = {
-            let interval = INTERVAL.as_nanos_f64();
+            let interval = self.interval.as_nanos_f64();
             let sqrt_count = match count {
                 0 => 1f64,
                 _ => (count as f64).sqrt(),
@@ -303,7 +328,7 @@ impl CoDelQueue {
     /// Requires the current time as an argument to avoid calling into the
     /// worker module internally.
     pub fn push(&mut self, mut packet: PacketRc, now: EmulatedTime) {
-        if self.elements.len() < LIMIT {
+        if self.elements.len() < self.limit {
             packet.add_status(PacketStatus::RouterEnqueued);
             self.total_bytes_stored += packet.total_size();
             self.elements.push_back(CoDelElement {
@@ -318,11 +343,36 @@ impl CoDelQueue {
         }
     }
 
+    /// Drop the packet at the front of the queue without running the CoDel
+    /// logic. Returns the size of the dropped packet, or None if the queue is
+    /// empty.
+    pub fn drop_front(&mut self) -> Option<usize> {
+        let element = self.elements.pop_front()?;
+        let size = element.packet.total_size();
+        self.total_bytes_stored = self.total_bytes_stored.saturating_sub(size);
+        self.drop_packet(element.packet);
+        Some(size)
+    }
+
     fn drop_packet(&self, mut packet: PacketRc) {
         packet.add_status(PacketStatus::RouterDropped);
     }
 }
 
+impl RouterQueue for CoDelQueue {
+    fn push(&mut self, packet: PacketRc, now: EmulatedTime) {
+        CoDelQueue::push(self, packet, now)
+    }
+
+    fn pop(&mut self, now: EmulatedTime) -> Option<PacketRc> {
+        CoDelQueue::pop(self, now)
+    }
+
+    fn len(&self) -> usize {
+        CoDelQueue::len(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,14 +414,48 @@ mod tests {
         assert!(cdq.pop(now).is_none());
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn limit() {
+        let now = mock_time_millis(1000);
+        let mut cdq = CoDelQueue::with_params(TARGET, INTERVAL, 3);
+        for _ in 0..5 {
+            cdq.push(PacketRc::mock_new(), now);
+        }
+        assert_eq!(cdq.len(), 3);
+
+        assert!(cdq.drop_front().is_some());
+        assert_eq!(cdq.len(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn custom_params() {
+        let target = SimulationTime::try_from_millis(1).unwrap();
+        let interval = SimulationTime::try_from_millis(5).unwrap();
+        let start = mock_time_millis(1000);
+
+        let mut cdq = CoDelQueue::with_params(target, interval, LIMIT);
+        for _ in 0..5 {
+            cdq.push(PacketRc::mock_new(), start);
+        }
+
+        // The interval is measured from when the delay first reached the target.
+        assert_eq!(cdq.process_standing_delay(&(start + target), target), false);
+        assert_eq!(cdq.interval_end.unwrap(), start + target + interval);
+        let now = start + target + interval;
+        assert_eq!(cdq.process_standing_delay(&now, target + interval), true);
+    }
+
     #[test]
     fn control_law() {
         let now = mock_time_millis(1000);
+        let cdq = CoDelQueue::new();
 
         // The increment should be a full interval.
         for i in 0..2 {
             assert_eq!(
-                CoDelQueue::apply_control_law(&now, i).duration_since(&now),
+                cdq.apply_control_law(&now, i).duration_since(&now),
                 INTERVAL
             );
         }
@@ -379,7 +463,7 @@ mod tests {
         // The increment should reduce exponentially.
         for i in 2..20 {
             assert_eq!(
-                CoDelQueue::apply_control_law(&now, i).duration_since(&now),
+                cdq.apply_control_law(&now, i).duration_since(&now),
                 SimulationTime::from_nanos(
                     (INTERVAL.as_nanos_f64() / (i as f64).sqrt()).round() as u64
                 )
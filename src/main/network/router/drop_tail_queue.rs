@@ -0,0 +1,104 @@
+//! A bounded first-in first-out packet queue that drops packets arriving when
+//! the queue is full.
+//!
+//!  More info:
+//!   - <https://en.wikipedia.org/wiki/Tail_drop>
+//!   - <https://man7.org/linux/man-pages/man8/tc-pfifo.8.html>
+
+use std::collections::VecDeque;
+
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+
+use crate::network::packet::{PacketRc, PacketStatus};
+use crate::network::router::RouterQueue;
+
+/// A packet queue that stores up to `limit` packets, and drops any packets
+/// that arrive while it is full.
+pub struct DropTailQueue {
+    /// The packets stored in the queue.
+    elements: VecDeque<PacketRc>,
+    /// The maximum number of packets we will store.
+    limit: usize,
+}
+
+impl DropTailQueue {
+    /// Creates a new empty packet queue that stores at most `limit` packets.
+    pub fn new(limit: usize) -> DropTailQueue {
+        DropTailQueue {
+            elements: VecDeque::new(),
+            limit,
+        }
+    }
+}
+
+impl RouterQueue for DropTailQueue {
+    fn push(&mut self, mut packet: PacketRc, _now: EmulatedTime) {
+        if self.elements.len() < self.limit {
+            packet.add_status(PacketStatus::RouterEnqueued);
+            self.elements.push_back(packet);
+        } else {
+            packet.add_status(PacketStatus::RouterDropped);
+        }
+    }
+
+    fn pop(&mut self, _now: EmulatedTime) -> Option<PacketRc> {
+        self.elements.pop_front().map(|mut p| {
+            p.add_status(PacketStatus::RouterDequeued);
+            p
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.elements.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::tests::mock_time_millis;
+
+    // Some of the tests here don't run in miri because they cause c::packet*
+    // functions to be called during the test.
+
+    #[test]
+    fn empty() {
+        let now = mock_time_millis(1000);
+        let mut q = DropTailQueue::new(10);
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn limit() {
+        let now = mock_time_millis(1000);
+        const LIMIT: usize = 5;
+        let mut q = DropTailQueue::new(LIMIT);
+
+        let packets: Vec<_> = (0..LIMIT + 3).map(|_| PacketRc::mock_new()).collect();
+        for p in &packets {
+            q.push(p.clone(), now);
+        }
+        assert_eq!(q.len(), LIMIT);
+
+        // packets come out in the order they were pushed, and the packets that
+        // arrived while the queue was full were dropped
+        for p in &packets[..LIMIT] {
+            assert_eq!(q.pop(now).as_ref(), Some(p));
+        }
+        assert!(q.is_empty());
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn zero_limit() {
+        let now = mock_time_millis(1000);
+        let mut q = DropTailQueue::new(0);
+        q.push(PacketRc::mock_new(), now);
+        assert!(q.is_empty());
+        assert!(q.pop(now).is_none());
+    }
+}
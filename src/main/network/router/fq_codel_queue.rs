@@ -0,0 +1,318 @@
+//! An active queue management (AQM) algorithm implementing FlowQueue-CoDel.
+//! <https://tools.ietf.org/html/rfc8290>
+//!
+//!  More info:
+//!   - <https://man7.org/linux/man-pages/man8/tc-fq_codel.8.html>
+
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+use shadow_shim_helper_rs::{emulated_time::EmulatedTime, simulation_time::SimulationTime};
+
+use crate::network::packet::PacketRc;
+use crate::network::router::codel_queue::CoDelQueue;
+use crate::network::router::RouterQueue;
+
+/// Which of the scheduler's lists a flow queue is in.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum FlowList {
+    /// The flow has no packets and isn't scheduled.
+    None,
+    New,
+    Old,
+}
+
+/// A single flow queue, which runs its own instance of CoDel.
+struct Flow {
+    queue: CoDelQueue,
+    /// The number of bytes the flow may still dequeue in the current round.
+    deficit: i64,
+    list: FlowList,
+}
+
+/// A packet queue implementing the FQ-CoDel active queue management
+/// algorithm. Packets are hashed by their source and destination addresses
+/// into one of a fixed number of flow queues, each managed by CoDel. Flows are
+/// scheduled using deficit round robin, and flows that have just become active
+/// are given priority over flows that have been active for a while.
+///
+/// Unlike the RFC and linux, the flow hash is not perturbed by a random value
+/// so that flow assignments are the same in every simulation.
+pub struct FqCoDelQueue {
+    /// The flow queues that packets are hashed into.
+    flows: Vec<Flow>,
+    /// Flows that recently became active.
+    new_flows: VecDeque<usize>,
+    /// Flows that have been active for at least one round.
+    old_flows: VecDeque<usize>,
+    /// The number of bytes each flow may dequeue in each round.
+    quantum: i64,
+    /// The maximum number of packets we will store across all flows.
+    limit: usize,
+    /// The number of packets stored across all flows.
+    len: usize,
+}
+
+impl FqCoDelQueue {
+    /// Creates a new empty packet queue with `num_flows` flow queues, each
+    /// using CoDel with the given target standing delay and interval. The
+    /// `limit` is the maximum number of packets stored across all flows.
+    pub fn new(
+        target: SimulationTime,
+        interval: SimulationTime,
+        limit: usize,
+        num_flows: usize,
+        quantum: u32,
+    ) -> FqCoDelQueue {
+        assert!(num_flows > 0);
+        assert!(quantum > 0);
+
+        let flows = (0..num_flows)
+            .map(|_| Flow {
+                // the limit is enforced across all flows, not per flow
+                queue: CoDelQueue::with_params(target, interval, usize::MAX),
+                deficit: 0,
+                list: FlowList::None,
+            })
+            .collect();
+
+        FqCoDelQueue {
+            flows,
+            new_flows: VecDeque::new(),
+            old_flows: VecDeque::new(),
+            quantum: quantum.into(),
+            limit,
+            len: 0,
+        }
+    }
+
+    /// Returns the index of the flow queue that the packet belongs to.
+    fn flow_index(&self, packet: &PacketRc) -> usize {
+        // `DefaultHasher::new()` always uses the same keys, so this is
+        // deterministic
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        packet.src_address().hash(&mut hasher);
+        packet.dst_address().hash(&mut hasher);
+        (hasher.finish() % self.flows.len() as u64) as usize
+    }
+
+    /// Drop a packet from the head of the flow with the most bytes queued.
+    /// This is done when the limit is exceeded so that the flows that are
+    /// causing the most congestion are penalized.
+    fn drop_from_fattest_flow(&mut self) {
+        let fattest = self
+            .flows
+            .iter_mut()
+            .max_by_key(|flow| flow.queue.bytes_stored())
+            .unwrap();
+
+        if fattest.queue.drop_front().is_some() {
+            self.len -= 1;
+        }
+    }
+}
+
+impl RouterQueue for FqCoDelQueue {
+    fn push(&mut self, packet: PacketRc, now: EmulatedTime) {
+        let index = self.flow_index(&packet);
+        let flow = &mut self.flows[index];
+
+        flow.queue.push(packet, now);
+        self.len += 1;
+
+        if flow.list == FlowList::None {
+            flow.list = FlowList::New;
+            flow.deficit = self.quantum;
+            self.new_flows.push_back(index);
+        }
+
+        if self.len > self.limit {
+            self.drop_from_fattest_flow();
+        }
+    }
+
+    fn pop(&mut self, now: EmulatedTime) -> Option<PacketRc> {
+        loop {
+            let (index, from_new) = match self.new_flows.front() {
+                Some(index) => (*index, true),
+                None => (*self.old_flows.front()?, false),
+            };
+            let flow = &mut self.flows[index];
+
+            if flow.deficit <= 0 {
+                // the flow used up its quantum, so move it to the end of the
+                // old list for the next round
+                flow.deficit += self.quantum;
+                flow.list = FlowList::Old;
+                match from_new {
+                    true => self.new_flows.pop_front(),
+                    false => self.old_flows.pop_front(),
+                };
+                self.old_flows.push_back(index);
+                continue;
+            }
+
+            // CoDel may drop packets from the flow while looking for one to
+            // return
+            let len_before = flow.queue.len();
+            let packet = flow.queue.pop(now);
+            self.len -= len_before - flow.queue.len();
+
+            let Some(packet) = packet else {
+                // the flow is empty
+                match from_new {
+                    true => self.new_flows.pop_front(),
+                    false => self.old_flows.pop_front(),
+                };
+
+                // to prevent starving old flows, an empty new flow is moved to
+                // the old list rather than being removed
+                if from_new && !self.old_flows.is_empty() {
+                    flow.list = FlowList::Old;
+                    self.old_flows.push_back(index);
+                } else {
+                    flow.list = FlowList::None;
+                }
+                continue;
+            };
+
+            flow.deficit -= i64::try_from(packet.total_size()).unwrap();
+            return Some(packet);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::network::tests::mock_time_millis;
+
+    // Some of the tests here don't run in miri because they cause c::packet*
+    // functions to be called during the test.
+
+    const TARGET: SimulationTime = SimulationTime::from_duration(Duration::from_millis(10));
+    const INTERVAL: SimulationTime = SimulationTime::from_duration(Duration::from_millis(100));
+
+    fn packet(src_port: u16) -> PacketRc {
+        let mut packet = PacketRc::mock_new();
+        packet.set_udp(
+            SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), src_port),
+            SocketAddrV4::new(Ipv4Addr::new(5, 6, 7, 8), 80),
+        );
+        packet
+    }
+
+    /// Returns a list of source ports that all hash to different flows.
+    fn distinct_flow_ports(q: &FqCoDelQueue, n: usize) -> Vec<u16> {
+        let mut ports = Vec::new();
+        let mut flows = Vec::new();
+        for port in 1.. {
+            let index = q.flow_index(&packet(port));
+            if !flows.contains(&index) {
+                flows.push(index);
+                ports.push(port);
+            }
+            if ports.len() == n {
+                break;
+            }
+        }
+        ports
+    }
+
+    #[test]
+    fn empty() {
+        let now = mock_time_millis(1000);
+        let mut q = FqCoDelQueue::new(TARGET, INTERVAL, usize::MAX, 1024, 1514);
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn push_pop_simple() {
+        let now = mock_time_millis(1000);
+        let mut q = FqCoDelQueue::new(TARGET, INTERVAL, usize::MAX, 1024, 1514);
+
+        const N: usize = 10;
+        for i in 1..=N {
+            q.push(packet(i as u16), now);
+            assert_eq!(q.len(), i);
+        }
+        for i in 1..=N {
+            assert!(q.pop(now).is_some());
+            assert_eq!(q.len(), N - i);
+        }
+        assert!(q.is_empty());
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn same_flow_is_fifo() {
+        let now = mock_time_millis(1000);
+        let mut q = FqCoDelQueue::new(TARGET, INTERVAL, usize::MAX, 1024, 1514);
+
+        let packets: Vec<_> = (0..5).map(|_| packet(1000)).collect();
+        for p in &packets {
+            q.push(p.clone(), now);
+        }
+        for p in &packets {
+            assert_eq!(q.pop(now).as_ref(), Some(p));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn round_robin() {
+        let now = mock_time_millis(1000);
+        // a quantum of 1 byte allows each flow to send one packet per round
+        let mut q = FqCoDelQueue::new(TARGET, INTERVAL, usize::MAX, 1024, 1);
+
+        let ports = distinct_flow_ports(&q, 2);
+        let a: Vec<_> = (0..3).map(|_| packet(ports[0])).collect();
+        let b: Vec<_> = (0..3).map(|_| packet(ports[1])).collect();
+
+        // all of flow a's packets arrive before flow b's packets
+        for p in a.iter().chain(b.iter()) {
+            q.push(p.clone(), now);
+        }
+
+        // but the flows take turns
+        for i in 0..3 {
+            assert_eq!(q.pop(now).as_ref(), Some(&a[i]));
+            assert_eq!(q.pop(now).as_ref(), Some(&b[i]));
+        }
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn limit_drops_from_fattest_flow() {
+        let now = mock_time_millis(1000);
+        let mut q = FqCoDelQueue::new(TARGET, INTERVAL, 4, 1024, 1514);
+
+        let ports = distinct_flow_ports(&q, 2);
+        let small = packet(ports[0]);
+        q.push(small.clone(), now);
+        for _ in 0..5 {
+            q.push(packet(ports[1]), now);
+        }
+        assert_eq!(q.len(), 4);
+
+        // the packet from the small flow was not dropped
+        let mut popped = Vec::new();
+        while let Some(p) = q.pop(now) {
+            popped.push(p);
+        }
+        assert_eq!(popped.len(), 4);
+        assert!(popped.contains(&small));
+    }
+}
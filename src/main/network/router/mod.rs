@@ -1,15 +1,110 @@
 use std::cell::RefCell;
 use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
 
 use self::codel_queue::CoDelQueue;
+use self::drop_tail_queue::DropTailQueue;
+use self::fq_codel_queue::FqCoDelQueue;
+use self::red_queue::RedQueue;
+use crate::core::support::configuration::RouterQueueOptions;
+use crate::core::support::units::{self, Unit};
 use crate::core::worker::Worker;
 use crate::cshadow as c;
 use crate::network::packet::PacketRc;
 use crate::network::PacketDevice;
 use crate::utility::{Magic, ObjectCounter};
 mod codel_queue;
+mod drop_tail_queue;
+mod fq_codel_queue;
+mod red_queue;
 
 use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+use shadow_shim_helper_rs::simulation_time::SimulationTime;
+
+/// A packet queue that holds packets arriving at a router from the simulated
+/// network until the host is ready to receive them. Implementations may drop
+/// packets on `push()` or `pop()` according to their queue management policy.
+pub trait RouterQueue: Send {
+    /// Append a packet to the queue. Requires the current time as an argument
+    /// to avoid calling into the worker module internally.
+    fn push(&mut self, packet: PacketRc, now: EmulatedTime);
+
+    /// Returns the next packet in the queue, or None if there are no more
+    /// packets. Requires the current time as an argument to avoid calling into
+    /// the worker module internally.
+    fn pop(&mut self, now: EmulatedTime) -> Option<PacketRc>;
+
+    /// Returns the total number of packets stored in the queue.
+    fn len(&self) -> usize;
+
+    /// Returns true if the queue is holding zero packets, false otherwise.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Create a new router queue from the configuration options. The `rng` is only
+/// used to seed queues that make random drop decisions, so other queues won't
+/// change its state.
+pub fn new_router_queue(
+    options: &RouterQueueOptions,
+    rng: &mut Xoshiro256PlusPlus,
+) -> Box<dyn RouterQueue> {
+    let to_simtime = |x: units::Time<units::TimePrefix>| -> SimulationTime {
+        Duration::from(x).try_into().unwrap()
+    };
+    let to_limit = |x: Option<u32>| x.map(|x| x as usize).unwrap_or(usize::MAX);
+
+    match *options {
+        RouterQueueOptions::Codel {
+            target,
+            interval,
+            limit,
+        } => Box::new(CoDelQueue::with_params(
+            to_simtime(target),
+            to_simtime(interval),
+            to_limit(limit),
+        )),
+        RouterQueueOptions::FqCodel {
+            target,
+            interval,
+            limit,
+            flows,
+            quantum,
+        } => Box::new(FqCoDelQueue::new(
+            to_simtime(target),
+            to_simtime(interval),
+            to_limit(limit),
+            flows.try_into().unwrap(),
+            quantum
+                .convert(units::SiPrefixUpper::Base)
+                .unwrap()
+                .value()
+                .try_into()
+                .unwrap(),
+        )),
+        RouterQueueOptions::Red {
+            min_threshold,
+            max_threshold,
+            max_probability,
+            weight,
+            limit,
+        } => Box::new(RedQueue::new(
+            min_threshold.into(),
+            max_threshold.into(),
+            max_probability,
+            weight,
+            to_limit(limit),
+            Xoshiro256PlusPlus::seed_from_u64(rng.gen()),
+        )),
+        RouterQueueOptions::DropTail { limit } => {
+            Box::new(DropTailQueue::new(limit.try_into().unwrap()))
+        }
+    }
+}
 
 /// A router assists with moving packets between hosts across the simulated
 /// network.
@@ -18,19 +113,20 @@ pub struct Router {
     _counter: ObjectCounter,
     address: Ipv4Addr,
     /// Packets inbound to the host from the simulated network.
-    inbound_packets: RefCell<CoDelQueue>,
+    inbound_packets: RefCell<Box<dyn RouterQueue>>,
 }
 
 impl Router {
     /// Create a new router for a host that will help route packets between it
     /// and other hosts. The `address` must uniquely identify this router to the
-    /// host that owns it.
-    pub fn new(address: Ipv4Addr) -> Router {
+    /// host that owns it. Packets arriving from the simulated network are held
+    /// in `queue` until the host receives them.
+    pub fn new(address: Ipv4Addr, queue: Box<dyn RouterQueue>) -> Router {
         Router {
             magic: Magic::new(),
             address,
             _counter: ObjectCounter::new("Router"),
-            inbound_packets: RefCell::new(CoDelQueue::new()),
+            inbound_packets: RefCell::new(queue),
         }
     }
 
@@ -54,7 +150,7 @@ impl Router {
         unsafe { c::packet_unref(cpacket) };
     }
 
-    /// Routes the packet from the virtual internet into our CoDel queue, which
+    /// Routes the packet from the virtual internet into our router queue, which
     /// can then be received by the destiantion host by calling pop().
     pub fn route_incoming_packet(&self, packet: PacketRc) {
         self.push_inner(packet, Worker::current_time().unwrap())
@@ -67,7 +163,7 @@ impl PacketDevice for Router {
     }
 
     fn pop(&self) -> Option<PacketRc> {
-        // When the host calls pop, we provide the next packet from the CoDel queue.
+        // When the host calls pop, we provide the next packet from the router queue.
         self.pop_inner(Worker::current_time().unwrap())
     }
 
@@ -85,8 +181,8 @@ mod tests {
     #[test]
     fn empty() {
         let now = mock_time_millis(1000);
-        let router = Router::new(Ipv4Addr::UNSPECIFIED);
-        assert!(router.inbound_packets.borrow().peek().is_none());
+        let router = Router::new(Ipv4Addr::UNSPECIFIED, Box::new(CoDelQueue::new()));
+        assert!(router.inbound_packets.borrow().is_empty());
         assert!(router.pop_inner(now).is_none());
     }
 
@@ -95,20 +191,20 @@ mod tests {
     #[cfg_attr(miri, ignore)]
     fn push_pop_simple() {
         let now = mock_time_millis(1000);
-        let router = Router::new(Ipv4Addr::UNSPECIFIED);
+        let router = Router::new(Ipv4Addr::UNSPECIFIED, Box::new(CoDelQueue::new()));
 
         const N: usize = 10;
 
         for _ in 1..=N {
             router.push_inner(PacketRc::mock_new(), now);
-            assert!(router.inbound_packets.borrow().peek().is_some());
+            assert!(!router.inbound_packets.borrow().is_empty());
         }
         for _ in 1..=N {
-            assert!(router.inbound_packets.borrow().peek().is_some());
+            assert!(!router.inbound_packets.borrow().is_empty());
             assert!(router.pop_inner(now).is_some());
         }
 
-        assert!(router.inbound_packets.borrow().peek().is_none());
+        assert!(router.inbound_packets.borrow().is_empty());
         assert!(router.pop_inner(now).is_none());
     }
 }
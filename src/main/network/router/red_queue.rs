@@ -0,0 +1,237 @@
+//! An active queue management (AQM) algorithm implementing Random Early
+//! Detection (RED).
+//! <https://www.icir.org/floyd/papers/red/red.html>
+//!
+//!  More info:
+//!   - <https://en.wikipedia.org/wiki/Random_early_detection>
+//!   - <https://man7.org/linux/man-pages/man8/tc-red.8.html>
+
+use std::collections::VecDeque;
+
+use rand::Rng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use shadow_shim_helper_rs::emulated_time::EmulatedTime;
+
+use crate::network::packet::{PacketRc, PacketStatus};
+use crate::network::router::RouterQueue;
+
+/// A packet queue implementing the RED active queue management algorithm. RED
+/// keeps an exponentially weighted moving average of the queue length, and
+/// drops arriving packets with a probability that increases linearly from 0 at
+/// `min_threshold` to `max_probability` at `max_threshold`. All arriving
+/// packets are dropped when the average is at or above `max_threshold`.
+///
+/// The average is only updated when packets arrive. Unlike the original
+/// algorithm, we don't decay the average over idle periods since we don't know
+/// the link's transmission time; an arrival to an empty queue is treated as a
+/// single sample of length 0.
+pub struct RedQueue {
+    /// The packets stored in the queue.
+    elements: VecDeque<PacketRc>,
+    /// The average queue length below which no packets are dropped.
+    min_threshold: f64,
+    /// The average queue length at or above which all packets are dropped.
+    max_threshold: f64,
+    /// The drop probability when the average queue length reaches
+    /// `max_threshold`.
+    max_probability: f64,
+    /// The weight of new samples in the average queue length.
+    weight: f64,
+    /// The maximum number of packets we will store.
+    limit: usize,
+    /// The average queue length.
+    avg: f64,
+    /// The number of packets enqueued since the last drop while the average
+    /// was between the thresholds, or None if the average was below
+    /// `min_threshold`.
+    count: Option<usize>,
+    /// The source of randomness for drop decisions.
+    rng: Xoshiro256PlusPlus,
+}
+
+impl RedQueue {
+    /// Creates a new empty packet queue. `min_threshold` must be less than
+    /// `max_threshold`, and `max_probability` and `weight` must be in the range
+    /// (0, 1].
+    pub fn new(
+        min_threshold: f64,
+        max_threshold: f64,
+        max_probability: f64,
+        weight: f64,
+        limit: usize,
+        rng: Xoshiro256PlusPlus,
+    ) -> RedQueue {
+        debug_assert!(min_threshold < max_threshold);
+        debug_assert!(max_probability > 0.0 && max_probability <= 1.0);
+        debug_assert!(weight > 0.0 && weight <= 1.0);
+
+        RedQueue {
+            elements: VecDeque::new(),
+            min_threshold,
+            max_threshold,
+            max_probability,
+            weight,
+            limit,
+            avg: 0.0,
+            count: None,
+            rng,
+        }
+    }
+
+    /// Update the average queue length and return true if the arriving packet
+    /// should be dropped, false otherwise.
+    fn should_drop_arrival(&mut self) -> bool {
+        let len = self.elements.len() as f64;
+        self.avg = (1.0 - self.weight) * self.avg + self.weight * len;
+
+        if self.avg < self.min_threshold {
+            self.count = None;
+            return false;
+        }
+
+        if self.avg >= self.max_threshold {
+            self.count = Some(0);
+            return true;
+        }
+
+        let count = self.count.map_or(0, |x| x + 1);
+
+        // the drop probability increases linearly between the thresholds, and
+        // is spread out based on the number of packets since the last drop
+        let pb = self.max_probability * (self.avg - self.min_threshold)
+            / (self.max_threshold - self.min_threshold);
+        let denominator = 1.0 - count as f64 * pb;
+        let pa = if denominator <= 0.0 {
+            1.0
+        } else {
+            pb / denominator
+        };
+
+        if self.rng.gen::<f64>() < pa {
+            self.count = Some(0);
+            true
+        } else {
+            self.count = Some(count);
+            false
+        }
+    }
+}
+
+impl RouterQueue for RedQueue {
+    fn push(&mut self, mut packet: PacketRc, _now: EmulatedTime) {
+        // the average is updated even if the queue is full
+        let early_drop = self.should_drop_arrival();
+
+        if early_drop || self.elements.len() >= self.limit {
+            packet.add_status(PacketStatus::RouterDropped);
+        } else {
+            packet.add_status(PacketStatus::RouterEnqueued);
+            self.elements.push_back(packet);
+        }
+    }
+
+    fn pop(&mut self, _now: EmulatedTime) -> Option<PacketRc> {
+        self.elements.pop_front().map(|mut p| {
+            p.add_status(PacketStatus::RouterDequeued);
+            p
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.elements.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::network::tests::mock_time_millis;
+
+    // Some of the tests here don't run in miri because they cause c::packet*
+    // functions to be called during the test.
+
+    fn new_queue(min: f64, max: f64, weight: f64, limit: usize) -> RedQueue {
+        RedQueue::new(
+            min,
+            max,
+            0.1,
+            weight,
+            limit,
+            Xoshiro256PlusPlus::seed_from_u64(1),
+        )
+    }
+
+    #[test]
+    fn empty() {
+        let now = mock_time_millis(1000);
+        let mut q = new_queue(5.0, 15.0, 0.002, usize::MAX);
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn below_min_threshold() {
+        let now = mock_time_millis(1000);
+        let mut q = new_queue(5.0, 15.0, 0.002, usize::MAX);
+
+        // with a small weight the average grows slowly, so nothing is dropped
+        const N: usize = 100;
+        for _ in 0..N {
+            q.push(PacketRc::mock_new(), now);
+        }
+        assert!(q.avg < q.min_threshold);
+        assert_eq!(q.len(), N);
+
+        for _ in 0..N {
+            assert!(q.pop(now).is_some());
+        }
+        assert!(q.pop(now).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn above_max_threshold() {
+        let now = mock_time_millis(1000);
+        // a weight of 1 makes the average equal to the instantaneous length
+        let mut q = new_queue(2.0, 4.0, 1.0, usize::MAX);
+
+        for _ in 0..100 {
+            q.push(PacketRc::mock_new(), now);
+        }
+
+        // the queue can never grow beyond max_threshold
+        assert!(q.len() <= 4);
+        assert!(q.len() >= 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn between_thresholds() {
+        let now = mock_time_millis(1000);
+        let mut q = new_queue(2.0, 1000.0, 1.0, usize::MAX);
+
+        // some, but not all, packets are dropped between the thresholds
+        const N: usize = 500;
+        for _ in 0..N {
+            q.push(PacketRc::mock_new(), now);
+        }
+        assert!(q.len() > 2);
+        assert!(q.len() < N);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn limit() {
+        let now = mock_time_millis(1000);
+        let mut q = new_queue(50.0, 100.0, 0.002, 10);
+
+        for _ in 0..20 {
+            q.push(PacketRc::mock_new(), now);
+        }
+        assert_eq!(q.len(), 10);
+    }
+}
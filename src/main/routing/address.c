@@ -14,6 +14,7 @@
 #include <glib.h>
 #include <netinet/in.h>
 #include <stddef.h>
+#include <string.h>
 #include <sys/socket.h>
 
 #include "main/routing/address.h"
@@ -30,6 +31,13 @@ struct _Address {
     /* the host-order IP in dots-and-decimals format */
     gchar* ipString;
 
+    /* the IPv6 address in network-order, valid only if hasIPv6 is set */
+    struct in6_addr ipv6;
+    gboolean hasIPv6;
+
+    /* the IPv6 address in its canonical text format, or NULL */
+    gchar* ipv6String;
+
     /* the hostname */
     gchar* name;
 
@@ -67,6 +75,7 @@ static void _address_free(Address* address) {
     MAGIC_ASSERT(address);
 
     g_free(address->ipString);
+    g_free(address->ipv6String);
     g_free(address->name);
     g_free(address->idString);
 
@@ -129,6 +138,32 @@ const gchar* address_toHostName(const Address* address) {
     return address->name;
 }
 
+void address_setIPv6(Address* address, const uint8_t* ipv6) {
+    MAGIC_ASSERT(address);
+    utility_debugAssert(ipv6);
+
+    memcpy(&address->ipv6, ipv6, sizeof(address->ipv6));
+    address->hasIPv6 = TRUE;
+
+    g_free(address->ipv6String);
+    address->ipv6String = address_ipv6ToNewString(ipv6);
+}
+
+gboolean address_hasIPv6(const Address* address) {
+    MAGIC_ASSERT(address);
+    return address->hasIPv6;
+}
+
+const uint8_t* address_toNetworkIPv6(const Address* address) {
+    MAGIC_ASSERT(address);
+    return address->hasIPv6 ? address->ipv6.s6_addr : NULL;
+}
+
+const gchar* address_toIPv6String(const Address* address) {
+    MAGIC_ASSERT(address);
+    return address->ipv6String;
+}
+
 const gchar* address_toString(const Address* address) {
     MAGIC_ASSERT(address);
     return address->idString;
@@ -144,6 +179,15 @@ gchar* address_ipToNewString(in_addr_t ip) {
     return g_string_free(result, FALSE);
 }
 
+// Address must be in network byte order.
+gchar* address_ipv6ToNewString(const uint8_t* ipv6) {
+    gchar ipStringBuffer[INET6_ADDRSTRLEN + 1] = {0};
+    struct in6_addr addr;
+    memcpy(&addr, ipv6, sizeof(addr));
+    const gchar* ipString = inet_ntop(AF_INET6, &addr, ipStringBuffer, INET6_ADDRSTRLEN);
+    return g_strdup(ipString ? ipString : "NULL");
+}
+
 // Returned address will be in network byte order.
 in_addr_t address_stringToIP(const gchar* ipString) {
     struct in_addr inaddr;
@@ -9,6 +9,7 @@
 
 #include <glib.h>
 #include <netinet/in.h>
+#include <stdint.h>
 
 #include "lib/shadow-shim-helper-rs/shim_helper.h"
 #include "main/core/support/definitions.h"
@@ -87,12 +88,54 @@ guint32 address_toNetworkIP(const Address* address);
  */
 const gchar* address_toHostName(const Address* address);
 
+/**
+ * Assigns an IPv6 address to this address structure, in addition to its IPv4
+ * address. The host will then be reachable using either address.
+ * @param address a valid, non-NULL Address structure previously created
+ * with address_new()
+ * @param ipv6 the 16-byte IPv6 address in network order
+ */
+void address_setIPv6(Address* address, const uint8_t* ipv6);
+
+/**
+ * Checks if an IPv6 address was assigned with address_setIPv6().
+ * @param address a valid, non-NULL Address structure previously created
+ * with address_new()
+ * @return TRUE if the address has an IPv6 address, FALSE otherwise
+ */
+gboolean address_hasIPv6(const Address* address);
+
+/**
+ * Retrieve the IPv6 address assigned to this address, if any. The caller does
+ * not own and should not modify or free the returned memory.
+ * @param address a valid, non-NULL Address structure previously created
+ * with address_new()
+ * @return a pointer to the 16-byte network-order IPv6 address, or NULL if the
+ * address has no IPv6 address
+ */
+const uint8_t* address_toNetworkIPv6(const Address* address);
+
+/**
+ * Retrieves the string representation of the IPv6 address of this address.
+ * The caller does not own and should not modify or free the string.
+ * @param address a valid, non-NULL Address structure previously created
+ * with address_new()
+ * @return the string, or NULL if the address has no IPv6 address
+ */
+const gchar* address_toIPv6String(const Address* address);
+
 /**
  * Turns the IPv4 address into a newly allocated string that should be freed by the caller.
  * Address must be in network byte order.
  */
 gchar* address_ipToNewString(in_addr_t ip);
 
+/**
+ * Turns the 16-byte IPv6 address into a newly allocated string that should be freed by the caller.
+ * Address must be in network byte order.
+ */
+gchar* address_ipv6ToNewString(const uint8_t* ipv6);
+
 /* Returned address will be in network byte order. */
 in_addr_t address_stringToIP(const gchar* ipString);
 
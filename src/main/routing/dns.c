@@ -30,6 +30,8 @@ struct _DNS {
     /* address mappings */
     GHashTable* addressByIP;
     GHashTable* addressByName;
+    /* keyed by the IPv6 address string, for hosts that have an IPv6 address */
+    GHashTable* addressByIPv6;
 
     int hosts_file_fd;
 
@@ -157,6 +159,58 @@ Address* dns_register(DNS* dns, HostId id, const gchar* name, in_addr_t requeste
     return address;
 }
 
+/* Address must be in network byte order. */
+static gboolean _dns_isIPv6Restricted(const struct in6_addr* ipv6) {
+    /* https://en.wikipedia.org/wiki/Reserved_IP_addresses#IPv6 */
+    return IN6_IS_ADDR_UNSPECIFIED(ipv6) || IN6_IS_ADDR_LOOPBACK(ipv6) ||
+           IN6_IS_ADDR_MULTICAST(ipv6) || IN6_IS_ADDR_LINKLOCAL(ipv6) ||
+           IN6_IS_ADDR_SITELOCAL(ipv6) || IN6_IS_ADDR_V4MAPPED(ipv6) ||
+           IN6_IS_ADDR_V4COMPAT(ipv6);
+}
+
+/* Address must be in network byte order. */
+gboolean dns_registerIPv6(DNS* dns, Address* address, const uint8_t* requestedIPv6) {
+    MAGIC_ASSERT(dns);
+    utility_debugAssert(address);
+    utility_debugAssert(requestedIPv6);
+    utility_debugAssert(!address_isLocal(address));
+    utility_debugAssert(!address_hasIPv6(address));
+
+    struct in6_addr ipv6;
+    memcpy(&ipv6, requestedIPv6, sizeof(ipv6));
+
+    g_mutex_lock(&dns->lock);
+
+    gchar* ipStr = address_ipv6ToNewString(requestedIPv6);
+    gboolean isRestricted = _dns_isIPv6Restricted(&ipv6);
+    gboolean isUnique = !g_hash_table_contains(dns->addressByIPv6, ipStr);
+
+    if (isRestricted || !isUnique) {
+        warning("Invalid IPv6 %s (restricted: %s, unique: %s)", ipStr,
+                isRestricted ? "true" : "false", isUnique ? "true" : "false");
+        g_free(ipStr);
+        g_mutex_unlock(&dns->lock);
+        return FALSE;
+    }
+    g_free(ipStr);
+
+    address_setIPv6(address, requestedIPv6);
+
+    /* the key is owned by the address */
+    g_hash_table_replace(dns->addressByIPv6, (gchar*)address_toIPv6String(address), address);
+    address_ref(address);
+
+    /* Any existing hosts file needs to be (lazily) updated. */
+    if (dns->hosts_file_fd >= 0) {
+        close(dns->hosts_file_fd);
+        dns->hosts_file_fd = -1;
+    }
+
+    g_mutex_unlock(&dns->lock);
+
+    return TRUE;
+}
+
 void dns_deregister(DNS* dns, Address* address) {
     MAGIC_ASSERT(dns);
     if(!address_isLocal(address)) {
@@ -165,6 +219,9 @@ void dns_deregister(DNS* dns, Address* address) {
         /* these remove functions will call address_unref as necessary */
         g_hash_table_remove(dns->addressByIP, GUINT_TO_POINTER(address_toNetworkIP(address)));
         g_hash_table_remove(dns->addressByName, address_toHostName(address));
+        if (address_hasIPv6(address)) {
+            g_hash_table_remove(dns->addressByIPv6, address_toIPv6String(address));
+        }
 
         /* Any existing hosts file needs to be (lazily) updated. */
         if (dns->hosts_file_fd >= 0) {
@@ -188,6 +245,18 @@ Address* dns_resolveIPToAddress(DNS* dns, in_addr_t ip) {
     return result;
 }
 
+/* Address must be in network byte order. */
+Address* dns_resolveIPv6ToAddress(DNS* dns, const uint8_t* ipv6) {
+    MAGIC_ASSERT(dns);
+    gchar* ipStr = address_ipv6ToNewString(ipv6);
+    Address* result = g_hash_table_lookup(dns->addressByIPv6, ipStr);
+    if (!result) {
+        debug("address for '%s' does not yet exist", ipStr);
+    }
+    g_free(ipStr);
+    return result;
+}
+
 Address* dns_resolveNameToAddress(DNS* dns, const gchar* name) {
     MAGIC_ASSERT(dns);
     Address* result = g_hash_table_lookup(dns->addressByName, name);
@@ -202,6 +271,9 @@ static void _dns_writeHostLine(gpointer key, gpointer value, gpointer data) {
     const Address* address = value;
     GString* buf = data;
     g_string_append_printf(buf, "%s %s\n", address_toHostIPString(address), name);
+    if (address_hasIPv6(address)) {
+        g_string_append_printf(buf, "%s %s\n", address_toIPv6String(address), name);
+    }
 }
 
 static bool _dns_writeNewHostsFile(DNS* dns) {
@@ -215,7 +287,7 @@ static bool _dns_writeNewHostsFile(DNS* dns) {
         return false;
     }
 
-    GString* buf = g_string_new("127.0.0.1 localhost\n");
+    GString* buf = g_string_new("127.0.0.1 localhost\n::1 localhost\n");
     g_hash_table_foreach(dns->addressByName, _dns_writeHostLine, buf);
 
     trace("Hosts file string buffer is %zu bytes.", buf->len);
@@ -273,6 +345,8 @@ DNS* dns_new() {
 
     dns->addressByIP = g_hash_table_new_full(g_direct_hash, g_direct_equal, NULL, (GDestroyNotify) address_unref);
     dns->addressByName = g_hash_table_new_full(g_str_hash, g_str_equal, NULL, (GDestroyNotify) address_unref);
+    dns->addressByIPv6 =
+        g_hash_table_new_full(g_str_hash, g_str_equal, NULL, (GDestroyNotify)address_unref);
 
     /* 11.0.0.0 -- 100.0.0.0 is the longest available unrestricted range */
     dns->ipAddressCounter = ntohl(address_stringToIP("11.0.0.0"));
@@ -292,6 +366,7 @@ void dns_free(DNS* dns) {
 
     g_hash_table_destroy(dns->addressByIP);
     g_hash_table_destroy(dns->addressByName);
+    g_hash_table_destroy(dns->addressByIPv6);
 
     g_mutex_clear(&(dns->lock));
 
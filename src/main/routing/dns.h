@@ -9,6 +9,7 @@
 
 #include <glib.h>
 #include <netinet/in.h>
+#include <stdint.h>
 
 #include "lib/shadow-shim-helper-rs/shim_helper.h"
 #include "main/routing/address.h"
@@ -22,8 +23,16 @@ void dns_free(DNS* dns);
 Address* dns_register(DNS* dns, HostId id, const gchar* name, in_addr_t requestedIP);
 void dns_deregister(DNS* dns, Address* address);
 
+/* Assigns an IPv6 address to a previously registered (non-local) address so
+ * that it can also be resolved by its IPv6 address. Returns FALSE if the IPv6
+ * address is reserved or already in use. The 16-byte address must be in network
+ * byte order. */
+gboolean dns_registerIPv6(DNS* dns, Address* address, const uint8_t* requestedIPv6);
+
 /* Address must be in network byte order. */
 Address* dns_resolveIPToAddress(DNS* dns, in_addr_t ip);
+/* The 16-byte address must be in network byte order. */
+Address* dns_resolveIPv6ToAddress(DNS* dns, const uint8_t* ipv6);
 Address* dns_resolveNameToAddress(DNS* dns, const gchar* name);
 
 /* Returns a string path to a file containing (ip,name) information for all
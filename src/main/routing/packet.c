@@ -6,6 +6,7 @@
 
 #include <assert.h>
 #include <netinet/in.h>
+#include <netinet/ip_icmp.h>
 #include <stddef.h>
 #include <stdint.h>
 
@@ -74,6 +75,19 @@ struct _Packet {
     gpointer header;
     Payload* payload;
 
+    /* the packet was sent over IPv6. the header addresses are still the IPv4
+     * addresses that hosts are routed by, but the packet has a larger IP header
+     * and is shown with the hosts' IPv6 addresses. */
+    gboolean isIPv6;
+
+    /* the packet was corrupted in the network. a bit of its payload was
+     * flipped, and the receiver treats it as if it failed checksum validation. */
+    gboolean isCorrupted;
+
+    /* the IP time-to-live, which is decremented by each router that forwards
+     * the packet */
+    guint8 ttl;
+
     /* tracks application priority so we flush packets from the interface to
      * the wire in the order intended by the application. this is used in
      * the default FIFO network interface scheduling discipline.
@@ -93,6 +107,7 @@ const gchar* protocol_toString(ProtocolType type) {
         case PUDP: return "UDP";
         case PTCP: return "TCP";
         case PMOCK: return "MOCK";
+        case PICMP: return "ICMP";
         default: return "UNKNOWN";
     }
 }
@@ -108,6 +123,7 @@ Packet* packet_new_inner(guint hostID, guint64 packetID) {
     packet->packetID = packetID;
 
     packet->orderedStatus = g_queue_new();
+    packet->ttl = CONFIG_IP_DEFAULT_TTL;
 
     return packet;
 }
@@ -194,6 +210,9 @@ Packet* packet_copy(Packet* packet) {
     }
 
     copy->protocol = packet->protocol;
+    copy->isIPv6 = packet->isIPv6;
+    copy->isCorrupted = packet->isCorrupted;
+    copy->ttl = packet->ttl;
     if(packet->header) {
         switch (packet->protocol) {
             case PLOCAL: {
@@ -206,6 +225,11 @@ Packet* packet_copy(Packet* packet) {
                 break;
             }
 
+            case PICMP: {
+                copy->header = compat_static_g_memdup(packet->header, sizeof(PacketICMPHeader));
+                break;
+            }
+
             case PTCP: {
                 copy->header = compat_static_g_memdup(packet->header, sizeof(PacketTCPHeader));
 
@@ -358,6 +382,26 @@ void packet_setTCP(Packet* packet, enum ProtocolTCPFlags flags,
     packet->protocol = PTCP;
 }
 
+// The addresses must be in network byte order.
+void packet_setICMP(Packet* packet, in_addr_t sourceIP, in_addr_t destinationIP, guint8 type,
+                    guint8 code, guint16 identifier, guint16 sequence) {
+    MAGIC_ASSERT(packet);
+    utility_debugAssert(!(packet->header) && packet->protocol == PNONE);
+    utility_debugAssert(sourceIP && destinationIP);
+
+    PacketICMPHeader* header = g_new0(PacketICMPHeader, 1);
+
+    header->sourceIP = sourceIP;
+    header->destinationIP = destinationIP;
+    header->type = type;
+    header->code = code;
+    header->identifier = identifier;
+    header->sequence = sequence;
+
+    packet->header = header;
+    packet->protocol = PICMP;
+}
+
 void packet_updateTCP(Packet* packet, guint acknowledgement, GList* selectiveACKs, guint window,
                       CSimulationTime timestampValue, CSimulationTime timestampEcho) {
     MAGIC_ASSERT(packet);
@@ -401,12 +445,65 @@ gsize packet_getPayloadSize(const Packet* packet) {
 
 gsize packet_getHeaderSize(const Packet* packet) {
     MAGIC_ASSERT(packet);
-    gsize size = packet->protocol == PUDP   ? CONFIG_HEADER_SIZE_UDPIP
-                 : packet->protocol == PTCP ? CONFIG_HEADER_SIZE_TCPIP
-                                            : 0;
+    gsize size = packet->protocol == PUDP    ? CONFIG_HEADER_SIZE_UDPIP
+                 : packet->protocol == PTCP  ? CONFIG_HEADER_SIZE_TCPIP
+                 : packet->protocol == PICMP ? CONFIG_HEADER_SIZE_ICMPIP
+                                             : 0;
+    if (size > 0 && packet->isIPv6) {
+        size += CONFIG_HEADER_SIZE_IPV6 - CONFIG_HEADER_SIZE_IP;
+    }
     return size;
 }
 
+void packet_setIPv6(Packet* packet, bool isIPv6) {
+    MAGIC_ASSERT(packet);
+    packet->isIPv6 = isIPv6;
+}
+
+bool packet_isIPv6(const Packet* packet) {
+    MAGIC_ASSERT(packet);
+    return packet->isIPv6;
+}
+
+void packet_setCorrupted(Packet* packet, gsize bitOffset) {
+    MAGIC_ASSERT(packet);
+    utility_alwaysAssert(packet->payload);
+
+    gsize length = payload_getLength(packet->payload);
+    utility_alwaysAssert(bitOffset / 8 < length);
+
+    /* the payload may be shared with other copies of the packet (for example
+     * the sender's copy that it may retransmit), so we flip the bit in a new
+     * copy of the payload */
+    guint8* data = g_malloc(length);
+    gsize copied = payload_getDataShadow(packet->payload, 0, data, length);
+    utility_alwaysAssert(copied == length);
+    data[bitOffset / 8] ^= (guint8)(1 << (bitOffset % 8));
+
+    Payload* corrupted = payload_newFromShadow(data, length);
+    utility_alwaysAssert(corrupted != NULL);
+    g_free(data);
+
+    payload_unref(packet->payload);
+    packet->payload = corrupted;
+    packet->isCorrupted = TRUE;
+}
+
+bool packet_isCorrupted(const Packet* packet) {
+    MAGIC_ASSERT(packet);
+    return packet->isCorrupted;
+}
+
+void packet_setTTL(Packet* packet, guint8 ttl) {
+    MAGIC_ASSERT(packet);
+    packet->ttl = ttl;
+}
+
+guint8 packet_getTTL(const Packet* packet) {
+    MAGIC_ASSERT(packet);
+    return packet->ttl;
+}
+
 uint64_t packet_getPriority(const Packet* packet) {
     MAGIC_ASSERT(packet);
     return packet->priority;
@@ -435,6 +532,12 @@ in_addr_t packet_getDestinationIP(const Packet* packet) {
             break;
         }
 
+        case PICMP: {
+            PacketICMPHeader* header = packet->header;
+            ip = header->destinationIP;
+            break;
+        }
+
         default: {
             utility_panic("unrecognized protocol");
             break;
@@ -469,6 +572,13 @@ in_port_t packet_getDestinationPort(const Packet* packet) {
             break;
         }
 
+        case PICMP: {
+            /* echo replies are delivered to the ping socket bound to the echo identifier */
+            PacketICMPHeader* header = packet->header;
+            port = header->type == ICMP_ECHOREPLY ? htons(header->identifier) : 0;
+            break;
+        }
+
         default: {
             utility_panic("unrecognized protocol");
             break;
@@ -502,6 +612,12 @@ in_addr_t packet_getSourceIP(const Packet* packet) {
             break;
         }
 
+        case PICMP: {
+            PacketICMPHeader* header = packet->header;
+            ip = header->sourceIP;
+            break;
+        }
+
         default: {
             utility_panic("unrecognized protocol");
             break;
@@ -536,6 +652,13 @@ in_port_t packet_getSourcePort(const Packet* packet) {
             break;
         }
 
+        case PICMP: {
+            /* echo requests are sent from the ping socket bound to the echo identifier */
+            PacketICMPHeader* header = packet->header;
+            port = header->type == ICMP_ECHO ? htons(header->identifier) : 0;
+            break;
+        }
+
         default: {
             utility_panic("unrecognized protocol");
             break;
@@ -550,6 +673,16 @@ ProtocolType packet_getProtocol(const Packet* packet) {
     return packet->protocol;
 }
 
+guint packet_getHostID(const Packet* packet) {
+    MAGIC_ASSERT(packet);
+    return packet->hostID;
+}
+
+guint64 packet_getPacketID(const Packet* packet) {
+    MAGIC_ASSERT(packet);
+    return packet->packetID;
+}
+
 /* If modifying this function, you should also modify `packet_copyPayloadWithMemoryManager` below.
  */
 gssize packet_copyPayload(const Packet* packet, const Thread* thread, gsize payloadOffset,
@@ -606,12 +739,58 @@ GList* packet_copyTCPSelectiveACKs(Packet* packet) {
     return selectiveACKsCopy;
 }
 
+void packet_setTCPSelectiveACKs(Packet* packet, const guint* selectiveACKs, gsize len) {
+    MAGIC_ASSERT(packet);
+    utility_debugAssert(packet->protocol == PTCP);
+
+    PacketTCPHeader* header = (PacketTCPHeader*)packet->header;
+
+    if (header->selectiveACKs != NULL) {
+        g_list_free(header->selectiveACKs);
+        header->selectiveACKs = NULL;
+    }
+
+    for (gsize i = 0; i < len; i++) {
+        header->selectiveACKs =
+            g_list_append(header->selectiveACKs, GUINT_TO_POINTER(selectiveACKs[i]));
+    }
+
+    if (len > 0) {
+        header->flags |= PTCP_SACK;
+    } else {
+        header->flags &= ~PTCP_SACK;
+    }
+}
+
+gsize packet_getTCPSelectiveACKs(const Packet* packet, guint* selectiveACKs, gsize len) {
+    MAGIC_ASSERT(packet);
+    utility_debugAssert(packet->protocol == PTCP);
+
+    const PacketTCPHeader* header = (const PacketTCPHeader*)packet->header;
+
+    gsize count = 0;
+    for (GList* iter = header->selectiveACKs; iter; iter = g_list_next(iter)) {
+        if (count < len) {
+            selectiveACKs[count] = GPOINTER_TO_UINT(iter->data);
+        }
+        count++;
+    }
+
+    return count;
+}
+
 PacketTCPHeader* packet_getTCPHeader(const Packet* packet) {
     MAGIC_ASSERT(packet);
     utility_debugAssert(packet->protocol == PTCP);
     return (PacketTCPHeader*)packet->header;
 }
 
+PacketICMPHeader* packet_getICMPHeader(const Packet* packet) {
+    MAGIC_ASSERT(packet);
+    utility_debugAssert(packet->protocol == PICMP);
+    return (PacketICMPHeader*)packet->header;
+}
+
 static const gchar* _packet_deliveryStatusToAscii(PacketDeliveryStatusFlags status) {
     switch (status) {
         case PDS_NONE: return "NONE";
@@ -637,6 +816,9 @@ static const gchar* _packet_deliveryStatusToAscii(PacketDeliveryStatusFlags stat
         case PDS_DESTROYED: return "PDS_DESTROYED";
         case PDS_RELAY_CACHED: return "RELAY_CACHED";
         case PDS_RELAY_FORWARDED: return "RELAY_FORWARDED";
+        case PDS_INET_REORDERED: return "INET_REORDERED";
+        case PDS_INET_DUPLICATED: return "INET_DUPLICATED";
+        case PDS_INET_CORRUPTED: return "INET_CORRUPTED";
         default: return "UKNOWN";
     }
 }
@@ -647,6 +829,9 @@ gchar* packet_toString(Packet* packet) {
 
     g_string_append_printf(packetString, "packetID=%u:%"G_GUINT64_FORMAT" ",
             packet->hostID, packet->packetID);
+    if (packet->isIPv6) {
+        g_string_append_printf(packetString, "ipv6 ");
+    }
 
     guint payloadLength = (packet->payload) ? (guint)payload_getLength(packet->payload) : 0;
 
@@ -740,6 +925,21 @@ gchar* packet_toString(Packet* packet) {
             break;
         }
 
+        case PICMP: {
+            PacketICMPHeader* header = packet->header;
+            gchar* sourceIPString = address_ipToNewString(header->sourceIP);
+            gchar* destinationIPString = address_ipToNewString(header->destinationIP);
+
+            g_string_append_printf(packetString, "%s -> %s type=%u code=%u id=%u seq=%u bytes=%u",
+                                   sourceIPString, destinationIPString, header->type,
+                                   header->code, header->identifier, header->sequence,
+                                   payloadLength);
+
+            g_free(sourceIPString);
+            g_free(destinationIPString);
+            break;
+        }
+
         case PMOCK: {
             // TODO: We should panic here if this isn't a test.  We don't have a
             // good way to check whether this is being run inside a test in C.
@@ -782,6 +982,8 @@ void packet_addDeliveryStatus(Packet* packet, PacketDeliveryStatusFlags status)
 
     packet->allStatus |= status;
 
+    packet_traceDeliveryStatus(packet, status);
+
     if (logger_isEnabled(logger_getDefault(), LOGLEVEL_TRACE)) {
         g_queue_push_tail(packet->orderedStatus, GUINT_TO_POINTER(status));
         gchar* packetStr = packet_toString(packet);
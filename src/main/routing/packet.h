@@ -9,6 +9,7 @@
 
 #include <glib.h>
 #include <netinet/in.h>
+#include <stdbool.h>
 
 #include "main/routing/packet.minimal.h"
 
@@ -39,6 +40,22 @@ struct _PacketTCPHeader {
     CSimulationTime timestampEcho;
 };
 
+typedef struct _PacketICMPHeader PacketICMPHeader;
+struct _PacketICMPHeader {
+    // address is in network byte order
+    in_addr_t sourceIP;
+    // address is in network byte order
+    in_addr_t destinationIP;
+
+    guint8 type;
+    guint8 code;
+
+    // the remaining 4 bytes of the ICMP header, which hold the identifier and
+    // sequence number of echo messages and are unused by most error messages
+    guint16 identifier;
+    guint16 sequence;
+};
+
 const gchar* protocol_toString(ProtocolType type);
 
 Packet* packet_new(const Host* host);
@@ -77,6 +94,27 @@ void packet_setTCP(Packet* packet, enum ProtocolTCPFlags flags,
         in_addr_t sourceIP, in_port_t sourcePort,
         in_addr_t destinationIP, in_port_t destinationPort, guint sequence);
 
+// The addresses must be in network byte order. The identifier and sequence are
+// in host byte order.
+void packet_setICMP(Packet* packet, in_addr_t sourceIP, in_addr_t destinationIP, guint8 type,
+                    guint8 code, guint16 identifier, guint16 sequence);
+
+// Mark the packet as being sent over IPv6 rather than IPv4. The packet is
+// still routed using its IPv4 header addresses.
+void packet_setIPv6(Packet* packet, bool isIPv6);
+bool packet_isIPv6(const Packet* packet);
+
+// Mark the packet as corrupted by the network, flipping the bit at `bitOffset`
+// in its payload. The packet must have a payload containing that bit. Receivers
+// drop the packet as if it had failed checksum validation.
+void packet_setCorrupted(Packet* packet, gsize bitOffset);
+bool packet_isCorrupted(const Packet* packet);
+
+// The IP time-to-live (or IPv6 hop limit) of the packet. New packets start with
+// CONFIG_IP_DEFAULT_TTL.
+void packet_setTTL(Packet* packet, guint8 ttl);
+guint8 packet_getTTL(const Packet* packet);
+
 void packet_updateTCP(Packet* packet, guint acknowledgement, GList* selectiveACKs, guint window,
                       CSimulationTime timestampValue, CSimulationTime timestampEcho);
 
@@ -96,6 +134,10 @@ in_port_t packet_getSourcePort(const Packet* packet);
 
 ProtocolType packet_getProtocol(const Packet* packet);
 
+// The id of the host that created the packet, and the id of the packet on that host.
+guint packet_getHostID(const Packet* packet);
+guint64 packet_getPacketID(const Packet* packet);
+
 gssize packet_copyPayload(const Packet* packet, const Thread* thread, gsize payloadOffset,
                           UntypedForeignPtr buffer, gsize bufferLength);
 gssize packet_copyPayloadWithMemoryManager(const Packet* packet, gsize payloadOffset,
@@ -104,7 +146,15 @@ gssize packet_copyPayloadWithMemoryManager(const Packet* packet, gsize payloadOf
 guint packet_copyPayloadShadow(const Packet* packet, gsize payloadOffset, void* buffer,
                                gsize bufferLength);
 GList* packet_copyTCPSelectiveACKs(Packet* packet);
+// Replace the selective acknowledgements in the TCP header with the `len` values in
+// `selectiveACKs`. The legacy TCP stores individual packet sequence numbers, while the Rust TCP
+// stores the start and end sequence numbers of each SACK block.
+void packet_setTCPSelectiveACKs(Packet* packet, const guint* selectiveACKs, gsize len);
+// Copy up to `len` of the TCP header's selective acknowledgements to `selectiveACKs`. Returns the
+// total number of selective acknowledgements in the header.
+gsize packet_getTCPSelectiveACKs(const Packet* packet, guint* selectiveACKs, gsize len);
 PacketTCPHeader* packet_getTCPHeader(const Packet* packet);
+PacketICMPHeader* packet_getICMPHeader(const Packet* packet);
 gint packet_compareTCPSequence(Packet* packet1, Packet* packet2, gpointer user_data);
 
 void packet_addDeliveryStatus(Packet* packet, PacketDeliveryStatusFlags status);
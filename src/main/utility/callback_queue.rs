@@ -131,12 +131,45 @@ impl<T: Clone + Copy + 'static> EventSource<T> {
         self.inner.borrow_mut().add_listener(inner_ref, notify_fn)
     }
 
+    /// Add an exclusive listener. When notified, exclusive listeners are called one at a time in
+    /// the order they were added, after all non-exclusive listeners. No further exclusive listeners
+    /// are called once one returns `true`.
+    pub fn add_exclusive_listener(
+        &mut self,
+        notify_fn: impl Fn(T, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<T> {
+        let inner_ref = Arc::downgrade(&Arc::clone(&self.inner));
+        self.inner
+            .borrow_mut()
+            .add_exclusive_listener(inner_ref, notify_fn)
+    }
+
     /// Notify all listeners.
     pub fn notify_listeners(&mut self, message: T, cb_queue: &mut CallbackQueue) {
-        for (_, l) in &self.inner.borrow().listeners {
+        let inner = self.inner.borrow();
+
+        for (_, l) in &inner.listeners {
             let l_clone = l.clone();
             cb_queue.add(move |cb_queue| (l_clone)(message, cb_queue));
         }
+
+        if !inner.exclusive_listeners.is_empty() {
+            let listeners: Vec<_> = inner
+                .exclusive_listeners
+                .iter()
+                .map(|(_, l)| l.clone())
+                .collect();
+
+            // call them from a single event so that we can stop after the first listener that
+            // accepts the message
+            cb_queue.add(move |cb_queue| {
+                for l in listeners {
+                    if (l)(message, cb_queue) {
+                        break;
+                    }
+                }
+            });
+        }
     }
 }
 
@@ -147,9 +180,11 @@ impl<T: Clone + Copy + 'static> Default for EventSource<T> {
 }
 
 type Listener<T> = Arc<dyn Fn(T, &mut CallbackQueue) + Send + Sync>;
+type ExclusiveListener<T> = Arc<dyn Fn(T, &mut CallbackQueue) -> bool + Send + Sync>;
 
 struct EventSourceInner<T> {
     listeners: std::vec::Vec<(HandleId, Listener<T>)>,
+    exclusive_listeners: std::vec::Vec<(HandleId, ExclusiveListener<T>)>,
     next_id: std::num::Wrapping<u32>,
 }
 
@@ -157,6 +192,7 @@ impl<T> EventSourceInner<T> {
     pub fn new() -> Self {
         Self {
             listeners: std::vec::Vec::new(),
+            exclusive_listeners: std::vec::Vec::new(),
             next_id: std::num::Wrapping(0),
         }
     }
@@ -168,7 +204,9 @@ impl<T> EventSourceInner<T> {
             let id = HandleId(self.next_id.0);
             self.next_id += std::num::Wrapping(1);
 
-            if !self.listeners.iter().any(|x| x.0 == id) {
+            if !self.listeners.iter().any(|x| x.0 == id)
+                && !self.exclusive_listeners.iter().any(|x| x.0 == id)
+            {
                 break id;
             }
         }
@@ -186,9 +224,31 @@ impl<T> EventSourceInner<T> {
         Handle::new(handle_id, inner)
     }
 
+    pub fn add_exclusive_listener(
+        &mut self,
+        inner: std::sync::Weak<AtomicRefCell<Self>>,
+        notify_fn: impl Fn(T, &mut CallbackQueue) -> bool + Send + Sync + 'static,
+    ) -> Handle<T> {
+        let handle_id = self.get_unused_id();
+
+        self.exclusive_listeners
+            .push((handle_id, Arc::new(notify_fn)));
+
+        Handle::new(handle_id, inner)
+    }
+
     pub fn remove_listener(&mut self, id: HandleId) {
-        self.listeners
-            .remove(self.listeners.iter().position(|x| x.0 == id).unwrap());
+        if let Some(pos) = self.listeners.iter().position(|x| x.0 == id) {
+            self.listeners.remove(pos);
+            return;
+        }
+
+        self.exclusive_listeners.remove(
+            self.exclusive_listeners
+                .iter()
+                .position(|x| x.0 == id)
+                .unwrap(),
+        );
     }
 }
 
@@ -217,4 +277,47 @@ mod tests {
 
         assert_eq!(*counter.borrow(), 4);
     }
+
+    #[test]
+    fn test_exclusive_listeners() {
+        let calls = Arc::new(AtomicRefCell::new(Vec::new()));
+        let mut source = EventSource::new();
+
+        let calls_clone = Arc::clone(&calls);
+        let _handle_1 = source.add_exclusive_listener(move |accept, _| {
+            calls_clone.borrow_mut().push(1);
+            accept == 1
+        });
+
+        let calls_clone = Arc::clone(&calls);
+        let _handle_2 = source.add_exclusive_listener(move |_, _| {
+            calls_clone.borrow_mut().push(2);
+            true
+        });
+
+        let calls_clone = Arc::clone(&calls);
+        let handle_3 = source.add_exclusive_listener(move |_, _| {
+            calls_clone.borrow_mut().push(3);
+            true
+        });
+
+        let calls_clone = Arc::clone(&calls);
+        let _handle_4 = source.add_listener(move |_, _| {
+            calls_clone.borrow_mut().push(4);
+        });
+
+        // the first exclusive listener accepts, so the others aren't called
+        CallbackQueue::queue_and_run(|queue| source.notify_listeners(1, queue));
+        assert_eq!(*calls.borrow(), [4, 1]);
+        calls.borrow_mut().clear();
+
+        // the first exclusive listener declines, so the second is called
+        CallbackQueue::queue_and_run(|queue| source.notify_listeners(0, queue));
+        assert_eq!(*calls.borrow(), [4, 1, 2]);
+        calls.borrow_mut().clear();
+
+        handle_3.stop_listening();
+        CallbackQueue::queue_and_run(|queue| source.notify_listeners(0, queue));
+        assert_eq!(*calls.borrow(), [4, 1, 2]);
+    }
 }
@@ -259,6 +259,15 @@ impl From<std::net::SocketAddrV6> for SockaddrStorage {
     }
 }
 
+impl From<std::net::SocketAddr> for SockaddrStorage {
+    fn from(addr: std::net::SocketAddr) -> Self {
+        match addr {
+            std::net::SocketAddr::V4(addr) => addr.into(),
+            std::net::SocketAddr::V6(addr) => addr.into(),
+        }
+    }
+}
+
 /// A Unix socket address. Typically will be used as an owned address
 /// `SockaddrUnix<libc::sockaddr_un>` or a borrowed address `SockaddrUnix<&libc::sockaddr_un>`, and
 /// you can convert between them using methods such as [`as_ref`](Self::as_ref) or
@@ -329,6 +329,262 @@ fn test_ctl_invalid_op() -> anyhow::Result<()> {
     })
 }
 
+fn epoll_add(epoll_fd: i32, fd: i32, flags: EpollFlags, data: u64) -> nix::Result<()> {
+    let mut event = epoll::EpollEvent::new(flags, data);
+    epoll::epoll_ctl(epoll_fd, epoll::EpollOp::EpollCtlAdd, fd, Some(&mut event))
+}
+
+fn epoll_wait_now(epoll_fd: i32) -> nix::Result<Vec<epoll::EpollEvent>> {
+    let mut events = vec![epoll::EpollEvent::empty(); 10];
+    let count = epoll::epoll_wait(epoll_fd, &mut events, 0)?;
+    events.truncate(count);
+    Ok(events)
+}
+
+fn test_ctl_errors() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+    let epoll_fd = epoll::epoll_create()?;
+    let file_fd = nix::fcntl::open(
+        "/dev/null",
+        nix::fcntl::OFlag::O_RDONLY,
+        nix::sys::stat::Mode::empty(),
+    )?;
+
+    test_utils::run_and_close_fds(&[epoll_fd, read_fd, write_fd, file_fd], || {
+        // an epoll can't monitor itself
+        assert_eq!(
+            epoll_add(epoll_fd, epoll_fd, EpollFlags::EPOLLIN, 0),
+            Err(Errno::EINVAL)
+        );
+
+        // modifying or deleting a file that isn't being monitored
+        let mut event = epoll::EpollEvent::new(EpollFlags::EPOLLIN, 0);
+        assert_eq!(
+            epoll::epoll_ctl(
+                epoll_fd,
+                epoll::EpollOp::EpollCtlMod,
+                read_fd,
+                Some(&mut event)
+            ),
+            Err(Errno::ENOENT)
+        );
+        assert_eq!(
+            epoll::epoll_ctl(epoll_fd, epoll::EpollOp::EpollCtlDel, read_fd, None),
+            Err(Errno::ENOENT)
+        );
+
+        // adding the same file twice
+        epoll_add(epoll_fd, read_fd, EpollFlags::EPOLLIN, 0)?;
+        assert_eq!(
+            epoll_add(epoll_fd, read_fd, EpollFlags::EPOLLIN, 0),
+            Err(Errno::EEXIST)
+        );
+
+        // regular files don't support epoll
+        assert_eq!(
+            epoll_add(epoll_fd, file_fd, EpollFlags::EPOLLIN, 0),
+            Err(Errno::EPERM)
+        );
+
+        // the epoll fd must be an epoll
+        assert_eq!(
+            epoll_add(read_fd, write_fd, EpollFlags::EPOLLOUT, 0),
+            Err(Errno::EINVAL)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_oneshot() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+    let epoll_fd = epoll::epoll_create()?;
+
+    test_utils::run_and_close_fds(&[epoll_fd, read_fd, write_fd], || {
+        epoll_add(
+            epoll_fd,
+            read_fd,
+            EpollFlags::EPOLLIN | EpollFlags::EPOLLONESHOT,
+            0,
+        )?;
+
+        unistd::write(write_fd, &[0])?;
+
+        // the event should only be reported once
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 1);
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 0);
+
+        // modifying the entry should re-arm it
+        let mut event = epoll::EpollEvent::new(EpollFlags::EPOLLIN | EpollFlags::EPOLLONESHOT, 0);
+        epoll::epoll_ctl(
+            epoll_fd,
+            epoll::EpollOp::EpollCtlMod,
+            read_fd,
+            Some(&mut event),
+        )?;
+
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 1);
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 0);
+
+        Ok(())
+    })
+}
+
+fn test_edge_ready_on_add() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+    let epoll_fd = epoll::epoll_create()?;
+
+    test_utils::run_and_close_fds(&[epoll_fd, read_fd, write_fd], || {
+        unistd::write(write_fd, &[0])?;
+
+        // a file that is already readable should be reported once after it's added
+        epoll_add(
+            epoll_fd,
+            read_fd,
+            EpollFlags::EPOLLIN | EpollFlags::EPOLLET,
+            0,
+        )?;
+
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 1);
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 0);
+
+        Ok(())
+    })
+}
+
+fn test_pipe_hup() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+    let epoll_fd = epoll::epoll_create()?;
+
+    test_utils::run_and_close_fds(&[epoll_fd, read_fd], || {
+        // EPOLLHUP is always reported, even if not requested
+        epoll_add(epoll_fd, read_fd, EpollFlags::EPOLLIN, 0)?;
+
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 0);
+
+        unistd::close(write_fd)?;
+
+        let events = epoll_wait_now(epoll_fd)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].events(), EpollFlags::EPOLLHUP);
+
+        Ok(())
+    })
+}
+
+fn test_socket_rdhup() -> anyhow::Result<()> {
+    let (fd_1, fd_2) = nix::sys::socket::socketpair(
+        nix::sys::socket::AddressFamily::Unix,
+        nix::sys::socket::SockType::Stream,
+        None,
+        nix::sys::socket::SockFlag::empty(),
+    )?;
+    let epoll_fd = epoll::epoll_create()?;
+
+    test_utils::run_and_close_fds(&[epoll_fd, fd_1, fd_2], || {
+        epoll_add(
+            epoll_fd,
+            fd_1,
+            EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP,
+            0,
+        )?;
+
+        assert_eq!(epoll_wait_now(epoll_fd)?.len(), 0);
+
+        nix::sys::socket::shutdown(fd_2, nix::sys::socket::Shutdown::Write)?;
+
+        // the peer has shut down writing, so we can read an EOF
+        let events = epoll_wait_now(epoll_fd)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].events(),
+            EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP
+        );
+
+        Ok(())
+    })
+}
+
+fn test_nested() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+    let outer_fd = epoll::epoll_create()?;
+    let inner_fd = epoll::epoll_create()?;
+
+    test_utils::run_and_close_fds(&[outer_fd, inner_fd, read_fd, write_fd], || {
+        epoll_add(inner_fd, read_fd, EpollFlags::EPOLLIN, 0)?;
+        epoll_add(outer_fd, inner_fd, EpollFlags::EPOLLIN, 1)?;
+
+        assert_eq!(epoll_wait_now(outer_fd)?.len(), 0);
+
+        unistd::write(write_fd, &[0])?;
+
+        // the inner epoll is readable when it has events
+        let events = epoll_wait_now(outer_fd)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].events(), EpollFlags::EPOLLIN);
+        assert_eq!(events[0].data(), 1);
+
+        // adding the outer epoll to the inner epoll would create a loop
+        assert_eq!(
+            epoll_add(inner_fd, outer_fd, EpollFlags::EPOLLIN, 0),
+            Err(Errno::ELOOP)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_exclusive_errors() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+    let epoll_fd = epoll::epoll_create()?;
+    let other_epoll_fd = epoll::epoll_create()?;
+
+    test_utils::run_and_close_fds(&[epoll_fd, other_epoll_fd, read_fd, write_fd], || {
+        epoll_add(
+            epoll_fd,
+            read_fd,
+            EpollFlags::EPOLLIN | EpollFlags::EPOLLEXCLUSIVE,
+            0,
+        )?;
+
+        // exclusive entries can't be modified
+        let mut event = epoll::EpollEvent::new(EpollFlags::EPOLLIN, 0);
+        assert_eq!(
+            epoll::epoll_ctl(
+                epoll_fd,
+                epoll::EpollOp::EpollCtlMod,
+                read_fd,
+                Some(&mut event)
+            ),
+            Err(Errno::EINVAL)
+        );
+
+        // exclusive entries can't be used with an epoll target
+        assert_eq!(
+            epoll_add(
+                epoll_fd,
+                other_epoll_fd,
+                EpollFlags::EPOLLIN | EpollFlags::EPOLLEXCLUSIVE,
+                0,
+            ),
+            Err(Errno::EINVAL)
+        );
+
+        // exclusive entries can't be used with EPOLLONESHOT
+        assert_eq!(
+            epoll_add(
+                epoll_fd,
+                write_fd,
+                EpollFlags::EPOLLOUT | EpollFlags::EPOLLEXCLUSIVE | EpollFlags::EPOLLONESHOT,
+                0,
+            ),
+            Err(Errno::EINVAL)
+        );
+
+        Ok(())
+    })
+}
+
 fn main() -> anyhow::Result<()> {
     // should we restrict the tests we run?
     let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
@@ -356,7 +612,18 @@ fn main() -> anyhow::Result<()> {
             test_wait_negative_timeout,
             all_envs.clone(),
         ),
-        ShadowTest::new("test_ctl_invalid_op", test_ctl_invalid_op, all_envs),
+        ShadowTest::new("test_ctl_invalid_op", test_ctl_invalid_op, all_envs.clone()),
+        ShadowTest::new("test_ctl_errors", test_ctl_errors, all_envs.clone()),
+        ShadowTest::new("test_oneshot", test_oneshot, all_envs.clone()),
+        ShadowTest::new(
+            "test_edge_ready_on_add",
+            test_edge_ready_on_add,
+            all_envs.clone(),
+        ),
+        ShadowTest::new("test_pipe_hup", test_pipe_hup, all_envs.clone()),
+        ShadowTest::new("test_socket_rdhup", test_socket_rdhup, all_envs.clone()),
+        ShadowTest::new("test_nested", test_nested, all_envs.clone()),
+        ShadowTest::new("test_exclusive_errors", test_exclusive_errors, all_envs),
     ];
 
     if filter_shadow_passing {
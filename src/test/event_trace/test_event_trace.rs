@@ -0,0 +1,146 @@
+//! Checks the event trace written by Shadow for `event_trace.yaml`. This isn't a managed process;
+//! it runs after the simulation and reads the trace given as its only argument.
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, Context};
+use event_trace::{Event, ExitStatus, PacketStatus, Protocol, Reader, Record};
+
+fn main() -> anyhow::Result<()> {
+    let path = std::env::args().nth(1).context("Missing the trace path")?;
+    let records = Reader::open(&path)
+        .with_context(|| format!("Unable to open {path}"))?
+        .collect::<Result<Vec<Record>, _>>()
+        .context("Unable to read the trace")?;
+
+    ensure!(!records.is_empty());
+    check_records(&records)?;
+    check_processes(&records)?;
+    check_syscalls(&records)?;
+    check_packets(&records)?;
+    check_timers(&records)?;
+
+    Ok(())
+}
+
+/// All records are from the same host, and are in order of simulation time.
+fn check_records(records: &[Record]) -> anyhow::Result<()> {
+    let host_id = records[0].host_id;
+    ensure!(records.iter().all(|x| x.host_id == host_id));
+    ensure!(records.windows(2).all(|x| x[0].time_ns <= x[1].time_ns));
+
+    // the processes start at 1 second
+    ensure!(records[0].time_ns >= 1_000_000_000);
+
+    Ok(())
+}
+
+/// Both processes started from the configuration and exited successfully.
+fn check_processes(records: &[Record]) -> anyhow::Result<()> {
+    let mut started = HashMap::new();
+    let mut exited = HashMap::new();
+
+    for record in records {
+        match &record.event {
+            Event::ProcessStart {
+                pid,
+                parent_pid,
+                name,
+            } => {
+                ensure!(parent_pid.is_none());
+                ensure!(started.insert(*pid, name.clone()).is_none());
+            }
+            Event::ProcessExit { pid, status } => {
+                ensure!(
+                    started.contains_key(pid),
+                    "Process {pid} exited before starting"
+                );
+                ensure!(exited.insert(*pid, *status).is_none());
+            }
+            _ => {}
+        }
+    }
+
+    let mut names: Vec<_> = started.values().map(String::as_str).collect();
+    names.sort();
+    ensure!(
+        names == ["test-timerfd", "test-udp-uniprocess"],
+        "{names:?}"
+    );
+
+    ensure!(exited.len() == 2);
+    ensure!(exited.values().all(|x| *x == ExitStatus::Exited(0)));
+
+    Ok(())
+}
+
+/// Every syscall exit follows an enter of the same syscall by the same thread.
+fn check_syscalls(records: &[Record]) -> anyhow::Result<()> {
+    let mut entered = HashMap::new();
+    let mut num_syscalls = 0;
+
+    for record in records {
+        match record.event {
+            Event::SyscallEnter {
+                pid, tid, number, ..
+            } => {
+                ensure!(entered.insert((pid, tid), number).is_none());
+            }
+            Event::SyscallExit {
+                pid, tid, number, ..
+            } => {
+                ensure!(entered.remove(&(pid, tid)) == Some(number));
+                num_syscalls += 1;
+            }
+            _ => {}
+        }
+    }
+
+    ensure!(num_syscalls > 0);
+
+    Ok(())
+}
+
+/// The UDP packets sent over the loopback interface were created and then received.
+fn check_packets(records: &[Record]) -> anyhow::Result<()> {
+    let mut statuses = HashMap::<_, Vec<_>>::new();
+
+    for record in records {
+        if let Event::PacketStatus { packet, status } = &record.event {
+            if packet.protocol == Protocol::Udp {
+                ensure!(packet.dst.ip().is_loopback());
+                statuses
+                    .entry((packet.host_id, packet.id))
+                    .or_default()
+                    .push(*status);
+            }
+        }
+    }
+
+    ensure!(!statuses.is_empty());
+    for statuses in statuses.values() {
+        ensure!(
+            statuses.first() == Some(&PacketStatus::SndCreated),
+            "{statuses:?}"
+        );
+        ensure!(
+            statuses.last() == Some(&PacketStatus::Destroyed),
+            "{statuses:?}"
+        );
+        ensure!(
+            statuses.contains(&PacketStatus::RcvSocketBuffered),
+            "{statuses:?}"
+        );
+    }
+
+    Ok(())
+}
+
+/// The timerfd expired.
+fn check_timers(records: &[Record]) -> anyhow::Result<()> {
+    ensure!(records
+        .iter()
+        .any(|x| matches!(x.event, Event::TimerFired { .. })));
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+/*
+ * The Shadow Simulator
+ * See LICENSE for licensing information
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+// Checks the state that should (and shouldn't) survive an `execve`. Runs as
+// the "child" image when given the fds to check as arguments.
+static int _run_child(pid_t expected_pid, int kept_fd, int cloexec_fd) {
+    if (getpid() != expected_pid) {
+        fprintf(stderr, "pid changed across exec: %d != %d\n", getpid(), expected_pid);
+        return EXIT_FAILURE;
+    }
+
+    if (fcntl(kept_fd, F_GETFD) < 0) {
+        fprintf(stderr, "fd %d without FD_CLOEXEC was closed: %s\n", kept_fd, strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    if (fcntl(cloexec_fd, F_GETFD) >= 0 || errno != EBADF) {
+        fprintf(stderr, "fd %d with FD_CLOEXEC was not closed\n", cloexec_fd);
+        return EXIT_FAILURE;
+    }
+
+    const char msg[] = "hello";
+    if (write(kept_fd, msg, sizeof(msg)) != sizeof(msg)) {
+        fprintf(stderr, "write to inherited fd failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    struct sigaction action;
+    if (sigaction(SIGUSR1, NULL, &action) != 0) {
+        fprintf(stderr, "sigaction failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+    if (action.sa_handler != SIG_DFL) {
+        fprintf(stderr, "caught signal handler was not reset\n");
+        return EXIT_FAILURE;
+    }
+
+    return EXIT_SUCCESS;
+}
+
+static void _sigusr1_handler(int signum) {}
+
+int main(int argc, char* argv[]) {
+    if (argc == 5 && strcmp(argv[1], "child") == 0) {
+        return _run_child(atoi(argv[2]), atoi(argv[3]), atoi(argv[4]));
+    }
+
+    int kept_fds[2];
+    if (pipe(kept_fds) != 0) {
+        fprintf(stderr, "pipe failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    int cloexec_fds[2];
+    if (pipe2(cloexec_fds, O_CLOEXEC) != 0) {
+        fprintf(stderr, "pipe2 failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    struct sigaction action = {.sa_handler = _sigusr1_handler};
+    if (sigaction(SIGUSR1, &action, NULL) != 0) {
+        fprintf(stderr, "sigaction failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    char exe[PATH_MAX] = {0};
+    if (readlink("/proc/self/exe", exe, sizeof(exe) - 1) < 0) {
+        fprintf(stderr, "readlink failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    char pid_str[16], kept_str[16], cloexec_str[16];
+    snprintf(pid_str, sizeof(pid_str), "%d", getpid());
+    snprintf(kept_str, sizeof(kept_str), "%d", kept_fds[1]);
+    snprintf(cloexec_str, sizeof(cloexec_str), "%d", cloexec_fds[1]);
+
+    char* child_argv[] = {exe, "child", pid_str, kept_str, cloexec_str, NULL};
+    execv(exe, child_argv);
+
+    fprintf(stderr, "execv failed: %s\n", strerror(errno));
+    return EXIT_FAILURE;
+}
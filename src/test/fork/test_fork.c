@@ -0,0 +1,134 @@
+/*
+ * The Shadow Simulator
+ * See LICENSE for licensing information
+ */
+
+#include <errno.h>
+#include <spawn.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+static int global_value = 1;
+
+// Result reported by the child to the parent over a pipe inherited across
+// `fork`.
+struct child_report {
+    pid_t pid;
+    pid_t ppid;
+    int global_value;
+    int heap_value;
+};
+
+static int _test_fork() {
+    int fds[2];
+    if (pipe(fds) != 0) {
+        fprintf(stderr, "pipe failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    int* heap_value = malloc(sizeof(*heap_value));
+    *heap_value = 1;
+
+    pid_t parent_pid = getpid();
+    pid_t child_pid = fork();
+    if (child_pid < 0) {
+        fprintf(stderr, "fork failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    if (child_pid == 0) {
+        // Child: should see the parent's memory as of the fork, but not share
+        // it.
+        struct child_report report = {
+            .pid = getpid(),
+            .ppid = getppid(),
+            .global_value = global_value,
+            .heap_value = *heap_value,
+        };
+        global_value = 2;
+        *heap_value = 2;
+        if (write(fds[1], &report, sizeof(report)) != sizeof(report)) {
+            _exit(EXIT_FAILURE);
+        }
+        _exit(EXIT_SUCCESS);
+    }
+
+    close(fds[1]);
+    struct child_report report;
+    if (read(fds[0], &report, sizeof(report)) != sizeof(report)) {
+        fprintf(stderr, "read from child failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    if (report.pid != child_pid || report.pid == parent_pid) {
+        fprintf(stderr, "child pid %d; expected %d\n", report.pid, child_pid);
+        return EXIT_FAILURE;
+    }
+    if (report.ppid != parent_pid) {
+        fprintf(stderr, "child ppid %d; expected %d\n", report.ppid, parent_pid);
+        return EXIT_FAILURE;
+    }
+    if (report.global_value != 1 || report.heap_value != 1) {
+        fprintf(stderr, "child didn't get a copy of parent memory\n");
+        return EXIT_FAILURE;
+    }
+    if (global_value != 1 || *heap_value != 1) {
+        fprintf(stderr, "child's writes are visible to the parent\n");
+        return EXIT_FAILURE;
+    }
+
+    // Closing the child's end of the pipe in the child as it exits should
+    // result in EOF, since the parent already closed its copy.
+    char c;
+    if (read(fds[0], &c, 1) != 0) {
+        fprintf(stderr, "expected EOF after child exited\n");
+        return EXIT_FAILURE;
+    }
+
+    close(fds[0]);
+    free(heap_value);
+    return EXIT_SUCCESS;
+}
+
+static int _test_vfork() {
+    pid_t child_pid = vfork();
+    if (child_pid < 0) {
+        fprintf(stderr, "vfork failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+    if (child_pid == 0) {
+        _exit(EXIT_SUCCESS);
+    }
+    return EXIT_SUCCESS;
+}
+
+// glibc's `posix_spawn` uses `clone(CLONE_VM | CLONE_VFORK)`, and the child
+// reports a failed `execve` by writing the error to the parent's memory before
+// exiting.
+static int _test_posix_spawn_missing_binary() {
+    char* const argv[] = {"/nonexistent/test_fork_child", NULL};
+    char* const envp[] = {NULL};
+    pid_t child_pid = -1;
+    int rv = posix_spawn(&child_pid, argv[0], NULL, NULL, argv, envp);
+    if (rv != ENOENT) {
+        fprintf(stderr, "posix_spawn returned %d; expected ENOENT\n", rv);
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+int main(int argc, char* argv[]) {
+    if (_test_fork() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_vfork() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_posix_spawn_missing_binary() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    printf("success\n");
+    return EXIT_SUCCESS;
+}
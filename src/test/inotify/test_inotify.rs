@@ -0,0 +1,361 @@
+use std::ffi::OsString;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use nix::unistd;
+
+use test_utils::{set, ShadowTest, TestEnvironment};
+
+const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::IN_CREATE
+    .union(AddWatchFlags::IN_MODIFY)
+    .union(AddWatchFlags::IN_CLOSE_WRITE)
+    .union(AddWatchFlags::IN_DELETE);
+
+/// Run `f` with a new empty directory (relative to the working directory), and remove the
+/// directory afterwards.
+fn with_temp_dir(name: &str, f: impl FnOnce(&Path) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let dir = PathBuf::from(format!("inotify-{name}-{}", unistd::getpid()));
+    std::fs::create_dir(&dir)?;
+
+    let rv = f(&dir);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    rv
+}
+
+/// Read all queued events from a non-blocking inotify instance.
+fn read_events(
+    inotify: Inotify,
+) -> anyhow::Result<Vec<(WatchDescriptor, AddWatchFlags, Option<OsString>)>> {
+    let mut events = Vec::new();
+
+    loop {
+        match inotify.read_events() {
+            Ok(x) => events.extend(x.into_iter().map(|e| (e.wd, e.mask, e.name))),
+            Err(Errno::EAGAIN) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(events)
+}
+
+fn test_invalid_args() -> anyhow::Result<()> {
+    // invalid flags
+    let rv = Errno::result(unsafe { libc::inotify_init1(libc::O_RDWR) });
+    assert_eq!(rv, Err(Errno::EINVAL));
+
+    let inotify = Inotify::init(InitFlags::empty())?;
+    let fd = inotify.as_raw_fd();
+
+    test_utils::run_and_close_fds(&[fd], || {
+        // the mask must contain an event
+        let rv = Errno::result(unsafe {
+            libc::inotify_add_watch(fd, b".\0".as_ptr() as *const libc::c_char, 0)
+        });
+        assert_eq!(rv, Err(Errno::EINVAL));
+
+        // the path must exist
+        assert_eq!(
+            inotify.add_watch("does-not-exist", WATCH_FLAGS),
+            Err(Errno::ENOENT)
+        );
+
+        // IN_ONLYDIR requires a directory
+        assert_eq!(
+            inotify.add_watch("/dev/null", WATCH_FLAGS | AddWatchFlags::IN_ONLYDIR),
+            Err(Errno::ENOTDIR)
+        );
+
+        // not a valid watch descriptor
+        let rv = Errno::result(unsafe { libc::inotify_rm_watch(fd, 1000) });
+        assert_eq!(rv, Err(Errno::EINVAL));
+
+        Ok(())
+    })?;
+
+    // the fd must be an inotify file
+    let (read_fd, write_fd) = unistd::pipe()?;
+    test_utils::run_and_close_fds(&[read_fd, write_fd], || {
+        let rv = Errno::result(unsafe {
+            libc::inotify_add_watch(
+                read_fd,
+                b".\0".as_ptr() as *const libc::c_char,
+                libc::IN_CREATE,
+            )
+        });
+        assert_eq!(rv, Err(Errno::EINVAL));
+        Ok(())
+    })
+}
+
+fn test_read_empty() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        assert_eq!(inotify.read_events().err(), Some(Errno::EAGAIN));
+        Ok(())
+    })
+}
+
+fn test_dir_events() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        with_temp_dir("dir-events", |dir| {
+            let wd = inotify.add_watch(dir, WATCH_FLAGS)?;
+            let name = Some(OsString::from("foo"));
+
+            let mut file = std::fs::File::create(dir.join("foo"))?;
+            // consecutive identical events are coalesced
+            file.write_all(b"hello")?;
+            file.write_all(b"world")?;
+            drop(file);
+
+            // opening and closing without writing doesn't generate events
+            drop(std::fs::File::open(dir.join("foo"))?);
+
+            std::fs::remove_file(dir.join("foo"))?;
+
+            assert_eq!(
+                read_events(inotify)?,
+                [
+                    (wd, AddWatchFlags::IN_CREATE, name.clone()),
+                    (wd, AddWatchFlags::IN_MODIFY, name.clone()),
+                    (wd, AddWatchFlags::IN_CLOSE_WRITE, name.clone()),
+                    (wd, AddWatchFlags::IN_DELETE, name),
+                ]
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn test_subdir_events() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        with_temp_dir("subdir-events", |dir| {
+            let wd = inotify.add_watch(dir, WATCH_FLAGS)?;
+            let name = Some(OsString::from("sub"));
+
+            std::fs::create_dir(dir.join("sub"))?;
+            std::fs::remove_dir(dir.join("sub"))?;
+
+            assert_eq!(
+                read_events(inotify)?,
+                [
+                    (
+                        wd,
+                        AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ISDIR,
+                        name.clone()
+                    ),
+                    (wd, AddWatchFlags::IN_DELETE | AddWatchFlags::IN_ISDIR, name),
+                ]
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn test_file_events() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        with_temp_dir("file-events", |dir| {
+            let path = dir.join("foo");
+            std::fs::write(&path, b"hello")?;
+
+            let wd = inotify.add_watch(&path, WATCH_FLAGS | AddWatchFlags::IN_DELETE_SELF)?;
+
+            // adding a watch for the same file returns the same watch descriptor
+            assert_eq!(inotify.add_watch(&path, WATCH_FLAGS)?, wd);
+            let wd = inotify.add_watch(&path, WATCH_FLAGS | AddWatchFlags::IN_DELETE_SELF)?;
+
+            let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+            file.write_all(b"world")?;
+            drop(file);
+
+            std::fs::remove_file(&path)?;
+
+            // events on the watched file itself have no name, and the watch is removed when the
+            // file is deleted
+            assert_eq!(
+                read_events(inotify)?,
+                [
+                    (wd, AddWatchFlags::IN_MODIFY, None),
+                    (wd, AddWatchFlags::IN_CLOSE_WRITE, None),
+                    (wd, AddWatchFlags::IN_DELETE_SELF, None),
+                    (wd, AddWatchFlags::IN_IGNORED, None),
+                ]
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn test_rm_watch() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        with_temp_dir("rm-watch", |dir| {
+            let wd = inotify.add_watch(dir, WATCH_FLAGS)?;
+            inotify.rm_watch(wd)?;
+
+            // no events after the watch was removed
+            std::fs::write(dir.join("foo"), b"hello")?;
+
+            assert_eq!(
+                read_events(inotify)?,
+                [(wd, AddWatchFlags::IN_IGNORED, None)]
+            );
+
+            // the watch no longer exists
+            assert_eq!(inotify.rm_watch(wd), Err(Errno::EINVAL));
+
+            Ok(())
+        })
+    })
+}
+
+fn test_oneshot() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        with_temp_dir("oneshot", |dir| {
+            let wd =
+                inotify.add_watch(dir, AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ONESHOT)?;
+
+            std::fs::write(dir.join("foo"), b"hello")?;
+            std::fs::write(dir.join("bar"), b"hello")?;
+
+            assert_eq!(
+                read_events(inotify)?,
+                [
+                    (wd, AddWatchFlags::IN_CREATE, Some(OsString::from("foo"))),
+                    (wd, AddWatchFlags::IN_IGNORED, None),
+                ]
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn test_small_buffer() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+    let fd = inotify.as_raw_fd();
+
+    test_utils::run_and_close_fds(&[fd], || {
+        with_temp_dir("small-buffer", |dir| {
+            inotify.add_watch(dir, WATCH_FLAGS)?;
+            std::fs::write(dir.join("foo"), b"hello")?;
+
+            // the buffer must fit the event's header and name
+            let mut buf = [0u8; std::mem::size_of::<libc::inotify_event>()];
+            assert_eq!(unistd::read(fd, &mut buf), Err(Errno::EINVAL));
+
+            // the event is still queued
+            let mut available: libc::c_int = 0;
+            Errno::result(unsafe { libc::ioctl(fd, libc::FIONREAD, &mut available) })?;
+            assert!(available as usize > buf.len());
+
+            Ok(())
+        })
+    })
+}
+
+fn test_poll() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK)?;
+    let fd = inotify.as_raw_fd();
+
+    test_utils::run_and_close_fds(&[fd], || {
+        with_temp_dir("poll", |dir| {
+            inotify.add_watch(dir, WATCH_FLAGS)?;
+
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            assert_eq!(nix::poll::poll(&mut fds, 0)?, 0);
+
+            std::fs::create_dir(dir.join("sub"))?;
+
+            assert_eq!(nix::poll::poll(&mut fds, 0)?, 1);
+            assert_eq!(fds[0].revents(), Some(PollFlags::POLLIN));
+
+            read_events(inotify)?;
+
+            assert_eq!(nix::poll::poll(&mut fds, 0)?, 0);
+
+            Ok(())
+        })
+    })
+}
+
+fn test_blocking_read() -> anyhow::Result<()> {
+    let inotify = Inotify::init(InitFlags::empty())?;
+
+    test_utils::run_and_close_fds(&[inotify.as_raw_fd()], || {
+        with_temp_dir("blocking-read", |dir| {
+            let wd = inotify.add_watch(dir, AddWatchFlags::IN_CREATE)?;
+
+            let path = dir.join("foo");
+            let t = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                std::fs::write(path, b"hello")
+            });
+
+            let events = inotify.read_events()?;
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].wd, wd);
+            assert_eq!(events[0].mask, AddWatchFlags::IN_CREATE);
+            assert_eq!(events[0].name, Some(OsString::from("foo")));
+
+            t.join().unwrap()?;
+
+            Ok(())
+        })
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let all_envs = set![TestEnvironment::Libc, TestEnvironment::Shadow];
+    let mut tests: Vec<test_utils::ShadowTest<(), anyhow::Error>> = vec![
+        ShadowTest::new("test_invalid_args", test_invalid_args, all_envs.clone()),
+        ShadowTest::new("test_read_empty", test_read_empty, all_envs.clone()),
+        ShadowTest::new("test_dir_events", test_dir_events, all_envs.clone()),
+        ShadowTest::new("test_subdir_events", test_subdir_events, all_envs.clone()),
+        ShadowTest::new("test_file_events", test_file_events, all_envs.clone()),
+        ShadowTest::new("test_rm_watch", test_rm_watch, all_envs.clone()),
+        ShadowTest::new("test_oneshot", test_oneshot, all_envs.clone()),
+        ShadowTest::new("test_small_buffer", test_small_buffer, all_envs.clone()),
+        ShadowTest::new("test_poll", test_poll, all_envs.clone()),
+        ShadowTest::new("test_blocking_read", test_blocking_read, all_envs),
+    ];
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+
+    Ok(())
+}
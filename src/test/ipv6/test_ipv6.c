@@ -0,0 +1,231 @@
+#include <arpa/inet.h>
+#include <errno.h>
+#include <glib.h>
+#include <netdb.h>
+#include <netinet/in.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+#include "test/test_glib_helpers.h"
+
+static void _assert_addr_is(const struct sockaddr_in6* addr, const struct in6_addr* expected) {
+    g_assert_cmpint(addr->sin6_family, ==, AF_INET6);
+    g_assert_true(memcmp(&addr->sin6_addr, expected, sizeof(*expected)) == 0);
+}
+
+static void _assert_addr_is_v4_mapped(const struct sockaddr_in6* addr, uint32_t s_addr) {
+    g_assert_cmpint(addr->sin6_family, ==, AF_INET6);
+    g_assert_true(IN6_IS_ADDR_V4MAPPED(&addr->sin6_addr));
+    g_assert_true(memcmp(&addr->sin6_addr.s6_addr[12], &s_addr, sizeof(s_addr)) == 0);
+}
+
+// Create a TCP listener bound to `addr`, and update `addr` with the bound port.
+static int _tcp_listener(struct sockaddr_in6* addr) {
+    int sock;
+    assert_nonneg_errno(sock = socket(AF_INET6, SOCK_STREAM, 0));
+    assert_nonneg_errno(bind(sock, (struct sockaddr*)addr, sizeof(*addr)));
+    assert_nonneg_errno(listen(sock, 10));
+
+    socklen_t addr_len = sizeof(*addr);
+    assert_nonneg_errno(getsockname(sock, (struct sockaddr*)addr, &addr_len));
+    g_assert_cmpint(addr_len, ==, sizeof(*addr));
+
+    return sock;
+}
+
+static void test_tcp_loopback() {
+    struct sockaddr_in6 server_addr = {.sin6_family = AF_INET6, .sin6_addr = IN6ADDR_LOOPBACK_INIT};
+    int listener = _tcp_listener(&server_addr);
+    _assert_addr_is(&server_addr, &in6addr_loopback);
+
+    int client;
+    assert_nonneg_errno(client = socket(AF_INET6, SOCK_STREAM, 0));
+    assert_nonneg_errno(connect(client, (struct sockaddr*)&server_addr, sizeof(server_addr)));
+
+    struct sockaddr_in6 peer_addr = {0};
+    socklen_t peer_addr_len = sizeof(peer_addr);
+    int server;
+    assert_nonneg_errno(server = accept(listener, (struct sockaddr*)&peer_addr, &peer_addr_len));
+    g_assert_cmpint(peer_addr_len, ==, sizeof(peer_addr));
+    _assert_addr_is(&peer_addr, &in6addr_loopback);
+
+    struct sockaddr_in6 client_addr = {0};
+    socklen_t client_addr_len = sizeof(client_addr);
+    assert_nonneg_errno(getsockname(client, (struct sockaddr*)&client_addr, &client_addr_len));
+    _assert_addr_is(&client_addr, &in6addr_loopback);
+    g_assert_cmpint(client_addr.sin6_port, ==, peer_addr.sin6_port);
+
+    const char msg[] = "hello";
+    char buf[sizeof(msg)] = {0};
+    g_assert_cmpint(send(client, msg, sizeof(msg), 0), ==, sizeof(msg));
+    g_assert_cmpint(recv(server, buf, sizeof(buf), MSG_WAITALL), ==, sizeof(msg));
+    g_assert_cmpstr(buf, ==, msg);
+
+    assert_nonneg_errno(close(client));
+    assert_nonneg_errno(close(server));
+    assert_nonneg_errno(close(listener));
+}
+
+static void test_tcp_dual_stack() {
+    // a listener on "::" without IPV6_V6ONLY accepts IPv4 connections
+    struct sockaddr_in6 server_addr = {.sin6_family = AF_INET6, .sin6_addr = IN6ADDR_ANY_INIT};
+    int listener = _tcp_listener(&server_addr);
+
+    int client;
+    assert_nonneg_errno(client = socket(AF_INET, SOCK_STREAM, 0));
+    struct sockaddr_in connect_addr = {.sin_family = AF_INET,
+                                       .sin_port = server_addr.sin6_port,
+                                       .sin_addr = {htonl(INADDR_LOOPBACK)}};
+    assert_nonneg_errno(connect(client, (struct sockaddr*)&connect_addr, sizeof(connect_addr)));
+
+    struct sockaddr_in6 peer_addr = {0};
+    socklen_t peer_addr_len = sizeof(peer_addr);
+    int server;
+    assert_nonneg_errno(server = accept(listener, (struct sockaddr*)&peer_addr, &peer_addr_len));
+    _assert_addr_is_v4_mapped(&peer_addr, htonl(INADDR_LOOPBACK));
+
+    // the accepted socket is still an IPv6 socket
+    int domain = 0;
+    socklen_t domain_len = sizeof(domain);
+    assert_nonneg_errno(getsockopt(server, SOL_SOCKET, SO_DOMAIN, &domain, &domain_len));
+    g_assert_cmpint(domain, ==, AF_INET6);
+
+    assert_nonneg_errno(close(client));
+    assert_nonneg_errno(close(server));
+    assert_nonneg_errno(close(listener));
+}
+
+static void test_udp_loopback() {
+    int server;
+    assert_nonneg_errno(server = socket(AF_INET6, SOCK_DGRAM, 0));
+    struct sockaddr_in6 server_addr = {.sin6_family = AF_INET6, .sin6_addr = IN6ADDR_LOOPBACK_INIT};
+    assert_nonneg_errno(bind(server, (struct sockaddr*)&server_addr, sizeof(server_addr)));
+    socklen_t server_addr_len = sizeof(server_addr);
+    assert_nonneg_errno(getsockname(server, (struct sockaddr*)&server_addr, &server_addr_len));
+
+    int client;
+    assert_nonneg_errno(client = socket(AF_INET6, SOCK_DGRAM, 0));
+
+    const char msg[] = "hello";
+    g_assert_cmpint(
+        sendto(client, msg, sizeof(msg), 0, (struct sockaddr*)&server_addr, sizeof(server_addr)),
+        ==, sizeof(msg));
+
+    char buf[sizeof(msg)] = {0};
+    struct sockaddr_in6 from_addr = {0};
+    socklen_t from_addr_len = sizeof(from_addr);
+    g_assert_cmpint(
+        recvfrom(server, buf, sizeof(buf), 0, (struct sockaddr*)&from_addr, &from_addr_len), ==,
+        sizeof(msg));
+    g_assert_cmpstr(buf, ==, msg);
+    g_assert_cmpint(from_addr_len, ==, sizeof(from_addr));
+    _assert_addr_is(&from_addr, &in6addr_loopback);
+
+    assert_nonneg_errno(close(client));
+    assert_nonneg_errno(close(server));
+}
+
+static void test_v6only() {
+    int sock;
+    assert_nonneg_errno(sock = socket(AF_INET6, SOCK_DGRAM, 0));
+
+    int domain = 0;
+    socklen_t optlen = sizeof(domain);
+    assert_nonneg_errno(getsockopt(sock, SOL_SOCKET, SO_DOMAIN, &domain, &optlen));
+    g_assert_cmpint(domain, ==, AF_INET6);
+
+    int v6only = -1;
+    optlen = sizeof(v6only);
+    assert_nonneg_errno(getsockopt(sock, IPPROTO_IPV6, IPV6_V6ONLY, &v6only, &optlen));
+    g_assert_cmpint(v6only, ==, 0);
+
+    v6only = 1;
+    assert_nonneg_errno(setsockopt(sock, IPPROTO_IPV6, IPV6_V6ONLY, &v6only, sizeof(v6only)));
+
+    v6only = -1;
+    optlen = sizeof(v6only);
+    assert_nonneg_errno(getsockopt(sock, IPPROTO_IPV6, IPV6_V6ONLY, &v6only, &optlen));
+    g_assert_cmpint(v6only, ==, 1);
+
+    // an IPV6_V6ONLY socket can't send to an IPv4-mapped address
+    struct sockaddr_in6 mapped_addr = {.sin6_family = AF_INET6, .sin6_port = htons(9)};
+    g_assert_cmpint(inet_pton(AF_INET6, "::ffff:127.0.0.1", &mapped_addr.sin6_addr), ==, 1);
+    const char msg[] = "hello";
+    g_assert_cmpint(
+        sendto(sock, msg, sizeof(msg), 0, (struct sockaddr*)&mapped_addr, sizeof(mapped_addr)), ==,
+        -1);
+    assert_errno_is(ENETUNREACH);
+
+    assert_nonneg_errno(close(sock));
+}
+
+static int run_server(const char* port) {
+    int listener;
+    assert_nonneg_errno(listener = socket(AF_INET6, SOCK_STREAM, 0));
+    struct sockaddr_in6 addr = {
+        .sin6_family = AF_INET6, .sin6_port = htons(atoi(port)), .sin6_addr = IN6ADDR_ANY_INIT};
+    assert_nonneg_errno(bind(listener, (struct sockaddr*)&addr, sizeof(addr)));
+    assert_nonneg_errno(listen(listener, 10));
+
+    struct sockaddr_in6 peer_addr = {0};
+    socklen_t peer_addr_len = sizeof(peer_addr);
+    int sock;
+    assert_nonneg_errno(sock = accept(listener, (struct sockaddr*)&peer_addr, &peer_addr_len));
+    g_assert_cmpint(peer_addr.sin6_family, ==, AF_INET6);
+    g_assert_false(IN6_IS_ADDR_V4MAPPED(&peer_addr.sin6_addr));
+    g_assert_false(IN6_IS_ADDR_LOOPBACK(&peer_addr.sin6_addr));
+
+    char buf[100] = {0};
+    ssize_t len;
+    assert_nonneg_errno(len = recv(sock, buf, sizeof(buf), 0));
+    g_assert_cmpint(send(sock, buf, len, 0), ==, len);
+
+    assert_nonneg_errno(close(sock));
+    assert_nonneg_errno(close(listener));
+    return 0;
+}
+
+static int run_client(const char* host, const char* port) {
+    struct addrinfo hints = {.ai_family = AF_INET6, .ai_socktype = SOCK_STREAM};
+    struct addrinfo* res = NULL;
+    g_assert_cmpint(getaddrinfo(host, port, &hints, &res), ==, 0);
+    g_assert_nonnull(res);
+    g_assert_cmpint(res->ai_family, ==, AF_INET6);
+
+    int sock;
+    assert_nonneg_errno(sock = socket(AF_INET6, SOCK_STREAM, 0));
+    assert_nonneg_errno(connect(sock, res->ai_addr, res->ai_addrlen));
+
+    struct sockaddr_in6 peer_addr = {0};
+    socklen_t peer_addr_len = sizeof(peer_addr);
+    assert_nonneg_errno(getpeername(sock, (struct sockaddr*)&peer_addr, &peer_addr_len));
+    _assert_addr_is(&peer_addr, &((struct sockaddr_in6*)res->ai_addr)->sin6_addr);
+    freeaddrinfo(res);
+
+    const char msg[] = "hello";
+    char buf[sizeof(msg)] = {0};
+    g_assert_cmpint(send(sock, msg, sizeof(msg), 0), ==, sizeof(msg));
+    g_assert_cmpint(recv(sock, buf, sizeof(buf), MSG_WAITALL), ==, sizeof(msg));
+    g_assert_cmpstr(buf, ==, msg);
+
+    assert_nonneg_errno(close(sock));
+    return 0;
+}
+
+int main(int argc, char* argv[]) {
+    if (argc == 3 && strcmp(argv[1], "server") == 0) {
+        return run_server(argv[2]);
+    } else if (argc == 4 && strcmp(argv[1], "client") == 0) {
+        return run_client(argv[2], argv[3]);
+    }
+
+    g_test_init(&argc, &argv, NULL);
+    g_test_add_func("/ipv6/tcp_loopback", test_tcp_loopback);
+    g_test_add_func("/ipv6/tcp_dual_stack", test_tcp_dual_stack);
+    g_test_add_func("/ipv6/udp_loopback", test_udp_loopback);
+    g_test_add_func("/ipv6/v6only", test_v6only);
+    return g_test_run();
+}
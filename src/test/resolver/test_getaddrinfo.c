@@ -120,7 +120,11 @@ bool sockaddr_equals(const struct sockaddr* lhs, const struct sockaddr* rhs) {
         return false;
     if (lhs->sa_family != rhs->sa_family)
         return false;
-    // INET6 unhandled
+    if (lhs->sa_family == AF_INET6) {
+        const struct sockaddr_in6* lhs_in6 = (const struct sockaddr_in6*)lhs;
+        const struct sockaddr_in6* rhs_in6 = (const struct sockaddr_in6*)rhs;
+        return !memcmp(lhs_in6, rhs_in6, sizeof(*lhs_in6));
+    }
     g_assert(lhs->sa_family == AF_INET);
     const struct sockaddr_in* lhs_in = (const struct sockaddr_in*)lhs;
     const struct sockaddr_in* rhs_in = (const struct sockaddr_in*)rhs;
@@ -310,16 +314,30 @@ void test_ipv6() {
     struct addrinfo hints = {
         .ai_family = AF_INET6, .ai_socktype = SOCK_STREAM, .ai_flags = AI_PASSIVE};
     struct addrinfo* res;
+    const struct sockaddr_in6 expected_sockaddr_in6 = {
+        .sin6_family = AF_INET6, .sin6_port = htons(80), .sin6_addr = IN6ADDR_ANY_INIT};
+    struct addrinfo expected_addrinfo = {
+        .ai_flags = 0,
+        .ai_family = AF_INET6,
+        .ai_socktype = SOCK_STREAM,
+        .ai_protocol = IPPROTO_TCP,
+        .ai_addrlen = sizeof(expected_sockaddr_in6),
+        .ai_addr = (struct sockaddr*)&expected_sockaddr_in6,
+    };
+
+    assert_getaddrinfo_rv_equals(getaddrinfo(NULL, "80", &hints, &res), 0);
+    assert_addrinfo_equals(res, &expected_addrinfo);
+    freeaddrinfo(res);
 
-    int rv = getaddrinfo(NULL, "80", &hints, &res);
+    // numeric IPv6 host
+    hints = (struct addrinfo){.ai_family = AF_INET6, .ai_socktype = SOCK_STREAM};
+    struct sockaddr_in6 expected_numeric_sockaddr_in6 = {.sin6_family = AF_INET6};
+    g_assert(inet_pton(AF_INET6, "2001:db8::1", &expected_numeric_sockaddr_in6.sin6_addr) == 1);
+    expected_addrinfo.ai_addr = (struct sockaddr*)&expected_numeric_sockaddr_in6;
 
-    if (running_in_shadow()) {
-        // shadow doesn't support IPv6
-        assert_getaddrinfo_rv_equals(rv, EAI_NONAME);
-    } else {
-        // linux should return a non-error result
-        assert_getaddrinfo_rv_equals(rv, 0);
-    }
+    assert_getaddrinfo_rv_equals(getaddrinfo("2001:db8::1", NULL, &hints, &res), 0);
+    assert_addrinfo_equals(res, &expected_addrinfo);
+    freeaddrinfo(res);
 }
 
 int main(int argc, char* argv[]) {
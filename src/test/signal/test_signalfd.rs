@@ -0,0 +1,293 @@
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags};
+use nix::sys::signal::{self, SigSet, Signal};
+use nix::sys::signalfd::SfdFlags;
+use nix::unistd;
+
+use test_utils::{set, ShadowTest, TestEnvironment};
+
+// Values of `si_code`. The libc crate doesn't define these.
+const SI_USER: i32 = 0;
+const SI_TKILL: i32 = -6;
+
+fn sigset(signals: &[Signal]) -> SigSet {
+    let mut set = SigSet::empty();
+    for signal in signals {
+        set.add(*signal);
+    }
+    set
+}
+
+fn signalfd(fd: libc::c_int, mask: &SigSet, flags: SfdFlags) -> nix::Result<libc::c_int> {
+    Errno::result(unsafe { libc::signalfd(fd, mask.as_ref(), flags.bits()) })
+}
+
+/// Read a single record from the signalfd.
+fn read_siginfo(fd: libc::c_int) -> nix::Result<libc::signalfd_siginfo> {
+    let mut info: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of_val(&info);
+    let buf = unsafe { std::slice::from_raw_parts_mut(&mut info as *mut _ as *mut u8, size) };
+
+    let count = unistd::read(fd, buf)?;
+    assert_eq!(count, size);
+
+    Ok(info)
+}
+
+/// Run `f` with the signals in `mask` blocked for the calling thread (and any threads it
+/// creates), and then discard any of those signals that are left pending.
+fn with_blocked(mask: &SigSet, f: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let mut old_mask = SigSet::empty();
+    signal::pthread_sigmask(
+        signal::SigmaskHow::SIG_BLOCK,
+        Some(mask),
+        Some(&mut old_mask),
+    )?;
+
+    let rv = f();
+
+    // discard any pending signals so that they aren't delivered when unblocked
+    let fd = signalfd(-1, mask, SfdFlags::SFD_NONBLOCK)?;
+    while read_siginfo(fd).is_ok() {}
+    unistd::close(fd)?;
+
+    signal::pthread_sigmask(signal::SigmaskHow::SIG_SETMASK, Some(&old_mask), None)?;
+
+    rv
+}
+
+fn test_invalid_args() -> anyhow::Result<()> {
+    let mask = SigSet::empty();
+
+    // the mask size must be the size of the kernel's sigset
+    let rv = Errno::result(unsafe {
+        libc::syscall(libc::SYS_signalfd4, -1, mask.as_ref() as *const _, 4, 0)
+    });
+    assert_eq!(rv, Err(Errno::EINVAL));
+
+    // invalid flags
+    let rv = Errno::result(unsafe {
+        libc::syscall(
+            libc::SYS_signalfd4,
+            -1,
+            mask.as_ref() as *const _,
+            8,
+            libc::O_RDWR,
+        )
+    });
+    assert_eq!(rv, Err(Errno::EINVAL));
+
+    // the fd must be a signalfd
+    let (read_fd, write_fd) = unistd::pipe()?;
+    test_utils::run_and_close_fds(&[read_fd, write_fd], || {
+        assert_eq!(
+            signalfd(read_fd, &mask, SfdFlags::empty()),
+            Err(Errno::EINVAL)
+        );
+        Ok(())
+    })
+}
+
+fn test_read_nonblock() -> anyhow::Result<()> {
+    let mask = sigset(&[Signal::SIGUSR1]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &mask, SfdFlags::SFD_NONBLOCK)?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            assert_eq!(read_siginfo(fd).err(), Some(Errno::EAGAIN));
+
+            signal::kill(unistd::getpid(), Signal::SIGUSR1)?;
+
+            let info = read_siginfo(fd)?;
+            assert_eq!(info.ssi_signo, Signal::SIGUSR1 as u32);
+            assert_eq!(info.ssi_code, SI_USER);
+            assert_eq!(info.ssi_pid, unistd::getpid().as_raw() as u32);
+
+            // the signal was consumed
+            assert_eq!(read_siginfo(fd).err(), Some(Errno::EAGAIN));
+
+            Ok(())
+        })
+    })
+}
+
+fn test_small_buffer() -> anyhow::Result<()> {
+    let mask = sigset(&[Signal::SIGUSR1]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &mask, SfdFlags::SFD_NONBLOCK)?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            signal::kill(unistd::getpid(), Signal::SIGUSR1)?;
+
+            let mut buf = [0u8; 64];
+            assert_eq!(unistd::read(fd, &mut buf), Err(Errno::EINVAL));
+
+            // the signal should still be pending
+            assert!(read_siginfo(fd).is_ok());
+
+            Ok(())
+        })
+    })
+}
+
+fn test_thread_directed() -> anyhow::Result<()> {
+    let mask = sigset(&[Signal::SIGUSR2]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &mask, SfdFlags::SFD_NONBLOCK)?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            let rv = unsafe {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    unistd::getpid().as_raw(),
+                    unistd::gettid().as_raw(),
+                    libc::SIGUSR2,
+                )
+            };
+            Errno::result(rv)?;
+
+            let info = read_siginfo(fd)?;
+            assert_eq!(info.ssi_signo, Signal::SIGUSR2 as u32);
+            assert_eq!(info.ssi_code, SI_TKILL);
+
+            Ok(())
+        })
+    })
+}
+
+fn test_change_mask() -> anyhow::Result<()> {
+    let mask = sigset(&[Signal::SIGUSR1, Signal::SIGUSR2]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &sigset(&[Signal::SIGUSR1]), SfdFlags::SFD_NONBLOCK)?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            signal::kill(unistd::getpid(), Signal::SIGUSR2)?;
+
+            // not in the signalfd's mask
+            assert_eq!(read_siginfo(fd).err(), Some(Errno::EAGAIN));
+
+            // changing the mask of an existing signalfd returns the same fd
+            assert_eq!(
+                signalfd(fd, &sigset(&[Signal::SIGUSR2]), SfdFlags::empty()),
+                Ok(fd)
+            );
+
+            let info = read_siginfo(fd)?;
+            assert_eq!(info.ssi_signo, Signal::SIGUSR2 as u32);
+
+            Ok(())
+        })
+    })
+}
+
+fn test_poll() -> anyhow::Result<()> {
+    let mask = sigset(&[Signal::SIGUSR1]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &mask, SfdFlags::SFD_NONBLOCK)?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            assert_eq!(nix::poll::poll(&mut fds, 0)?, 0);
+
+            signal::kill(unistd::getpid(), Signal::SIGUSR1)?;
+
+            assert_eq!(nix::poll::poll(&mut fds, 0)?, 1);
+            assert_eq!(fds[0].revents(), Some(PollFlags::POLLIN));
+
+            read_siginfo(fd)?;
+
+            assert_eq!(nix::poll::poll(&mut fds, 0)?, 0);
+
+            Ok(())
+        })
+    })
+}
+
+fn test_blocking_read() -> anyhow::Result<()> {
+    let mask = sigset(&[Signal::SIGUSR1]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &mask, SfdFlags::empty())?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            // the new thread inherits our signal mask
+            let t = std::thread::spawn(|| {
+                std::thread::sleep(Duration::from_millis(100));
+                signal::kill(unistd::getpid(), Signal::SIGUSR1)
+            });
+
+            let info = read_siginfo(fd)?;
+            assert_eq!(info.ssi_signo, Signal::SIGUSR1 as u32);
+
+            t.join().unwrap()?;
+
+            Ok(())
+        })
+    })
+}
+
+fn test_default_ignored_signal() -> anyhow::Result<()> {
+    // SIGCHLD is ignored by default, but can still be read from a signalfd while it's blocked
+    let mask = sigset(&[Signal::SIGCHLD]);
+
+    with_blocked(&mask, || {
+        let fd = signalfd(-1, &mask, SfdFlags::SFD_NONBLOCK)?;
+
+        test_utils::run_and_close_fds(&[fd], || {
+            signal::kill(unistd::getpid(), Signal::SIGCHLD)?;
+
+            let info = read_siginfo(fd)?;
+            assert_eq!(info.ssi_signo, Signal::SIGCHLD as u32);
+
+            Ok(())
+        })
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let all_envs = set![TestEnvironment::Libc, TestEnvironment::Shadow];
+    let mut tests: Vec<test_utils::ShadowTest<(), anyhow::Error>> = vec![
+        ShadowTest::new("test_invalid_args", test_invalid_args, all_envs.clone()),
+        ShadowTest::new("test_read_nonblock", test_read_nonblock, all_envs.clone()),
+        ShadowTest::new("test_small_buffer", test_small_buffer, all_envs.clone()),
+        ShadowTest::new(
+            "test_thread_directed",
+            test_thread_directed,
+            all_envs.clone(),
+        ),
+        ShadowTest::new("test_change_mask", test_change_mask, all_envs.clone()),
+        ShadowTest::new("test_poll", test_poll, all_envs.clone()),
+        ShadowTest::new("test_blocking_read", test_blocking_read, all_envs.clone()),
+        ShadowTest::new(
+            "test_default_ignored_signal",
+            test_default_ignored_signal,
+            all_envs,
+        ),
+    ];
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+
+    Ok(())
+}
@@ -0,0 +1,479 @@
+/*
+ * The Shadow Simulator
+ * See LICENSE for licensing information
+ */
+
+use std::io::{IoSlice, IoSliceMut};
+use std::os::unix::io::RawFd;
+
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sys::socket::{
+    self, sockopt, AddressFamily, ControlMessage, ControlMessageOwned, MsgFlags, SockFlag,
+    SockType, UnixAddr, UnixCredentials,
+};
+use nix::unistd;
+
+use test_utils::set;
+use test_utils::TestEnvironment as TestEnv;
+
+fn main() -> Result<(), String> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let mut tests = get_tests();
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnv::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnv::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+    Ok(())
+}
+
+fn get_tests() -> Vec<test_utils::ShadowTest<(), String>> {
+    let mut tests: Vec<test_utils::ShadowTest<_, _>> = vec![
+        test_utils::ShadowTest::new(
+            "test_rights_stream_boundary",
+            test_rights_stream_boundary,
+            set![TestEnv::Libc, TestEnv::Shadow],
+        ),
+        test_utils::ShadowTest::new(
+            "test_peercred_connect",
+            test_peercred_connect,
+            set![TestEnv::Libc, TestEnv::Shadow],
+        ),
+    ];
+
+    for &sock_type in &[SockType::Stream, SockType::Datagram, SockType::SeqPacket] {
+        let append_args = |s| format!("{s} <type={sock_type:?}>");
+
+        tests.extend(vec![
+            test_utils::ShadowTest::new(
+                &append_args("test_rights"),
+                move || test_rights(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_rights_after_close"),
+                move || test_rights_after_close(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_rights_cloexec"),
+                move || test_rights_cloexec(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_rights_truncated"),
+                move || test_rights_truncated(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_rights_bad_fd"),
+                move || test_rights_bad_fd(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_credentials"),
+                move || test_credentials(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_credentials_without_passcred"),
+                move || test_credentials_without_passcred(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_credentials_bad_pid"),
+                move || test_credentials_bad_pid(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_passcred_sockopt"),
+                move || test_passcred_sockopt(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+            test_utils::ShadowTest::new(
+                &append_args("test_peercred_socketpair"),
+                move || test_peercred_socketpair(sock_type),
+                set![TestEnv::Libc, TestEnv::Shadow],
+            ),
+        ]);
+    }
+
+    tests
+}
+
+fn socketpair(sock_type: SockType) -> Result<(RawFd, RawFd), String> {
+    socket::socketpair(AddressFamily::Unix, sock_type, None, SockFlag::empty())
+        .map_err(|e| e.to_string())
+}
+
+/// Send `bytes` with the given control messages.
+fn send(fd: RawFd, bytes: &[u8], cmsgs: &[ControlMessage]) -> Result<usize, Errno> {
+    socket::sendmsg::<()>(fd, &[IoSlice::new(bytes)], cmsgs, MsgFlags::empty(), None)
+}
+
+/// Receive a message into a buffer of length `len`, with a control buffer of length
+/// `cmsg_len`. Returns the bytes, the received control messages, and the message flags.
+fn recv(
+    fd: RawFd,
+    len: usize,
+    cmsg_len: usize,
+    flags: MsgFlags,
+) -> Result<(Vec<u8>, Vec<ControlMessageOwned>, MsgFlags), Errno> {
+    let mut buf = vec![0u8; len];
+    let mut cmsg_buf = Vec::with_capacity(cmsg_len);
+
+    let msg = socket::recvmsg::<()>(
+        fd,
+        &mut [IoSliceMut::new(&mut buf)],
+        Some(&mut cmsg_buf),
+        flags,
+    )?;
+
+    let cmsgs = msg.cmsgs().collect();
+    let msg_flags = msg.flags;
+    let bytes = msg.bytes;
+
+    buf.truncate(bytes);
+    Ok((buf, cmsgs, msg_flags))
+}
+
+/// Returns the fds from the received control messages.
+fn received_fds(cmsgs: &[ControlMessageOwned]) -> Vec<RawFd> {
+    cmsgs
+        .iter()
+        .flat_map(|x| match x {
+            ControlMessageOwned::ScmRights(fds) => fds.clone(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// Check that `read_fd` reads the bytes written to `write_fd`.
+fn check_pipe(write_fd: RawFd, read_fd: RawFd) -> Result<(), String> {
+    test_utils::result_assert_eq(unistd::write(write_fd, b"hello"), Ok(5), "write failed")?;
+
+    let mut buf = [0u8; 10];
+    let n = unistd::read(read_fd, &mut buf).map_err(|e| e.to_string())?;
+    test_utils::result_assert_eq(&buf[..n], b"hello", "unexpected pipe data")
+}
+
+/// Test sending a pipe's read end to the peer.
+fn test_rights(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+    let (read_fd, write_fd) = unistd::pipe().map_err(|e| e.to_string())?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b, read_fd, write_fd], || {
+        let rv = send(fd_a, b"x", &[ControlMessage::ScmRights(&[read_fd])]);
+        test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+        let (bytes, cmsgs, flags) = recv(fd_b, 10, nix::cmsg_space!([RawFd; 1]), MsgFlags::empty())
+            .map_err(|e| e.to_string())?;
+        test_utils::result_assert_eq(&bytes[..], b"x", "unexpected message")?;
+        test_utils::result_assert(!flags.contains(MsgFlags::MSG_CTRUNC), "MSG_CTRUNC set")?;
+
+        let fds = received_fds(&cmsgs);
+        test_utils::result_assert_eq(fds.len(), 1, "unexpected number of fds")?;
+        let new_fd = fds[0];
+
+        test_utils::run_and_close_fds(&[new_fd], || {
+            // the received fd is a new descriptor for the same pipe
+            test_utils::result_assert_ne(new_fd, read_fd, "received the same fd")?;
+            check_pipe(write_fd, new_fd)
+        })
+    })
+}
+
+/// Test that a sent file stays open after the sender closes it.
+fn test_rights_after_close(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+    let (read_fd, write_fd) = unistd::pipe().map_err(|e| e.to_string())?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b, write_fd], || {
+        let rv = send(fd_a, b"x", &[ControlMessage::ScmRights(&[read_fd])]);
+        test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+        // close the original descriptor while the file is in flight
+        unistd::close(read_fd).map_err(|e| e.to_string())?;
+
+        let (_, cmsgs, _) = recv(fd_b, 10, nix::cmsg_space!([RawFd; 1]), MsgFlags::empty())
+            .map_err(|e| e.to_string())?;
+
+        let fds = received_fds(&cmsgs);
+        test_utils::result_assert_eq(fds.len(), 1, "unexpected number of fds")?;
+
+        test_utils::run_and_close_fds(&fds, || check_pipe(write_fd, fds[0]))
+    })
+}
+
+/// Test that `MSG_CMSG_CLOEXEC` sets `FD_CLOEXEC` on received descriptors.
+fn test_rights_cloexec(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+    let (read_fd, write_fd) = unistd::pipe().map_err(|e| e.to_string())?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b, read_fd, write_fd], || {
+        for flags in [MsgFlags::empty(), MsgFlags::MSG_CMSG_CLOEXEC] {
+            let rv = send(fd_a, b"x", &[ControlMessage::ScmRights(&[read_fd])]);
+            test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+            let (_, cmsgs, _) =
+                recv(fd_b, 10, nix::cmsg_space!([RawFd; 1]), flags).map_err(|e| e.to_string())?;
+
+            let fds = received_fds(&cmsgs);
+            test_utils::result_assert_eq(fds.len(), 1, "unexpected number of fds")?;
+
+            test_utils::run_and_close_fds(&fds, || {
+                let fd_flags = fcntl(fds[0], FcntlArg::F_GETFD).map_err(|e| e.to_string())?;
+                let fd_flags = FdFlag::from_bits_truncate(fd_flags);
+                test_utils::result_assert_eq(
+                    fd_flags.contains(FdFlag::FD_CLOEXEC),
+                    flags.contains(MsgFlags::MSG_CMSG_CLOEXEC),
+                    "unexpected FD_CLOEXEC flag",
+                )
+            })?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Test receiving more fds than there is room for in the control buffer.
+fn test_rights_truncated(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+    let (read_fd, write_fd) = unistd::pipe().map_err(|e| e.to_string())?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b, read_fd, write_fd], || {
+        let rv = send(
+            fd_a,
+            b"x",
+            &[ControlMessage::ScmRights(&[read_fd, write_fd, read_fd])],
+        );
+        test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+        // not enough room for all of the fds (the control buffer is padded, so there may be room
+        // for more than one)
+        let (_, cmsgs, flags) = recv(fd_b, 10, nix::cmsg_space!([RawFd; 1]), MsgFlags::empty())
+            .map_err(|e| e.to_string())?;
+        test_utils::result_assert(flags.contains(MsgFlags::MSG_CTRUNC), "MSG_CTRUNC not set")?;
+
+        let fds = received_fds(&cmsgs);
+        test_utils::run_and_close_fds(&fds, || {
+            test_utils::result_assert(
+                !fds.is_empty() && fds.len() < 3,
+                &format!("unexpected number of fds: {}", fds.len()),
+            )
+        })?;
+
+        // no room for any fds
+        let rv = send(fd_a, b"x", &[ControlMessage::ScmRights(&[read_fd])]);
+        test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+        let (bytes, cmsgs, flags) =
+            recv(fd_b, 10, 0, MsgFlags::empty()).map_err(|e| e.to_string())?;
+        test_utils::result_assert_eq(&bytes[..], b"x", "unexpected message")?;
+        test_utils::result_assert(flags.contains(MsgFlags::MSG_CTRUNC), "MSG_CTRUNC not set")?;
+        test_utils::result_assert(cmsgs.is_empty(), "received control messages")
+    })
+}
+
+/// Test sending an fd that isn't open.
+fn test_rights_bad_fd(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b], || {
+        let rv = send(fd_a, b"x", &[ControlMessage::ScmRights(&[fd_a, 1000])]);
+        test_utils::result_assert_eq(rv, Err(Errno::EBADF), "unexpected sendmsg result")?;
+
+        // nothing was sent
+        let rv = recv(fd_b, 10, 0, MsgFlags::MSG_DONTWAIT).map(|_| ());
+        test_utils::result_assert_eq(rv, Err(Errno::EAGAIN), "unexpected recvmsg result")
+    })
+}
+
+/// Test that data sent with fds on a stream socket isn't combined with other data.
+fn test_rights_stream_boundary() -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(SockType::Stream)?;
+    let (read_fd, write_fd) = unistd::pipe().map_err(|e| e.to_string())?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b, read_fd, write_fd], || {
+        let rv = send(fd_a, b"ab", &[ControlMessage::ScmRights(&[read_fd])]);
+        test_utils::result_assert_eq(rv, Ok(2), "sendmsg failed")?;
+        let rv = send(fd_a, b"cd", &[]);
+        test_utils::result_assert_eq(rv, Ok(2), "sendmsg failed")?;
+
+        // the read stops after the data that was sent with the fd
+        let (bytes, cmsgs, _) = recv(fd_b, 10, nix::cmsg_space!([RawFd; 1]), MsgFlags::empty())
+            .map_err(|e| e.to_string())?;
+        let fds = received_fds(&cmsgs);
+        test_utils::run_and_close_fds(&fds, || {
+            test_utils::result_assert_eq(&bytes[..], b"ab", "unexpected message")?;
+            test_utils::result_assert_eq(fds.len(), 1, "unexpected number of fds")
+        })?;
+
+        let (bytes, cmsgs, _) = recv(fd_b, 10, nix::cmsg_space!([RawFd; 1]), MsgFlags::empty())
+            .map_err(|e| e.to_string())?;
+        test_utils::result_assert_eq(&bytes[..], b"cd", "unexpected message")?;
+        test_utils::result_assert(cmsgs.is_empty(), "received control messages")
+    })
+}
+
+/// Test receiving credentials with `SO_PASSCRED`.
+fn test_credentials(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b], || {
+        socket::setsockopt(fd_b, sockopt::PassCred, &true).map_err(|e| e.to_string())?;
+
+        let expected = UnixCredentials::new();
+
+        // credentials are received whether or not the sender sent them
+        for cmsgs in [vec![], vec![ControlMessage::ScmCredentials(&expected)]] {
+            let rv = send(fd_a, b"x", &cmsgs);
+            test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+            let (_, cmsgs, _) = recv(fd_b, 10, nix::cmsg_space!(libc::ucred), MsgFlags::empty())
+                .map_err(|e| e.to_string())?;
+
+            let creds: Vec<_> = cmsgs
+                .iter()
+                .filter_map(|x| match x {
+                    ControlMessageOwned::ScmCredentials(cred) => Some(cred),
+                    _ => None,
+                })
+                .collect();
+
+            test_utils::result_assert_eq(creds.len(), 1, "unexpected number of credentials")?;
+            test_utils::result_assert_eq(creds[0].pid(), expected.pid(), "unexpected pid")?;
+            test_utils::result_assert_eq(creds[0].uid(), expected.uid(), "unexpected uid")?;
+            test_utils::result_assert_eq(creds[0].gid(), expected.gid(), "unexpected gid")?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Test that credentials aren't received without `SO_PASSCRED`.
+fn test_credentials_without_passcred(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b], || {
+        let cred = UnixCredentials::new();
+        let rv = send(fd_a, b"x", &[ControlMessage::ScmCredentials(&cred)]);
+        test_utils::result_assert_eq(rv, Ok(1), "sendmsg failed")?;
+
+        let (bytes, cmsgs, _) = recv(fd_b, 10, nix::cmsg_space!(libc::ucred), MsgFlags::empty())
+            .map_err(|e| e.to_string())?;
+        test_utils::result_assert_eq(&bytes[..], b"x", "unexpected message")?;
+        test_utils::result_assert(cmsgs.is_empty(), "received control messages")
+    })
+}
+
+/// Test sending credentials with a pid that isn't our own.
+fn test_credentials_bad_pid(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b], || {
+        // linux allows any pid with `CAP_SYS_ADMIN`, but shadow doesn't emulate capabilities
+        if !test_utils::running_in_shadow() && unistd::geteuid().is_root() {
+            return Ok(());
+        }
+
+        let own = UnixCredentials::new();
+        let cred = UnixCredentials::from(libc::ucred {
+            pid: own.pid() + 1,
+            uid: own.uid(),
+            gid: own.gid(),
+        });
+
+        let rv = send(fd_a, b"x", &[ControlMessage::ScmCredentials(&cred)]);
+        test_utils::result_assert_eq(rv, Err(Errno::EPERM), "unexpected sendmsg result")
+    })
+}
+
+/// Test getting and setting `SO_PASSCRED`.
+fn test_passcred_sockopt(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b], || {
+        for val in [false, true, false] {
+            socket::setsockopt(fd_a, sockopt::PassCred, &val).map_err(|e| e.to_string())?;
+
+            let rv = socket::getsockopt(fd_a, sockopt::PassCred);
+            test_utils::result_assert_eq(rv, Ok(val), "unexpected SO_PASSCRED value")?;
+        }
+
+        // the peer's option is unaffected
+        let rv = socket::getsockopt(fd_b, sockopt::PassCred);
+        test_utils::result_assert_eq(rv, Ok(false), "unexpected SO_PASSCRED value")
+    })
+}
+
+/// Test `SO_PEERCRED` for sockets created with `socketpair()`.
+fn test_peercred_socketpair(sock_type: SockType) -> Result<(), String> {
+    let (fd_a, fd_b) = socketpair(sock_type)?;
+
+    test_utils::run_and_close_fds(&[fd_a, fd_b], || {
+        let own = UnixCredentials::new();
+
+        for fd in [fd_a, fd_b] {
+            let cred =
+                socket::getsockopt(fd, sockopt::PeerCredentials).map_err(|e| e.to_string())?;
+            test_utils::result_assert_eq(cred.pid(), own.pid(), "unexpected pid")?;
+            test_utils::result_assert_eq(cred.uid(), own.uid(), "unexpected uid")?;
+            test_utils::result_assert_eq(cred.gid(), own.gid(), "unexpected gid")?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Test `SO_PEERCRED` for connected stream sockets.
+fn test_peercred_connect() -> Result<(), String> {
+    let addr = UnixAddr::new_abstract(b"test_peercred_connect").unwrap();
+    let new_socket = || {
+        socket::socket(
+            AddressFamily::Unix,
+            SockType::Stream,
+            SockFlag::empty(),
+            None,
+        )
+        .map_err(|e| e.to_string())
+    };
+
+    let server = new_socket()?;
+    let client = new_socket()?;
+
+    test_utils::run_and_close_fds(&[server, client], || {
+        // a socket that isn't connected has no peer
+        let cred =
+            socket::getsockopt(client, sockopt::PeerCredentials).map_err(|e| e.to_string())?;
+        test_utils::result_assert_eq(cred.pid(), 0, "unexpected pid")?;
+
+        socket::bind(server, &addr).map_err(|e| e.to_string())?;
+        socket::listen(server, 10).map_err(|e| e.to_string())?;
+        socket::connect(client, &addr).map_err(|e| e.to_string())?;
+        let accepted = socket::accept(server).map_err(|e| e.to_string())?;
+
+        test_utils::run_and_close_fds(&[accepted], || {
+            let own = UnixCredentials::new();
+
+            for fd in [client, accepted] {
+                let cred =
+                    socket::getsockopt(fd, sockopt::PeerCredentials).map_err(|e| e.to_string())?;
+                test_utils::result_assert_eq(cred.pid(), own.pid(), "unexpected pid")?;
+            }
+
+            Ok(())
+        })
+    })
+}
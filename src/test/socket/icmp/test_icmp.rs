@@ -0,0 +1,422 @@
+use std::io::IoSliceMut;
+use std::os::fd::RawFd;
+
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags};
+use nix::sys::socket::{
+    self, sockopt, AddressFamily, ControlMessageOwned, MsgFlags, SockFlag, SockType, SockaddrIn,
+};
+use nix::unistd;
+
+use test_utils::{set, ShadowTest, TestEnvironment};
+
+const ICMP_ECHOREPLY: u8 = 0;
+const ICMP_DEST_UNREACH: u8 = 3;
+const ICMP_ECHO: u8 = 8;
+const ICMP_PORT_UNREACH: u8 = 3;
+
+const IP_HEADER_LEN: usize = 20;
+
+/// Create a new `IPPROTO_ICMP` socket. A `SOCK_DGRAM` socket is an unprivileged "ping" socket, and
+/// a `SOCK_RAW` socket requires `CAP_NET_RAW` outside of Shadow.
+fn icmp_socket(sock_type: libc::c_int) -> nix::Result<RawFd> {
+    Errno::result(unsafe { libc::socket(libc::AF_INET, sock_type, libc::IPPROTO_ICMP) })
+}
+
+/// The 16-bit one's complement checksum used by ICMP.
+fn internet_checksum(bytes: &[u8]) -> u16 {
+    let mut sum: u32 = bytes
+        .chunks(2)
+        .map(|x| u32::from(u16::from_be_bytes([x[0], *x.get(1).unwrap_or(&0)])))
+        .sum();
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Build an ICMP echo request message.
+fn echo_request(identifier: u16, sequence: u16, payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![ICMP_ECHO, 0, 0, 0];
+    message.extend(identifier.to_be_bytes());
+    message.extend(sequence.to_be_bytes());
+    message.extend(payload);
+
+    let checksum = internet_checksum(&message);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    message
+}
+
+/// Create a new UDP socket.
+fn udp_socket() -> nix::Result<RawFd> {
+    socket::socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::empty(),
+        None,
+    )
+}
+
+/// Find a UDP port on localhost that no socket is bound to.
+fn closed_udp_port() -> nix::Result<u16> {
+    let fd = udp_socket()?;
+    socket::bind(fd, &SockaddrIn::new(127, 0, 0, 1, 0))?;
+    let port = socket::getsockname::<SockaddrIn>(fd)?.port();
+    unistd::close(fd)?;
+    Ok(port)
+}
+
+/// Wait for up to `timeout_ms` milliseconds for the socket to have an error. Returns the poll
+/// events.
+fn poll_error(fd: RawFd, timeout_ms: libc::c_int) -> nix::Result<PollFlags> {
+    let mut fds = [PollFd::new(fd, PollFlags::empty())];
+    nix::poll::poll(&mut fds, timeout_ms)?;
+    Ok(fds[0].revents().unwrap())
+}
+
+/// Receive a message on a raw ICMP socket, skipping any that don't match `predicate`. The message
+/// includes the IP header.
+fn recv_raw_matching(fd: RawFd, predicate: impl Fn(&[u8]) -> bool) -> nix::Result<Vec<u8>> {
+    loop {
+        let mut buf = [0u8; 1024];
+        let (len, _addr) = socket::recvfrom::<SockaddrIn>(fd, &mut buf)?;
+        let message = &buf[..len];
+
+        if predicate(&message[IP_HEADER_LEN..]) {
+            return Ok(message.to_vec());
+        }
+    }
+}
+
+fn test_ping_localhost() -> anyhow::Result<()> {
+    let fd = icmp_socket(libc::SOCK_DGRAM)?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        let payload = b"hello";
+        let localhost = SockaddrIn::new(127, 0, 0, 1, 0);
+        socket::sendto(
+            fd,
+            &echo_request(0, 1, payload),
+            &localhost,
+            MsgFlags::empty(),
+        )?;
+
+        // the socket's identifier is its local port
+        let identifier = socket::getsockname::<SockaddrIn>(fd)?.port();
+        assert_ne!(identifier, 0);
+
+        let mut buf = [0u8; 100];
+        let (len, addr) = socket::recvfrom::<SockaddrIn>(fd, &mut buf)?;
+        let reply = &buf[..len];
+
+        // a ping socket receives the reply without the IP header
+        assert_eq!(addr.unwrap().ip(), u32::from(std::net::Ipv4Addr::LOCALHOST));
+        assert_eq!(len, 8 + payload.len());
+        assert_eq!(reply[0], ICMP_ECHOREPLY);
+        assert_eq!(reply[1], 0);
+        assert_eq!(internet_checksum(reply), 0);
+        assert_eq!(u16::from_be_bytes([reply[4], reply[5]]), identifier);
+        assert_eq!(u16::from_be_bytes([reply[6], reply[7]]), 1);
+        assert_eq!(&reply[8..], payload);
+
+        Ok(())
+    })
+}
+
+fn test_ping_invalid_type() -> anyhow::Result<()> {
+    let fd = icmp_socket(libc::SOCK_DGRAM)?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        let localhost = SockaddrIn::new(127, 0, 0, 1, 0);
+
+        // a ping socket can only send echo requests
+        let mut message = echo_request(0, 1, &[]);
+        message[0] = ICMP_ECHOREPLY;
+        assert_eq!(
+            socket::sendto(fd, &message, &localhost, MsgFlags::empty()),
+            Err(Errno::EINVAL)
+        );
+
+        // the message must contain an ICMP header
+        assert_eq!(
+            socket::sendto(fd, &[ICMP_ECHO, 0, 0, 0], &localhost, MsgFlags::empty()),
+            Err(Errno::EINVAL)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_raw_echo() -> anyhow::Result<()> {
+    let fd = icmp_socket(libc::SOCK_RAW)?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        let payload = b"hello";
+        let localhost = SockaddrIn::new(127, 0, 0, 1, 0);
+        socket::sendto(
+            fd,
+            &echo_request(0x1234, 7, payload),
+            &localhost,
+            MsgFlags::empty(),
+        )?;
+
+        // the raw socket may also receive the request, so wait for the reply
+        let reply = recv_raw_matching(fd, |x| x[0] == ICMP_ECHOREPLY)?;
+
+        // a raw socket receives the IP header
+        assert_eq!(reply[0] >> 4, 4);
+        assert_eq!(reply[9], libc::IPPROTO_ICMP as u8);
+        assert_eq!(reply.len(), IP_HEADER_LEN + 8 + payload.len());
+
+        let reply = &reply[IP_HEADER_LEN..];
+        assert_eq!(internet_checksum(reply), 0);
+        assert_eq!(u16::from_be_bytes([reply[4], reply[5]]), 0x1234);
+        assert_eq!(u16::from_be_bytes([reply[6], reply[7]]), 7);
+        assert_eq!(&reply[8..], payload);
+
+        Ok(())
+    })
+}
+
+fn test_udp_port_unreachable() -> anyhow::Result<()> {
+    let closed_port = closed_udp_port()?;
+    let raw_fd = icmp_socket(libc::SOCK_RAW)?;
+    let udp_fd = udp_socket()?;
+
+    test_utils::run_and_close_fds(&[raw_fd, udp_fd], || {
+        let dst = SockaddrIn::new(127, 0, 0, 1, closed_port);
+        socket::sendto(udp_fd, &[1, 2, 3], &dst, MsgFlags::empty())?;
+
+        let error = recv_raw_matching(raw_fd, |x| x[0] == ICMP_DEST_UNREACH)?;
+        let error = &error[IP_HEADER_LEN..];
+        assert_eq!(error[1], ICMP_PORT_UNREACH);
+
+        // the error contains the IP header and UDP header of the original datagram
+        let original = &error[8..];
+        assert_eq!(original[9], libc::IPPROTO_UDP as u8);
+        let udp_header = &original[IP_HEADER_LEN..];
+        assert_eq!(
+            u16::from_be_bytes([udp_header[2], udp_header[3]]),
+            closed_port
+        );
+
+        Ok(())
+    })
+}
+
+fn test_udp_connected_refused() -> anyhow::Result<()> {
+    let closed_port = closed_udp_port()?;
+    let fd = udp_socket()?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        socket::connect(fd, &SockaddrIn::new(127, 0, 0, 1, closed_port))?;
+        socket::send(fd, &[1, 2, 3], MsgFlags::empty())?;
+
+        // a blocked recv is woken up by the port unreachable error
+        let mut buf = [0u8; 10];
+        assert_eq!(
+            socket::recv(fd, &mut buf, MsgFlags::empty()),
+            Err(Errno::ECONNREFUSED)
+        );
+
+        // the error is only reported once
+        assert_eq!(
+            socket::recv(fd, &mut buf, MsgFlags::MSG_DONTWAIT),
+            Err(Errno::EAGAIN)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_udp_connected_so_error() -> anyhow::Result<()> {
+    let closed_port = closed_udp_port()?;
+    let fd = udp_socket()?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        socket::connect(fd, &SockaddrIn::new(127, 0, 0, 1, closed_port))?;
+        socket::send(fd, &[1, 2, 3], MsgFlags::empty())?;
+
+        assert_eq!(poll_error(fd, 1000)?, PollFlags::POLLERR);
+
+        // reading the error clears it
+        assert_eq!(
+            socket::getsockopt(fd, sockopt::SocketError)?,
+            libc::ECONNREFUSED
+        );
+        assert_eq!(socket::getsockopt(fd, sockopt::SocketError)?, 0);
+        assert_eq!(poll_error(fd, 0)?, PollFlags::empty());
+
+        Ok(())
+    })
+}
+
+fn test_udp_unconnected_ignored() -> anyhow::Result<()> {
+    let closed_port = closed_udp_port()?;
+    let fd = udp_socket()?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        let dst = SockaddrIn::new(127, 0, 0, 1, closed_port);
+        socket::sendto(fd, &[1, 2, 3], &dst, MsgFlags::empty())?;
+
+        // without `IP_RECVERR`, an unconnected socket doesn't receive the error
+        assert_eq!(poll_error(fd, 100)?, PollFlags::empty());
+        assert_eq!(socket::getsockopt(fd, sockopt::SocketError)?, 0);
+
+        let mut buf = [0u8; 10];
+        assert_eq!(
+            socket::recv(fd, &mut buf, MsgFlags::MSG_DONTWAIT),
+            Err(Errno::EAGAIN)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_udp_recverr() -> anyhow::Result<()> {
+    let closed_port = closed_udp_port()?;
+    let fd = udp_socket()?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        socket::setsockopt(fd, sockopt::Ipv4RecvErr, &true)?;
+
+        let payload = b"hello";
+        let dst = SockaddrIn::new(127, 0, 0, 1, closed_port);
+        socket::sendto(fd, payload, &dst, MsgFlags::empty())?;
+
+        assert_eq!(poll_error(fd, 1000)?, PollFlags::POLLERR);
+
+        let mut buf = [0u8; 100];
+        let mut iov = [IoSliceMut::new(&mut buf)];
+        let mut cmsg_buf = nix::cmsg_space!(libc::sock_extended_err, libc::sockaddr_in);
+        let msg = socket::recvmsg::<SockaddrIn>(
+            fd,
+            &mut iov,
+            Some(&mut cmsg_buf),
+            MsgFlags::MSG_ERRQUEUE,
+        )?;
+
+        assert!(msg.flags.contains(MsgFlags::MSG_ERRQUEUE));
+        assert_eq!(msg.bytes, payload.len());
+        // the address is the destination of the datagram that caused the error
+        assert_eq!(msg.address, Some(dst));
+
+        let cmsgs: Vec<_> = msg.cmsgs().collect();
+        let [ControlMessageOwned::Ipv4RecvErr(err, offender)] = &cmsgs[..] else {
+            panic!("Unexpected control messages: {cmsgs:?}");
+        };
+        assert_eq!(err.ee_errno, libc::ECONNREFUSED as u32);
+        assert_eq!(err.ee_origin, libc::SO_EE_ORIGIN_ICMP);
+        assert_eq!(err.ee_type, ICMP_DEST_UNREACH);
+        assert_eq!(err.ee_code, ICMP_PORT_UNREACH);
+        assert_eq!(
+            offender.unwrap().sin_addr.s_addr,
+            u32::from(std::net::Ipv4Addr::LOCALHOST).to_be()
+        );
+
+        // the message is the payload of the original datagram
+        assert_eq!(&buf[..payload.len()], payload);
+
+        // the error queue is now empty and there's no pending error
+        let mut iov = [IoSliceMut::new(&mut buf)];
+        assert_eq!(
+            socket::recvmsg::<SockaddrIn>(fd, &mut iov, None, MsgFlags::MSG_ERRQUEUE).err(),
+            Some(Errno::EAGAIN)
+        );
+        assert_eq!(socket::getsockopt(fd, sockopt::SocketError)?, 0);
+        assert_eq!(poll_error(fd, 0)?, PollFlags::empty());
+
+        Ok(())
+    })
+}
+
+fn test_ip_ttl(fd: RawFd) -> anyhow::Result<()> {
+    test_utils::run_and_close_fds(&[fd], || {
+        assert_eq!(socket::getsockopt(fd, sockopt::Ipv4Ttl)?, 64);
+
+        socket::setsockopt(fd, sockopt::Ipv4Ttl, &5)?;
+        assert_eq!(socket::getsockopt(fd, sockopt::Ipv4Ttl)?, 5);
+
+        for invalid in [0, 256, -2] {
+            assert_eq!(
+                socket::setsockopt(fd, sockopt::Ipv4Ttl, &invalid),
+                Err(Errno::EINVAL)
+            );
+        }
+
+        // -1 resets the TTL to the default
+        socket::setsockopt(fd, sockopt::Ipv4Ttl, &-1)?;
+        assert_eq!(socket::getsockopt(fd, sockopt::Ipv4Ttl)?, 64);
+
+        Ok(())
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let all_envs = set![TestEnvironment::Libc, TestEnvironment::Shadow];
+    // raw sockets require privileges outside of Shadow
+    let shadow_only = set![TestEnvironment::Shadow];
+
+    let mut tests: Vec<test_utils::ShadowTest<(), anyhow::Error>> = vec![
+        ShadowTest::new("test_ping_localhost", test_ping_localhost, all_envs.clone()),
+        ShadowTest::new(
+            "test_ping_invalid_type",
+            test_ping_invalid_type,
+            all_envs.clone(),
+        ),
+        ShadowTest::new("test_raw_echo", test_raw_echo, shadow_only.clone()),
+        ShadowTest::new(
+            "test_udp_port_unreachable",
+            test_udp_port_unreachable,
+            shadow_only,
+        ),
+        ShadowTest::new(
+            "test_udp_connected_refused",
+            test_udp_connected_refused,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_udp_connected_so_error",
+            test_udp_connected_so_error,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_udp_unconnected_ignored",
+            test_udp_unconnected_ignored,
+            all_envs.clone(),
+        ),
+        ShadowTest::new("test_udp_recverr", test_udp_recverr, all_envs.clone()),
+        ShadowTest::new(
+            "test_ip_ttl <type=udp>",
+            || test_ip_ttl(udp_socket()?),
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_ip_ttl <type=ping>",
+            || test_ip_ttl(icmp_socket(libc::SOCK_DGRAM)?),
+            all_envs,
+        ),
+    ];
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+
+    Ok(())
+}
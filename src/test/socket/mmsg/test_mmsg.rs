@@ -0,0 +1,362 @@
+use nix::errno::Errno;
+use nix::sys::socket::{AddressFamily, SockFlag, SockType};
+use nix::unistd;
+
+use test_utils::{set, ShadowTest, TestEnvironment};
+
+/// Build `mmsghdr` structs and their iovecs for the given buffers. The returned iovecs must
+/// outlive any use of the returned messages.
+fn build_msgs(bufs: &mut [Vec<u8>]) -> (Vec<libc::iovec>, Vec<libc::mmsghdr>) {
+    let iovs: Vec<libc::iovec> = bufs
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut _,
+            iov_len: buf.len(),
+        })
+        .collect();
+
+    let msgs = iovs
+        .iter()
+        .map(|iov| {
+            let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+            hdr.msg_iov = iov as *const _ as *mut _;
+            hdr.msg_iovlen = 1;
+            libc::mmsghdr {
+                msg_hdr: hdr,
+                msg_len: 0,
+            }
+        })
+        .collect();
+
+    (iovs, msgs)
+}
+
+fn sendmmsg(
+    fd: libc::c_int,
+    msgs: &mut [libc::mmsghdr],
+    flags: libc::c_int,
+) -> nix::Result<libc::c_int> {
+    Errno::result(unsafe {
+        libc::sendmmsg(fd, msgs.as_mut_ptr(), msgs.len() as libc::c_uint, flags)
+    })
+}
+
+fn recvmmsg(
+    fd: libc::c_int,
+    msgs: &mut [libc::mmsghdr],
+    flags: libc::c_int,
+    timeout: Option<&mut libc::timespec>,
+) -> nix::Result<libc::c_int> {
+    let timeout = timeout.map_or(std::ptr::null_mut(), |x| x as *mut _);
+    Errno::result(unsafe {
+        libc::recvmmsg(
+            fd,
+            msgs.as_mut_ptr(),
+            msgs.len() as libc::c_uint,
+            flags,
+            timeout,
+        )
+    })
+}
+
+/// Run `f` with a new unix datagram socket pair, and close the sockets afterwards.
+fn with_dgram_socketpair(
+    f: impl FnOnce(libc::c_int, libc::c_int) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let (fd_1, fd_2) = nix::sys::socket::socketpair(
+        AddressFamily::Unix,
+        SockType::Datagram,
+        None,
+        SockFlag::empty(),
+    )?;
+
+    test_utils::run_and_close_fds(&[fd_1, fd_2], || f(fd_1, fd_2))
+}
+
+/// Send three messages of different lengths from `fd` using `sendmmsg()`.
+fn send_three(fd: libc::c_int) -> anyhow::Result<()> {
+    let mut bufs = vec![b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()];
+    let (_iovs, mut msgs) = build_msgs(&mut bufs);
+
+    assert_eq!(sendmmsg(fd, &mut msgs, 0), Ok(3));
+
+    let lens: Vec<_> = msgs.iter().map(|x| x.msg_len).collect();
+    assert_eq!(lens, [1, 2, 3]);
+
+    Ok(())
+}
+
+fn test_unix_dgram() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        send_three(fd_1)?;
+
+        // fewer messages than are available
+        let mut bufs = vec![vec![0u8; 10]; 2];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(recvmmsg(fd_2, &mut msgs, 0, None), Ok(2));
+        assert_eq!((msgs[0].msg_len, msgs[1].msg_len), (1, 2));
+        assert_eq!(&bufs[0][..1], b"a");
+        assert_eq!(&bufs[1][..2], b"bb");
+
+        // more messages than are available
+        let mut bufs = vec![vec![0u8; 10]; 4];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(recvmmsg(fd_2, &mut msgs, libc::MSG_WAITFORONE, None), Ok(1));
+        assert_eq!(msgs[0].msg_len, 3);
+        assert_eq!(&bufs[0][..3], b"ccc");
+
+        // no messages are available
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(
+            recvmmsg(fd_2, &mut msgs, libc::MSG_DONTWAIT, None),
+            Err(Errno::EAGAIN)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_truncated() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        send_three(fd_1)?;
+
+        // buffers smaller than the messages
+        let mut bufs = vec![vec![0u8; 1]; 3];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(recvmmsg(fd_2, &mut msgs, 0, None), Ok(3));
+
+        let lens: Vec<_> = msgs.iter().map(|x| x.msg_len).collect();
+        assert_eq!(lens, [1, 1, 1]);
+        assert_eq!(bufs, [b"a", b"b", b"c"]);
+
+        Ok(())
+    })
+}
+
+fn test_timeout() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        send_three(fd_1)?;
+
+        // the timeout is only checked after a message is received
+        let mut timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let mut bufs = vec![vec![0u8; 10]; 3];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(recvmmsg(fd_2, &mut msgs, 0, Some(&mut timeout)), Ok(1));
+        assert_eq!(msgs[0].msg_len, 1);
+
+        // a long timeout doesn't stop us from receiving the remaining messages
+        let mut timeout = libc::timespec {
+            tv_sec: 10,
+            tv_nsec: 0,
+        };
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(
+            recvmmsg(fd_2, &mut msgs, libc::MSG_WAITFORONE, Some(&mut timeout)),
+            Ok(2)
+        );
+        assert_eq!((msgs[0].msg_len, msgs[1].msg_len), (2, 3));
+
+        // the remaining time is written back
+        assert!(timeout.tv_sec <= 10);
+        assert!(timeout.tv_sec > 0);
+
+        // an invalid timeout
+        let mut timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 2_000_000_000,
+        };
+        send_three(fd_1)?;
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(
+            recvmmsg(fd_2, &mut msgs, 0, Some(&mut timeout)),
+            Err(Errno::EINVAL)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_empty() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        assert_eq!(sendmmsg(fd_1, &mut [], 0), Ok(0));
+        assert_eq!(recvmmsg(fd_2, &mut [], 0, None), Ok(0));
+
+        Ok(())
+    })
+}
+
+fn test_blocking() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        let t = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            send_three(fd_1)
+        });
+
+        // blocks until the first message arrives
+        let mut bufs = vec![vec![0u8; 10]; 3];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        let count = recvmmsg(fd_2, &mut msgs, libc::MSG_WAITFORONE, None)?;
+        assert!(count >= 1);
+        assert_eq!(&bufs[0][..1], b"a");
+
+        t.join().unwrap()?;
+
+        Ok(())
+    })
+}
+
+/// Send each message from `fd` after sleeping for its delay, measured from when the previous
+/// message was sent.
+fn send_delayed(
+    fd: libc::c_int,
+    msgs: &'static [(u64, &'static [u8])],
+) -> std::thread::JoinHandle<anyhow::Result<()>> {
+    std::thread::spawn(move || {
+        for (delay_ms, msg) in msgs {
+            std::thread::sleep(std::time::Duration::from_millis(*delay_ms));
+            assert_eq!(unistd::write(fd, msg)?, msg.len());
+        }
+        Ok(())
+    })
+}
+
+fn test_blocking_all() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        let t = send_delayed(fd_1, &[(100, b"a"), (100, b"bb")]);
+
+        // without MSG_WAITFORONE, blocks until all of the messages have arrived
+        let mut bufs = vec![vec![0u8; 10]; 2];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(recvmmsg(fd_2, &mut msgs, 0, None), Ok(2));
+        assert_eq!((msgs[0].msg_len, msgs[1].msg_len), (1, 2));
+
+        t.join().unwrap()?;
+
+        Ok(())
+    })
+}
+
+fn test_blocking_timeout() -> anyhow::Result<()> {
+    with_dgram_socketpair(|fd_1, fd_2| {
+        // two messages arrive before the timeout, and one after
+        let t = send_delayed(fd_1, &[(100, b"a"), (100, b"bb"), (400, b"ccc")]);
+
+        let mut timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 400_000_000,
+        };
+        let mut bufs = vec![vec![0u8; 10]; 3];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+
+        // blocks for more messages until the timeout expires
+        let start = std::time::Instant::now();
+        assert_eq!(recvmmsg(fd_2, &mut msgs, 0, Some(&mut timeout)), Ok(2));
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= std::time::Duration::from_millis(400),
+            "{elapsed:?}"
+        );
+        assert!(
+            elapsed < std::time::Duration::from_millis(600),
+            "{elapsed:?}"
+        );
+        assert_eq!((msgs[0].msg_len, msgs[1].msg_len), (1, 2));
+        assert_eq!((timeout.tv_sec, timeout.tv_nsec), (0, 0));
+
+        // the late message is still received
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        assert_eq!(recvmmsg(fd_2, &mut msgs, libc::MSG_WAITFORONE, None), Ok(1));
+        assert_eq!(msgs[0].msg_len, 3);
+
+        t.join().unwrap()?;
+
+        Ok(())
+    })
+}
+
+fn test_udp() -> anyhow::Result<()> {
+    let server = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    let client = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    client.connect(server.local_addr()?)?;
+
+    send_three(std::os::fd::AsRawFd::as_raw_fd(&client))?;
+
+    // the messages may not all have arrived yet
+    let mut received = Vec::new();
+    while received.len() < 3 {
+        let mut bufs = vec![vec![0u8; 10]; 3];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+        let count = recvmmsg(
+            std::os::fd::AsRawFd::as_raw_fd(&server),
+            &mut msgs,
+            libc::MSG_WAITFORONE,
+            None,
+        )?;
+
+        for (msg, buf) in msgs.iter().zip(&bufs).take(count as usize) {
+            received.push(buf[..msg.msg_len as usize].to_vec());
+        }
+    }
+
+    assert_eq!(received, [b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()]);
+
+    Ok(())
+}
+
+fn test_not_socket() -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+
+    test_utils::run_and_close_fds(&[read_fd, write_fd], || {
+        let mut bufs = vec![vec![0u8; 10]; 1];
+        let (_iovs, mut msgs) = build_msgs(&mut bufs);
+
+        assert_eq!(sendmmsg(write_fd, &mut msgs, 0), Err(Errno::ENOTSOCK));
+        assert_eq!(recvmmsg(read_fd, &mut msgs, 0, None), Err(Errno::ENOTSOCK));
+        assert_eq!(sendmmsg(-1, &mut msgs, 0), Err(Errno::EBADF));
+
+        Ok(())
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let all_envs = set![TestEnvironment::Libc, TestEnvironment::Shadow];
+    let mut tests: Vec<test_utils::ShadowTest<(), anyhow::Error>> = vec![
+        ShadowTest::new("test_unix_dgram", test_unix_dgram, all_envs.clone()),
+        ShadowTest::new("test_truncated", test_truncated, all_envs.clone()),
+        ShadowTest::new("test_timeout", test_timeout, all_envs.clone()),
+        ShadowTest::new("test_empty", test_empty, all_envs.clone()),
+        ShadowTest::new("test_blocking", test_blocking, all_envs.clone()),
+        ShadowTest::new("test_blocking_all", test_blocking_all, all_envs.clone()),
+        // linux only checks the timeout after receiving a message, so it would wait for the late
+        // message
+        ShadowTest::new(
+            "test_blocking_timeout",
+            test_blocking_timeout,
+            set![TestEnvironment::Shadow],
+        ),
+        ShadowTest::new("test_udp", test_udp, all_envs.clone()),
+        ShadowTest::new("test_not_socket", test_not_socket, all_envs),
+    ];
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::os::fd::RawFd;
+
+use nix::errno::Errno;
+use nix::sys::socket::{self, sockopt, AddressFamily, MsgFlags, SockFlag, SockType, SockaddrIn};
+use nix::unistd;
+
+use test_utils::{set, ShadowTest, TestEnvironment};
+
+#[derive(Debug, Copy, Clone)]
+struct Reuse {
+    addr: bool,
+    port: bool,
+}
+
+impl Reuse {
+    const NONE: Self = Self {
+        addr: false,
+        port: false,
+    };
+    const ADDR: Self = Self {
+        addr: true,
+        port: false,
+    };
+    const PORT: Self = Self {
+        addr: false,
+        port: true,
+    };
+}
+
+/// Create a new socket with the given reuse options.
+fn new_socket(sock_type: SockType, reuse: Reuse) -> nix::Result<RawFd> {
+    let fd = socket::socket(AddressFamily::Inet, sock_type, SockFlag::empty(), None)?;
+    socket::setsockopt(fd, sockopt::ReuseAddr, &reuse.addr)?;
+    socket::setsockopt(fd, sockopt::ReusePort, &reuse.port)?;
+    Ok(fd)
+}
+
+/// Create a new socket with the given reuse options and bind it to `port` on the loopback
+/// interface. If `port` is 0, an ephemeral port will be used.
+fn new_bound_socket(sock_type: SockType, reuse: Reuse, port: u16) -> nix::Result<RawFd> {
+    let fd = new_socket(sock_type, reuse)?;
+    if let Err(e) = socket::bind(fd, &SockaddrIn::new(127, 0, 0, 1, port)) {
+        unistd::close(fd).unwrap();
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+fn local_port(fd: RawFd) -> u16 {
+    socket::getsockname::<SockaddrIn>(fd).unwrap().port()
+}
+
+fn close_all(fds: &[RawFd]) {
+    for fd in fds {
+        unistd::close(*fd).unwrap();
+    }
+}
+
+fn test_getsockopt(sock_type: SockType) -> anyhow::Result<()> {
+    let fd = new_socket(sock_type, Reuse::NONE)?;
+
+    test_utils::run_and_close_fds(&[fd], || {
+        assert!(!socket::getsockopt(fd, sockopt::ReuseAddr)?);
+        assert!(!socket::getsockopt(fd, sockopt::ReusePort)?);
+
+        socket::setsockopt(fd, sockopt::ReuseAddr, &true)?;
+        assert!(socket::getsockopt(fd, sockopt::ReuseAddr)?);
+        assert!(!socket::getsockopt(fd, sockopt::ReusePort)?);
+
+        socket::setsockopt(fd, sockopt::ReusePort, &true)?;
+        assert!(socket::getsockopt(fd, sockopt::ReusePort)?);
+
+        Ok(())
+    })
+}
+
+fn test_reuseport_bind(sock_type: SockType) -> anyhow::Result<()> {
+    let fd_1 = new_bound_socket(sock_type, Reuse::PORT, 0)?;
+    let port = local_port(fd_1);
+
+    let fd_2 = new_bound_socket(sock_type, Reuse::PORT, port)?;
+
+    test_utils::run_and_close_fds(&[fd_1, fd_2], || {
+        // every socket must set SO_REUSEPORT
+        assert_eq!(
+            new_bound_socket(sock_type, Reuse::NONE, port),
+            Err(Errno::EADDRINUSE)
+        );
+        assert_eq!(
+            new_bound_socket(sock_type, Reuse::ADDR, port),
+            Err(Errno::EADDRINUSE)
+        );
+
+        Ok(())
+    })?;
+
+    // the port is free once all sockets are closed
+    let fd_3 = new_bound_socket(sock_type, Reuse::NONE, port)?;
+    close_all(&[fd_3]);
+
+    Ok(())
+}
+
+fn test_reuseaddr_udp() -> anyhow::Result<()> {
+    let fd_1 = new_bound_socket(SockType::Datagram, Reuse::ADDR, 0)?;
+    let port = local_port(fd_1);
+
+    let fd_2 = new_bound_socket(SockType::Datagram, Reuse::ADDR, port)?;
+
+    test_utils::run_and_close_fds(&[fd_1, fd_2], || {
+        assert_eq!(
+            new_bound_socket(SockType::Datagram, Reuse::NONE, port),
+            Err(Errno::EADDRINUSE)
+        );
+        Ok(())
+    })
+}
+
+fn test_reuseaddr_tcp_listening() -> anyhow::Result<()> {
+    let fd_1 = new_bound_socket(SockType::Stream, Reuse::ADDR, 0)?;
+    let port = local_port(fd_1);
+
+    // a socket that isn't listening can share the address
+    let fd_2 = new_bound_socket(SockType::Stream, Reuse::ADDR, port)?;
+    close_all(&[fd_2]);
+
+    test_utils::run_and_close_fds(&[fd_1], || {
+        socket::listen(fd_1, 10)?;
+
+        // but a listening socket can't
+        assert_eq!(
+            new_bound_socket(SockType::Stream, Reuse::ADDR, port),
+            Err(Errno::EADDRINUSE)
+        );
+
+        Ok(())
+    })
+}
+
+fn test_reuseaddr_time_wait() -> anyhow::Result<()> {
+    let listen_fd = new_bound_socket(SockType::Stream, Reuse::ADDR, 0)?;
+    let port = local_port(listen_fd);
+    socket::listen(listen_fd, 10)?;
+
+    let client_fd = new_socket(SockType::Stream, Reuse::NONE)?;
+    socket::connect(client_fd, &SockaddrIn::new(127, 0, 0, 1, port))?;
+    let server_fd = socket::accept(listen_fd)?;
+
+    // the server closes first, so its side of the connection will enter TIME_WAIT
+    unistd::close(server_fd)?;
+    assert_eq!(unistd::read(client_fd, &mut [0u8; 10]), Ok(0));
+    unistd::close(client_fd)?;
+
+    // give the FIN time to reach the server
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    unistd::close(listen_fd)?;
+
+    // a new server can bind to the same port
+    let listen_fd = new_bound_socket(SockType::Stream, Reuse::ADDR, port)?;
+
+    test_utils::run_and_close_fds(&[listen_fd], || {
+        socket::listen(listen_fd, 10)?;
+
+        // and accept new connections
+        let client_fd = new_socket(SockType::Stream, Reuse::NONE)?;
+        socket::connect(client_fd, &SockaddrIn::new(127, 0, 0, 1, port))?;
+        let server_fd = socket::accept(listen_fd)?;
+        close_all(&[client_fd, server_fd]);
+
+        Ok(())
+    })
+}
+
+fn test_reuseport_udp_distribution() -> anyhow::Result<()> {
+    const NUM_SERVERS: usize = 4;
+    const NUM_CLIENTS: usize = 16;
+
+    let mut server_fds = vec![new_bound_socket(SockType::Datagram, Reuse::PORT, 0)?];
+    let port = local_port(server_fds[0]);
+    for _ in 1..NUM_SERVERS {
+        server_fds.push(new_bound_socket(SockType::Datagram, Reuse::PORT, port)?);
+    }
+
+    let client_fds: Vec<_> = (0..NUM_CLIENTS)
+        .map(|_| new_bound_socket(SockType::Datagram, Reuse::NONE, 0))
+        .collect::<Result<_, _>>()?;
+
+    let all_fds: Vec<_> = server_fds.iter().chain(&client_fds).copied().collect();
+
+    test_utils::run_and_close_fds(&all_fds, || {
+        // each client sends two datagrams
+        let server_addr = SockaddrIn::new(127, 0, 0, 1, port);
+        for _ in 0..2 {
+            for fd in &client_fds {
+                socket::sendto(*fd, &[1, 2, 3], &server_addr, MsgFlags::empty())?;
+            }
+        }
+
+        // give the datagrams time to arrive
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // the server that received datagrams from each client port
+        let mut receivers = HashMap::<u16, Vec<usize>>::new();
+
+        for (index, fd) in server_fds.iter().enumerate() {
+            while has_data(*fd)? {
+                let mut buf = [0u8; 10];
+                let (len, addr) = socket::recvfrom::<SockaddrIn>(*fd, &mut buf)?;
+                assert_eq!(len, 3);
+                receivers
+                    .entry(addr.unwrap().port())
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        // every datagram was received, and datagrams from the same flow went to the same server
+        assert_eq!(receivers.len(), NUM_CLIENTS);
+        for servers in receivers.values() {
+            assert_eq!(servers.len(), 2);
+            assert_eq!(servers[0], servers[1]);
+        }
+
+        // the flows were distributed across the servers
+        let mut used: Vec<_> = receivers.values().map(|x| x[0]).collect();
+        used.sort();
+        used.dedup();
+        assert!(used.len() > 1);
+
+        Ok(())
+    })
+}
+
+fn test_reuseport_tcp_distribution() -> anyhow::Result<()> {
+    const NUM_SERVERS: usize = 2;
+    const NUM_CLIENTS: usize = 16;
+
+    let mut listen_fds = vec![new_bound_socket(SockType::Stream, Reuse::PORT, 0)?];
+    let port = local_port(listen_fds[0]);
+    for _ in 1..NUM_SERVERS {
+        listen_fds.push(new_bound_socket(SockType::Stream, Reuse::PORT, port)?);
+    }
+    for fd in &listen_fds {
+        socket::listen(*fd, NUM_CLIENTS)?;
+    }
+
+    let mut client_fds = vec![];
+    for _ in 0..NUM_CLIENTS {
+        let fd = new_socket(SockType::Stream, Reuse::NONE)?;
+        client_fds.push(fd);
+        socket::connect(fd, &SockaddrIn::new(127, 0, 0, 1, port))?;
+    }
+
+    let mut fds: Vec<_> = listen_fds.iter().chain(&client_fds).copied().collect();
+
+    let rv = (|| -> anyhow::Result<()> {
+        // the number of connections accepted by each listening socket
+        let mut accepted = vec![0; NUM_SERVERS];
+
+        for (index, fd) in listen_fds.iter().enumerate() {
+            while has_data(*fd)? {
+                fds.push(socket::accept(*fd)?);
+                accepted[index] += 1;
+            }
+        }
+
+        assert_eq!(accepted.iter().sum::<usize>(), NUM_CLIENTS);
+        assert!(accepted.iter().all(|x| *x > 0));
+
+        Ok(())
+    })();
+
+    close_all(&fds);
+    rv
+}
+
+/// Returns true if the socket is readable.
+fn has_data(fd: RawFd) -> nix::Result<bool> {
+    let mut poll_fds = [nix::poll::PollFd::new(fd, nix::poll::PollFlags::POLLIN)];
+    Ok(nix::poll::poll(&mut poll_fds, 0)? > 0)
+}
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let all_envs = set![TestEnvironment::Libc, TestEnvironment::Shadow];
+    let mut tests: Vec<test_utils::ShadowTest<(), anyhow::Error>> = vec![];
+
+    for sock_type in [SockType::Stream, SockType::Datagram] {
+        let append_args = |s| format!("{s} <type={sock_type:?}>");
+
+        tests.extend(vec![
+            ShadowTest::new(
+                &append_args("test_getsockopt"),
+                move || test_getsockopt(sock_type),
+                all_envs.clone(),
+            ),
+            ShadowTest::new(
+                &append_args("test_reuseport_bind"),
+                move || test_reuseport_bind(sock_type),
+                all_envs.clone(),
+            ),
+        ]);
+    }
+
+    tests.extend(vec![
+        ShadowTest::new("test_reuseaddr_udp", test_reuseaddr_udp, all_envs.clone()),
+        ShadowTest::new(
+            "test_reuseaddr_tcp_listening",
+            test_reuseaddr_tcp_listening,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_reuseaddr_time_wait",
+            test_reuseaddr_time_wait,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_reuseport_udp_distribution",
+            test_reuseport_udp_distribution,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_reuseport_tcp_distribution",
+            test_reuseport_tcp_distribution,
+            all_envs,
+        ),
+    ]);
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+
+    Ok(())
+}
@@ -200,6 +200,17 @@ fn get_tests() -> Vec<test_utils::ShadowTest<(), String>> {
                     move || test_tcp_congestion(domain, sock_type),
                     set![TestEnv::Libc, TestEnv::Shadow],
                 ),
+                test_utils::ShadowTest::new(
+                    &append_args("test_tcp_congestion_set <name=cubic>"),
+                    move || test_tcp_congestion_set(domain, sock_type, "cubic"),
+                    set![TestEnv::Libc, TestEnv::Shadow],
+                ),
+                // the bbr module isn't always loaded in linux
+                test_utils::ShadowTest::new(
+                    &append_args("test_tcp_congestion_set <name=bbr>"),
+                    move || test_tcp_congestion_set(domain, sock_type, "bbr"),
+                    set![TestEnv::Shadow],
+                ),
             ];
 
             tests.extend(more_tests);
@@ -925,6 +936,48 @@ fn test_tcp_congestion(domain: libc::c_int, sock_type: libc::c_int) -> Result<()
     })
 }
 
+/// Test that setting TCP_CONGESTION changes the value returned by getsockopt().
+fn test_tcp_congestion_set(
+    domain: libc::c_int,
+    sock_type: libc::c_int,
+    name: &str,
+) -> Result<(), String> {
+    let fd = unsafe { libc::socket(domain, sock_type, 0) };
+    assert!(fd >= 0);
+
+    let level = libc::SOL_TCP;
+    let optname = libc::TCP_CONGESTION;
+
+    let mut set_args = SetsockoptArguments::new(fd, level, optname, Some(name.into()));
+    let mut get_args = GetsockoptArguments::new(fd, level, optname, Some(vec![0u8; 16]));
+
+    test_utils::run_and_close_fds(&[fd], || {
+        if sock_type != libc::SOCK_STREAM {
+            let expected_errnos = vec![libc::ENOPROTOOPT, libc::EOPNOTSUPP];
+            check_setsockopt_call(&mut set_args, &expected_errnos)?;
+            return Ok(());
+        }
+
+        check_setsockopt_call(&mut set_args, &[])?;
+        check_getsockopt_call(&mut get_args, &[])?;
+
+        let returned_str = get_args.optval.as_ref().unwrap();
+        let returned_str = &returned_str[..get_args.optlen.unwrap() as usize];
+        let returned_str = &returned_str[..returned_str
+            .iter()
+            .position(|&c| c == b'\0')
+            .unwrap_or(returned_str.len())];
+
+        test_utils::result_assert_eq(
+            returned_str,
+            name.as_bytes(),
+            "Unexpected value for TCP_CONGESTION",
+        )?;
+
+        Ok(())
+    })
+}
+
 fn check_getsockopt_call(
     args: &mut GetsockoptArguments,
     expected_errnos: &[libc::c_int],
@@ -0,0 +1,522 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+
+use nix::errno::Errno;
+use nix::unistd;
+
+use test_utils::{set, ShadowTest, TestEnvironment};
+
+/// Run `f` with a new file (relative to the working directory) containing `contents`, and remove
+/// the file afterwards. The file's offset is at the start of the file.
+fn with_temp_file(
+    name: &str,
+    contents: &[u8],
+    f: impl FnOnce(&mut std::fs::File) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let path = PathBuf::from(format!("splice-{name}-{}", unistd::getpid()));
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(contents)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let rv = f(&mut file);
+
+    std::fs::remove_file(&path)?;
+
+    rv
+}
+
+/// Run `f` with a new pipe, and close the pipe afterwards.
+fn with_pipe(f: impl FnOnce(libc::c_int, libc::c_int) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let (read_fd, write_fd) = unistd::pipe()?;
+
+    test_utils::run_and_close_fds(&[read_fd, write_fd], || f(read_fd, write_fd))
+}
+
+fn sendfile(
+    out_fd: libc::c_int,
+    in_fd: libc::c_int,
+    offset: Option<&mut libc::off_t>,
+    count: usize,
+) -> nix::Result<usize> {
+    let offset = offset.map_or(std::ptr::null_mut(), |x| x as *mut _);
+    Errno::result(unsafe { libc::sendfile(out_fd, in_fd, offset, count) }).map(|x| x as usize)
+}
+
+fn splice(
+    fd_in: libc::c_int,
+    off_in: Option<&mut libc::loff_t>,
+    fd_out: libc::c_int,
+    off_out: Option<&mut libc::loff_t>,
+    len: usize,
+    flags: libc::c_uint,
+) -> nix::Result<usize> {
+    let off_in = off_in.map_or(std::ptr::null_mut(), |x| x as *mut _);
+    let off_out = off_out.map_or(std::ptr::null_mut(), |x| x as *mut _);
+    Errno::result(unsafe { libc::splice(fd_in, off_in, fd_out, off_out, len, flags) })
+        .map(|x| x as usize)
+}
+
+fn copy_file_range(
+    fd_in: libc::c_int,
+    off_in: Option<&mut libc::loff_t>,
+    fd_out: libc::c_int,
+    off_out: Option<&mut libc::loff_t>,
+    len: usize,
+    flags: libc::c_uint,
+) -> nix::Result<usize> {
+    let off_in = off_in.map_or(std::ptr::null_mut(), |x| x as *mut _);
+    let off_out = off_out.map_or(std::ptr::null_mut(), |x| x as *mut _);
+    Errno::result(unsafe {
+        libc::syscall(
+            libc::SYS_copy_file_range,
+            fd_in,
+            off_in,
+            fd_out,
+            off_out,
+            len,
+            flags,
+        )
+    })
+    .map(|x| x as usize)
+}
+
+/// Get the current file offset.
+fn tell(fd: libc::c_int) -> nix::Result<libc::off_t> {
+    Errno::result(unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) })
+}
+
+/// Read exactly `len` bytes from `fd`.
+fn read_exact(fd: libc::c_int, len: usize) -> nix::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let mut num_read = 0;
+
+    while num_read < len {
+        let n = unistd::read(fd, &mut buf[num_read..])?;
+        assert_ne!(n, 0, "Unexpected EOF");
+        num_read += n;
+    }
+
+    Ok(buf)
+}
+
+fn test_sendfile_to_pipe() -> anyhow::Result<()> {
+    with_temp_file("sendfile-pipe", b"hello world", |file| {
+        let fd = file.as_raw_fd();
+
+        with_pipe(|read_fd, write_fd| {
+            // with an offset, the file offset doesn't change
+            let mut offset = 6;
+            assert_eq!(sendfile(write_fd, fd, Some(&mut offset), 100), Ok(5));
+            assert_eq!(offset, 11);
+            assert_eq!(tell(fd), Ok(0));
+            assert_eq!(read_exact(read_fd, 5)?, b"world");
+
+            // without an offset, the file offset is used and updated
+            assert_eq!(sendfile(write_fd, fd, None, 5), Ok(5));
+            assert_eq!(tell(fd), Ok(5));
+            assert_eq!(read_exact(read_fd, 5)?, b"hello");
+
+            // at the end of the file
+            assert_eq!(sendfile(write_fd, fd, Some(&mut offset), 100), Ok(0));
+
+            Ok(())
+        })
+    })
+}
+
+fn test_sendfile_invalid_args() -> anyhow::Result<()> {
+    with_temp_file("sendfile-invalid", b"hello world", |file| {
+        let fd = file.as_raw_fd();
+
+        with_pipe(|read_fd, write_fd| {
+            // the source must be readable
+            assert_eq!(sendfile(write_fd, write_fd, None, 5), Err(Errno::EBADF));
+
+            // the destination must be writable
+            assert_eq!(sendfile(read_fd, fd, None, 5), Err(Errno::EBADF));
+
+            // negative offset
+            let mut offset = -1;
+            assert_eq!(
+                sendfile(write_fd, fd, Some(&mut offset), 5),
+                Err(Errno::EINVAL)
+            );
+
+            // bad fd
+            assert_eq!(sendfile(write_fd, -1, None, 5), Err(Errno::EBADF));
+
+            Ok(())
+        })
+    })
+}
+
+fn test_sendfile_to_tcp() -> anyhow::Result<()> {
+    // larger than the socket buffers, so the sender will need to block
+    let contents: Vec<u8> = (0..4 * 1024 * 1024).map(|x| (x % 251) as u8).collect();
+
+    with_temp_file("sendfile-tcp", &contents, |file| {
+        let fd = file.as_raw_fd();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let client = std::net::TcpStream::connect(listener.local_addr()?)?;
+        let (mut server, _) = listener.accept()?;
+
+        let expected_len = contents.len();
+        let reader = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            server.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let mut offset = 0;
+        while (offset as usize) < expected_len {
+            let n = sendfile(client.as_raw_fd(), fd, Some(&mut offset), expected_len)?;
+            assert_ne!(n, 0);
+        }
+        assert_eq!(offset as usize, expected_len);
+
+        client.shutdown(std::net::Shutdown::Write)?;
+
+        let received = reader.join().unwrap();
+        assert_eq!(received, contents);
+
+        Ok(())
+    })
+}
+
+fn test_splice_file_and_pipe() -> anyhow::Result<()> {
+    with_temp_file("splice-file", b"hello world", |file| {
+        let fd = file.as_raw_fd();
+
+        with_pipe(|read_fd, write_fd| {
+            // file to pipe, using an offset
+            let mut offset = 6;
+            assert_eq!(splice(fd, Some(&mut offset), write_fd, None, 5, 0), Ok(5));
+            assert_eq!(offset, 11);
+            assert_eq!(tell(fd), Ok(0));
+
+            // pipe to file, using the file offset
+            assert_eq!(splice(read_fd, None, fd, None, 100, 0), Ok(5));
+            assert_eq!(tell(fd), Ok(5));
+
+            let mut contents = String::new();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_string(&mut contents)?;
+            assert_eq!(contents, "world world");
+
+            Ok(())
+        })
+    })
+}
+
+fn test_splice_pipe_to_pipe() -> anyhow::Result<()> {
+    with_pipe(|read_fd_1, write_fd_1| {
+        with_pipe(|read_fd_2, write_fd_2| {
+            unistd::write(write_fd_1, b"hello world")?;
+
+            // only part of the data is moved
+            assert_eq!(splice(read_fd_1, None, write_fd_2, None, 5, 0), Ok(5));
+            assert_eq!(read_exact(read_fd_2, 5)?, b"hello");
+
+            // the rest stays in the first pipe
+            assert_eq!(read_exact(read_fd_1, 6)?, b" world");
+
+            Ok(())
+        })
+    })
+}
+
+fn test_splice_invalid_args() -> anyhow::Result<()> {
+    with_temp_file("splice-invalid", b"hello world", |file| {
+        let fd = file.as_raw_fd();
+
+        with_pipe(|read_fd, write_fd| {
+            unistd::write(write_fd, b"hello")?;
+
+            // at least one end must be a pipe
+            assert_eq!(splice(fd, None, fd, None, 5, 0), Err(Errno::EINVAL));
+
+            // both ends can't be the same pipe
+            assert_eq!(
+                splice(read_fd, None, write_fd, None, 5, 0),
+                Err(Errno::EINVAL)
+            );
+
+            // pipes don't have offsets
+            let mut offset = 0;
+            assert_eq!(
+                splice(read_fd, Some(&mut offset), fd, None, 5, 0),
+                Err(Errno::ESPIPE)
+            );
+
+            // invalid flags
+            assert_eq!(
+                splice(read_fd, None, fd, None, 5, 0x100),
+                Err(Errno::EINVAL)
+            );
+
+            // wrong direction
+            assert_eq!(splice(write_fd, None, fd, None, 5, 0), Err(Errno::EBADF));
+
+            Ok(())
+        })
+    })
+}
+
+fn test_splice_nonblock() -> anyhow::Result<()> {
+    with_temp_file("splice-nonblock", b"", |file| {
+        let fd = file.as_raw_fd();
+
+        with_pipe(|read_fd, _write_fd| {
+            // the pipe is empty and has a writer
+            assert_eq!(
+                splice(read_fd, None, fd, None, 5, libc::SPLICE_F_NONBLOCK),
+                Err(Errno::EAGAIN)
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn test_splice_blocking() -> anyhow::Result<()> {
+    with_temp_file("splice-blocking", b"", |file| {
+        let fd = file.as_raw_fd();
+
+        with_pipe(|read_fd, write_fd| {
+            let t = std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                unistd::write(write_fd, b"hello")
+            });
+
+            // blocks until the other thread writes
+            assert_eq!(splice(read_fd, None, fd, None, 100, 0), Ok(5));
+
+            t.join().unwrap()?;
+
+            Ok(())
+        })
+    })
+}
+
+fn test_splice_socket_to_pipe() -> anyhow::Result<()> {
+    let (sock_1, sock_2) = nix::sys::socket::socketpair(
+        nix::sys::socket::AddressFamily::Unix,
+        nix::sys::socket::SockType::Stream,
+        None,
+        nix::sys::socket::SockFlag::empty(),
+    )?;
+
+    test_utils::run_and_close_fds(&[sock_1, sock_2], || {
+        with_pipe(|read_fd, write_fd| {
+            unistd::write(sock_1, b"hello world")?;
+
+            assert_eq!(splice(sock_2, None, write_fd, None, 100, 0), Ok(11));
+            assert_eq!(read_exact(read_fd, 11)?, b"hello world");
+
+            // the socket is now empty
+            assert_eq!(
+                splice(sock_2, None, write_fd, None, 100, libc::SPLICE_F_NONBLOCK),
+                Err(Errno::EAGAIN)
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn test_tee() -> anyhow::Result<()> {
+    with_pipe(|read_fd_1, write_fd_1| {
+        with_pipe(|read_fd_2, write_fd_2| {
+            unistd::write(write_fd_1, b"hello world")?;
+
+            let rv = Errno::result(unsafe { libc::tee(read_fd_1, write_fd_2, 100, 0) });
+            assert_eq!(rv, Ok(11));
+
+            // both pipes contain the data
+            assert_eq!(read_exact(read_fd_1, 11)?, b"hello world");
+            assert_eq!(read_exact(read_fd_2, 11)?, b"hello world");
+
+            // an empty pipe with SPLICE_F_NONBLOCK
+            let rv = Errno::result(unsafe {
+                libc::tee(read_fd_1, write_fd_2, 100, libc::SPLICE_F_NONBLOCK)
+            });
+            assert_eq!(rv, Err(Errno::EAGAIN));
+
+            Ok(())
+        })
+    })
+}
+
+fn test_vmsplice() -> anyhow::Result<()> {
+    with_pipe(|read_fd, write_fd| {
+        let hello = b"hello ";
+        let world = b"world";
+        let iovs = [
+            libc::iovec {
+                iov_base: hello.as_ptr() as *mut _,
+                iov_len: hello.len(),
+            },
+            libc::iovec {
+                iov_base: world.as_ptr() as *mut _,
+                iov_len: world.len(),
+            },
+        ];
+
+        let rv = Errno::result(unsafe { libc::vmsplice(write_fd, iovs.as_ptr(), 2, 0) });
+        assert_eq!(rv, Ok(11));
+        assert_eq!(read_exact(read_fd, 11)?, b"hello world");
+
+        // the pipe is empty
+        let mut buf = [0u8; 16];
+        let iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut _,
+            iov_len: buf.len(),
+        };
+        let rv =
+            Errno::result(unsafe { libc::vmsplice(read_fd, &iov, 1, libc::SPLICE_F_NONBLOCK) });
+        assert_eq!(rv, Err(Errno::EAGAIN));
+
+        Ok(())
+    })
+}
+
+fn test_copy_file_range() -> anyhow::Result<()> {
+    with_temp_file("copy-src", b"hello world", |src| {
+        with_temp_file("copy-dst", b"", |dst| {
+            // using offsets
+            let mut off_in = 6;
+            let mut off_out = 0;
+            assert_eq!(
+                copy_file_range(
+                    src.as_raw_fd(),
+                    Some(&mut off_in),
+                    dst.as_raw_fd(),
+                    Some(&mut off_out),
+                    100,
+                    0,
+                ),
+                Ok(5)
+            );
+            assert_eq!((off_in, off_out), (11, 5));
+            assert_eq!(tell(src.as_raw_fd()), Ok(0));
+            assert_eq!(tell(dst.as_raw_fd()), Ok(0));
+
+            // using the file offsets
+            dst.seek(SeekFrom::Start(5))?;
+            assert_eq!(
+                copy_file_range(src.as_raw_fd(), None, dst.as_raw_fd(), None, 6, 0),
+                Ok(6)
+            );
+            assert_eq!(tell(src.as_raw_fd()), Ok(6));
+            assert_eq!(tell(dst.as_raw_fd()), Ok(11));
+
+            let mut contents = String::new();
+            dst.seek(SeekFrom::Start(0))?;
+            dst.read_to_string(&mut contents)?;
+            assert_eq!(contents, "worldhello ");
+
+            // no flags are supported
+            assert_eq!(
+                copy_file_range(src.as_raw_fd(), None, dst.as_raw_fd(), None, 6, 1),
+                Err(Errno::EINVAL)
+            );
+
+            // overlapping ranges in the same file
+            let mut off_in = 0;
+            let mut off_out = 2;
+            assert_eq!(
+                copy_file_range(
+                    src.as_raw_fd(),
+                    Some(&mut off_in),
+                    src.as_raw_fd(),
+                    Some(&mut off_out),
+                    5,
+                    0,
+                ),
+                Err(Errno::EINVAL)
+            );
+
+            Ok(())
+        })
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let all_envs = set![TestEnvironment::Libc, TestEnvironment::Shadow];
+    let mut tests: Vec<test_utils::ShadowTest<(), anyhow::Error>> = vec![
+        ShadowTest::new(
+            "test_sendfile_to_pipe",
+            test_sendfile_to_pipe,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_sendfile_invalid_args",
+            test_sendfile_invalid_args,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_sendfile_to_tcp",
+            test_sendfile_to_tcp,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_splice_file_and_pipe",
+            test_splice_file_and_pipe,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_splice_pipe_to_pipe",
+            test_splice_pipe_to_pipe,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_splice_invalid_args",
+            test_splice_invalid_args,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_splice_nonblock",
+            test_splice_nonblock,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_splice_blocking",
+            test_splice_blocking,
+            all_envs.clone(),
+        ),
+        ShadowTest::new(
+            "test_splice_socket_to_pipe",
+            test_splice_socket_to_pipe,
+            all_envs.clone(),
+        ),
+        ShadowTest::new("test_tee", test_tee, all_envs.clone()),
+        ShadowTest::new("test_vmsplice", test_vmsplice, all_envs.clone()),
+        ShadowTest::new("test_copy_file_range", test_copy_file_range, all_envs),
+    ];
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    println!("Success.");
+
+    Ok(())
+}
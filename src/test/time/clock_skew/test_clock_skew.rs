@@ -0,0 +1,212 @@
+//! Tests for the per-host clock model. The expected values depend on the host's clock options in
+//! `clock_skew.yaml`, so these tests only run in Shadow.
+
+use std::time::Duration;
+
+use test_utils::time::*;
+use test_utils::{ensure_ord, set, TestEnvironment};
+
+/// The offset of the host's realtime clock (`clock.offset` is "-5 s").
+const OFFSET: Duration = Duration::from_secs(5);
+/// The rate at which the host's clocks run fast (`clock.drift_ppm` is 1000).
+const DRIFT_PER_SEC: Duration = Duration::from_millis(1);
+/// The interval at which the realtime clock is stepped (`clock.step_interval` is "20 s").
+const STEP_INTERVAL_SECS: u32 = 20;
+
+/// The tolerance for comparing the clocks, which allows for the time spent making syscalls.
+const TOLERANCE: Duration = Duration::from_micros(100);
+
+fn main() -> anyhow::Result<()> {
+    // should we restrict the tests we run?
+    let filter_shadow_passing = std::env::args().any(|x| x == "--shadow-passing");
+    let filter_libc_passing = std::env::args().any(|x| x == "--libc-passing");
+    // should we summarize the results rather than exit on a failed test
+    let summarize = std::env::args().any(|x| x == "--summarize");
+
+    let mut tests = get_tests();
+
+    if filter_shadow_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Shadow));
+    }
+    if filter_libc_passing {
+        tests.retain(|x| x.passing(TestEnvironment::Libc));
+    }
+
+    test_utils::run_tests(&tests, summarize)?;
+
+    Ok(())
+}
+
+fn get_tests() -> Vec<test_utils::ShadowTest<(), anyhow::Error>> {
+    // the tests run in order, and `test_step` must run last since it depends on how much time has
+    // passed
+    vec![
+        test_utils::ShadowTest::new("offset", test_offset, set![TestEnvironment::Shadow]),
+        test_utils::ShadowTest::new("rel_sleep", test_rel_sleep, set![TestEnvironment::Shadow]),
+        test_utils::ShadowTest::new(
+            "timerfd_rel",
+            test_timerfd_rel,
+            set![TestEnvironment::Shadow],
+        ),
+        test_utils::ShadowTest::new(
+            "abs_sleep <clockid=CLOCK_REALTIME>",
+            || test_abs_sleep(libc::CLOCK_REALTIME),
+            set![TestEnvironment::Shadow],
+        ),
+        test_utils::ShadowTest::new(
+            "abs_sleep <clockid=CLOCK_MONOTONIC>",
+            || test_abs_sleep(libc::CLOCK_MONOTONIC),
+            set![TestEnvironment::Shadow],
+        ),
+        test_utils::ShadowTest::new(
+            "timerfd_abs",
+            test_timerfd_abs,
+            set![TestEnvironment::Shadow],
+        ),
+        test_utils::ShadowTest::new("step", test_step, set![TestEnvironment::Shadow]),
+    ]
+}
+
+fn assert_near(actual: Duration, expected: Duration) -> anyhow::Result<()> {
+    let diff = duration_abs_diff(actual, expected);
+    ensure_ord!(diff, <=, TOLERANCE);
+    Ok(())
+}
+
+/// Before the realtime clock is first stepped, it's behind the monotonic clock by the offset.
+fn test_offset() -> anyhow::Result<()> {
+    let realtime = clock_now_duration(libc::CLOCK_REALTIME)?;
+    let monotonic = clock_now_duration(libc::CLOCK_MONOTONIC)?;
+    assert_near(monotonic - realtime, OFFSET)?;
+
+    let mut tv = libc::timeval {
+        tv_sec: 0,
+        tv_usec: 0,
+    };
+    let rv = unsafe { libc::gettimeofday(&mut tv, std::ptr::null_mut()) };
+    ensure_ord!(rv, ==, 0);
+    let timeofday = Duration::new(
+        tv.tv_sec.try_into().unwrap(),
+        (tv.tv_usec * 1000).try_into().unwrap(),
+    );
+    assert_near(timeofday, realtime)?;
+
+    let time = unsafe { libc::time(std::ptr::null_mut()) };
+    ensure_ord!(u64::try_from(time).unwrap(), ==, realtime.as_secs());
+
+    Ok(())
+}
+
+/// Relative sleeps are measured by the host's monotonic clock, so the host's clocks advance by
+/// exactly the requested duration even though they run fast.
+fn test_rel_sleep() -> anyhow::Result<()> {
+    let realtime_before = clock_now_duration(libc::CLOCK_REALTIME)?;
+    let monotonic_before = clock_now_duration(libc::CLOCK_MONOTONIC)?;
+
+    std::thread::sleep(Duration::from_secs(2));
+
+    let realtime_after = clock_now_duration(libc::CLOCK_REALTIME)?;
+    let monotonic_after = clock_now_duration(libc::CLOCK_MONOTONIC)?;
+
+    let expected = Duration::from_secs(2);
+    ensure_ord!(monotonic_after - monotonic_before, >=, expected);
+    assert_near(realtime_after - realtime_before, expected)?;
+    assert_near(monotonic_after - monotonic_before, expected)?;
+
+    Ok(())
+}
+
+/// Relative timerfd expirations and intervals are measured by the host's monotonic clock.
+fn test_timerfd_rel() -> anyhow::Result<()> {
+    let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, 0) };
+    ensure_ord!(fd, >=, 0);
+
+    test_utils::run_and_close_fds(&[fd], || {
+        let start = clock_now_duration(libc::CLOCK_MONOTONIC)?;
+        let value = libc::itimerspec {
+            it_interval: duration_to_timespec(Duration::from_millis(500)),
+            it_value: duration_to_timespec(Duration::from_secs(1)),
+        };
+
+        let rv = unsafe { libc::timerfd_settime(fd, 0, &value, std::ptr::null_mut()) };
+        ensure_ord!(rv, ==, 0);
+
+        for expected in [Duration::from_secs(1), Duration::from_millis(1500)] {
+            let mut expirations = [0u8; 8];
+            let rv = unsafe { libc::read(fd, expirations.as_mut_ptr().cast(), expirations.len()) };
+            ensure_ord!(rv, ==, 8);
+            ensure_ord!(u64::from_ne_bytes(expirations), ==, 1);
+
+            let elapsed = clock_now_duration(libc::CLOCK_MONOTONIC)? - start;
+            ensure_ord!(elapsed, >=, expected);
+            assert_near(elapsed, expected)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Absolute sleeps wake up when the host's clock reaches the requested time.
+fn test_abs_sleep(clockid: libc::clockid_t) -> anyhow::Result<()> {
+    let target = clock_now_duration(clockid)? + Duration::from_secs(1);
+
+    let rv = unsafe {
+        libc::clock_nanosleep(
+            clockid,
+            libc::TIMER_ABSTIME,
+            &duration_to_timespec(target),
+            std::ptr::null_mut(),
+        )
+    };
+    ensure_ord!(rv, ==, 0);
+
+    let now = clock_now_duration(clockid)?;
+    ensure_ord!(now, >=, target);
+    assert_near(now, target)?;
+
+    Ok(())
+}
+
+/// Absolute timerfd expirations are measured by the host's clock.
+fn test_timerfd_abs() -> anyhow::Result<()> {
+    let fd = unsafe { libc::timerfd_create(libc::CLOCK_REALTIME, 0) };
+    ensure_ord!(fd, >=, 0);
+
+    test_utils::run_and_close_fds(&[fd], || {
+        let target = clock_now_duration(libc::CLOCK_REALTIME)? + Duration::from_secs(1);
+        let value = libc::itimerspec {
+            it_interval: duration_to_timespec(Duration::ZERO),
+            it_value: duration_to_timespec(target),
+        };
+
+        let rv = unsafe {
+            libc::timerfd_settime(fd, libc::TFD_TIMER_ABSTIME, &value, std::ptr::null_mut())
+        };
+        ensure_ord!(rv, ==, 0);
+
+        let mut expirations = [0u8; 8];
+        let rv = unsafe { libc::read(fd, expirations.as_mut_ptr().cast(), expirations.len()) };
+        ensure_ord!(rv, ==, 8);
+        ensure_ord!(u64::from_ne_bytes(expirations), ==, 1);
+
+        let now = clock_now_duration(libc::CLOCK_REALTIME)?;
+        ensure_ord!(now, >=, target);
+        assert_near(now, target)?;
+
+        Ok(())
+    })
+}
+
+/// After the realtime clock is stepped, it only includes the drift since the step, while the
+/// monotonic clock still includes the drift since the start of the simulation.
+fn test_step() -> anyhow::Result<()> {
+    // the earlier tests took less than 10 seconds, so this sleeps past the first step but not the
+    // second
+    std::thread::sleep(Duration::from_secs(15));
+
+    let realtime = clock_now_duration(libc::CLOCK_REALTIME)?;
+    let monotonic = clock_now_duration(libc::CLOCK_MONOTONIC)?;
+    assert_near(monotonic - realtime, STEP_INTERVAL_SECS * DRIFT_PER_SEC)?;
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+/*
+ * The Shadow Simulator
+ * See LICENSE for licensing information
+ */
+
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// Forks a child that sleeps for `sleep_secs` and then exits with
+// `exit_status`. Returns the child's pid, or -1 on failure.
+static pid_t _fork_child(unsigned int sleep_secs, int exit_status) {
+    pid_t child_pid = fork();
+    if (child_pid < 0) {
+        fprintf(stderr, "fork failed: %s\n", strerror(errno));
+        return -1;
+    }
+    if (child_pid == 0) {
+        if (sleep_secs > 0) {
+            sleep(sleep_secs);
+        }
+        _exit(exit_status);
+    }
+    return child_pid;
+}
+
+static int _test_waitpid_blocking() {
+    pid_t child_pid = _fork_child(1, 42);
+    if (child_pid < 0) {
+        return EXIT_FAILURE;
+    }
+
+    int status = 0;
+    pid_t rv = waitpid(child_pid, &status, 0);
+    if (rv != child_pid) {
+        fprintf(stderr, "waitpid returned %d (%s); expected %d\n", rv, strerror(errno), child_pid);
+        return EXIT_FAILURE;
+    }
+    if (!WIFEXITED(status) || WEXITSTATUS(status) != 42) {
+        fprintf(stderr, "unexpected wait status %#x\n", status);
+        return EXIT_FAILURE;
+    }
+
+    // The child has been reaped.
+    rv = waitpid(child_pid, &status, 0);
+    if (rv != -1 || errno != ECHILD) {
+        fprintf(stderr, "waitpid on reaped child returned %d; expected ECHILD\n", rv);
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+static int _test_waitpid_nohang() {
+    pid_t child_pid = _fork_child(1, 0);
+    if (child_pid < 0) {
+        return EXIT_FAILURE;
+    }
+
+    int status = 0;
+    pid_t rv = waitpid(-1, &status, WNOHANG);
+    if (rv != 0) {
+        fprintf(stderr, "waitpid with WNOHANG returned %d; expected 0\n", rv);
+        return EXIT_FAILURE;
+    }
+
+    rv = waitpid(-1, &status, 0);
+    if (rv != child_pid) {
+        fprintf(stderr, "waitpid returned %d (%s); expected %d\n", rv, strerror(errno), child_pid);
+        return EXIT_FAILURE;
+    }
+    if (!WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+        fprintf(stderr, "unexpected wait status %#x\n", status);
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+static int _test_waitpid_no_children() {
+    pid_t rv = waitpid(-1, NULL, 0);
+    if (rv != -1 || errno != ECHILD) {
+        fprintf(stderr, "waitpid without children returned %d; expected ECHILD\n", rv);
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+static int _test_waitpid_signaled() {
+    pid_t child_pid = fork();
+    if (child_pid < 0) {
+        fprintf(stderr, "fork failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+    if (child_pid == 0) {
+        // Killed by the parent before this returns.
+        sleep(100);
+        _exit(EXIT_FAILURE);
+    }
+
+    if (kill(child_pid, SIGTERM) != 0) {
+        fprintf(stderr, "kill failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    int status = 0;
+    pid_t rv = waitpid(child_pid, &status, 0);
+    if (rv != child_pid) {
+        fprintf(stderr, "waitpid returned %d (%s); expected %d\n", rv, strerror(errno), child_pid);
+        return EXIT_FAILURE;
+    }
+    if (!WIFSIGNALED(status) || WTERMSIG(status) != SIGTERM) {
+        fprintf(stderr, "unexpected wait status %#x\n", status);
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+static int _test_waitid() {
+    pid_t child_pid = _fork_child(1, 7);
+    if (child_pid < 0) {
+        return EXIT_FAILURE;
+    }
+
+    // Wait without reaping the child.
+    siginfo_t info = {0};
+    if (waitid(P_PID, child_pid, &info, WEXITED | WNOWAIT) != 0) {
+        fprintf(stderr, "waitid failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+    if (info.si_signo != SIGCHLD || info.si_pid != child_pid || info.si_code != CLD_EXITED ||
+        info.si_status != 7) {
+        fprintf(stderr, "unexpected siginfo: signo=%d pid=%d code=%d status=%d\n", info.si_signo,
+                info.si_pid, info.si_code, info.si_status);
+        return EXIT_FAILURE;
+    }
+
+    // The child is still waitable, this time by process group.
+    memset(&info, 0, sizeof(info));
+    if (waitid(P_PGID, 0, &info, WEXITED) != 0) {
+        fprintf(stderr, "waitid failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+    if (info.si_pid != child_pid || info.si_status != 7) {
+        fprintf(stderr, "unexpected siginfo: pid=%d status=%d\n", info.si_pid, info.si_status);
+        return EXIT_FAILURE;
+    }
+
+    // Now it's been reaped.
+    memset(&info, 0, sizeof(info));
+    if (waitid(P_ALL, 0, &info, WEXITED | WNOHANG) != -1 || errno != ECHILD) {
+        fprintf(stderr, "waitid after reaping child didn't fail with ECHILD\n");
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+static int _test_waitid_invalid_options() {
+    // 0x100 isn't a valid option for any of the wait syscalls.
+    siginfo_t info = {0};
+    int rv = waitid(P_ALL, 0, &info, WEXITED | 0x100);
+    if (rv != -1 || errno != EINVAL) {
+        fprintf(stderr, "waitid with invalid options returned %d; expected EINVAL\n", rv);
+        return EXIT_FAILURE;
+    }
+    return EXIT_SUCCESS;
+}
+
+static volatile sig_atomic_t sigchld_pid = 0;
+static volatile sig_atomic_t sigchld_code = 0;
+
+static void _sigchld_handler(int signo, siginfo_t* info, void* context) {
+    sigchld_pid = info->si_pid;
+    sigchld_code = info->si_code;
+}
+
+static int _test_sigchld() {
+    struct sigaction action = {
+        .sa_sigaction = _sigchld_handler,
+        .sa_flags = SA_SIGINFO | SA_RESTART,
+    };
+    if (sigaction(SIGCHLD, &action, NULL) != 0) {
+        fprintf(stderr, "sigaction failed: %s\n", strerror(errno));
+        return EXIT_FAILURE;
+    }
+
+    pid_t child_pid = _fork_child(1, 0);
+    if (child_pid < 0) {
+        return EXIT_FAILURE;
+    }
+
+    // The signal interrupts the sleep. Loop in case it arrives just before we
+    // start sleeping.
+    while (sigchld_pid == 0) {
+        sleep(1);
+    }
+    if (sigchld_pid != child_pid || sigchld_code != CLD_EXITED) {
+        fprintf(stderr, "SIGCHLD handler got pid=%d code=%d; expected pid=%d code=%d\n",
+                sigchld_pid, sigchld_code, child_pid, CLD_EXITED);
+        return EXIT_FAILURE;
+    }
+
+    // The child is a zombie until reaped.
+    pid_t rv = waitpid(child_pid, NULL, WNOHANG);
+    if (rv != child_pid) {
+        fprintf(stderr, "waitpid returned %d (%s); expected %d\n", rv, strerror(errno), child_pid);
+        return EXIT_FAILURE;
+    }
+
+    signal(SIGCHLD, SIG_DFL);
+    return EXIT_SUCCESS;
+}
+
+static int _test_sigchld_ignored() {
+    // From wait(2): if SIGCHLD is ignored, children don't become zombies, and a
+    // call to wait blocks until all children have terminated and then fails
+    // with ECHILD.
+    signal(SIGCHLD, SIG_IGN);
+
+    pid_t child_pid = _fork_child(1, 0);
+    if (child_pid < 0) {
+        return EXIT_FAILURE;
+    }
+
+    pid_t rv = waitpid(child_pid, NULL, 0);
+    if (rv != -1 || errno != ECHILD) {
+        fprintf(stderr, "waitpid returned %d; expected ECHILD\n", rv);
+        return EXIT_FAILURE;
+    }
+
+    signal(SIGCHLD, SIG_DFL);
+    return EXIT_SUCCESS;
+}
+
+int main(int argc, char* argv[]) {
+    if (_test_waitpid_blocking() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_waitpid_nohang() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_waitpid_no_children() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_waitpid_signaled() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_waitid() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_waitid_invalid_options() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_sigchld() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    if (_test_sigchld_ignored() != EXIT_SUCCESS) {
+        return EXIT_FAILURE;
+    }
+    printf("success\n");
+    return EXIT_SUCCESS;
+}